@@ -10,7 +10,7 @@ use criterion::{
     BenchmarkId, Criterion,
 };
 use pprof::criterion::{Output, PProfProfiler};
-use sketch_traits::HeavyDistinctHitterSketch;
+use sketch_traits::{CardinalitySketch, HeavyDistinctHitterSketch, New};
 
 const MEMORY_SIZE: f32 = 1.0; // 1MB
 const MAX_NUM_ENTRIES: usize = usize::MAX; // cached entries
@@ -33,8 +33,11 @@ macro_rules! for_all_algorithms {
         $fn(algo::Achll);
         $fn(algo::Spread);
         // $fn(algo::Schll);
+        // $fn(algo::Sschll);
         // $fn(algo::Osss);
         // $fn(algo::Rsss);
+        // $fn(algo::SsssTz);
+        // $fn(algo::Ssss2h);
     };
 }
 
@@ -138,6 +141,155 @@ fn bench_merge_with<A, D>(
             )
         },
     );
+    benchmark_group.bench_with_input(
+        BenchmarkId::new(format!("{}/owned", algorithm), dataset),
+        &NUM_ENTRIES,
+        |b, _| {
+            b.iter_batched_ref(
+                || (sketch.clone(), Some(sketch.clone())),
+                |(s, other)| black_box(s).merge_owned(black_box(other.take().unwrap())),
+                BatchSize::SmallInput,
+            )
+        },
+    );
+}
+
+/// Merges a single HyperLogLog, the loop aggregators merging many counters
+/// spend most of their time in.
+fn bench_hll_merge(c: &mut Criterion) {
+    let mut benchmark_group = c.benchmark_group("HllMerge");
+
+    for num_registers in [256, 4096, 65536] {
+        let config = hll::Config::builder()
+            .registers(num_registers)
+            .sparse_registers(false)
+            .build()
+            .unwrap();
+        let mut sketch = hll::HyperLogLog::new(&config);
+        let mut other = hll::HyperLogLog::new(&config);
+        for i in 0..10 * num_registers as u64 {
+            sketch.insert(&i);
+            other.insert(&(i + 5 * num_registers as u64));
+        }
+        benchmark_group.bench_with_input(
+            BenchmarkId::from_parameter(num_registers),
+            &num_registers,
+            |b, _| {
+                b.iter_batched_ref(
+                    || sketch.clone(),
+                    |s| black_box(s).merge(black_box(&other)),
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+    }
+
+    benchmark_group.finish()
+}
+
+/// Inserts into a single HyperLogLog, past the point where most inserts
+/// leave the registers unchanged.
+fn bench_hll_insertion(c: &mut Criterion) {
+    let mut benchmark_group = c.benchmark_group("HllInsertion");
+
+    for num_registers in [256, 4096, 65536] {
+        let config = hll::Config::builder()
+            .registers(num_registers)
+            .sparse_registers(false)
+            .build()
+            .unwrap();
+        let mut sketch = hll::HyperLogLog::new(&config);
+        benchmark_group.bench_with_input(
+            BenchmarkId::from_parameter(num_registers),
+            &num_registers,
+            |b, _| {
+                let mut item = 0_u64;
+                b.iter(|| {
+                    sketch.insert(black_box(&item));
+                    item += 1;
+                })
+            },
+        );
+    }
+
+    benchmark_group.finish()
+}
+
+/// Inserts into SpreadSketches of compact HyperLogLogs, whose buckets are
+/// cache-resident for the fewest columns but mostly missed for the most, as
+/// when ingesting packets of many sources.
+fn bench_spread_insertion(c: &mut Criterion) {
+    const NUM_ROWS: usize = 4;
+    const NUM_REGISTERS: usize = 32;
+
+    let mut benchmark_group = c.benchmark_group("SpreadInsertion");
+
+    for num_cols in [1 << 8, 1 << 14, 1 << 18] {
+        let hll_config = hll::Config::builder()
+            .registers(NUM_REGISTERS)
+            .sparse_registers(false)
+            .build()
+            .unwrap();
+        let config = spread::Config::builder()
+            .rows(NUM_ROWS)
+            .cols(num_cols)
+            .cardinality_sketch_config(hll_config)
+            .build()
+            .unwrap();
+        let mut sketch = spread::SpreadSketch::<u64, hll::HyperLogLog<u64>>::new(&config);
+        benchmark_group.bench_with_input(
+            BenchmarkId::from_parameter(num_cols),
+            &num_cols,
+            |b, _| {
+                let mut item = 0_u64;
+                b.iter(|| {
+                    // Four times as many labels as columns, in no particular order.
+                    let label = item.wrapping_mul(0x9e37_79b9_7f4a_7c15) % (4 * num_cols as u64);
+                    sketch.insert(black_box(label), black_box(&item));
+                    item += 1;
+                })
+            },
+        );
+    }
+
+    benchmark_group.finish()
+}
+
+/// Inserts into SSSS with and without the hot array of counters ahead of the
+/// map, on Zipf-distributed labels: most inserts hit a few dozen labels, and
+/// the sampled tail misses the hot array.
+fn bench_ssss_hot_counters(c: &mut Criterion) {
+    const NUM_COUNTERS: usize = 1000;
+    const NUM_SAMPLES: usize = 1_000_000;
+
+    let mut benchmark_group = c.benchmark_group("SsssHotCounters");
+
+    let entries = Dataset::iter(&data::synth::Zipf::new(100_000, 1.1, NUM_SAMPLES, false))
+        .collect::<Vec<_>>();
+    for hot_capacity in [0, 64] {
+        let config = ssss::Config::builder()
+            .counters(NUM_COUNTERS)
+            .cardinality_sketch_config(hll::Config::builder().registers(256).build().unwrap())
+            .hot_capacity(hot_capacity)
+            .build()
+            .unwrap();
+        let mut sketch =
+            ssss::SamplingSpaceSavingSets::<usize, hll::HyperLogLog<u128>>::new(&config);
+        benchmark_group.bench_with_input(
+            BenchmarkId::from_parameter(hot_capacity),
+            &hot_capacity,
+            |b, _| {
+                let mut i = 0;
+                b.iter(|| {
+                    let (label, item) = &entries[i];
+                    i = (i + 1) % entries.len();
+                    sketch.insert(black_box(*label), black_box(item));
+                })
+            },
+        );
+    }
+
+    benchmark_group.finish()
 }
 
 fn bench_top(c: &mut Criterion) {
@@ -187,9 +339,66 @@ fn bench_top_with<A, D>(
     );
 }
 
+fn bench_cardinality_many(c: &mut Criterion) {
+    let mut benchmark_group = c.benchmark_group("CardinalityMany");
+    benchmark_group.sample_size(10); // Querying Count-HLL takes a lot of time.
+
+    for_all_datasets!(|dataset| {
+        for_all_algorithms!(|algorithm| bench_cardinality_many_with(
+            &mut benchmark_group,
+            &algorithm,
+            &dataset,
+        ));
+    });
+
+    benchmark_group.finish()
+}
+
+/// Queries the labels of a dashboard refresh at once.
+fn bench_cardinality_many_with<A, D>(
+    benchmark_group: &mut BenchmarkGroup<impl Measurement>,
+    algorithm: &A,
+    dataset: &D,
+) where
+    A: Algorithm + MaxCapacity,
+    D: Dataset,
+    D::Label: Clone,
+    A::Sketch<D::Label, D::Item>: HeavyDistinctHitterSketch<Label = D::Label, Item = D::Item>,
+{
+    const NUM_LABELS: usize = 500;
+
+    let sketch = {
+        let mut sketch = algorithm.new_sketch(
+            algorithm.entries_for_mbs(MEMORY_SIZE, algorithm.optimal_counter_size()),
+            algorithm.optimal_counter_size(),
+        );
+        dataset
+            .iter()
+            .take(MAX_NUM_ENTRIES)
+            .for_each(|(label, item)| sketch.insert(label, &item));
+        sketch
+    };
+    let labels = sketch
+        .labels()
+        .take(NUM_LABELS)
+        .cloned()
+        .collect::<Vec<_>>();
+
+    benchmark_group.bench_with_input(
+        BenchmarkId::new(format!("{}", algorithm), dataset),
+        &MAX_NUM_ENTRIES,
+        |b, _| {
+            let mut out = Vec::with_capacity(labels.len());
+            b.iter(|| {
+                black_box(&sketch).cardinality_many(black_box(&labels), &mut out);
+            })
+        },
+    );
+}
+
 criterion_group!(
     name = hs_benchmarks;
     config = Criterion::default().with_profiler(PProfProfiler::new(100, Output::Flamegraph(None)));
-    targets = bench_insertion, bench_merge, bench_top,
+    targets = bench_hll_merge, bench_hll_insertion, bench_spread_insertion, bench_ssss_hot_counters, bench_insertion, bench_merge, bench_top, bench_cardinality_many,
 );
 criterion_main!(hs_benchmarks);
@@ -72,9 +72,12 @@ where
 {
     run_generative_case(num_entries, k, &algo::Achll, dataset, verbose);
     run_generative_case(num_entries, k, &algo::Schll, dataset, verbose);
+    run_generative_case(num_entries, k, &algo::Sschll, dataset, verbose);
     run_generative_case(num_entries, k, &algo::Osss, dataset, verbose);
     run_generative_case(num_entries, k, &algo::Rsss, dataset, verbose);
     run_generative_case(num_entries, k, &algo::Ssss, dataset, verbose);
+    run_generative_case(num_entries, k, &algo::SsssTz, dataset, verbose);
+    run_generative_case(num_entries, k, &algo::Ssss2h, dataset, verbose);
 }
 
 #[test]
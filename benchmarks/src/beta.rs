@@ -0,0 +1,179 @@
+//! The coefficients of the LogLog-Beta estimator (see
+//! `hll::Estimator::LogLogBeta`), published in `hll/src/beta_tables.rs`;
+//! regenerate them with the `beta-tables` command.
+//!
+//! For each precision, i.e., log of the number of registers `m`, sketches are
+//! fed distinct items, and their number of zero registers `z` and sum of
+//! `2^-r` are recorded at [`NUM_POINTS`] evenly spaced cardinalities up to
+//! `m * (ln(m) + 4)`, past which registers are hardly ever zero. Over enough
+//! runs for each precision to see 2^24 registers, beta, as a polynomial of
+//! `z` and `ln(z + 1)`, is fitted by least squares to what makes each
+//! estimate exact, weighted so that the relative errors of estimates are
+//! minimized.
+use std::fmt::Write;
+
+use hll::{Config, HyperLogLog};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use sketch_traits::{CardinalitySketch, New};
+
+const MIN_PRECISION: u32 = 4;
+const MAX_PRECISION: u32 = 18;
+
+/// The number of cardinalities the registers are recorded at, per precision.
+const NUM_POINTS: usize = 64;
+
+/// The registers each precision's fit is over.
+const REGISTERS_PER_PRECISION: u64 = 1 << 24;
+
+/// The coefficients of `z` and of the powers of `ln(z + 1)` in beta.
+pub const NUM_COEFFICIENTS: usize = 8;
+
+/// The bias correction of the raw estimator, as `hll::Config` has it.
+fn alpha(num_registers: u64) -> f64 {
+    match num_registers {
+        ..=16 => 0.673,
+        32 => 0.697,
+        64 => 0.709,
+        128 => 0.715,
+        256 => 0.718,
+        512 => 0.720,
+        _ => 0.7213 / (1.0 + 1.079 / (num_registers as f64)),
+    }
+}
+
+/// The coefficients of beta for `2^precision` registers.
+fn fit(precision: u32) -> [f64; NUM_COEFFICIENTS] {
+    let num_registers = 1_u64 << precision;
+    let m = num_registers as f64;
+    let num_runs = REGISTERS_PER_PRECISION / num_registers;
+    // The powers of `ln(z + 1)` are fitted relative to `ln(m + 1)`, and beta
+    // relative to `m`, for the normal equations to be well conditioned.
+    let scale = (m + 1.0).ln();
+    let max_cardinality = m * (m.ln() + 4.0);
+    let mut normal = [[0.0; NUM_COEFFICIENTS]; NUM_COEFFICIENTS];
+    let mut target = [0.0; NUM_COEFFICIENTS];
+    // Random seeds: the two hashers of seeds differing in few words are
+    // correlated.
+    let mut rng = StdRng::seed_from_u64(u64::from(precision));
+    for _ in 0..num_runs {
+        let config = Config::builder()
+            .registers(1 << precision)
+            .seeds(rng.gen())
+            .sparse_registers(false)
+            .build()
+            .expect("coefficients are for supported numbers of registers");
+        let mut sketch = HyperLogLog::<u64>::new(&config);
+        let mut inserted = 0;
+        for point in 1..=NUM_POINTS {
+            // Rounded to the nearest integer.
+            let cardinality = (max_cardinality * point as f64 / NUM_POINTS as f64).round() as u64;
+            (inserted..cardinality).for_each(|i| sketch.insert(&i));
+            inserted = cardinality;
+            let registers = sketch.registers();
+            let num_zeros = registers.iter().filter(|&&r| r == 0).count() as f64;
+            if num_zeros == 0.0 || cardinality == 0 {
+                continue;
+            }
+            let z_inv = registers
+                .iter()
+                .map(|&r| 2.0_f64.powi(-i32::from(r)))
+                .sum::<f64>();
+            // The denominator that makes the estimate exact, whose relative
+            // errors are those of the estimate.
+            let denominator = alpha(num_registers) * m * (m - num_zeros) / cardinality as f64;
+            let weight = (m / denominator).powi(2);
+            let features = features(num_zeros, m, scale);
+            let beta = (denominator - z_inv) / m;
+            for i in 0..NUM_COEFFICIENTS {
+                for j in 0..NUM_COEFFICIENTS {
+                    normal[i][j] += weight * features[i] * features[j];
+                }
+                target[i] += weight * features[i] * beta;
+            }
+        }
+    }
+    let mut coefficients = solve(normal, target);
+    // Back to the coefficients of `z` and of the powers of `ln(z + 1)`.
+    for (k, coefficient) in coefficients.iter_mut().enumerate().skip(1) {
+        *coefficient *= m / scale.powi(k as i32);
+    }
+    coefficients
+}
+
+/// `z / m` and the powers of `ln(z + 1) / scale`.
+fn features(num_zeros: f64, m: f64, scale: f64) -> [f64; NUM_COEFFICIENTS] {
+    let zl = (num_zeros + 1.0).ln() / scale;
+    let mut features = [num_zeros / m; NUM_COEFFICIENTS];
+    for (k, feature) in features.iter_mut().enumerate().skip(1) {
+        *feature = zl.powi(k as i32);
+    }
+    features
+}
+
+/// The solution of `a x = b`, by Gaussian elimination with partial pivoting.
+fn solve(
+    mut a: [[f64; NUM_COEFFICIENTS]; NUM_COEFFICIENTS],
+    mut b: [f64; NUM_COEFFICIENTS],
+) -> [f64; NUM_COEFFICIENTS] {
+    for col in 0..NUM_COEFFICIENTS {
+        let pivot = (col..NUM_COEFFICIENTS)
+            .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))
+            .expect("there are rows left");
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        for row in col + 1..NUM_COEFFICIENTS {
+            let factor = a[row][col] / a[col][col];
+            let pivot_row = a[col];
+            for (entry, pivot_entry) in a[row].iter_mut().zip(pivot_row).skip(col) {
+                *entry -= factor * pivot_entry;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+    let mut x = [0.0; NUM_COEFFICIENTS];
+    for row in (0..NUM_COEFFICIENTS).rev() {
+        let sum = (row + 1..NUM_COEFFICIENTS)
+            .map(|k| a[row][k] * x[k])
+            .sum::<f64>();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    x
+}
+
+/// The source of `hll/src/beta_tables.rs`.
+pub fn beta_tables() -> String {
+    let tables = (MIN_PRECISION..=MAX_PRECISION).map(fit).collect::<Vec<_>>();
+    let mut source = String::new();
+    writeln!(
+        source,
+        "//! Generated by `cargo run --release --bin benchmarks -- beta-tables \
+         hll/src/beta_tables.rs`,\n\
+         //! see `benchmarks/src/beta.rs`. Do not edit.\n\
+         //!\n\
+         //! Row `p - {}` holds the coefficients of `z` and of `ln(z + 1)^k`, for `k` in `1..{}`,\n\
+         //! in the beta of sketches of `m = 2^p` registers, `z` of which are zero.\n",
+        MIN_PRECISION, NUM_COEFFICIENTS,
+    )
+    .unwrap();
+    writeln!(source, "#[rustfmt::skip]").unwrap();
+    writeln!(
+        source,
+        "pub(crate) const BETAS: [[f64; {}]; {}] = [",
+        NUM_COEFFICIENTS,
+        tables.len()
+    )
+    .unwrap();
+    for coefficients in &tables {
+        writeln!(source, "    [").unwrap();
+        for line in coefficients.chunks(4) {
+            let line = line
+                .iter()
+                .map(|c| format!("{:.9e},", c))
+                .collect::<Vec<_>>();
+            writeln!(source, "        {}", line.join(" ")).unwrap();
+        }
+        writeln!(source, "    ],").unwrap();
+    }
+    writeln!(source, "];").unwrap();
+    source
+}
@@ -0,0 +1,120 @@
+//! The empirical bias tables of HyperLogLog++'s estimator (see
+//! `hll::Estimator::BiasCorrected`), published in `hll/src/bias_tables.rs`;
+//! regenerate them with the `bias-tables` command.
+//!
+//! For each precision, i.e., log of the number of registers `m`, sketches are
+//! fed distinct items, and their raw estimates are recorded at
+//! [`NUM_POINTS`] evenly spaced cardinalities from 0 to `5m`. Over enough
+//! runs for each table to see 2^24 registers, the mean raw estimates and
+//! their mean bias are tabulated relative to `m`, so that they read the same
+//! for any precision.
+use std::fmt::Write;
+
+use hll::{Config, HyperLogLog};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use sketch_traits::{CardinalitySketch, New};
+
+const MIN_PRECISION: u32 = 4;
+const MAX_PRECISION: u32 = 18;
+
+/// The number of cardinalities the bias is measured at, per precision.
+pub const NUM_POINTS: usize = 65;
+
+/// The registers each precision's measurements are averaged over.
+const REGISTERS_PER_PRECISION: u64 = 1 << 24;
+
+/// The mean raw estimates and biases at each point, relative to the number
+/// of registers.
+fn measure(precision: u32) -> (Vec<f64>, Vec<f64>) {
+    let num_registers = 1_u64 << precision;
+    let num_runs = REGISTERS_PER_PRECISION / num_registers;
+    let mut raw_estimates = vec![0.0; NUM_POINTS];
+    let mut biases = vec![0.0; NUM_POINTS];
+    // Random seeds: the two hashers of seeds differing in few words are
+    // correlated.
+    let mut rng = StdRng::seed_from_u64(u64::from(precision));
+    for _ in 0..num_runs {
+        let config = Config::builder()
+            .registers(1 << precision)
+            .seeds(rng.gen())
+            .sparse_registers(false)
+            .build()
+            .expect("tables are for supported numbers of registers");
+        let mut sketch = HyperLogLog::<u64>::new(&config);
+        let mut inserted = 0;
+        for point in 0..NUM_POINTS {
+            let cardinality = cardinality(num_registers, point);
+            (inserted..cardinality).for_each(|i| sketch.insert(&i));
+            inserted = cardinality;
+            let raw_estimate = sketch.raw_cardinality();
+            raw_estimates[point] += raw_estimate;
+            biases[point] += raw_estimate - cardinality as f64;
+        }
+    }
+    let scale = (num_runs * num_registers) as f64;
+    raw_estimates.iter_mut().for_each(|r| *r /= scale);
+    biases.iter_mut().for_each(|b| *b /= scale);
+    assert!(
+        raw_estimates.windows(2).all(|w| w[0] < w[1]),
+        "the mean raw estimates should increase with cardinalities"
+    );
+    (raw_estimates, biases)
+}
+
+/// The cardinality of `point`, `5m * point / (NUM_POINTS - 1)` rounded to the
+/// nearest integer.
+fn cardinality(num_registers: u64, point: usize) -> u64 {
+    let last_point = NUM_POINTS as u64 - 1;
+    (10 * num_registers * point as u64 + last_point) / (2 * last_point)
+}
+
+/// The source of `hll/src/bias_tables.rs`.
+pub fn bias_tables() -> String {
+    let tables = (MIN_PRECISION..=MAX_PRECISION)
+        .map(measure)
+        .collect::<Vec<_>>();
+    let mut source = String::new();
+    writeln!(
+        source,
+        "//! Generated by `cargo run --release --bin benchmarks -- bias-tables \
+         hll/src/bias_tables.rs`,\n\
+         //! see `benchmarks/src/bias.rs`. Do not edit.\n\
+         //!\n\
+         //! Row `p - {}` holds the mean raw estimates and biases of sketches of `m = 2^p`\n\
+         //! registers, relative to `m`, at cardinalities `5m * i / {}`, rounded, for `i` in `0..{}`.\n",
+        MIN_PRECISION,
+        NUM_POINTS - 1,
+        NUM_POINTS
+    )
+    .unwrap();
+    writeln!(
+        source,
+        "pub(crate) const NUM_POINTS: usize = {};",
+        NUM_POINTS
+    )
+    .unwrap();
+    for (name, column) in [("RAW_ESTIMATES", 0), ("BIASES", 1)] {
+        writeln!(source, "\n#[rustfmt::skip]").unwrap();
+        writeln!(
+            source,
+            "pub(crate) const {}: [[f64; NUM_POINTS]; {}] = [",
+            name,
+            tables.len()
+        )
+        .unwrap();
+        for table in &tables {
+            let values = if column == 0 { &table.0 } else { &table.1 };
+            writeln!(source, "    [").unwrap();
+            for line in values.chunks(8) {
+                let line = line
+                    .iter()
+                    .map(|v| format!("{:.6},", v))
+                    .collect::<Vec<_>>();
+                writeln!(source, "        {}", line.join(" ")).unwrap();
+            }
+            writeln!(source, "    ],").unwrap();
+        }
+        writeln!(source, "];").unwrap();
+    }
+    source
+}
@@ -0,0 +1,31 @@
+//! Writes the deterministic fixtures of a scenario (see
+//! [`benchmarks::fixtures`]) to a directory, for downstream repos to test
+//! their integrations of the sketches against.
+use std::{fs, io, path::PathBuf};
+
+use benchmarks::fixtures::{fixtures, Scenario};
+use clap::Parser;
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// Scenario to generate the dataset of
+    #[clap(short, long, value_enum)]
+    scenario: Scenario,
+
+    /// Seed of the dataset
+    #[clap(long, value_parser, default_value_t = 0)]
+    seed: u64,
+
+    /// Directory to write the fixtures to, created if needed
+    output: PathBuf,
+}
+
+fn main() -> io::Result<()> {
+    let args = Args::parse();
+    fs::create_dir_all(&args.output)?;
+    for (name, contents) in fixtures(args.scenario, args.seed) {
+        fs::write(args.output.join(name), contents)?;
+    }
+    Ok(())
+}
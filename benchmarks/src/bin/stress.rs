@@ -0,0 +1,262 @@
+//! Inserts synthetic (src, dst) pairs into a sketch shared by several
+//! threads, as a network probe would, and reports the sustained throughput,
+//! the share of pairs dropped, and insert latencies.
+//!
+//! Threads either insert into the sketch behind a mutex, or, with
+//! `--queue-capacity`, hand batches of pairs off through a bounded queue to a
+//! thread owning the sketch, dropping them when the queue is full. Sources
+//! follow a Zipf distribution, so that a few of them are superspreaders.
+extern crate clap;
+use std::{
+    sync::{
+        mpsc::{self, TrySendError},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use benchmarks::algo::{Algorithm, Spread, Ssss};
+use clap::{Parser, ValueEnum};
+use num_format::{Locale, ToFormattedString};
+use rand::{distributions::Distribution, thread_rng, Rng};
+use sketch_traits::HeavyDistinctHitterSketch;
+
+const BATCH_SIZE: usize = 1024;
+/// Only one in this many inserts is timed, so that timing doesn't dominate.
+const LATENCY_SAMPLING: usize = 16;
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// Sketch type
+    #[clap(short, long, value_enum, default_value_t = StressedSketch::Ssss)]
+    sketch_type: StressedSketch,
+
+    /// Number of threads generating pairs
+    #[clap(short, long, value_parser, default_value_t = 4)]
+    threads: usize,
+
+    /// Pairs generated per second across all threads, or 0 for as many as
+    /// possible
+    #[clap(short, long, value_parser, default_value_t = 0)]
+    rate: u64,
+
+    /// How long to generate pairs for, in seconds
+    #[clap(short, long, value_parser, default_value_t = 10)]
+    duration: u64,
+
+    /// Hand pairs off through a bounded queue of this many batches instead of
+    /// locking the sketch
+    #[clap(short, long, value_parser)]
+    queue_capacity: Option<usize>,
+
+    /// Number of distinct sources
+    #[clap(long, value_parser, default_value_t = 100_000)]
+    sources: usize,
+
+    /// Number of distinct destinations
+    #[clap(long, value_parser, default_value_t = 1_000_000)]
+    destinations: u32,
+
+    /// Number of entries kept by the sketch
+    #[clap(short, long, value_parser, default_value_t = 100)]
+    entries: usize,
+
+    /// The size of the cardinality counters
+    #[clap(short, long, value_parser, default_value_t = 1024)]
+    counter_size: usize,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum StressedSketch {
+    Spread,
+    Ssss,
+}
+
+#[derive(Default)]
+struct Report {
+    generated: u64,
+    inserted: u64,
+    /// Sampled insert latencies, in nanoseconds.
+    latencies: Vec<u64>,
+}
+
+impl Report {
+    fn add(&mut self, other: Report) {
+        self.generated += other.generated;
+        self.inserted += other.inserted;
+        self.latencies.extend(other.latencies);
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    match args.sketch_type {
+        StressedSketch::Spread => stress(&Spread, &args),
+        StressedSketch::Ssss => stress(&Ssss, &args),
+    }
+}
+
+fn stress<A>(algorithm: &A, args: &Args)
+where
+    A: Algorithm,
+    A::Sketch<u32, u32>: HeavyDistinctHitterSketch<Label = u32, Item = u32> + Send,
+{
+    let sketch = algorithm.new_sketch(args.entries, args.counter_size);
+    let start = Instant::now();
+    let (sketch, mut report) = match args.queue_capacity {
+        None => stress_shared(sketch, args),
+        Some(capacity) => stress_queued(sketch, capacity, args),
+    };
+    let elapsed = start.elapsed();
+
+    report.latencies.sort_unstable();
+    let percentile = |p: f64| {
+        let i = ((report.latencies.len() as f64 * p) as usize).min(report.latencies.len() - 1);
+        Duration::from_nanos(report.latencies[i])
+    };
+    let dropped = report.generated - report.inserted;
+    println!("{} with {} threads", algorithm, args.threads);
+    println!(
+        "inserted {} pairs in {:.2?}: {} pairs/s",
+        report.inserted.to_formatted_string(&Locale::en),
+        elapsed,
+        ((report.inserted as f64 / elapsed.as_secs_f64()) as u64).to_formatted_string(&Locale::en),
+    );
+    println!(
+        "dropped {} pairs ({:.3}%)",
+        dropped.to_formatted_string(&Locale::en),
+        100.0 * dropped as f64 / report.generated.max(1) as f64,
+    );
+    if !report.latencies.is_empty() {
+        println!(
+            "insert latency: p50 {:?}, p99 {:?}, max {:?}",
+            percentile(0.5),
+            percentile(0.99),
+            percentile(1.0),
+        );
+    }
+    println!("top sources:");
+    for (label, cardinality) in sketch.top(5) {
+        println!("  {}: {}", label, cardinality);
+    }
+}
+
+/// Every thread locks the sketch for each of its inserts.
+fn stress_shared<S>(sketch: S, args: &Args) -> (S, Report)
+where
+    S: HeavyDistinctHitterSketch<Label = u32, Item = u32> + Send,
+{
+    let sketch = Mutex::new(sketch);
+    let report = thread::scope(|scope| {
+        let threads = (0..args.threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut report = Report::default();
+                    generate(args, |batch| {
+                        report.generated += batch.len() as u64;
+                        for (i, &(src, dst)) in batch.iter().enumerate() {
+                            let start = Instant::now();
+                            sketch
+                                .lock()
+                                .unwrap_or_else(|e| e.into_inner())
+                                .insert(src, &dst);
+                            if i % LATENCY_SAMPLING == 0 {
+                                report.latencies.push(start.elapsed().as_nanos() as u64);
+                            }
+                        }
+                        report.inserted += batch.len() as u64;
+                    });
+                    report
+                })
+            })
+            .collect::<Vec<_>>();
+        threads
+            .into_iter()
+            .fold(Report::default(), |mut report, t| {
+                report.add(t.join().unwrap());
+                report
+            })
+    });
+    (
+        sketch.into_inner().unwrap_or_else(|e| e.into_inner()),
+        report,
+    )
+}
+
+/// Threads hand batches off to a thread owning the sketch, and drop them
+/// when the queue is full. Latencies are those of the owning thread's
+/// inserts.
+fn stress_queued<S>(mut sketch: S, capacity: usize, args: &Args) -> (S, Report)
+where
+    S: HeavyDistinctHitterSketch<Label = u32, Item = u32> + Send,
+{
+    let (sender, receiver) = mpsc::sync_channel::<Vec<(u32, u32)>>(capacity);
+    let report = thread::scope(|scope| {
+        let consumer = scope.spawn(|| {
+            let mut report = Report::default();
+            for batch in receiver {
+                for (i, (src, dst)) in batch.into_iter().enumerate() {
+                    let start = Instant::now();
+                    sketch.insert(src, &dst);
+                    if i % LATENCY_SAMPLING == 0 {
+                        report.latencies.push(start.elapsed().as_nanos() as u64);
+                    }
+                    report.inserted += 1;
+                }
+            }
+            report
+        });
+        let producers = (0..args.threads)
+            .map(|_| {
+                let sender = sender.clone();
+                scope.spawn(move || {
+                    let mut generated = 0;
+                    generate(args, |batch| {
+                        generated += batch.len() as u64;
+                        match sender.try_send(batch.to_vec()) {
+                            Ok(()) | Err(TrySendError::Full(_)) => {}
+                            Err(TrySendError::Disconnected(_)) => unreachable!(),
+                        }
+                    });
+                    generated
+                })
+            })
+            .collect::<Vec<_>>();
+        drop(sender);
+        let generated = producers.into_iter().map(|t| t.join().unwrap()).sum();
+        let mut report = consumer.join().unwrap();
+        report.generated = generated;
+        report
+    });
+    (sketch, report)
+}
+
+/// Generates batches of pairs for `args.duration`, at this thread's share of
+/// `args.rate`.
+fn generate(args: &Args, mut f: impl FnMut(&[(u32, u32)])) {
+    let mut rng = thread_rng();
+    let sources = zipf::ZipfDistribution::new(args.sources, 1.0).unwrap();
+    let per_thread_rate = args.rate as f64 / args.threads as f64;
+    let duration = Duration::from_secs(args.duration);
+    let start = Instant::now();
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    let mut generated = 0;
+    while start.elapsed() < duration {
+        batch.clear();
+        batch.extend((0..BATCH_SIZE).map(|_| {
+            let src = sources.sample(&mut rng) as u32;
+            (src, rng.gen_range(0..args.destinations))
+        }));
+        f(&batch);
+        generated += BATCH_SIZE;
+        if args.rate > 0 {
+            // Sleep until the next batch is due.
+            let due = Duration::from_secs_f64(generated as f64 / per_thread_rate);
+            if let Some(ahead) = due.checked_sub(start.elapsed()) {
+                thread::sleep(ahead);
+            }
+        }
+    }
+}
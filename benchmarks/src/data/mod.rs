@@ -64,6 +64,58 @@ impl Dataset for FolderDataset {
     }
 }
 
+/// Like [`FolderDataset`], but with labels made of the `num_label_fields`
+/// columns that follow the item column, e.g., `(customer_id, endpoint)`.
+#[derive(Clone, Debug)]
+pub struct CompositeFolderDataset {
+    path: PathBuf,
+    max_per_file: usize,
+    num_label_fields: usize,
+}
+
+impl CompositeFolderDataset {
+    pub fn new(path: impl AsRef<Path>, max_per_file: usize, num_label_fields: usize) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            max_per_file,
+            num_label_fields,
+        }
+    }
+}
+
+impl fmt::Display for CompositeFolderDataset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path.file_name().unwrap().to_str().unwrap())
+    }
+}
+
+impl Dataset for CompositeFolderDataset {
+    type Label = Vec<String>;
+    type Item = String;
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (Self::Label, Self::Item)>> {
+        let max_per_file = self.max_per_file;
+        let num_label_fields = self.num_label_fields;
+        Box::new(
+            read_dir(&self.path)
+                .unwrap()
+                .map(|path| path.unwrap())
+                .map(|dir_entry| dir_entry.path())
+                .flat_map(move |file_path| {
+                    BufReader::new(GzDecoder::new(File::open(file_path).unwrap()))
+                        .lines()
+                        .take(max_per_file)
+                        .map(move |line| {
+                            let line_str = line.unwrap();
+                            let mut fields = line_str.split(',').map(|field| field.to_string());
+                            let item = fields.next().unwrap();
+                            (fields.take(num_label_fields).collect(), item)
+                        })
+                }),
+        )
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct FileDataset {
     path: PathBuf,
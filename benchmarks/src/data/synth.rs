@@ -252,13 +252,13 @@ pub struct Overlap {
     k_small: u32,
     n_big: usize,
     verbose: bool,
-    data: Vec<(Uuid, u32)>,
+    data: Vec<(u128, u32)>,
 }
 
 impl Overlap {
-    const UNIVERSE_SIZE: u32 = 1_000_000;
-    const COMMON_SIZE: u32 = 100_000;
-    const N_SMALL: usize = 1000; // size of small sets from common
+    pub const UNIVERSE_SIZE: u32 = 1_000_000;
+    pub const COMMON_SIZE: u32 = 100_000;
+    pub const N_SMALL: usize = 1000; // size of small sets from common
     pub const K_BIG: usize = 1000; // # of big sets from full universe
 
     // const N_BIG: [u32; 5] = [20_000, 50_000, 100_000, 200_000, 500_000];  // size of big sets from full universe
@@ -269,10 +269,9 @@ impl Overlap {
         let mut data = Vec::new();
         let common_items: Vec<u32> = (0..Self::COMMON_SIZE).collect();
         for _ in 0..k_small {
-            let label = Uuid::new_v4();
+            let label = Uuid::new_v4().as_u128();
             let mut small_set: Vec<_> = common_items
                 .choose_multiple(&mut rng, Self::N_SMALL)
-                .into_iter()
                 .map(|&i| (label, i))
                 .collect();
             data.append(&mut small_set);
@@ -280,10 +279,9 @@ impl Overlap {
 
         let full_universe: Vec<u32> = (0..Self::UNIVERSE_SIZE).collect();
         for _ in 0..Self::K_BIG {
-            let label = Uuid::new_v4();
+            let label = Uuid::new_v4().as_u128();
             let mut large_set: Vec<_> = full_universe
                 .choose_multiple(&mut rng, n_big)
-                .into_iter()
                 .map(|&i| (label, i))
                 .collect();
             data.append(&mut large_set);
@@ -307,7 +305,7 @@ impl fmt::Display for Overlap {
 }
 
 impl Dataset for Overlap {
-    type Label = Uuid;
+    type Label = u128;
     type Item = u32;
 
     fn iter(&self) -> Box<dyn Iterator<Item = (Self::Label, Self::Item)>> {
@@ -332,14 +330,14 @@ impl Dataset for Overlap {
 }
 
 pub struct Zipf {
-    data: Vec<(usize, Uuid)>,
+    data: Vec<(usize, u128)>,
 }
 
 impl Zipf {
     pub fn new(num_labels: usize, exponent: f64, num_samples: usize, verbose: bool) -> Self {
         let mut rng = rand::thread_rng();
         let zipf = zipf::ZipfDistribution::new(num_labels, exponent).unwrap();
-        let mut data: Vec<(usize, Uuid)> = Vec::new();
+        let mut data: Vec<(usize, u128)> = Vec::new();
 
         if verbose {
             println!();
@@ -351,7 +349,7 @@ impl Zipf {
 
         for _ in 0..num_samples {
             let label = zipf.sample(&mut rng);
-            data.push((label, Uuid::new_v4()));
+            data.push((label, Uuid::new_v4().as_u128()));
         }
         data.shuffle(&mut rng);
 
@@ -367,7 +365,7 @@ impl fmt::Display for Zipf {
 
 impl Dataset for Zipf {
     type Label = usize;
-    type Item = Uuid;
+    type Item = u128;
 
     fn iter(&self) -> Box<dyn Iterator<Item = (Self::Label, Self::Item)>> {
         let data_copy = self.data.to_vec();
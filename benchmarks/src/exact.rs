@@ -1,16 +1,14 @@
+use ordered_float::NotNan;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{hash_map::Entry, HashMap, HashSet},
     convert::Infallible,
     fmt::Debug,
     hash::Hash,
-    mem::size_of_val,
+    io::{self, Write},
 };
-use ordered_float::NotNan;
 
 use itertools::Itertools;
-use sketch_traits::HeavyDistinctHitterSketch;
-
-use crate::memory::MemorySize;
+use sketch_traits::{HeavyDistinctHitterSketch, InsertOutcome, LabelKey, MemorySize};
 
 #[derive(Clone, Debug, Default)]
 pub struct GroundTruth<L, I> {
@@ -27,8 +25,21 @@ where
     type MergeError = Infallible;
 
     #[inline]
-    fn insert(&mut self, label: Self::Label, item: &Self::Item) {
-        self.sets.entry(label).or_default().insert(item.clone());
+    fn insert_with_outcome(
+        &mut self,
+        label: Self::Label,
+        item: &Self::Item,
+    ) -> InsertOutcome<Self::Label> {
+        match self.sets.entry(label) {
+            Entry::Occupied(mut entry) => {
+                entry.get_mut().insert(item.clone());
+                InsertOutcome::UpdatedExisting
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(HashSet::from([item.clone()]));
+                InsertOutcome::CreatedNew
+            }
+        }
     }
 
     #[inline]
@@ -41,7 +52,11 @@ where
 
     #[inline]
     fn clear(&mut self) {
-        todo!()
+        self.sets.clear();
+    }
+
+    fn remove(&mut self, label: &Self::Label) -> bool {
+        self.sets.remove(label).is_some()
     }
 
     #[inline]
@@ -49,18 +64,32 @@ where
         self.sets.get(label).map(HashSet::len).unwrap_or(0) as u64
     }
 
+    fn total_cardinality(&self) -> Option<u64> {
+        Some(self.sets.values().flatten().collect::<HashSet<_>>().len() as u64)
+    }
+
     #[inline]
+    fn labels(&self) -> impl Iterator<Item = &L> {
+        self.sets.keys()
+    }
+
+    #[inline]
+    fn num_labels(&self) -> usize {
+        self.sets.len()
+    }
+
     fn top(&self, k: usize) -> Vec<(&Self::Label, u64)> {
         self.top_cardinalities().take(k).collect::<Vec<_>>()
     }
 }
 
-impl<L, I> MemorySize for GroundTruth<L, I> {
-    fn mem_size(&self) -> usize {
-        self.sets
-            .iter()
-            .map(|(l, i)| size_of_val(l) + size_of_val(i))
-            .sum::<usize>()
+impl<L, I> MemorySize for GroundTruth<L, I>
+where
+    L: MemorySize,
+    I: MemorySize,
+{
+    fn heap_size(&self) -> usize {
+        self.sets.heap_size()
     }
 }
 
@@ -75,10 +104,6 @@ where
         }
     }
 
-    pub fn num_labels(&self) -> usize {
-        self.sets.len()
-    }
-
     pub fn top_cardinalities(&self) -> impl Iterator<Item = (&L, u64)> {
         self.sets
             .iter()
@@ -127,7 +152,7 @@ where
     }
 
     pub fn l1norm(&self) -> f64 {
-        self.sets.values().map(|items| (items.len())).sum::<usize>() as f64
+        self.sets.values().map(|items| items.len()).sum::<usize>() as f64
     }
 
     pub fn l2norm2(&self) -> f64 {
@@ -142,15 +167,66 @@ where
     }
 
     pub fn percentile(&self, p: f64) -> usize {
-        let mut sizes: Vec<usize> = self.sets.values().map(|items| (items.len())).collect();
+        let mut sizes: Vec<usize> = self.sets.values().map(|items| items.len()).collect();
         sizes.sort();
         // TODO: deal with boundary conditions
         let location = (p * sizes.len() as f64) as usize;
         sizes[location]
     }
 
+    /// Writes a CSV row for each of the true and sketched top `k` labels
+    /// whose estimate is off by more than `min_rel_error`, along with whether
+    /// the sketch tracks the label, to look for systematic failure modes that
+    /// aggregate metrics hide. Returns the number of rows written.
+    pub fn write_disagreements(
+        &self,
+        sketch: &impl HeavyDistinctHitterSketch<Label = L, Item = I>,
+        k: usize,
+        min_rel_error: f64,
+        out: &mut impl Write,
+    ) -> io::Result<usize>
+    where
+        L: LabelKey,
+    {
+        let tracked = sketch
+            .top(usize::MAX)
+            .into_iter()
+            .map(|(label, _)| label)
+            .collect::<HashSet<_>>();
+        let labels = self
+            .top_cardinalities()
+            .take(k)
+            .map(|(label, _)| label)
+            .chain(sketch.top(k).into_iter().map(|(label, _)| label))
+            .unique();
+
+        writeln!(
+            out,
+            "label,true_cardinality,estimate,relative_error,tracked"
+        )?;
+        let mut num_rows = 0;
+        for label in labels {
+            let cardinality = self.sets.get(label).map_or(0, HashSet::len) as u64;
+            let estimate = sketch.cardinality(label);
+            let rel_error = (estimate as f64 - cardinality as f64).abs() / cardinality as f64;
+            if rel_error > min_rel_error {
+                writeln!(
+                    out,
+                    "{},{},{},{:.4},{}",
+                    csv_field(&label.display().to_string()),
+                    cardinality,
+                    estimate,
+                    rel_error,
+                    tracked.contains(label),
+                )?;
+                num_rows += 1;
+            }
+        }
+        Ok(num_rows)
+    }
+
     pub fn max(&self) -> usize {
-        self.sets.values().map(|items| (items.len())).max().unwrap()
+        self.sets.values().map(|items| items.len()).max().unwrap()
     }
 
     /// Return an iterator (in true cardinality order) over the relative errors
@@ -299,14 +375,14 @@ where
     }
 
     /// Relative Mean Absolute Error over Sketch Top
-    pub fn sketch_rmae(&self, sketch_top: &Vec<(&L, u64)>) -> f64 {
+    pub fn sketch_rmae(&self, sketch_top: &[(&L, u64)]) -> f64 {
         let n = sketch_top.len();
 
         rel_l1(&mut self.rel_sketch_errors(sketch_top.iter()), n)
     }
 
     /// Relative Root Mean Square Error over Sketch Top
-    pub fn sketch_rrmse(&self, sketch_top: &Vec<(&L, u64)>) -> f64 {
+    pub fn sketch_rrmse(&self, sketch_top: &[(&L, u64)]) -> f64 {
         let n = sketch_top.len();
         rel_l2(&mut self.rel_sketch_errors(sketch_top.iter()), n)
     }
@@ -408,6 +484,15 @@ where
 }
 
 /// Calculate the L1 relative error
+/// Quotes a CSV field if it contains separators, quotes or newlines.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
 pub fn rel_l1(rel_errs: &mut dyn Iterator<Item = f64>, k: usize) -> f64 {
     rel_errs
         .take(k)
@@ -426,10 +511,9 @@ pub fn rel_l2(rel_errs: &mut dyn Iterator<Item = f64>, k: usize) -> f64 {
 
 #[cfg(test)]
 mod tests {
-    use sketch_traits::HeavyDistinctHitterSketch;
+    use sketch_traits::{HeavyDistinctHitterSketch, MemorySize};
 
     use super::GroundTruth;
-    use crate::memory::MemorySize;
 
     #[test]
     fn ground_truth_is_exact() {
@@ -489,4 +573,28 @@ mod tests {
         ground_truth.print_top(&great_sketch, 10);
         ground_truth.mem_size();
     }
+
+    #[test]
+    fn it_writes_disagreements() {
+        let mut ground_truth: GroundTruth<u32, u32> = GroundTruth::new();
+        let mut sketch: GroundTruth<u32, u32> = GroundTruth::new();
+        for i in 1..11 {
+            for j in 0..10 * i {
+                ground_truth.insert(i, &j);
+                // Label 10 is underestimated by half.
+                if i < 10 || j % 2 == 0 {
+                    sketch.insert(i, &j);
+                }
+            }
+        }
+        let mut out = Vec::new();
+        let num_rows = ground_truth
+            .write_disagreements(&sketch, 5, 0.1, &mut out)
+            .unwrap();
+        assert_eq!(num_rows, 1);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "label,true_cardinality,estimate,relative_error,tracked\n10,100,50,0.5000,true\n"
+        );
+    }
 }
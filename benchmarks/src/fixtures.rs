@@ -0,0 +1,192 @@
+//! Deterministic fixtures, for repos integrating the sketches to test against
+//! the same datasets, sketches and query results.
+//!
+//! A scenario and a seed determine a dataset of (label, item) pairs, which is
+//! inserted into each sketch with the fixed seeds of [`algo`]. The fixtures
+//! are the dataset, each sketch serialized with serde as JSON, the SSSS
+//! sketch in its binary codec too, and the expected query results of each
+//! sketch along with the exact cardinalities of the labels.
+//!
+//! Datasets are drawn from rand's `StdRng`, so datasets and expected results
+//! only change with the seed, the scenario, or an intended change of the
+//! sketches. Serialized sketches list the entries of hash maps in their
+//! iteration order, so they may differ byte for byte across runs while
+//! holding the same state. SSS is left out, since it breaks ties between
+//! its minimum counters arbitrarily (see [`crate::vectors`]).
+use std::collections::{HashMap, HashSet};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sketch_traits::{codec::Encode, HeavyDistinctHitterSketch};
+
+use crate::algo::{self, Algorithm};
+
+/// The number of entries every sketch keeps.
+const SKETCH_SIZE: usize = 32;
+/// The number of pairs of every dataset.
+const NUM_PAIRS: usize = 20_000;
+/// The number of labels the expected results list.
+const TOP_K: usize = 10;
+
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Scenario {
+    /// Labels drawn uniformly out of 1,000, with random items.
+    Uniform,
+    /// Labels following a Zipf distribution, with random items.
+    Zipf,
+    /// A handful of labels with many distinct items, among many labels
+    /// repeating a few items.
+    Superspreaders,
+}
+
+/// The pairs of `scenario`, drawn from `seed`.
+pub fn dataset(scenario: Scenario, seed: u64) -> Vec<(u64, u64)> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    match scenario {
+        Scenario::Uniform => (0..NUM_PAIRS)
+            .map(|_| (rng.gen_range(0..1_000), rng.gen()))
+            .collect(),
+        Scenario::Zipf => {
+            let labels = zipf::ZipfDistribution::new(1_000, 1.0).unwrap();
+            (0..NUM_PAIRS)
+                .map(|_| (rng.sample(labels) as u64, rng.gen()))
+                .collect()
+        }
+        Scenario::Superspreaders => (0..NUM_PAIRS)
+            .map(|_| {
+                if rng.gen_bool(0.5) {
+                    (rng.gen_range(0..5), rng.gen())
+                } else {
+                    (rng.gen_range(5..5_000), rng.gen_range(0..3))
+                }
+            })
+            .collect(),
+    }
+}
+
+/// The fixtures of `scenario` for `seed`, as file names and contents.
+pub fn fixtures(scenario: Scenario, seed: u64) -> Vec<(String, Vec<u8>)> {
+    let pairs = dataset(scenario, seed);
+    let mut files = vec![(
+        "dataset.json".to_string(),
+        to_json(&json!({ "scenario": format!("{:?}", scenario), "seed": seed, "pairs": pairs })),
+    )];
+    let mut expected = serde_json::Map::new();
+    expected.insert("exact".to_string(), exact(&pairs));
+
+    let mut add = |name: &str, sketch: Value, results: Value| {
+        files.push((format!("{}.json", name), to_json(&sketch)));
+        expected.insert(name.to_string(), results);
+    };
+    macro_rules! add_sketches {
+        ($($name:literal => $algorithm:expr),*) => {
+            $(
+                let (sketch, results) = build(&$algorithm, &pairs);
+                add($name, sketch, results);
+            )*
+        };
+    }
+    add_sketches!(
+        "achll" => algo::Achll,
+        "schll" => algo::Schll,
+        "sschll" => algo::Sschll,
+        "spread" => algo::Spread,
+        "ssss" => algo::Ssss,
+        "sssstz" => algo::SsssTz,
+        "ssss2h" => algo::Ssss2h
+    );
+
+    let mut ssss = algo::Ssss.new_sketch(SKETCH_SIZE, algo::Ssss.optimal_counter_size());
+    pairs
+        .iter()
+        .for_each(|(label, item)| ssss.insert(*label, item));
+    let mut payload = vec![];
+    ssss.encode(&mut payload);
+    files.push(("ssss.bin".to_string(), payload));
+    files.push(("expected.json".to_string(), to_json(&Value::from(expected))));
+    files
+}
+
+/// The sketch of `algorithm` over `pairs`, serialized, and its expected query
+/// results.
+fn build<A>(algorithm: &A, pairs: &[(u64, u64)]) -> (Value, Value)
+where
+    A: Algorithm,
+    A::Sketch<u64, u64>: HeavyDistinctHitterSketch<Label = u64, Item = u64> + Serialize,
+{
+    let counter_size = algorithm.optimal_counter_size();
+    let mut sketch = algorithm.new_sketch(SKETCH_SIZE, counter_size);
+    pairs
+        .iter()
+        .for_each(|(label, item)| sketch.insert(*label, item));
+    // Ties are listed by label, so that results don't depend on the order of
+    // hash maps.
+    let mut top = sketch.top(usize::MAX);
+    top.sort_unstable_by_key(|&(label, cardinality)| (std::cmp::Reverse(cardinality), *label));
+    top.truncate(TOP_K);
+    let results = json!({
+        "sketch_size": SKETCH_SIZE,
+        "counter_size": counter_size,
+        "top": top,
+        "cardinalities": (0..TOP_K as u64)
+            .map(|label| (label, sketch.cardinality(&label)))
+            .collect::<Vec<_>>(),
+    });
+    (
+        serde_json::to_value(&sketch).expect("sketches serialize to JSON"),
+        results,
+    )
+}
+
+/// The exact cardinalities of the `TOP_K` labels with the most distinct
+/// items.
+fn exact(pairs: &[(u64, u64)]) -> Value {
+    let mut sets = HashMap::<u64, HashSet<u64>>::new();
+    for &(label, item) in pairs {
+        sets.entry(label).or_default().insert(item);
+    }
+    let mut top = sets
+        .into_iter()
+        .map(|(label, items)| (label, items.len() as u64))
+        .collect::<Vec<_>>();
+    top.sort_unstable_by_key(|&(label, cardinality)| (std::cmp::Reverse(cardinality), label));
+    top.truncate(TOP_K);
+    json!(top)
+}
+
+fn to_json(value: &Value) -> Vec<u8> {
+    let mut json = serde_json::to_vec_pretty(value).expect("values serialize to JSON");
+    json.push(b'\n');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixtures_only_depend_on_the_seed() {
+        for scenario in [Scenario::Uniform, Scenario::Zipf, Scenario::Superspreaders] {
+            assert_eq!(dataset(scenario, 7), dataset(scenario, 7));
+            assert_ne!(dataset(scenario, 7), dataset(scenario, 8));
+        }
+        let file = |files: &[(String, Vec<u8>)], name: &str| {
+            files
+                .iter()
+                .find(|(file_name, _)| file_name == name)
+                .map(|(_, contents)| contents.clone())
+                .unwrap()
+        };
+        let files = fixtures(Scenario::Superspreaders, 7);
+        let again = fixtures(Scenario::Superspreaders, 7);
+        for name in ["dataset.json", "expected.json", "ssss.bin"] {
+            assert_eq!(file(&files, name), file(&again, name), "{}", name);
+        }
+
+        let expected: Value = serde_json::from_slice(&file(&files, "expected.json")).unwrap();
+        let exact_top = expected["exact"][0][0].as_u64().unwrap();
+        assert!(exact_top < 5);
+        assert_eq!(expected["ssss"]["top"].as_array().unwrap().len(), TOP_K);
+    }
+}
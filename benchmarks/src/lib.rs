@@ -1,4 +1,51 @@
-// Exposed for perf benchmarks.
-pub mod algo;
+//! The benchmark harness, as a library so that sweeps can be run
+//! programmatically. The `benchmarks` binary is a thin CLI over [`sweep`].
+use std::fmt;
+
+mod accuracy;
+pub mod beta;
+pub mod bias;
 pub mod data;
+pub mod exact;
+pub mod fixtures;
 pub mod memory;
+pub mod sweep;
+pub mod vectors;
+
+pub use ssss_sketches::algo;
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum SketchType {
+    Achll,
+    Schll,
+    Sschll,
+    Osss,
+    Rsss,
+    Spread,
+    Ssss,
+    SsssTz,
+    Ssss2h,
+}
+
+impl fmt::Display for SketchType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+macro_rules! specialized_dispatch {
+    ($sketch_type:ident, $fn:expr) => {
+        match $sketch_type {
+            crate::SketchType::Achll => $fn(crate::algo::Achll),
+            crate::SketchType::Schll => $fn(crate::algo::Schll),
+            crate::SketchType::Sschll => $fn(crate::algo::Sschll),
+            crate::SketchType::Osss => $fn(crate::algo::Osss),
+            crate::SketchType::Rsss => $fn(crate::algo::Rsss),
+            crate::SketchType::Spread => $fn(crate::algo::Spread),
+            crate::SketchType::Ssss => $fn(crate::algo::Ssss),
+            crate::SketchType::SsssTz => $fn(crate::algo::SsssTz),
+            crate::SketchType::Ssss2h => $fn(crate::algo::Ssss2h),
+        }
+    };
+}
+pub(crate) use specialized_dispatch;
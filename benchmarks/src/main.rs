@@ -1,20 +1,27 @@
 extern crate clap;
-use std::{fmt, path::PathBuf};
-
+use std::{fs, io, path::PathBuf};
+
+use benchmarks::{
+    beta::beta_tables,
+    bias::bias_tables,
+    data::synth::Overlap,
+    sweep::{
+        run_combos, run_merge_rounds, run_overlap, run_sketch, run_zipf, ComboType, Disagreements,
+    },
+    vectors::test_vectors,
+    SketchType,
+};
 use clap::{ArgAction, Parser, Subcommand};
 
-use crate::dataset::{run_combos, run_overlap, run_zipf, run_sketch, ComboType};
+use crate::report::{print_merge_rounds, print_sweep};
 
-pub mod accuracy;
-pub mod algo;
-pub mod data;
-pub mod dataset;
-pub mod exact;
-pub mod memory;
+mod report;
 
 const DEFAULT_COUNTER_SIZE: usize = 1024;
 const DEFAULT_COUNTER_SIZES: [usize; 7] = [32, 64, 128, 256, 512, 1024, 2048];
+const DEFAULT_DISAGREEMENT_THRESHOLD: f64 = 0.1;
 const DEFAULT_MAX_PER_FILE: usize = 100_000_000;
+const DEFAULT_MERGE_ROUNDS: u32 = 6;
 const DEFAULT_MEMORY: [f32; 5] = [1.0, 2.0, 3.0, 4.0, 5.0];
 const DEFAULT_NUM_SKETCH_ENTRIES: usize = 100;
 const DEFAULT_SKETCH_TYPES: [SketchType; 3] =
@@ -54,6 +61,14 @@ enum Command {
         #[clap(short, long, value_parser, default_value_t=DEFAULT_COUNTER_SIZE)]
         counter_size: usize,
 
+        /// Write the labels whose estimates are off to a CSV file
+        #[clap(long, value_parser)]
+        disagreements: Option<PathBuf>,
+
+        /// Relative error above which estimates are considered off
+        #[clap(long, value_parser, default_value_t=DEFAULT_DISAGREEMENT_THRESHOLD)]
+        disagreement_threshold: f64,
+
         /// Control the amount of output
         #[clap(short, long, action = ArgAction::SetTrue)]
         verbose: bool,
@@ -111,6 +126,37 @@ enum Command {
         verbose: bool,
     },
 
+    /// Split a real dataset into 2^r shards, sketch them, then merge the
+    /// sketches pairwise over r rounds, for every r up to the given number
+    MergeRounds {
+        /// Path to dataset
+        input: PathBuf,
+
+        /// Number of lines to take per file
+        #[clap(long, value_parser, default_value_t=DEFAULT_MAX_PER_FILE)]
+        max_per_file: usize,
+
+        /// Max number of merge rounds
+        #[clap(short, long, value_parser, default_value_t=DEFAULT_MERGE_ROUNDS)]
+        rounds: u32,
+
+        /// Sketch type
+        #[clap(short, long, value_parser, default_values_t=DEFAULT_SKETCH_TYPES)]
+        sketch_type: Vec<SketchType>,
+
+        /// Max amount of memory used by sketch (in MB)
+        #[clap(short, long, value_parser, default_values_t=DEFAULT_MEMORY)]
+        memory: Vec<f32>,
+
+        /// The size of the cardinality counters
+        #[clap(short, long, value_parser, default_values_t=DEFAULT_COUNTER_SIZES)]
+        counter_size: Vec<usize>,
+
+        /// Control the amount of output
+        #[clap(short, long, action = ArgAction::SetTrue)]
+        verbose: bool,
+    },
+
     Overlap {
         /// k_small, # of small sets from common
         #[clap(short, long, value_parser)]
@@ -125,7 +171,7 @@ enum Command {
         sketch_type: Vec<SketchType>,
 
         /// Number of entries kept by sketches
-        #[clap(short, long, value_parser, default_value_t=data::synth::Overlap::K_BIG)]
+        #[clap(short, long, value_parser, default_value_t=Overlap::K_BIG)]
         entries: usize,
 
         /// The size of the cardinality counters
@@ -167,39 +213,28 @@ enum Command {
         #[clap(short, long, action = ArgAction::SetTrue)]
         verbose: bool,
     },
-}
 
-#[derive(clap::ValueEnum, Clone, Debug)]
-pub enum SketchType {
-    Achll,
-    Schll,
-    Osss,
-    Rsss,
-    Spread,
-    Ssss,
-}
+    /// Write the canonical test vectors of the sketches, for other
+    /// implementations to check against
+    TestVectors {
+        /// Path to write the vectors to, as JSON
+        output: PathBuf,
+    },
 
-impl fmt::Display for SketchType {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", format!("{:?}", self).to_lowercase())
-    }
-}
+    /// Measure the bias tables of HyperLogLog++'s estimator
+    BiasTables {
+        /// Path to write the tables to, as Rust source
+        output: PathBuf,
+    },
 
-macro_rules! specialized_dispatch {
-    ($sketch_type:ident, $fn:expr) => {
-        match $sketch_type {
-            crate::SketchType::Achll => $fn(crate::algo::Achll),
-            crate::SketchType::Schll => $fn(crate::algo::Schll),
-            crate::SketchType::Osss => $fn(crate::algo::Osss),
-            crate::SketchType::Rsss => $fn(crate::algo::Rsss),
-            crate::SketchType::Spread => $fn(crate::algo::Spread),
-            crate::SketchType::Ssss => $fn(crate::algo::Ssss),
-        }
-    };
+    /// Fit the coefficients of the LogLog-Beta estimator
+    BetaTables {
+        /// Path to write the coefficients to, as Rust source
+        output: PathBuf,
+    },
 }
-pub(crate) use specialized_dispatch;
 
-fn main() {
+fn main() -> io::Result<()> {
     let cli = Cli::parse();
 
     match &cli.command {
@@ -209,16 +244,33 @@ fn main() {
             sketch_type,
             entries,
             counter_size,
+            disagreements,
+            disagreement_threshold,
             verbose,
         } => {
-            specialized_dispatch!(sketch_type, |algorithm| run_sketch(
+            let disagreements = disagreements.as_ref().map(|path| Disagreements {
+                path: path.clone(),
+                min_rel_error: *disagreement_threshold,
+            });
+            let sweep = run_sketch(
                 input,
                 *max_per_file,
-                &algorithm,
+                sketch_type,
                 *entries,
                 *counter_size,
-                *verbose,
-            ))
+                disagreements.as_ref(),
+            )?;
+            print_sweep(&sweep, *verbose);
+            if let (Some(disagreements), Some(num_rows)) = (
+                &disagreements,
+                sweep.runs.first().and_then(|run| run.disagreements),
+            ) {
+                println!(
+                    "Wrote {} disagreements to {}",
+                    num_rows,
+                    disagreements.path.display()
+                );
+            }
         }
         Command::Combo {
             input,
@@ -228,15 +280,15 @@ fn main() {
             counter_size,
             verbose,
         } => {
-            run_combos(
+            let sweep = run_combos(
                 ComboType::SingleSketch,
                 input,
                 *max_per_file,
                 sketch_type,
                 memory,
                 counter_size,
-                *verbose,
-            );
+            )?;
+            print_sweep(&sweep, *verbose);
         }
         Command::Merge {
             input,
@@ -246,15 +298,34 @@ fn main() {
             counter_size,
             verbose,
         } => {
-            run_combos(
+            let sweep = run_combos(
                 ComboType::MergeSketches,
                 input,
                 *max_per_file,
                 sketch_type,
                 memory,
                 counter_size,
-                *verbose,
+            )?;
+            print_sweep(&sweep, *verbose);
+        }
+        Command::MergeRounds {
+            input,
+            max_per_file,
+            rounds,
+            sketch_type,
+            memory,
+            counter_size,
+            verbose,
+        } => {
+            let sweep = run_merge_rounds(
+                input,
+                *max_per_file,
+                sketch_type,
+                memory,
+                counter_size,
+                *rounds,
             );
+            print_merge_rounds(&sweep, *verbose);
         }
         Command::Overlap {
             k_small,
@@ -264,14 +335,21 @@ fn main() {
             counter_size,
             verbose,
         } => {
-            run_overlap(
-                *k_small,
-                *n_big,
-                sketch_type,
-                *entries,
-                *counter_size,
-                *verbose,
+            println!();
+            println!(
+                "{} Small Sets of size: {} (sets chosen randomly from [0, {}))",
+                k_small,
+                Overlap::N_SMALL,
+                Overlap::COMMON_SIZE,
             );
+            println!(
+                "{} Large Sets of size: {} (sets chosen randomly from [0, {}))",
+                Overlap::K_BIG,
+                n_big,
+                Overlap::UNIVERSE_SIZE,
+            );
+            let sweep = run_overlap(*k_small, *n_big, sketch_type, *entries, *counter_size);
+            print_sweep(&sweep, *verbose);
         }
         Command::Zipf {
             labels,
@@ -282,15 +360,31 @@ fn main() {
             counter_size,
             verbose,
         } => {
-            run_zipf(
+            println!();
+            println!(
+                "Zipf({};{}) Distribution ; {} Samples",
+                labels, exponent, num_samples
+            );
+            let sweep = run_zipf(
                 *labels,
                 *exponent,
                 *num_samples,
                 sketch_type,
                 memory,
                 counter_size,
-                *verbose,
             );
+            print_sweep(&sweep, *verbose);
+        }
+        Command::TestVectors { output } => {
+            let vectors = serde_json::to_string_pretty(&test_vectors())?;
+            fs::write(output, vectors + "\n")?;
+        }
+        Command::BiasTables { output } => {
+            fs::write(output, bias_tables())?;
+        }
+        Command::BetaTables { output } => {
+            fs::write(output, beta_tables())?;
         }
     }
+    Ok(())
 }
@@ -1,65 +1,28 @@
+//! How many entries sketches can hold within a memory budget. Sketches
+//! measure themselves with [`sketch_traits::MemorySize`].
 use std::mem::size_of;
 
-use ahash::RandomState;
-use hll::HyperLogLog;
-use sketch_traits::New;
+use hll::{HyperLogLog, MAX_REGISTER_VALUE};
 
 use crate::algo;
 
 const LABEL_SIZE: usize = size_of::<u32>(); // using u32 as a proxy for the label size
 const MEGABYTE: usize = 1_048_576;
 
-pub trait MemorySize {
-    /// Estimates the memory footprint of the object.
-    // TODO: comment about it being a lower bound for HashMap.
-    fn mem_size(&self) -> usize;
+/// HyperLogLogs hold their config inline, and their registers and the
+/// histogram of their values on the heap.
+fn hll_mem_size(size: usize) -> usize {
+    size_of::<HyperLogLog<u32>>() + hll_heap_size(size)
 }
 
-fn hll_mem_size(size: usize) -> usize {
-    size_of::<usize>() * 2
-        + size_of::<u8>() * size
-        + size_of::<RandomState>() * 2
-        + size_of::<f64>() * 2
+fn hll_heap_size(size: usize) -> usize {
+    size_of::<u8>() * size + size_of::<usize>() * (usize::from(MAX_REGISTER_VALUE) + 1)
 }
 
 fn recycling_mem_size(counter_size: usize) -> usize {
     size_of::<usize>() + hll_mem_size(counter_size)
 }
 
-impl<L, I> MemorySize for count_hll::LabelArrayCountHLL<L, I> {
-    fn mem_size(&self) -> usize {
-        let _constants =
-            size_of::<usize>() * 3 + size_of::<RandomState>() * 3 + size_of::<f64>() * 64;
-        let pointwise_mem_size = size_of::<u8>() * self.num_registers();
-        let size_of_labels = LABEL_SIZE * self.num_labels();
-        let size_of_levels = size_of::<u8>() * self.num_registers();
-        pointwise_mem_size + size_of_labels + size_of_levels
-    }
-}
-
-impl<L, I> MemorySize for count_hll::LabelSetCountHLL<L, I> {
-    fn mem_size(&self) -> usize {
-        let _constants =
-            size_of::<usize>() * 3 + size_of::<RandomState>() * 3 + size_of::<f64>() * 64;
-        let pointwise_mem_size = size_of::<u8>() * self.num_registers();
-        let size_of_labels = size_of::<L>() * self.num_labels();
-        pointwise_mem_size + size_of_labels
-    }
-}
-
-impl<L, S> MemorySize for spread::SpreadSketch<L, S>
-where
-    S: New<Config = hll::Config>,
-{
-    fn mem_size(&self) -> usize {
-        let _constants = size_of::<usize>() * 2 + size_of::<RandomState>() * 2;
-        let size_of_bucket = size_of::<u8>()
-            + LABEL_SIZE
-            + hll_mem_size(self.config().cardinality_sketch_config().num_registers());
-        size_of_bucket * self.config().num_rows() * self.config().num_cols()
-    }
-}
-
 fn sss_counter_size(
     reset_strategy: &sss::ResetStrategy,
     cardinality_sketch_config: &hll::Config,
@@ -74,31 +37,13 @@ fn sss_counter_size(
     }
 }
 
-impl<L, I> MemorySize for sss::SpaceSavingSets<L, HyperLogLog<I>>
-where
-    HyperLogLog<I>: New<Config = hll::Config>,
-{
-    fn mem_size(&self) -> usize {
-        let _constants = size_of::<usize>();
-        (sss_counter_size(
-            self.config().reset_strategy(),
-            self.config().cardinality_sketch_config(),
-        ) + LABEL_SIZE)
-            * self.num_counters()
-    }
-}
-
-impl<L, I> MemorySize for ssss::SamplingSpaceSavingSets<L, HyperLogLog<I>>
-where
-    HyperLogLog<I>: New<Config = hll::Config>,
-{
-    fn mem_size(&self) -> usize {
-        let _config_mem_size = size_of::<usize>() * 2 + size_of::<u64>();
-        let _constants = _config_mem_size + size_of::<usize>() + size_of::<RandomState>() * 2;
+/// Set aside for the parts of sketches that don't grow with their number of
+/// entries, e.g., their configs.
+const FIXED_SIZE: usize = 4096;
 
-        (recycling_mem_size(self.config().cardinality_sketch_config().num_registers()) + LABEL_SIZE)
-            * self.num_counters()
-    }
+/// How many entries of `entry_size` bytes fit within `memory` MBs.
+fn entries_within(memory: f32, entry_size: usize) -> usize {
+    ((memory * MEGABYTE as f32 - FIXED_SIZE as f32).max(0.0) / entry_size as f32) as usize
 }
 
 pub trait MaxCapacity {
@@ -108,8 +53,9 @@ pub trait MaxCapacity {
 
 impl MaxCapacity for algo::Achll {
     fn entries_for_mbs(&self, memory: f32, counter_size: usize) -> usize {
-        let column_size = (LABEL_SIZE + size_of::<u8>() + size_of::<u8>()) * counter_size;
-        (memory * MEGABYTE as f32 / column_size as f32) as usize
+        // Each cell holds a label and its level, next to its register.
+        let column_size = (size_of::<(Option<u32>, u8)>() + size_of::<u8>()) * counter_size;
+        entries_within(memory, column_size)
     }
 }
 
@@ -119,45 +65,69 @@ impl MaxCapacity for algo::Schll {
     }
 }
 
+impl MaxCapacity for algo::Sschll {
+    fn entries_for_mbs(&self, memory: f32, counter_size: usize) -> usize {
+        // Hash tables have 8/7 as many buckets as candidates, rounded up to a
+        // power of two, each with a control byte.
+        let candidate_size = 16 * (size_of::<(u32, u64)>() + 1) / 7;
+        let column_size = size_of::<u8>() * counter_size + candidate_size;
+        entries_within(memory, column_size)
+    }
+}
+
 impl MaxCapacity for algo::Spread {
     fn entries_for_mbs(&self, memory: f32, counter_size: usize) -> usize {
-        let size_of_bucket = size_of::<u8>() + LABEL_SIZE + hll_mem_size(counter_size);
+        // Buckets are laid out like this tuple, with registers on the heap.
+        let size_of_bucket =
+            size_of::<(Option<u32>, u8, HyperLogLog<u32>)>() + hll_heap_size(counter_size);
         let size_of_row = size_of_bucket * algo::Spread::DEPTH;
-        (memory * MEGABYTE as f32 / size_of_row as f32) as usize
+        entries_within(memory, size_of_row)
     }
 }
 
 impl MaxCapacity for algo::Osss {
     fn entries_for_mbs(&self, memory: f32, counter_size: usize) -> usize {
-        (memory * MEGABYTE as f32
-            / (sss_counter_size(
-                &sss::ResetStrategy::Offset,
-                &hll::Config::new(counter_size, None).unwrap(),
-            ) + LABEL_SIZE) as f32) as usize
+        let counter_size = sss_counter_size(
+            &sss::ResetStrategy::Offset,
+            &hll::Config::new(counter_size, None).unwrap(),
+        );
+        entries_within(memory, counter_size + LABEL_SIZE)
     }
 }
 
 impl MaxCapacity for algo::Rsss {
     fn entries_for_mbs(&self, memory: f32, counter_size: usize) -> usize {
-        (memory * MEGABYTE as f32
-            / (sss_counter_size(
-                &sss::ResetStrategy::Recycle,
-                &hll::Config::new(counter_size, None).unwrap(),
-            ) + LABEL_SIZE) as f32) as usize
+        let counter_size = sss_counter_size(
+            &sss::ResetStrategy::Recycle,
+            &hll::Config::new(counter_size, None).unwrap(),
+        );
+        entries_within(memory, counter_size + LABEL_SIZE)
     }
 }
 
 impl MaxCapacity for algo::Ssss {
     fn entries_for_mbs(&self, memory: f32, counter_size: usize) -> usize {
-        (memory * MEGABYTE as f32 / (recycling_mem_size(counter_size) + LABEL_SIZE) as f32) as usize
+        entries_within(memory, recycling_mem_size(counter_size) + LABEL_SIZE)
+    }
+}
+
+impl MaxCapacity for algo::SsssTz {
+    fn entries_for_mbs(&self, memory: f32, counter_size: usize) -> usize {
+        algo::Ssss.entries_for_mbs(memory, counter_size)
+    }
+}
+
+impl MaxCapacity for algo::Ssss2h {
+    fn entries_for_mbs(&self, memory: f32, counter_size: usize) -> usize {
+        algo::Ssss.entries_for_mbs(memory, counter_size)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use sketch_traits::HeavyDistinctHitterSketch;
+    use sketch_traits::{HeavyDistinctHitterSketch, MemorySize};
 
-    use super::{MaxCapacity, MemorySize, MEGABYTE};
+    use super::{MaxCapacity, MEGABYTE};
     use crate::algo;
 
     #[test]
@@ -165,10 +135,13 @@ mod tests {
         for memory in [1.0, 2.0, 3.0, 4.0, 5.0, 10.0] {
             for counter_size in [256, 512, 1024] {
                 print_sizes(&algo::Achll, memory, counter_size);
+                print_sizes(&algo::Sschll, memory, counter_size);
                 print_sizes(&algo::Osss, memory, counter_size);
                 print_sizes(&algo::Rsss, memory, counter_size);
                 print_sizes(&algo::Spread, memory, counter_size);
                 print_sizes(&algo::Ssss, memory, counter_size);
+                print_sizes(&algo::SsssTz, memory, counter_size);
+                print_sizes(&algo::Ssss2h, memory, counter_size);
             }
         }
 
@@ -0,0 +1,183 @@
+//! Prints the results of sweeps.
+use std::{fmt::Debug, mem::size_of};
+
+use benchmarks::sweep::{Accuracy, DatasetStats, MergeRounds, SketchRun, Sweep};
+use num_format::{Locale, ToFormattedString};
+
+pub fn print_sweep<L>(sweep: &Sweep<L>, verbose: bool)
+where
+    L: Debug,
+{
+    if verbose {
+        print_dataset(&sweep.dataset);
+    }
+    let mut algorithm = None;
+    for run in &sweep.runs {
+        if algorithm != Some(&run.algorithm) {
+            algorithm = Some(&run.algorithm);
+            if run.num_sketches > 1 {
+                println!("Algo: {} (Merged)", run.algorithm);
+            } else {
+                println!("Algo: {}", run.algorithm);
+            }
+        }
+        print_run(run);
+    }
+}
+
+pub fn print_merge_rounds<L>(sweep: &Sweep<L, MergeRounds>, verbose: bool)
+where
+    L: Debug,
+{
+    if verbose {
+        print_dataset(&sweep.dataset);
+    }
+    let mut algorithm = None;
+    for run in &sweep.runs {
+        if algorithm != Some(&run.algorithm) {
+            algorithm = Some(&run.algorithm);
+            println!("Algo: {} (Merge Rounds)", run.algorithm);
+        }
+        println!(
+            "Counter Size: {}; Entries: {}",
+            run.counter_size, run.entries
+        );
+        println!("Rounds\tShards\tNAE(T)\tNAE(S)\tRMAE(T)\tRMAE(S)\tTime");
+        for round in &run.rounds {
+            println!(
+                "{}\t{}\t{:.3}\t{:.3}\t{:.3}\t{:.3}\t{:.2?}",
+                round.rounds,
+                round.num_shards,
+                round.true_nae,
+                round.sketch_nae,
+                round.true_rmae,
+                round.sketch_rmae,
+                round.time,
+            );
+        }
+        println!();
+    }
+}
+
+fn print_dataset<L>(stats: &DatasetStats<L>)
+where
+    L: Debug,
+{
+    let num_entries = stats.num_entries as f64;
+    println!(
+        "Num Entries: {}",
+        stats.num_entries.to_formatted_string(&Locale::en)
+    );
+    println!(
+        "Unique Entries: {} ({:.0}%)",
+        stats.unique_entries,
+        100.0 * (stats.unique_entries as f64 / num_entries)
+    );
+    println!(
+        "Top 100 Label Entries: {} ({:.0}%)",
+        stats.top100_entries,
+        100.0 * (stats.top100_entries as f64 / num_entries)
+    );
+    println!(
+        "Top 1000 Label Entries: {} ({:.0}%)",
+        stats.top1000_entries,
+        100.0 * (stats.top1000_entries as f64 / num_entries)
+    );
+    println!("Unique Items: {}", stats.unique_items);
+    println!(
+        "Num Labels: {} ({:.1} MB)",
+        stats.num_labels,
+        (size_of::<u64>() * stats.num_labels) as f64 / 1_048_576.0
+    );
+    println!("Mean Label Set Sizes: {:.1?}", stats.mean);
+    let percentiles = stats
+        .percentiles
+        .iter()
+        .map(|&(_, size)| size)
+        .collect::<Vec<_>>();
+    if let [p25, p50, p75, p90, p95, p99, p999, p9999] = percentiles[..] {
+        println!("p25/p50/p75 Set Sizes: {} {} {}", p25, p50, p75);
+        println!("p90/p95/p99 Set Sizes: {} {} {}", p90, p95, p99);
+        println!("p999/p9999/max Set Sizes: {} {} {}", p999, p9999, stats.max);
+    }
+    println!(
+        "Ground Truth Memory: {:.1} MB ({:.0} kB)",
+        stats.ground_truth_mem_size as f64 / 1_048_576.0,
+        stats.ground_truth_mem_size as f64 / 1024.0,
+    );
+    println!("Ground Truth Top {}: {:?}", stats.top.len(), stats.top);
+    println!("Ground Truth Time: {:.2?}", stats.ground_truth_time);
+    println!();
+}
+
+fn print_run(run: &SketchRun) {
+    if run.num_sketches > 1 {
+        println!("Running {}:", run.algorithm);
+        println!("Merged {} sketches.", run.num_sketches);
+    }
+    println!("Insertion Time: {:.2?}", run.insertion_time);
+    println!(
+        "Memory: {:.1} MB ({:.0} kB); Counter Size: {}; Entries: {}",
+        run.mem_size as f64 / 1_048_576.0,
+        run.mem_size as f64 / 1024.0,
+        run.counter_size,
+        run.entries,
+    );
+    println!(
+        "Query Time to retrieve Sketch Top {}: {:.2?}",
+        run.accuracy.last().map_or(0, |accuracy| accuracy.k),
+        run.query_time
+    );
+
+    // print header
+    print!("Top\tNAE(T)\tNAE(S)\tNAE(M)\tNAE(Q)\t");
+    print!("NRSE(T)\tNRSE(S)\tNRSE(Q)\t");
+    print!("RMAE(T)\tRMAE(S)\tRMAE(Q)\t");
+    print!("RMSE(T)\tRMSE(S)\tRMSE(Q)\t");
+    println!("RMAX(T)\tRMAX(S)\tRMAX(Q)");
+    run.accuracy.iter().for_each(print_accuracy);
+    println!();
+}
+
+fn print_accuracy(accuracy: &Accuracy) {
+    print!(
+        "{:05}\t{:.3}\t{:.3}\t{:.3}\t{:.3}\t",
+        accuracy.k,
+        accuracy.true_nae,
+        accuracy.sketch_nae,
+        mean(accuracy.true_nae, accuracy.sketch_nae),
+        quadratic_mean(accuracy.true_nae, accuracy.sketch_nae)
+    );
+    print!(
+        "{:.3}\t{:.3}\t{:.3}\t",
+        accuracy.true_nrse,
+        accuracy.sketch_nrse,
+        quadratic_mean(accuracy.true_nrse, accuracy.sketch_nrse)
+    );
+    print!(
+        "{:.3}\t{:.3}\t{:.3}\t",
+        accuracy.true_rmae,
+        accuracy.sketch_rmae,
+        quadratic_mean(accuracy.true_rmae, accuracy.sketch_rmae)
+    );
+    print!(
+        "{:.3}\t{:.3}\t{:.3}\t",
+        accuracy.true_rrmse,
+        accuracy.sketch_rrmse,
+        quadratic_mean(accuracy.true_rrmse, accuracy.sketch_rrmse)
+    );
+    println!(
+        "{:.3}\t{:.3}\t{:.3}",
+        accuracy.true_rel_max,
+        accuracy.sketch_rel_max,
+        quadratic_mean(accuracy.true_rel_max, accuracy.sketch_rel_max)
+    );
+}
+
+fn mean(a: f64, b: f64) -> f64 {
+    (a + b) / 2.0
+}
+
+fn quadratic_mean(a: f64, b: f64) -> f64 {
+    ((a * a + b * b) / 2.0).sqrt()
+}
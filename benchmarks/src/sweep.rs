@@ -0,0 +1,560 @@
+//! Runs sketches over datasets and measures their accuracy against the ground
+//! truth, returning the measurements rather than printing them.
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    fs::{read_dir, File},
+    hash::Hash,
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use sketch_traits::{HeavyDistinctHitterSketch, MemorySize};
+
+use crate::{
+    algo::Algorithm,
+    data::synth::{Overlap, Zipf},
+    data::{Dataset, FileDataset, FolderDataset},
+    exact::GroundTruth,
+    memory::MaxCapacity,
+    specialized_dispatch, SketchType,
+};
+
+/// The percentiles of the label set sizes reported in [`DatasetStats`].
+pub const PERCENTILES: [f64; 8] = [0.25, 0.5, 0.75, 0.90, 0.95, 0.99, 0.999, 0.9999];
+
+/// The number of top labels accuracy is measured over, and that are listed in
+/// [`DatasetStats::top`].
+const TOP_K: usize = 1000;
+const NUM_LISTED_LABELS: usize = 10;
+
+/// The results of running sketches over a dataset, along with statistics about
+/// the dataset itself.
+#[derive(Clone, Debug)]
+pub struct Sweep<L, R = SketchRun> {
+    pub dataset: DatasetStats<L>,
+    pub runs: Vec<R>,
+}
+
+/// Statistics about a dataset, gathered while computing its ground truth.
+#[derive(Clone, Debug)]
+pub struct DatasetStats<L> {
+    pub num_entries: usize,
+    pub unique_entries: usize,
+    /// The number of entries of the 100 labels with the largest sets.
+    pub top100_entries: usize,
+    /// The number of entries of the 1000 labels with the largest sets.
+    pub top1000_entries: usize,
+    pub unique_items: usize,
+    pub num_labels: usize,
+    pub mean: f64,
+    /// The set sizes at each of [`PERCENTILES`].
+    pub percentiles: Vec<(f64, usize)>,
+    pub max: usize,
+    pub ground_truth_mem_size: usize,
+    /// The labels with the largest sets, and their cardinalities.
+    pub top: Vec<(L, u64)>,
+    pub ground_truth_time: Duration,
+}
+
+/// The outcome of a single sketch, or of the merge of several, over a dataset.
+#[derive(Clone, Debug)]
+pub struct SketchRun {
+    pub algorithm: String,
+    pub entries: usize,
+    pub counter_size: usize,
+    /// The number of sketches merged into the one measured, 1 if none were.
+    pub num_sketches: usize,
+    pub mem_size: usize,
+    pub insertion_time: Duration,
+    pub query_time: Duration,
+    /// The accuracy over the top 10, 100 and 1000 labels.
+    pub accuracy: Vec<Accuracy>,
+    /// The number of disagreements written, if asked to.
+    pub disagreements: Option<usize>,
+}
+
+/// The errors of a sketch over the top `k` labels, either the true top labels
+/// (`true_*`) or the ones reported by the sketch (`sketch_*`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Accuracy {
+    pub k: usize,
+    pub true_nae: f64,
+    pub sketch_nae: f64,
+    pub true_nrse: f64,
+    pub sketch_nrse: f64,
+    pub true_rmae: f64,
+    pub sketch_rmae: f64,
+    pub true_rrmse: f64,
+    pub sketch_rrmse: f64,
+    pub true_rel_max: f64,
+    pub sketch_rel_max: f64,
+}
+
+/// The errors of a sketch merged pairwise over a number of rounds, see
+/// [`merge_rounds_on_data`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MergeRound {
+    pub rounds: u32,
+    pub num_shards: usize,
+    pub true_nae: f64,
+    pub sketch_nae: f64,
+    pub true_rmae: f64,
+    pub sketch_rmae: f64,
+    pub time: Duration,
+}
+
+/// The merge rounds of one sketch configuration.
+#[derive(Clone, Debug)]
+pub struct MergeRounds {
+    pub algorithm: String,
+    pub entries: usize,
+    pub counter_size: usize,
+    pub rounds: Vec<MergeRound>,
+}
+
+/// Where to write the labels whose estimates are off by more than
+/// `min_rel_error`, see [`GroundTruth::write_disagreements`].
+#[derive(Clone, Debug)]
+pub struct Disagreements {
+    pub path: PathBuf,
+    pub min_rel_error: f64,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ComboType {
+    SingleSketch,
+    MergeSketches,
+}
+
+pub fn dataset_ground_truth<L, I>(
+    dataset: &impl Dataset<Label = L, Item = I>,
+) -> (GroundTruth<L, I>, DatasetStats<L>)
+where
+    L: Eq + Hash + Clone + Debug + MemorySize,
+    I: Eq + Hash + Clone + Debug + MemorySize,
+{
+    let mut ground_truth = GroundTruth::new();
+    let mut entries = HashSet::new();
+    let mut items = HashSet::new();
+    let mut label_count = HashMap::new();
+    let mut num_entries = 0;
+    let start = Instant::now();
+
+    for (label, item) in dataset.iter() {
+        entries.insert((label.clone(), item.clone()));
+        items.insert(item.clone());
+        *label_count.entry(label.clone()).or_insert(0) += 1;
+        ground_truth.insert(label.clone(), &item);
+        num_entries += 1;
+    }
+
+    let top_entries = |k: usize| -> usize {
+        ground_truth
+            .top_cardinalities()
+            .take(k)
+            .map(|(label, _)| label_count[label])
+            .sum()
+    };
+    let stats = DatasetStats {
+        num_entries,
+        unique_entries: entries.len(),
+        top100_entries: top_entries(100),
+        top1000_entries: top_entries(1000),
+        unique_items: items.len(),
+        num_labels: ground_truth.num_labels(),
+        mean: ground_truth.mean(),
+        percentiles: PERCENTILES
+            .iter()
+            .map(|&p| (p, ground_truth.percentile(p)))
+            .collect(),
+        max: ground_truth.max(),
+        ground_truth_mem_size: ground_truth.mem_size(),
+        top: ground_truth
+            .top_cardinalities()
+            .take(NUM_LISTED_LABELS)
+            .map(|(label, cardinality)| (label.clone(), cardinality))
+            .collect(),
+        ground_truth_time: start.elapsed(),
+    };
+    (ground_truth, stats)
+}
+
+pub fn run_sketch(
+    folder_path: &Path,
+    max_per_file: usize,
+    sketch_type: &SketchType,
+    entries: usize,
+    counter_size: usize,
+    disagreements: Option<&Disagreements>,
+) -> io::Result<Sweep<String>> {
+    let dataset = FolderDataset::new(folder_path, max_per_file);
+    let (ground_truth, stats) = dataset_ground_truth(&dataset);
+    let run = specialized_dispatch!(sketch_type, |algorithm| {
+        let (sketch, mut run) =
+            sketch_dataset(entries, counter_size, &algorithm, &ground_truth, &dataset);
+        if let Some(disagreements) = disagreements {
+            let mut out = BufWriter::new(File::create(&disagreements.path)?);
+            run.disagreements = Some(ground_truth.write_disagreements(
+                &sketch,
+                TOP_K,
+                disagreements.min_rel_error,
+                &mut out,
+            )?);
+        }
+        io::Result::Ok(run)
+    })?;
+    Ok(Sweep {
+        dataset: stats,
+        runs: vec![run],
+    })
+}
+
+pub fn run_combos(
+    combo_type: ComboType,
+    folder_path: &Path,
+    max_per_file: usize,
+    sketch_types: &[SketchType],
+    memories: &[f32],
+    counter_sizes: &[usize],
+) -> io::Result<Sweep<String>> {
+    let dataset = FolderDataset::new(folder_path, max_per_file);
+    let (ground_truth, stats) = dataset_ground_truth(&dataset);
+
+    let mut runs = Vec::new();
+    for sketch_type in sketch_types {
+        specialized_dispatch! {
+            sketch_type,
+            |algorithm| {
+                for memory in memories {
+                    for counter_size in counter_sizes {
+                        let entries = MaxCapacity::entries_for_mbs(&algorithm, *memory, *counter_size);
+                        runs.push(match combo_type {
+                            ComboType::SingleSketch => {
+                                sketch_dataset(entries, *counter_size, &algorithm, &ground_truth, &dataset).1
+                            }
+                            ComboType::MergeSketches => {
+                                let file_datasets = read_dir(folder_path)?
+                                    .map(|entry| entry.map(|entry| FileDataset::new(entry.path(), max_per_file)))
+                                    .collect::<io::Result<Vec<_>>>()?;
+                                merge_on_data(file_datasets.into_iter(), entries, *counter_size, &algorithm, &ground_truth)
+                            }
+                        });
+                    }
+                }
+                io::Result::Ok(())
+            }
+        }?;
+    }
+    Ok(Sweep {
+        dataset: stats,
+        runs,
+    })
+}
+
+pub fn run_merge_rounds(
+    folder_path: &Path,
+    max_per_file: usize,
+    sketch_types: &[SketchType],
+    memories: &[f32],
+    counter_sizes: &[usize],
+    max_rounds: u32,
+) -> Sweep<String, MergeRounds> {
+    let dataset = FolderDataset::new(folder_path, max_per_file);
+    let (ground_truth, stats) = dataset_ground_truth(&dataset);
+
+    let mut runs = Vec::new();
+    for sketch_type in sketch_types {
+        specialized_dispatch! {
+            sketch_type,
+            |algorithm| {
+                for memory in memories {
+                    for counter_size in counter_sizes {
+                        let entries = MaxCapacity::entries_for_mbs(&algorithm, *memory, *counter_size);
+                        let rounds = merge_rounds_on_data(&dataset, entries, *counter_size, &algorithm, &ground_truth, max_rounds);
+                        runs.push(MergeRounds {
+                            algorithm: format!("{}", algorithm),
+                            entries,
+                            counter_size: *counter_size,
+                            rounds,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    Sweep {
+        dataset: stats,
+        runs,
+    }
+}
+
+pub fn run_zipf(
+    num_labels: usize,
+    exponent: f64,
+    num_samples: usize,
+    sketch_types: &[SketchType],
+    memories: &[f32],
+    counter_sizes: &[usize],
+) -> Sweep<usize> {
+    let dataset = Zipf::new(num_labels, exponent, num_samples, false);
+    let (ground_truth, stats) = dataset_ground_truth(&dataset);
+
+    let mut runs = Vec::new();
+    for sketch_type in sketch_types {
+        specialized_dispatch! {
+            sketch_type,
+            |algorithm| {
+                for memory in memories {
+                    for counter_size in counter_sizes {
+                        let entries = MaxCapacity::entries_for_mbs(&algorithm, *memory, *counter_size);
+                        runs.push(sketch_dataset(entries, *counter_size, &algorithm, &ground_truth, &dataset).1);
+                    }
+                }
+            }
+        }
+    }
+    Sweep {
+        dataset: stats,
+        runs,
+    }
+}
+
+pub fn run_overlap(
+    k_small: u32,
+    n_big: usize,
+    sketch_types: &[SketchType],
+    entries: usize,
+    counter_size: usize,
+) -> Sweep<u128> {
+    let dataset = Overlap::new(k_small, n_big, false);
+    let (ground_truth, stats) = dataset_ground_truth(&dataset);
+
+    let runs = sketch_types
+        .iter()
+        .map(|sketch_type| {
+            specialized_dispatch!(sketch_type, |algorithm| {
+                sketch_dataset(entries, counter_size, &algorithm, &ground_truth, &dataset).1
+            })
+        })
+        .collect();
+    Sweep {
+        dataset: stats,
+        runs,
+    }
+}
+
+/// Sketches the dataset, and returns the sketch along with its measurements.
+pub fn sketch_dataset<L, I, A>(
+    entries: usize,
+    counter_size: usize,
+    algorithm: &A,
+    ground_truth: &GroundTruth<L, I>,
+    dataset: &impl Dataset<Label = L, Item = I>,
+) -> (A::Sketch<L, I>, SketchRun)
+where
+    L: Eq + Hash + Clone + Debug,
+    I: Eq + Hash + Clone + Debug,
+    A: Algorithm,
+    A::Sketch<L, I>: HeavyDistinctHitterSketch<Label = L, Item = I> + MemorySize,
+{
+    let mut sketch = algorithm.new_sketch(entries, counter_size);
+    let start = Instant::now();
+    for (label, item) in dataset.iter() {
+        sketch.insert(label.clone(), &item);
+    }
+    let insertion_time = start.elapsed();
+    let run = measure(
+        algorithm,
+        entries,
+        counter_size,
+        1,
+        insertion_time,
+        ground_truth,
+        &sketch,
+    );
+    (sketch, run)
+}
+
+/// Sketches each dataset separately, then merges the sketches.
+pub fn merge_on_data<L, I, A>(
+    sketch_datasets: impl Iterator<Item = impl Dataset<Label = L, Item = I>>,
+    entries: usize,
+    counter_size: usize,
+    algorithm: &A,
+    ground_truth: &GroundTruth<L, I>,
+) -> SketchRun
+where
+    L: Eq + Hash + Clone + Debug,
+    I: Eq + Hash + Clone + Debug,
+    A: Algorithm,
+    A::Sketch<L, I>: HeavyDistinctHitterSketch<Label = L, Item = I> + MemorySize,
+{
+    let mut sketch = algorithm.new_sketch(entries, counter_size);
+    let mut file_count = 0;
+    let start = Instant::now();
+    for dataset in sketch_datasets {
+        let mut file_sketch = algorithm.new_sketch(entries, counter_size);
+        for (label, item) in dataset.iter() {
+            file_sketch.insert(label.clone(), &item);
+        }
+        sketch
+            .merge(&file_sketch)
+            .unwrap_or_else(|e| panic!("{:?}", e));
+        file_count += 1;
+    }
+    let insertion_time = start.elapsed();
+    measure(
+        algorithm,
+        entries,
+        counter_size,
+        file_count,
+        insertion_time,
+        ground_truth,
+        &sketch,
+    )
+}
+
+/// Splits the dataset into `2^r` shards for each number of rounds `r` up to
+/// `max_rounds`, sketches each shard, then merges the sketches pairwise, `r`
+/// rounds deep, to see how errors compound with the depth of the merge tree.
+pub fn merge_rounds_on_data<L, I, A>(
+    dataset: &impl Dataset<Label = L, Item = I>,
+    entries: usize,
+    counter_size: usize,
+    algorithm: &A,
+    ground_truth: &GroundTruth<L, I>,
+    max_rounds: u32,
+) -> Vec<MergeRound>
+where
+    L: Eq + Hash + Clone + Debug,
+    I: Eq + Hash + Clone + Debug,
+    A: Algorithm,
+    A::Sketch<L, I>: HeavyDistinctHitterSketch<Label = L, Item = I> + MemorySize,
+{
+    let top_k = 100;
+    (0..=max_rounds)
+        .map(|rounds| {
+            let start = Instant::now();
+            let num_shards = 1 << rounds;
+            let mut sketches = (0..num_shards)
+                .map(|_| algorithm.new_sketch(entries, counter_size))
+                .collect::<Vec<_>>();
+            for (i, (label, item)) in dataset.iter().enumerate() {
+                sketches[i % num_shards].insert(label.clone(), &item);
+            }
+            while sketches.len() > 1 {
+                let mut merged = Vec::with_capacity(sketches.len() / 2);
+                let mut shards = sketches.into_iter();
+                while let Some(mut left) = shards.next() {
+                    if let Some(right) = shards.next() {
+                        left.merge_owned(right)
+                            .unwrap_or_else(|e| panic!("{:?}", e));
+                    }
+                    merged.push(left);
+                }
+                sketches = merged;
+            }
+            let sketch = &sketches[0];
+            let sketch_top_k = sketch.top(top_k);
+            MergeRound {
+                rounds,
+                num_shards,
+                true_nae: ground_truth.top_nae(sketch, top_k),
+                sketch_nae: ground_truth.sketch_nae(&sketch_top_k),
+                true_rmae: ground_truth.actual_rmae(sketch, top_k),
+                sketch_rmae: ground_truth.sketch_rmae(&sketch_top_k),
+                time: start.elapsed(),
+            }
+        })
+        .collect()
+}
+
+fn measure<L, I, A>(
+    algorithm: &A,
+    entries: usize,
+    counter_size: usize,
+    num_sketches: usize,
+    insertion_time: Duration,
+    ground_truth: &GroundTruth<L, I>,
+    sketch: &A::Sketch<L, I>,
+) -> SketchRun
+where
+    L: Eq + Hash + Clone + Debug,
+    I: Eq + Hash + Clone + Debug,
+    A: Algorithm,
+    A::Sketch<L, I>: HeavyDistinctHitterSketch<Label = L, Item = I> + MemorySize,
+{
+    let start = Instant::now();
+    let sketch_top_k = sketch.top(TOP_K);
+    let query_time = start.elapsed();
+
+    let accuracy = (1..4)
+        .map(|p| {
+            let k = usize::pow(10, p);
+            let sketch_top = &sketch_top_k[..k.min(sketch_top_k.len())];
+            Accuracy {
+                k,
+                true_nae: ground_truth.top_nae(sketch, k),
+                sketch_nae: ground_truth.sketch_nae(sketch_top),
+                true_nrse: ground_truth.top_nrse(sketch, k),
+                sketch_nrse: ground_truth.sketch_nrse(sketch_top),
+                true_rmae: ground_truth.actual_rmae(sketch, k),
+                sketch_rmae: ground_truth.sketch_rmae(sketch_top),
+                true_rrmse: ground_truth.actual_rrmse(sketch, k),
+                sketch_rrmse: ground_truth.sketch_rrmse(sketch_top),
+                true_rel_max: ground_truth.actual_rel_max(sketch, k),
+                sketch_rel_max: ground_truth.sketch_rel_max(sketch_top),
+            }
+        })
+        .collect();
+    SketchRun {
+        algorithm: algorithm.to_string(),
+        entries,
+        counter_size,
+        num_sketches,
+        mem_size: sketch.mem_size(),
+        insertion_time,
+        query_time,
+        accuracy,
+        disagreements: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{algo, data::synth};
+
+    #[test]
+    fn it_measures_sketches() {
+        let dataset = synth::Zipf::new(100, 1.0, 10_000, false);
+        let (ground_truth, stats) = dataset_ground_truth(&dataset);
+        assert_eq!(stats.num_entries, 10_000);
+        assert_eq!(stats.num_labels, ground_truth.num_labels());
+        assert_eq!(stats.percentiles.len(), PERCENTILES.len());
+
+        let (_, run) = sketch_dataset(100, 1024, &algo::Ssss, &ground_truth, &dataset);
+        assert_eq!(run.num_sketches, 1);
+        assert_eq!(
+            run.accuracy.iter().map(|a| a.k).collect::<Vec<_>>(),
+            [10, 100, 1000]
+        );
+
+        let run = merge_on_data(
+            (0..2).map(|_| synth::Zipf::new(100, 1.0, 5_000, false)),
+            100,
+            1024,
+            &algo::Ssss,
+            &ground_truth,
+        );
+        assert_eq!(run.num_sketches, 2);
+
+        let rounds = merge_rounds_on_data(&dataset, 100, 1024, &algo::Ssss, &ground_truth, 2);
+        assert_eq!(
+            rounds.iter().map(|r| r.num_shards).collect::<Vec<_>>(),
+            [1, 2, 4]
+        );
+    }
+}
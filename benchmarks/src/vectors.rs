@@ -0,0 +1,249 @@
+//! Canonical test vectors, for reimplementations of the sketches (e.g., in
+//! other languages) to check that they hash and estimate exactly like these.
+//!
+//! For fixed seeds, configs and streams, the vectors hold the hashes of items,
+//! the registers of the sketches and their estimates. The published vectors
+//! live in `benchmarks/vectors/test_vectors.json`; regenerate them with the
+//! `test-vectors` command whenever an intended change alters them.
+//!
+//! The sketches hash with ahash by default, whose output depends on its
+//! version and on the target features it's compiled with, so the vectors are
+//! generated with sketches hashing with SipHash-1-3 instead (see
+//! [`SipHash13`]), which is specified and stable. A hasher built from 4 seeds
+//! is keyed by the first two, and hashes the little-endian bytes the `Hash`
+//! impls of the values write: a `u64` or `usize` as 8 bytes, and a tuple its
+//! fields in order.
+//!
+//! Streams insert item `i` in `0..num_items` with label
+//! `trailing_zeros(i + 1) % num_labels`, so that labels have geometrically
+//! decreasing cardinalities. Estimates are listed by increasing label.
+use std::{fmt::Write, hash::BuildHasher};
+
+use serde_json::{json, Value};
+use siphasher::sip::SipHasher13;
+use sketch_traits::{CardinalitySketch, HeavyDistinctHitterSketch, New, SeedableBuildHasher};
+
+const HLL_SEEDS: [u64; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+const COUNT_HLL_SEEDS: [u64; 12] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+const SSSS_SEEDS: [u64; 4] = [9, 10, 11, 12];
+const SPREAD_SEEDS: [u64; 8] = [9, 10, 11, 12, 13, 14, 15, 16];
+
+/// SipHash-1-3 keyed by the first two of the seeds it's built from.
+#[derive(Clone, Debug)]
+pub struct SipHash13 {
+    keys: [u64; 2],
+}
+
+impl BuildHasher for SipHash13 {
+    type Hasher = SipHasher13;
+
+    fn build_hasher(&self) -> SipHasher13 {
+        SipHasher13::new_with_keys(self.keys[0], self.keys[1])
+    }
+}
+
+impl SeedableBuildHasher for SipHash13 {
+    fn with_seeds(seeds: [u64; 4]) -> Self {
+        Self {
+            keys: [seeds[0], seeds[1]],
+        }
+    }
+}
+
+type HyperLogLog = hll::HyperLogLog<u64, SipHash13>;
+
+/// The vectors of all sketches, as published.
+pub fn test_vectors() -> Value {
+    json!({
+        "hashes": hash_vectors(),
+        "hll": hll_vectors(),
+        "count_hll": count_hll_vectors(),
+        "ssss": ssss_vectors(),
+        "sss": sss_vectors(),
+        "spread": spread_vectors(),
+    })
+}
+
+fn stream(num_labels: u64, num_items: u64) -> impl Iterator<Item = (u64, u64)> {
+    (0..num_items).map(move |i| (u64::from((i + 1).trailing_zeros()) % num_labels, i))
+}
+
+fn hex(registers: &[u8]) -> String {
+    registers.iter().fold(String::new(), |mut s, r| {
+        // Writing to a String cannot fail.
+        let _ = write!(s, "{:02x}", r);
+        s
+    })
+}
+
+fn estimates<S>(sketch: &S) -> Vec<(u64, u64)>
+where
+    S: HeavyDistinctHitterSketch<Label = u64>,
+{
+    let mut estimates = sketch
+        .labels()
+        .map(|&label| (label, sketch.cardinality(&label)))
+        .collect::<Vec<_>>();
+    estimates.sort_unstable();
+    estimates
+}
+
+fn hll_config(num_registers: usize) -> hll::Config<SipHash13> {
+    hll::Config::with_hasher(num_registers, Some(HLL_SEEDS)).unwrap()
+}
+
+fn hash_vectors() -> Vec<Value> {
+    let [a, b, c, d, ..] = HLL_SEEDS;
+    let hash_builder = SipHash13::with_seeds([a, b, c, d]);
+    let items = [0, 1, 2, 42, u64::MAX];
+    vec![
+        json!({
+            "seeds": [a, b, c, d],
+            "values": items
+                .iter()
+                .map(|&item| json!({ "u64": item, "hash": hash_builder.hash_one(item) }))
+                .collect::<Vec<_>>(),
+        }),
+        json!({
+            "seeds": [a, b, c, d],
+            "values": items
+                .iter()
+                .map(|&item| {
+                    json!({ "tuple": [item, 7_u64], "hash": hash_builder.hash_one((item, 7_u64)) })
+                })
+                .collect::<Vec<_>>(),
+        }),
+    ]
+}
+
+fn hll_vectors() -> Vec<Value> {
+    let mut vectors = vec![];
+    for num_registers in [16, 256] {
+        for hash_width in [hll::HashWidth::Bits64, hll::HashWidth::Bits32] {
+            for num_items in [0, 10, 1_000, 100_000] {
+                let config = hll_config(num_registers).with_hash_width(hash_width);
+                let mut sketch = HyperLogLog::new(&config);
+                (0..num_items).for_each(|i: u64| sketch.insert(&i));
+                let mut bias_corrected =
+                    HyperLogLog::new(&config.with_estimator(hll::Estimator::BiasCorrected));
+                bias_corrected
+                    .merge_raw_registers(&sketch.registers())
+                    .unwrap();
+                vectors.push(json!({
+                    "num_registers": num_registers,
+                    "hash_width": format!("{:?}", hash_width),
+                    "seeds": HLL_SEEDS,
+                    "num_items": num_items,
+                    "registers": hex(&sketch.registers()),
+                    "cardinality": sketch.cardinality(),
+                    "bias_corrected_cardinality": bias_corrected.cardinality(),
+                }));
+            }
+        }
+    }
+    vectors
+}
+
+fn count_hll_vectors() -> Vec<Value> {
+    let mut vectors = vec![];
+    for (depth, width) in [(16, 4), (64, 8)] {
+        for (num_labels, num_items) in [(4, 100), (8, 10_000)] {
+            let config =
+                count_hll::Config::<SipHash13>::with_hasher(depth, width, Some(COUNT_HLL_SEEDS))
+                    .unwrap();
+            let mut sketch = count_hll::PointwiseSketch::new(&config);
+            stream(num_labels, num_items).for_each(|(label, i)| sketch.insert(&label, &i));
+            vectors.push(json!({
+                "depth": depth,
+                "width": width,
+                "seeds": COUNT_HLL_SEEDS,
+                "num_labels": num_labels,
+                "num_items": num_items,
+                "registers": hex(sketch.registers()),
+                "estimates": (0..num_labels)
+                    .map(|label| (label, sketch.cardinality(&label)))
+                    .collect::<Vec<_>>(),
+            }));
+        }
+    }
+    vectors
+}
+
+/// Tracks at most 64 counters, all of them in the hot array, so that ties
+/// between the counters with the minimum cardinality are broken by insertion
+/// order.
+fn ssss_vectors() -> Vec<Value> {
+    let mut vectors = vec![];
+    for (max_num_counters, num_labels, num_items) in [(4, 4, 1_000), (4, 12, 10_000)] {
+        let config = ssss::Config::<_, _, SipHash13>::with_hasher(
+            max_num_counters,
+            hll_config(64),
+            Some(SSSS_SEEDS),
+        )
+        .unwrap();
+        let mut sketch = ssss::SamplingSpaceSavingSets::<_, HyperLogLog, _, _>::new(&config);
+        stream(num_labels, num_items).for_each(|(label, i)| sketch.insert(label, &i));
+        vectors.push(json!({
+            "max_num_counters": max_num_counters,
+            "seeds": SSSS_SEEDS,
+            "hll": { "num_registers": 64, "seeds": HLL_SEEDS },
+            "num_labels": num_labels,
+            "num_items": num_items,
+            "estimates": estimates(&sketch),
+        }));
+    }
+    vectors
+}
+
+/// Ties between the counters with the minimum cardinality are broken
+/// arbitrarily, so labels all fit in the counters, and how counters are reset
+/// when remapped doesn't matter.
+fn sss_vectors() -> Vec<Value> {
+    let config = sss::Config::new(8, sss::ResetStrategy::Recycle, hll_config(64)).unwrap();
+    let mut sketch = sss::SpaceSavingSets::<_, HyperLogLog>::new(&config);
+    stream(8, 10_000).for_each(|(label, i)| sketch.insert(label, &i));
+    vec![json!({
+        "max_num_counters": 8,
+        "hll": { "num_registers": 64, "seeds": HLL_SEEDS },
+        "num_labels": 8,
+        "num_items": 10_000,
+        "estimates": estimates(&sketch),
+    })]
+}
+
+fn spread_vectors() -> Vec<Value> {
+    let mut vectors = vec![];
+    for (num_rows, num_cols) in [(2, 4), (4, 16)] {
+        let config = spread::Config::<_, SipHash13>::with_hasher(
+            num_rows,
+            num_cols,
+            hll_config(32),
+            Some(SPREAD_SEEDS),
+        )
+        .unwrap();
+        let mut sketch = spread::SpreadSketch::<_, HyperLogLog, _>::new(&config);
+        stream(12, 10_000).for_each(|(label, i)| sketch.insert(label, &i));
+        vectors.push(json!({
+            "num_rows": num_rows,
+            "num_cols": num_cols,
+            "seeds": SPREAD_SEEDS,
+            "hll": { "num_registers": 32, "seeds": HLL_SEEDS },
+            "num_labels": 12,
+            "num_items": 10_000,
+            "estimates": estimates(&sketch),
+        }));
+    }
+    vectors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_matches_the_published_vectors() {
+        let published: Value =
+            serde_json::from_str(include_str!("../vectors/test_vectors.json")).unwrap();
+        assert_eq!(test_vectors(), published);
+    }
+}
@@ -1,62 +1,239 @@
-use std::{error, fmt};
+use core::{error, fmt};
 
 use ahash::RandomState;
-use rand::random;
+
+use sketch_traits::{default_seeds, MemorySize, SeedableBuildHasher};
 
 use crate::dist::{geometric, Distribution};
 
+/// The smallest depth, i.e., number of registers per label: the bias of the
+/// estimator isn't known for fewer.
+pub const MIN_DEPTH: usize = 16;
+
+/// The parameters of Count-HLLs, hashing labels and items with `H`, ahash by
+/// default.
 #[derive(Clone, Debug)]
-pub struct Config {
+pub struct Config<H = RandomState> {
     pub(crate) depth: usize,
     pub(crate) depth_log2: usize,
     pub(crate) width: usize,
-    seeds: [u64; 12],
-    pub(crate) hash_builders: [RandomState; 3],
+    pub(crate) seeds: [u64; 12],
+    pub(crate) hash_builders: [H; 3],
     pub(crate) cardinality_estimation_method: CardinalityEstimationMethod,
+    pub(crate) newton_tolerance: f64,
+    pub(crate) newton_max_iterations: usize,
     // FIXME: Use the same across one across sketch instances.
     pub(crate) geometric: Distribution,
 }
 
 impl Config {
     pub fn new(d: usize, w: usize, seeds: Option<[u64; 12]>) -> Result<Self, ConfigError> {
-        if d & (d - 1) != 0 {
+        Self::with_hasher(d, w, seeds)
+    }
+}
+
+impl<H> Config<H>
+where
+    H: SeedableBuildHasher,
+{
+    /// Like [`Config::new`], but hashing labels and items with `H`, built
+    /// from the seeds.
+    pub fn with_hasher(d: usize, w: usize, seeds: Option<[u64; 12]>) -> Result<Self, ConfigError> {
+        if !d.is_power_of_two() {
             return Err(ConfigError::NonPowerOfTwoDepth);
+        } else if d < MIN_DEPTH {
+            return Err(ConfigError::DepthTooSmall);
         } else if w == 0 {
             return Err(ConfigError::ZeroWidth);
         }
-        let seeds_or_random = seeds.unwrap_or_else(random);
+        let seeds_or_random = seeds.unwrap_or_else(default_seeds);
         Ok(Self {
             depth: d,
-            depth_log2: d.trailing_zeros().try_into().unwrap(),
+            depth_log2: d.trailing_zeros() as usize,
             width: w,
             seeds: seeds_or_random,
             hash_builders: [
-                RandomState::with_seeds(
+                H::with_seeds([
                     seeds_or_random[0],
                     seeds_or_random[1],
                     seeds_or_random[2],
                     seeds_or_random[3],
-                ),
-                RandomState::with_seeds(
+                ]),
+                H::with_seeds([
                     seeds_or_random[4],
                     seeds_or_random[5],
                     seeds_or_random[6],
                     seeds_or_random[7],
-                ),
-                RandomState::with_seeds(
+                ]),
+                H::with_seeds([
                     seeds_or_random[8],
                     seeds_or_random[9],
                     seeds_or_random[10],
                     seeds_or_random[11],
-                ),
+                ]),
             ],
-            cardinality_estimation_method: CardinalityEstimationMethod::MaximumLikelihood,
+            cardinality_estimation_method: CardinalityEstimationMethod::default(),
+            newton_tolerance: DEFAULT_NEWTON_TOLERANCE,
+            newton_max_iterations: DEFAULT_NEWTON_MAX_ITERATIONS,
             geometric: geometric(64, d),
         })
     }
+
+    /// Estimates cardinalities with `cardinality_estimation_method`, e.g.,
+    /// the original estimator, which is cheaper but less accurate than the
+    /// default maximum likelihood one. Sketches only merge with those
+    /// estimating the same way.
+    pub fn with_cardinality_estimation_method(
+        mut self,
+        cardinality_estimation_method: CardinalityEstimationMethod,
+    ) -> Self {
+        self.cardinality_estimation_method = cardinality_estimation_method;
+        self
+    }
+
+    /// Stops the maximum likelihood estimator once a step moves the estimate
+    /// by less than `tolerance` of it, or after `max_iterations` steps.
+    /// Should Newton's method diverge or run out of steps, the estimator
+    /// falls back to bisecting the derivative of the likelihood, to the same
+    /// tolerance and within as many more steps. Fails unless `tolerance` is
+    /// positive and finite and `max_iterations` non-zero. These only tune
+    /// the estimator, so sketches merge whatever their settings.
+    pub fn with_newton_convergence(
+        mut self,
+        tolerance: f64,
+        max_iterations: usize,
+    ) -> Result<Self, ConfigError> {
+        if !(tolerance.is_finite() && tolerance > 0.0) || max_iterations == 0 {
+            return Err(ConfigError::InvalidNewtonConvergence);
+        }
+        self.newton_tolerance = tolerance;
+        self.newton_max_iterations = max_iterations;
+        Ok(self)
+    }
+}
+
+impl<H> Config<H> {
+    pub fn cardinality_estimation_method(&self) -> CardinalityEstimationMethod {
+        self.cardinality_estimation_method
+    }
+
+    pub fn newton_tolerance(&self) -> f64 {
+        self.newton_tolerance
+    }
+
+    pub fn newton_max_iterations(&self) -> usize {
+        self.newton_max_iterations
+    }
 }
 
-impl PartialEq for Config {
+/// The relative tolerance of the maximum likelihood estimator, see
+/// [`Config::with_newton_convergence`].
+pub const DEFAULT_NEWTON_TOLERANCE: f64 = 1e-3;
+
+/// The most steps the maximum likelihood estimator takes with Newton's
+/// method, see [`Config::with_newton_convergence`].
+pub const DEFAULT_NEWTON_MAX_ITERATIONS: usize = 100;
+
+/// The depth of [`Config::default`] and [`Config::builder`].
+pub const DEFAULT_DEPTH: usize = 512;
+
+/// The width of [`Config::default`] and [`Config::builder`], for about a
+/// hundred heavy hitters.
+pub const DEFAULT_WIDTH: usize = 128;
+
+impl Config {
+    /// Builds a config by naming only the parameters that differ from
+    /// [`Config::default`]'s.
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+}
+
+/// [`DEFAULT_DEPTH`] by [`DEFAULT_WIDTH`] registers, random seeds and the
+/// maximum likelihood estimator.
+impl Default for Config {
+    fn default() -> Self {
+        Self::builder()
+            .build()
+            .expect("the default depth and width are supported")
+    }
+}
+
+/// Builds [`Config`]s, see [`Config::builder`].
+#[derive(Clone, Debug)]
+pub struct ConfigBuilder {
+    depth: usize,
+    width: usize,
+    seeds: Option<[u64; 12]>,
+    cardinality_estimation_method: CardinalityEstimationMethod,
+    newton_tolerance: f64,
+    newton_max_iterations: usize,
+}
+
+impl Default for ConfigBuilder {
+    fn default() -> Self {
+        Self {
+            depth: DEFAULT_DEPTH,
+            width: DEFAULT_WIDTH,
+            seeds: None,
+            cardinality_estimation_method: CardinalityEstimationMethod::default(),
+            newton_tolerance: DEFAULT_NEWTON_TOLERANCE,
+            newton_max_iterations: DEFAULT_NEWTON_MAX_ITERATIONS,
+        }
+    }
+}
+
+impl ConfigBuilder {
+    pub fn depth(mut self, depth: usize) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    pub fn width(mut self, width: usize) -> Self {
+        self.width = width;
+        self
+    }
+
+    /// Seeds the hashers, rather than drawing random seeds.
+    pub fn seeds(mut self, seeds: [u64; 12]) -> Self {
+        self.seeds = Some(seeds);
+        self
+    }
+
+    pub fn cardinality_estimation_method(
+        mut self,
+        cardinality_estimation_method: CardinalityEstimationMethod,
+    ) -> Self {
+        self.cardinality_estimation_method = cardinality_estimation_method;
+        self
+    }
+
+    /// See [`Config::with_newton_convergence`], [`build`](Self::build) fails
+    /// for the same settings.
+    pub fn newton_convergence(mut self, tolerance: f64, max_iterations: usize) -> Self {
+        self.newton_tolerance = tolerance;
+        self.newton_max_iterations = max_iterations;
+        self
+    }
+
+    /// Fails like [`Config::new`].
+    pub fn build(self) -> Result<Config, ConfigError> {
+        self.build_with_hasher()
+    }
+
+    /// Like [`build`](Self::build), but hashing labels and items with `H`, as
+    /// [`Config::with_hasher`].
+    pub fn build_with_hasher<H>(self) -> Result<Config<H>, ConfigError>
+    where
+        H: SeedableBuildHasher,
+    {
+        Config::with_hasher(self.depth, self.width, self.seeds)?
+            .with_cardinality_estimation_method(self.cardinality_estimation_method)
+            .with_newton_convergence(self.newton_tolerance, self.newton_max_iterations)
+    }
+}
+
+impl<H> PartialEq for Config<H> {
     fn eq(&self, other: &Self) -> bool {
         self.depth == other.depth
             && self.depth_log2 == other.depth_log2
@@ -66,21 +243,31 @@ impl PartialEq for Config {
     }
 }
 
-impl Eq for Config {}
+impl<H> Eq for Config<H> {}
+
+impl<H> MemorySize for Config<H> {
+    fn heap_size(&self) -> usize {
+        self.geometric.heap_size()
+    }
+}
 
-#[derive(Clone, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CardinalityEstimationMethod {
     /// The original cardinality estimator
     Original,
     /// An estimator that maximizes the composite (log) likelihood using a
     /// Newton-Raphson procedure
+    #[default]
     MaximumLikelihood,
 }
 
 #[derive(Clone, Debug)]
 pub enum ConfigError {
     NonPowerOfTwoDepth,
+    DepthTooSmall,
     ZeroWidth,
+    InvalidNewtonConvergence,
 }
 
 impl fmt::Display for ConfigError {
@@ -89,9 +276,117 @@ impl fmt::Display for ConfigError {
             ConfigError::NonPowerOfTwoDepth => {
                 write!(f, "the depth should be a non-zero power of two")
             }
+            ConfigError::DepthTooSmall => {
+                write!(f, "the depth should be at least {}", MIN_DEPTH)
+            }
             ConfigError::ZeroWidth => write!(f, "the width should not be zero"),
+            ConfigError::InvalidNewtonConvergence => write!(
+                f,
+                "the Newton tolerance should be positive and finite, and the iterations non-zero"
+            ),
         }
     }
 }
 
 impl error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_rejects_unsupported_depths() {
+        for d in [0, 3, 17, usize::MAX] {
+            assert!(matches!(
+                Config::new(d, 8, None),
+                Err(ConfigError::NonPowerOfTwoDepth)
+            ));
+        }
+        for d in [1, 2, 4, 8] {
+            assert!(matches!(
+                Config::new(d, 8, None),
+                Err(ConfigError::DepthTooSmall)
+            ));
+        }
+        assert!(matches!(
+            Config::new(MIN_DEPTH, 0, None),
+            Err(ConfigError::ZeroWidth)
+        ));
+        assert!(Config::new(MIN_DEPTH, 1, None).is_ok());
+    }
+
+    #[test]
+    fn builders_default_to_new() {
+        let seeds = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+        assert_eq!(
+            Config::builder().seeds(seeds).build().unwrap(),
+            Config::new(DEFAULT_DEPTH, DEFAULT_WIDTH, Some(seeds)).unwrap()
+        );
+        assert_eq!(
+            Config::builder()
+                .depth(64)
+                .width(10)
+                .seeds(seeds)
+                .build()
+                .unwrap(),
+            Config::new(64, 10, Some(seeds)).unwrap()
+        );
+        assert!(matches!(
+            Config::builder().width(0).build(),
+            Err(ConfigError::ZeroWidth)
+        ));
+        assert_eq!(Config::default().depth, DEFAULT_DEPTH);
+        assert_eq!(
+            Config::builder()
+                .seeds(seeds)
+                .cardinality_estimation_method(CardinalityEstimationMethod::Original)
+                .build()
+                .unwrap(),
+            Config::new(DEFAULT_DEPTH, DEFAULT_WIDTH, Some(seeds))
+                .unwrap()
+                .with_cardinality_estimation_method(CardinalityEstimationMethod::Original)
+        );
+        assert_eq!(
+            Config::builder()
+                .seeds(seeds)
+                .newton_convergence(1e-6, 10)
+                .build()
+                .unwrap()
+                .newton_tolerance(),
+            1e-6
+        );
+        assert_eq!(
+            Config::default().cardinality_estimation_method(),
+            CardinalityEstimationMethod::MaximumLikelihood
+        );
+    }
+
+    #[test]
+    fn it_rejects_invalid_newton_convergence() {
+        let config = Config::new(MIN_DEPTH, 1, None).unwrap();
+        for (tolerance, max_iterations) in [
+            (0.0, 10),
+            (-1e-3, 10),
+            (f64::NAN, 10),
+            (f64::INFINITY, 10),
+            (1e-3, 0),
+        ] {
+            assert!(matches!(
+                config
+                    .clone()
+                    .with_newton_convergence(tolerance, max_iterations),
+                Err(ConfigError::InvalidNewtonConvergence)
+            ));
+            assert!(matches!(
+                Config::builder()
+                    .newton_convergence(tolerance, max_iterations)
+                    .build(),
+                Err(ConfigError::InvalidNewtonConvergence)
+            ));
+        }
+        // Only tuning the estimator, the settings don't prevent merges.
+        let tuned = config.clone().with_newton_convergence(1e-9, 1).unwrap();
+        assert_eq!(tuned, config);
+        assert_eq!(tuned.newton_max_iterations(), 1);
+    }
+}
@@ -1,3 +1,10 @@
+use alloc::{vec, vec::Vec};
+
+#[cfg(not(feature = "std"))]
+#[allow(unused_imports)]
+use num_traits::Float as _;
+use sketch_traits::MemorySize;
+
 #[derive(Clone, Debug)]
 pub(crate) struct Distribution {
     cdf: Vec<f64>,
@@ -13,7 +20,7 @@ impl Distribution {
         }
 
         // Normalize.
-        let sum = *cdf.last().unwrap();
+        let sum = cdf.last().copied().unwrap_or(0.0);
         cdf.iter_mut().for_each(|c| *c /= sum);
 
         Self { cdf }
@@ -26,7 +33,7 @@ impl Distribution {
     pub(crate) fn cdf(&self, i: isize) -> f64 {
         match i {
             i if i < 0 => 0.0,
-            i if i >= self.cdf.len() as isize => *self.cdf.last().unwrap(),
+            i if i >= self.cdf.len() as isize => self.cdf.last().copied().unwrap_or(0.0),
             i => self.cdf[i as usize],
         }
     }
@@ -42,6 +49,12 @@ impl Distribution {
     }
 }
 
+impl MemorySize for Distribution {
+    fn heap_size(&self) -> usize {
+        self.cdf.heap_size()
+    }
+}
+
 impl FromIterator<usize> for Distribution {
     fn from_iter<T: IntoIterator<Item = usize>>(iter: T) -> Self {
         let mut pmf = vec![];
@@ -1,50 +1,88 @@
-use std::{collections::HashSet, hash::Hash, iter, marker::PhantomData};
+use alloc::vec::Vec;
+use core::{cmp::Reverse, hash::Hash, iter, marker::PhantomData};
 
+use ahash::RandomState;
 use itertools::Itertools;
-use sketch_traits::{HeavyDistinctHitterSketch, New};
+use sketch_traits::{
+    collections::{HashMap, HashSet},
+    truncate_top, HeavyDistinctHitterSketch, InsertOutcome, MemorySize, New, SeedableBuildHasher,
+    Validate, Versioned,
+};
 
-use crate::{Config, MergeError, PointwiseSketch};
+use crate::{
+    Config, InvariantViolation, MergeError, NewtonStep, PointwiseSketch, MAX_REGISTER_VALUE,
+};
 
 #[derive(Clone, Debug)]
-pub struct LabelSetCountHLL<L, I> {
-    sketch: PointwiseSketch,
-    labels: HashSet<L>,
-    item_type: PhantomData<I>,
+pub struct LabelSetCountHLL<L, I, H = RandomState> {
+    pub(crate) sketch: PointwiseSketch<H>,
+    pub(crate) labels: HashSet<L>,
+    item_type: PhantomData<fn(&I)>,
+    version: u64,
 }
 
-impl<L, I> New for LabelSetCountHLL<L, I> {
-    type Config = Config;
+impl<L, I, H> New for LabelSetCountHLL<L, I, H>
+where
+    H: SeedableBuildHasher,
+{
+    type Config = Config<H>;
 
     fn new(config: &Self::Config) -> Self {
         Self {
             sketch: PointwiseSketch::new(config),
             labels: HashSet::new(),
             item_type: PhantomData,
+            version: 0,
         }
     }
 }
 
-impl<L, I> HeavyDistinctHitterSketch for LabelSetCountHLL<L, I>
+impl<L, I, H> HeavyDistinctHitterSketch for LabelSetCountHLL<L, I, H>
 where
     L: Eq + Hash + Clone,
     I: Hash,
+    H: SeedableBuildHasher,
 {
     type Label = L;
     type Item = I;
     type MergeError = MergeError;
 
-    fn insert(&mut self, label: Self::Label, item: &Self::Item) {
+    fn insert_with_outcome(
+        &mut self,
+        label: Self::Label,
+        item: &Self::Item,
+    ) -> InsertOutcome<Self::Label> {
+        self.version = self.version.wrapping_add(1);
         self.sketch.insert(&label, &item);
-        self.labels.insert(label);
+        if self.labels.insert(label) {
+            InsertOutcome::CreatedNew
+        } else {
+            InsertOutcome::UpdatedExisting
+        }
+    }
+
+    /// Adds the label of consecutive pairs sharing it to the set only once.
+    fn insert_iter(&mut self, pairs: impl IntoIterator<Item = (L, I)>) {
+        let mut pairs = pairs.into_iter().peekable();
+        while let Some((label, item)) = pairs.next() {
+            let mut next = Some(item);
+            while let Some(item) = next {
+                self.version = self.version.wrapping_add(1);
+                self.sketch.insert(&label, &item);
+                next = pairs
+                    .next_if(|(next, _)| *next == label)
+                    .map(|(_, item)| item);
+            }
+            self.labels.insert(label);
+        }
     }
 
     fn merge(&mut self, other: &Self) -> Result<(), Self::MergeError> {
-        self.sketch.merge(&other.sketch)?;
-        self.labels.extend(other.labels.iter().cloned());
-        Ok(())
+        self.merge_with(other, PointwiseSketch::merge)
     }
 
     fn clear(&mut self) {
+        self.version = self.version.wrapping_add(1);
         self.sketch.clear();
         self.labels.clear();
     }
@@ -53,6 +91,18 @@ where
         self.sketch.cardinality(label)
     }
 
+    fn cardinality_many(&self, labels: &[L], out: &mut Vec<u64>) {
+        self.sketch.cardinality_many(labels, out);
+    }
+
+    fn labels(&self) -> impl Iterator<Item = &L> {
+        self.labels.iter()
+    }
+
+    fn num_labels(&self) -> usize {
+        self.labels.len()
+    }
+
     fn top(&self, k: usize) -> Vec<(&Self::Label, u64)> {
         self.labels
             .iter()
@@ -62,27 +112,107 @@ where
             .take(k)
             .collect::<Vec<_>>()
     }
+
+    fn top_into(&self, k: usize, out: &mut Vec<(L, u64)>) {
+        out.clear();
+        out.extend(
+            self.labels
+                .iter()
+                .map(|label| (label.clone(), self.cardinality(label))),
+        );
+        truncate_top(out, k);
+    }
 }
 
-impl<L, I> LabelSetCountHLL<L, I> {
-    pub fn num_labels(&self) -> usize {
-        self.labels.len()
+impl<L, I, H> LabelSetCountHLL<L, I, H>
+where
+    L: Eq + Hash + Clone,
+    H: SeedableBuildHasher,
+{
+    /// Merges `other` into `self`, with its registers merged by
+    /// `merge_registers`.
+    pub(crate) fn merge_with(
+        &mut self,
+        other: &Self,
+        merge_registers: impl FnOnce(
+            &mut PointwiseSketch<H>,
+            &PointwiseSketch<H>,
+        ) -> Result<(), MergeError>,
+    ) -> Result<(), MergeError> {
+        merge_registers(&mut self.sketch, &other.sketch)?;
+        self.version = self.version.wrapping_add(1);
+        self.labels.extend(other.labels.iter().cloned());
+        Ok(())
     }
+}
 
+impl<L, I, H> Extend<(L, I)> for LabelSetCountHLL<L, I, H>
+where
+    L: Eq + Hash + Clone,
+    I: Hash,
+    H: SeedableBuildHasher,
+{
+    fn extend<T: IntoIterator<Item = (L, I)>>(&mut self, pairs: T) {
+        self.insert_iter(pairs);
+    }
+}
+
+impl<L, I, H> Versioned for LabelSetCountHLL<L, I, H> {
+    fn version(&self) -> u64 {
+        self.version
+    }
+}
+
+impl<L, I, H> MemorySize for LabelSetCountHLL<L, I, H>
+where
+    L: MemorySize,
+{
+    fn heap_size(&self) -> usize {
+        self.sketch.heap_size() + self.labels.heap_size()
+    }
+}
+
+impl<L, I, H> Validate for LabelSetCountHLL<L, I, H>
+where
+    H: SeedableBuildHasher,
+{
+    type Violation = InvariantViolation;
+
+    fn debug_validate(&self) -> Result<(), Self::Violation> {
+        self.sketch.debug_validate()
+    }
+}
+
+impl<L, I, H> LabelSetCountHLL<L, I, H>
+where
+    H: SeedableBuildHasher,
+{
     pub fn num_registers(&self) -> usize {
         self.sketch.num_registers()
     }
+
+    /// See [`PointwiseSketch::estimate_with_trace`].
+    pub fn estimate_with_trace(&self, label: &L) -> (u64, Vec<NewtonStep>)
+    where
+        L: Hash,
+    {
+        self.sketch.estimate_with_trace(label)
+    }
 }
 
 #[derive(Clone, Debug)]
-pub struct LabelArrayCountHLL<L, I> {
-    sketch: PointwiseSketch,
-    labels: Vec<(Option<L>, u8)>, // and their respective levels
-    item_type: PhantomData<I>,
+pub struct LabelArrayCountHLL<L, I, H = RandomState> {
+    pub(crate) sketch: PointwiseSketch<H>,
+    pub(crate) labels: Vec<(Option<L>, u8)>, // and their respective levels
+    item_type: PhantomData<fn(&I)>,
+    version: u64,
 }
 
-impl<L, I> New for LabelArrayCountHLL<L, I> {
-    type Config = Config;
+impl<L, I, H> New for LabelArrayCountHLL<L, I, H>
+where
+    H: SeedableBuildHasher,
+{
+    type Config = Config<H>;
 
     fn new(config: &Self::Config) -> Self {
         Self {
@@ -91,69 +221,417 @@ impl<L, I> New for LabelArrayCountHLL<L, I> {
                 .take(config.depth * config.width)
                 .collect(),
             item_type: PhantomData,
+            version: 0,
         }
     }
 }
 
-impl<L, I> HeavyDistinctHitterSketch for LabelArrayCountHLL<L, I>
+impl<L, I, H> HeavyDistinctHitterSketch for LabelArrayCountHLL<L, I, H>
 where
     L: Eq + Hash + Clone,
     I: Hash,
+    H: SeedableBuildHasher,
 {
     type Label = L;
     type Item = I;
     type MergeError = MergeError;
 
-    fn insert(&mut self, label: Self::Label, item: &Self::Item) {
+    /// Outcomes only describe the cell the item maps to; the label may also
+    /// be tracked by other cells.
+    fn insert_with_outcome(
+        &mut self,
+        label: Self::Label,
+        item: &Self::Item,
+    ) -> InsertOutcome<Self::Label> {
+        self.version = self.version.wrapping_add(1);
         self.sketch.insert(&label, item);
         let index = self.sketch.get_index(&label, item);
         let z = self.sketch.get_z(&label, item);
         let (label_at_index, level_at_index) = &mut self.labels[index];
-        if z > *level_at_index {
-            (*label_at_index, *level_at_index) = (Some(label), z);
+        if label_at_index.as_ref() == Some(&label) {
+            *level_at_index = (*level_at_index).max(z);
+            InsertOutcome::UpdatedExisting
+        } else if z > *level_at_index {
+            *level_at_index = z;
+            match label_at_index.replace(label) {
+                Some(evicted) => InsertOutcome::Evicted { label: evicted },
+                None => InsertOutcome::CreatedNew,
+            }
+        } else {
+            InsertOutcome::RejectedByThreshold
         }
     }
 
     fn merge(&mut self, other: &Self) -> Result<(), Self::MergeError> {
-        self.sketch.merge(&other.sketch)?;
+        self.merge_with(other, PointwiseSketch::merge)
+    }
+
+    fn clear(&mut self) {
+        self.version = self.version.wrapping_add(1);
+        self.sketch.clear();
+        self.labels.iter_mut().for_each(|l| *l = (None, 0));
+    }
+
+    fn cardinality(&self, label: &Self::Label) -> u64 {
+        self.sketch.cardinality(label)
+    }
+
+    fn cardinality_many(&self, labels: &[L], out: &mut Vec<u64>) {
+        self.sketch.cardinality_many(labels, out);
+    }
+
+    fn labels(&self) -> impl Iterator<Item = &L> {
+        let mut seen = HashSet::new();
+        self.labels
+            .iter()
+            .flat_map(|(label, _)| label)
+            .filter(move |&label| seen.insert(label))
+    }
+
+    /// Counts the cells holding a label, without deduplicating labels held
+    /// by several cells as `labels` does, so this overestimates the number
+    /// of labels.
+    fn num_labels(&self) -> usize {
+        self.labels
+            .iter()
+            .filter(|(label, _)| label.is_some())
+            .count()
+    }
+
+    fn top(&self, k: usize) -> Vec<(&Self::Label, u64)> {
+        self.labels()
+            .map(|label| (label, self.cardinality(label)))
+            .sorted_by_key(|&(_, cardinality)| cardinality)
+            .rev()
+            .take(k)
+            .collect::<Vec<_>>()
+    }
+}
+
+impl<L, I, H> LabelArrayCountHLL<L, I, H>
+where
+    L: Eq + Hash + Clone,
+    H: SeedableBuildHasher,
+{
+    /// Merges `other` into `self`, with its registers merged by
+    /// `merge_registers`.
+    pub(crate) fn merge_with(
+        &mut self,
+        other: &Self,
+        merge_registers: impl FnOnce(
+            &mut PointwiseSketch<H>,
+            &PointwiseSketch<H>,
+        ) -> Result<(), MergeError>,
+    ) -> Result<(), MergeError> {
+        merge_registers(&mut self.sketch, &other.sketch)?;
+        self.version = self.version.wrapping_add(1);
+        // Level ties are broken by keeping the label with the lowest hash, so
+        // that merging is commutative.
+        let sketch = &self.sketch;
+        let priority = |label: &Option<L>, level: u8| {
+            (level, label.as_ref().map(|l| Reverse(sketch.label_hash(l))))
+        };
         self.labels.iter_mut().zip(other.labels.iter()).for_each(
             |((s_label, s_level), (o_label, o_level))| {
-                if o_level > s_level {
+                if priority(o_label, *o_level) > priority(s_label, *s_level) {
                     (*s_label, *s_level) = (o_label.clone(), *o_level);
                 }
             },
         );
         Ok(())
     }
+}
+
+impl<L, I, H> Extend<(L, I)> for LabelArrayCountHLL<L, I, H>
+where
+    L: Eq + Hash + Clone,
+    I: Hash,
+    H: SeedableBuildHasher,
+{
+    fn extend<T: IntoIterator<Item = (L, I)>>(&mut self, pairs: T) {
+        self.insert_iter(pairs);
+    }
+}
+
+impl<L, I, H> Versioned for LabelArrayCountHLL<L, I, H> {
+    fn version(&self) -> u64 {
+        self.version
+    }
+}
+
+impl<L, I, H> MemorySize for LabelArrayCountHLL<L, I, H>
+where
+    L: MemorySize,
+{
+    fn heap_size(&self) -> usize {
+        self.sketch.heap_size() + self.labels.heap_size()
+    }
+}
+
+impl<L, I, H> Validate for LabelArrayCountHLL<L, I, H>
+where
+    H: SeedableBuildHasher,
+{
+    type Violation = InvariantViolation;
+
+    fn debug_validate(&self) -> Result<(), Self::Violation> {
+        self.sketch.debug_validate()?;
+        if self.labels.len() != self.sketch.num_registers() {
+            return Err(InvariantViolation::NumLabelsMismatch);
+        }
+        for (label, level) in self.labels.iter() {
+            if *level > MAX_REGISTER_VALUE {
+                return Err(InvariantViolation::LevelOutOfRange);
+            }
+            if label.is_none() && *level != 0 {
+                return Err(InvariantViolation::EmptyLabelWithLevel);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<L, I, H> LabelArrayCountHLL<L, I, H>
+where
+    H: SeedableBuildHasher,
+{
+    pub fn num_registers(&self) -> usize {
+        self.sketch.num_registers()
+    }
+
+    /// See [`PointwiseSketch::estimate_with_trace`].
+    pub fn estimate_with_trace(&self, label: &L) -> (u64, Vec<NewtonStep>)
+    where
+        L: Hash,
+    {
+        self.sketch.estimate_with_trace(label)
+    }
+}
+
+/// A Count-HLL that keeps a small Space-Saving list of candidate labels
+/// instead of one label per register.
+///
+/// Candidates are weighted by the levels they raise registers to, so labels
+/// with many distinct items get heavier. Up to `width` candidates are kept,
+/// and their cardinalities are still estimated from the whole sketch.
+#[derive(Clone, Debug)]
+pub struct SpaceSavingCountHLL<L, I, H = RandomState> {
+    pub(crate) sketch: PointwiseSketch<H>,
+    pub(crate) candidates: HashMap<L, u64>,
+    item_type: PhantomData<fn(&I)>,
+    version: u64,
+}
+
+impl<L, I, H> New for SpaceSavingCountHLL<L, I, H>
+where
+    H: SeedableBuildHasher,
+{
+    type Config = Config<H>;
+
+    fn new(config: &Self::Config) -> Self {
+        Self {
+            sketch: PointwiseSketch::new(config),
+            candidates: HashMap::with_capacity(config.width),
+            item_type: PhantomData,
+            version: 0,
+        }
+    }
+}
+
+impl<L, I, H> HeavyDistinctHitterSketch for SpaceSavingCountHLL<L, I, H>
+where
+    L: Eq + Hash + Clone,
+    I: Hash,
+    H: SeedableBuildHasher,
+{
+    type Label = L;
+    type Item = I;
+    type MergeError = MergeError;
+
+    fn insert_with_outcome(
+        &mut self,
+        label: Self::Label,
+        item: &Self::Item,
+    ) -> InsertOutcome<Self::Label> {
+        self.version = self.version.wrapping_add(1);
+        let index = self.sketch.get_index(&label, item);
+        let z = self.sketch.get_z(&label, item);
+        let register = self.sketch.register_mut(index);
+        if z <= *register {
+            // Nothing new was learnt about the label.
+            return if self.candidates.contains_key(&label) {
+                InsertOutcome::UpdatedExisting
+            } else {
+                InsertOutcome::RejectedByThreshold
+            };
+        }
+        *register = z;
+
+        // Reaching level z takes about 2^z distinct items.
+        let weight = 1_u64.checked_shl(u32::from(z)).unwrap_or(u64::MAX);
+        if let Some(count) = self.candidates.get_mut(&label) {
+            *count = count.saturating_add(weight);
+            InsertOutcome::UpdatedExisting
+        } else if self.candidates.len() < self.max_num_candidates() {
+            self.candidates.insert(label, weight);
+            InsertOutcome::CreatedNew
+        } else if let Some((min_label, min_count)) = self
+            .candidates
+            .iter()
+            .min_by_key(|&(_, count)| *count)
+            .map(|(label, count)| (label.clone(), *count))
+        {
+            self.candidates.remove(&min_label);
+            self.candidates
+                .insert(label, min_count.saturating_add(weight));
+            InsertOutcome::Evicted { label: min_label }
+        } else {
+            InsertOutcome::RejectedByThreshold
+        }
+    }
+
+    fn merge(&mut self, other: &Self) -> Result<(), Self::MergeError> {
+        self.merge_with(other, PointwiseSketch::merge)
+    }
 
     fn clear(&mut self) {
+        self.version = self.version.wrapping_add(1);
         self.sketch.clear();
-        self.labels.iter_mut().for_each(|l| *l = (None, 0));
+        self.candidates.clear();
     }
 
     fn cardinality(&self, label: &Self::Label) -> u64 {
         self.sketch.cardinality(label)
     }
 
+    fn cardinality_many(&self, labels: &[L], out: &mut Vec<u64>) {
+        self.sketch.cardinality_many(labels, out);
+    }
+
+    fn labels(&self) -> impl Iterator<Item = &L> {
+        self.candidates.keys()
+    }
+
+    fn num_labels(&self) -> usize {
+        self.candidates.len()
+    }
+
     fn top(&self, k: usize) -> Vec<(&Self::Label, u64)> {
-        self.labels
-            .iter()
-            .flat_map(|(label, _)| label)
-            .unique()
+        self.candidates
+            .keys()
             .map(|label| (label, self.cardinality(label)))
-            .sorted_by_key(|&(_, cardinality)| cardinality)
-            .rev()
+            .sorted_by_key(|&(_, cardinality)| Reverse(cardinality))
             .take(k)
             .collect::<Vec<_>>()
     }
+
+    fn top_into(&self, k: usize, out: &mut Vec<(L, u64)>) {
+        out.clear();
+        out.extend(
+            self.candidates
+                .keys()
+                .map(|label| (label.clone(), self.cardinality(label))),
+        );
+        truncate_top(out, k);
+    }
 }
 
-impl<L, I> LabelArrayCountHLL<L, I> {
-    pub fn num_labels(&self) -> usize {
-        self.sketch.config.depth * self.sketch.config.width
+impl<L, I, H> SpaceSavingCountHLL<L, I, H>
+where
+    L: Eq + Hash + Clone,
+    H: SeedableBuildHasher,
+{
+    /// Merges `other` into `self`, with its registers merged by
+    /// `merge_registers`.
+    pub(crate) fn merge_with(
+        &mut self,
+        other: &Self,
+        merge_registers: impl FnOnce(
+            &mut PointwiseSketch<H>,
+            &PointwiseSketch<H>,
+        ) -> Result<(), MergeError>,
+    ) -> Result<(), MergeError> {
+        merge_registers(&mut self.sketch, &other.sketch)?;
+        self.version = self.version.wrapping_add(1);
+        for (label, count) in other.candidates.iter() {
+            let merged = self.candidates.entry(label.clone()).or_insert(0);
+            *merged = merged.saturating_add(*count);
+        }
+        // Ties are broken by label hash so that merging is commutative.
+        let sketch = &self.sketch;
+        let evicted = self
+            .candidates
+            .iter()
+            .map(|(label, count)| (label, *count))
+            .sorted_by_cached_key(|&(label, count)| (Reverse(count), sketch.label_hash(label)))
+            .skip(self.max_num_candidates())
+            .map(|(label, _)| label.clone())
+            .collect::<Vec<_>>();
+        evicted.iter().for_each(|label| {
+            self.candidates.remove(label);
+        });
+        Ok(())
+    }
+}
+
+impl<L, I, H> Extend<(L, I)> for SpaceSavingCountHLL<L, I, H>
+where
+    L: Eq + Hash + Clone,
+    I: Hash,
+    H: SeedableBuildHasher,
+{
+    fn extend<T: IntoIterator<Item = (L, I)>>(&mut self, pairs: T) {
+        self.insert_iter(pairs);
+    }
+}
+
+impl<L, I, H> Versioned for SpaceSavingCountHLL<L, I, H> {
+    fn version(&self) -> u64 {
+        self.version
+    }
+}
+
+impl<L, I, H> MemorySize for SpaceSavingCountHLL<L, I, H>
+where
+    L: MemorySize,
+{
+    fn heap_size(&self) -> usize {
+        self.sketch.heap_size() + self.candidates.heap_size()
+    }
+}
+
+impl<L, I, H> Validate for SpaceSavingCountHLL<L, I, H>
+where
+    H: SeedableBuildHasher,
+{
+    type Violation = InvariantViolation;
+
+    fn debug_validate(&self) -> Result<(), Self::Violation> {
+        self.sketch.debug_validate()?;
+        if self.candidates.len() > self.max_num_candidates() {
+            return Err(InvariantViolation::TooManyCandidates);
+        }
+        Ok(())
+    }
+}
+
+impl<L, I, H> SpaceSavingCountHLL<L, I, H>
+where
+    H: SeedableBuildHasher,
+{
+    pub fn max_num_candidates(&self) -> usize {
+        self.sketch.config.width
     }
 
     pub fn num_registers(&self) -> usize {
         self.sketch.num_registers()
     }
+
+    /// See [`PointwiseSketch::estimate_with_trace`].
+    pub fn estimate_with_trace(&self, label: &L) -> (u64, Vec<NewtonStep>)
+    where
+        L: Hash,
+    {
+        self.sketch.estimate_with_trace(label)
+    }
 }
@@ -1,35 +1,63 @@
-use std::{error, fmt, hash::Hash, iter};
+#![cfg_attr(not(test), deny(clippy::unwrap_used))]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+use alloc::{vec, vec::Vec};
+use core::{error, fmt, hash::Hash};
+
+// The logarithms and powers of the estimators without std, unless std is
+// linked by another crate of the build, e.g., the tests, whose inherent
+// methods then take precedence.
+#[cfg(not(feature = "std"))]
+#[allow(unused_imports)]
+use num_traits::Float as _;
 
 mod config;
 mod dist;
 use crate::dist::Distribution;
 
 mod invertible;
-use sketch_traits::New;
+#[cfg(feature = "rayon")]
+mod par;
+#[cfg(feature = "serde")]
+mod serde_impls;
+use ahash::RandomState;
+use hll::HyperLogLog;
+use sketch_traits::{MemorySize, New, SeedableBuildHasher, Validate};
 
 pub use crate::{
-    config::{CardinalityEstimationMethod, Config, ConfigError},
+    config::{
+        CardinalityEstimationMethod, Config, ConfigBuilder, ConfigError, DEFAULT_DEPTH,
+        DEFAULT_NEWTON_MAX_ITERATIONS, DEFAULT_NEWTON_TOLERANCE, DEFAULT_WIDTH, MIN_DEPTH,
+    },
     invertible::*,
 };
 
 #[derive(Clone, Debug)]
-pub struct PointwiseSketch {
-    config: Config,
+pub struct PointwiseSketch<H = RandomState> {
+    config: Config<H>,
     registers: Vec<u8>,
 }
 
-impl New for PointwiseSketch {
-    type Config = Config;
+impl<H> New for PointwiseSketch<H>
+where
+    H: SeedableBuildHasher,
+{
+    type Config = Config<H>;
 
     fn new(config: &Self::Config) -> Self {
         Self {
             config: config.clone(),
-            registers: iter::repeat(0).take(config.depth * config.width).collect(),
+            registers: vec![0; config.depth * config.width],
         }
     }
 }
 
-impl PointwiseSketch {
+impl<H> PointwiseSketch<H>
+where
+    H: SeedableBuildHasher,
+{
     fn register(&self, r: usize, b: usize) -> &u8 {
         &self.registers[r + (b << self.config.depth_log2)]
     }
@@ -46,6 +74,12 @@ impl PointwiseSketch {
         self.registers.fill(0);
     }
 
+    /// The registers, e.g., to check them against another implementation.
+    /// The register of row `r` in column `b` is at `r + b * depth`.
+    pub fn registers(&self) -> &[u8] {
+        &self.registers
+    }
+
     fn get_index<L, I>(&self, label: &L, item: &I) -> usize
     where
         L: Hash,
@@ -58,17 +92,20 @@ impl PointwiseSketch {
         r + (b << self.config.depth_log2)
     }
 
+    /// Used to break ties between labels deterministically.
+    fn label_hash<L: Hash>(&self, label: &L) -> u64 {
+        self.config.hash_builders[2].hash_one(label)
+    }
+
     fn get_z<L, I>(&self, label: &L, item: &I) -> u8
     where
         I: Hash,
         L: Hash,
     {
-        u8::try_from(
-            self.config.hash_builders[1]
-                .hash_one((item, label))
-                .trailing_zeros(),
-        )
-        .unwrap()
+        // A 64-bit hash has at most 64 trailing zeros, so this cannot truncate.
+        self.config.hash_builders[1]
+            .hash_one((item, label))
+            .trailing_zeros() as u8
             + 1
     }
 
@@ -102,7 +139,7 @@ impl PointwiseSketch {
                     / self
                         .signal(label)
                         .pmf_iter()
-                        .map(|(c, p)| p * 2.0_f64.powi(-i32::try_from(c).unwrap()))
+                        .map(|(c, p)| p * 2.0_f64.powi(-(c as i32)))
                         .sum::<f64>()) as u64
             }
             CardinalityEstimationMethod::MaximumLikelihood => {
@@ -111,16 +148,128 @@ impl PointwiseSketch {
         }
     }
 
+    /// Estimates the cardinality of each of `labels` like
+    /// [`Self::cardinality`] into a caller-provided buffer, which is cleared
+    /// first.
+    ///
+    /// The background of a label is made of all the registers but its own,
+    /// so the maximum likelihood estimator counts the values of all the
+    /// registers once, and takes each label's own registers out of the counts,
+    /// rather than going through all the registers for every label.
+    pub fn cardinality_many<L: Hash>(&self, labels: &[L], out: &mut Vec<u64>) {
+        out.clear();
+        match self.config.cardinality_estimation_method {
+            CardinalityEstimationMethod::Original => {
+                out.extend(labels.iter().map(|label| self.cardinality(label)));
+            }
+            CardinalityEstimationMethod::MaximumLikelihood => {
+                let mut counts = vec![0_usize; usize::from(MAX_REGISTER_VALUE) + 1];
+                self.registers
+                    .iter()
+                    .for_each(|&r| counts[usize::from(r)] += 1);
+                out.extend(labels.iter().map(|label| {
+                    let signal = self
+                        .label_register_indices(label)
+                        .map(|index| usize::from(self.registers[index]))
+                        .collect::<Vec<_>>();
+                    let mut background = counts.clone();
+                    signal.iter().for_each(|&z| background[z] -= 1);
+                    // Like collecting the registers, which only counts values
+                    // up to the largest one.
+                    while background.last() == Some(&0) {
+                        background.pop();
+                    }
+                    self.argmax_cl(
+                        &signal.into_iter().collect(),
+                        &Distribution::new_from_pmf(background),
+                    )
+                }));
+            }
+        }
+    }
+
+    /// Merges the registers `labels` can be inserted into, i.e., one per row
+    /// for each label, instead of all the registers.
+    ///
+    /// Registers are shared by the labels that hash to them, so this also
+    /// updates colliding labels, as well as the background of every label.
+    /// Since merging takes register-wise maxima, shards owning disjoint label
+    /// ranges can each merge their labels, and the result is the same as a
+    /// full merge of the registers of all of these labels.
+    pub fn merge_labels<'a, L>(
+        &mut self,
+        other: &Self,
+        labels: impl IntoIterator<Item = &'a L>,
+    ) -> Result<(), MergeError>
+    where
+        L: Hash + 'a,
+    {
+        if self.config != other.config {
+            return Err(MergeError::ConfigMismatch);
+        }
+        for label in labels {
+            // The configs match, so `other` locates the same registers.
+            for index in other.label_register_indices(label) {
+                self.registers[index] = self.registers[index].max(other.registers[index]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Absorbs `hll`, a HyperLogLog of the items of `label` kept elsewhere,
+    /// as if its items had been inserted under `label`, without replaying
+    /// them.
+    ///
+    /// The registers of a label, one per row, are a HyperLogLog of `depth`
+    /// registers: items pick a row and a trailing zero count by independent
+    /// hashes. So are those of `hll`, and since its register index is
+    /// independent of the value too, folding its registers modulo `depth`
+    /// gives the registers of the same items in a HyperLogLog of `depth`
+    /// registers. Their values, not the items, are the same as inserting
+    /// would have set, as the hashes differ, but they're equally likely.
+    /// Fails if `hll` has fewer registers than `depth`, which can't be spread
+    /// over the rows.
+    pub fn merge_label_hll<L, I, G>(
+        &mut self,
+        label: &L,
+        hll: &HyperLogLog<I, G>,
+    ) -> Result<(), MergeError>
+    where
+        L: Hash,
+    {
+        let depth = self.config.depth;
+        let registers = hll.registers();
+        if registers.len() < depth {
+            return Err(MergeError::TooFewRegisters);
+        }
+        let mut folded = vec![0; depth];
+        for (i, &value) in registers.iter().enumerate() {
+            // Both register counts are powers of two.
+            let r = i & (depth - 1);
+            folded[r] = folded[r].max(value);
+        }
+        let indices = self.label_register_indices(label).collect::<Vec<_>>();
+        for (index, value) in indices.into_iter().zip(folded) {
+            let register = self.register_mut(index);
+            *register = (*register).max(value.min(MAX_REGISTER_VALUE));
+        }
+        Ok(())
+    }
+
+    /// The indices of the registers `label` can be inserted into, one per row.
+    pub fn label_register_indices<'a, L: Hash>(
+        &'a self,
+        label: &'a L,
+    ) -> impl Iterator<Item = usize> + 'a {
+        (0..self.config.depth).map(move |r| {
+            let b = self.config.hash_builders[2].hash_one((r, label)) as usize % self.config.width;
+            r + (b << self.config.depth_log2)
+        })
+    }
+
     fn signal<L: Hash>(&self, label: &L) -> Distribution {
-        (0..self.config.depth)
-            .map(|r| {
-                (
-                    r,
-                    self.config.hash_builders[2].hash_one((r, label)) as usize % self.config.width,
-                )
-            })
-            .map(|(r, b)| *self.register(r, b))
-            .map(|r| r as usize)
+        self.label_register_indices(label)
+            .map(|index| self.registers[index] as usize)
             .collect()
     }
 
@@ -143,7 +292,6 @@ impl PointwiseSketch {
     }
 
     /// Calculates the composite log likelihood.
-    #[allow(dead_code)]
     fn cl(&self, signal: &Distribution, background: &Distribution, n: f64) -> f64 {
         signal
             .pmf_iter()
@@ -163,9 +311,6 @@ impl PointwiseSketch {
             .pmf_iter()
             .map(|(z, w)| (z as isize, w))
             .map(|(z, w)| {
-                #[cfg(feature = "dbg")]
-                dbg!((z, w));
-
                 // TODO: this is used by cl_1 and cl_2, compute once only?
                 (
                     w,
@@ -176,9 +321,6 @@ impl PointwiseSketch {
                 )
             })
             .map(|(w_x, g_x, r_x, phi_x_nom, phi_x_den)| {
-                #[cfg(feature = "dbg")]
-                dbg!((w_x, g_x, r_x, phi_x_nom, phi_x_den));
-
                 let frac = {
                     // We need special handling of the asymptotic behavior.
                     let nom = if r_x == 0.0 && phi_x_nom == 0.0 {
@@ -191,30 +333,20 @@ impl PointwiseSketch {
                     if r_x == 0.00 {
                         0.0
                     } else if den == 0.0 {
-                        #[cfg(feature = "dbg")]
-                        dbg!(n);
-
                         // From the Taylor expansion, assuming that (r[x] - 1) << n(phi[x] - 1).
                         // TODO: prove above assumption.
                         -1.0 / n
                     } else {
-                        #[cfg(feature = "dbg")]
-                        dbg!((nom, den));
-
                         nom / den
                     }
                 };
 
-                #[cfg(feature = "dbg")]
-                dbg!(frac);
                 debug_assert!(n == 0.0 || frac <= 0.0);
 
                 w_x * ((g_x - 1.0).ln_1p() - frac)
             })
             .sum();
 
-        #[cfg(feature = "dbg")]
-        dbg!(cl_1);
         debug_assert!(n == 0.0 || cl_1.is_finite());
 
         cl_1
@@ -227,9 +359,6 @@ impl PointwiseSketch {
             .pmf_iter()
             .map(|(z, w)| (z as isize, w))
             .map(|(z, w)| {
-                #[cfg(feature = "dbg")]
-                dbg!((z, w));
-
                 // TODO: this is used by cl_1 and cl_2, compute once only?
                 (
                     w,
@@ -239,9 +368,6 @@ impl PointwiseSketch {
                 )
             })
             .map(|(w_x, r_x, phi_x_nom, phi_x_den)| {
-                #[cfg(feature = "dbg")]
-                dbg!((w_x, r_x, phi_x_nom, phi_x_den));
-
                 let nom = if r_x == 0.0 && phi_x_nom == 0.0 {
                     // TODO: prove.
                     0.0
@@ -254,29 +380,19 @@ impl PointwiseSketch {
                 let frac = if r_x == 0.0 {
                     0.0
                 } else if den == 0.0 {
-                    #[cfg(feature = "dbg")]
-                    dbg!(n);
-
                     // From the Taylor expansion, assuming that (r[x] - 1) << n(phi[x] - 1).
                     // TODO: prove above assumption.
                     -1.0 / n.powi(2)
                 } else {
-                    #[cfg(feature = "dbg")]
-                    dbg!((nom, den));
-
                     nom / den
                 };
 
-                #[cfg(feature = "dbg")]
-                dbg!(frac);
                 debug_assert!(frac <= 0.0);
 
                 w_x * frac
             })
             .sum();
 
-        #[cfg(feature = "dbg")]
-        dbg!(cl_2);
         debug_assert!(n == 0.0 || cl_2.is_finite());
         // cl is concave.
         debug_assert!(cl_2 <= 0.0);
@@ -285,56 +401,226 @@ impl PointwiseSketch {
     }
 
     fn argmax_cl(&self, signal: &Distribution, background: &Distribution) -> u64 {
-        let max_iters = 100;
-        let mut iters = 0;
+        self.argmax_cl_traced(signal, background, None)
+    }
+
+    /// Maximizes the composite likelihood with Newton's method, recording the
+    /// steps into `trace` if any. Should Newton's method diverge, i.e., step
+    /// to a non-finite or negative estimate, or fail to converge within the
+    /// configured number of steps, the zero of `cl_1` is bisected instead:
+    /// the likelihood is concave, so `cl_1` is decreasing and bisection
+    /// always converges.
+    fn argmax_cl_traced(
+        &self,
+        signal: &Distribution,
+        background: &Distribution,
+        mut trace: Option<&mut Vec<NewtonStep>>,
+    ) -> u64 {
+        let tolerance = self.config.newton_tolerance;
+        let max_iterations = self.config.newton_max_iterations;
         let mut n = 1.0;
-        loop {
-            if iters > max_iters {
+        for _ in 0..max_iterations {
+            let cl_1 = self.cl_1(signal, background, n);
+            let cl_2 = self.cl_2(signal, background, n);
+            let shift = -cl_1 / cl_2;
+            let next = n + shift;
+            if !next.is_finite() || next < 0.0 {
                 break;
             }
 
-            #[cfg(feature = "dbg")]
-            let cl = self.cl(signal, background, n);
+            if let Some(trace) = trace.as_mut() {
+                trace.push(NewtonStep {
+                    n,
+                    cl: self.cl(signal, background, n),
+                    cl_1,
+                    cl_2,
+                    shift,
+                });
+            }
+            n = next;
+            if shift.abs() / n < tolerance {
+                return n.round() as u64;
+            }
+        }
+        self.bisect_cl_1(signal, background, trace).round() as u64
+    }
 
+    /// Bisects the zero of `cl_1` between 0 and a power of two it's negative
+    /// at, recording the steps into `trace` if any, each shifting the
+    /// estimate to the middle of the remaining bracket.
+    fn bisect_cl_1(
+        &self,
+        signal: &Distribution,
+        background: &Distribution,
+        mut trace: Option<&mut Vec<NewtonStep>>,
+    ) -> f64 {
+        let tolerance = self.config.newton_tolerance;
+        let max_iterations = self.config.newton_max_iterations;
+        let mut low = 0.0;
+        let mut high = 1.0_f64;
+        // Beyond 2^64, estimates saturate when rounded anyway.
+        while high < TWO_POW_64 && self.cl_1(signal, background, high) > 0.0 {
+            low = high;
+            high *= 2.0;
+        }
+        let mut n = 0.5 * (low + high);
+        for _ in 0..max_iterations {
+            // Relative to the estimate, but not below 1, as the zero may be 0.
+            if high - low < tolerance * n.max(1.0) {
+                break;
+            }
             let cl_1 = self.cl_1(signal, background, n);
-            let cl_2 = self.cl_2(signal, background, n);
-            let shift = -cl_1 / cl_2;
+            if cl_1 > 0.0 {
+                low = n;
+            } else {
+                high = n;
+            }
+            let next = 0.5 * (low + high);
+            if let Some(trace) = trace.as_mut() {
+                trace.push(NewtonStep {
+                    n,
+                    cl: self.cl(signal, background, n),
+                    cl_1,
+                    cl_2: self.cl_2(signal, background, n),
+                    shift: next - n,
+                });
+            }
+            n = next;
+        }
+        n
+    }
 
-            #[cfg(feature = "dbg")]
-            dbg!((n, cl, cl_1, cl_2, shift));
-            n += shift;
-            // TODO: refine convergence criterion.
-            if shift.abs() / n < 1e-3 {
-                return n.round() as u64;
+    /// Estimates the cardinality of `label` like [`Self::cardinality`], and
+    /// returns the steps the maximum likelihood estimator took to get there,
+    /// e.g., to inspect or plot its convergence. The trace is empty with the
+    /// original estimator.
+    pub fn estimate_with_trace<L: Hash>(&self, label: &L) -> (u64, Vec<NewtonStep>) {
+        match self.config.cardinality_estimation_method {
+            CardinalityEstimationMethod::Original => (self.cardinality(label), vec![]),
+            CardinalityEstimationMethod::MaximumLikelihood => {
+                let mut trace = vec![];
+                let estimate = self.argmax_cl_traced(
+                    &self.signal(label),
+                    &self.background(label),
+                    Some(&mut trace),
+                );
+                (estimate, trace)
             }
-            iters += 1;
         }
-        #[cfg(feature = "dbg")]
-        dbg!("Broke after {} iters", max_iters);
-        n.round() as u64
     }
 }
 
+/// A Newton step of the maximum likelihood cardinality estimator, or one of
+/// the bisection it falls back to, see [`Config::with_newton_convergence`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct NewtonStep {
+    /// The estimate the step started from.
+    pub n: f64,
+    /// The composite log likelihood at `n`.
+    pub cl: f64,
+    /// Its first derivative with respect to `n`.
+    pub cl_1: f64,
+    /// Its second derivative with respect to `n`.
+    pub cl_2: f64,
+    /// How much the step moved the estimate.
+    pub shift: f64,
+}
+
+/// 2^64, past which the maximum likelihood estimator stops bracketing.
+const TWO_POW_64: f64 = 18_446_744_073_709_551_616.0;
+
+/// The largest value a register can hold: the number of trailing zeros of a
+/// 64-bit hash, plus one.
+pub const MAX_REGISTER_VALUE: u8 = 65;
+
+impl<H> MemorySize for PointwiseSketch<H> {
+    fn heap_size(&self) -> usize {
+        self.config.heap_size() + self.registers.heap_size()
+    }
+}
+
+impl<H> Validate for PointwiseSketch<H>
+where
+    H: SeedableBuildHasher,
+{
+    type Violation = InvariantViolation;
+
+    fn debug_validate(&self) -> Result<(), Self::Violation> {
+        if self.registers.len() != self.num_registers() {
+            return Err(InvariantViolation::NumRegistersMismatch);
+        }
+        if self.registers.iter().any(|&r| r > MAX_REGISTER_VALUE) {
+            return Err(InvariantViolation::RegisterOutOfRange);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum InvariantViolation {
+    NumRegistersMismatch,
+    RegisterOutOfRange,
+    NumLabelsMismatch,
+    LevelOutOfRange,
+    EmptyLabelWithLevel,
+    TooManyCandidates,
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvariantViolation::NumRegistersMismatch => {
+                write!(f, "the number of registers does not match the config")
+            }
+            InvariantViolation::RegisterOutOfRange => {
+                write!(f, "a register exceeds {}", MAX_REGISTER_VALUE)
+            }
+            InvariantViolation::NumLabelsMismatch => {
+                write!(f, "the number of label slots does not match the config")
+            }
+            InvariantViolation::LevelOutOfRange => {
+                write!(f, "a label level exceeds {}", MAX_REGISTER_VALUE)
+            }
+            InvariantViolation::EmptyLabelWithLevel => {
+                write!(f, "an empty label slot has a non-zero level")
+            }
+            InvariantViolation::TooManyCandidates => {
+                write!(f, "there are more candidate labels than the sketch width")
+            }
+        }
+    }
+}
+
+impl error::Error for InvariantViolation {}
+
 #[derive(Clone, Debug)]
 pub enum MergeError {
     ConfigMismatch,
+    /// A HyperLogLog merged into a label has fewer registers than rows.
+    TooFewRegisters,
 }
 
 impl fmt::Display for MergeError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             MergeError::ConfigMismatch => write!(f, "sketch configs do not match"),
+            MergeError::TooFewRegisters => {
+                write!(
+                    f,
+                    "the HyperLogLog has fewer registers than the sketch depth"
+                )
+            }
         }
     }
 }
 
 impl error::Error for MergeError {}
 
+/// The bias correction of the estimator, for a power of two depth of at
+/// least [`MIN_DEPTH`].
 const fn alpha(d: usize) -> f64 {
-    assert!(d & (d - 1) == 0); // non-zero power of 2
     match d {
-        1 | 2 | 4 | 8 => panic!(),
-        16 => 0.673,
+        ..=16 => 0.673,
         32 => 0.697,
         64 => 0.709,
         128 => 0.715,
@@ -439,6 +725,144 @@ mod tests {
         });
     }
 
+    #[test]
+    fn it_traces_the_estimator() {
+        let mut sketch = PointwiseSketch::new(&seeded_config(COUNTER_SIZE, 1000));
+        (0..6_000_u64).for_each(|x| sketch.insert(&(x % 3), &x));
+
+        let (estimate, trace) = sketch.estimate_with_trace(&0_u64);
+        assert_eq!(estimate, sketch.cardinality(&0_u64));
+        assert!(!trace.is_empty());
+        assert_eq!(trace[0].n, 1.0);
+        let last = trace.last().unwrap();
+        assert_eq!(estimate, (last.n + last.shift).round() as u64);
+        // Newton's method climbs the concave likelihood.
+        assert!(trace.windows(2).all(|steps| steps[1].cl >= steps[0].cl));
+    }
+
+    #[test]
+    fn merging_all_labels_matches_merge() {
+        let config = seeded_config(64, 100);
+        let mut sketch1 = PointwiseSketch::new(&config);
+        let mut sketch2 = PointwiseSketch::new(&config);
+        (0..5_000_u64).for_each(|x| sketch1.insert(&(x % 10), &x));
+        (0..5_000_u64).for_each(|x| sketch2.insert(&(x % 20), &(x + 2_500)));
+
+        // Shards owning even and odd labels.
+        let even = (0..20_u64).filter(|l| l % 2 == 0).collect::<Vec<_>>();
+        let odd = (0..20_u64).filter(|l| l % 2 == 1).collect::<Vec<_>>();
+        let mut partial = sketch1.clone();
+        assert!(partial.merge_labels(&sketch2, &even).is_ok());
+        let mut merged = sketch1.clone();
+        assert!(merged.merge(&sketch2).is_ok());
+        for label in even.iter() {
+            assert!(partial
+                .label_register_indices(label)
+                .all(|i| partial.registers[i] == merged.registers[i]));
+        }
+
+        assert!(partial.merge_labels(&sketch2, &odd).is_ok());
+        assert_eq!(partial.registers, merged.registers);
+        assert!(partial
+            .merge_labels(&PointwiseSketch::new(&seeded_config(64, 50)), &odd)
+            .is_err());
+    }
+
+    #[test]
+    fn merging_label_hlls_estimates_like_inserting() {
+        let config = seeded_config(COUNTER_SIZE, 100);
+        let mut inserted = PointwiseSketch::new(&config);
+        let mut merged = PointwiseSketch::new(&config);
+        for (label, num_items, num_hll_registers) in [(0_u64, 3_000_u64, 1024), (1, 20_000, 4096)] {
+            let hll_config = hll::Config::new(num_hll_registers, Some([0, 1, 2, 3, 4, 5, 6, 7]));
+            let mut hll = HyperLogLog::new(&hll_config.unwrap());
+            for i in 0..num_items {
+                inserted.insert(&label, &i);
+                sketch_traits::CardinalitySketch::insert(&mut hll, &i);
+            }
+            assert!(merged.merge_label_hll(&label, &hll).is_ok());
+        }
+        for (label, num_items) in [(0_u64, 3_000_f64), (1, 20_000_f64)] {
+            let estimate = merged.cardinality(&label) as f64;
+            assert!((estimate - num_items).abs() / num_items < 0.1);
+            let expected = inserted.cardinality(&label) as f64;
+            assert!((estimate - expected).abs() / expected < 0.1);
+        }
+
+        let small = HyperLogLog::<u64>::new(&hll::Config::new(512, None).unwrap());
+        assert!(matches!(
+            merged.merge_label_hll(&0_u64, &small),
+            Err(MergeError::TooFewRegisters)
+        ));
+    }
+
+    #[test]
+    fn estimation_methods_are_part_of_merge_compatibility() {
+        let config = seeded_config(COUNTER_SIZE, 100);
+        let original = config
+            .clone()
+            .with_cardinality_estimation_method(CardinalityEstimationMethod::Original);
+        let mut sketch = PointwiseSketch::new(&config);
+        let mut other = PointwiseSketch::new(&original);
+        (0..5_000_u64).for_each(|x| other.insert(&(x % 2), &x));
+        assert!(matches!(
+            sketch.merge(&other),
+            Err(MergeError::ConfigMismatch)
+        ));
+        assert!(sketch.merge_labels(&other, &[0_u64]).is_err());
+
+        let mut merged = PointwiseSketch::new(&original);
+        assert!(merged.merge(&other).is_ok());
+        let estimate = merged.cardinality(&0_u64) as f64;
+        assert!((estimate - 2_500.0).abs() / 2_500.0 < 0.1);
+        assert!(merged.estimate_with_trace(&0_u64).1.is_empty());
+    }
+
+    #[test]
+    fn it_falls_back_to_bisection_on_pathological_registers() {
+        let sketch = PointwiseSketch::new(&seeded_config(COUNTER_SIZE, 100));
+        // The registers of a label explained by the background, all zero or
+        // all saturated.
+        let cases = [
+            (vec![0; COUNTER_SIZE], vec![20; 1000], 0..=0),
+            (vec![12; COUNTER_SIZE], vec![12; 1000], 0..=0),
+            (vec![65; COUNTER_SIZE], vec![0; 1000], 1 << 63..=u64::MAX),
+        ];
+        for (signal, background, expected) in cases {
+            let signal = signal.into_iter().collect();
+            let background = background.into_iter().collect();
+            let mut trace = vec![];
+            let estimate = sketch.argmax_cl_traced(&signal, &background, Some(&mut trace));
+            assert!(expected.contains(&estimate));
+            assert!(trace.iter().all(|step| step.n.is_finite() && step.n >= 0.0));
+            let bisected = sketch.bisect_cl_1(&signal, &background, None);
+            assert_eq!(estimate, bisected.round() as u64);
+        }
+    }
+
+    #[test]
+    fn it_converges_within_the_configured_iterations() {
+        let config = seeded_config(COUNTER_SIZE, 1000);
+        let mut sketch = PointwiseSketch::new(&config);
+        (0..6_000_u64).for_each(|x| sketch.insert(&(x % 3), &x));
+        let expected = sketch.cardinality(&0_u64) as f64;
+
+        // Too few Newton steps to converge from 1, so the rest is bisected.
+        let mut bisected =
+            PointwiseSketch::new(&config.clone().with_newton_convergence(1e-3, 12).unwrap());
+        bisected.merge(&sketch).unwrap();
+        let (estimate, trace) = bisected.estimate_with_trace(&0_u64);
+        assert!((estimate as f64 - expected).abs() / expected < 2e-3);
+        assert!(trace.len() > 12);
+        let last = trace.last().unwrap();
+        assert_eq!(estimate, (last.n + last.shift).round() as u64);
+
+        let mut precise = PointwiseSketch::new(&config.with_newton_convergence(1e-9, 100).unwrap());
+        precise.merge(&sketch).unwrap();
+        let estimate = precise.cardinality(&0_u64) as f64;
+        assert!((estimate - expected).abs() / expected < 2e-3);
+    }
+
     #[test]
     #[ignore]
     fn print_cl() {
@@ -467,6 +891,25 @@ mod tests {
             });
     }
 
+    #[test]
+    fn it_validates_invariants() {
+        let mut sketch = LabelArrayCountHLL::new(&seeded_config(COUNTER_SIZE, 10));
+        assert!(sketch.debug_validate().is_ok());
+        for l in 1..8 {
+            for i in 0..l * 10 {
+                sketch.insert(l, &i);
+            }
+        }
+        assert!(sketch.debug_validate().is_ok());
+
+        let mut corrupted = PointwiseSketch::new(&seeded_config(COUNTER_SIZE, 10));
+        corrupted.registers[0] = MAX_REGISTER_VALUE + 1;
+        assert!(matches!(
+            corrupted.debug_validate(),
+            Err(InvariantViolation::RegisterOutOfRange)
+        ));
+    }
+
     #[test]
     fn test_top() {
         let num_labels = 8;
@@ -478,6 +921,7 @@ mod tests {
             }
         }
         assert!(sketch.top(10).len() == num_labels - 1);
+        assert_eq!(sketch.num_labels(), num_labels - 1);
 
         let mut sketch = LabelArrayCountHLL::new(&seeded_config(COUNTER_SIZE, 10));
         for l in 1..num_labels {
@@ -487,5 +931,101 @@ mod tests {
             }
         }
         assert!(sketch.top(10).len() == num_labels - 1);
+        // Labels span several cells.
+        assert!(sketch.num_labels() >= num_labels - 1);
+    }
+
+    #[test]
+    fn space_saving_count_hll_finds_heavy_labels() {
+        let config = seeded_config(256, 16);
+        let mut sketch1 = SpaceSavingCountHLL::<u64, u64>::new(&config);
+        let mut sketch2 = SpaceSavingCountHLL::<u64, u64>::new(&config);
+        // 8 heavy labels with 2000 items each, among 2000 light labels.
+        for i in 0..16_000_u64 {
+            let sketch = if i % 2 == 0 {
+                &mut sketch1
+            } else {
+                &mut sketch2
+            };
+            sketch.insert(i % 8, &i);
+            sketch.insert(8 + i % 2000, &(i / 2000));
+        }
+        assert!(sketch1.merge(&sketch2).is_ok());
+        assert!(sketch1.debug_validate().is_ok());
+        assert!(sketch1.num_labels() <= sketch1.max_num_candidates());
+
+        let top = sketch1.top(8);
+        assert!(top.iter().all(|(&label, _)| label < 8));
+        let mut out = vec![];
+        sketch1.top_into(8, &mut out);
+        assert_eq!(
+            out.iter().map(|&(_, c)| c).collect::<Vec<_>>(),
+            top.iter().map(|&(_, c)| c).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn cardinality_many_matches_cardinality() {
+        let config = seeded_config(COUNTER_SIZE, 10);
+        proptest!(ProptestConfig::with_cases(32), |(pairs in proptest::collection::vec((0..20_u8, any::<u64>()), 0..500))| {
+            let mut sketch = LabelSetCountHLL::new(&config);
+            sketch.extend(pairs);
+            // Labels that were never inserted are estimated too.
+            let labels = (0..30_u8).collect::<Vec<_>>();
+            let mut out = vec![];
+            sketch.cardinality_many(&labels, &mut out);
+            let expected = labels.iter().map(|label| sketch.cardinality(label)).collect::<Vec<_>>();
+            prop_assert_eq!(out, expected);
+        });
+    }
+
+    const MERGE_ORDERS: [[usize; 3]; 6] = [
+        [0, 1, 2],
+        [0, 2, 1],
+        [1, 0, 2],
+        [1, 2, 0],
+        [2, 0, 1],
+        [2, 1, 0],
+    ];
+
+    #[test]
+    fn merge_order_does_not_matter() {
+        let config = seeded_config(16, 4);
+        // Few labels make level ties between labels likely.
+        proptest!(ProptestConfig::with_cases(64), |(pairs in proptest::collection::vec(proptest::collection::vec((0..8_u8, any::<u64>()), 0..100), 3))| {
+            let sketches = pairs
+                .iter()
+                .map(|pairs| {
+                    let mut sketch = LabelArrayCountHLL::<u8, u64>::new(&config);
+                    pairs.iter().for_each(|(label, item)| sketch.insert(*label, item));
+                    sketch
+                })
+                .collect::<Vec<_>>();
+            let tops = MERGE_ORDERS.map(|order| {
+                let mut merged = sketches[order[0]].clone();
+                order[1..].iter().for_each(|&i| merged.merge(&sketches[i]).unwrap());
+                let mut top = merged
+                    .top(usize::MAX)
+                    .into_iter()
+                    .map(|(label, cardinality)| (*label, cardinality))
+                    .collect::<Vec<_>>();
+                top.sort();
+                top
+            });
+            for top in tops.iter() {
+                prop_assert_eq!(top, &tops[0]);
+            }
+        });
+    }
+
+    #[test]
+    fn it_is_send_and_sync_regardless_of_items() {
+        use std::rc::Rc;
+
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<PointwiseSketch>();
+        assert_send_sync::<LabelSetCountHLL<u32, Rc<str>>>();
+        assert_send_sync::<LabelArrayCountHLL<u32, Rc<str>>>();
+        assert_send_sync::<SpaceSavingCountHLL<u32, Rc<str>>>();
     }
 }
@@ -0,0 +1,75 @@
+//! Parallel merges, for aggregators merging many sketches.
+
+use core::hash::Hash;
+
+use rayon::prelude::*;
+use sketch_traits::SeedableBuildHasher;
+
+use crate::{
+    LabelArrayCountHLL, LabelSetCountHLL, MergeError, PointwiseSketch, SpaceSavingCountHLL,
+};
+
+/// Enough registers for merging a chunk to outweigh handing it off to
+/// another thread.
+const CHUNK_SIZE: usize = 4096;
+
+impl<H> PointwiseSketch<H>
+where
+    H: SeedableBuildHasher,
+{
+    /// Like `merge`, but merges chunks of registers in parallel, on rayon's
+    /// thread pool.
+    pub fn par_merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        if self.config != other.config {
+            return Err(MergeError::ConfigMismatch);
+        }
+        self.registers
+            .par_chunks_mut(CHUNK_SIZE)
+            .zip(other.registers.par_chunks(CHUNK_SIZE))
+            .for_each(|(s, o)| s.iter_mut().zip(o.iter()).for_each(|(s, o)| *s = *o.max(s)));
+        Ok(())
+    }
+}
+
+macro_rules! impl_par_merge {
+    ($($sketch:ident),*) => {
+        $(
+            impl<L, I, H> $sketch<L, I, H>
+            where
+                L: Eq + Hash + Clone,
+                H: SeedableBuildHasher,
+            {
+                /// Like `merge`, but merges the registers in parallel, on
+                /// rayon's thread pool.
+                pub fn par_merge(&mut self, other: &Self) -> Result<(), MergeError> {
+                    self.merge_with(other, PointwiseSketch::par_merge)
+                }
+            }
+        )*
+    };
+}
+
+impl_par_merge!(LabelSetCountHLL, LabelArrayCountHLL, SpaceSavingCountHLL);
+
+#[cfg(test)]
+mod tests {
+    use sketch_traits::{HeavyDistinctHitterSketch, New};
+
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn par_merge_matches_merge() {
+        let config = Config::new(64, 1024, Some([7; 12])).unwrap();
+        let mut left = SpaceSavingCountHLL::new(&config);
+        let mut right = left.clone();
+        left.extend((0..10_000_u64).map(|i| (i % 30, i)));
+        right.extend((0..10_000_u64).map(|i| (i % 50, i * 7)));
+
+        let mut merged = left.clone();
+        assert!(merged.merge(&right).is_ok());
+        assert!(left.par_merge(&right).is_ok());
+        assert_eq!(left.sketch.registers(), merged.sketch.registers());
+        assert_eq!(left.top(usize::MAX), merged.top(usize::MAX));
+    }
+}
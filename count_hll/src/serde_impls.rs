@@ -0,0 +1,387 @@
+//! Sketches serialize their registers along with their config, and their
+//! labels; configs only serialize their parameters and seeds, and are
+//! rebuilt, and checked, from them when deserializing.
+
+use alloc::vec::Vec;
+use core::hash::Hash;
+
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+use sketch_traits::{
+    collections::{HashMap, HashSet},
+    New, SeedableBuildHasher, Validate,
+};
+
+use crate::{
+    CardinalityEstimationMethod, Config, LabelArrayCountHLL, LabelSetCountHLL, PointwiseSketch,
+    SpaceSavingCountHLL, DEFAULT_NEWTON_MAX_ITERATIONS, DEFAULT_NEWTON_TOLERANCE,
+};
+
+#[derive(Serialize)]
+#[serde(rename = "Config")]
+struct ConfigRef<'a> {
+    depth: usize,
+    width: usize,
+    seeds: &'a [u64; 12],
+    cardinality_estimation_method: &'a CardinalityEstimationMethod,
+    newton_tolerance: f64,
+    newton_max_iterations: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "Config")]
+struct ConfigRepr {
+    depth: usize,
+    width: usize,
+    seeds: [u64; 12],
+    cardinality_estimation_method: CardinalityEstimationMethod,
+    // Configs serialized before these were configurable used the defaults.
+    #[serde(default = "default_newton_tolerance")]
+    newton_tolerance: f64,
+    #[serde(default = "default_newton_max_iterations")]
+    newton_max_iterations: usize,
+}
+
+fn default_newton_tolerance() -> f64 {
+    DEFAULT_NEWTON_TOLERANCE
+}
+
+fn default_newton_max_iterations() -> usize {
+    DEFAULT_NEWTON_MAX_ITERATIONS
+}
+
+impl<H> Serialize for Config<H> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ConfigRef {
+            depth: self.depth,
+            width: self.width,
+            seeds: &self.seeds,
+            cardinality_estimation_method: &self.cardinality_estimation_method,
+            newton_tolerance: self.newton_tolerance,
+            newton_max_iterations: self.newton_max_iterations,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, H> Deserialize<'de> for Config<H>
+where
+    H: SeedableBuildHasher,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = ConfigRepr::deserialize(deserializer)?;
+        if repr.depth == 0 {
+            return Err(D::Error::custom(
+                "the depth should be a non-zero power of two",
+            ));
+        }
+        let mut config = Config::with_hasher(repr.depth, repr.width, Some(repr.seeds))
+            .map_err(D::Error::custom)?;
+        config.cardinality_estimation_method = repr.cardinality_estimation_method;
+        config
+            .with_newton_convergence(repr.newton_tolerance, repr.newton_max_iterations)
+            .map_err(D::Error::custom)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename = "PointwiseSketch", bound = "")]
+struct PointwiseSketchRef<'a, H> {
+    config: &'a Config<H>,
+    registers: &'a [u8],
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "PointwiseSketch", bound = "H: SeedableBuildHasher")]
+struct PointwiseSketchRepr<H> {
+    config: Config<H>,
+    registers: Vec<u8>,
+}
+
+impl<H> Serialize for PointwiseSketch<H> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        PointwiseSketchRef {
+            config: &self.config,
+            registers: &self.registers,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, H> Deserialize<'de> for PointwiseSketch<H>
+where
+    H: SeedableBuildHasher,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = PointwiseSketchRepr::<H>::deserialize(deserializer)?;
+        let mut sketch = Self::new(&repr.config);
+        sketch.registers = repr.registers;
+        sketch.debug_validate().map_err(D::Error::custom)?;
+        Ok(sketch)
+    }
+}
+
+/// Serializes labels as a sequence, whichever hash set holds them.
+struct LabelsRef<'a, L>(&'a HashSet<L>);
+
+impl<L> Serialize for LabelsRef<'_, L>
+where
+    L: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter())
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename = "LabelSetCountHLL", bound = "L: Serialize")]
+struct LabelSetRef<'a, L, H> {
+    sketch: &'a PointwiseSketch<H>,
+    labels: LabelsRef<'a, L>,
+}
+
+#[derive(Deserialize)]
+#[serde(
+    rename = "LabelSetCountHLL",
+    bound = "L: Deserialize<'de>, H: SeedableBuildHasher"
+)]
+struct LabelSetRepr<L, H> {
+    sketch: PointwiseSketch<H>,
+    labels: Vec<L>,
+}
+
+impl<L, I, H> Serialize for LabelSetCountHLL<L, I, H>
+where
+    L: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        LabelSetRef {
+            sketch: &self.sketch,
+            labels: LabelsRef(&self.labels),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, L, I, H> Deserialize<'de> for LabelSetCountHLL<L, I, H>
+where
+    L: Deserialize<'de> + Eq + Hash,
+    H: SeedableBuildHasher,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = LabelSetRepr::<L, H>::deserialize(deserializer)?;
+        let mut sketch = Self::new(&repr.sketch.config);
+        sketch.sketch = repr.sketch;
+        sketch.labels = repr.labels.into_iter().collect();
+        Ok(sketch)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename = "LabelArrayCountHLL", bound = "L: Serialize")]
+struct LabelArrayRef<'a, L, H> {
+    sketch: &'a PointwiseSketch<H>,
+    labels: &'a [(Option<L>, u8)],
+}
+
+#[derive(Deserialize)]
+#[serde(
+    rename = "LabelArrayCountHLL",
+    bound = "L: Deserialize<'de>, H: SeedableBuildHasher"
+)]
+struct LabelArrayRepr<L, H> {
+    sketch: PointwiseSketch<H>,
+    labels: Vec<(Option<L>, u8)>,
+}
+
+impl<L, I, H> Serialize for LabelArrayCountHLL<L, I, H>
+where
+    L: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        LabelArrayRef {
+            sketch: &self.sketch,
+            labels: &self.labels,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, L, I, H> Deserialize<'de> for LabelArrayCountHLL<L, I, H>
+where
+    L: Deserialize<'de>,
+    H: SeedableBuildHasher,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = LabelArrayRepr::<L, H>::deserialize(deserializer)?;
+        let mut sketch = Self::new(&repr.sketch.config);
+        sketch.sketch = repr.sketch;
+        sketch.labels = repr.labels;
+        sketch.debug_validate().map_err(D::Error::custom)?;
+        Ok(sketch)
+    }
+}
+
+/// Serializes candidates as a sequence of pairs, since labels may not be
+/// valid map keys in every format.
+struct CandidatesRef<'a, L>(&'a HashMap<L, u64>);
+
+impl<L> Serialize for CandidatesRef<'_, L>
+where
+    L: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter())
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename = "SpaceSavingCountHLL", bound = "L: Serialize")]
+struct SpaceSavingRef<'a, L, H> {
+    sketch: &'a PointwiseSketch<H>,
+    candidates: CandidatesRef<'a, L>,
+}
+
+#[derive(Deserialize)]
+#[serde(
+    rename = "SpaceSavingCountHLL",
+    bound = "L: Deserialize<'de>, H: SeedableBuildHasher"
+)]
+struct SpaceSavingRepr<L, H> {
+    sketch: PointwiseSketch<H>,
+    candidates: Vec<(L, u64)>,
+}
+
+impl<L, I, H> Serialize for SpaceSavingCountHLL<L, I, H>
+where
+    L: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SpaceSavingRef {
+            sketch: &self.sketch,
+            candidates: CandidatesRef(&self.candidates),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, L, I, H> Deserialize<'de> for SpaceSavingCountHLL<L, I, H>
+where
+    L: Deserialize<'de> + Eq + Hash,
+    H: SeedableBuildHasher,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = SpaceSavingRepr::<L, H>::deserialize(deserializer)?;
+        let mut sketch = Self::new(&repr.sketch.config);
+        sketch.sketch = repr.sketch;
+        for (label, estimate) in repr.candidates {
+            if sketch.candidates.insert(label, estimate).is_some() {
+                return Err(D::Error::custom("a label is a candidate more than once"));
+            }
+        }
+        sketch.debug_validate().map_err(D::Error::custom)?;
+        Ok(sketch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sketch_traits::HeavyDistinctHitterSketch;
+
+    use super::*;
+
+    fn config() -> Config {
+        Config::new(16, 8, Some([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11])).unwrap()
+    }
+
+    fn round_trip<T>(sketch: &T) -> T
+    where
+        T: Serialize + for<'de> Deserialize<'de>,
+    {
+        serde_json::from_str(&serde_json::to_string(sketch).unwrap()).unwrap()
+    }
+
+    fn fill(sketch: &mut impl HeavyDistinctHitterSketch<Label = String, Item = u64>) {
+        for label in 0..20_u64 {
+            for i in 0..label * 10 {
+                sketch.insert(label.to_string(), &i);
+            }
+        }
+    }
+
+    fn sorted_top(
+        sketch: &impl HeavyDistinctHitterSketch<Label = String, Item = u64>,
+    ) -> Vec<(String, u64)> {
+        let mut top = sketch
+            .top(usize::MAX)
+            .into_iter()
+            .map(|(label, cardinality)| (label.clone(), cardinality))
+            .collect::<Vec<_>>();
+        top.sort();
+        top
+    }
+
+    #[test]
+    fn it_round_trips() {
+        let mut label_set = LabelSetCountHLL::new(&config());
+        fill(&mut label_set);
+        let decoded: LabelSetCountHLL<String, u64> = round_trip(&label_set);
+        assert_eq!(decoded.sketch.registers, label_set.sketch.registers);
+        assert_eq!(sorted_top(&decoded), sorted_top(&label_set));
+
+        let mut label_array = LabelArrayCountHLL::new(&config());
+        fill(&mut label_array);
+        let decoded: LabelArrayCountHLL<String, u64> = round_trip(&label_array);
+        assert_eq!(decoded.sketch.config, label_array.sketch.config);
+        assert_eq!(sorted_top(&decoded), sorted_top(&label_array));
+
+        let mut space_saving = SpaceSavingCountHLL::new(&config());
+        fill(&mut space_saving);
+        let decoded: SpaceSavingCountHLL<String, u64> = round_trip(&space_saving);
+        assert_eq!(sorted_top(&decoded), sorted_top(&space_saving));
+    }
+
+    #[test]
+    fn it_rejects_invalid_states() {
+        let json = serde_json::to_value(LabelArrayCountHLL::<u32, u64>::new(&config())).unwrap();
+
+        let mut register = json.clone();
+        register["sketch"]["registers"][0] = 66.into();
+        assert!(serde_json::from_value::<LabelArrayCountHLL<u32, u64>>(register).is_err());
+
+        let mut labels = json.clone();
+        labels["labels"].as_array_mut().unwrap().pop();
+        assert!(serde_json::from_value::<LabelArrayCountHLL<u32, u64>>(labels).is_err());
+
+        let mut depth = json;
+        depth["sketch"]["config"]["depth"] = 0.into();
+        assert!(serde_json::from_value::<LabelArrayCountHLL<u32, u64>>(depth).is_err());
+    }
+
+    #[test]
+    fn it_round_trips_estimator_settings() {
+        let configured = config()
+            .with_cardinality_estimation_method(CardinalityEstimationMethod::Original)
+            .with_newton_convergence(1e-6, 10)
+            .unwrap();
+        let round_tripped = round_trip(&configured);
+        assert_eq!(round_tripped, configured);
+        assert_eq!(round_tripped.newton_tolerance(), 1e-6);
+        assert_eq!(round_tripped.newton_max_iterations(), 10);
+
+        // Configs serialized without the convergence settings get the
+        // defaults.
+        let mut json = serde_json::to_value(config()).unwrap();
+        let fields = json.as_object_mut().unwrap();
+        fields.remove("newton_tolerance");
+        fields.remove("newton_max_iterations");
+        let defaulted = serde_json::from_value::<Config>(json.clone()).unwrap();
+        assert_eq!(defaulted.newton_tolerance(), DEFAULT_NEWTON_TOLERANCE);
+        assert_eq!(
+            defaulted.newton_max_iterations(),
+            DEFAULT_NEWTON_MAX_ITERATIONS
+        );
+
+        json["newton_max_iterations"] = 0.into();
+        assert!(serde_json::from_value::<Config>(json).is_err());
+    }
+}
@@ -0,0 +1,227 @@
+//! A HyperLogLog that threads insert into concurrently, e.g., the counter of
+//! a label fed by several ingestion threads, without a mutex.
+//!
+//! Registers only ever grow to the max of the values inserted into them, so
+//! they're atomic bytes updated with relaxed `fetch_max`: inserts commute,
+//! and any interleaving ends with the registers of a sequential sketch.
+//! Estimates go through all the registers, which may be mid-update, so they
+//! count some of the concurrent inserts.
+
+use alloc::vec::Vec;
+use core::{
+    hash::Hash,
+    marker::PhantomData,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use ahash::RandomState;
+use sketch_traits::{Estimate, MemorySize, New, SeedableBuildHasher};
+
+use crate::{Config, HyperLogLog, MergeError};
+
+/// A HyperLogLog of atomic registers of a byte each, whatever the register
+/// width of the config, inserted into by shared reference. Convert it to and
+/// from [`HyperLogLog`] to merge, encode or serialize it.
+#[derive(Debug)]
+pub struct AtomicHyperLogLog<I, H = RandomState> {
+    config: Config<H>,
+    registers: Vec<AtomicU8>,
+    // See `HyperLogLog`.
+    item_type: PhantomData<fn(&I)>,
+}
+
+impl<I, H> New for AtomicHyperLogLog<I, H>
+where
+    H: SeedableBuildHasher,
+{
+    type Config = Config<H>;
+
+    fn new(config: &Self::Config) -> Self {
+        Self {
+            config: config.clone(),
+            registers: (0..config.num_registers)
+                .map(|_| AtomicU8::new(0))
+                .collect(),
+            item_type: PhantomData,
+        }
+    }
+}
+
+impl<I, H> AtomicHyperLogLog<I, H>
+where
+    H: SeedableBuildHasher,
+{
+    pub fn config(&self) -> &Config<H> {
+        &self.config
+    }
+
+    /// Inserts an item, as [`CardinalitySketch::insert`] would into a
+    /// [`HyperLogLog`] of the same config.
+    #[inline]
+    pub fn insert(&self, item: &I)
+    where
+        I: Hash,
+    {
+        self.insert_key(item);
+    }
+
+    /// Inserts an item by its hash, as
+    /// [`PrehashedCardinalitySketch::insert_hashed`](sketch_traits::PrehashedCardinalitySketch::insert_hashed)
+    /// would into a [`HyperLogLog`] of the same config.
+    #[inline]
+    pub fn insert_hashed(&self, item_hash: u64) {
+        self.insert_key(&item_hash);
+    }
+
+    #[inline]
+    fn insert_key(&self, key: &impl Hash) {
+        let r = HyperLogLog::<I, H>::register_index(&self.config, key);
+        let z = HyperLogLog::<I, H>::item_hash(&self.config, key);
+        // `r` is masked by `num_registers - 1`, so it is always in bounds.
+        self.registers[r].fetch_max(z, Ordering::Relaxed);
+    }
+
+    /// Takes the register-wise max with `other`, which must have the same
+    /// config, e.g., to fold in a sketch built elsewhere.
+    pub fn merge(&self, other: &HyperLogLog<I, H>) -> Result<(), MergeError> {
+        if self.config != other.config {
+            return Err(MergeError::ConfigMismatch);
+        }
+        for (register, &value) in self.registers.iter().zip(other.registers().iter()) {
+            if value != 0 {
+                register.fetch_max(value, Ordering::Relaxed);
+            }
+        }
+        Ok(())
+    }
+
+    /// Zeroes the registers. Concurrent inserts may survive it.
+    pub fn clear(&self) {
+        for register in &self.registers {
+            register.store(0, Ordering::Relaxed);
+        }
+    }
+
+    pub fn cardinality(&self) -> u64 {
+        self.to_sketch().estimate().cardinality
+    }
+
+    /// The cardinality before it's rounded, as for [`HyperLogLog`].
+    pub fn cardinality_f64(&self) -> f64 {
+        self.to_sketch().unrounded_estimate()
+    }
+
+    /// The cardinality with the error of the estimator it's from, as for
+    /// [`HyperLogLog`].
+    pub fn cardinality_with_error(&self) -> Option<Estimate> {
+        Some(self.to_sketch().estimate())
+    }
+
+    /// A snapshot of the registers, e.g., to export them.
+    pub fn registers(&self) -> Vec<u8> {
+        self.registers
+            .iter()
+            .map(|register| register.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    /// A [`HyperLogLog`] of a snapshot of the registers, e.g., to merge it
+    /// with others. Like after a merge, it estimates like the original
+    /// estimator if configured with [`Estimator::Martingale`](crate::Estimator::Martingale).
+    pub fn to_sketch(&self) -> HyperLogLog<I, H> {
+        let mut sketch = HyperLogLog::new(&self.config);
+        sketch.merge_registers(&self.registers());
+        sketch
+    }
+}
+
+impl<I, H> From<&HyperLogLog<I, H>> for AtomicHyperLogLog<I, H>
+where
+    H: SeedableBuildHasher,
+{
+    fn from(sketch: &HyperLogLog<I, H>) -> Self {
+        Self {
+            config: sketch.config.clone(),
+            registers: sketch
+                .registers()
+                .iter()
+                .map(|&r| AtomicU8::new(r))
+                .collect(),
+            item_type: PhantomData,
+        }
+    }
+}
+
+impl<I, H> MemorySize for AtomicHyperLogLog<I, H> {
+    fn heap_size(&self) -> usize {
+        self.config.heap_size() + self.registers.capacity() * core::mem::size_of::<AtomicU8>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use sketch_traits::{CardinalitySketch, PrehashedCardinalitySketch};
+
+    use super::*;
+
+    const NUM_THREADS: u64 = 4;
+    const ITEMS_PER_THREAD: u64 = 10_000;
+
+    fn config() -> Config {
+        Config::new(1024, Some([0, 1, 2, 3, 4, 5, 6, 7])).unwrap()
+    }
+
+    #[test]
+    fn concurrent_inserts_match_sequential_ones() {
+        let atomic = AtomicHyperLogLog::<u64>::new(&config());
+        thread::scope(|scope| {
+            for t in 0..NUM_THREADS {
+                let atomic = &atomic;
+                scope.spawn(move || {
+                    for i in t * ITEMS_PER_THREAD..(t + 1) * ITEMS_PER_THREAD {
+                        atomic.insert(&i);
+                        atomic.insert_hashed(i);
+                    }
+                });
+            }
+        });
+
+        let mut sequential = HyperLogLog::new(&config());
+        for i in 0..NUM_THREADS * ITEMS_PER_THREAD {
+            sequential.insert(&i);
+            sequential.insert_hashed(i);
+        }
+        assert_eq!(atomic.registers()[..], sequential.registers()[..]);
+        assert_eq!(atomic.cardinality(), sequential.cardinality());
+        assert_eq!(
+            atomic.cardinality_with_error(),
+            sequential.cardinality_with_error()
+        );
+    }
+
+    #[test]
+    fn it_converts_from_and_to_plain_sketches() {
+        let mut sketch = HyperLogLog::new(&config());
+        (0..1000_u64).for_each(|i| sketch.insert(&i));
+        let atomic = AtomicHyperLogLog::from(&sketch);
+        assert_eq!(atomic.to_sketch().registers(), sketch.registers());
+
+        let mut other = HyperLogLog::new(&config());
+        (1000..2000_u64).for_each(|i| other.insert(&i));
+        assert!(atomic.merge(&other).is_ok());
+        assert!(sketch.merge(&other).is_ok());
+        assert_eq!(atomic.to_sketch().registers(), sketch.registers());
+        assert_eq!(atomic.cardinality(), sketch.cardinality());
+
+        let mismatched = HyperLogLog::new(&Config::new(512, None).unwrap());
+        assert!(matches!(
+            atomic.merge(&mismatched),
+            Err(MergeError::ConfigMismatch)
+        ));
+
+        atomic.clear();
+        assert_eq!(atomic.cardinality(), 0);
+    }
+}
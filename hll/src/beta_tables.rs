@@ -0,0 +1,69 @@
+//! Generated by `cargo run --release --bin benchmarks -- beta-tables hll/src/beta_tables.rs`,
+//! see `benchmarks/src/beta.rs`. Do not edit.
+//!
+//! Row `p - 4` holds the coefficients of `z` and of `ln(z + 1)^k`, for `k` in `1..8`,
+//! in the beta of sketches of `m = 2^p` registers, `z` of which are zero.
+
+#[rustfmt::skip]
+pub(crate) const BETAS: [[f64; 8]; 15] = [
+    [
+        3.196939905e2, -3.519563630e2, -2.210565124e1, -2.941398177e2,
+        2.021119119e2, -1.071983569e2, 2.580070427e1, -2.769490260e0,
+    ],
+    [
+        3.928097624e2, -4.023199592e2, -1.606934373e2, -1.238619871e2,
+        3.260557981e1, -2.586499743e1, 5.024217593e0, -6.969144817e-1,
+    ],
+    [
+        -1.982900280e2, 2.021082104e2, 7.999499195e1, 6.404646613e1,
+        -1.716348703e1, 1.303620754e1, -2.466139917e0, 3.399957305e-1,
+    ],
+    [
+        -2.097409991e1, 2.137761152e1, 5.867562236e0, 1.067146531e1,
+        -4.574801534e0, 2.360655148e0, -4.363921311e-1, 4.811764901e-2,
+    ],
+    [
+        -2.672044157e0, 2.132965515e0, 4.810789389e-1, 2.014621031e0,
+        -1.097563798e0, 4.678414920e-1, -8.223343706e-2, 7.528886946e-3,
+    ],
+    [
+        -1.195602694e0, 6.854233545e-1, 6.912422056e-2, 1.214903329e0,
+        -7.179553956e-1, 2.739299344e-1, -4.545978270e-2, 3.652796852e-3,
+    ],
+    [
+        -4.734482684e-1, -3.629005503e-1, 7.876690583e-1, -1.564055799e-1,
+        7.305464712e-3, 2.301009163e-2, -4.874977926e-3, 4.808190071e-4,
+    ],
+    [
+        -3.970570200e-1, -3.838300809e-1, 5.043107170e-1, -3.235717756e-2,
+        1.346135591e-3, 9.846345471e-3, -1.581514261e-3, 1.873002527e-4,
+    ],
+    [
+        -2.925775932e-1, -6.314477352e-1, 1.997120075e0, -1.834490238e0,
+        8.864238582e-1, -2.125436955e-1, 2.585063730e-2, -1.220884837e-3,
+    ],
+    [
+        -4.243321384e-1, 9.112018321e-1, -2.557689399e0, 2.925277854e0,
+        -1.281896619e0, 2.941827399e-1, -3.270529810e-2, 1.602649710e-3,
+    ],
+    [
+        -3.986382180e-1, 2.841594397e0, -5.537022077e0, 4.602993793e0,
+        -1.742992915e0, 3.557932228e-1, -3.616956452e-2, 1.640625455e-3,
+    ],
+    [
+        -3.726124834e-1, 6.711460651e-1, -8.810547219e-1, 8.164046702e-1,
+        -3.059325264e-1, 8.130961149e-2, -1.063547102e-2, 7.141025464e-4,
+    ],
+    [
+        -3.753076677e-1, 8.873506903e0, -1.497090679e1, 9.732598991e0,
+        -3.098964130e0, 5.464021387e-1, -5.024034672e-2, 2.107390149e-3,
+    ],
+    [
+        -3.611549381e-1, 2.141170475e0, -4.178662925e0, 3.720912007e0,
+        -1.477453731e0, 3.207620449e-1, -3.494580125e-2, 1.727603544e-3,
+    ],
+    [
+        -3.652138450e-1, 8.793490910e0, -2.205589077e1, 1.646897786e1,
+        -5.713092649e0, 1.044201682e0, -9.670855492e-2, 3.858693051e-3,
+    ],
+];
@@ -0,0 +1,345 @@
+//! Generated by `cargo run --release --bin benchmarks -- bias-tables hll/src/bias_tables.rs`,
+//! see `benchmarks/src/bias.rs`. Do not edit.
+//!
+//! Row `p - 4` holds the mean raw estimates and biases of sketches of `m = 2^p`
+//! registers, relative to `m`, at cardinalities `5m * i / 64`, rounded, for `i` in `0..65`.
+
+pub(crate) const NUM_POINTS: usize = 65;
+
+#[rustfmt::skip]
+pub(crate) const RAW_ESTIMATES: [[f64; NUM_POINTS]; 15] = [
+    [
+        0.673000, 0.702349, 0.763957, 0.796211, 0.829479, 0.863729, 0.935164, 0.972290,
+        1.010520, 1.049665, 1.130934, 1.172991, 1.215980, 1.259911, 1.350558, 1.397119,
+        1.444536, 1.492924, 1.591901, 1.642531, 1.693784, 1.745797, 1.851969, 1.905927,
+        1.960610, 2.015792, 2.127697, 2.184434, 2.241408, 2.298912, 2.415265, 2.473859,
+        2.532729, 2.592140, 2.711066, 2.771201, 2.831620, 2.892308, 3.013540, 3.074620,
+        3.135716, 3.196988, 3.319409, 3.381054, 3.442704, 3.504440, 3.628223, 3.689949,
+        3.752115, 3.814003, 3.938625, 4.000834, 4.063107, 4.125228, 4.249381, 4.311883,
+        4.373816, 4.435971, 4.561045, 4.623474, 4.686036, 4.748319, 4.873110, 4.935499,
+        4.997689,
+    ],
+    [
+        0.697000, 0.742240, 0.773558, 0.822298, 0.855961, 0.908232, 0.944281, 1.000010,
+        1.038344, 1.097624, 1.138312, 1.200991, 1.243947, 1.309770, 1.354827, 1.423848,
+        1.470966, 1.543088, 1.592029, 1.666897, 1.717630, 1.794917, 1.847203, 1.927036,
+        1.980971, 2.062664, 2.117802, 2.201537, 2.258005, 2.343336, 2.400724, 2.487273,
+        2.545657, 2.633571, 2.692456, 2.781583, 2.841023, 2.930802, 2.991167, 3.081702,
+        3.142103, 3.233445, 3.294474, 3.386161, 3.447523, 3.540124, 3.601366, 3.693640,
+        3.755491, 3.848169, 3.909999, 4.003101, 4.065067, 4.158158, 4.220424, 4.313575,
+        4.375714, 4.469174, 4.531411, 4.624829, 4.686790, 4.780144, 4.842435, 4.935750,
+        4.998207,
+    ],
+    [
+        0.709000, 0.746914, 0.786258, 0.827041, 0.869258, 0.912904, 0.957999, 1.004497,
+        1.052419, 1.101792, 1.152552, 1.204611, 1.258065, 1.312806, 1.368712, 1.425965,
+        1.484482, 1.544091, 1.604932, 1.666764, 1.729709, 1.793736, 1.858783, 1.924622,
+        1.991304, 2.058716, 2.127106, 2.196265, 2.266293, 2.336784, 2.408067, 2.479799,
+        2.552117, 2.624855, 2.698033, 2.771726, 2.846022, 2.920455, 2.995314, 3.070511,
+        3.145998, 3.221463, 3.297384, 3.373359, 3.449722, 3.526175, 3.602888, 3.679742,
+        3.756627, 3.833595, 3.911275, 3.988428, 4.066149, 4.143659, 4.221476, 4.298781,
+        4.376529, 4.454503, 4.532447, 4.610583, 4.688332, 4.766111, 4.844314, 4.922171,
+        5.000540,
+    ],
+    [
+        0.715000, 0.753072, 0.792565, 0.833453, 0.875803, 0.919551, 0.964713, 1.011353,
+        1.059332, 1.108730, 1.159486, 1.211499, 1.264883, 1.319591, 1.375591, 1.432776,
+        1.491164, 1.550751, 1.611437, 1.673238, 1.736163, 1.800205, 1.864916, 1.930605,
+        1.997176, 2.064672, 2.132921, 2.201876, 2.271496, 2.341673, 2.412667, 2.484059,
+        2.556148, 2.628776, 2.702065, 2.775543, 2.849757, 2.923653, 2.998234, 3.073198,
+        3.148316, 3.223936, 3.299971, 3.376123, 3.452420, 3.528987, 3.605841, 3.682502,
+        3.759409, 3.836634, 3.913596, 3.991026, 4.068247, 4.145922, 4.223410, 4.300711,
+        4.378265, 4.456451, 4.534620, 4.612410, 4.689915, 4.767819, 4.845624, 4.923470,
+        5.001766,
+    ],
+    [
+        0.718000, 0.756169, 0.795766, 0.836741, 0.879146, 0.922912, 0.968122, 1.014710,
+        1.062801, 1.112224, 1.162937, 1.214987, 1.268472, 1.323148, 1.379122, 1.436282,
+        1.494678, 1.554177, 1.614881, 1.676643, 1.739310, 1.803087, 1.867760, 1.933367,
+        1.999732, 2.067030, 2.135196, 2.203950, 2.273399, 2.343519, 2.414267, 2.485514,
+        2.557193, 2.629464, 2.702292, 2.775585, 2.849605, 2.923617, 2.998252, 3.073061,
+        3.148264, 3.223499, 3.299300, 3.375056, 3.451431, 3.527887, 3.604493, 3.681008,
+        3.757883, 3.834592, 3.911641, 3.988660, 4.065800, 4.142925, 4.220251, 4.298046,
+        4.375827, 4.453770, 4.531635, 4.609442, 4.687613, 4.765407, 4.843394, 4.921200,
+        4.998962,
+    ],
+    [
+        0.720000, 0.758210, 0.797853, 0.838905, 0.881352, 0.925250, 0.970496, 1.017165,
+        1.065260, 1.114629, 1.165420, 1.217504, 1.270888, 1.325553, 1.381461, 1.438582,
+        1.496899, 1.556268, 1.616836, 1.678456, 1.741181, 1.805028, 1.869705, 1.935384,
+        2.001802, 2.069108, 2.137143, 2.206004, 2.275550, 2.345806, 2.416665, 2.488060,
+        2.560016, 2.632374, 2.705145, 2.778411, 2.852297, 2.926255, 3.000989, 3.075506,
+        3.150427, 3.225929, 3.301588, 3.377559, 3.453492, 3.529990, 3.606563, 3.683391,
+        3.760401, 3.837588, 3.914515, 3.991855, 4.069165, 4.146666, 4.224460, 4.302235,
+        4.379802, 4.457562, 4.535387, 4.613305, 4.691240, 4.769363, 4.847403, 4.925412,
+        5.003542,
+    ],
+    [
+        0.720541, 0.758786, 0.798411, 0.839478, 0.881954, 0.925833, 0.971111, 1.017727,
+        1.065835, 1.115319, 1.166072, 1.218163, 1.271569, 1.326159, 1.382137, 1.439322,
+        1.497546, 1.557255, 1.617810, 1.679487, 1.742140, 1.805806, 1.870455, 1.935942,
+        2.002475, 2.069696, 2.137685, 2.206494, 2.275969, 2.346017, 2.417135, 2.488349,
+        2.560264, 2.632603, 2.705241, 2.778559, 2.852452, 2.926588, 3.001191, 3.075968,
+        3.151244, 3.226642, 3.302432, 3.378386, 3.454468, 3.530810, 3.607720, 3.684563,
+        3.761605, 3.838844, 3.915753, 3.992964, 4.070364, 4.147838, 4.225546, 4.303215,
+        4.380683, 4.458522, 4.535745, 4.613526, 4.691731, 4.769686, 4.847377, 4.925657,
+        5.003321,
+    ],
+    [
+        0.720920, 0.759175, 0.798813, 0.839897, 0.882409, 0.926275, 0.971592, 1.018336,
+        1.066475, 1.115914, 1.166738, 1.218750, 1.272193, 1.326867, 1.382740, 1.439892,
+        1.498287, 1.557844, 1.618375, 1.680064, 1.742726, 1.806488, 1.871169, 1.936542,
+        2.002887, 2.070169, 2.138245, 2.207150, 2.276631, 2.346736, 2.417399, 2.488628,
+        2.560417, 2.633003, 2.705882, 2.779012, 2.852677, 2.926982, 3.001333, 3.075978,
+        3.151192, 3.226714, 3.302049, 3.378095, 3.454185, 3.530389, 3.606731, 3.683650,
+        3.760158, 3.836841, 3.913954, 3.990774, 4.068083, 4.145673, 4.222812, 4.300497,
+        4.377997, 4.455465, 4.533416, 4.611250, 4.689155, 4.766902, 4.844778, 4.922816,
+        5.000771,
+    ],
+    [
+        0.721110, 0.759375, 0.799046, 0.840097, 0.882576, 0.926477, 0.971785, 1.018502,
+        1.066570, 1.116003, 1.166787, 1.218889, 1.272264, 1.326926, 1.382994, 1.440174,
+        1.498535, 1.558092, 1.618650, 1.680309, 1.743035, 1.806890, 1.871586, 1.937120,
+        2.003488, 2.070647, 2.138625, 2.207214, 2.276642, 2.346610, 2.417436, 2.488703,
+        2.560693, 2.632909, 2.705634, 2.778656, 2.852216, 2.926375, 3.000875, 3.075778,
+        3.150945, 3.226435, 3.302063, 3.377998, 3.454219, 3.530653, 3.607451, 3.684265,
+        3.761185, 3.838267, 3.915735, 3.993034, 4.070264, 4.147814, 4.225164, 4.302815,
+        4.380547, 4.458554, 4.536283, 4.614041, 4.692015, 4.769881, 4.847638, 4.926033,
+        5.003751,
+    ],
+    [
+        0.721205, 0.759469, 0.799148, 0.840200, 0.882703, 0.926578, 0.971882, 1.018536,
+        1.066635, 1.116035, 1.166837, 1.218943, 1.272342, 1.327025, 1.382973, 1.439994,
+        1.498414, 1.557765, 1.618358, 1.680138, 1.742787, 1.806540, 1.871139, 1.936554,
+        2.002908, 2.070206, 2.138340, 2.207181, 2.276745, 2.346982, 2.417712, 2.489156,
+        2.560947, 2.633282, 2.706216, 2.779708, 2.853669, 2.927829, 3.002337, 3.076807,
+        3.151927, 3.227545, 3.303428, 3.379262, 3.455539, 3.531949, 3.608350, 3.685138,
+        3.761815, 3.838825, 3.915736, 3.992761, 4.069747, 4.147456, 4.224795, 4.302692,
+        4.380309, 4.458373, 4.536181, 4.613740, 4.691803, 4.769544, 4.847491, 4.925245,
+        5.003200,
+    ],
+    [
+        0.721253, 0.759530, 0.799215, 0.840304, 0.882786, 0.926660, 0.971962, 1.018658,
+        1.066744, 1.116193, 1.166927, 1.219081, 1.272507, 1.327147, 1.383077, 1.440178,
+        1.498424, 1.557890, 1.618462, 1.680191, 1.742725, 1.806435, 1.871097, 1.936473,
+        2.002970, 2.070245, 2.138413, 2.207139, 2.276626, 2.346549, 2.417330, 2.488532,
+        2.560557, 2.632901, 2.705917, 2.779381, 2.853071, 2.927239, 3.001860, 3.076750,
+        3.151796, 3.227239, 3.303001, 3.378956, 3.455156, 3.531457, 3.607775, 3.684240,
+        3.761043, 3.837645, 3.914627, 3.991870, 4.068656, 4.146164, 4.223412, 4.300796,
+        4.378646, 4.456347, 4.534093, 4.611867, 4.689435, 4.767229, 4.845231, 4.923175,
+        5.001111,
+    ],
+    [
+        0.721276, 0.759550, 0.799227, 0.840293, 0.882801, 0.926715, 0.972014, 1.018757,
+        1.066813, 1.116231, 1.167043, 1.219172, 1.272594, 1.327322, 1.383224, 1.440387,
+        1.498691, 1.558044, 1.618699, 1.680390, 1.743170, 1.806866, 1.871465, 1.936963,
+        2.003441, 2.070531, 2.138418, 2.207210, 2.276503, 2.346523, 2.417072, 2.488457,
+        2.560416, 2.632742, 2.705481, 2.778696, 2.852180, 2.926345, 3.000801, 3.075607,
+        3.150791, 3.226134, 3.301809, 3.377656, 3.453801, 3.530237, 3.606830, 3.683273,
+        3.760109, 3.837038, 3.914274, 3.991621, 4.068853, 4.146295, 4.223817, 4.301360,
+        4.379011, 4.456612, 4.533972, 4.611750, 4.689775, 4.767746, 4.845817, 4.923491,
+        5.001229,
+    ],
+    [
+        0.721288, 0.759556, 0.799229, 0.840325, 0.882834, 0.926740, 0.972012, 1.018661,
+        1.066687, 1.116104, 1.166858, 1.218973, 1.272329, 1.326999, 1.383044, 1.440262,
+        1.498496, 1.557947, 1.618557, 1.680249, 1.742925, 1.806660, 1.871348, 1.936895,
+        2.003281, 2.070287, 2.138266, 2.206751, 2.276075, 2.346147, 2.416747, 2.488071,
+        2.560117, 2.632460, 2.705326, 2.778600, 2.852444, 2.926570, 3.000995, 3.075806,
+        3.150994, 3.226297, 3.301981, 3.377758, 3.453976, 3.530710, 3.607320, 3.684032,
+        3.760882, 3.837631, 3.914584, 3.991678, 4.068867, 4.146313, 4.223540, 4.300966,
+        4.378723, 4.456381, 4.533736, 4.611736, 4.689369, 4.767109, 4.845005, 4.923046,
+        5.001072,
+    ],
+    [
+        0.721294, 0.759569, 0.799250, 0.840342, 0.882858, 0.926789, 0.972127, 1.018767,
+        1.066786, 1.116227, 1.167018, 1.219113, 1.272582, 1.327220, 1.383076, 1.440161,
+        1.498485, 1.557948, 1.618484, 1.680247, 1.742917, 1.806574, 1.871289, 1.936710,
+        2.003295, 2.070507, 2.138456, 2.207236, 2.276594, 2.346641, 2.417323, 2.488700,
+        2.560589, 2.632929, 2.706002, 2.779350, 2.853233, 2.927341, 3.001713, 3.076804,
+        3.151903, 3.227046, 3.302601, 3.378164, 3.454608, 3.531090, 3.607600, 3.684204,
+        3.761039, 3.838200, 3.915446, 3.992615, 4.069835, 4.146845, 4.224389, 4.301843,
+        4.379401, 4.457033, 4.535008, 4.612965, 4.690972, 4.768612, 4.846481, 4.924271,
+        5.002065,
+    ],
+    [
+        0.721297, 0.759559, 0.799224, 0.840310, 0.882774, 0.926678, 0.971963, 1.018675,
+        1.066708, 1.116178, 1.167059, 1.219225, 1.272692, 1.327328, 1.383303, 1.440517,
+        1.498741, 1.558256, 1.618829, 1.680474, 1.743219, 1.806925, 1.871404, 1.936885,
+        2.003193, 2.070393, 2.138453, 2.207301, 2.276723, 2.346764, 2.417571, 2.489046,
+        2.561080, 2.633431, 2.706419, 2.779838, 2.853625, 2.927736, 3.002393, 3.077123,
+        3.152233, 3.227519, 3.303169, 3.379262, 3.455269, 3.531495, 3.607925, 3.684436,
+        3.761169, 3.838242, 3.915299, 3.992524, 4.069848, 4.147252, 4.224645, 4.302180,
+        4.379849, 4.457288, 4.534739, 4.612802, 4.690679, 4.768380, 4.846285, 4.923725,
+        5.001334,
+    ],
+];
+
+#[rustfmt::skip]
+pub(crate) const BIASES: [[f64; NUM_POINTS]; 15] = [
+    [
+        0.673000, 0.639849, 0.576457, 0.546211, 0.516979, 0.488729, 0.435164, 0.409790,
+        0.385520, 0.362165, 0.318434, 0.297991, 0.278480, 0.259911, 0.225558, 0.209619,
+        0.194536, 0.180424, 0.154401, 0.142531, 0.131284, 0.120797, 0.101969, 0.093427,
+        0.085610, 0.078292, 0.065197, 0.059434, 0.053908, 0.048912, 0.040265, 0.036359,
+        0.032729, 0.029640, 0.023566, 0.021201, 0.019120, 0.017308, 0.013540, 0.012120,
+        0.010716, 0.009488, 0.006909, 0.006054, 0.005204, 0.004440, 0.003223, 0.002449,
+        0.002115, 0.001503, 0.001125, 0.000834, 0.000607, 0.000228, -0.000619, -0.000617,
+        -0.001184, -0.001529, -0.001455, -0.001526, -0.001464, -0.001681, -0.001890, -0.002001,
+        -0.002311,
+    ],
+    [
+        0.697000, 0.648490, 0.617308, 0.572298, 0.543461, 0.501982, 0.475531, 0.437510,
+        0.413344, 0.378874, 0.357062, 0.325991, 0.306447, 0.278520, 0.261077, 0.236348,
+        0.220966, 0.199338, 0.185779, 0.166897, 0.155130, 0.138667, 0.128453, 0.114536,
+        0.105971, 0.093914, 0.086552, 0.076537, 0.070505, 0.062086, 0.056974, 0.049773,
+        0.045657, 0.039821, 0.036206, 0.031583, 0.028523, 0.024552, 0.022417, 0.019202,
+        0.017103, 0.014695, 0.013224, 0.011161, 0.010023, 0.008874, 0.007616, 0.006140,
+        0.005491, 0.004419, 0.003749, 0.003101, 0.002567, 0.001908, 0.001674, 0.001075,
+        0.000714, 0.000424, 0.000161, -0.000171, -0.000710, -0.001106, -0.001315, -0.001750,
+        -0.001793,
+    ],
+    [
+        0.709000, 0.668789, 0.630008, 0.592666, 0.556758, 0.522279, 0.489249, 0.457622,
+        0.427419, 0.398667, 0.371302, 0.345236, 0.320565, 0.297181, 0.274962, 0.254090,
+        0.234482, 0.215966, 0.198682, 0.182389, 0.167209, 0.153111, 0.140033, 0.127747,
+        0.116304, 0.105591, 0.095856, 0.086890, 0.078793, 0.071159, 0.064317, 0.057924,
+        0.052117, 0.046730, 0.041783, 0.037351, 0.033522, 0.029830, 0.026564, 0.023636,
+        0.020998, 0.018338, 0.016134, 0.013984, 0.012222, 0.010550, 0.009138, 0.007867,
+        0.006627, 0.005470, 0.005025, 0.004053, 0.003649, 0.003034, 0.002726, 0.001906,
+        0.001529, 0.001378, 0.001197, 0.001208, 0.000832, 0.000486, 0.000564, 0.000296,
+        0.000540,
+    ],
+    [
+        0.715000, 0.674947, 0.636315, 0.599078, 0.563303, 0.528926, 0.495963, 0.464478,
+        0.434332, 0.405605, 0.378236, 0.352124, 0.327383, 0.303966, 0.281841, 0.260901,
+        0.241164, 0.222626, 0.205187, 0.188863, 0.173663, 0.159580, 0.146166, 0.133730,
+        0.122176, 0.111547, 0.101671, 0.092501, 0.083996, 0.076048, 0.068917, 0.062184,
+        0.056148, 0.050651, 0.045815, 0.041168, 0.037257, 0.033028, 0.029484, 0.026323,
+        0.023316, 0.020811, 0.018721, 0.016748, 0.014920, 0.013362, 0.012091, 0.010627,
+        0.009409, 0.008509, 0.007346, 0.006651, 0.005747, 0.005297, 0.004660, 0.003836,
+        0.003265, 0.003326, 0.003370, 0.003035, 0.002415, 0.002194, 0.001874, 0.001595,
+        0.001766,
+    ],
+    [
+        0.718000, 0.678044, 0.639516, 0.602366, 0.566646, 0.532287, 0.499372, 0.467835,
+        0.437801, 0.409099, 0.381687, 0.355612, 0.330972, 0.307523, 0.285372, 0.264407,
+        0.244678, 0.226052, 0.208631, 0.192268, 0.176810, 0.162462, 0.149010, 0.136492,
+        0.124732, 0.113905, 0.103946, 0.094575, 0.085899, 0.077894, 0.070517, 0.063639,
+        0.057193, 0.051339, 0.046042, 0.041210, 0.037105, 0.032992, 0.029502, 0.026186,
+        0.023264, 0.020374, 0.018050, 0.015681, 0.013931, 0.012262, 0.010743, 0.009133,
+        0.007883, 0.006467, 0.005391, 0.004285, 0.003300, 0.002300, 0.001501, 0.001171,
+        0.000827, 0.000645, 0.000385, 0.000067, 0.000113, -0.000218, -0.000356, -0.000675,
+        -0.001038,
+    ],
+    [
+        0.720000, 0.680085, 0.641603, 0.604530, 0.568852, 0.534625, 0.501746, 0.470290,
+        0.440260, 0.411504, 0.384170, 0.358129, 0.333388, 0.309928, 0.287711, 0.266707,
+        0.246899, 0.228143, 0.210586, 0.194081, 0.178681, 0.164403, 0.150955, 0.138509,
+        0.126802, 0.115983, 0.105893, 0.096629, 0.088050, 0.080181, 0.072915, 0.066185,
+        0.060016, 0.054249, 0.048895, 0.044036, 0.039797, 0.035630, 0.032239, 0.028631,
+        0.025427, 0.022804, 0.020338, 0.018184, 0.015992, 0.014365, 0.012813, 0.011516,
+        0.010401, 0.009463, 0.008265, 0.007480, 0.006665, 0.006041, 0.005710, 0.005360,
+        0.004802, 0.004437, 0.004137, 0.003930, 0.003740, 0.003738, 0.003653, 0.003537,
+        0.003542,
+    ],
+    [
+        0.720541, 0.680661, 0.642161, 0.605103, 0.569454, 0.535208, 0.502361, 0.470852,
+        0.440835, 0.412194, 0.384822, 0.358788, 0.334069, 0.310534, 0.288387, 0.267447,
+        0.247546, 0.229130, 0.211560, 0.195112, 0.179640, 0.165181, 0.151705, 0.139067,
+        0.127475, 0.116571, 0.106435, 0.097119, 0.088469, 0.080392, 0.073385, 0.066474,
+        0.060264, 0.054478, 0.048991, 0.044184, 0.039952, 0.035963, 0.032441, 0.029093,
+        0.026244, 0.023517, 0.021182, 0.019011, 0.016968, 0.015185, 0.013970, 0.012688,
+        0.011605, 0.010719, 0.009503, 0.008589, 0.007864, 0.007213, 0.006796, 0.006340,
+        0.005683, 0.005397, 0.004495, 0.004151, 0.004231, 0.004061, 0.003627, 0.003782,
+        0.003321,
+    ],
+    [
+        0.720920, 0.681050, 0.642563, 0.605522, 0.569909, 0.535650, 0.502842, 0.471461,
+        0.441475, 0.412789, 0.385488, 0.359375, 0.334693, 0.311242, 0.288990, 0.268017,
+        0.248287, 0.229719, 0.212125, 0.195689, 0.180226, 0.165863, 0.152419, 0.139667,
+        0.127887, 0.117044, 0.106995, 0.097775, 0.089131, 0.081111, 0.073649, 0.066753,
+        0.060417, 0.054878, 0.049632, 0.044637, 0.040177, 0.036357, 0.032583, 0.029103,
+        0.026192, 0.023589, 0.020799, 0.018720, 0.016685, 0.014764, 0.012981, 0.011775,
+        0.010158, 0.008716, 0.007704, 0.006399, 0.005583, 0.005048, 0.004062, 0.003622,
+        0.002997, 0.002340, 0.002166, 0.001875, 0.001655, 0.001277, 0.001028, 0.000941,
+        0.000771,
+    ],
+    [
+        0.721110, 0.681250, 0.642796, 0.605722, 0.570076, 0.535852, 0.503035, 0.471627,
+        0.441570, 0.412878, 0.385537, 0.359514, 0.334764, 0.311301, 0.289244, 0.268299,
+        0.248535, 0.229967, 0.212400, 0.195934, 0.180535, 0.166265, 0.152836, 0.140245,
+        0.128488, 0.117522, 0.107375, 0.097839, 0.089142, 0.080985, 0.073686, 0.066828,
+        0.060693, 0.054784, 0.049384, 0.044281, 0.039716, 0.035750, 0.032125, 0.028903,
+        0.025945, 0.023310, 0.020813, 0.018623, 0.016719, 0.015028, 0.013701, 0.012390,
+        0.011185, 0.010142, 0.009485, 0.008659, 0.007764, 0.007189, 0.006414, 0.005940,
+        0.005547, 0.005429, 0.005033, 0.004666, 0.004515, 0.004256, 0.003888, 0.004158,
+        0.003751,
+    ],
+    [
+        0.721205, 0.681344, 0.642898, 0.605825, 0.570203, 0.535953, 0.503132, 0.471661,
+        0.441635, 0.412910, 0.385587, 0.359568, 0.334842, 0.311400, 0.289223, 0.268119,
+        0.248414, 0.229640, 0.212108, 0.195763, 0.180287, 0.165915, 0.152389, 0.139679,
+        0.127908, 0.117081, 0.107090, 0.097806, 0.089245, 0.081357, 0.073962, 0.067281,
+        0.060947, 0.055157, 0.049966, 0.045333, 0.041169, 0.037204, 0.033587, 0.029932,
+        0.026927, 0.024420, 0.022178, 0.019887, 0.018039, 0.016324, 0.014600, 0.013263,
+        0.011815, 0.010700, 0.009486, 0.008386, 0.007247, 0.006831, 0.006045, 0.005817,
+        0.005309, 0.005248, 0.004931, 0.004365, 0.004303, 0.003919, 0.003741, 0.003370,
+        0.003200,
+    ],
+    [
+        0.721253, 0.681405, 0.642965, 0.605929, 0.570286, 0.536035, 0.503212, 0.471783,
+        0.441744, 0.413068, 0.385677, 0.359706, 0.335007, 0.311522, 0.289327, 0.268303,
+        0.248424, 0.229765, 0.212212, 0.195816, 0.180225, 0.165810, 0.152347, 0.139598,
+        0.127970, 0.117120, 0.107163, 0.097764, 0.089126, 0.080924, 0.073580, 0.066657,
+        0.060557, 0.054776, 0.049667, 0.045006, 0.040571, 0.036614, 0.033110, 0.029875,
+        0.026796, 0.024114, 0.021751, 0.019581, 0.017656, 0.015832, 0.014025, 0.012365,
+        0.011043, 0.009520, 0.008377, 0.007495, 0.006156, 0.005539, 0.004662, 0.003921,
+        0.003646, 0.003222, 0.002843, 0.002492, 0.001935, 0.001604, 0.001481, 0.001300,
+        0.001111,
+    ],
+    [
+        0.721276, 0.681425, 0.642977, 0.605918, 0.570301, 0.536090, 0.503264, 0.471882,
+        0.441813, 0.413106, 0.385793, 0.359797, 0.335094, 0.311697, 0.289474, 0.268512,
+        0.248691, 0.229919, 0.212449, 0.196015, 0.180670, 0.166241, 0.152715, 0.140088,
+        0.128441, 0.117406, 0.107168, 0.097835, 0.089003, 0.080898, 0.073322, 0.066582,
+        0.060416, 0.054617, 0.049231, 0.044321, 0.039680, 0.035720, 0.032051, 0.028732,
+        0.025791, 0.023009, 0.020559, 0.018281, 0.016301, 0.014612, 0.013080, 0.011398,
+        0.010109, 0.008913, 0.008024, 0.007246, 0.006353, 0.005670, 0.005067, 0.004485,
+        0.004011, 0.003487, 0.002722, 0.002375, 0.002275, 0.002121, 0.002067, 0.001616,
+        0.001229,
+    ],
+    [
+        0.721288, 0.681431, 0.642979, 0.605950, 0.570334, 0.536115, 0.503262, 0.471786,
+        0.441687, 0.412979, 0.385608, 0.359598, 0.334829, 0.311374, 0.289294, 0.268387,
+        0.248496, 0.229822, 0.212307, 0.195874, 0.180425, 0.166035, 0.152598, 0.140020,
+        0.128281, 0.117162, 0.107016, 0.097376, 0.088575, 0.080522, 0.072997, 0.066196,
+        0.060117, 0.054335, 0.049076, 0.044225, 0.039944, 0.035945, 0.032245, 0.028931,
+        0.025994, 0.023172, 0.020731, 0.018383, 0.016476, 0.015085, 0.013570, 0.012157,
+        0.010882, 0.009506, 0.008334, 0.007303, 0.006367, 0.005688, 0.004790, 0.004091,
+        0.003723, 0.003256, 0.002486, 0.002361, 0.001869, 0.001484, 0.001255, 0.001171,
+        0.001072,
+    ],
+    [
+        0.721294, 0.681444, 0.643000, 0.605967, 0.570358, 0.536164, 0.503377, 0.471892,
+        0.441786, 0.413102, 0.385768, 0.359738, 0.335082, 0.311595, 0.289326, 0.268286,
+        0.248485, 0.229823, 0.212234, 0.195872, 0.180417, 0.165949, 0.152539, 0.139835,
+        0.128295, 0.117382, 0.107206, 0.097861, 0.089094, 0.081016, 0.073573, 0.066825,
+        0.060589, 0.054804, 0.049752, 0.044975, 0.040733, 0.036716, 0.032963, 0.029929,
+        0.026903, 0.023921, 0.021351, 0.018789, 0.017108, 0.015465, 0.013850, 0.012329,
+        0.011039, 0.010075, 0.009196, 0.008240, 0.007335, 0.006220, 0.005639, 0.004968,
+        0.004401, 0.003908, 0.003758, 0.003590, 0.003472, 0.002987, 0.002731, 0.002396,
+        0.002065,
+    ],
+    [
+        0.721297, 0.681434, 0.642974, 0.605935, 0.570274, 0.536053, 0.503213, 0.471800,
+        0.441708, 0.413053, 0.385809, 0.359850, 0.335192, 0.311703, 0.289553, 0.268642,
+        0.248741, 0.230131, 0.212579, 0.196099, 0.180719, 0.166300, 0.152654, 0.140010,
+        0.128193, 0.117268, 0.107203, 0.097926, 0.089223, 0.081139, 0.073821, 0.067171,
+        0.061080, 0.055306, 0.050169, 0.045463, 0.041125, 0.037111, 0.033643, 0.030248,
+        0.027233, 0.024394, 0.021919, 0.019887, 0.017769, 0.015870, 0.014175, 0.012561,
+        0.011169, 0.010117, 0.009049, 0.008149, 0.007348, 0.006627, 0.005895, 0.005305,
+        0.004849, 0.004163, 0.003489, 0.003427, 0.003179, 0.002755, 0.002535, 0.001850,
+        0.001334,
+    ],
+];
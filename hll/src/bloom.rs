@@ -0,0 +1,260 @@
+use alloc::{vec, vec::Vec};
+use core::hash::Hash;
+
+use ahash::RandomState;
+use sketch_traits::{
+    default_seeds, CardinalitySketch, Estimate, Membership, MemorySize, New,
+    PrehashedCardinalitySketch, SeedableBuildHasher, Validate,
+};
+
+use crate::{Config, ConfigError, HyperLogLog, InvariantViolation, MergeError};
+
+/// A HyperLogLog paired with a Bloom filter of the same items, so that it can
+/// also tell whether an item was likely inserted, e.g., as the counters of
+/// heavy distinct hitter sketches.
+#[derive(Clone, Debug)]
+pub struct BloomHyperLogLog<I, H = RandomState> {
+    config: BloomConfig<H>,
+    hll: HyperLogLog<I, H>,
+    bits: Vec<u64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct BloomConfig<H = RandomState> {
+    pub(crate) hll_config: Config<H>,
+    pub(crate) num_bits: usize,
+    pub(crate) num_hashes: u32,
+    pub(crate) seeds: [u64; 4],
+    pub(crate) hash_builder: H,
+}
+
+impl<H> BloomConfig<H>
+where
+    H: SeedableBuildHasher,
+{
+    /// Bloom filters of `num_bits` bits, each item setting `num_hashes` of
+    /// them. About `1.44 * log2(1 / p)` bits per item and `log2(1 / p)`
+    /// hashes keep false positives at a rate `p`.
+    pub fn new(
+        hll_config: Config<H>,
+        num_bits: usize,
+        num_hashes: u32,
+        seeds: Option<[u64; 4]>,
+    ) -> Result<Self, ConfigError> {
+        if num_bits == 0 {
+            return Err(ConfigError::ZeroBloomBits);
+        } else if num_hashes == 0 {
+            return Err(ConfigError::ZeroBloomHashes);
+        }
+        let seeds = seeds.unwrap_or_else(default_seeds);
+        Ok(Self {
+            hll_config,
+            num_bits,
+            num_hashes,
+            seeds,
+            hash_builder: H::with_seeds(seeds),
+        })
+    }
+
+    pub fn hll_config(&self) -> &Config<H> {
+        &self.hll_config
+    }
+
+    pub fn num_bits(&self) -> usize {
+        self.num_bits
+    }
+
+    pub fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+}
+
+impl<H> PartialEq for BloomConfig<H> {
+    fn eq(&self, other: &Self) -> bool {
+        self.hll_config == other.hll_config
+            && self.num_bits == other.num_bits
+            && self.num_hashes == other.num_hashes
+            && self.seeds == other.seeds
+    }
+}
+
+impl<H> Eq for BloomConfig<H> {}
+
+/// Configs only hold their parameters and hashers, inline.
+impl<H> MemorySize for BloomConfig<H> {
+    fn heap_size(&self) -> usize {
+        0
+    }
+}
+
+impl<I, H> New for BloomHyperLogLog<I, H>
+where
+    H: SeedableBuildHasher,
+{
+    type Config = BloomConfig<H>;
+
+    fn new(config: &Self::Config) -> Self {
+        Self {
+            config: config.clone(),
+            hll: HyperLogLog::new(&config.hll_config),
+            bits: vec![0; config.num_bits.div_ceil(64)],
+        }
+    }
+}
+
+impl<I, H> CardinalitySketch for BloomHyperLogLog<I, H>
+where
+    I: Hash,
+    H: SeedableBuildHasher,
+{
+    type Item = I;
+    type MergeError = MergeError;
+
+    #[inline]
+    fn insert(&mut self, item: &Self::Item) {
+        self.hll.insert(item);
+        self.set_bits(item);
+    }
+
+    fn merge(&mut self, other: &Self) -> Result<(), Self::MergeError> {
+        if self.config != other.config {
+            return Err(MergeError::ConfigMismatch);
+        }
+        self.hll.merge(&other.hll)?;
+        self.bits
+            .iter_mut()
+            .zip(other.bits.iter())
+            .for_each(|(s, o)| *s |= o);
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.hll.clear();
+        self.bits.fill(0);
+    }
+
+    #[inline]
+    fn cardinality(&self) -> u64 {
+        self.hll.cardinality()
+    }
+
+    fn cardinality_with_error(&self) -> Option<Estimate> {
+        self.hll.cardinality_with_error()
+    }
+
+    #[inline]
+    fn cardinality_f64(&self) -> f64 {
+        self.hll.cardinality_f64()
+    }
+}
+
+impl<I, H> Membership for BloomHyperLogLog<I, H>
+where
+    I: Hash,
+    H: SeedableBuildHasher,
+{
+    fn may_contain(&self, item: &I) -> bool {
+        self.bit_indices(item)
+            .all(|i| self.bits[i / 64] & (1 << (i % 64)) != 0)
+    }
+}
+
+/// Item hashes are filtered as `u64` items would be.
+impl<I, H> PrehashedCardinalitySketch for BloomHyperLogLog<I, H>
+where
+    I: Hash,
+    H: SeedableBuildHasher,
+{
+    #[inline]
+    fn insert_hashed(&mut self, item_hash: u64) {
+        self.hll.insert_hashed(item_hash);
+        self.set_bits(&item_hash);
+    }
+}
+
+impl<I, H> BloomHyperLogLog<I, H>
+where
+    H: SeedableBuildHasher,
+{
+    pub fn config(&self) -> &BloomConfig<H> {
+        &self.config
+    }
+
+    pub fn hll(&self) -> &HyperLogLog<I, H> {
+        &self.hll
+    }
+
+    #[inline]
+    fn set_bits(&mut self, key: &impl Hash) {
+        for i in self.bit_indices(key) {
+            self.bits[i / 64] |= 1 << (i % 64);
+        }
+    }
+
+    /// The bits of `key`, from the two halves of a single hash (Kirsch and
+    /// Mitzenmacher's double hashing).
+    #[inline]
+    fn bit_indices(&self, key: &impl Hash) -> impl Iterator<Item = usize> {
+        let hash = self.config.hash_builder.hash_one(key);
+        let (h1, h2) = (hash & u64::from(u32::MAX), (hash >> 32) | 1);
+        let num_bits = self.config.num_bits as u64;
+        (0..u64::from(self.config.num_hashes))
+            .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % num_bits) as usize)
+    }
+}
+
+impl<I, H> Validate for BloomHyperLogLog<I, H> {
+    type Violation = InvariantViolation;
+
+    fn debug_validate(&self) -> Result<(), Self::Violation> {
+        if self.bits.len() != self.config.num_bits.div_ceil(64) {
+            return Err(InvariantViolation::BloomFilterSizeMismatch);
+        }
+        self.hll.debug_validate()
+    }
+}
+
+impl<I, H> MemorySize for BloomHyperLogLog<I, H> {
+    fn heap_size(&self) -> usize {
+        self.config.heap_size() + self.hll.heap_size() + self.bits.heap_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> BloomConfig {
+        let hll_config = Config::new(256, Some([0, 1, 2, 3, 4, 5, 6, 7])).unwrap();
+        // About 1% of false positives for 1000 items.
+        BloomConfig::new(hll_config, 9_600, 7, Some([8, 9, 10, 11])).unwrap()
+    }
+
+    #[test]
+    fn it_has_no_false_negatives() {
+        let mut sketch = BloomHyperLogLog::new(&config());
+        (0..1_000_u64).for_each(|i| sketch.insert(&i));
+        assert!((0..1_000_u64).all(|i| sketch.may_contain(&i)));
+        let false_positives = (1_000..11_000_u64)
+            .filter(|i| sketch.may_contain(i))
+            .count();
+        assert!(false_positives < 300, "{}", false_positives);
+        assert_eq!(sketch.cardinality(), sketch.hll().cardinality());
+        assert!(sketch.debug_validate().is_ok());
+    }
+
+    #[test]
+    fn merged_filters_contain_both_sides() {
+        let mut left = BloomHyperLogLog::new(&config());
+        let mut right = BloomHyperLogLog::new(&config());
+        (0..100_u64).for_each(|i| left.insert(&i));
+        (100..200_u64).for_each(|i| right.insert(&i));
+        assert!(left.merge(&right).is_ok());
+        assert!((0..200_u64).all(|i| left.may_contain(&i)));
+
+        left.clear();
+        assert!(!left.may_contain(&0));
+        let other_config = BloomConfig::new(config().hll_config, 64, 1, None).unwrap();
+        assert!(left.merge(&BloomHyperLogLog::new(&other_config)).is_err());
+    }
+}
@@ -0,0 +1,501 @@
+//! Registers are encoded whichever of three ways is the most compact: raw
+//! bytes, packed on 6 bits each, or as runs of equal registers, which suits
+//! sparse sketches.
+//!
+//! Deltas share the layout, with the registers that didn't change since the
+//! snapshot zeroed, so that they shrink to runs of zeros.
+
+use alloc::{borrow::Cow, vec, vec::Vec};
+
+use sketch_traits::{
+    codec::{
+        decode_varint, decode_version, encode_varint, encode_version, take, varint_len, Decode,
+        DecodeError, Encode, EncodeDelta, EncodeState, MergeEncoded,
+    },
+    New, SeedableBuildHasher,
+};
+
+use crate::{
+    fold,
+    packed::{self, packed_len, MAX_PACKED_VALUE, PACKED_BITS},
+    Config, HyperLogLog, MergeError,
+};
+
+// Version 2 added the hash width to the config, version 3 the packed and
+// run-length encodings of registers, version 4 the estimator to the config,
+// version 5 the sparse encoding of registers, and version 6 the register width
+// to the config.
+const FORMAT_VERSION: u8 = 6;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum RegisterEncoding {
+    Raw = 0,
+    Packed = 1,
+    RunLength = 2,
+    /// The number of non-zero registers, then each of them as the number of
+    /// zero registers since the previous one and its value, the numbers as
+    /// varints. Counters of few items, or deltas, mostly hold zeros.
+    Sparse = 3,
+}
+
+impl RegisterEncoding {
+    /// The most compact encoding of `registers`.
+    fn for_registers(registers: &[u8]) -> Self {
+        let raw_len = registers.len();
+        let packed_len = if registers.iter().all(|&r| r <= MAX_PACKED_VALUE) {
+            packed_len(registers.len(), PACKED_BITS)
+        } else {
+            usize::MAX
+        };
+        let run_length_len = 3 * runs(registers).count();
+        let sparse_len = sparse_len(registers);
+        if sparse_len < run_length_len.min(packed_len).min(raw_len) {
+            RegisterEncoding::Sparse
+        } else if run_length_len < packed_len.min(raw_len) {
+            RegisterEncoding::RunLength
+        } else if packed_len < raw_len {
+            RegisterEncoding::Packed
+        } else {
+            RegisterEncoding::Raw
+        }
+    }
+}
+
+impl Encode for RegisterEncoding {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u8).encode(out);
+    }
+}
+
+impl Decode for RegisterEncoding {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match u8::decode(input)? {
+            0 => Ok(RegisterEncoding::Raw),
+            1 => Ok(RegisterEncoding::Packed),
+            2 => Ok(RegisterEncoding::RunLength),
+            3 => Ok(RegisterEncoding::Sparse),
+            _ => Err(DecodeError::InvalidValue),
+        }
+    }
+}
+
+/// Runs of equal registers, as `(value, length)`, split so that lengths fit
+/// on 16 bits.
+fn runs(registers: &[u8]) -> impl Iterator<Item = (u8, u16)> + '_ {
+    registers
+        .chunk_by(|a, b| a == b)
+        .flat_map(|run| run.chunks(usize::from(u16::MAX)))
+        // Chunks are at most `u16::MAX` long, so this cannot truncate.
+        .map(|run| (run[0], run.len() as u16))
+}
+
+/// The non-zero registers, as the number of zero registers before each and
+/// its value.
+fn gaps(registers: &[u8]) -> impl Iterator<Item = (u64, u8)> + '_ {
+    let mut previous = 0;
+    registers
+        .iter()
+        .enumerate()
+        .filter(|(_, &r)| r != 0)
+        .map(move |(index, &r)| {
+            let gap = index - previous;
+            previous = index + 1;
+            (gap as u64, r)
+        })
+}
+
+/// The number of bytes of the sparse encoding of `registers`.
+fn sparse_len(registers: &[u8]) -> usize {
+    let (num_set, len) = gaps(registers).fold((0, 0), |(num_set, len), (gap, _)| {
+        (num_set + 1, len + varint_len(gap) + 1)
+    });
+    varint_len(num_set) + len
+}
+
+/// Writes `registers` in the most compact encoding, prefixed by a tag.
+pub(crate) fn encode_registers(registers: &[u8], out: &mut Vec<u8>) {
+    let encoding = RegisterEncoding::for_registers(registers);
+    encoding.encode(out);
+    match encoding {
+        RegisterEncoding::Raw => out.extend_from_slice(registers),
+        RegisterEncoding::Packed => out.extend_from_slice(&packed::pack(registers, PACKED_BITS)),
+        RegisterEncoding::RunLength => runs(registers).for_each(|run| run.encode(out)),
+        RegisterEncoding::Sparse => {
+            let num_set = registers.iter().filter(|&&r| r != 0).count();
+            encode_varint(out, num_set as u64);
+            for (gap, value) in gaps(registers) {
+                encode_varint(out, gap);
+                value.encode(out);
+            }
+        }
+    }
+}
+
+/// Reads `num_registers` registers written by [`encode_registers`], borrowing
+/// them from `input` if they were encoded raw.
+pub(crate) fn decode_registers<'a>(
+    input: &mut &'a [u8],
+    num_registers: usize,
+) -> Result<Cow<'a, [u8]>, DecodeError> {
+    match RegisterEncoding::decode(input)? {
+        RegisterEncoding::Raw => Ok(Cow::Borrowed(take(input, num_registers)?)),
+        RegisterEncoding::Packed => {
+            let packed = take(input, packed_len(num_registers, PACKED_BITS))?;
+            Ok(Cow::Owned(packed::unpack(
+                packed,
+                num_registers,
+                PACKED_BITS,
+            )))
+        }
+        RegisterEncoding::RunLength => {
+            let mut registers = Vec::with_capacity(num_registers);
+            while registers.len() < num_registers {
+                let (value, len) = <(u8, u16)>::decode(input)?;
+                let len = usize::from(len);
+                if len == 0 || registers.len() + len > num_registers {
+                    return Err(DecodeError::InvalidValue);
+                }
+                registers.resize(registers.len() + len, value);
+            }
+            Ok(Cow::Owned(registers))
+        }
+        RegisterEncoding::Sparse => {
+            let mut registers = vec![0; num_registers];
+            let mut index = 0_usize;
+            for _ in 0..decode_varint(input)? {
+                let gap = usize::try_from(decode_varint(input)?)
+                    .map_err(|_| DecodeError::InvalidValue)?;
+                let value = u8::decode(input)?;
+                index = index
+                    .checked_add(gap)
+                    .filter(|&index| index < num_registers && value != 0)
+                    .ok_or(DecodeError::InvalidValue)?;
+                registers[index] = value;
+                index += 1;
+            }
+            Ok(Cow::Owned(registers))
+        }
+    }
+}
+
+impl<I, H> EncodeState for HyperLogLog<I, H>
+where
+    H: SeedableBuildHasher,
+{
+    fn encode_state(&self, out: &mut Vec<u8>) {
+        encode_registers(&self.registers(), out);
+    }
+
+    fn merge_encoded_state(&mut self, input: &mut &[u8]) -> Result<(), DecodeError> {
+        let registers = decode_registers(input, self.config.num_registers)?;
+        let max_register_value = self.config.max_register_value();
+        if registers.iter().any(|&r| r > max_register_value) {
+            return Err(DecodeError::InvalidValue);
+        }
+        self.merge_registers(&registers);
+        Ok(())
+    }
+}
+
+impl<I, H> Encode for HyperLogLog<I, H>
+where
+    H: SeedableBuildHasher,
+{
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_version(out, FORMAT_VERSION);
+        self.config.encode(out);
+        self.encode_state(out);
+    }
+}
+
+impl<I, H> EncodeDelta for HyperLogLog<I, H>
+where
+    H: SeedableBuildHasher,
+{
+    /// The registers at the time.
+    type Snapshot = Vec<u8>;
+
+    fn snapshot(&self) -> Vec<u8> {
+        self.registers().into_owned()
+    }
+
+    /// Registers only grow until the sketch is cleared, and zero ones don't
+    /// change anything when merged. Should any register have been lowered
+    /// since, or the snapshot be of another number of registers, the whole
+    /// sketch is encoded instead, as on the first payload.
+    fn encode_delta(&self, snapshot: &Vec<u8>, out: &mut Vec<u8>) {
+        let registers = self.registers();
+        if snapshot.len() != registers.len() || registers.iter().zip(snapshot).any(|(&r, &s)| r < s)
+        {
+            self.encode(out);
+            return;
+        }
+        let changed = registers
+            .iter()
+            .zip(snapshot)
+            .map(|(&r, &s)| if r == s { 0 } else { r })
+            .collect::<Vec<_>>();
+        encode_version(out, FORMAT_VERSION);
+        self.config.encode(out);
+        encode_registers(&changed, out);
+    }
+}
+
+impl<I, H> Decode for HyperLogLog<I, H>
+where
+    H: SeedableBuildHasher,
+{
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        decode_version(input, FORMAT_VERSION)?;
+        let mut sketch = Self::new(&Config::<H>::decode(input)?);
+        sketch.merge_encoded_state(input)?;
+        Ok(sketch)
+    }
+}
+
+impl<I, H> MergeEncoded for HyperLogLog<I, H>
+where
+    H: SeedableBuildHasher,
+{
+    type Error = MergeError;
+
+    fn merge_encoded(&mut self, mut input: &[u8]) -> Result<(), Self::Error> {
+        decode_version(&mut input, FORMAT_VERSION)?;
+        let config = Config::<H>::decode(&mut input)?;
+        if !config.merges_into(&self.config) {
+            return Err(MergeError::ConfigMismatch);
+        }
+        if config.num_registers > self.config.num_registers {
+            // Folded like by `merge`.
+            let mut other = Self::new(&config);
+            other.merge_encoded_state(&mut input)?;
+            self.merge_registers(&fold(other.registers.non_zero(), self.config.num_registers));
+            return Ok(());
+        }
+        self.merge_encoded_state(&mut input)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sketch_traits::{codec::DeltaEncoder, CardinalitySketch};
+
+    use super::*;
+    use crate::{Estimator, HashWidth, RegisterWidth, MAX_REGISTER_VALUE};
+
+    fn config(seed: u64) -> Config {
+        Config::new(256, Some([seed; 8])).unwrap()
+    }
+
+    #[test]
+    fn it_round_trips() {
+        let mut sketch = HyperLogLog::new(&config(0));
+        (0..1000_u64).for_each(|i| sketch.insert(&i));
+
+        let mut buf = vec![];
+        sketch.encode(&mut buf);
+        let decoded = HyperLogLog::<u64>::decode(&mut buf.as_slice()).unwrap();
+        assert_eq!(decoded.registers(), sketch.registers());
+        assert_eq!(decoded.cardinality(), sketch.cardinality());
+    }
+
+    #[test]
+    fn it_round_trips_hash_widths_estimators_and_register_widths() {
+        for (estimator, register_width) in [
+            (Estimator::BiasCorrected, RegisterWidth::Bits8),
+            (Estimator::Improved, RegisterWidth::Bits4),
+            (Estimator::MaximumLikelihood, RegisterWidth::Bits5),
+            (Estimator::Martingale, RegisterWidth::Bits6),
+            (Estimator::LogLogBeta, RegisterWidth::Bits8),
+        ] {
+            let config = config(0)
+                .with_hash_width(HashWidth::Bits32)
+                .with_estimator(estimator)
+                .with_register_width(register_width);
+            let mut sketch = HyperLogLog::new(&config);
+            (0..1000_u64).for_each(|i| sketch.insert(&i));
+
+            let mut buf = vec![];
+            sketch.encode(&mut buf);
+            let decoded = HyperLogLog::<u64>::decode(&mut buf.as_slice()).unwrap();
+            assert_eq!(decoded.config(), &config);
+            assert_eq!(decoded.registers(), sketch.registers());
+        }
+    }
+
+    #[test]
+    fn it_merges_encoded_like_merge() {
+        let mut sketch1 = HyperLogLog::new(&config(0));
+        let mut sketch2 = HyperLogLog::new(&config(0));
+        (0..1000_u64).for_each(|i| sketch1.insert(&i));
+        (500..2000_u64).for_each(|i| sketch2.insert(&i));
+
+        let mut buf = vec![];
+        sketch2.encode(&mut buf);
+        let mut merged = sketch1.clone();
+        assert!(merged.merge_encoded(&buf).is_ok());
+        assert!(sketch1.merge(&sketch2).is_ok());
+        assert_eq!(merged.registers(), sketch1.registers());
+        assert_eq!(merged.cardinality(), sketch1.cardinality());
+
+        // Sketches of more registers are folded.
+        let mut large = HyperLogLog::new(&Config::new(1024, Some([0; 8])).unwrap());
+        (2000..3000_u64).for_each(|i| large.insert(&i));
+        buf.clear();
+        large.encode(&mut buf);
+        assert!(merged.merge_encoded(&buf).is_ok());
+        assert!(sketch1.merge(&large).is_ok());
+        assert_eq!(merged.registers(), sketch1.registers());
+        buf.clear();
+        sketch1.encode(&mut buf);
+        assert!(large.merge_encoded(&buf).is_err());
+    }
+
+    #[test]
+    fn deltas_merge_like_full_payloads() {
+        let mut agent = HyperLogLog::new(&config(0));
+        let mut aggregate = HyperLogLog::<u64>::new(&config(0));
+        let mut encoder = DeltaEncoder::new();
+        let mut payloads = vec![];
+        for batch in 0..4_u64 {
+            (batch * 1000..(batch + 1) * 1000).for_each(|i| agent.insert(&i));
+            let mut buf = vec![];
+            encoder.encode_next(&agent, &mut buf);
+            assert!(aggregate.merge_encoded(&buf).is_ok());
+            payloads.push(buf.len());
+        }
+        assert_eq!(aggregate.registers(), agent.registers());
+        // Fewer registers change as the sketch fills up.
+        assert!(payloads[3] < payloads[0]);
+
+        let mut empty = vec![];
+        encoder.encode_next(&agent, &mut empty);
+        // Nothing changed, so the delta is the payload of an empty sketch.
+        let mut expected = vec![];
+        HyperLogLog::<u64>::new(&config(0)).encode(&mut expected);
+        assert_eq!(empty, expected);
+    }
+
+    #[test]
+    fn deltas_of_cleared_sketches_hold_the_whole_sketch() {
+        let mut agent = HyperLogLog::new(&config(0));
+        (0..1000_u64).for_each(|i| agent.insert(&i));
+        let snapshot = agent.snapshot();
+        agent.clear();
+        (1000..1100_u64).for_each(|i| agent.insert(&i));
+
+        let mut delta = vec![];
+        agent.encode_delta(&snapshot, &mut delta);
+        let mut expected = vec![];
+        agent.encode(&mut expected);
+        assert_eq!(delta, expected);
+
+        // As are deltas from snapshots of other numbers of registers.
+        let other = HyperLogLog::<u64>::new(&Config::new(128, Some([0; 8])).unwrap());
+        delta.clear();
+        agent.encode_delta(&other.snapshot(), &mut delta);
+        assert_eq!(delta, expected);
+    }
+
+    #[test]
+    fn out_of_range_registers_are_rejected_before_merging() {
+        let mut corrupted = HyperLogLog::<u64>::new(&config(0));
+        (0..1000_u64).for_each(|i| corrupted.insert(&i));
+        corrupted.registers.as_dense_mut()[1] = MAX_REGISTER_VALUE + 1;
+        let mut buf = vec![];
+        corrupted.encode(&mut buf);
+
+        let mut sketch = HyperLogLog::<u64>::new(&config(0));
+        (0..10_u64).for_each(|i| sketch.insert(&i));
+        let registers = sketch.registers().into_owned();
+        assert!(matches!(
+            sketch.merge_encoded(&buf),
+            Err(MergeError::Decode(DecodeError::InvalidValue))
+        ));
+        assert_eq!(sketch.registers(), registers);
+        assert!(HyperLogLog::<u64>::decode(&mut buf.as_slice()).is_err());
+    }
+
+    #[test]
+    fn it_picks_the_most_compact_register_encoding() {
+        let mut sketch = HyperLogLog::<u64>::new(&config(0));
+        let encoded_len = |sketch: &HyperLogLog<u64>| {
+            let mut buf = vec![];
+            sketch.encode_state(&mut buf);
+            let decoded = decode_registers(&mut &buf[..], sketch.config.num_registers).unwrap();
+            assert_eq!(decoded, sketch.registers());
+            (RegisterEncoding::decode(&mut &buf[..]).unwrap(), buf.len())
+        };
+        // Empty registers only take their number of non-zero registers.
+        assert_eq!(encoded_len(&sketch), (RegisterEncoding::Sparse, 2));
+
+        // A few registers take a byte for their gap and one for their value.
+        (0..10_u64).for_each(|i| sketch.insert(&i));
+        let num_set = sketch.registers().iter().filter(|&&r| r != 0).count();
+        assert_eq!(
+            encoded_len(&sketch),
+            (RegisterEncoding::Sparse, 2 + 2 * num_set)
+        );
+
+        let mut ones = HyperLogLog::<u64>::new(&config(0));
+        ones.merge_raw_registers(&[1; 256]).unwrap();
+        assert_eq!(encoded_len(&ones), (RegisterEncoding::RunLength, 4));
+
+        (0..100_000_u64).for_each(|i| sketch.insert(&i));
+        assert_eq!(encoded_len(&sketch), (RegisterEncoding::Packed, 1 + 192));
+
+        sketch.registers.as_dense_mut()[1] = 65;
+        assert_eq!(encoded_len(&sketch), (RegisterEncoding::Raw, 1 + 256));
+    }
+
+    #[test]
+    fn it_rejects_malformed_register_runs() {
+        let mut sketch = HyperLogLog::<u64>::new(&config(0));
+        let mut buf = vec![];
+        RegisterEncoding::RunLength.encode(&mut buf);
+        (1_u8, 200_u16).encode(&mut buf);
+        (2_u8, 100_u16).encode(&mut buf);
+        assert_eq!(
+            sketch.merge_encoded_state(&mut buf.as_slice()),
+            Err(DecodeError::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn it_rejects_malformed_sparse_registers() {
+        let mut sketch = HyperLogLog::<u64>::new(&config(0));
+        // Zero registers, and registers past the last one, aren't encoded.
+        for (gap, value) in [(3, 0), (256, 1), (u64::MAX, 1)] {
+            let mut buf = vec![];
+            RegisterEncoding::Sparse.encode(&mut buf);
+            encode_varint(&mut buf, 1);
+            encode_varint(&mut buf, gap);
+            value.encode(&mut buf);
+            assert_eq!(
+                sketch.merge_encoded_state(&mut buf.as_slice()),
+                Err(DecodeError::InvalidValue)
+            );
+        }
+        assert_eq!(sketch.cardinality(), 0);
+    }
+
+    #[test]
+    fn it_rejects_mismatched_or_truncated_payloads() {
+        let mut sketch = HyperLogLog::<u64>::new(&config(0));
+        let mut buf = vec![];
+        HyperLogLog::<u64>::new(&config(1)).encode(&mut buf);
+        assert!(matches!(
+            sketch.merge_encoded(&buf),
+            Err(MergeError::ConfigMismatch)
+        ));
+
+        buf.clear();
+        sketch.encode(&mut buf);
+        buf.pop();
+        assert!(matches!(
+            sketch.merge_encoded(&buf),
+            Err(MergeError::Decode(DecodeError::UnexpectedEnd))
+        ));
+    }
+}
@@ -1,61 +1,528 @@
-use std::{error, fmt};
+use alloc::vec::Vec;
+use core::{error, fmt};
 
 use ahash::RandomState;
-use rand::random;
+use sketch_traits::{
+    codec::{Decode, DecodeError, Encode},
+    default_seeds, seeds_from_key, MemorySize, SeedableBuildHasher,
+};
 
+#[allow(unused_imports)]
+use crate::float::*;
+use crate::{
+    packed::{self, PACKED_BITS},
+    sparse::Layout,
+};
+
+/// The parameters of HyperLogLogs, hashing items with `H`, ahash by default.
 #[derive(Clone, Debug)]
-pub struct Config {
+pub struct Config<H = RandomState> {
     pub(crate) num_registers: usize,
     pub(crate) alpha: f64,
-    seeds: [u64; 8],
-    pub(crate) hash_builders: [RandomState; 2],
+    pub(crate) seeds: [u64; 8],
+    pub(crate) hash_builders: [H; 2],
+    pub(crate) hash_width: HashWidth,
+    pub(crate) estimator: Estimator,
+    pub(crate) register_width: RegisterWidth,
+    /// Whether sketches start with sparse registers, and pack them on 6 bits
+    /// once they aren't. Only the representation changes, so these aren't
+    /// compared, encoded or serialized.
+    pub(crate) sparse_registers: bool,
+    pub(crate) packed_registers: bool,
+}
+
+/// The number of hash bits the registers are computed from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HashWidth {
+    /// Only the low 32 bits of item hashes are used, as in HyperLogLogs
+    /// built on 32-bit hash functions. Registers saturate at 33 and large
+    /// cardinalities are corrected for hash collisions.
+    Bits32,
+    #[default]
+    Bits64,
+}
+
+impl HashWidth {
+    /// The largest value a register can hold: the number of trailing zeros of
+    /// a hash, plus one.
+    pub fn max_register_value(self) -> u8 {
+        match self {
+            HashWidth::Bits32 => 33,
+            HashWidth::Bits64 => 65,
+        }
+    }
+}
+
+/// The bits each register is stored on.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RegisterWidth {
+    /// Registers saturate at 15, from about `2^14` items per register on,
+    /// e.g., 16 million items over 1024 registers.
+    Bits4,
+    /// Registers saturate at 31, from about `2^30` items per register on.
+    Bits5,
+    /// Registers saturate at 63, which 32-bit hashes never reach, and 64-bit
+    /// ones from about `2^62` items per register on.
+    Bits6,
+    /// A byte per register, which holds any register value.
+    #[default]
+    Bits8,
+}
+
+impl RegisterWidth {
+    pub fn bits(self) -> u32 {
+        match self {
+            RegisterWidth::Bits4 => 4,
+            RegisterWidth::Bits5 => 5,
+            RegisterWidth::Bits6 => 6,
+            RegisterWidth::Bits8 => 8,
+        }
+    }
+
+    /// The largest value that fits in a register.
+    pub fn max_register_value(self) -> u8 {
+        packed::max_value(self.bits())
+    }
 }
 
+/// How cardinalities are estimated from the registers.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Estimator {
+    /// The original HyperLogLog estimator, switching to linear counting for
+    /// small ranges.
+    #[default]
+    Original,
+    /// HyperLogLog++'s: raw estimates of up to 5 times the number of
+    /// registers are corrected by empirical bias tables, which removes the
+    /// bump in error where the original estimator leaves linear counting.
+    /// Sketches of more than 2^18 registers, for which there are no tables,
+    /// estimate as with [`Estimator::Original`].
+    BiasCorrected,
+    /// Ertl's improved raw estimator, which replaces zero and saturated
+    /// registers by their expectations in the raw estimate, so it needs no
+    /// correction over the whole range. Estimating goes through all the
+    /// registers rather than sums kept up to date by inserts.
+    Improved,
+    /// Ertl's maximum likelihood estimator, slightly more accurate than
+    /// [`Estimator::Improved`] for a few Newton iterations more per estimate.
+    MaximumLikelihood,
+    /// The martingale, or historic inverse probability, estimator: each
+    /// insert that raises a register adds the inverse of the probability it
+    /// had to, `m / sum(2^-r)`, to a running estimate, with a lower variance
+    /// than any estimate from the registers alone. The running estimate only
+    /// holds for a single stream, so sketches that merge registers, including
+    /// decoded ones, estimate as with [`Estimator::Original`] from then on.
+    Martingale,
+    /// Qin et al.'s LogLog-Beta estimator, which corrects the raw estimate
+    /// by a polynomial of the number of zero registers, rather than by linear
+    /// counting or bias tables, over the whole range. Like the original
+    /// estimator, it estimates from sums kept up to date by inserts. Sketches
+    /// of more than 2^18 registers, for which there are no fitted
+    /// coefficients, estimate as with [`Estimator::Original`].
+    LogLogBeta,
+}
+
+/// The fewest registers a HyperLogLog can have: the bias of the estimator
+/// isn't known for fewer.
+pub const MIN_NUM_REGISTERS: usize = 16;
+
+/// The most registers a HyperLogLog can have, 64 MiB of them on a byte each,
+/// for a relative standard error of about 0.013%.
+pub const MAX_NUM_REGISTERS: usize = 1 << 26;
+
 impl Config {
+    /// Fails unless `num_registers` is a power of two from
+    /// [`MIN_NUM_REGISTERS`] to [`MAX_NUM_REGISTERS`], so that configs can be
+    /// built from user input.
     pub fn new(num_registers: usize, seeds: Option<[u64; 8]>) -> Result<Self, ConfigError> {
-        if num_registers & (num_registers - 1) != 0 {
+        Self::with_hasher(num_registers, seeds)
+    }
+
+    /// Like [`Config::new`], with seeds derived from `key` (see
+    /// [`seeds_from_key`]), e.g., `"service-ingest-v1"`, so that
+    /// independently deployed processes build configs whose sketches merge
+    /// by agreeing on the key.
+    pub fn from_key(key: &str, num_registers: usize) -> Result<Self, ConfigError> {
+        Self::new(num_registers, Some(seeds_from_key(key)))
+    }
+
+    /// The config of the fewest registers whose estimates have a relative
+    /// standard error of at most `relative_error`, `1.04 / sqrt(m)` for `m`
+    /// registers, e.g., 1024 registers for 0.0325. Fails unless
+    /// `relative_error` is positive and met by at most [`MAX_NUM_REGISTERS`]
+    /// registers.
+    pub fn with_relative_error(
+        relative_error: f64,
+        seeds: Option<[u64; 8]>,
+    ) -> Result<Self, ConfigError> {
+        if relative_error.is_nan() || relative_error <= 0.0 {
+            return Err(ConfigError::InvalidRelativeError);
+        }
+        let ratio = 1.04 / relative_error;
+        let min_num_registers = ratio * ratio;
+        if min_num_registers > MAX_NUM_REGISTERS as f64 {
+            return Err(ConfigError::InvalidRelativeError);
+        }
+        // At most `MAX_NUM_REGISTERS`, so this cannot truncate.
+        let num_registers = (min_num_registers.ceil() as usize)
+            .next_power_of_two()
+            .max(MIN_NUM_REGISTERS);
+        Self::new(num_registers, seeds)
+    }
+}
+
+impl<H> Config<H>
+where
+    H: SeedableBuildHasher,
+{
+    /// Like [`Config::new`], but hashing items with `H`, built from the seeds.
+    pub fn with_hasher(num_registers: usize, seeds: Option<[u64; 8]>) -> Result<Self, ConfigError> {
+        if !num_registers.is_power_of_two() {
             return Err(ConfigError::NonPowerOfTwoNumRegisters);
         }
-        let seeds_or_random = seeds.unwrap_or_else(random);
+        if num_registers < MIN_NUM_REGISTERS {
+            return Err(ConfigError::TooFewRegisters);
+        }
+        if num_registers > MAX_NUM_REGISTERS {
+            return Err(ConfigError::TooManyRegisters);
+        }
+        let seeds_or_random = seeds.unwrap_or_else(default_seeds);
         Ok(Self {
             num_registers,
             alpha: alpha(num_registers),
             seeds: seeds_or_random,
             hash_builders: [
-                RandomState::with_seeds(
+                H::with_seeds([
                     seeds_or_random[0],
                     seeds_or_random[1],
                     seeds_or_random[2],
                     seeds_or_random[3],
-                ),
-                RandomState::with_seeds(
+                ]),
+                H::with_seeds([
                     seeds_or_random[4],
                     seeds_or_random[5],
                     seeds_or_random[6],
                     seeds_or_random[7],
-                ),
+                ]),
             ],
+            hash_width: HashWidth::Bits64,
+            estimator: Estimator::Original,
+            register_width: RegisterWidth::Bits8,
+            sparse_registers: true,
+            packed_registers: false,
         })
     }
 
+    /// Uses `hash_width` bits of item hashes, e.g., to merge registers built
+    /// by systems using 32-bit hashes.
+    pub fn with_hash_width(mut self, hash_width: HashWidth) -> Self {
+        self.hash_width = hash_width;
+        self
+    }
+
+    /// Estimates cardinalities with `estimator`, e.g., HyperLogLog++'s bias
+    /// correction for mid-range cardinalities.
+    pub fn with_estimator(mut self, estimator: Estimator) -> Self {
+        self.estimator = estimator;
+        self
+    }
+
+    /// Stores registers on `register_width` bits, e.g., 4 or 5 bits for
+    /// cardinalities known to be bounded, rather than a byte. Registers then
+    /// saturate at the most that fits, which the original and bias-corrected
+    /// estimators underestimate from, while Ertl's estimators account for it.
+    pub fn with_register_width(mut self, register_width: RegisterWidth) -> Self {
+        self.register_width = register_width;
+        self
+    }
+
+    /// Whether sketches keep only their non-zero registers, as long as they
+    /// take less than half the memory of all registers, which is the default.
+    /// Sparse registers suit the many small counters of SSS and SSSS, while
+    /// dense ones are faster to insert into. Sketches with either merge and
+    /// encode the same.
+    pub fn with_sparse_registers(mut self, sparse_registers: bool) -> Self {
+        self.sparse_registers = sparse_registers;
+        self
+    }
+
+    /// Whether sketches of registers of a byte pack them on 6 bits once they
+    /// aren't sparse, cutting their memory by a quarter at the cost of slower
+    /// inserts and merges. Registers are unpacked in the unlikely event one
+    /// exceeds 63. Sketches with either merge and encode the same. Registers
+    /// of fewer bits are always packed on them.
+    pub fn with_packed_registers(mut self, packed_registers: bool) -> Self {
+        self.packed_registers = packed_registers;
+        self
+    }
+}
+
+impl<H> Config<H> {
     pub fn num_registers(&self) -> usize {
         self.num_registers
     }
+
+    pub fn hash_width(&self) -> HashWidth {
+        self.hash_width
+    }
+
+    pub fn estimator(&self) -> Estimator {
+        self.estimator
+    }
+
+    pub fn register_width(&self) -> RegisterWidth {
+        self.register_width
+    }
+
+    /// The largest value registers hold, that of the hash width unless the
+    /// register width saturates them earlier.
+    pub fn max_register_value(&self) -> u8 {
+        self.hash_width
+            .max_register_value()
+            .min(self.register_width.max_register_value())
+    }
+
+    pub fn sparse_registers(&self) -> bool {
+        self.sparse_registers
+    }
+
+    pub fn packed_registers(&self) -> bool {
+        self.packed_registers
+    }
+
+    /// Whether sketches of this config fold into sketches of `config`: they
+    /// hash items alike into at least as many registers, of the same width.
+    pub(crate) fn folds_into(&self, config: &Config<H>) -> bool {
+        self.num_registers >= config.num_registers
+            && self.seeds == config.seeds
+            && self.hash_width == config.hash_width
+            && self.register_width == config.register_width
+    }
+
+    /// Whether sketches of this config merge into sketches of `config`: they
+    /// fold into them and estimate alike.
+    pub(crate) fn merges_into(&self, config: &Config<H>) -> bool {
+        self.folds_into(config) && self.estimator == config.estimator
+    }
+
+    /// The initial running estimate of sketches, if they keep one.
+    pub(crate) fn martingale(&self) -> Option<f64> {
+        (self.estimator == Estimator::Martingale).then_some(0.0)
+    }
+
+    pub(crate) fn layout(&self) -> Layout {
+        Layout {
+            num_registers: self.num_registers,
+            sparse: self.sparse_registers,
+            packed_bits: match self.register_width {
+                RegisterWidth::Bits8 => self.packed_registers.then_some(PACKED_BITS),
+                register_width => Some(register_width.bits()),
+            },
+        }
+    }
 }
 
-impl PartialEq for Config {
+/// The number of registers of [`Config::default`] and [`Config::builder`], for
+/// a relative standard error of about 3%.
+pub const DEFAULT_NUM_REGISTERS: usize = 1024;
+
+impl Config {
+    /// Builds a config by naming only the parameters that differ from
+    /// [`Config::default`]'s.
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+}
+
+/// [`DEFAULT_NUM_REGISTERS`] registers, random seeds, 64-bit hashes and the
+/// original estimator.
+impl Default for Config {
+    fn default() -> Self {
+        Self::builder()
+            .build()
+            .expect("the default number of registers is supported")
+    }
+}
+
+/// Builds [`Config`]s, see [`Config::builder`].
+#[derive(Clone, Debug)]
+pub struct ConfigBuilder {
+    num_registers: usize,
+    seeds: Option<[u64; 8]>,
+    hash_width: HashWidth,
+    estimator: Estimator,
+    register_width: RegisterWidth,
+    sparse_registers: bool,
+    packed_registers: bool,
+}
+
+impl Default for ConfigBuilder {
+    fn default() -> Self {
+        Self {
+            num_registers: DEFAULT_NUM_REGISTERS,
+            seeds: None,
+            hash_width: HashWidth::default(),
+            estimator: Estimator::default(),
+            register_width: RegisterWidth::default(),
+            sparse_registers: true,
+            packed_registers: false,
+        }
+    }
+}
+
+impl ConfigBuilder {
+    pub fn registers(mut self, num_registers: usize) -> Self {
+        self.num_registers = num_registers;
+        self
+    }
+
+    /// Seeds the hashers, rather than drawing random seeds.
+    pub fn seeds(mut self, seeds: [u64; 8]) -> Self {
+        self.seeds = Some(seeds);
+        self
+    }
+
+    pub fn hash_width(mut self, hash_width: HashWidth) -> Self {
+        self.hash_width = hash_width;
+        self
+    }
+
+    pub fn estimator(mut self, estimator: Estimator) -> Self {
+        self.estimator = estimator;
+        self
+    }
+
+    pub fn register_width(mut self, register_width: RegisterWidth) -> Self {
+        self.register_width = register_width;
+        self
+    }
+
+    pub fn sparse_registers(mut self, sparse_registers: bool) -> Self {
+        self.sparse_registers = sparse_registers;
+        self
+    }
+
+    pub fn packed_registers(mut self, packed_registers: bool) -> Self {
+        self.packed_registers = packed_registers;
+        self
+    }
+
+    /// Fails like [`Config::new`].
+    pub fn build(self) -> Result<Config, ConfigError> {
+        self.build_with_hasher()
+    }
+
+    /// Like [`build`](Self::build), but hashing items with `H`, as
+    /// [`Config::with_hasher`].
+    pub fn build_with_hasher<H>(self) -> Result<Config<H>, ConfigError>
+    where
+        H: SeedableBuildHasher,
+    {
+        Config::with_hasher(self.num_registers, self.seeds).map(|config| {
+            config
+                .with_hash_width(self.hash_width)
+                .with_estimator(self.estimator)
+                .with_register_width(self.register_width)
+                .with_sparse_registers(self.sparse_registers)
+                .with_packed_registers(self.packed_registers)
+        })
+    }
+}
+
+impl<H> PartialEq for Config<H> {
     fn eq(&self, other: &Self) -> bool {
         self.num_registers == other.num_registers
             && self.alpha == other.alpha
             && self.seeds == other.seeds
+            && self.hash_width == other.hash_width
+            && self.estimator == other.estimator
+            && self.register_width == other.register_width
     }
 }
 
-impl Eq for Config {}
+impl<H> Eq for Config<H> {}
+
+/// Configs only hold their parameters and hashers, inline.
+impl<H> MemorySize for Config<H> {
+    fn heap_size(&self) -> usize {
+        0
+    }
+}
+
+impl<H> Encode for Config<H> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.num_registers.encode(out);
+        self.seeds.encode(out);
+        match self.hash_width {
+            HashWidth::Bits32 => 32_u8,
+            HashWidth::Bits64 => 64_u8,
+        }
+        .encode(out);
+        match self.estimator {
+            Estimator::Original => 0_u8,
+            Estimator::BiasCorrected => 1_u8,
+            Estimator::Improved => 2_u8,
+            Estimator::MaximumLikelihood => 3_u8,
+            Estimator::Martingale => 4_u8,
+            Estimator::LogLogBeta => 5_u8,
+        }
+        .encode(out);
+        // At most 8, so this cannot truncate.
+        (self.register_width.bits() as u8).encode(out);
+    }
+}
+
+impl<H> Decode for Config<H>
+where
+    H: SeedableBuildHasher,
+{
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let num_registers = usize::decode(input)?;
+        let seeds = <[u64; 8]>::decode(input)?;
+        let hash_width = match u8::decode(input)? {
+            32 => HashWidth::Bits32,
+            64 => HashWidth::Bits64,
+            _ => return Err(DecodeError::InvalidValue),
+        };
+        let estimator = match u8::decode(input)? {
+            0 => Estimator::Original,
+            1 => Estimator::BiasCorrected,
+            2 => Estimator::Improved,
+            3 => Estimator::MaximumLikelihood,
+            4 => Estimator::Martingale,
+            5 => Estimator::LogLogBeta,
+            _ => return Err(DecodeError::InvalidValue),
+        };
+        let register_width = match u8::decode(input)? {
+            4 => RegisterWidth::Bits4,
+            5 => RegisterWidth::Bits5,
+            6 => RegisterWidth::Bits6,
+            8 => RegisterWidth::Bits8,
+            _ => return Err(DecodeError::InvalidValue),
+        };
+        Config::with_hasher(num_registers, Some(seeds))
+            .map(|config| {
+                config
+                    .with_hash_width(hash_width)
+                    .with_estimator(estimator)
+                    .with_register_width(register_width)
+            })
+            .map_err(|_| DecodeError::InvalidValue)
+    }
+}
 
 #[derive(Clone, Debug)]
 pub enum ConfigError {
     NonPowerOfTwoNumRegisters,
+    TooFewRegisters,
+    TooManyRegisters,
+    ZeroBloomBits,
+    ZeroBloomHashes,
+    ZeroWindow,
+    InvalidRelativeError,
 }
 
 impl fmt::Display for ConfigError {
@@ -64,17 +531,45 @@ impl fmt::Display for ConfigError {
             ConfigError::NonPowerOfTwoNumRegisters => {
                 write!(f, "the number of registers should be a non-zero power of 2")
             }
+            ConfigError::TooFewRegisters => {
+                write!(
+                    f,
+                    "the number of registers should be at least {}",
+                    MIN_NUM_REGISTERS
+                )
+            }
+            ConfigError::TooManyRegisters => {
+                write!(
+                    f,
+                    "the number of registers should be at most {}",
+                    MAX_NUM_REGISTERS
+                )
+            }
+            ConfigError::ZeroBloomBits => {
+                write!(f, "the number of Bloom filter bits should be non-zero")
+            }
+            ConfigError::ZeroBloomHashes => {
+                write!(f, "the number of Bloom filter hashes should be non-zero")
+            }
+            ConfigError::ZeroWindow => write!(f, "the sliding window should be non-zero"),
+            ConfigError::InvalidRelativeError => {
+                write!(
+                    f,
+                    "the relative error should be positive and met by at most {} registers",
+                    MAX_NUM_REGISTERS
+                )
+            }
         }
     }
 }
 
 impl error::Error for ConfigError {}
 
+/// The bias correction of the estimator, for a power of two of at least
+/// [`MIN_NUM_REGISTERS`] registers.
 fn alpha(num_registers: usize) -> f64 {
-    debug_assert!(num_registers & (num_registers - 1) == 0); // non-zero power of 2
     match num_registers {
-        1 | 2 | 4 | 8 => panic!(),
-        16 => 0.673,
+        ..=16 => 0.673,
         32 => 0.697,
         64 => 0.709,
         128 => 0.715,
@@ -83,3 +578,93 @@ fn alpha(num_registers: usize) -> f64 {
         _ => 0.7213 / (1.0 + 1.079 / (num_registers as f64)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_rejects_unsupported_numbers_of_registers() {
+        for num_registers in [0, 3, 17, 1000, usize::MAX] {
+            assert!(matches!(
+                Config::new(num_registers, None),
+                Err(ConfigError::NonPowerOfTwoNumRegisters)
+            ));
+        }
+        for num_registers in [1, 2, 4, 8] {
+            assert!(matches!(
+                Config::new(num_registers, None),
+                Err(ConfigError::TooFewRegisters)
+            ));
+        }
+        for num_registers in [MIN_NUM_REGISTERS, 32, 1 << 20, MAX_NUM_REGISTERS] {
+            assert!(Config::new(num_registers, None).is_ok());
+        }
+        for num_registers in [2 * MAX_NUM_REGISTERS, 1 << 32, 1 << (usize::BITS - 1)] {
+            assert!(matches!(
+                Config::new(num_registers, None),
+                Err(ConfigError::TooManyRegisters)
+            ));
+        }
+    }
+
+    #[test]
+    fn it_picks_the_fewest_registers_meeting_a_relative_error() {
+        for (relative_error, num_registers) in [
+            (1.0, MIN_NUM_REGISTERS),
+            (0.26, MIN_NUM_REGISTERS),
+            (0.0325, 1024),
+            (0.0324, 2048),
+            (0.01, 16384),
+        ] {
+            let config = Config::with_relative_error(relative_error, None).unwrap();
+            assert_eq!(config.num_registers(), num_registers, "{}", relative_error);
+            assert!(1.04 / (num_registers as f64).sqrt() <= relative_error);
+        }
+        for relative_error in [0.0, -0.1, f64::NAN, 1e-4, 1e-12] {
+            assert!(matches!(
+                Config::with_relative_error(relative_error, None),
+                Err(ConfigError::InvalidRelativeError)
+            ));
+        }
+    }
+
+    #[test]
+    fn configs_of_the_same_key_match() {
+        let config = Config::from_key("service-ingest-v1", 256).unwrap();
+        assert_eq!(config, Config::from_key("service-ingest-v1", 256).unwrap());
+        assert_eq!(config.seeds, seeds_from_key("service-ingest-v1"));
+        assert_ne!(config, Config::from_key("service-ingest-v2", 256).unwrap());
+        assert!(matches!(
+            Config::from_key("service-ingest-v1", 100),
+            Err(ConfigError::NonPowerOfTwoNumRegisters)
+        ));
+    }
+
+    #[test]
+    fn builders_default_to_new() {
+        let seeds = [0, 1, 2, 3, 4, 5, 6, 7];
+        assert_eq!(
+            Config::builder().seeds(seeds).build().unwrap(),
+            Config::new(DEFAULT_NUM_REGISTERS, Some(seeds)).unwrap()
+        );
+        assert_eq!(
+            Config::builder()
+                .registers(64)
+                .seeds(seeds)
+                .hash_width(HashWidth::Bits32)
+                .estimator(Estimator::BiasCorrected)
+                .build()
+                .unwrap(),
+            Config::new(64, Some(seeds))
+                .unwrap()
+                .with_hash_width(HashWidth::Bits32)
+                .with_estimator(Estimator::BiasCorrected)
+        );
+        assert!(matches!(
+            Config::builder().registers(100).build(),
+            Err(ConfigError::NonPowerOfTwoNumRegisters)
+        ));
+        assert_eq!(Config::default().num_registers(), DEFAULT_NUM_REGISTERS);
+    }
+}
@@ -0,0 +1,420 @@
+//! Conversions from and to the serialized HLL sketches of Apache DataSketches,
+//! e.g., to merge the distinct counts of Java pipelines with these sketches.
+//!
+//! DataSketches HLL sketches of `2^lgK` registers hold, like these, the
+//! number of zeros of a hash plus one, and index registers with the low bits
+//! of another, so sketches of more registers fold into sketches of fewer.
+//! DataSketches hashes items with MurmurHash3 and counts leading rather than
+//! trailing zeros, though, so converted registers estimate the same
+//! cardinalities, but only merge meaningfully with registers from the same
+//! system: the union of a DataSketches sketch and a sketch of the same items
+//! counts them twice.
+//!
+//! Sketches are imported from any of their modes (list, set, or HLL with 4, 6
+//! or 8 bits per register), and exported in HLL mode with 8 bits per
+//! register, which DataSketches reads as is.
+
+use alloc::{vec, vec::Vec};
+
+use sketch_traits::{
+    codec::{take, DecodeError},
+    SeedableBuildHasher,
+};
+
+#[allow(unused_imports)]
+use crate::float::*;
+use crate::{
+    packed::{self, PACKED_BITS},
+    HashWidth, HyperLogLog, MergeError,
+};
+
+/// The precisions, i.e., the logs of the numbers of registers, of
+/// DataSketches HLL sketches.
+const MIN_LG_K: u8 = 4;
+const MAX_LG_K: u8 = 21;
+
+/// The largest register value of DataSketches sketches, on 6 bits.
+const MAX_DATASKETCHES_REGISTER_VALUE: u8 = 63;
+
+const SER_VER: u8 = 1;
+const FAMILY_ID: u8 = 7;
+
+/// The preamble bytes of each mode, a multiple of 4 bytes.
+const LIST_PREAMBLE_INTS: u8 = 2;
+const SET_PREAMBLE_INTS: u8 = 3;
+const HLL_PREAMBLE_INTS: u8 = 10;
+
+const LIST_MODE: u8 = 0;
+const SET_MODE: u8 = 1;
+const HLL_MODE: u8 = 2;
+
+const HLL_4: u8 = 0;
+const HLL_6: u8 = 1;
+const HLL_8: u8 = 2;
+
+const BIG_ENDIAN_FLAG: u8 = 1 << 0;
+const EMPTY_FLAG: u8 = 1 << 2;
+const COMPACT_FLAG: u8 = 1 << 3;
+/// Tells DataSketches the HIP accumulator isn't valid, so that it estimates
+/// from the registers instead.
+const OUT_OF_ORDER_FLAG: u8 = 1 << 4;
+
+/// The nibble of HLL_4 registers whose value is in the auxiliary map.
+const AUX_TOKEN: u8 = 15;
+
+/// Coupons, i.e., registers of list and set modes and HLL_4 exceptions, hold
+/// the low 26 bits of the index hash and the value above them.
+const COUPON_INDEX_BITS: u32 = 26;
+
+impl<I, H> HyperLogLog<I, H>
+where
+    H: SeedableBuildHasher,
+{
+    /// Merges a serialized DataSketches HLL sketch of at least as many
+    /// registers, folding them if there are more. Fails unless the sketch
+    /// uses 64-bit hashes.
+    pub fn merge_datasketches(&mut self, bytes: &[u8]) -> Result<(), MergeError> {
+        if self.config.hash_width != HashWidth::Bits64 {
+            return Err(MergeError::ConfigMismatch);
+        }
+        let mut input = bytes;
+        let preamble = take(&mut input, 8)?;
+        let [preamble_ints, ser_ver, family_id, lg_k, lg_arr, flags, count, mode] =
+            <[u8; 8]>::try_from(preamble).map_err(|_| DecodeError::UnexpectedEnd)?;
+        // Hash tables of coupons, in list and set modes or the auxiliary map
+        // of HLL_4, have at most as many slots as there are registers.
+        if ser_ver != SER_VER
+            || family_id != FAMILY_ID
+            || !(MIN_LG_K..=MAX_LG_K).contains(&lg_k)
+            || lg_arr > lg_k
+            || flags & BIG_ENDIAN_FLAG != 0
+        {
+            return Err(DecodeError::InvalidValue.into());
+        }
+        let num_registers = 1_usize << lg_k;
+        if num_registers < self.config.num_registers {
+            return Err(MergeError::ConfigMismatch);
+        }
+        if flags & EMPTY_FLAG != 0 {
+            return Ok(());
+        }
+        let compact = flags & COMPACT_FLAG != 0;
+        let mut registers = vec![0; self.config.num_registers];
+        let mut fold = |index: usize, value: u8| {
+            let register = &mut registers[index & (self.config.num_registers - 1)];
+            *register = (*register).max(value);
+        };
+        match (mode & 3, preamble_ints) {
+            (LIST_MODE, LIST_PREAMBLE_INTS) => {
+                let len = if compact {
+                    usize::from(count)
+                } else {
+                    1 << lg_arr
+                };
+                decode_coupons(&mut input, len, &mut fold)?;
+            }
+            (SET_MODE, SET_PREAMBLE_INTS) => {
+                let count = decode_u32(&mut input)? as usize;
+                let len = if compact { count } else { 1 << lg_arr };
+                decode_coupons(&mut input, len, &mut fold)?;
+            }
+            (HLL_MODE, HLL_PREAMBLE_INTS) => {
+                // The HIP accumulator, the sums of `2^-r`, and the number of
+                // registers at the smallest value are all recomputed.
+                take(&mut input, 24)?;
+                let _num_at_cur_min = decode_u32(&mut input)?;
+                let aux_count = decode_u32(&mut input)? as usize;
+                match mode >> 2 & 3 {
+                    HLL_4 => {
+                        let nibbles = take(&mut input, num_registers / 2)?;
+                        // HLL_4 registers are offset by the smallest value.
+                        let cur_min = count;
+                        for index in 0..num_registers {
+                            let nibble = nibbles[index / 2] >> (4 * (index % 2)) & 0xf;
+                            if nibble != AUX_TOKEN {
+                                let value = nibble
+                                    .checked_add(cur_min)
+                                    .filter(|&value| value <= MAX_DATASKETCHES_REGISTER_VALUE)
+                                    .ok_or(DecodeError::InvalidValue)?;
+                                fold(index, value);
+                            }
+                        }
+                        let len = if compact { aux_count } else { 1 << lg_arr };
+                        decode_coupons(&mut input, len, &mut fold)?;
+                    }
+                    HLL_6 => {
+                        let packed = take(&mut input, num_registers * 3 / 4 + 1)?;
+                        let registers =
+                            packed::unpack(&packed[..packed.len() - 1], num_registers, PACKED_BITS);
+                        registers
+                            .into_iter()
+                            .enumerate()
+                            .for_each(|(i, r)| fold(i, r));
+                    }
+                    HLL_8 => {
+                        let registers = take(&mut input, num_registers)?;
+                        if registers
+                            .iter()
+                            .any(|&r| r > MAX_DATASKETCHES_REGISTER_VALUE)
+                        {
+                            return Err(DecodeError::InvalidValue.into());
+                        }
+                        registers.iter().enumerate().for_each(|(i, &r)| fold(i, r));
+                    }
+                    _ => return Err(DecodeError::InvalidValue.into()),
+                }
+            }
+            _ => return Err(DecodeError::InvalidValue.into()),
+        }
+        self.merge_registers(&registers);
+        Ok(())
+    }
+
+    /// The sketch as a serialized DataSketches HLL sketch, in HLL mode with 8
+    /// bits per register. Registers above 63, the most DataSketches registers
+    /// hold, saturate at it, which takes about 2^62 items per register. Fails
+    /// unless the sketch uses 64-bit hashes and has from 2^4 to 2^21
+    /// registers, as DataSketches sketches do.
+    pub fn to_datasketches(&self) -> Result<Vec<u8>, MergeError> {
+        let lg_k = self.config.num_registers.trailing_zeros();
+        if self.config.hash_width != HashWidth::Bits64
+            || !(u32::from(MIN_LG_K)..=u32::from(MAX_LG_K)).contains(&lg_k)
+        {
+            return Err(MergeError::ConfigMismatch);
+        }
+        let registers = self
+            .registers()
+            .iter()
+            .map(|&r| r.min(MAX_DATASKETCHES_REGISTER_VALUE))
+            .collect::<Vec<_>>();
+        let (mut kxq0, mut kxq1, mut num_zeros) = (0.0_f64, 0.0_f64, 0_u32);
+        for &r in &registers {
+            let inverse = 2.0_f64.powi(-i32::from(r));
+            if r < 32 {
+                kxq0 += inverse;
+            } else {
+                kxq1 += inverse;
+            }
+            num_zeros += u32::from(r == 0);
+        }
+
+        let mut bytes = Vec::with_capacity(4 * usize::from(HLL_PREAMBLE_INTS) + registers.len());
+        bytes.extend_from_slice(&[
+            HLL_PREAMBLE_INTS,
+            SER_VER,
+            FAMILY_ID,
+            // At most 21, so this cannot truncate.
+            lg_k as u8,
+            0,
+            COMPACT_FLAG | OUT_OF_ORDER_FLAG,
+            // The smallest value, which registers on 8 bits aren't offset by.
+            0,
+            HLL_8 << 2 | HLL_MODE,
+        ]);
+        // No HIP accumulator.
+        bytes.extend_from_slice(&0.0_f64.to_le_bytes());
+        bytes.extend_from_slice(&kxq0.to_le_bytes());
+        bytes.extend_from_slice(&kxq1.to_le_bytes());
+        // The number of registers at the smallest value, zero.
+        bytes.extend_from_slice(&num_zeros.to_le_bytes());
+        // No auxiliary map.
+        bytes.extend_from_slice(&0_u32.to_le_bytes());
+        bytes.extend_from_slice(&registers);
+        Ok(bytes)
+    }
+}
+
+fn decode_u32(input: &mut &[u8]) -> Result<u32, DecodeError> {
+    let bytes = take(input, 4)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Folds `len` coupons, skipping the empty slots of hash tables.
+fn decode_coupons(
+    input: &mut &[u8],
+    len: usize,
+    fold: &mut impl FnMut(usize, u8),
+) -> Result<(), DecodeError> {
+    for _ in 0..len {
+        let coupon = decode_u32(input)?;
+        if coupon == 0 {
+            continue;
+        }
+        // The value is the 6 bits above the index, so this cannot truncate.
+        let value = (coupon >> COUPON_INDEX_BITS) as u8;
+        if value == 0 || value > MAX_DATASKETCHES_REGISTER_VALUE {
+            return Err(DecodeError::InvalidValue);
+        }
+        fold((coupon & ((1 << COUPON_INDEX_BITS) - 1)) as usize, value);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use sketch_traits::{CardinalitySketch, Fold, New};
+
+    use super::*;
+    use crate::Config;
+
+    fn config(num_registers: usize) -> Config {
+        Config::new(num_registers, Some([0, 1, 2, 3, 4, 5, 6, 7])).unwrap()
+    }
+
+    fn coupon(index: u32, value: u8) -> [u8; 4] {
+        (u32::from(value) << COUPON_INDEX_BITS | index).to_le_bytes()
+    }
+
+    #[test]
+    fn it_round_trips_through_hll_8() {
+        let mut sketch = HyperLogLog::new(&config(1024));
+        (0..10_000_u64).for_each(|i| sketch.insert(&i));
+        let bytes = sketch.to_datasketches().unwrap();
+        assert_eq!(bytes.len(), 40 + 1024);
+        assert_eq!(bytes[..8], [10, 1, 7, 10, 0, 24, 0, 10]);
+
+        let mut imported = HyperLogLog::<u64>::new(&config(1024));
+        assert!(imported.merge_datasketches(&bytes).is_ok());
+        assert_eq!(imported.registers(), sketch.registers());
+        assert_eq!(imported.cardinality(), sketch.cardinality());
+
+        // Sketches of more registers fold into sketches of fewer.
+        let mut folded = HyperLogLog::<u64>::new(&config(256));
+        assert!(folded.merge_datasketches(&bytes).is_ok());
+        assert_eq!(
+            folded.registers(),
+            sketch.fold_to(&config(256)).unwrap().registers()
+        );
+        let mut larger = HyperLogLog::<u64>::new(&config(2048));
+        assert!(matches!(
+            larger.merge_datasketches(&bytes),
+            Err(MergeError::ConfigMismatch)
+        ));
+    }
+
+    #[test]
+    fn it_imports_lists_and_sets_of_coupons() {
+        // A compact list of 2 coupons, whose indices have more bits than the
+        // registers.
+        let mut list = vec![2, 1, 7, 4, 0, COMPACT_FLAG, 2, LIST_MODE];
+        list.extend_from_slice(&coupon(0x123, 5));
+        list.extend_from_slice(&coupon(0x2, 7));
+        let mut sketch = HyperLogLog::<u64>::new(&config(16));
+        assert!(sketch.merge_datasketches(&list).is_ok());
+        let mut expected = [0; 16];
+        (expected[3], expected[2]) = (5, 7);
+        assert_eq!(sketch.registers()[..], expected[..]);
+
+        // An updatable set of 4 slots, 2 of them empty.
+        let mut set = vec![3, 1, 7, 4, 2, 0, 0, SET_MODE];
+        set.extend_from_slice(&2_u32.to_le_bytes());
+        set.extend_from_slice(&[0; 4]);
+        set.extend_from_slice(&coupon(0x5, 1));
+        set.extend_from_slice(&[0; 4]);
+        set.extend_from_slice(&coupon(0x3, 9));
+        assert!(sketch.merge_datasketches(&set).is_ok());
+        (expected[3], expected[5]) = (9, 1);
+        assert_eq!(sketch.registers()[..], expected[..]);
+
+        // Empty sketches only hold their preamble.
+        let empty = [2, 1, 7, 4, 0, EMPTY_FLAG | COMPACT_FLAG, 0, LIST_MODE];
+        assert!(sketch.merge_datasketches(&empty).is_ok());
+        assert_eq!(sketch.registers()[..], expected[..]);
+    }
+
+    #[test]
+    fn it_imports_hll_4_and_hll_6_registers() {
+        let preamble = |tgt_hll_type: u8, cur_min: u8, aux_count: u32| {
+            let mut bytes = vec![
+                10,
+                1,
+                7,
+                4,
+                0,
+                COMPACT_FLAG,
+                cur_min,
+                tgt_hll_type << 2 | HLL_MODE,
+            ];
+            bytes.extend_from_slice(&[0; 28]);
+            bytes.extend_from_slice(&aux_count.to_le_bytes());
+            bytes
+        };
+        let expected = (0..16).map(|i| 2 + i % 5).collect::<Vec<u8>>();
+
+        // Nibbles above the smallest value, 2, with register 1 in the
+        // auxiliary map.
+        let mut hll_4 = preamble(HLL_4, 2, 1);
+        let mut nibbles = expected.iter().map(|&r| r - 2).collect::<Vec<_>>();
+        nibbles[1] = AUX_TOKEN;
+        hll_4.extend(nibbles.chunks(2).map(|pair| pair[1] << 4 | pair[0]));
+        hll_4.extend_from_slice(&coupon(1, expected[1]));
+        let mut sketch = HyperLogLog::<u64>::new(&config(16));
+        assert!(sketch.merge_datasketches(&hll_4).is_ok());
+        assert_eq!(sketch.registers()[..], expected[..]);
+
+        let mut hll_6 = preamble(HLL_6, 0, 0);
+        hll_6.extend_from_slice(&packed::pack(&expected, PACKED_BITS));
+        hll_6.push(0);
+        let mut sketch = HyperLogLog::<u64>::new(&config(16));
+        assert!(sketch.merge_datasketches(&hll_6).is_ok());
+        assert_eq!(sketch.registers()[..], expected[..]);
+    }
+
+    #[test]
+    fn it_rejects_other_configs_and_payloads() {
+        let sketch = HyperLogLog::<u64>::new(&config(1 << 22));
+        assert!(matches!(
+            sketch.to_datasketches(),
+            Err(MergeError::ConfigMismatch)
+        ));
+        let bytes = HyperLogLog::<u64>::new(&config(16))
+            .to_datasketches()
+            .unwrap();
+        let mut sketch = HyperLogLog::<u64>::new(&config(16).with_hash_width(HashWidth::Bits32));
+        assert!(matches!(
+            sketch.merge_datasketches(&bytes),
+            Err(MergeError::ConfigMismatch)
+        ));
+
+        let mut sketch = HyperLogLog::<u64>::new(&config(16));
+        assert!(sketch.merge_datasketches(&bytes[..50]).is_err());
+        let mut other_family = bytes.clone();
+        other_family[2] = 3;
+        assert!(sketch.merge_datasketches(&other_family).is_err());
+        let mut big_endian = bytes.clone();
+        big_endian[5] |= BIG_ENDIAN_FLAG;
+        assert!(sketch.merge_datasketches(&big_endian).is_err());
+        let mut out_of_range = bytes;
+        out_of_range[40] = 64;
+        assert!(sketch.merge_datasketches(&out_of_range).is_err());
+        assert_eq!(sketch.cardinality(), 0);
+    }
+
+    #[test]
+    fn it_rejects_malformed_headers_and_registers() {
+        let mut sketch = HyperLogLog::<u64>::new(&config(16));
+
+        // Updatable lists and sets whose hash tables are larger than the
+        // number of registers, up to shifting out of a `usize`.
+        for lg_arr in [5, 63, 64, 255] {
+            let list = [2, 1, 7, 4, lg_arr, 0, 0, LIST_MODE];
+            assert!(sketch.merge_datasketches(&list).is_err());
+            let mut set = vec![3, 1, 7, 4, lg_arr, 0, 0, SET_MODE];
+            set.extend_from_slice(&0_u32.to_le_bytes());
+            assert!(sketch.merge_datasketches(&set).is_err());
+        }
+
+        // HLL_4 nibbles whose value above the smallest one is out of range,
+        // or overflows a byte.
+        for (cur_min, nibble) in [(60, 4), (63, 1), (250, 0), (250, 14)] {
+            let mut hll_4 = vec![10, 1, 7, 4, 0, COMPACT_FLAG, cur_min, HLL_4 << 2 | HLL_MODE];
+            hll_4.extend_from_slice(&[0; 32]);
+            hll_4.extend_from_slice(&[nibble; 8]);
+            assert!(matches!(
+                sketch.merge_datasketches(&hll_4),
+                Err(MergeError::Decode(DecodeError::InvalidValue))
+            ));
+        }
+        assert_eq!(sketch.cardinality(), 0);
+    }
+}
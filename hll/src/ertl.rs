@@ -0,0 +1,228 @@
+//! Ertl's estimators, which estimate cardinalities over their whole range
+//! from the histogram of the registers, without switching to linear counting
+//! for small ones or correcting saturated registers for large ones.
+//!
+//! O. Ertl. New cardinality estimation algorithms for HyperLogLog sketches,
+//! 2017.
+//!
+//! With `q` the bits of hash rho is counted in, or one less than the value
+//! narrower registers saturate at, registers of `m` are about
+//! independent under the Poisson model of `n = m * x` items, register `k`
+//! of `1..=q` holding `k` with probability `e^(-x/2^k) - e^(-x/2^(k-1))`, and
+//! register `q + 1` the rest.
+
+use alloc::vec::Vec;
+use core::f64::consts::LN_2;
+
+use sketch_traits::Estimate;
+
+#[allow(unused_imports)]
+use crate::float::*;
+use crate::{linear_counting::linear_counting_error, Config, Estimator};
+
+/// The relative change of the maximum likelihood estimate under which Newton
+/// iterations stop.
+const MAX_RELATIVE_STEP: f64 = 1e-10;
+
+/// Newton iterations converge in a few dozen steps at most, so this only
+/// guards against non-finite histograms.
+const MAX_ITERATIONS: usize = 1_000;
+
+/// Estimates the cardinality of registers of `histogram` (see
+/// [`crate::histogram`]) with Ertl's estimators, or returns `None` if the
+/// config uses another one. The relative standard error is that of the
+/// original estimator, `1.04 / sqrt(m)`, or of linear counting for small
+/// ranges, where Ertl's estimators perform as well.
+pub(crate) fn estimate<H>(config: &Config<H>, histogram: &[usize]) -> Option<Estimate> {
+    let num_registers = config.num_registers;
+    // Rounded, since truncating biases the small estimates these are exact
+    // for. Float to int casts saturate, so infinite estimates are `u64::MAX`.
+    let cardinality = unrounded_estimate(config, histogram)?.round() as u64;
+    Some(Estimate {
+        cardinality,
+        relative_standard_error: (1.04 / (num_registers as f64).sqrt())
+            .min(linear_counting_error(num_registers, cardinality)),
+    })
+}
+
+/// Like [`estimate`], but only the cardinality, before rounding it.
+pub(crate) fn unrounded_estimate<H>(config: &Config<H>, histogram: &[usize]) -> Option<f64> {
+    let num_registers = config.num_registers;
+    match config.estimator {
+        Estimator::Improved => Some(improved(histogram, num_registers)),
+        Estimator::MaximumLikelihood => Some(maximum_likelihood(histogram, num_registers)),
+        Estimator::Original
+        | Estimator::BiasCorrected
+        | Estimator::Martingale
+        | Estimator::LogLogBeta => None,
+    }
+}
+
+/// The improved raw estimator, `alpha_inf * m^2 / z`, where `z` is the sum of
+/// `2^-r` with zero and saturated registers replaced by their expectations.
+fn improved(histogram: &[usize], num_registers: usize) -> f64 {
+    let m = num_registers as f64;
+    let q = histogram.len() - 2;
+    let mut z = m * tau(1.0 - histogram[q + 1] as f64 / m);
+    for &count in histogram[1..=q].iter().rev() {
+        z = 0.5 * (z + count as f64);
+    }
+    z += m * sigma(histogram[0] as f64 / m);
+    m * m / (2.0 * LN_2 * z)
+}
+
+/// `x + sum_k x^(2^k) * 2^(k - 1)`, infinite for 1.
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let (mut y, mut z) = (1.0, x);
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if z == previous {
+            return z;
+        }
+    }
+}
+
+/// `(1 - x - sum_k (1 - x^(2^-k))^2 * 2^-k) / 3`, zero for 0 and 1.
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let (mut y, mut z) = (1.0, 1.0 - x);
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if z == previous {
+            return z / 3.0;
+        }
+    }
+}
+
+/// The maximum likelihood estimate `m * x` of the Poisson model.
+///
+/// Zeroing the derivative of the log-likelihood in `x` gives
+/// `x * a + sum_k c_k * h(x / 2^k) = m'`, where `a` is the sum of `2^-r` over
+/// non-saturated registers (zero ones included), `c_k` counts registers `k`
+/// of `1..=q`, with saturated registers counted at `q`, `m'` counts the
+/// non-zero registers, and `h(t) = 1 - t / (e^t - 1)`. The left-hand side is
+/// increasing and concave in `x` and zero at 0, so Newton iterations from 0
+/// converge to its only root from below.
+fn maximum_likelihood(histogram: &[usize], num_registers: usize) -> f64 {
+    let q = histogram.len() - 2;
+    let num_non_zero = num_registers - histogram[0];
+    if num_non_zero == 0 {
+        return 0.0;
+    }
+    if histogram[q + 1] == num_registers {
+        return f64::INFINITY;
+    }
+    let a = histogram[..=q]
+        .iter()
+        .enumerate()
+        .map(|(k, &count)| count as f64 * 2.0_f64.powi(-(k as i32)))
+        .sum::<f64>();
+    // The weight `2^-k` each count's `h` is evaluated at.
+    let terms = (1..=q + 1)
+        .filter(|&k| histogram[k] > 0)
+        .map(|k| {
+            let weight = 2.0_f64.powi(-(k.min(q) as i32));
+            (histogram[k] as f64, weight)
+        })
+        .collect::<Vec<_>>();
+
+    let mut x = 0.0_f64;
+    for _ in 0..MAX_ITERATIONS {
+        let (mut value, mut slope) = (x * a, a);
+        for &(count, weight) in &terms {
+            value += count * h(x * weight);
+            slope += count * weight * h_prime(x * weight);
+        }
+        let step = (num_non_zero as f64 - value) / slope;
+        x += step;
+        if step <= x * MAX_RELATIVE_STEP {
+            break;
+        }
+    }
+    num_registers as f64 * x
+}
+
+/// `h(t) = 1 - t / (e^t - 1)`, from its series around 0, where the closed
+/// form cancels.
+fn h(t: f64) -> f64 {
+    if t < 0.01 {
+        let t2 = t * t;
+        t / 2.0 - t2 / 12.0 + t2 * t2 / 720.0
+    } else {
+        1.0 - t / t.exp_m1()
+    }
+}
+
+/// The derivative of [`h`], `(t e^t - e^t + 1) / (e^t - 1)^2`, written in
+/// `e^-t` so as not to overflow.
+fn h_prime(t: f64) -> f64 {
+    if t < 0.01 {
+        0.5 - t / 6.0 + t * t * t / 180.0
+    } else {
+        let u = (-t).exp();
+        (t - 1.0 + u) * u / (-t).exp_m1().powi(2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::histogram;
+
+    #[test]
+    fn h_is_continuous_where_its_series_takes_over() {
+        for f in [h, h_prime] {
+            let (below, above) = (f(0.01 - 1e-12), f(0.01 + 1e-12));
+            assert!((below - above).abs() < 1e-12, "{} != {}", below, above);
+        }
+        assert_eq!(h(0.0), 0.0);
+        assert_eq!(h(1e4), 1.0);
+        assert_eq!(h_prime(1e4), 0.0);
+    }
+
+    #[test]
+    fn maximum_likelihood_zeroes_the_derivative_of_the_likelihood() {
+        // 1024 registers of 64-bit hashes, about 10,000 items.
+        let histogram = histogram::from_non_zero(
+            1024,
+            65,
+            [0_u8, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13]
+                .iter()
+                .zip([0, 8, 29, 107, 197, 225, 189, 117, 65, 43, 25, 12, 5, 2])
+                .flat_map(|(&r, count)| core::iter::repeat_n(r, count))
+                .filter(|&r| r != 0),
+        );
+        let x = maximum_likelihood(&histogram, 1024) / 1024.0;
+        let log_likelihood = |x: f64| {
+            histogram
+                .iter()
+                .enumerate()
+                .filter(|&(_, &count)| count > 0)
+                .map(|(k, &count)| {
+                    let cdf = |k: i32| (-x * 2.0_f64.powi(-k)).exp();
+                    let p = match k {
+                        0 => cdf(0),
+                        65 => 1.0 - cdf(64),
+                        _ => cdf(k as i32) - cdf(k as i32 - 1),
+                    };
+                    count as f64 * p.ln()
+                })
+                .sum::<f64>()
+        };
+        for dx in [1e-3, 1e-2, 1e-1] {
+            assert!(log_likelihood(x) > log_likelihood(x * (1.0 + dx)));
+            assert!(log_likelihood(x) > log_likelihood(x * (1.0 - dx)));
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! Histograms of registers, the number of them holding each value from 0 to
+//! the max register value, which estimates are computed from. Unlike a
+//! running sum of `2^-r`, the counts stay exact through any number of inserts
+//! and merges, so estimates don't drift from those of the registers.
+
+use alloc::{vec, vec::Vec};
+
+/// The histogram of `num_registers` zero registers.
+pub(crate) fn zeros(num_registers: usize, max_register_value: u8) -> Vec<usize> {
+    let mut histogram = vec![0; usize::from(max_register_value) + 1];
+    histogram[0] = num_registers;
+    histogram
+}
+
+/// The histogram of `num_registers` registers whose non-zero values are
+/// `non_zero`.
+#[cfg(test)]
+pub(crate) fn from_non_zero(
+    num_registers: usize,
+    max_register_value: u8,
+    non_zero: impl Iterator<Item = u8>,
+) -> Vec<usize> {
+    let mut histogram = zeros(num_registers, max_register_value);
+    for value in non_zero {
+        raise(&mut histogram, 0, value);
+    }
+    histogram
+}
+
+/// Counts `values` into `histogram`.
+pub(crate) fn count(histogram: &mut [usize], values: impl Iterator<Item = u8>) {
+    for value in values {
+        histogram[usize::from(value)] += 1;
+    }
+}
+
+/// Moves a register raised from `previous` to `value` to the count of
+/// `value`.
+#[inline]
+pub(crate) fn raise(histogram: &mut [usize], previous: u8, value: u8) {
+    histogram[usize::from(previous)] -= 1;
+    histogram[usize::from(value)] += 1;
+}
+
+/// The sum of `2^-r` over the registers, by Horner's method from the highest
+/// value down, so that it only depends on the counts.
+#[inline]
+pub(crate) fn z_inv(histogram: &[usize]) -> f64 {
+    histogram
+        .iter()
+        .rev()
+        .fold(0.0, |z_inv, &count| 0.5 * z_inv + count as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn z_inv_sums_inverse_powers_of_two() {
+        let registers = [0_u8, 0, 1, 3, 3, 7, 65];
+        let histogram = from_non_zero(
+            registers.len(),
+            65,
+            registers.iter().copied().filter(|&r| r != 0),
+        );
+        assert_eq!(histogram[..4], [2, 1, 0, 2]);
+        let expected = registers
+            .iter()
+            .map(|&r| 2.0_f64.powi(-i32::from(r)))
+            .sum::<f64>();
+        assert!((z_inv(&histogram) - expected).abs() < 1e-15);
+    }
+}
@@ -1,140 +1,859 @@
-use std::{error, fmt, hash::Hash, iter::repeat, marker::PhantomData};
+#![cfg_attr(not(test), deny(clippy::unwrap_used))]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+use alloc::{borrow::Cow, vec, vec::Vec};
+use core::{error, f64::consts::LN_2, fmt, hash::Hash, marker::PhantomData};
 
 use ahash::RandomState;
-use sketch_traits::{CardinalitySketch, New};
+use sketch_traits::{
+    codec::DecodeError, BufferPool, CardinalitySketch, Estimate, Fold, MemorySize, New,
+    PrehashedCardinalitySketch, SeedableBuildHasher, Validate,
+};
+
+#[allow(unused_imports)]
+use crate::float::*;
 
+mod atomic;
+mod beta_tables;
+mod bias;
+mod bias_tables;
+mod bloom;
+mod codec;
 mod config;
+mod datasketches;
+mod ertl;
+mod histogram;
 mod linear_counting;
-pub use crate::config::Config;
-use crate::linear_counting::linear_counting;
+mod loglog_beta;
+mod packed;
+mod pool;
+mod redis;
+#[cfg(feature = "serde")]
+mod serde_impls;
+mod simd;
+mod sliding;
+mod sparse;
+
+/// The float methods core lacks, e.g., `ln` or `powi`, which the modules
+/// doing float math glob-import. Without std, they come from num_traits.
+/// std's inherent methods take precedence over num_traits' whenever any
+/// crate of the build links std, e.g., the tests of a no_std build, so the
+/// imports are allowed to go unused.
+mod float {
+    #[cfg(not(feature = "std"))]
+    pub(crate) use num_traits::Float;
+}
+
+pub use crate::{
+    atomic::AtomicHyperLogLog,
+    bloom::{BloomConfig, BloomHyperLogLog},
+    config::{
+        Config, ConfigBuilder, ConfigError, Estimator, HashWidth, RegisterWidth,
+        DEFAULT_NUM_REGISTERS, MAX_NUM_REGISTERS, MIN_NUM_REGISTERS,
+    },
+    redis::REDIS_NUM_REGISTERS,
+    sliding::{SlidingConfig, SlidingHyperLogLog},
+};
+use crate::{
+    linear_counting::{linear_counting, linear_counting_error},
+    sparse::{Layout, Registers},
+};
+
+/// The largest value a register can hold: the number of trailing zeros of a
+/// 64-bit hash, plus one. See [`HashWidth::max_register_value`] for other hash
+/// widths.
+pub const MAX_REGISTER_VALUE: u8 = 65;
+
+/// 2^32, the number of distinct 32-bit hashes.
+const TWO_POW_32: f64 = 4_294_967_296.0;
+
+/// The length of the longest histogram of registers, that of 64-bit hashes.
+const MAX_HISTOGRAM_LEN: usize = MAX_REGISTER_VALUE as usize + 1;
+
+/// The number of items hashed ahead of updating their registers by
+/// [`HyperLogLog::insert_batch`] and [`CardinalitySketch::insert_all`].
+const INSERT_BATCH_SIZE: usize = 64;
 
 #[derive(Clone, Debug)]
-pub struct HyperLogLog<I> {
-    config: Config,
-    registers: Vec<u8>,
-    num_zero_registers: usize,
-    z_inv: f64,
-    item_type: PhantomData<I>,
+pub struct HyperLogLog<I, H = RandomState> {
+    config: Config<H>,
+    registers: Registers,
+    /// The number of registers of each value, which estimates are computed
+    /// from, or empty while the registers are sparse, which are few enough
+    /// to count on the fly.
+    histogram: Vec<usize>,
+    /// The running estimate of [`Estimator::Martingale`], until registers
+    /// are merged.
+    martingale: Option<f64>,
+    // Only items' hashes are stored, so the sketch shouldn't inherit their
+    // auto traits: `fn(&I)` keeps it `Send` and `Sync` even when `I` isn't.
+    item_type: PhantomData<fn(&I)>,
 }
 
-impl<I> New for HyperLogLog<I> {
-    type Config = Config;
+impl<I, H> New for HyperLogLog<I, H>
+where
+    H: SeedableBuildHasher,
+{
+    type Config = Config<H>;
 
     fn new(config: &Self::Config) -> Self {
-        Self {
+        let mut sketch = Self {
             config: config.clone(),
-            registers: repeat(0).take(config.num_registers).collect(),
-            num_zero_registers: config.num_registers,
-            z_inv: config.num_registers as f64,
+            registers: Registers::new(config.layout()),
+            histogram: Vec::new(),
+            martingale: config.martingale(),
             item_type: PhantomData,
-        }
+        };
+        sketch.sync_histogram();
+        sketch
     }
 }
 
-impl<I> CardinalitySketch for HyperLogLog<I>
+impl<I, H> CardinalitySketch for HyperLogLog<I, H>
 where
     I: Hash,
+    H: SeedableBuildHasher,
 {
     type Item = I;
     type MergeError = MergeError;
 
     #[inline]
     fn insert(&mut self, item: &Self::Item) {
-        let z = Self::item_hash(&self.config.hash_builders[1], item);
-        self.insert_hash(item, z);
+        self.insert_key(item, None);
+    }
+
+    /// Reports whether a register was raised, which the estimate only
+    /// changes with.
+    #[inline]
+    fn insert_report(&mut self, item: &Self::Item) -> bool {
+        self.insert_key(item, None)
+    }
+
+    /// Sparse registers promoted by the insert take a buffer of `pool`.
+    #[inline]
+    fn insert_in(&mut self, item: &Self::Item, pool: &mut BufferPool) -> bool {
+        self.insert_key(item, Some(pool))
+    }
+
+    /// Hashes items in fixed-size batches before touching the registers, as
+    /// [`HyperLogLog::insert_batch`] does.
+    fn insert_all<'a>(&mut self, items: impl IntoIterator<Item = &'a Self::Item>)
+    where
+        Self::Item: 'a,
+    {
+        self.insert_batches(items, None);
     }
 
+    /// Sparse registers promoted by the inserts take a buffer of `pool`.
+    fn insert_all_in<'a>(
+        &mut self,
+        items: impl IntoIterator<Item = &'a Self::Item>,
+        pool: &mut BufferPool,
+    ) where
+        Self::Item: 'a,
+    {
+        self.insert_batches(items, Some(pool));
+    }
+
+    /// Prefetches the register of `item`, unless they're sparse.
+    #[inline]
+    fn prefetch(&self, item: &Self::Item) {
+        self.registers
+            .prefetch(Self::register_index(&self.config, item));
+    }
+
+    /// Sketches of more registers but otherwise the same config are folded
+    /// into this one's registers (see [`Fold`]), e.g., while a fleet rolls out
+    /// a lower precision.
     #[inline]
     fn merge(&mut self, other: &Self) -> Result<(), Self::MergeError> {
-        if self.config != other.config {
+        if !other.config.merges_into(&self.config) {
             return Err(MergeError::ConfigMismatch);
         }
+        if other.config.num_registers > self.config.num_registers {
+            let folded = fold(other.registers.non_zero(), self.config.num_registers);
+            self.merge_registers(&folded);
+            return Ok(());
+        }
 
-        self.registers
-            .iter_mut()
-            .zip(other.registers.iter())
-            .for_each(|(s, o)| *s = *o.max(s));
-        self.z_inv = self
-            .registers
-            .iter()
-            .map(|r| 2.0_f64.powi(-i32::try_from(*r).unwrap()))
-            .sum::<f64>();
-        self.num_zero_registers = self.registers.iter().filter(|&n| *n == 0).count();
+        self.merge_with(|registers, layout, histogram| {
+            registers.merge(&other.registers, layout, histogram);
+        });
         Ok(())
     }
 
     #[inline]
     fn clear(&mut self) {
-        self.registers.fill(0);
-        self.z_inv = self.config.num_registers as f64;
-        self.num_zero_registers = self.config.num_registers;
+        self.registers.clear(self.config.layout());
+        self.reset_estimate();
+    }
+
+    /// Registers that are sparse again hand their buffer to `pool`, until
+    /// they're promoted again, while the others are zeroed in place.
+    fn clear_into(&mut self, pool: &mut BufferPool) {
+        self.registers.clear_into(self.config.layout(), pool);
+        self.reset_estimate();
     }
 
     #[inline]
     fn cardinality(&self) -> u64 {
-        let mut estimate = (((self.config.num_registers * self.config.num_registers) as f64
-            * self.config.alpha)
-            / self.z_inv) as u64;
-
-        if estimate <= 5 * (self.config.num_registers as u64 >> 1) {
-            // small range correction for estimate < (5/2)d
-            if self.num_zero_registers > 0 {
-                estimate =
-                    linear_counting(self.config.num_registers, self.num_zero_registers) as u64;
-            }
+        self.estimate().cardinality
+    }
+
+    fn cardinality_with_error(&self) -> Option<Estimate> {
+        Some(self.estimate())
+    }
+
+    #[inline]
+    fn cardinality_f64(&self) -> f64 {
+        self.unrounded_estimate()
+    }
+
+    fn union_cardinality(&self, other: &Self) -> Result<u64, Self::MergeError> {
+        self.union_estimate(other)
+            .map(|estimate| estimate.cardinality)
+    }
+
+    /// Like [`HyperLogLog::intersection_estimate`], from the registers only.
+    fn intersection_cardinality(&self, other: &Self) -> Result<u64, Self::MergeError> {
+        self.intersection_estimate(other)
+            .map(|estimate| estimate.cardinality)
+    }
+}
+
+impl<I, H> PrehashedCardinalitySketch for HyperLogLog<I, H>
+where
+    I: Hash,
+    H: SeedableBuildHasher,
+{
+    #[inline]
+    fn insert_hashed(&mut self, item_hash: u64) {
+        self.insert_key(&item_hash, None);
+    }
+
+    #[inline]
+    fn insert_hashed_report(&mut self, item_hash: u64) -> bool {
+        self.insert_key(&item_hash, None)
+    }
+
+    #[inline]
+    fn prefetch_hashed(&self, item_hash: u64) {
+        self.registers
+            .prefetch(Self::register_index(&self.config, &item_hash));
+    }
+}
+
+/// Registers are indexed by the low bits of a hash, so the registers of a
+/// smaller power of two are the max of those sharing their low bits. Folded
+/// sketches are exactly those that would have been built with `config`.
+impl<I, H> Fold for HyperLogLog<I, H>
+where
+    H: SeedableBuildHasher,
+{
+    type FoldError = MergeError;
+
+    fn fold_to(&self, config: &Self::Config) -> Result<Self, Self::FoldError> {
+        if !self.config.folds_into(config) {
+            return Err(MergeError::ConfigMismatch);
         }
-        // TODO: large range correction
+        let mut folded = Self::new(config);
+        folded.merge_registers(&fold(self.registers.non_zero(), config.num_registers));
+        Ok(folded)
+    }
+}
 
-        estimate
+/// The max of the registers `(index, value)` sharing the low bits of
+/// `num_registers`, a power of two.
+fn fold(non_zero: impl Iterator<Item = (usize, u8)>, num_registers: usize) -> Vec<u8> {
+    let mut registers = vec![0; num_registers];
+    for (index, value) in non_zero {
+        let register = &mut registers[index & (num_registers - 1)];
+        *register = (*register).max(value);
     }
+    registers
 }
 
-impl<I> HyperLogLog<I> {
+impl<I, H> HyperLogLog<I, H>
+where
+    H: SeedableBuildHasher,
+{
+    #[inline]
+    fn estimate(&self) -> Estimate {
+        if let Some(martingale) = self.martingale {
+            return martingale_estimate(&self.config, martingale);
+        }
+        self.registers_estimate()
+    }
+
+    /// Like [`Self::estimate`], but before the cardinality is rounded.
+    #[inline]
+    fn unrounded_estimate(&self) -> f64 {
+        if let Some(martingale) = self.martingale {
+            return martingale;
+        }
+        let mut buffer = [0; MAX_HISTOGRAM_LEN];
+        let histogram = self.histogram(&mut buffer);
+        ertl::unrounded_estimate(&self.config, histogram)
+            .unwrap_or_else(|| unrounded_estimate(&self.config, histogram).0)
+    }
+
+    /// Keeps the histogram of registers that aren't sparse, and only them,
+    /// e.g., after they're promoted or cleared.
+    fn sync_histogram(&mut self) {
+        match (self.is_sparse(), self.histogram.is_empty()) {
+            (true, false) => self.histogram = Vec::new(),
+            (false, true) => {
+                self.histogram = zero_histogram(&self.config);
+                self.registers
+                    .count_into(self.config.num_registers, &mut self.histogram);
+            }
+            _ => {}
+        }
+    }
+
+    /// Merges into the registers with `merge`, which keeps the histogram it's
+    /// passed counting them, and drops the martingale estimate, which
+    /// doesn't survive merges.
+    fn merge_with(&mut self, merge: impl FnOnce(&mut Registers, Layout, &mut [usize])) {
+        let layout = self.config.layout();
+        self.martingale = None;
+        if !self.histogram.is_empty() {
+            merge(&mut self.registers, layout, &mut self.histogram);
+            return;
+        }
+        let mut buffer = [0; MAX_HISTOGRAM_LEN];
+        let histogram = &mut buffer[..usize::from(self.config.max_register_value()) + 1];
+        self.registers
+            .count_into(self.config.num_registers, histogram);
+        merge(&mut self.registers, layout, histogram);
+        if !self.is_sparse() {
+            self.histogram = histogram.to_vec();
+        }
+    }
+
     #[inline]
-    pub fn config(&self) -> &Config {
+    pub fn config(&self) -> &Config<H> {
         &self.config
     }
 
+    /// The number of distinct items inserted into either sketch, with the
+    /// error of the estimator it's from, like
+    /// [`CardinalitySketch::cardinality_with_error`] for the merged sketch.
+    /// The union is estimated from the register-wise max, without merging
+    /// into a copy of the sketch unless either is sparse. Fails unless the
+    /// configs are the same.
+    pub fn union_estimate(&self, other: &Self) -> Result<Estimate, MergeError> {
+        if self.config != other.config {
+            return Err(MergeError::ConfigMismatch);
+        }
+        let (Registers::Dense(registers), Registers::Dense(other_registers)) =
+            (&self.registers, &other.registers)
+        else {
+            let mut union = self.registers.clone();
+            let mut buffer = [0; MAX_HISTOGRAM_LEN];
+            let histogram = &mut buffer[..usize::from(self.config.max_register_value()) + 1];
+            histogram.copy_from_slice(self.histogram(&mut [0; MAX_HISTOGRAM_LEN]));
+            union.merge(&other.registers, self.config.layout(), histogram);
+            return Ok(self.estimate_histogram(histogram));
+        };
+        let mut buffer = [0; MAX_HISTOGRAM_LEN];
+        let histogram = &mut buffer[..usize::from(self.config.max_register_value()) + 1];
+        histogram::count(
+            histogram,
+            registers
+                .iter()
+                .zip(other_registers)
+                .map(|(s, o)| *s.max(o)),
+        );
+        Ok(self.estimate_histogram(histogram))
+    }
+
+    /// The estimate of registers of `histogram`, e.g., of the union of two
+    /// sketches, which the martingale estimate doesn't survive.
+    fn estimate_histogram(&self, histogram: &[usize]) -> Estimate {
+        ertl::estimate(&self.config, histogram).unwrap_or_else(|| estimate(&self.config, histogram))
+    }
+
+    /// The estimate of the registers, ignoring the running estimate of
+    /// [`Estimator::Martingale`].
+    fn registers_estimate(&self) -> Estimate {
+        let mut buffer = [0; MAX_HISTOGRAM_LEN];
+        self.estimate_histogram(self.histogram(&mut buffer))
+    }
+
+    /// The number of distinct items inserted into both sketches, by
+    /// inclusion-exclusion: `|A| + |B| - |A ∪ B|`, e.g., the overlap of two
+    /// labels' counters.
+    ///
+    /// The three estimates are about `1.04 / sqrt(m)` off each, relative to
+    /// their own cardinality, so the standard error of the intersection is
+    /// taken as the root sum of their squared standard errors. That's
+    /// relative to the larger sets rather than to the intersection: over `m`
+    /// registers, intersections below a few `sqrt(m)`-th of the union are
+    /// mostly noise, and the error is infinite for an empty intersection.
+    /// Fails unless the configs are the same.
+    ///
+    /// All three are estimated from the histograms of the registers, as the
+    /// union is, rather than from the running estimates of
+    /// [`Estimator::Martingale`], which the union has none of: mixing them
+    /// would leave a sketch's intersection with itself off its estimate.
+    pub fn intersection_estimate(&self, other: &Self) -> Result<Estimate, MergeError> {
+        let union = self.union_estimate(other)?;
+        let (a, b) = (self.registers_estimate(), other.registers_estimate());
+        let cardinality = a
+            .cardinality
+            .saturating_add(b.cardinality)
+            .saturating_sub(union.cardinality);
+        let standard_error = [a, b, union]
+            .iter()
+            .map(|e| (e.cardinality as f64 * e.relative_standard_error).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        Ok(Estimate {
+            cardinality,
+            relative_standard_error: if cardinality == 0 {
+                f64::INFINITY
+            } else {
+                standard_error / cardinality as f64
+            },
+        })
+    }
+
+    /// A sketch of raw `registers`, e.g., dumped by another system using the
+    /// same hash functions, which must be as many as the config's and at most
+    /// [`Config::max_register_value`]. Like after a merge, the sketch
+    /// estimates like the original estimator if configured with
+    /// [`Estimator::Martingale`].
+    pub fn from_registers(
+        config: &Config<H>,
+        registers: Vec<u8>,
+    ) -> Result<Self, InvariantViolation> {
+        if registers.len() != config.num_registers {
+            return Err(InvariantViolation::NumRegistersMismatch);
+        }
+        let max_register_value = config.max_register_value();
+        if registers.iter().any(|&r| r > max_register_value) {
+            return Err(InvariantViolation::RegisterOutOfRange);
+        }
+        let mut sketch = Self::new(config);
+        sketch.merge_with(|zeros, layout, histogram| {
+            *zeros = Registers::from_dense(registers, layout, histogram);
+        });
+        Ok(sketch)
+    }
+
+    /// Merges raw registers, e.g., exported by another system using the same
+    /// hash functions. Registers above the maximum value for the configured
+    /// hash and register widths saturate at it.
+    pub fn merge_raw_registers(&mut self, registers: &[u8]) -> Result<(), MergeError> {
+        if registers.len() != self.config.num_registers {
+            return Err(MergeError::ConfigMismatch);
+        }
+        let max_register_value = self.config.max_register_value();
+        let saturated = registers
+            .iter()
+            .map(|&r| r.min(max_register_value))
+            .collect::<Vec<_>>();
+        self.merge_registers(&saturated);
+        Ok(())
+    }
+
+    /// Inserts an item by a uniform 64-bit hash of it, e.g., computed once
+    /// upstream for other uses too, without hashing it again: the low bits of
+    /// `hash` index the register, and the trailing zeros of the bits above
+    /// them, up to the hash width, give its value. This mapping doesn't depend
+    /// on the config's seeds and won't change, but differs from
+    /// [`CardinalitySketch::insert`]'s, so a sketch should only be fed either
+    /// way.
+    #[inline]
+    pub fn insert_raw(&mut self, hash: u64) {
+        // At least 2^4 registers, so fewer than 64 bits are left for rho.
+        let index_bits = self.config.num_registers.trailing_zeros();
+        let rho_bits = (64 - index_bits).min(match self.config.hash_width {
+            HashWidth::Bits32 => 32,
+            HashWidth::Bits64 => 64,
+        });
+        let r = hash as usize & (self.config.num_registers - 1);
+        // At most 64 trailing zeros, so this cannot truncate.
+        let z = ((hash >> index_bits) | 1 << rho_bits).trailing_zeros() as u8 + 1;
+        self.update_register(r, z.min(self.config.max_register_value()), None);
+    }
+
+    /// Takes the register-wise max with `registers`, which must have the same
+    /// length as the sketch's and be in range for the hash width.
     #[inline]
-    fn item_hash(hash_builder: &RandomState, item: &I) -> u8
+    fn merge_registers(&mut self, registers: &[u8]) {
+        debug_assert_eq!(self.config.num_registers, registers.len());
+        let max_register_value = self.config.max_register_value();
+        if max_register_value < self.config.hash_width.max_register_value()
+            && registers.iter().any(|&r| r > max_register_value)
+        {
+            // Registers of other systems, e.g., Redis', saturate at the
+            // register width.
+            let saturated = registers
+                .iter()
+                .map(|&r| r.min(max_register_value))
+                .collect::<Vec<_>>();
+            self.merge_with(|registers, layout, histogram| {
+                registers.merge_dense(&saturated, layout, histogram);
+            });
+        } else {
+            self.merge_with(|zeros, layout, histogram| {
+                zeros.merge_dense(registers, layout, histogram);
+            });
+        }
+    }
+
+    /// Resets the histogram behind the estimate to that of zero registers.
+    fn reset_estimate(&mut self) {
+        if self.histogram.is_empty() {
+            self.sync_histogram();
+        } else if self.is_sparse() {
+            self.histogram = Vec::new();
+        } else {
+            self.histogram.fill(0);
+            self.histogram[0] = self.config.num_registers;
+        }
+        self.martingale = self.config.martingale();
+    }
+
+    /// Inserts `items` in fixed-size batches: the items of a batch are all
+    /// hashed and their registers prefetched before any of them is updated,
+    /// so that the hashing and the cache misses of a batch overlap, e.g., for
+    /// the bursts of items of a label that SSSS feeds its counters.
+    pub fn insert_batch(&mut self, items: &[I])
     where
         I: Hash,
     {
-        u8::try_from(hash_builder.hash_one(item).trailing_zeros()).unwrap() + 1
+        let mut batch = [(0, 0); INSERT_BATCH_SIZE];
+        for chunk in items.chunks(INSERT_BATCH_SIZE) {
+            for (slot, item) in batch.iter_mut().zip(chunk) {
+                *slot = self.locate(item);
+            }
+            self.update_registers(&batch[..chunk.len()], None);
+        }
     }
 
+    /// The register index and value of `item`, whose register is prefetched
+    /// for [`HyperLogLog::update_registers`].
     #[inline]
-    fn insert_hash(&mut self, item: &I, z: u8)
-    where
-        I: Hash,
+    fn locate(&self, item: &impl Hash) -> (usize, u8) {
+        let r = Self::register_index(&self.config, item);
+        self.registers.prefetch(r);
+        (r, Self::item_hash(&self.config, item))
+    }
+
+    /// Inserts `items` in batches like [`HyperLogLog::insert_batch`], into
+    /// a buffer of `pool` if they promote sparse registers.
+    fn insert_batches<'a>(
+        &mut self,
+        items: impl IntoIterator<Item = &'a I>,
+        mut pool: Option<&mut BufferPool>,
+    ) where
+        I: Hash + 'a,
     {
-        let r: usize =
-            self.config.hash_builders[0].hash_one(item) as usize & (self.config.num_registers - 1);
-        let register = self.registers.get_mut(r).unwrap();
-        if z > *register {
-            if *register == 0 {
-                self.num_zero_registers -= 1;
+        let mut batch = [(0, 0); INSERT_BATCH_SIZE];
+        let mut items = items.into_iter();
+        loop {
+            let mut len = 0;
+            for (slot, item) in batch.iter_mut().zip(items.by_ref()) {
+                *slot = self.locate(item);
+                len += 1;
+            }
+            self.update_registers(&batch[..len], pool.as_deref_mut());
+            if len < INSERT_BATCH_SIZE {
+                break;
+            }
+        }
+    }
+
+    #[inline]
+    fn update_registers(&mut self, batch: &[(usize, u8)], mut pool: Option<&mut BufferPool>) {
+        for &(r, z) in batch {
+            self.update_register(r, z, pool.as_deref_mut());
+        }
+    }
+
+    /// Updates the register of `key`, which is either an item or the hash of
+    /// one, and returns whether it was raised.
+    #[inline]
+    fn insert_key(&mut self, key: &impl Hash, pool: Option<&mut BufferPool>) -> bool {
+        let z = Self::item_hash(&self.config, key);
+        let r = Self::register_index(&self.config, key);
+        self.update_register(r, z, pool)
+    }
+
+    #[inline]
+    fn item_hash(config: &Config<H>, item: &impl Hash) -> u8 {
+        let hash = config.hash_builders[1].hash_one(item);
+        // A hash has at most 64 trailing zeros, so this cannot truncate.
+        let z = match config.hash_width {
+            HashWidth::Bits32 => (hash as u32).trailing_zeros() as u8 + 1,
+            HashWidth::Bits64 => hash.trailing_zeros() as u8 + 1,
+        };
+        z.min(config.max_register_value())
+    }
+
+    #[inline]
+    fn register_index(config: &Config<H>, item: &impl Hash) -> usize {
+        config.hash_builders[0].hash_one(item) as usize & (config.num_registers - 1)
+    }
+
+    /// Raises register `r` to `z` if that's higher, and returns whether it
+    /// did. Sparse registers promoted by the update take a buffer of `pool`.
+    #[inline]
+    fn update_register(&mut self, r: usize, z: u8, pool: Option<&mut BufferPool>) -> bool {
+        // `r` is masked by `num_registers - 1`, so it is always in bounds.
+        let Some(previous) = self.registers.update(r, z, self.config.layout(), pool) else {
+            return false;
+        };
+        if self.histogram.is_empty() {
+            // Counted if the update promoted sparse registers.
+            self.sync_histogram();
+        } else {
+            histogram::raise(&mut self.histogram, previous, z);
+        }
+        if let Some(martingale) = self.martingale {
+            // The probability that an item raised a register was the
+            // chance of landing in one times that of exceeding it, from
+            // the registers before the update.
+            let mut buffer = [0; MAX_HISTOGRAM_LEN];
+            let z_inv = histogram::z_inv(self.histogram(&mut buffer))
+                + 2.0_f64.powi(-i32::from(previous))
+                - 2.0_f64.powi(-i32::from(z));
+            self.martingale = Some(martingale + self.config.num_registers as f64 / z_inv);
+        }
+        true
+    }
+}
+
+impl<I, H> HyperLogLog<I, H> {
+    /// The registers, e.g., to export them to another system using the same
+    /// hash functions. Sparse registers are expanded into a new buffer.
+    #[inline]
+    pub fn registers(&self) -> Cow<'_, [u8]> {
+        self.registers.to_dense(self.config.num_registers)
+    }
+
+    /// The raw estimate of the registers, before any correction of small or
+    /// large ranges, e.g., to measure the bias of the estimator.
+    pub fn raw_cardinality(&self) -> f64 {
+        let mut buffer = [0; MAX_HISTOGRAM_LEN];
+        raw_estimate(&self.config, histogram::z_inv(self.histogram(&mut buffer)))
+    }
+
+    /// Whether the registers are still sparse (see
+    /// [`Config::with_sparse_registers`]).
+    pub fn is_sparse(&self) -> bool {
+        matches!(self.registers, Registers::Sparse(_))
+    }
+
+    /// The histogram of the registers, counted into `buffer` if they're
+    /// sparse.
+    #[inline]
+    fn histogram<'a>(&'a self, buffer: &'a mut [usize; MAX_HISTOGRAM_LEN]) -> &'a [usize] {
+        if !self.histogram.is_empty() {
+            return &self.histogram;
+        }
+        let histogram = &mut buffer[..usize::from(self.config.max_register_value()) + 1];
+        self.registers
+            .count_into(self.config.num_registers, histogram);
+        histogram
+    }
+}
+
+/// Estimates the cardinality of registers of `histogram`, along with the
+/// relative standard error of the estimator it's from: linear counting for
+/// small ranges, and `1.04 / sqrt(m)` otherwise.
+#[inline]
+fn estimate<H>(config: &Config<H>, histogram: &[usize]) -> Estimate {
+    let (estimate, relative_standard_error) = unrounded_estimate(config, histogram);
+    Estimate {
+        // Truncated once, at the end. Float to int casts saturate.
+        cardinality: estimate as u64,
+        relative_standard_error,
+    }
+}
+
+/// Like [`estimate`], but before the cardinality is truncated.
+#[inline]
+fn unrounded_estimate<H>(config: &Config<H>, histogram: &[usize]) -> (f64, f64) {
+    let (z_inv, num_zero_registers) = (histogram::z_inv(histogram), histogram[0]);
+    let raw_estimate = raw_estimate(config, z_inv);
+    let mut estimate = raw_estimate;
+    let mut relative_standard_error = 1.04 / (config.num_registers as f64).sqrt();
+
+    let bias = match config.estimator {
+        // Ertl's estimators don't correct the raw estimate (see `ertl`), and
+        // the martingale one falls back to the original one.
+        Estimator::Original
+        | Estimator::Improved
+        | Estimator::MaximumLikelihood
+        | Estimator::Martingale
+        | Estimator::LogLogBeta => None,
+        Estimator::BiasCorrected => bias::bias(config.num_registers, raw_estimate),
+    };
+    let beta_estimate = match config.estimator {
+        Estimator::LogLogBeta => loglog_beta::estimate(
+            config.num_registers,
+            config.alpha,
+            z_inv,
+            num_zero_registers,
+        ),
+        _ => None,
+    };
+    if let Some(beta_estimate) = beta_estimate {
+        // Beta corrects small and mid ranges.
+        estimate = beta_estimate.max(0.0);
+    } else if let Some(bias) = bias {
+        // mid range correction, unless linear counting is more accurate
+        estimate = (raw_estimate - bias).max(0.0);
+        if num_zero_registers > 0 {
+            let linear_estimate = linear_counting(config.num_registers, num_zero_registers);
+            if bias::linear_counting_threshold(config.num_registers)
+                .is_some_and(|threshold| linear_estimate <= threshold)
+            {
+                estimate = linear_estimate;
+                relative_standard_error =
+                    linear_counting_error(config.num_registers, estimate as u64);
             }
-            self.z_inv -= 2.0_f64.powi(-i32::try_from(*register).unwrap());
-            self.z_inv += 2.0_f64.powi(-i32::try_from(z).unwrap());
-            *register = z;
         }
+    } else if estimate <= 2.5 * config.num_registers as f64 {
+        // small range correction for estimate < (5/2)d
+        if num_zero_registers > 0 {
+            estimate = linear_counting(config.num_registers, num_zero_registers);
+            relative_standard_error = linear_counting_error(config.num_registers, estimate as u64);
+        }
+    }
+    if config.hash_width == HashWidth::Bits32 && estimate > TWO_POW_32 / 30.0 {
+        // large range correction for hash collisions
+        let ratio = (1.0 - estimate / TWO_POW_32).max(f64::MIN_POSITIVE);
+        estimate = -TWO_POW_32 * ratio.ln();
+    }
+    // 64-bit hashes need no large range correction: items are indexed by
+    // another hash, so registers only saturate around `m * 2^64` items, and
+    // estimates saturate at `u64::MAX` well before.
+
+    (estimate, relative_standard_error)
+}
+
+/// The running estimate of [`Estimator::Martingale`], whose relative
+/// standard error is about `sqrt(ln(2) / m)`, i.e., `0.83 / sqrt(m)`.
+#[inline]
+fn martingale_estimate<H>(config: &Config<H>, martingale: f64) -> Estimate {
+    Estimate {
+        // Float to int casts saturate.
+        cardinality: martingale.round() as u64,
+        relative_standard_error: (LN_2 / config.num_registers as f64).sqrt(),
     }
 }
 
+/// The histogram of zero registers.
+fn zero_histogram<H>(config: &Config<H>) -> Vec<usize> {
+    histogram::zeros(config.num_registers, config.max_register_value())
+}
+
+/// The raw HyperLogLog estimate of registers summing to `z_inv`.
+#[inline]
+fn raw_estimate<H>(config: &Config<H>, z_inv: f64) -> f64 {
+    let num_registers = config.num_registers as f64;
+    num_registers * num_registers * config.alpha / z_inv
+}
+
+impl<I, H> Validate for HyperLogLog<I, H> {
+    type Violation = InvariantViolation;
+
+    fn debug_validate(&self) -> Result<(), Self::Violation> {
+        self.registers.validate_layout(self.config.num_registers)?;
+        let max_register_value = self.config.max_register_value();
+        if self
+            .registers
+            .non_zero()
+            .any(|(_, r)| r > max_register_value)
+        {
+            return Err(InvariantViolation::RegisterOutOfRange);
+        }
+        let mut histogram = Vec::new();
+        if !self.is_sparse() {
+            histogram = zero_histogram(&self.config);
+            self.registers
+                .count_into(self.config.num_registers, &mut histogram);
+        }
+        if histogram != self.histogram {
+            return Err(InvariantViolation::HistogramMismatch);
+        }
+        Ok(())
+    }
+}
+
+impl<I, H> MemorySize for HyperLogLog<I, H> {
+    fn heap_size(&self) -> usize {
+        self.config.heap_size() + self.registers.heap_size() + self.histogram.heap_size()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum InvariantViolation {
+    NumRegistersMismatch,
+    MalformedSparseRegisters,
+    RegisterOutOfRange,
+    HistogramMismatch,
+    BloomFilterSizeMismatch,
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvariantViolation::NumRegistersMismatch => {
+                write!(f, "the number of registers does not match the config")
+            }
+            InvariantViolation::MalformedSparseRegisters => {
+                write!(f, "sparse registers are unsorted, out of range or zero")
+            }
+            InvariantViolation::RegisterOutOfRange => {
+                write!(
+                    f,
+                    "a register exceeds the maximum for the hash and register widths"
+                )
+            }
+            InvariantViolation::HistogramMismatch => {
+                write!(
+                    f,
+                    "the register histogram is inconsistent with the registers"
+                )
+            }
+            InvariantViolation::BloomFilterSizeMismatch => {
+                write!(f, "the Bloom filter size does not match the config")
+            }
+        }
+    }
+}
+
+impl error::Error for InvariantViolation {}
+
 #[derive(Clone, Debug)]
 pub enum MergeError {
     ConfigMismatch,
+    Decode(DecodeError),
 }
 
 impl fmt::Display for MergeError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             MergeError::ConfigMismatch => write!(f, "sketch configs do not match"),
+            MergeError::Decode(e) => write!(f, "failed to decode sketch: {}", e),
         }
     }
 }
 
+impl From<DecodeError> for MergeError {
+    fn from(e: DecodeError) -> Self {
+        MergeError::Decode(e)
+    }
+}
+
 impl error::Error for MergeError {}
 
 #[cfg(test)]
@@ -209,7 +928,7 @@ mod tests {
     }
 
     fn merge_diff() -> impl Strategy<Value = TestCase> {
-        let test_dims = vec![100, 6_000, 10_000_000];
+        let test_dims = [100, 6_000, 10_000_000];
 
         let mut sketch = HyperLogLog::new(&seeded_config());
         let mut sketch2 = HyperLogLog::new(&seeded_config());
@@ -237,6 +956,22 @@ mod tests {
         })
     }
 
+    #[test]
+    fn error_intervals_cover_the_cardinality() {
+        let mut sketch = HyperLogLog::new(&seeded_config());
+        let estimate = sketch.cardinality_with_error().unwrap();
+        assert_eq!(estimate.interval(3.0), 0..=0);
+        let mut inserted = 0;
+        for cardinality in [100, 2_000, 100_000] {
+            (inserted..cardinality).for_each(|item: u64| sketch.insert(&item));
+            inserted = cardinality;
+            let estimate = sketch.cardinality_with_error().unwrap();
+            assert_eq!(estimate.cardinality, sketch.cardinality());
+            assert!((0.0..0.05).contains(&estimate.relative_standard_error));
+            assert!(estimate.interval(3.0).contains(&cardinality));
+        }
+    }
+
     #[test]
     fn it_estimates_cardinality_after_merging_same() {
         proptest!(ProptestConfig::with_cases(16), |(test_case in merge_same())| {
@@ -255,6 +990,314 @@ mod tests {
         })
     }
 
+    #[test]
+    fn it_validates_invariants() {
+        let mut sketch = HyperLogLog::new(&seeded_config());
+        assert!(sketch.debug_validate().is_ok());
+        (0..100_000_u64).for_each(|i| sketch.insert(&i));
+        assert!(sketch.debug_validate().is_ok());
+        assert!(sketch.merge(&sketch.clone()).is_ok());
+        assert!(sketch.debug_validate().is_ok());
+
+        let mut corrupted = sketch.clone();
+        corrupted.histogram[1] += 1;
+        assert!(matches!(
+            corrupted.debug_validate(),
+            Err(InvariantViolation::HistogramMismatch)
+        ));
+        let mut corrupted = sketch.clone();
+        corrupted.registers.as_dense_mut()[0] = MAX_REGISTER_VALUE + 1;
+        assert!(matches!(
+            corrupted.debug_validate(),
+            Err(InvariantViolation::RegisterOutOfRange)
+        ));
+    }
+
+    #[test]
+    fn insert_all_matches_insert() {
+        let mut sketch = HyperLogLog::new(&seeded_config());
+        let mut bulk = HyperLogLog::new(&seeded_config());
+        let items = (0..10_000_u64).collect::<Vec<_>>();
+        items.iter().for_each(|item| sketch.insert(item));
+        bulk.insert_all(&items);
+        assert_eq!(sketch.registers(), bulk.registers());
+        assert_eq!(sketch.cardinality(), bulk.cardinality());
+        assert!(bulk.debug_validate().is_ok());
+    }
+
+    #[test]
+    fn insert_batch_matches_insert() {
+        let items = (0..10_000_u64).collect::<Vec<_>>();
+        for config in [
+            seeded_config(),
+            seeded_config().with_sparse_registers(false),
+            seeded_config().with_packed_registers(true),
+        ] {
+            let mut sketch = HyperLogLog::new(&config);
+            let mut batched = HyperLogLog::new(&config);
+            items.iter().for_each(|item| sketch.insert(item));
+            batched.insert_batch(&items[..10]);
+            batched.insert_batch(&items[10..]);
+            assert_eq!(sketch.registers(), batched.registers());
+            assert_eq!(sketch.cardinality(), batched.cardinality());
+            assert!(batched.debug_validate().is_ok());
+        }
+    }
+
+    #[test]
+    fn it_builds_sketches_from_registers() {
+        for config in [
+            seeded_config(),
+            seeded_config().with_sparse_registers(false),
+            seeded_config().with_packed_registers(true),
+        ] {
+            for num_items in [10_u64, 10_000] {
+                let mut sketch = HyperLogLog::new(&config);
+                (0..num_items).for_each(|i| sketch.insert(&i));
+                let rebuilt =
+                    HyperLogLog::<u64>::from_registers(&config, sketch.registers().into_owned())
+                        .unwrap();
+                assert_eq!(rebuilt.registers(), sketch.registers());
+                assert_eq!(rebuilt.is_sparse(), sketch.is_sparse());
+                assert_eq!(rebuilt.cardinality(), sketch.cardinality());
+                assert!(rebuilt.debug_validate().is_ok());
+            }
+        }
+
+        let config = seeded_config();
+        assert!(matches!(
+            HyperLogLog::<u64>::from_registers(&config, vec![0; COUNTER_SIZE - 1]),
+            Err(InvariantViolation::NumRegistersMismatch)
+        ));
+        let mut registers = vec![0; COUNTER_SIZE];
+        registers[0] = MAX_REGISTER_VALUE + 1;
+        assert!(matches!(
+            HyperLogLog::<u64>::from_registers(&config, registers),
+            Err(InvariantViolation::RegisterOutOfRange)
+        ));
+    }
+
+    #[test]
+    fn inserts_report_whether_a_register_was_raised() {
+        for config in [seeded_config(), seeded_config().with_packed_registers(true)] {
+            let mut sketch = HyperLogLog::new(&config);
+            let mut reported = HyperLogLog::new(&config);
+            for i in 0..10_000_u64 {
+                let registers = sketch.registers().into_owned();
+                sketch.insert(&i);
+                let changed = sketch.registers()[..] != registers[..];
+                assert_eq!(reported.insert_report(&i), changed);
+                assert!(!reported.insert_report(&i));
+            }
+            assert_eq!(reported.registers(), sketch.registers());
+            assert_eq!(reported.cardinality(), sketch.cardinality());
+        }
+
+        let mut hashed = HyperLogLog::<String>::new(&seeded_config());
+        assert!(hashed.insert_hashed_report(0));
+        assert!(!hashed.insert_hashed_report(0));
+    }
+
+    #[test]
+    fn hashes_are_inserted_like_u64_items() {
+        let mut hashed: HyperLogLog<String> = HyperLogLog::new(&seeded_config());
+        let mut items: HyperLogLog<u64> = HyperLogLog::new(&seeded_config());
+        for i in 0..10_000_u64 {
+            hashed.insert_hashed(i);
+            items.insert(&i);
+        }
+        assert_eq!(hashed.registers(), items.registers());
+        assert_eq!(hashed.cardinality(), items.cardinality());
+    }
+
+    #[test]
+    fn it_estimates_overlaps() {
+        let mut a = HyperLogLog::new(&seeded_config());
+        let mut b = HyperLogLog::new(&seeded_config());
+        (0..30_000_u64).for_each(|i| a.insert(&i));
+        (10_000..40_000_u64).for_each(|i| b.insert(&i));
+
+        let mut merged = a.clone();
+        merged.merge(&b).unwrap();
+        assert_eq!(a.union_cardinality(&b).unwrap(), merged.cardinality());
+        let intersection = a.intersection_cardinality(&b).unwrap() as f64;
+        assert!((intersection - 20_000.0).abs() / 20_000.0 < 0.2);
+        assert!((a.jaccard(&b).unwrap() - 0.5).abs() < 0.1);
+        assert_eq!(a.jaccard(&a).unwrap(), 1.0);
+
+        let empty = HyperLogLog::<u64>::new(&seeded_config());
+        assert_eq!(empty.jaccard(&empty).unwrap(), 0.0);
+        let other = HyperLogLog::new(&Config::new(16, Some([7; 8])).unwrap());
+        assert!(a.union_cardinality(&other).is_err());
+        assert!(a.intersection_estimate(&other).is_err());
+    }
+
+    #[test]
+    fn overlap_estimates_come_with_their_errors() {
+        let mut a = HyperLogLog::new(&seeded_config());
+        let mut b = HyperLogLog::new(&seeded_config());
+        (0..30_000_u64).for_each(|i| a.insert(&i));
+        (10_000..40_000_u64).for_each(|i| b.insert(&i));
+
+        let union = a.union_estimate(&b).unwrap();
+        assert_eq!(union.cardinality, a.union_cardinality(&b).unwrap());
+        assert_eq!(
+            union.relative_standard_error,
+            1.04 / (COUNTER_SIZE as f64).sqrt()
+        );
+        let intersection = a.intersection_estimate(&b).unwrap();
+        assert_eq!(
+            intersection.cardinality,
+            a.intersection_cardinality(&b).unwrap()
+        );
+        // The errors of the three estimates, of 30,000 to 40,000 items, make
+        // for a larger relative error of the 20,000 shared ones.
+        assert!(intersection.relative_standard_error > 2.0 * union.relative_standard_error);
+        assert!(intersection.interval(3.0).contains(&20_000));
+
+        let empty = HyperLogLog::<u64>::new(&seeded_config());
+        let disjoint = empty.intersection_estimate(&a).unwrap();
+        assert_eq!(disjoint.cardinality, 0);
+        assert_eq!(disjoint.relative_standard_error, f64::INFINITY);
+    }
+
+    #[test]
+    fn martingale_sketches_intersect_with_themselves_to_their_registers_estimate() {
+        let config = seeded_config().with_estimator(Estimator::Martingale);
+        let mut sketch = HyperLogLog::new(&config);
+        (0..30_000_u64).for_each(|i| sketch.insert(&i));
+        let union = sketch.union_estimate(&sketch).unwrap();
+        let intersection = sketch.intersection_estimate(&sketch).unwrap();
+        assert_eq!(intersection.cardinality, union.cardinality);
+        assert_eq!(
+            sketch.intersection_cardinality(&sketch).unwrap(),
+            union.cardinality
+        );
+        // The running estimate differs from that of the registers.
+        assert_ne!(sketch.cardinality(), union.cardinality);
+    }
+
+    #[test]
+    fn folded_sketches_match_smaller_ones() {
+        let seeds = [0, 1, 2, 3, 4, 5, 6, 7];
+        let large_config = Config::new(2048, Some(seeds)).unwrap();
+        let small_config = Config::new(512, Some(seeds)).unwrap();
+        let mut large = HyperLogLog::new(&large_config);
+        let mut small = HyperLogLog::new(&small_config);
+        for i in 0..50_000_u64 {
+            large.insert(&i);
+            small.insert(&i);
+        }
+
+        let folded = large.fold_to(&small_config).unwrap();
+        assert_eq!(folded.registers(), small.registers());
+        assert_eq!(folded.cardinality(), small.cardinality());
+        assert!(folded.debug_validate().is_ok());
+        assert!(small.fold_to(&large_config).is_err());
+        let other_seeds = Config::new(512, Some([7; 8])).unwrap();
+        assert!(large.fold_to(&other_seeds).is_err());
+    }
+
+    #[test]
+    fn it_merges_sketches_of_more_registers_by_folding() {
+        let seeds = [0, 1, 2, 3, 4, 5, 6, 7];
+        let large_config = Config::new(2048, Some(seeds)).unwrap();
+        let small_config = Config::new(512, Some(seeds)).unwrap();
+        let mut large = HyperLogLog::new(&large_config);
+        let mut small = HyperLogLog::new(&small_config);
+        let mut expected = HyperLogLog::new(&small_config);
+        for i in 0..50_000_u64 {
+            if i % 2 == 0 {
+                large.insert(&i);
+            } else {
+                small.insert(&i);
+            }
+            expected.insert(&i);
+        }
+
+        assert!(large.clone().merge(&small).is_err());
+        assert!(small.merge(&large).is_ok());
+        assert_eq!(small.registers(), expected.registers());
+        assert_eq!(small.cardinality(), expected.cardinality());
+        assert!(small.debug_validate().is_ok());
+
+        let other_estimator = HyperLogLog::new(
+            &large_config
+                .clone()
+                .with_estimator(Estimator::MaximumLikelihood),
+        );
+        assert!(small.merge(&other_estimator).is_err());
+        let other_seeds = HyperLogLog::new(&Config::new(2048, Some([7; 8])).unwrap());
+        assert!(small.merge(&other_seeds).is_err());
+    }
+
+    #[test]
+    fn it_supports_32_bit_hashes() {
+        let config = seeded_config().with_hash_width(HashWidth::Bits32);
+        let mut sketch = HyperLogLog::new(&config);
+        (0..100_000_u64).for_each(|i| sketch.insert(&i));
+        assert!(sketch.registers().iter().all(|&r| r <= 33));
+        assert!((sketch.cardinality() as f64 - 1e5).abs() / 1e5 <= 5e-2);
+        assert!(sketch.debug_validate().is_ok());
+
+        // Registers can only be merged with matching hash widths.
+        assert!(matches!(
+            HyperLogLog::new(&seeded_config()).merge(&sketch),
+            Err(MergeError::ConfigMismatch)
+        ));
+
+        // Legacy registers saturate at the maximum value.
+        let mut legacy = vec![0; COUNTER_SIZE];
+        legacy[0] = 40;
+        assert!(sketch.merge_raw_registers(&legacy).is_ok());
+        assert_eq!(sketch.registers()[0], 33);
+        assert!(sketch.debug_validate().is_ok());
+        assert!(matches!(
+            sketch.merge_raw_registers(&legacy[1..]),
+            Err(MergeError::ConfigMismatch)
+        ));
+    }
+
+    /// SipHash, keyed by writing the seeds first.
+    #[derive(Clone, Debug)]
+    struct SipBuildHasher([u64; 4]);
+
+    impl core::hash::BuildHasher for SipBuildHasher {
+        type Hasher = std::hash::DefaultHasher;
+
+        fn build_hasher(&self) -> Self::Hasher {
+            let mut hasher = std::hash::DefaultHasher::new();
+            self.0.hash(&mut hasher);
+            hasher
+        }
+    }
+
+    impl SeedableBuildHasher for SipBuildHasher {
+        fn with_seeds(seeds: [u64; 4]) -> Self {
+            Self(seeds)
+        }
+    }
+
+    #[test]
+    fn it_hashes_with_custom_hashers() {
+        use sketch_traits::codec::{Decode, Encode};
+
+        let config = Config::<SipBuildHasher>::with_hasher(COUNTER_SIZE, Some(SEEDS)).unwrap();
+        let mut sketch = HyperLogLog::new(&config);
+        (0..100_000_u64).for_each(|i| sketch.insert(&i));
+        assert!((sketch.cardinality() as f64 - 1e5).abs() / 1e5 <= 5e-2);
+
+        let mut default = HyperLogLog::new(&seeded_config());
+        (0..100_000_u64).for_each(|i| default.insert(&i));
+        assert_ne!(sketch.registers(), default.registers());
+
+        let mut buf = Vec::new();
+        sketch.encode(&mut buf);
+        let decoded = HyperLogLog::<u64, SipBuildHasher>::decode(&mut &buf[..]).unwrap();
+        assert_eq!(decoded.config(), sketch.config());
+        assert_eq!(decoded.registers(), sketch.registers());
+    }
+
     #[test]
     fn merge_into_empty() {
         let mut sketch = HyperLogLog::new(&seeded_config());
@@ -269,4 +1312,441 @@ mod tests {
             (cardinality as f64 - sketch.cardinality() as f64).abs() / cardinality as f64 <= 5e-2
         );
     }
+
+    const MERGE_ORDERS: [[usize; 3]; 6] = [
+        [0, 1, 2],
+        [0, 2, 1],
+        [1, 0, 2],
+        [1, 2, 0],
+        [2, 0, 1],
+        [2, 1, 0],
+    ];
+
+    #[test]
+    fn merge_order_does_not_matter() {
+        proptest!(|(items in proptest::collection::vec(proptest::collection::vec(any::<u64>(), 0..500), 3))| {
+            let sketches = items
+                .iter()
+                .map(|items| {
+                    let mut sketch = HyperLogLog::new(&seeded_config());
+                    sketch.insert_all(items);
+                    sketch
+                })
+                .collect::<Vec<_>>();
+            let merged = MERGE_ORDERS.map(|order| {
+                let mut merged = sketches[order[0]].clone();
+                order[1..].iter().for_each(|&i| merged.merge(&sketches[i]).unwrap());
+                merged
+            });
+            for sketch in merged.iter() {
+                prop_assert_eq!(sketch.registers(), merged[0].registers());
+                prop_assert_eq!(sketch.cardinality(), merged[0].cardinality());
+            }
+        });
+    }
+
+    #[test]
+    fn sparse_and_packed_sketches_match_dense_ones() {
+        let dense_config = seeded_config().with_sparse_registers(false);
+        let configs = [
+            seeded_config(),
+            seeded_config().with_packed_registers(true),
+            dense_config.clone().with_packed_registers(true),
+        ];
+        let sketch = |config: &Config, items: &[u64]| {
+            let mut sketch = HyperLogLog::new(config);
+            sketch.insert_all(items);
+            sketch
+        };
+        proptest!(|(left in proptest::collection::vec(any::<u64>(), 0..300), right in proptest::collection::vec(any::<u64>(), 0..300))| {
+            let dense = [sketch(&dense_config, &left), sketch(&dense_config, &right)];
+            let mut expected = dense[0].clone();
+            expected.merge(&dense[1]).unwrap();
+            for config in &configs {
+                let other = [sketch(config, &left), sketch(config, &right)];
+                for (o, d) in other.iter().zip(&dense) {
+                    prop_assert!(o.debug_validate().is_ok());
+                    prop_assert_eq!(o.registers(), d.registers());
+                    prop_assert_eq!(o.cardinality(), d.cardinality());
+                }
+                for (into, from) in [(&other[0], &other[1]), (&other[0], &dense[1]), (&dense[0], &other[1])] {
+                    let mut merged = into.clone();
+                    merged.merge(from).unwrap();
+                    prop_assert!(merged.debug_validate().is_ok());
+                    prop_assert_eq!(merged.registers(), expected.registers());
+                    prop_assert_eq!(merged.cardinality(), expected.cardinality());
+                    prop_assert_eq!(into.union_cardinality(from).unwrap(), expected.cardinality());
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn sparse_registers_are_promoted_before_taking_half_the_memory() {
+        let mut sketch = HyperLogLog::new(&seeded_config());
+        (0..50_u64).for_each(|i| sketch.insert(&i));
+        assert!(sketch.is_sparse());
+        assert!(sketch.heap_size() < COUNTER_SIZE / 2);
+        assert!(sketch.debug_validate().is_ok());
+        (0..1_000_u64).for_each(|i| sketch.insert(&i));
+        assert!(!sketch.is_sparse());
+        assert!(sketch.debug_validate().is_ok());
+        sketch.clear();
+        assert!(sketch.is_sparse());
+        assert_eq!(sketch.cardinality(), 0);
+    }
+
+    #[test]
+    fn packed_registers_take_three_quarters_of_the_memory() {
+        let config = seeded_config().with_sparse_registers(false);
+        let mut dense = HyperLogLog::new(&config);
+        let mut packed = HyperLogLog::new(&config.clone().with_packed_registers(true));
+        (0..10_000_u64).for_each(|i| {
+            dense.insert(&i);
+            packed.insert(&i);
+        });
+        assert_eq!(packed.registers.heap_size(), COUNTER_SIZE * 3 / 4);
+        assert_eq!(packed.registers(), dense.registers());
+
+        // Registers beyond 6 bits unpack them.
+        let mut registers = vec![0; COUNTER_SIZE];
+        registers[3] = MAX_REGISTER_VALUE;
+        assert!(packed.merge_raw_registers(&registers).is_ok());
+        assert!(dense.merge_raw_registers(&registers).is_ok());
+        assert_eq!(packed.registers.heap_size(), COUNTER_SIZE);
+        assert_eq!(packed.registers(), dense.registers());
+        assert_eq!(packed.cardinality(), dense.cardinality());
+        assert!(packed.debug_validate().is_ok());
+        packed.clear();
+        assert_eq!(packed.registers.heap_size(), COUNTER_SIZE * 3 / 4);
+    }
+
+    #[test]
+    fn narrow_registers_saturate_and_take_less_memory() {
+        let config = seeded_config().with_sparse_registers(false);
+        let mut dense = HyperLogLog::new(&config);
+        (0..10_000_u64).for_each(|i| dense.insert(&i));
+        for (register_width, heap_size) in [
+            (RegisterWidth::Bits4, COUNTER_SIZE / 2),
+            (RegisterWidth::Bits5, COUNTER_SIZE * 5 / 8),
+            (RegisterWidth::Bits6, COUNTER_SIZE * 3 / 4),
+        ] {
+            let narrow_config = config.clone().with_register_width(register_width);
+            let mut narrow = HyperLogLog::new(&narrow_config);
+            (0..10_000_u64).for_each(|i| narrow.insert(&i));
+            assert_eq!(narrow.registers.heap_size(), heap_size);
+            let max_register_value = register_width.max_register_value();
+            let saturated = dense
+                .registers()
+                .iter()
+                .map(|&r| r.min(max_register_value))
+                .collect::<Vec<_>>();
+            assert_eq!(narrow.registers()[..], saturated[..]);
+
+            // Merged registers saturate too, and never unpack.
+            assert!(narrow
+                .merge_raw_registers(&[MAX_REGISTER_VALUE; COUNTER_SIZE])
+                .is_ok());
+            assert_eq!(narrow.registers.heap_size(), heap_size);
+            assert!(narrow.registers().iter().all(|&r| r == max_register_value));
+            assert!(narrow.debug_validate().is_ok());
+            assert!(narrow.merge(&dense).is_err());
+        }
+    }
+
+    #[test]
+    fn ertl_estimators_count_past_saturated_registers() {
+        const NUM_REGISTERS: usize = 256;
+        // 2^15 items per register, which saturate 4 bits most of the time.
+        const CARDINALITY: u64 = 1 << 23;
+        let config = Config::new(NUM_REGISTERS, Some(SEEDS))
+            .unwrap()
+            .with_register_width(RegisterWidth::Bits4);
+        let mut sketch = HyperLogLog::<u64>::new(&config);
+        (0..CARDINALITY).for_each(|i| sketch.insert(&i));
+        let num_saturated = sketch.registers().iter().filter(|&&r| r == 15).count();
+        assert!(num_saturated > NUM_REGISTERS / 2, "{}", num_saturated);
+
+        let error = |estimator| {
+            let mut sketch = sketch.clone();
+            sketch.config = config.clone().with_estimator(estimator);
+            sketch.cardinality() as f64 / CARDINALITY as f64 - 1.0
+        };
+        assert!(error(Estimator::Original) < -0.25);
+        for estimator in [Estimator::Improved, Estimator::MaximumLikelihood] {
+            assert!(error(estimator).abs() < 0.1, "{:?}", estimator);
+        }
+    }
+
+    #[test]
+    fn bias_correction_removes_the_mid_range_bump() {
+        const NUM_REGISTERS: usize = 256;
+        const NUM_RUNS: u64 = 200;
+        let config = Config::new(NUM_REGISTERS, Some(SEEDS)).unwrap();
+        let corrected_config = config.clone().with_estimator(Estimator::BiasCorrected);
+        // Just above where the original estimator leaves linear counting.
+        let cardinality = 3 * NUM_REGISTERS as u64;
+        let (mut error, mut corrected_error) = (0.0, 0.0);
+        for run in 0..NUM_RUNS {
+            let mut sketch = HyperLogLog::new(&config);
+            (run * cardinality..(run + 1) * cardinality).for_each(|i| sketch.insert(&i));
+            let mut corrected = HyperLogLog::<u64>::new(&corrected_config);
+            corrected.merge_registers(&sketch.registers());
+            error += sketch.cardinality() as f64 - cardinality as f64;
+            corrected_error += corrected.cardinality() as f64 - cardinality as f64;
+        }
+        let bias = error / (NUM_RUNS * cardinality) as f64;
+        let corrected_bias = corrected_error / (NUM_RUNS * cardinality) as f64;
+        assert!(
+            corrected_bias.abs() < bias.abs() / 2.0,
+            "{} {}",
+            bias,
+            corrected_bias
+        );
+    }
+
+    #[test]
+    fn ertl_estimators_are_unbiased_over_the_whole_range() {
+        const NUM_REGISTERS: usize = 256;
+        const NUM_RUNS: u64 = 100;
+        let config = Config::new(NUM_REGISTERS, Some(SEEDS)).unwrap();
+        for cardinality in [10, 100, 640, 3_000, 30_000] {
+            let mut errors = [(0.0, 0.0); 2];
+            for run in 0..NUM_RUNS {
+                let mut sketch = HyperLogLog::<u64>::new(&config);
+                (run * cardinality..(run + 1) * cardinality).for_each(|i| sketch.insert(&i));
+                for (estimator, (bias, squared_error)) in
+                    [Estimator::Improved, Estimator::MaximumLikelihood]
+                        .into_iter()
+                        .zip(&mut errors)
+                {
+                    let mut ertl =
+                        HyperLogLog::<u64>::new(&config.clone().with_estimator(estimator));
+                    ertl.merge_registers(&sketch.registers());
+                    let error = ertl.cardinality() as f64 / cardinality as f64 - 1.0;
+                    *bias += error / NUM_RUNS as f64;
+                    *squared_error += error * error / NUM_RUNS as f64;
+                }
+            }
+            for (bias, squared_error) in errors {
+                assert!(bias.abs() < 0.03, "{}: {}", cardinality, bias);
+                // 1.04 / sqrt(m) is 0.065.
+                assert!(
+                    squared_error.sqrt() < 0.08,
+                    "{}: {}",
+                    cardinality,
+                    squared_error
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn loglog_beta_is_unbiased_over_the_whole_range() {
+        const NUM_RUNS: u64 = 100;
+        for num_registers in [256, 4096] {
+            let config = Config::new(num_registers, Some(SEEDS)).unwrap();
+            let beta_config = config.clone().with_estimator(Estimator::LogLogBeta);
+            let num_registers = num_registers as u64;
+            for cardinality in [
+                num_registers / 10,
+                num_registers,
+                3 * num_registers,
+                10 * num_registers,
+                100 * num_registers,
+            ] {
+                let (mut bias, mut squared_error) = (0.0, 0.0);
+                for run in 0..NUM_RUNS {
+                    let mut sketch = HyperLogLog::<u64>::new(&config);
+                    (run * cardinality..(run + 1) * cardinality).for_each(|i| sketch.insert(&i));
+                    let mut beta = HyperLogLog::<u64>::new(&beta_config);
+                    beta.merge_registers(&sketch.registers());
+                    let error = beta.cardinality() as f64 / cardinality as f64 - 1.0;
+                    bias += error / NUM_RUNS as f64;
+                    squared_error += error * error / NUM_RUNS as f64;
+                }
+                let relative_standard_error = 1.04 / (num_registers as f64).sqrt();
+                assert!(
+                    bias.abs() < relative_standard_error / 4.0,
+                    "{}: {}",
+                    cardinality,
+                    bias
+                );
+                assert!(
+                    squared_error.sqrt() < 1.1 * relative_standard_error,
+                    "{}: {}",
+                    cardinality,
+                    squared_error
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn it_inserts_raw_hashes() {
+        let mut sketch = HyperLogLog::<u64>::new(&Config::new(16, Some(SEEDS)).unwrap());
+        sketch.insert_raw(0b1000_0000_0011);
+        // 60 bits are left above the index of a zero hash.
+        sketch.insert_raw(0);
+        let mut expected = [0; 16];
+        (expected[3], expected[0]) = (8, 61);
+        assert_eq!(sketch.registers()[..], expected);
+        assert!(sketch.debug_validate().is_ok());
+
+        let config = Config::new(1024, Some(SEEDS))
+            .unwrap()
+            .with_hash_width(HashWidth::Bits32);
+        let mut sketch = HyperLogLog::<u64>::new(&config);
+        sketch.insert_raw(0);
+        assert_eq!(sketch.registers()[0], 33);
+
+        let hasher = ahash::RandomState::with_seeds(0, 1, 2, 3);
+        let mut sketch = HyperLogLog::<u64>::new(&config);
+        (0..100_000_u64).for_each(|i| sketch.insert_raw(hasher.hash_one(i)));
+        let error = (sketch.cardinality() as f64 - 100_000.0).abs() / 100_000.0;
+        assert!(error < 0.1, "{}", error);
+    }
+
+    #[test]
+    fn the_martingale_estimator_is_more_accurate_on_single_streams() {
+        const NUM_REGISTERS: usize = 256;
+        const NUM_RUNS: u64 = 300;
+        let original_config = Config::new(NUM_REGISTERS, Some(SEEDS)).unwrap();
+        let config = original_config
+            .clone()
+            .with_estimator(Estimator::Martingale);
+        for cardinality in [100, 1_000, 20_000] {
+            let (mut squared_error, mut martingale_squared_error) = (0.0, 0.0);
+            for run in 0..NUM_RUNS {
+                let mut sketch = HyperLogLog::<u64>::new(&config);
+                (run * cardinality..(run + 1) * cardinality).for_each(|i| sketch.insert(&i));
+                let mut original = HyperLogLog::<u64>::new(&original_config);
+                assert!(original.merge_raw_registers(&sketch.registers()).is_ok());
+                let error = original.cardinality() as f64 / cardinality as f64 - 1.0;
+                squared_error += error * error / NUM_RUNS as f64;
+                let error = sketch.cardinality() as f64 / cardinality as f64 - 1.0;
+                martingale_squared_error += error * error / NUM_RUNS as f64;
+            }
+            assert!(
+                martingale_squared_error < squared_error,
+                "{}: {} {}",
+                cardinality,
+                martingale_squared_error,
+                squared_error
+            );
+        }
+        let mut sketch = HyperLogLog::<u64>::new(&config);
+        (0..20_000_u64).for_each(|i| sketch.insert(&i));
+        let estimate = sketch.cardinality_with_error().unwrap();
+        assert!((estimate.relative_standard_error - 0.83 / 16.0).abs() < 1e-3);
+        assert!(estimate.interval(3.0).contains(&20_000));
+
+        // Merged sketches fall back to the original estimator.
+        let mut original = HyperLogLog::<u64>::new(&original_config);
+        assert!(original.merge_raw_registers(&sketch.registers()).is_ok());
+        let other = HyperLogLog::new(&config);
+        assert!(sketch.merge(&other).is_ok());
+        assert_eq!(sketch.cardinality(), original.cardinality());
+        sketch.clear();
+        sketch.insert(&0);
+        assert_eq!(sketch.cardinality(), 1);
+    }
+
+    /// Registers as if `cardinality` items had been inserted, drawn from the
+    /// distribution of the max of `cardinality / COUNTER_SIZE` register values.
+    fn registers_of(cardinality: f64) -> Vec<u8> {
+        let uniform = ahash::RandomState::with_seeds(0, 1, 2, 3);
+        let items_per_register = cardinality / COUNTER_SIZE as f64;
+        (0..COUNTER_SIZE)
+            .map(|i| {
+                let u = (uniform.hash_one(i) >> 11) as f64 / (1_u64 << 53) as f64;
+                // The smallest `k` such that `(1 - 2^-k)^items_per_register >= u`.
+                let k = -(-(u.ln() / items_per_register).exp_m1()).log2();
+                k.ceil().clamp(0.0, f64::from(MAX_REGISTER_VALUE)) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_estimates_very_high_cardinalities_without_bias() {
+        for estimator in [
+            Estimator::Original,
+            Estimator::Improved,
+            Estimator::MaximumLikelihood,
+        ] {
+            let config = seeded_config().with_estimator(estimator);
+            for log_cardinality in [33, 40, 50, 60, 63] {
+                let cardinality = 2.0_f64.powi(log_cardinality);
+                let mut sketch = HyperLogLog::<u64>::new(&config);
+                assert!(sketch
+                    .merge_raw_registers(&registers_of(cardinality))
+                    .is_ok());
+                let error = (sketch.cardinality() as f64 - cardinality).abs() / cardinality;
+                assert!(
+                    error <= 0.1,
+                    "{:?} 2^{}: {}",
+                    estimator,
+                    log_cardinality,
+                    error
+                );
+            }
+
+            // Estimates saturate rather than wrap.
+            let mut sketch = HyperLogLog::<u64>::new(&config);
+            assert!(sketch
+                .merge_raw_registers(&[MAX_REGISTER_VALUE; COUNTER_SIZE])
+                .is_ok());
+            assert_eq!(sketch.cardinality(), u64::MAX);
+        }
+    }
+
+    #[test]
+    fn estimates_do_not_drift_through_inserts() {
+        let mut sketch = HyperLogLog::new(&seeded_config());
+        (0..1_000_000_u64).for_each(|i| sketch.insert(&i));
+        let mut recounted = HyperLogLog::<u64>::new(&seeded_config());
+        recounted.merge_registers(&sketch.registers());
+        assert_eq!(sketch.histogram, recounted.histogram);
+        assert_eq!(
+            sketch.raw_cardinality().to_bits(),
+            recounted.raw_cardinality().to_bits()
+        );
+        assert_eq!(sketch.cardinality_f64(), recounted.cardinality_f64());
+    }
+
+    #[test]
+    fn unrounded_cardinalities_are_within_one_of_the_cardinality() {
+        for estimator in [
+            Estimator::Original,
+            Estimator::BiasCorrected,
+            Estimator::Improved,
+            Estimator::MaximumLikelihood,
+            Estimator::Martingale,
+            Estimator::LogLogBeta,
+        ] {
+            let mut sketch = HyperLogLog::new(&seeded_config().with_estimator(estimator));
+            assert_eq!(sketch.cardinality_f64(), 0.0);
+            // Small and mid ranges, each through linear counting or not.
+            for item in 0..10_000_u64 {
+                sketch.insert(&item);
+                let unrounded = sketch.cardinality_f64();
+                assert!(
+                    (unrounded - sketch.cardinality() as f64).abs() < 1.0,
+                    "{:?} {}: {} {}",
+                    estimator,
+                    item,
+                    unrounded,
+                    sketch.cardinality()
+                );
+            }
+            assert_ne!(sketch.cardinality_f64().fract(), 0.0, "{:?}", estimator);
+        }
+    }
+
+    #[test]
+    fn it_is_send_and_sync_regardless_of_items() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<HyperLogLog<u64>>();
+        assert_send_sync::<HyperLogLog<std::rc::Rc<str>>>();
+    }
 }
@@ -5,10 +5,24 @@
 ///
 /// K. Whang, B. T. Vander-Zanden, and H. M. Taylor. A Linear-Time Probabilistic
 /// Counting Algorithm for Database Applications, 1990.
-use std::f64::consts::{LN_10, LN_2};
+use core::f64::consts::{LN_10, LN_2};
 
-pub fn linear_counting(total: usize, zeros: usize) -> usize {
-    ((total as f64) * (integer_ln(total) - integer_ln(zeros))) as usize
+#[cfg(not(feature = "std"))]
+#[allow(unused_imports)]
+use num_traits::Float as _;
+
+pub fn linear_counting(total: usize, zeros: usize) -> f64 {
+    (total as f64) * (integer_ln(total) - integer_ln(zeros))
+}
+
+/// The relative standard error of linear counting's `estimate` over `total`
+/// registers, from the same paper.
+pub fn linear_counting_error(total: usize, estimate: u64) -> f64 {
+    if estimate == 0 {
+        return 0.0;
+    }
+    let load = estimate as f64 / total as f64;
+    (total as f64 * (load.exp() - load - 1.0)).sqrt() / estimate as f64
 }
 
 fn integer_ln(x: usize) -> f64 {
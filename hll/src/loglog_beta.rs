@@ -0,0 +1,45 @@
+//! The LogLog-Beta estimator, from J. Qin, D. Kim and Y. Tung. LogLog-Beta
+//! and More: A New Algorithm for Cardinality Estimation Based on LogLog
+//! Counting, 2016.
+//!
+//! The raw estimator's bias for small and mid ranges comes from its zero
+//! registers, so LogLog-Beta adds beta, a polynomial of `z` and `ln(z + 1)`
+//! with `z` the number of zero registers, to the sum of `2^-r` the estimate
+//! divides by: `alpha * m * (m - z) / (beta(z) + sum(2^-r))`. Beta's
+//! coefficients are fitted for each number of registers, so the estimator
+//! needs neither linear counting nor bias tables over the whole range.
+
+use crate::beta_tables::BETAS;
+#[allow(unused_imports)]
+use crate::float::*;
+
+/// The precisions, i.e., the logs of the numbers of registers, with fitted
+/// coefficients.
+const MIN_PRECISION: u32 = 4;
+const MAX_PRECISION: u32 = 18;
+
+/// The LogLog-Beta estimate of `num_registers` registers summing to `z_inv`,
+/// `num_zero_registers` of which are zero, with bias correction `alpha`.
+/// `None` if there are no coefficients for as many registers.
+pub(crate) fn estimate(
+    num_registers: usize,
+    alpha: f64,
+    z_inv: f64,
+    num_zero_registers: usize,
+) -> Option<f64> {
+    let precision = num_registers.trailing_zeros();
+    if !(MIN_PRECISION..=MAX_PRECISION).contains(&precision) {
+        return None;
+    }
+    let coefficients = &BETAS[(precision - MIN_PRECISION) as usize];
+    let (m, z) = (num_registers as f64, num_zero_registers as f64);
+    let zl = (z + 1.0).ln();
+    let polynomial = coefficients[1..]
+        .iter()
+        .rev()
+        .fold(0.0, |polynomial, &coefficient| {
+            polynomial * zl + coefficient
+        });
+    let beta = coefficients[0] * z + polynomial * zl;
+    Some(alpha * m * (m - z) / (beta + z_inv))
+}
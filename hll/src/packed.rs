@@ -0,0 +1,110 @@
+//! Registers packed on fewer bits than a byte, e.g., 6 bits, 4 to 3 bytes:
+//! register `i` takes bits `6i` to `6i + 5` of the little-endian bytes.
+//! Sketches keep them that way when configured to, and encodings use the
+//! same layout on 6 bits.
+
+use alloc::{vec, vec::Vec};
+
+/// The bits of the encodings' packed registers, and of sketches with packed
+/// registers of bytes.
+pub(crate) const PACKED_BITS: u32 = 6;
+
+/// The largest register value that fits on 6 bits. Registers of 64-bit hashes
+/// only exceed it with negligible probability.
+pub(crate) const MAX_PACKED_VALUE: u8 = max_value(PACKED_BITS);
+
+/// The largest register value that fits on `bits`, at most 8.
+pub(crate) const fn max_value(bits: u32) -> u8 {
+    // At most 8 bits, so this cannot truncate.
+    ((1_u16 << bits) - 1) as u8
+}
+
+/// The number of bytes of `num_registers` registers packed on `bits`.
+pub(crate) fn packed_len(num_registers: usize, bits: u32) -> usize {
+    (num_registers * bits as usize).div_ceil(8)
+}
+
+/// `registers`, which must all be at most [`max_value`] of `bits`, packed on
+/// them.
+pub(crate) fn pack(registers: &[u8], bits: u32) -> Vec<u8> {
+    let mut packed = zeros(registers.len(), bits);
+    for (r, &value) in registers.iter().enumerate() {
+        set(&mut packed, r, value, bits);
+    }
+    packed
+}
+
+/// The `num_registers` registers of `packed` on `bits`, which must be as
+/// long as they take.
+pub(crate) fn unpack(packed: &[u8], num_registers: usize, bits: u32) -> Vec<u8> {
+    (0..num_registers).map(|r| get(packed, r, bits)).collect()
+}
+
+/// `num_registers` zero registers packed on `bits`.
+pub(crate) fn zeros(num_registers: usize, bits: u32) -> Vec<u8> {
+    vec![0; packed_len(num_registers, bits)]
+}
+
+/// Register `r` of `packed` on `bits`.
+#[inline]
+pub(crate) fn get(packed: &[u8], r: usize, bits: u32) -> u8 {
+    let offset = bits as usize * r;
+    let (byte, shift) = (offset / 8, offset % 8);
+    let word = u16::from_le_bytes([packed[byte], packed.get(byte + 1).copied().unwrap_or(0)]);
+    // Masked to at most 8 bits, so this cannot truncate.
+    (word >> shift & u16::from(max_value(bits))) as u8
+}
+
+/// Sets register `r` of `packed` on `bits` to `value`, at most
+/// [`max_value`] of `bits`.
+#[inline]
+pub(crate) fn set(packed: &mut [u8], r: usize, value: u8, bits: u32) {
+    debug_assert!(value <= max_value(bits));
+    let offset = bits as usize * r;
+    let (byte, shift) = (offset / 8, offset % 8);
+    let word = u16::from(value) << shift;
+    let mask = !(u16::from(max_value(bits)) << shift);
+    packed[byte] = packed[byte] & mask as u8 | word as u8;
+    if shift + bits as usize > 8 {
+        // The register straddles two bytes, so keeps the high byte of the
+        // word and mask.
+        packed[byte + 1] = packed[byte + 1] & (mask >> 8) as u8 | (word >> 8) as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn it_round_trips(
+            bits in prop::sample::select(vec![4_u32, 5, 6, 8]),
+            registers in proptest::collection::vec(any::<u8>(), 0..100),
+        ) {
+            let registers = registers
+                .into_iter()
+                .map(|r| r & max_value(bits))
+                .collect::<Vec<_>>();
+            let packed = pack(&registers, bits);
+            prop_assert_eq!(packed.len(), packed_len(registers.len(), bits));
+            prop_assert_eq!(unpack(&packed, registers.len(), bits), registers.clone());
+            for (r, &value) in registers.iter().enumerate() {
+                prop_assert_eq!(get(&packed, r, bits), value);
+            }
+
+            let mut set_one_by_one = zeros(registers.len(), bits);
+            for (r, &value) in registers.iter().enumerate().rev() {
+                set(&mut set_one_by_one, r, value, bits);
+            }
+            prop_assert_eq!(set_one_by_one, packed);
+        }
+    }
+
+    #[test]
+    fn six_bits_pack_four_registers_in_three_bytes() {
+        assert_eq!(pack(&[1, 2, 3, 63], PACKED_BITS), [0x81, 0x30, 0xfc]);
+    }
+}
@@ -0,0 +1,157 @@
+//! Register buffers recycled through a [`BufferPool`], for counters that are
+//! cleared and reused over and over, e.g., those of SSS with the offset
+//! reset strategy, rather than relying on the allocator to.
+//!
+//! Sparse registers hand their buffer to the pool when cleared (see
+//! [`CardinalitySketch::clear_into`]), and take one back when promoted by an
+//! insert (see [`CardinalitySketch::insert_in`]). Registers that aren't
+//! sparse are zeroed in place instead.
+//!
+//! [`CardinalitySketch::clear_into`]: sketch_traits::CardinalitySketch::clear_into
+//! [`CardinalitySketch::insert_in`]: sketch_traits::CardinalitySketch::insert_in
+
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use sketch_traits::{BufferPool, SeedableBuildHasher};
+
+use crate::{sparse::Registers, Config, HyperLogLog};
+
+impl<I, H> HyperLogLog<I, H>
+where
+    H: SeedableBuildHasher,
+{
+    /// A sketch like [`New::new`](sketch_traits::New::new)'s, whose
+    /// registers take a buffer of `pool` unless they start sparse.
+    pub fn new_in(config: &Config<H>, pool: &mut BufferPool) -> Self {
+        let mut sketch = Self {
+            config: config.clone(),
+            registers: Registers::new_in(config.layout(), pool),
+            histogram: Vec::new(),
+            martingale: config.martingale(),
+            item_type: PhantomData,
+        };
+        sketch.sync_histogram();
+        sketch
+    }
+
+    /// Drops the sketch, handing the buffer of its registers to `pool`.
+    pub fn release(self, pool: &mut BufferPool) {
+        self.registers.release(pool);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sketch_traits::{CardinalitySketch, MemorySize, New, Validate};
+
+    use super::*;
+
+    fn config() -> Config {
+        Config::new(1024, Some([0, 1, 2, 3, 4, 5, 6, 7])).unwrap()
+    }
+
+    fn buffer_ptr(sketch: &HyperLogLog<u64>) -> *const u8 {
+        sketch.registers().as_ptr()
+    }
+
+    #[test]
+    fn released_buffers_are_reused_zeroed() {
+        for config in [
+            config().with_sparse_registers(false),
+            config()
+                .with_sparse_registers(false)
+                .with_packed_registers(true),
+        ] {
+            let mut pool = BufferPool::new(1);
+            let mut sketch = HyperLogLog::<u64>::new_in(&config, &mut pool);
+            (0..10_000_u64).for_each(|i| sketch.insert(&i));
+            let heap_size = sketch.heap_size();
+            sketch.release(&mut pool);
+            assert_eq!(pool.len(), 1);
+
+            let sketch = HyperLogLog::<u64>::new_in(&config, &mut pool);
+            assert!(pool.is_empty());
+            assert_eq!(sketch.heap_size(), heap_size);
+            assert_eq!(sketch.cardinality(), 0);
+            assert!(sketch.registers().iter().all(|&r| r == 0));
+            assert!(sketch.debug_validate().is_ok());
+        }
+    }
+
+    #[test]
+    fn dense_sketches_are_cleared_in_place() {
+        let config = config().with_sparse_registers(false);
+        let mut pool = BufferPool::new(4);
+        let mut sketch = HyperLogLog::<u64>::new_in(&config, &mut pool);
+        (0..10_000_u64).for_each(|i| sketch.insert(&i));
+        let ptr = buffer_ptr(&sketch);
+        sketch.clear_into(&mut pool);
+        assert_eq!(buffer_ptr(&sketch), ptr);
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn sparse_sketches_are_promoted_into_the_buffers_they_were_cleared_of() {
+        // Packed registers are unpacked into a new buffer to be read.
+        for (config, packed) in [
+            (config(), false),
+            (config().with_packed_registers(true), true),
+        ] {
+            let mut pool = BufferPool::new(4);
+            let mut sketch = HyperLogLog::<u64>::new_in(&config, &mut pool);
+            let mut expected = HyperLogLog::new(&config);
+            (0..10_000_u64).for_each(|i| {
+                sketch.insert_in(&i, &mut pool);
+                expected.insert(&i);
+            });
+            assert!(!sketch.is_sparse());
+            let ptr = buffer_ptr(&sketch);
+            sketch.clear_into(&mut pool);
+            expected.clear();
+            assert_eq!(pool.len(), 1);
+            assert!(sketch.is_sparse());
+            assert_eq!(sketch.cardinality(), 0);
+
+            let items = (10_000..20_000_u64).collect::<Vec<_>>();
+            sketch.insert_all_in(&items, &mut pool);
+            expected.insert_all(&items);
+            assert!(pool.is_empty());
+            assert!(!sketch.is_sparse());
+            if !packed {
+                assert_eq!(buffer_ptr(&sketch), ptr);
+            }
+            assert_eq!(sketch.registers(), expected.registers());
+            assert_eq!(sketch.cardinality(), expected.cardinality());
+            assert!(sketch.debug_validate().is_ok());
+        }
+    }
+
+    #[test]
+    fn it_hands_out_buffers_only_of_the_right_length() {
+        let small_config = config().with_sparse_registers(false);
+        let mut pool = BufferPool::new(2);
+        HyperLogLog::<u64>::new(&small_config).release(&mut pool);
+        assert_eq!(pool.len(), 1);
+
+        let configs = [
+            Config::new(2048, Some([0, 1, 2, 3, 4, 5, 6, 7]))
+                .unwrap()
+                .with_sparse_registers(false),
+            small_config.clone().with_packed_registers(true),
+            Config::new(2048, Some([0, 1, 2, 3, 4, 5, 6, 7])).unwrap(),
+        ];
+        for config in configs {
+            let mut sketch = HyperLogLog::<u64>::new_in(&config, &mut pool);
+            assert_eq!(pool.len(), 1);
+            let mut expected = HyperLogLog::new(&config);
+            (0..10_000_u64).for_each(|i| {
+                sketch.insert_in(&i, &mut pool);
+                expected.insert(&i);
+            });
+            assert_eq!(pool.len(), 1);
+            assert_eq!(sketch.registers(), expected.registers());
+            assert!(sketch.debug_validate().is_ok());
+        }
+    }
+}
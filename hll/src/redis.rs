@@ -0,0 +1,203 @@
+//! Conversions from and to Redis HyperLogLogs (`PFADD`, `PFCOUNT`, ...), e.g.,
+//! to migrate distinct counts kept in Redis, or to compare them against these
+//! sketches.
+//!
+//! Redis HyperLogLogs have 2^14 registers holding, like these, the number of
+//! trailing zeros of a hash plus one. Redis hashes items once with
+//! MurmurHash64A, though, indexing registers with the low 14 bits of the hash
+//! and counting the trailing zeros of the other 50, while these hash items
+//! twice with the config's hasher. Converted registers estimate the same
+//! cardinalities, but only merge meaningfully with registers from the same
+//! system: the union of a Redis HyperLogLog and a sketch of the same items
+//! counts them twice.
+
+use alloc::vec::Vec;
+
+use sketch_traits::{
+    codec::{take, DecodeError},
+    SeedableBuildHasher,
+};
+
+use crate::{
+    packed::{self, packed_len, PACKED_BITS},
+    HashWidth, HyperLogLog, MergeError,
+};
+
+/// The number of registers of Redis HyperLogLogs.
+pub const REDIS_NUM_REGISTERS: usize = 1 << 14;
+
+/// The largest value of Redis registers: the 50 hash bits left after indexing
+/// registers have at most 50 trailing zeros.
+const MAX_REDIS_REGISTER_VALUE: u8 = 51;
+
+const MAGIC: &[u8; 4] = b"HYLL";
+const DENSE: u8 = 0;
+const SPARSE: u8 = 1;
+
+/// The magic, encoding, 3 unused bytes and the cached cardinality.
+const HEADER_LEN: usize = 16;
+
+impl<I, H> HyperLogLog<I, H>
+where
+    H: SeedableBuildHasher,
+{
+    /// Merges a Redis HyperLogLog, as `GET` returns it, in either of Redis'
+    /// dense or sparse encodings. Fails unless the sketch has
+    /// [`REDIS_NUM_REGISTERS`] registers of 64-bit hashes.
+    pub fn merge_redis(&mut self, bytes: &[u8]) -> Result<(), MergeError> {
+        self.check_redis_config()?;
+        let mut input = bytes;
+        let header = take(&mut input, HEADER_LEN)?;
+        if &header[..4] != MAGIC {
+            return Err(DecodeError::InvalidValue.into());
+        }
+        let registers = match header[4] {
+            DENSE => {
+                let packed = take(&mut input, packed_len(REDIS_NUM_REGISTERS, PACKED_BITS))?;
+                packed::unpack(packed, REDIS_NUM_REGISTERS, PACKED_BITS)
+            }
+            SPARSE => decode_sparse(input)?,
+            _ => return Err(DecodeError::InvalidValue.into()),
+        };
+        if registers.iter().any(|&r| r > MAX_REDIS_REGISTER_VALUE) {
+            return Err(DecodeError::InvalidValue.into());
+        }
+        self.merge_registers(&registers);
+        Ok(())
+    }
+
+    /// The sketch as a Redis HyperLogLog in Redis' dense encoding, e.g., to
+    /// `SET` under a key that `PFCOUNT` and `PFMERGE` then accept. Registers
+    /// above 51, the most Redis registers hold, saturate at it, which takes
+    /// about 2^50 items per register. Fails unless the sketch has
+    /// [`REDIS_NUM_REGISTERS`] registers of 64-bit hashes.
+    pub fn to_redis(&self) -> Result<Vec<u8>, MergeError> {
+        self.check_redis_config()?;
+        let registers = self
+            .registers()
+            .iter()
+            .map(|&r| r.min(MAX_REDIS_REGISTER_VALUE))
+            .collect::<Vec<_>>();
+        let mut bytes =
+            Vec::with_capacity(HEADER_LEN + packed_len(REDIS_NUM_REGISTERS, PACKED_BITS));
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&[DENSE, 0, 0, 0]);
+        // No cached cardinality: the high bit of its last byte tells Redis
+        // to estimate it from the registers.
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0x80]);
+        bytes.extend_from_slice(&packed::pack(&registers, PACKED_BITS));
+        Ok(bytes)
+    }
+
+    fn check_redis_config(&self) -> Result<(), MergeError> {
+        if self.config.num_registers != REDIS_NUM_REGISTERS
+            || self.config.hash_width != HashWidth::Bits64
+        {
+            return Err(MergeError::ConfigMismatch);
+        }
+        Ok(())
+    }
+}
+
+/// The registers of Redis' sparse encoding, runs of registers whose opcodes
+/// are:
+/// - `00xxxxxx`: `xxxxxx + 1` zero registers,
+/// - `01xxxxxx yyyyyyyy`: `xxxxxxyyyyyyyy + 1` zero registers,
+/// - `1vvvvvxx`: `xx + 1` registers of value `vvvvv + 1`.
+fn decode_sparse(mut input: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let mut registers = Vec::with_capacity(REDIS_NUM_REGISTERS);
+    while let Some((&opcode, rest)) = input.split_first() {
+        input = rest;
+        let (value, len) = match opcode >> 6 {
+            0b00 => (0, usize::from(opcode & 0x3f) + 1),
+            0b01 => {
+                let low = take(&mut input, 1)?[0];
+                (0, (usize::from(opcode & 0x3f) << 8 | usize::from(low)) + 1)
+            }
+            _ => ((opcode >> 2 & 0x1f) + 1, usize::from(opcode & 0x03) + 1),
+        };
+        if registers.len() + len > REDIS_NUM_REGISTERS {
+            return Err(DecodeError::InvalidValue);
+        }
+        registers.resize(registers.len() + len, value);
+    }
+    if registers.len() != REDIS_NUM_REGISTERS {
+        return Err(DecodeError::InvalidValue);
+    }
+    Ok(registers)
+}
+
+#[cfg(test)]
+mod tests {
+    use sketch_traits::{CardinalitySketch, New};
+
+    use super::*;
+    use crate::Config;
+
+    fn config() -> Config {
+        Config::new(REDIS_NUM_REGISTERS, Some([0, 1, 2, 3, 4, 5, 6, 7])).unwrap()
+    }
+
+    #[test]
+    fn it_round_trips_through_the_dense_encoding() {
+        let mut sketch = HyperLogLog::new(&config());
+        (0..100_000_u64).for_each(|i| sketch.insert(&i));
+        let bytes = sketch.to_redis().unwrap();
+        assert_eq!(bytes.len(), 12_304);
+        assert_eq!(&bytes[..5], b"HYLL\0");
+
+        let mut imported = HyperLogLog::<u64>::new(&config());
+        assert!(imported.merge_redis(&bytes).is_ok());
+        assert_eq!(imported.registers(), sketch.registers());
+        assert_eq!(imported.cardinality(), sketch.cardinality());
+    }
+
+    #[test]
+    fn it_imports_the_sparse_encoding() {
+        let val = |value: u8, len: u8| 0x80 | (value - 1) << 2 | (len - 1);
+        // Zero runs of up to 2^14 registers fit on 14 bits.
+        let xzero = |len: usize| [0x40 | ((len - 1) >> 8) as u8, (len - 1) as u8];
+
+        let mut bytes = b"HYLL\x01\0\0\0\0\0\0\0\0\0\0\x80".to_vec();
+        // Registers 0 and 1 are 3, then 100 zero registers, register 102 is
+        // 1, and the rest are zero.
+        bytes.push(val(3, 2));
+        bytes.extend_from_slice(&xzero(100));
+        bytes.push(val(1, 1));
+        bytes.extend_from_slice(&xzero(REDIS_NUM_REGISTERS - 103));
+
+        let mut sketch = HyperLogLog::<u64>::new(&config());
+        assert!(sketch.merge_redis(&bytes).is_ok());
+        let registers = sketch.registers();
+        assert_eq!(registers[..3], [3, 3, 0]);
+        assert_eq!(registers[102], 1);
+        assert_eq!(registers.iter().filter(|&&r| r != 0).count(), 3);
+
+        // Runs must add up to all the registers.
+        bytes.pop();
+        assert!(matches!(
+            sketch.merge_redis(&bytes),
+            Err(MergeError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn it_rejects_other_configs_and_payloads() {
+        let sketch = HyperLogLog::<u64>::new(&Config::new(1024, None).unwrap());
+        assert!(matches!(sketch.to_redis(), Err(MergeError::ConfigMismatch)));
+        let bytes = HyperLogLog::<u64>::new(&config()).to_redis().unwrap();
+        let mut sketch = HyperLogLog::<u64>::new(&config().with_hash_width(HashWidth::Bits32));
+        assert!(matches!(
+            sketch.merge_redis(&bytes),
+            Err(MergeError::ConfigMismatch)
+        ));
+
+        let mut sketch = HyperLogLog::<u64>::new(&config());
+        assert!(sketch.merge_redis(&bytes[..100]).is_err());
+        assert!(sketch.merge_redis(b"HYLX\0\0\0\0\0\0\0\0\0\0\0\0").is_err());
+        let mut out_of_range = bytes.clone();
+        out_of_range[HEADER_LEN] = 52;
+        assert!(sketch.merge_redis(&out_of_range).is_err());
+        assert_eq!(sketch.cardinality(), 0);
+    }
+}
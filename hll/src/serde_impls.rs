@@ -0,0 +1,138 @@
+//! Sketches serialize their config along with their registers; configs only
+//! serialize their parameters and seeds, and are rebuilt, and checked, from
+//! them when deserializing.
+
+use alloc::vec::Vec;
+
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+use sketch_traits::SeedableBuildHasher;
+
+use crate::{Config, Estimator, HashWidth, HyperLogLog, RegisterWidth};
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "Config")]
+struct ConfigRepr {
+    num_registers: usize,
+    seeds: [u64; 8],
+    hash_width: HashWidth,
+    /// Configs serialized before estimators were selectable used the
+    /// original one.
+    #[serde(default)]
+    estimator: Estimator,
+    /// Configs serialized before register widths were selectable used bytes.
+    #[serde(default)]
+    register_width: RegisterWidth,
+}
+
+impl<H> Serialize for Config<H> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ConfigRepr {
+            num_registers: self.num_registers,
+            seeds: self.seeds,
+            hash_width: self.hash_width,
+            estimator: self.estimator,
+            register_width: self.register_width,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, H> Deserialize<'de> for Config<H>
+where
+    H: SeedableBuildHasher,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = ConfigRepr::deserialize(deserializer)?;
+        Config::with_hasher(repr.num_registers, Some(repr.seeds))
+            .map(|config| {
+                config
+                    .with_hash_width(repr.hash_width)
+                    .with_estimator(repr.estimator)
+                    .with_register_width(repr.register_width)
+            })
+            .map_err(D::Error::custom)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename = "HyperLogLog", bound = "")]
+struct HyperLogLogRef<'a, H> {
+    config: &'a Config<H>,
+    registers: &'a [u8],
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "HyperLogLog", bound = "H: SeedableBuildHasher")]
+struct HyperLogLogRepr<H> {
+    config: Config<H>,
+    registers: Vec<u8>,
+}
+
+impl<I, H> Serialize for HyperLogLog<I, H> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        HyperLogLogRef {
+            config: &self.config,
+            registers: &self.registers(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, I, H> Deserialize<'de> for HyperLogLog<I, H>
+where
+    H: SeedableBuildHasher,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = HyperLogLogRepr::<H>::deserialize(deserializer)?;
+        // Checks the registers before counting them into the histogram.
+        HyperLogLog::from_registers(&repr.config, repr.registers).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sketch_traits::{CardinalitySketch, New};
+
+    use super::*;
+
+    #[test]
+    fn it_round_trips() {
+        let config = Config::new(256, Some([0; 8]))
+            .unwrap()
+            .with_hash_width(HashWidth::Bits32)
+            .with_estimator(Estimator::BiasCorrected);
+        let mut sketch = HyperLogLog::new(&config);
+        (0..1000_u64).for_each(|i| sketch.insert(&i));
+
+        let json = serde_json::to_string(&sketch).unwrap();
+        let decoded: HyperLogLog<u64> = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.config, sketch.config);
+        assert_eq!(decoded.registers(), sketch.registers());
+        assert_eq!(decoded.cardinality(), sketch.cardinality());
+    }
+
+    #[test]
+    fn it_defaults_to_the_original_estimator() {
+        let config = Config::new(16, Some([0; 8]))
+            .unwrap()
+            .with_estimator(Estimator::BiasCorrected);
+        let mut json = serde_json::to_value(&config).unwrap();
+        json.as_object_mut().unwrap().remove("estimator");
+        let decoded: Config = serde_json::from_value(json).unwrap();
+        assert_eq!(decoded.estimator(), Estimator::Original);
+    }
+
+    #[test]
+    fn it_rejects_invalid_states() {
+        let mut json = serde_json::to_value(HyperLogLog::<u64>::new(
+            &Config::new(16, Some([0; 8])).unwrap(),
+        ))
+        .unwrap();
+        json["registers"][0] = 66.into();
+        assert!(serde_json::from_value::<HyperLogLog<u64>>(json.clone()).is_err());
+        json["registers"] = vec![0; 8].into();
+        assert!(serde_json::from_value::<HyperLogLog<u64>>(json.clone()).is_err());
+        json["config"]["num_registers"] = 0.into();
+        assert!(serde_json::from_value::<HyperLogLog<u64>>(json).is_err());
+    }
+}
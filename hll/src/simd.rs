@@ -0,0 +1,133 @@
+//! The loop over dense registers that merges spend most of their time in,
+//! with AVX2 on x86-64 CPUs that have it and a scalar fallback elsewhere.
+//!
+//! Merges only move the registers they raise in the histogram, which AVX2
+//! finds 32 registers at a time: merging similar sketches raises few.
+
+use crate::histogram;
+
+/// Sets each register to the max of it and the register of `other` at the
+/// same index, of as many registers, and keeps `histogram` counting them.
+#[inline]
+pub(crate) fn max_assign(registers: &mut [u8], other: &[u8], histogram: &mut [usize]) {
+    debug_assert_eq!(registers.len(), other.len());
+    #[cfg(target_arch = "x86_64")]
+    if has_avx2() {
+        // SAFETY: the CPU supports AVX2.
+        unsafe { avx2::max_assign(registers, other, histogram) };
+        return;
+    }
+    max_assign_scalar(registers, other, histogram);
+}
+
+fn max_assign_scalar(registers: &mut [u8], other: &[u8], histogram: &mut [usize]) {
+    for (s, &o) in registers.iter_mut().zip(other) {
+        if o > *s {
+            histogram::raise(histogram, *s, o);
+            *s = o;
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[inline]
+fn has_avx2() -> bool {
+    #[cfg(feature = "std")]
+    {
+        std::is_x86_feature_detected!("avx2")
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        cfg!(target_feature = "avx2")
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use core::arch::x86_64::{
+        __m256i, _mm256_cmpeq_epi8, _mm256_loadu_si256, _mm256_max_epu8, _mm256_movemask_epi8,
+        _mm256_storeu_si256,
+    };
+
+    use super::max_assign_scalar;
+    use crate::histogram;
+
+    /// The registers of a 256-bit vector.
+    const WIDTH: usize = 32;
+
+    /// See [`super::max_assign`].
+    ///
+    /// # Safety
+    ///
+    /// The CPU must support AVX2.
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn max_assign(registers: &mut [u8], other: &[u8], histogram: &mut [usize]) {
+        let len = registers.len().min(other.len()) / WIDTH * WIDTH;
+        for (s, o) in registers[..len]
+            .chunks_exact_mut(WIDTH)
+            .zip(other[..len].chunks_exact(WIDTH))
+        {
+            // SAFETY: both chunks are 32 bytes, and unaligned loads take any
+            // address.
+            let (registers, max) = unsafe {
+                let registers = _mm256_loadu_si256(s.as_ptr().cast::<__m256i>());
+                let max =
+                    _mm256_max_epu8(registers, _mm256_loadu_si256(o.as_ptr().cast::<__m256i>()));
+                (registers, max)
+            };
+            // A bit per register, set unless the max is the register.
+            let mut raised = !(_mm256_movemask_epi8(_mm256_cmpeq_epi8(max, registers)) as u32);
+            if raised == 0 {
+                continue;
+            }
+            while raised != 0 {
+                let i = raised.trailing_zeros() as usize;
+                histogram::raise(histogram, s[i], o[i]);
+                raised &= raised - 1;
+            }
+            // SAFETY: the chunk is 32 bytes, and unaligned stores take any
+            // address.
+            unsafe { _mm256_storeu_si256(s.as_mut_ptr().cast::<__m256i>(), max) };
+        }
+        max_assign_scalar(&mut registers[len..], &other[len..], histogram);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::MAX_REGISTER_VALUE;
+
+    proptest! {
+        #[test]
+        fn it_matches_the_scalar_loop(
+            registers in proptest::collection::vec(0..=MAX_REGISTER_VALUE, 0..200),
+            other in proptest::collection::vec(0..=MAX_REGISTER_VALUE, 200),
+        ) {
+            let other = &other[..registers.len()];
+            let histogram = histogram::from_non_zero(
+                registers.len(),
+                MAX_REGISTER_VALUE,
+                registers.iter().copied().filter(|&r| r != 0),
+            );
+            let mut merged = registers.clone();
+            let mut merged_histogram = histogram.clone();
+            max_assign(&mut merged, other, &mut merged_histogram);
+            let mut expected = registers.clone();
+            let mut expected_histogram = histogram;
+            max_assign_scalar(&mut expected, other, &mut expected_histogram);
+            prop_assert_eq!(&merged, &expected);
+            prop_assert_eq!(&merged_histogram, &expected_histogram);
+            prop_assert_eq!(
+                merged_histogram,
+                histogram::from_non_zero(
+                    merged.len(),
+                    MAX_REGISTER_VALUE,
+                    merged.iter().copied().filter(|&r| r != 0),
+                )
+            );
+        }
+    }
+}
@@ -0,0 +1,368 @@
+//! A HyperLogLog over a sliding window of time, e.g., to count the distinct
+//! IPs of each customer in the last 5 minutes.
+//!
+//! Y. Chabchoub and G. Hébrail. Sliding HyperLogLog: Estimating cardinality
+//! in a data stream over a sliding window, 2010.
+//!
+//! Rather than a value, each register keeps the values it would hold over
+//! any window, as the list of future possible maxima: the `(timestamp,
+//! value)` pairs no later pair has a value at least as large as. Pairs are
+//! thus ordered by increasing timestamps and decreasing values, and the
+//! register over a window is the value of its first pair within it. Lists
+//! hold about `ln(n / m)` pairs for `n` items in the window.
+
+use alloc::{vec, vec::Vec};
+use core::{hash::Hash, marker::PhantomData};
+
+use ahash::RandomState;
+use sketch_traits::{CardinalitySketch, Estimate, MemorySize, New, SeedableBuildHasher};
+
+use crate::{Config, ConfigError, HyperLogLog, MergeError};
+
+#[derive(Clone, Debug)]
+pub struct SlidingHyperLogLog<I, H = RandomState> {
+    config: SlidingConfig<H>,
+    /// For each register, the `(timestamp, value)` pairs that are the max of
+    /// some window, by increasing timestamp.
+    registers: Vec<Vec<(u64, u8)>>,
+    /// The latest timestamp, windows end at.
+    now: u64,
+    // See `HyperLogLog`.
+    item_type: PhantomData<fn(&I)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SlidingConfig<H = RandomState> {
+    pub(crate) hll_config: Config<H>,
+    pub(crate) max_window: u64,
+}
+
+impl<H> SlidingConfig<H> {
+    /// Sliding HyperLogLogs of the registers of `hll_config`, that can be
+    /// queried over windows of up to `max_window` time units, in the units
+    /// of the timestamps, e.g., seconds.
+    pub fn new(hll_config: Config<H>, max_window: u64) -> Result<Self, ConfigError> {
+        if max_window == 0 {
+            return Err(ConfigError::ZeroWindow);
+        }
+        Ok(Self {
+            hll_config,
+            max_window,
+        })
+    }
+
+    pub fn hll_config(&self) -> &Config<H> {
+        &self.hll_config
+    }
+
+    pub fn max_window(&self) -> u64 {
+        self.max_window
+    }
+}
+
+impl<H> PartialEq for SlidingConfig<H> {
+    fn eq(&self, other: &Self) -> bool {
+        self.hll_config == other.hll_config && self.max_window == other.max_window
+    }
+}
+
+impl<H> Eq for SlidingConfig<H> {}
+
+/// Configs only hold their parameters and hashers, inline.
+impl<H> MemorySize for SlidingConfig<H> {
+    fn heap_size(&self) -> usize {
+        0
+    }
+}
+
+impl<I, H> New for SlidingHyperLogLog<I, H>
+where
+    H: SeedableBuildHasher,
+{
+    type Config = SlidingConfig<H>;
+
+    fn new(config: &Self::Config) -> Self {
+        Self {
+            config: config.clone(),
+            registers: vec![Vec::new(); config.hll_config.num_registers],
+            now: 0,
+            item_type: PhantomData,
+        }
+    }
+}
+
+/// Items are inserted at the latest timestamp (see
+/// [`SlidingHyperLogLog::advance`]), and counted over the maximum window.
+impl<I, H> CardinalitySketch for SlidingHyperLogLog<I, H>
+where
+    I: Hash,
+    H: SeedableBuildHasher,
+{
+    type Item = I;
+    type MergeError = MergeError;
+
+    #[inline]
+    fn insert(&mut self, item: &Self::Item) {
+        let config = &self.config.hll_config;
+        let r = HyperLogLog::<I, H>::register_index(config, item);
+        let z = HyperLogLog::<I, H>::item_hash(config, item);
+        insert_pair(&mut self.registers[r], self.now, z);
+    }
+
+    /// Takes the union of the pairs of both sketches, up to the latest
+    /// timestamp of either.
+    fn merge(&mut self, other: &Self) -> Result<(), Self::MergeError> {
+        if self.config != other.config {
+            return Err(MergeError::ConfigMismatch);
+        }
+        for (pairs, other_pairs) in self.registers.iter_mut().zip(&other.registers) {
+            for &(timestamp, value) in other_pairs {
+                insert_pair(pairs, timestamp, value);
+            }
+        }
+        self.now = self.now.max(other.now);
+        self.expire();
+        Ok(())
+    }
+
+    /// Drops the items inserted so far, but keeps the latest timestamp.
+    fn clear(&mut self) {
+        self.registers.iter_mut().for_each(Vec::clear);
+    }
+
+    fn cardinality(&self) -> u64 {
+        self.cardinality_within(self.config.max_window)
+    }
+
+    fn cardinality_with_error(&self) -> Option<Estimate> {
+        self.window(self.config.max_window).cardinality_with_error()
+    }
+
+    fn cardinality_f64(&self) -> f64 {
+        self.window(self.config.max_window).cardinality_f64()
+    }
+}
+
+impl<I, H> SlidingHyperLogLog<I, H>
+where
+    H: SeedableBuildHasher,
+{
+    pub fn config(&self) -> &SlidingConfig<H> {
+        &self.config
+    }
+
+    /// The latest timestamp, set by [`Self::advance`] or
+    /// [`Self::insert_at`].
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// Moves the end of windows to `now`, unless it's earlier than the
+    /// latest timestamp, and drops the pairs that fall out of the maximum
+    /// window.
+    pub fn advance(&mut self, now: u64) {
+        if now <= self.now {
+            return;
+        }
+        self.now = now;
+        self.expire();
+    }
+
+    /// Drops the pairs out of the maximum window.
+    fn expire(&mut self) {
+        let (now, max_window) = (self.now, self.config.max_window);
+        for pairs in &mut self.registers {
+            // Pairs are by increasing timestamp, so expired ones come first.
+            let num_expired =
+                pairs.partition_point(|&(timestamp, _)| !in_window(timestamp, now, max_window));
+            pairs.drain(..num_expired);
+        }
+    }
+
+    /// Inserts `item` at `timestamp`, advancing to it if it's the latest.
+    /// Late items are counted in the windows that include their timestamp.
+    pub fn insert_at(&mut self, item: &I, timestamp: u64)
+    where
+        I: Hash,
+    {
+        self.advance(timestamp);
+        if !in_window(timestamp, self.now, self.config.max_window) {
+            return;
+        }
+        let config = &self.config.hll_config;
+        let r = HyperLogLog::<I, H>::register_index(config, item);
+        let z = HyperLogLog::<I, H>::item_hash(config, item);
+        insert_pair(&mut self.registers[r], timestamp, z);
+    }
+
+    /// The number of distinct items inserted over the last `window` time
+    /// units, i.e., at timestamps after `now - window`. Windows longer than
+    /// the maximum window are cut to it.
+    pub fn cardinality_within(&self, window: u64) -> u64 {
+        self.window(window).estimate().cardinality
+    }
+
+    /// A HyperLogLog of the items inserted over the last `window` time units,
+    /// e.g., to merge the windows of several sketches.
+    pub fn window(&self, window: u64) -> HyperLogLog<I, H> {
+        let window = window.min(self.config.max_window);
+        let registers = self
+            .registers
+            .iter()
+            .map(|pairs| {
+                // Values decrease with timestamps, so the first pair in the
+                // window holds the max.
+                pairs
+                    .iter()
+                    .find(|&&(timestamp, _)| in_window(timestamp, self.now, window))
+                    .map_or(0, |&(_, value)| value)
+            })
+            .collect::<Vec<_>>();
+        let mut sketch = HyperLogLog::new(&self.config.hll_config);
+        sketch.merge_registers(&registers);
+        sketch
+    }
+}
+
+impl<I, H> MemorySize for SlidingHyperLogLog<I, H> {
+    fn heap_size(&self) -> usize {
+        self.config.heap_size() + self.registers.heap_size()
+    }
+}
+
+/// Whether `timestamp` is within the `window` ending at `now`.
+#[inline]
+fn in_window(timestamp: u64, now: u64, window: u64) -> bool {
+    now - timestamp.min(now) < window
+}
+
+/// Adds the pair to a register's, unless a later one has a value at least as
+/// large, dropping the earlier ones whose values aren't larger.
+#[inline]
+fn insert_pair(pairs: &mut Vec<(u64, u8)>, timestamp: u64, value: u8) {
+    let position = pairs.partition_point(|&(t, _)| t < timestamp);
+    if pairs
+        .get(position)
+        .is_some_and(|&(_, later_value)| later_value >= value)
+    {
+        return;
+    }
+    // Values decrease with timestamps, so the dominated pairs are the last
+    // ones before the position, and one at the same timestamp.
+    let first_dominated = pairs[..position].partition_point(|&(_, v)| v > value);
+    let end = position + usize::from(pairs.get(position).is_some_and(|&(t, _)| t == timestamp));
+    pairs.splice(first_dominated..end, [(timestamp, value)]);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn config(max_window: u64) -> SlidingConfig {
+        SlidingConfig::new(
+            Config::new(1024, Some([0, 1, 2, 3, 4, 5, 6, 7])).unwrap(),
+            max_window,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn it_counts_the_items_of_recent_windows() {
+        let mut sketch = SlidingHyperLogLog::new(&config(600));
+        // 100 distinct items per second, for 10 minutes.
+        for second in 0..600_u64 {
+            for i in 0..100 {
+                sketch.insert_at(&(second * 100 + i), second);
+            }
+        }
+        for (window, expected) in [(1, 100.0), (60, 6_000.0), (300, 30_000.0)] {
+            let estimate = sketch.cardinality_within(window) as f64;
+            assert!(
+                (estimate - expected).abs() / expected < 0.1,
+                "{}: {}",
+                window,
+                estimate
+            );
+        }
+        assert_eq!(
+            sketch.cardinality_within(600),
+            sketch.cardinality_within(1_000)
+        );
+        assert_eq!(sketch.cardinality(), sketch.cardinality_within(600));
+
+        // Nothing was inserted over the last minute.
+        sketch.advance(659);
+        assert_eq!(sketch.cardinality_within(60), 0);
+        assert!(sketch.cardinality_within(61) > 0);
+        sketch.advance(1_199);
+        assert_eq!(sketch.cardinality(), 0);
+        assert_eq!(sketch.registers.iter().map(Vec::len).sum::<usize>(), 0);
+    }
+
+    #[test]
+    fn it_merges_windows() {
+        let mut a = SlidingHyperLogLog::new(&config(100));
+        let mut b = SlidingHyperLogLog::new(&config(100));
+        let mut all = SlidingHyperLogLog::new(&config(100));
+        for t in 0..200_u64 {
+            for i in 0..50 {
+                let item = t * 50 + i;
+                // `a` ends at an earlier timestamp, so the union is as of
+                // `b`'s.
+                if item % 3 == 0 && t < 150 {
+                    a.insert_at(&item, t);
+                } else if item % 3 != 0 {
+                    b.insert_at(&item, t);
+                } else {
+                    continue;
+                }
+                all.insert_at(&item, t);
+            }
+        }
+        assert!(a.merge(&b).is_ok());
+        assert_eq!(a.now(), 199);
+        for window in [1, 10, 100] {
+            assert_eq!(a.window(window).registers(), all.window(window).registers());
+        }
+        assert!(a.merge(&SlidingHyperLogLog::new(&config(50))).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn windows_match_sketches_of_their_items(
+            inserts in proptest::collection::vec((0..2_000_u64, 0..20_u64), 0..300),
+            window in 1..30_u64,
+        ) {
+            // Timestamps increase by up to 20 with each insert.
+            let mut timestamp = 0;
+            let inserts = inserts
+                .into_iter()
+                .map(|(item, step)| {
+                    timestamp += step;
+                    (item, timestamp)
+                })
+                .collect::<Vec<_>>();
+            let mut sketch = SlidingHyperLogLog::new(&config(20));
+            for &(item, timestamp) in &inserts {
+                sketch.insert_at(&item, timestamp);
+            }
+            for pairs in &sketch.registers {
+                prop_assert!(pairs.windows(2).all(|w| w[0].0 < w[1].0 && w[0].1 > w[1].1));
+            }
+
+            let mut expected = HyperLogLog::new(sketch.config().hll_config());
+            let items = inserts
+                .iter()
+                .filter(|&&(_, timestamp)| in_window(timestamp, sketch.now(), window.min(20)))
+                .map(|&(item, _)| item)
+                .collect::<HashSet<_>>();
+            items.iter().for_each(|item| expected.insert(item));
+            let windowed = sketch.window(window);
+            prop_assert_eq!(windowed.registers(), expected.registers());
+        }
+    }
+}
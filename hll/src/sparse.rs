@@ -0,0 +1,504 @@
+//! Sparse registers, for the many sketches that only ever see a few items,
+//! e.g., the counters of small labels in SSS and SSSS, promoted to a byte or
+//! fewer bits per register once they'd take half the memory.
+
+use alloc::{borrow::Cow, vec, vec::Vec};
+use core::mem;
+
+use sketch_traits::{BufferPool, MemorySize};
+
+use crate::{
+    histogram,
+    packed::{self, packed_len},
+    simd, InvariantViolation,
+};
+
+/// The most registers sparse entries can index, on their 24 high bits.
+const MAX_SPARSE_NUM_REGISTERS: usize = 1 << 24;
+
+/// How the registers of a config are laid out.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Layout {
+    pub(crate) num_registers: usize,
+    /// Whether registers start sparse.
+    pub(crate) sparse: bool,
+    /// The bits registers are packed on once they aren't sparse, if any.
+    pub(crate) packed_bits: Option<u32>,
+}
+
+impl Layout {
+    /// The most sparse entries before they're promoted: entries take 4 bytes,
+    /// so they'd then take half the memory of the registers they're promoted
+    /// to.
+    fn max_sparse_len(self) -> usize {
+        self.buffer_len() / 2 / mem::size_of::<u32>()
+    }
+
+    /// Whether zero registers are sparse.
+    fn starts_sparse(self) -> bool {
+        self.sparse && self.num_registers <= MAX_SPARSE_NUM_REGISTERS
+    }
+
+    /// The bytes of the registers once they aren't sparse.
+    pub(crate) fn buffer_len(self) -> usize {
+        match self.packed_bits {
+            Some(bits) => packed_len(self.num_registers, bits),
+            None => self.num_registers,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum Registers {
+    /// The non-zero registers, as `index << 8 | value`, sorted by index.
+    Sparse(Vec<u32>),
+    Dense(Vec<u8>),
+    /// Registers packed on `bits` (see [`packed`]). They're unpacked into
+    /// dense ones if a register ever exceeds the most that fits, which
+    /// registers saturating on as many bits never do.
+    Packed {
+        registers: Vec<u8>,
+        bits: u32,
+    },
+}
+
+impl Registers {
+    /// Zero registers, sparse if the layout says so and they can be.
+    pub(crate) fn new(layout: Layout) -> Self {
+        if layout.starts_sparse() {
+            Registers::Sparse(Vec::new())
+        } else if let Some(bits) = layout.packed_bits {
+            Registers::Packed {
+                registers: packed::zeros(layout.num_registers, bits),
+                bits,
+            }
+        } else {
+            Registers::Dense(vec![0; layout.num_registers])
+        }
+    }
+
+    /// Registers laid out as `layout` holding dense `registers`, of as many
+    /// registers, counted into `histogram`, which counts zero registers.
+    pub(crate) fn from_dense(registers: Vec<u8>, layout: Layout, histogram: &mut [usize]) -> Self {
+        if !layout.starts_sparse() && layout.packed_bits.is_none() {
+            let registers = Registers::Dense(registers);
+            registers.count_into(layout.num_registers, histogram);
+            return registers;
+        }
+        let mut zeros = Registers::new(layout);
+        zeros.merge_dense(&registers, layout, histogram);
+        zeros
+    }
+
+    /// Zero registers like [`Registers::new`], in a buffer of `pool` unless
+    /// they're sparse.
+    pub(crate) fn new_in(layout: Layout, pool: &mut BufferPool) -> Self {
+        if layout.starts_sparse() {
+            return Registers::Sparse(Vec::new());
+        }
+        let Some(buffer) = pool.take(layout.buffer_len()) else {
+            return Registers::new(layout);
+        };
+        match layout.packed_bits {
+            Some(bits) => Registers::Packed {
+                registers: buffer,
+                bits,
+            },
+            None => Registers::Dense(buffer),
+        }
+    }
+
+    /// Hands the buffer of the registers, unless they're sparse, to `pool`.
+    pub(crate) fn release(self, pool: &mut BufferPool) {
+        match self {
+            Registers::Dense(buffer)
+            | Registers::Packed {
+                registers: buffer, ..
+            } => pool.put(buffer),
+            Registers::Sparse(_) => {}
+        }
+    }
+
+    /// Sets register `r` to `z` if that's higher, and returns its previous
+    /// value if so. Registers promoted by the update take a buffer of `pool`
+    /// if it has one.
+    #[inline]
+    pub(crate) fn update(
+        &mut self,
+        r: usize,
+        z: u8,
+        layout: Layout,
+        pool: Option<&mut BufferPool>,
+    ) -> Option<u8> {
+        let entries = match self {
+            Registers::Sparse(entries) => entries,
+            _ => return self.set_max(r, z),
+        };
+        // Sparse registers index fewer than 2^24 registers, so this cannot
+        // truncate.
+        let index = r as u32;
+        match entries.binary_search_by_key(&index, |&entry| entry >> 8) {
+            Ok(i) => {
+                let previous = entries[i] as u8;
+                if z <= previous {
+                    return None;
+                }
+                entries[i] = entry(index, z);
+                Some(previous)
+            }
+            Err(i) => {
+                entries.insert(i, entry(index, z));
+                if entries.len() > layout.max_sparse_len() {
+                    self.promote(layout, pool);
+                }
+                Some(0)
+            }
+        }
+    }
+
+    /// Hints the CPU to start loading register `r`, so that the cache misses
+    /// of a batch of updates overlap. Sparse registers are searched through
+    /// rather than indexed, so aren't prefetched.
+    #[inline]
+    pub(crate) fn prefetch(&self, r: usize) {
+        match self {
+            Registers::Dense(registers) => prefetch(&registers[r]),
+            Registers::Packed { registers, bits } => prefetch(&registers[*bits as usize * r / 8]),
+            Registers::Sparse(_) => {}
+        }
+    }
+
+    /// Takes the register-wise max with `other`, of as many registers, and
+    /// keeps `histogram` counting the registers.
+    pub(crate) fn merge(&mut self, other: &Registers, layout: Layout, histogram: &mut [usize]) {
+        let other_entries = match other {
+            Registers::Sparse(entries) => entries,
+            _ => {
+                let other = other.to_dense(layout.num_registers);
+                return self.merge_dense(&other, layout, histogram);
+            }
+        };
+        match self {
+            Registers::Sparse(entries) => {
+                *entries = merge_entries(entries, other_entries.iter().map(|&e| split(e)));
+                let promote = entries.len() > layout.max_sparse_len();
+                // Few enough to recount.
+                self.count_into(layout.num_registers, histogram);
+                if promote {
+                    self.promote(layout, None);
+                }
+            }
+            _ => {
+                for &entry in other_entries {
+                    let (index, value) = split(entry);
+                    if let Some(previous) = self.set_max(index, value) {
+                        histogram::raise(histogram, previous, value);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Takes the register-wise max with dense `registers`, staying sparse if
+    /// few enough of them are set, and keeps `histogram` counting the
+    /// registers.
+    pub(crate) fn merge_dense(
+        &mut self,
+        registers: &[u8],
+        layout: Layout,
+        histogram: &mut [usize],
+    ) {
+        debug_assert_eq!(layout.num_registers, registers.len());
+        if let Registers::Sparse(entries) = self {
+            let num_set = registers.iter().filter(|&&r| r != 0).count();
+            if entries.len() + num_set <= layout.max_sparse_len() {
+                *entries = merge_entries(entries, non_zero(registers));
+                // Few enough to recount.
+                self.count_into(layout.num_registers, histogram);
+                return;
+            }
+            self.promote(layout, None);
+        }
+        if let Registers::Packed { bits, .. } = *self {
+            if registers.iter().any(|&r| r > packed::max_value(bits)) {
+                self.unpack();
+            }
+        }
+        match self {
+            Registers::Dense(dense) => simd::max_assign(dense, registers, histogram),
+            Registers::Packed {
+                registers: packed,
+                bits,
+            } => {
+                for (r, &value) in registers.iter().enumerate() {
+                    let previous = packed::get(packed, r, *bits);
+                    if value > previous {
+                        packed::set(packed, r, value, *bits);
+                        histogram::raise(histogram, previous, value);
+                    }
+                }
+            }
+            Registers::Sparse(_) => unreachable!("sparse registers were promoted"),
+        }
+    }
+
+    /// Zeroes the registers, making them sparse again if the layout says so.
+    pub(crate) fn clear(&mut self, layout: Layout) {
+        match self {
+            Registers::Dense(registers) if !layout.sparse && layout.packed_bits.is_none() => {
+                registers.fill(0)
+            }
+            Registers::Packed { registers, .. } if !layout.sparse => registers.fill(0),
+            _ => *self = Registers::new(layout),
+        }
+    }
+
+    /// Like [`Registers::clear`], but registers that aren't zeroed in place
+    /// hand their buffer to `pool`, and take one from it unless they're
+    /// sparse again.
+    pub(crate) fn clear_into(&mut self, layout: Layout, pool: &mut BufferPool) {
+        match self {
+            Registers::Dense(registers) if !layout.sparse && layout.packed_bits.is_none() => {
+                registers.fill(0)
+            }
+            Registers::Packed { registers, .. } if !layout.sparse => registers.fill(0),
+            _ => {
+                mem::replace(self, Registers::Sparse(Vec::new())).release(pool);
+                *self = Registers::new_in(layout, pool);
+            }
+        }
+    }
+
+    /// The registers, only allocated if sparse or packed.
+    pub(crate) fn to_dense(&self, num_registers: usize) -> Cow<'_, [u8]> {
+        match self {
+            Registers::Dense(registers) => Cow::Borrowed(registers),
+            Registers::Sparse(entries) => {
+                let mut registers = vec![0; num_registers];
+                for &entry in entries {
+                    let (index, value) = split(entry);
+                    registers[index] = value;
+                }
+                Cow::Owned(registers)
+            }
+            Registers::Packed { registers, bits } => {
+                Cow::Owned(packed::unpack(registers, num_registers, *bits))
+            }
+        }
+    }
+
+    /// The `(index, value)` of the non-zero registers, by increasing index.
+    pub(crate) fn non_zero(&self) -> impl Iterator<Item = (usize, u8)> + '_ {
+        let (dense, sparse, packed) = match self {
+            Registers::Dense(registers) => (Some(registers), None, None),
+            Registers::Sparse(entries) => (None, Some(entries), None),
+            Registers::Packed { registers, bits } => (None, None, Some((registers, *bits))),
+        };
+        dense
+            .into_iter()
+            .flat_map(|registers| non_zero(registers))
+            .chain(sparse.into_iter().flatten().map(|&entry| split(entry)))
+            .chain(packed.into_iter().flat_map(|(packed, bits)| {
+                (0..num_packed(packed, bits))
+                    .map(move |r| (r, packed::get(packed, r, bits)))
+                    .filter(|&(_, value)| value != 0)
+            }))
+    }
+
+    /// Recounts the registers into `histogram` (see [`crate::histogram`]).
+    pub(crate) fn count_into(&self, num_registers: usize, histogram: &mut [usize]) {
+        histogram.fill(0);
+        match self {
+            Registers::Dense(registers) => histogram::count(histogram, registers.iter().copied()),
+            Registers::Sparse(entries) => {
+                histogram[0] = num_registers - entries.len();
+                histogram::count(histogram, entries.iter().map(|&entry| entry as u8));
+            }
+            Registers::Packed { registers, bits } => histogram::count(
+                histogram,
+                (0..num_registers).map(|r| packed::get(registers, r, *bits)),
+            ),
+        }
+    }
+
+    /// Checks that dense or packed registers take as many bytes as
+    /// `num_registers` do, or that sparse entries are sorted by distinct
+    /// indices below `num_registers` and hold non-zero values.
+    pub(crate) fn validate_layout(&self, num_registers: usize) -> Result<(), InvariantViolation> {
+        match self {
+            Registers::Dense(registers) if registers.len() != num_registers => {
+                Err(InvariantViolation::NumRegistersMismatch)
+            }
+            Registers::Packed { registers, bits }
+                if registers.len() != packed_len(num_registers, *bits) =>
+            {
+                Err(InvariantViolation::NumRegistersMismatch)
+            }
+            Registers::Sparse(entries)
+                if !entries.windows(2).all(|w| w[0] >> 8 < w[1] >> 8)
+                    || !entries.iter().all(|&entry| {
+                        let (index, value) = split(entry);
+                        index < num_registers && value != 0
+                    }) =>
+            {
+                Err(InvariantViolation::MalformedSparseRegisters)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The dense registers, for tests to corrupt.
+    #[cfg(test)]
+    pub(crate) fn as_dense_mut(&mut self) -> &mut [u8] {
+        match self {
+            Registers::Dense(registers) => registers,
+            _ => panic!("the registers aren't dense"),
+        }
+    }
+
+    /// Sets register `r` of dense or packed registers to `z` if that's
+    /// higher, and returns its previous value if so.
+    #[inline]
+    fn set_max(&mut self, r: usize, z: u8) -> Option<u8> {
+        if let Registers::Packed { registers, bits } = self {
+            let previous = packed::get(registers, r, *bits);
+            if z <= previous {
+                return None;
+            }
+            if z <= packed::max_value(*bits) {
+                packed::set(registers, r, z, *bits);
+                return Some(previous);
+            }
+            self.unpack();
+        }
+        match self {
+            Registers::Dense(registers) => {
+                let register = &mut registers[r];
+                (z > *register).then(|| mem::replace(register, z))
+            }
+            _ => unreachable!("only dense or packed registers are set"),
+        }
+    }
+
+    /// Turns sparse registers into packed or dense ones, as laid out, in a
+    /// buffer of `pool` if it has one of their length.
+    fn promote(&mut self, layout: Layout, pool: Option<&mut BufferPool>) {
+        let Registers::Sparse(entries) = self else {
+            return;
+        };
+        let packed_bits = layout
+            .packed_bits
+            .filter(|&bits| entries.iter().all(|&e| e as u8 <= packed::max_value(bits)));
+        let len = match packed_bits {
+            Some(bits) => packed_len(layout.num_registers, bits),
+            None => layout.num_registers,
+        };
+        let mut buffer = pool
+            .and_then(|pool| pool.take(len))
+            .unwrap_or_else(|| vec![0; len]);
+        for &entry in entries.iter() {
+            let (index, value) = split(entry);
+            match packed_bits {
+                Some(bits) => packed::set(&mut buffer, index, value, bits),
+                None => buffer[index] = value,
+            }
+        }
+        *self = match packed_bits {
+            Some(bits) => Registers::Packed {
+                registers: buffer,
+                bits,
+            },
+            None => Registers::Dense(buffer),
+        };
+    }
+
+    /// Turns packed registers into dense ones.
+    fn unpack(&mut self) {
+        if let Registers::Packed { registers, bits } = self {
+            let num_registers = num_packed(registers, *bits);
+            *self = Registers::Dense(packed::unpack(registers, num_registers, *bits));
+        }
+    }
+}
+
+impl MemorySize for Registers {
+    fn heap_size(&self) -> usize {
+        match self {
+            Registers::Dense(registers) | Registers::Packed { registers, .. } => {
+                registers.heap_size()
+            }
+            Registers::Sparse(entries) => entries.heap_size(),
+        }
+    }
+}
+
+#[inline]
+fn entry(index: u32, value: u8) -> u32 {
+    index << 8 | u32::from(value)
+}
+
+#[inline]
+fn split(entry: u32) -> (usize, u8) {
+    // The value is the low byte, so this truncates on purpose.
+    ((entry >> 8) as usize, entry as u8)
+}
+
+/// The number of registers packed on `bits`: there are at least 16, a power
+/// of two, so they take a whole number of bytes.
+fn num_packed(packed: &[u8], bits: u32) -> usize {
+    packed.len() * 8 / bits as usize
+}
+
+/// Hints the CPU to start loading `value` into the cache.
+#[inline(always)]
+fn prefetch<T>(value: &T) {
+    #[cfg(target_arch = "x86_64")]
+    // SAFETY: prefetching is a hint that never faults, and `value` is valid anyway.
+    unsafe {
+        use core::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+        _mm_prefetch::<_MM_HINT_T0>((value as *const T).cast());
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    let _ = value;
+}
+
+fn non_zero(registers: &[u8]) -> impl Iterator<Item = (usize, u8)> + '_ {
+    registers
+        .iter()
+        .enumerate()
+        .filter(|(_, &r)| r != 0)
+        .map(|(index, &r)| (index, r))
+}
+
+/// The entries of the register-wise max of `entries` and the non-zero
+/// registers `other`, by increasing index.
+fn merge_entries(entries: &[u32], other: impl Iterator<Item = (usize, u8)>) -> Vec<u32> {
+    let mut merged = Vec::with_capacity(entries.len());
+    let mut entries = entries.iter().map(|&e| split(e)).peekable();
+    let mut other = other.peekable();
+    loop {
+        let (index, value) = match (entries.peek(), other.peek()) {
+            (Some(&(i, a)), Some(&(j, b))) if i == j => {
+                entries.next();
+                other.next();
+                (i, a.max(b))
+            }
+            (Some(&(i, _)), Some(&(j, b))) if j < i => {
+                other.next();
+                (j, b)
+            }
+            (Some(&next), _) => {
+                entries.next();
+                next
+            }
+            (None, Some(&next)) => {
+                other.next();
+                next
+            }
+            (None, None) => break,
+        };
+        // Indices of sparse registers fit on 24 bits.
+        merged.push(entry(index as u32, value));
+    }
+    merged
+}
@@ -0,0 +1,18 @@
+use core::fmt;
+
+use crate::HeavyDistinctHitterSketch;
+
+/// A kind of heavy distinct hitter sketch, along with how to build one from
+/// the number of entries it keeps and the size of its cardinality counters,
+/// so that services and tools can pick sketches by name and size them
+/// uniformly. The name is the `Display` output.
+pub trait Algorithm: fmt::Display {
+    type Sketch<L, I>;
+
+    fn new_sketch<L, I>(&self, sketch_size: usize, counter_size: usize) -> Self::Sketch<L, I>
+    where
+        Self::Sketch<L, I>: HeavyDistinctHitterSketch<Label = L, Item = I>;
+
+    /// The counter size giving the best accuracy for a given memory budget.
+    fn optimal_counter_size(&self) -> usize;
+}
@@ -0,0 +1,119 @@
+//! Arrow record batches of the labels tracked by sketches, to hand them off to
+//! dataframe libraries (e.g., polars or pandas) without parsing them, and
+//! Arrow IPC files (a.k.a. Feather v2) to write them to.
+//!
+//! Batches have a `label` column, with labels displayed by [`LabelKey`], and
+//! an `estimate` column. Metadata, e.g., the sketch's type and config, is
+//! attached to the schema.
+
+use std::{collections::HashMap, io::Write, sync::Arc};
+
+use arrow_array::{RecordBatch, StringArray, UInt64Array};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{ArrowError, DataType, Field, Schema};
+
+use crate::{HeavyDistinctHitterSketch, LabelKey};
+
+/// The `k` labels with the highest cardinalities, in decreasing order.
+pub fn top_batch<S>(
+    sketch: &S,
+    k: usize,
+    metadata: HashMap<String, String>,
+) -> Result<RecordBatch, ArrowError>
+where
+    S: HeavyDistinctHitterSketch,
+    S::Label: LabelKey,
+{
+    batch(sketch.top(k), metadata)
+}
+
+/// All the labels tracked by the sketch, in no particular order.
+pub fn labels_batch<S>(
+    sketch: &S,
+    metadata: HashMap<String, String>,
+) -> Result<RecordBatch, ArrowError>
+where
+    S: HeavyDistinctHitterSketch,
+    S::Label: LabelKey,
+{
+    batch(
+        sketch
+            .labels()
+            .map(|label| (label, sketch.cardinality(label))),
+        metadata,
+    )
+}
+
+fn batch<'a, L>(
+    entries: impl IntoIterator<Item = (&'a L, u64)>,
+    metadata: HashMap<String, String>,
+) -> Result<RecordBatch, ArrowError>
+where
+    L: LabelKey + 'a,
+{
+    let (labels, estimates): (Vec<String>, Vec<u64>) = entries
+        .into_iter()
+        .map(|(label, estimate)| (label.display().to_string(), estimate))
+        .unzip();
+    let schema = Schema::new(vec![
+        Field::new("label", DataType::Utf8, false),
+        Field::new("estimate", DataType::UInt64, false),
+    ])
+    .with_metadata(metadata);
+    RecordBatch::try_new(
+        Arc::new(schema),
+        vec![
+            Arc::new(StringArray::from(labels)),
+            Arc::new(UInt64Array::from(estimates)),
+        ],
+    )
+}
+
+/// Writes `batch` as an Arrow IPC file, e.g., for `polars.read_ipc`.
+pub fn write_ipc<W: Write>(batch: &RecordBatch, writer: W) -> Result<(), ArrowError> {
+    let mut writer = FileWriter::try_new(writer, &batch.schema())?;
+    writer.write(batch)?;
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Seek};
+
+    use arrow_array::Array;
+    use arrow_ipc::reader::FileReader;
+
+    use super::*;
+    use crate::exact::Exact;
+
+    #[test]
+    fn it_writes_readable_ipc_files() {
+        let mut sketch = Exact::default();
+        for label in 1..=10_u32 {
+            (0..u64::from(label) * 10).for_each(|i| sketch.insert(label, &i));
+        }
+        let metadata = HashMap::from([("sketch".to_string(), "exact".to_string())]);
+
+        let batch = top_batch(&sketch, 3, metadata.clone()).unwrap();
+        let mut file = Cursor::new(vec![]);
+        write_ipc(&batch, &mut file).unwrap();
+        file.rewind().unwrap();
+        let mut reader = FileReader::try_new(file, None).unwrap();
+        assert_eq!(reader.schema().metadata(), &metadata);
+        let read = reader.next().unwrap().unwrap();
+        assert_eq!(read, batch);
+        let labels = read
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(
+            labels.iter().flatten().collect::<Vec<_>>(),
+            ["10", "9", "8"]
+        );
+
+        let batch = labels_batch(&sketch, HashMap::new()).unwrap();
+        assert_eq!(batch.num_rows(), 10);
+        assert_eq!(batch.column(1).null_count(), 0);
+    }
+}
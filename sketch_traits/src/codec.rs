@@ -0,0 +1,418 @@
+//! A minimal byte codec for shipping sketches between processes.
+//!
+//! Sketches encode their config once, followed by their state. Aggregators
+//! merge a payload directly into an in-memory sketch with
+//! [`MergeEncoded::merge_encoded`], which walks the payload counter by counter
+//! instead of materializing a deserialized copy of the sketch first.
+//!
+//! Sketches replicated periodically, e.g., from agents to an aggregator, can
+//! ship only what changed since the previous payload with [`EncodeDelta`].
+
+use alloc::{string::String, vec::Vec};
+use core::{error, fmt};
+
+/// Values that can be written to a byte buffer.
+pub trait Encode {
+    fn encode(&self, out: &mut Vec<u8>);
+}
+
+/// Values that can be read back from a byte buffer. On success, `input` is
+/// advanced past the decoded value.
+pub trait Decode: Sized {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError>;
+}
+
+/// The state of a sketch, without its config.
+///
+/// Used for nested sketches (e.g., the per-label counters of a Space-Saving
+/// sketch) whose config is shared and only encoded once by the parent.
+pub trait EncodeState {
+    fn encode_state(&self, out: &mut Vec<u8>);
+
+    /// Merges encoded state into `self`, assuming matching configs.
+    fn merge_encoded_state(&mut self, input: &mut &[u8]) -> Result<(), DecodeError>;
+}
+
+/// Sketches that can merge an encoded payload in place.
+pub trait MergeEncoded {
+    type Error: error::Error;
+
+    fn merge_encoded(&mut self, input: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// Sketches that can encode only the state that changed since a snapshot of
+/// theirs.
+///
+/// Deltas are payloads like any other: receivers that already merged the
+/// payloads sent up to the snapshot apply them with
+/// [`MergeEncoded::merge_encoded`], and end up as if they had merged the
+/// whole sketch, since merging is idempotent.
+pub trait EncodeDelta: Encode {
+    /// What the sketch remembers of itself to tell what changed since.
+    type Snapshot;
+
+    fn snapshot(&self) -> Self::Snapshot;
+
+    /// Encodes the state that changed since `snapshot` was taken of this
+    /// sketch.
+    fn encode_delta(&self, snapshot: &Self::Snapshot, out: &mut Vec<u8>);
+}
+
+/// Encodes a sketch as a sequence of deltas, each holding what changed since
+/// the previous one, starting with the whole sketch.
+#[derive(Clone, Debug)]
+pub struct DeltaEncoder<S>
+where
+    S: EncodeDelta,
+{
+    last: Option<S::Snapshot>,
+}
+
+impl<S> Default for DeltaEncoder<S>
+where
+    S: EncodeDelta,
+{
+    fn default() -> Self {
+        Self { last: None }
+    }
+}
+
+impl<S> DeltaEncoder<S>
+where
+    S: EncodeDelta,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Encodes what changed in `sketch` since the previous call, or the whole
+    /// sketch on the first one.
+    pub fn encode_next(&mut self, sketch: &S, out: &mut Vec<u8>) {
+        match &self.last {
+            Some(snapshot) => sketch.encode_delta(snapshot, out),
+            None => sketch.encode(out),
+        }
+        self.last = Some(sketch.snapshot());
+    }
+
+    /// Makes the next payload hold the whole sketch again, e.g., after the
+    /// receiver lost its state or a payload was lost on the way.
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum DecodeError {
+    UnexpectedEnd,
+    UnsupportedVersion(u8),
+    InvalidValue,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd => write!(f, "unexpected end of input"),
+            DecodeError::UnsupportedVersion(v) => write!(f, "unsupported format version {}", v),
+            DecodeError::InvalidValue => write!(f, "invalid encoded value"),
+        }
+    }
+}
+
+impl error::Error for DecodeError {}
+
+/// Splits off the first `n` bytes of `input`.
+pub fn take<'a>(input: &mut &'a [u8], n: usize) -> Result<&'a [u8], DecodeError> {
+    if input.len() < n {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+    let (head, tail) = input.split_at(n);
+    *input = tail;
+    Ok(head)
+}
+
+/// Writes the format version byte that prefixes top-level payloads.
+pub fn encode_version(out: &mut Vec<u8>, version: u8) {
+    out.push(version);
+}
+
+/// Reads and checks the format version byte that prefixes top-level payloads.
+pub fn decode_version(input: &mut &[u8], version: u8) -> Result<(), DecodeError> {
+    match u8::decode(input)? {
+        v if v == version => Ok(()),
+        v => Err(DecodeError::UnsupportedVersion(v)),
+    }
+}
+
+/// Writes `value` as a LEB128 varint: 7 bits per byte, low bits first, with
+/// the high bit set on all bytes but the last. Small values take a byte.
+pub fn encode_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        // Masked to 7 bits, so this cannot truncate.
+        out.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    // Below 0x80, so this cannot truncate.
+    out.push(value as u8);
+}
+
+/// Reads a varint written by [`encode_varint`]. Fails on varints that don't
+/// fit in a `u64`.
+pub fn decode_varint(input: &mut &[u8]) -> Result<u64, DecodeError> {
+    let mut value = 0_u64;
+    for shift in (0..64).step_by(7) {
+        let byte = u8::decode(input)?;
+        let bits = u64::from(byte & 0x7f);
+        if bits << shift >> shift != bits {
+            return Err(DecodeError::InvalidValue);
+        }
+        value |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(DecodeError::InvalidValue)
+}
+
+/// The number of bytes [`encode_varint`] writes for `value`.
+pub fn varint_len(value: u64) -> usize {
+    // A `u64` has at most 64 significant bits, so this cannot truncate.
+    (64 - value.leading_zeros() as usize).div_ceil(7).max(1)
+}
+
+macro_rules! impl_int_codec {
+    ($($t:ty),*) => {
+        $(
+            impl Encode for $t {
+                #[inline]
+                fn encode(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+            }
+
+            impl Decode for $t {
+                #[inline]
+                fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+                    let bytes = take(input, core::mem::size_of::<$t>())?;
+                    let bytes = bytes.try_into().map_err(|_| DecodeError::UnexpectedEnd)?;
+                    Ok(<$t>::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+impl_int_codec!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+// usize is always encoded on 64 bits so payloads are portable.
+impl Encode for usize {
+    #[inline]
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u64).encode(out);
+    }
+}
+
+impl Decode for usize {
+    #[inline]
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        usize::try_from(u64::decode(input)?).map_err(|_| DecodeError::InvalidValue)
+    }
+}
+
+impl Encode for char {
+    fn encode(&self, out: &mut Vec<u8>) {
+        u32::from(*self).encode(out);
+    }
+}
+
+impl Decode for char {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        char::from_u32(u32::decode(input)?).ok_or(DecodeError::InvalidValue)
+    }
+}
+
+impl Encode for str {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.as_str().encode(out);
+    }
+}
+
+impl Decode for String {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let len = usize::decode(input)?;
+        let bytes = take(input, len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidValue)
+    }
+}
+
+impl<T: Encode, const N: usize> Encode for [T; N] {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.iter().for_each(|t| t.encode(out));
+    }
+}
+
+impl<T: Decode, const N: usize> Decode for [T; N] {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let mut values = Vec::with_capacity(N);
+        for _ in 0..N {
+            values.push(T::decode(input)?);
+        }
+        values.try_into().map_err(|_| DecodeError::InvalidValue)
+    }
+}
+
+impl<T: Encode> Encode for [T] {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        self.iter().for_each(|t| t.encode(out));
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.as_slice().encode(out);
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let len = usize::decode(input)?;
+        // Don't trust the length for preallocation; the input may be corrupt.
+        let mut values = Vec::with_capacity(len.min(input.len()));
+        for _ in 0..len {
+            values.push(T::decode(input)?);
+        }
+        Ok(values)
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for &T {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (**self).encode(out);
+    }
+}
+
+macro_rules! impl_tuple_codec {
+    ($($name:ident),*) => {
+        impl<$($name: Encode),*> Encode for ($($name,)*) {
+            #[allow(non_snake_case)]
+            fn encode(&self, out: &mut Vec<u8>) {
+                let ($($name,)*) = self;
+                $($name.encode(out);)*
+            }
+        }
+
+        impl<$($name: Decode),*> Decode for ($($name,)*) {
+            fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+                Ok(($($name::decode(input)?,)*))
+            }
+        }
+    };
+}
+
+impl_tuple_codec!(A, B);
+impl_tuple_codec!(A, B, C);
+impl_tuple_codec!(A, B, C, D);
+
+/// Zstandard compression of whole payloads, for links where bytes cost more
+/// than CPU. Payloads must be decompressed before being decoded or merged.
+#[cfg(feature = "zstd")]
+pub mod zstd {
+    use std::io;
+
+    use super::DecodeError;
+
+    /// Compresses `payload` at `level` (1 to 22, higher is smaller but
+    /// slower; 0 is zstd's default).
+    pub fn compress(payload: &[u8], level: i32) -> io::Result<Vec<u8>> {
+        ::zstd::bulk::compress(payload, level)
+    }
+
+    /// Decompresses a payload written by [`compress`], failing if it would
+    /// take more than `max_len` bytes, so that corrupt or hostile inputs
+    /// can't exhaust memory.
+    pub fn decompress(input: &[u8], max_len: usize) -> Result<Vec<u8>, DecodeError> {
+        ::zstd::bulk::decompress(input, max_len).map_err(|_| DecodeError::InvalidValue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T: Encode + Decode + PartialEq + fmt::Debug>(value: T) {
+        let mut buf = vec![];
+        value.encode(&mut buf);
+        let mut input = buf.as_slice();
+        assert_eq!(T::decode(&mut input).unwrap(), value);
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn it_round_trips() {
+        round_trip(42_u8);
+        round_trip(u64::MAX);
+        round_trip(-7_i32);
+        round_trip(usize::MAX);
+        round_trip('λ');
+        round_trip(String::from("label"));
+        round_trip([1_u64, 2, 3, 4]);
+        round_trip((String::from("customer"), 443_u16));
+        round_trip(vec![String::from("customer"), String::from("endpoint")]);
+    }
+
+    #[test]
+    fn it_round_trips_varints() {
+        for value in [0, 1, 0x7f, 0x80, 300, u64::from(u32::MAX), u64::MAX] {
+            let mut buf = vec![];
+            encode_varint(&mut buf, value);
+            assert_eq!(buf.len(), varint_len(value));
+            let mut input = buf.as_slice();
+            assert_eq!(decode_varint(&mut input), Ok(value));
+            assert!(input.is_empty());
+        }
+        assert_eq!(
+            decode_varint(&mut [0x80].as_slice()),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        // 2^64 doesn't fit.
+        let mut overflowing = [0xff; 10];
+        overflowing[9] = 0x02;
+        assert_eq!(
+            decode_varint(&mut overflowing.as_slice()),
+            Err(DecodeError::InvalidValue)
+        );
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn it_round_trips_compressed_payloads() {
+        let mut buf = vec![];
+        vec![String::from("label"); 100].encode(&mut buf);
+        let compressed = zstd::compress(&buf, 3).unwrap();
+        assert!(compressed.len() < buf.len() / 10);
+        assert_eq!(zstd::decompress(&compressed, buf.len()).unwrap(), buf);
+        assert_eq!(
+            zstd::decompress(&compressed, buf.len() - 1),
+            Err(DecodeError::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn it_rejects_truncated_input() {
+        let mut buf = vec![];
+        String::from("label").encode(&mut buf);
+        buf.pop();
+        assert_eq!(
+            String::decode(&mut buf.as_slice()),
+            Err(DecodeError::UnexpectedEnd)
+        );
+    }
+}
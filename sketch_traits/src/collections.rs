@@ -0,0 +1,10 @@
+//! The hash maps and sets of the sketches: std's, or the hashbrown ones std's
+//! are built on when building without std.
+//!
+//! Sketch crates use these rather than naming either, so that their public
+//! types (e.g., the groups of `top_grouped`) follow the `std` feature.
+
+#[cfg(not(feature = "std"))]
+pub use hashbrown::{hash_map, hash_set, HashMap, HashSet};
+#[cfg(feature = "std")]
+pub use std::collections::{hash_map, hash_set, HashMap, HashSet};
@@ -0,0 +1,76 @@
+use core::{
+    fmt,
+    ops::{Add, AddAssign},
+};
+
+#[cfg(not(feature = "std"))]
+#[allow(unused_imports)]
+use num_traits::Float as _;
+
+/// The numeric type counters accumulate offsets and estimates in.
+///
+/// Counts are `u64` by default. Decayed or weighted variants accumulate
+/// fractional contributions, and use `f64` instead.
+pub trait Count: Copy + Default + PartialOrd + Add<Output = Self> + AddAssign + fmt::Debug {
+    fn from_u64(n: u64) -> Self;
+
+    /// Adds `other`, saturating at the bounds of the type, so that corrupted
+    /// offsets (e.g., deserialized ones) can't overflow.
+    fn saturating_add(self, other: Self) -> Self;
+
+    /// Rounds to the nearest integer, saturating at the bounds of `u64`.
+    fn to_u64(self) -> u64;
+}
+
+impl Count for u64 {
+    #[inline]
+    fn from_u64(n: u64) -> Self {
+        n
+    }
+
+    #[inline]
+    fn saturating_add(self, other: Self) -> Self {
+        u64::saturating_add(self, other)
+    }
+
+    #[inline]
+    fn to_u64(self) -> u64 {
+        self
+    }
+}
+
+impl Count for f64 {
+    #[inline]
+    fn from_u64(n: u64) -> Self {
+        n as f64
+    }
+
+    /// Floats saturate at infinity anyway.
+    #[inline]
+    fn saturating_add(self, other: Self) -> Self {
+        self + other
+    }
+
+    /// NaNs round to 0.
+    #[inline]
+    fn to_u64(self) -> u64 {
+        // Float to int casts saturate.
+        self.round() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_converts_counts() {
+        assert_eq!(u64::from_u64(42).to_u64(), 42);
+        assert_eq!(f64::from_u64(42).to_u64(), 42);
+        assert_eq!(2.5_f64.to_u64(), 3);
+        assert_eq!((-1.0_f64).to_u64(), 0);
+        assert_eq!(f64::INFINITY.to_u64(), u64::MAX);
+        assert_eq!(f64::NAN.to_u64(), 0);
+        assert_eq!(Count::saturating_add(u64::MAX, 1), u64::MAX);
+    }
+}
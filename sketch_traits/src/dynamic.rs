@@ -0,0 +1,253 @@
+use alloc::{boxed::Box, vec::Vec};
+use core::{any::Any, error, fmt};
+
+use crate::{HeavyDistinctHitterSketch, InsertOutcome};
+
+/// An object-safe counterpart of [`HeavyDistinctHitterSketch`], so that the
+/// sketch can be picked at runtime, e.g., from configuration:
+///
+/// ```ignore
+/// let sketch: Box<dyn DynHeavyDistinctHitter<Label = String, Item = String>> =
+///     match config.algorithm {
+///         Algorithm::Ssss => Box::new(ssss::HllSamplingSpaceSavingSets::new(&ssss_config)),
+///         Algorithm::Spread => Box::new(spread::SpreadSketch::new(&spread_config)),
+///     };
+/// ```
+///
+/// Every sketch implements it, and boxed sketches implement
+/// [`HeavyDistinctHitterSketch`] in turn, which is how they're meant to be
+/// used; methods are prefixed so as not to clash with it. Since the type of
+/// the sketch is erased, merging sketches of different types is only caught
+/// at runtime.
+pub trait DynHeavyDistinctHitter {
+    type Label;
+    type Item;
+
+    fn dyn_insert_with_outcome(
+        &mut self,
+        label: Self::Label,
+        item: &Self::Item,
+    ) -> InsertOutcome<Self::Label>;
+
+    /// Merges `other`, which must be a sketch of the same type.
+    fn dyn_merge(&mut self, other: &dyn Any) -> Result<(), DynMergeError>;
+
+    fn dyn_clear(&mut self);
+
+    fn dyn_remove(&mut self, label: &Self::Label) -> bool;
+
+    fn dyn_cardinality(&self, label: &Self::Label) -> u64;
+
+    fn dyn_total_cardinality(&self) -> Option<u64>;
+
+    fn dyn_labels(&self) -> Box<dyn Iterator<Item = &Self::Label> + '_>;
+
+    fn dyn_num_labels(&self) -> usize;
+
+    fn dyn_top(&self, k: usize) -> Vec<(&Self::Label, u64)>;
+
+    fn dyn_top_into(&self, k: usize, out: &mut Vec<(Self::Label, u64)>);
+
+    fn dyn_labels_above(&self, threshold: u64) -> Vec<(&Self::Label, u64)>;
+
+    fn dyn_cardinality_many(&self, labels: &[Self::Label], out: &mut Vec<u64>);
+
+    /// The sketch itself, to pass to [`dyn_merge`](Self::dyn_merge).
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<S> DynHeavyDistinctHitter for S
+where
+    S: HeavyDistinctHitterSketch + Any,
+    S::Label: Clone,
+    S::MergeError: Send + Sync + 'static,
+{
+    type Label = S::Label;
+    type Item = S::Item;
+
+    #[inline]
+    fn dyn_insert_with_outcome(
+        &mut self,
+        label: S::Label,
+        item: &S::Item,
+    ) -> InsertOutcome<S::Label> {
+        HeavyDistinctHitterSketch::insert_with_outcome(self, label, item)
+    }
+
+    fn dyn_merge(&mut self, other: &dyn Any) -> Result<(), DynMergeError> {
+        let other = other
+            .downcast_ref::<S>()
+            .ok_or(DynMergeError::TypeMismatch)?;
+        HeavyDistinctHitterSketch::merge(self, other)
+            .map_err(|error| DynMergeError::Merge(Box::new(error)))
+    }
+
+    fn dyn_clear(&mut self) {
+        HeavyDistinctHitterSketch::clear(self);
+    }
+
+    fn dyn_remove(&mut self, label: &S::Label) -> bool {
+        HeavyDistinctHitterSketch::remove(self, label)
+    }
+
+    fn dyn_cardinality(&self, label: &S::Label) -> u64 {
+        HeavyDistinctHitterSketch::cardinality(self, label)
+    }
+
+    fn dyn_total_cardinality(&self) -> Option<u64> {
+        HeavyDistinctHitterSketch::total_cardinality(self)
+    }
+
+    fn dyn_labels(&self) -> Box<dyn Iterator<Item = &S::Label> + '_> {
+        Box::new(HeavyDistinctHitterSketch::labels(self))
+    }
+
+    fn dyn_num_labels(&self) -> usize {
+        HeavyDistinctHitterSketch::num_labels(self)
+    }
+
+    fn dyn_top(&self, k: usize) -> Vec<(&S::Label, u64)> {
+        HeavyDistinctHitterSketch::top(self, k)
+    }
+
+    fn dyn_top_into(&self, k: usize, out: &mut Vec<(S::Label, u64)>) {
+        HeavyDistinctHitterSketch::top_into(self, k, out);
+    }
+
+    fn dyn_labels_above(&self, threshold: u64) -> Vec<(&S::Label, u64)> {
+        HeavyDistinctHitterSketch::labels_above(self, threshold)
+    }
+
+    fn dyn_cardinality_many(&self, labels: &[S::Label], out: &mut Vec<u64>) {
+        HeavyDistinctHitterSketch::cardinality_many(self, labels, out);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl<D> HeavyDistinctHitterSketch for Box<D>
+where
+    D: DynHeavyDistinctHitter + ?Sized,
+{
+    type Label = D::Label;
+    type Item = D::Item;
+    type MergeError = DynMergeError;
+
+    #[inline]
+    fn insert_with_outcome(&mut self, label: D::Label, item: &D::Item) -> InsertOutcome<D::Label> {
+        (**self).dyn_insert_with_outcome(label, item)
+    }
+
+    fn merge(&mut self, other: &Self) -> Result<(), DynMergeError> {
+        (**self).dyn_merge((**other).as_any())
+    }
+
+    fn clear(&mut self) {
+        (**self).dyn_clear();
+    }
+
+    fn remove(&mut self, label: &D::Label) -> bool {
+        (**self).dyn_remove(label)
+    }
+
+    fn cardinality(&self, label: &D::Label) -> u64 {
+        (**self).dyn_cardinality(label)
+    }
+
+    fn total_cardinality(&self) -> Option<u64> {
+        (**self).dyn_total_cardinality()
+    }
+
+    fn labels(&self) -> impl Iterator<Item = &D::Label> {
+        (**self).dyn_labels()
+    }
+
+    fn num_labels(&self) -> usize {
+        (**self).dyn_num_labels()
+    }
+
+    fn top(&self, k: usize) -> Vec<(&D::Label, u64)> {
+        (**self).dyn_top(k)
+    }
+
+    fn top_into(&self, k: usize, out: &mut Vec<(D::Label, u64)>)
+    where
+        D::Label: Clone,
+    {
+        (**self).dyn_top_into(k, out);
+    }
+
+    fn labels_above(&self, threshold: u64) -> Vec<(&D::Label, u64)> {
+        (**self).dyn_labels_above(threshold)
+    }
+
+    fn cardinality_many(&self, labels: &[D::Label], out: &mut Vec<u64>) {
+        (**self).dyn_cardinality_many(labels, out);
+    }
+}
+
+#[derive(Debug)]
+pub enum DynMergeError {
+    /// The sketches are of different types.
+    TypeMismatch,
+    /// The sketches are of the same type, but failed to merge.
+    Merge(Box<dyn error::Error + Send + Sync>),
+}
+
+impl fmt::Display for DynMergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DynMergeError::TypeMismatch => write!(f, "cannot merge sketches of different types"),
+            DynMergeError::Merge(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl error::Error for DynMergeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            DynMergeError::TypeMismatch => None,
+            DynMergeError::Merge(error) => Some(error.as_ref()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exact::Exact;
+
+    type BoxedSketch = Box<dyn DynHeavyDistinctHitter<Label = String, Item = u64> + Send>;
+
+    fn exact() -> BoxedSketch {
+        Box::<Exact<String>>::default()
+    }
+
+    #[test]
+    fn boxed_sketches_are_sketches() {
+        let mut left = exact();
+        let mut right = exact();
+        for i in 0..100 {
+            left.insert((i % 3).to_string(), &i);
+            right.insert((i % 5).to_string(), &i);
+        }
+        assert!(left.merge(&right).is_ok());
+        assert_eq!(left.cardinality(&"0".to_string()), 47);
+        assert_eq!(left.labels().count(), 5);
+        assert_eq!(left.top(1), [(&"0".to_string(), 47)]);
+        let mut out = vec![];
+        left.top_into(2, &mut out);
+        assert_eq!(out.len(), 2);
+        assert_eq!(left.labels_above(46), [(&"0".to_string(), 47)]);
+        assert!(left.remove(&"0".to_string()));
+
+        // Boxed sketches are sketches of another type, that can be boxed too.
+        let other: BoxedSketch = Box::new(exact());
+        assert!(matches!(
+            left.merge(&other),
+            Err(DynMergeError::TypeMismatch)
+        ));
+    }
+}
@@ -0,0 +1,108 @@
+use alloc::collections::VecDeque;
+use core::mem::size_of;
+#[cfg(feature = "std")]
+use std::time::SystemTime;
+
+use crate::MemorySize;
+
+/// A label a sketch stopped tracking, e.g., to find out why it disappeared
+/// from reports.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Eviction<L> {
+    pub label: L,
+    /// The label's estimate when it was evicted.
+    pub cardinality: u64,
+    /// When the label was evicted. Without std, there's no clock to read.
+    #[cfg(feature = "std")]
+    pub timestamp: SystemTime,
+}
+
+/// The last `capacity` evictions of a sketch, oldest first.
+#[derive(Clone, Debug)]
+pub struct EvictionLog<L> {
+    capacity: usize,
+    evictions: VecDeque<Eviction<L>>,
+}
+
+impl<L> EvictionLog<L> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            evictions: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Logs the eviction of `label` now, dropping the oldest eviction if the
+    /// log is full.
+    pub fn record(&mut self, label: L, cardinality: u64) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.evictions.len() == self.capacity {
+            self.evictions.pop_front();
+        }
+        self.evictions.push_back(Eviction {
+            label,
+            cardinality,
+            #[cfg(feature = "std")]
+            timestamp: SystemTime::now(),
+        });
+    }
+
+    /// The logged evictions, most recent first.
+    pub fn recent(&self) -> impl Iterator<Item = &Eviction<L>> {
+        self.evictions.iter().rev()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.evictions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.evictions.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.evictions.clear();
+    }
+}
+
+impl<L> MemorySize for EvictionLog<L>
+where
+    L: MemorySize,
+{
+    fn heap_size(&self) -> usize {
+        self.evictions.capacity() * size_of::<Eviction<L>>()
+            + self
+                .evictions
+                .iter()
+                .map(|eviction| eviction.label.heap_size())
+                .sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_keeps_the_last_evictions() {
+        let mut log = EvictionLog::new(3);
+        (0..5_u64).for_each(|label| log.record(label, 10 * label));
+        assert_eq!(log.len(), 3);
+        assert_eq!(
+            log.recent()
+                .map(|eviction| (eviction.label, eviction.cardinality))
+                .collect::<Vec<_>>(),
+            [(4, 40), (3, 30), (2, 20)]
+        );
+
+        let mut disabled = EvictionLog::new(0);
+        disabled.record(0, 1);
+        assert!(disabled.is_empty());
+    }
+}
@@ -0,0 +1,79 @@
+//! An exact sketch, keeping the set of items of every label, to exercise the
+//! helpers built on top of the traits in tests.
+
+use std::{
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+    hash::Hash,
+};
+
+use crate::{truncate_top, HeavyDistinctHitterSketch, InsertOutcome};
+
+#[derive(Clone, Debug)]
+pub(crate) struct Exact<L>(HashMap<L, HashSet<u64>>);
+
+impl<L> Default for Exact<L> {
+    fn default() -> Self {
+        Self(HashMap::new())
+    }
+}
+
+impl<L> HeavyDistinctHitterSketch for Exact<L>
+where
+    L: Eq + Hash + Clone,
+{
+    type Label = L;
+    type Item = u64;
+    type MergeError = Infallible;
+
+    fn insert_with_outcome(&mut self, label: L, item: &u64) -> InsertOutcome<L> {
+        let outcome = if self.0.contains_key(&label) {
+            InsertOutcome::UpdatedExisting
+        } else {
+            InsertOutcome::CreatedNew
+        };
+        self.0.entry(label).or_default().insert(*item);
+        outcome
+    }
+
+    fn merge(&mut self, other: &Self) -> Result<(), Infallible> {
+        for (label, items) in &other.0 {
+            self.0.entry(label.clone()).or_default().extend(items);
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    fn remove(&mut self, label: &L) -> bool {
+        self.0.remove(label).is_some()
+    }
+
+    fn cardinality(&self, label: &L) -> u64 {
+        self.0.get(label).map_or(0, |items| items.len() as u64)
+    }
+
+    fn total_cardinality(&self) -> Option<u64> {
+        Some(self.0.values().flatten().collect::<HashSet<_>>().len() as u64)
+    }
+
+    fn labels(&self) -> impl Iterator<Item = &L> {
+        self.0.keys()
+    }
+
+    fn num_labels(&self) -> usize {
+        self.0.len()
+    }
+
+    fn top(&self, k: usize) -> Vec<(&L, u64)> {
+        let mut entries = self
+            .0
+            .iter()
+            .map(|(label, items)| (label, items.len() as u64))
+            .collect::<Vec<_>>();
+        truncate_top(&mut entries, k);
+        entries
+    }
+}
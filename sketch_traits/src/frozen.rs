@@ -0,0 +1,123 @@
+use alloc::vec::Vec;
+
+use crate::HeavyDistinctHitterSketch;
+
+/// A sketch that won't be inserted into or merged anymore, with its labels
+/// ranked once and for all, e.g., for query servers sharing snapshots of a
+/// sketch between threads behind an `Arc`.
+///
+/// Queries only take `&self` and don't allocate: `top` and `labels_above`
+/// return slices of the ranking, and `cardinality` defers to the sketch, whose
+/// queries don't mutate it either. The ingest thread can then keep inserting
+/// into its own sketch, and periodically publish a frozen clone of it.
+#[derive(Clone, Debug)]
+pub struct Frozen<S>
+where
+    S: HeavyDistinctHitterSketch,
+{
+    sketch: S,
+    /// All the labels, in decreasing order of cardinality.
+    ranked: Vec<(S::Label, u64)>,
+}
+
+impl<S> Frozen<S>
+where
+    S: HeavyDistinctHitterSketch,
+    S::Label: Clone,
+{
+    pub fn new(sketch: S) -> Self {
+        let mut ranked = Vec::new();
+        sketch.top_into(usize::MAX, &mut ranked);
+        Self { sketch, ranked }
+    }
+}
+
+impl<S> Frozen<S>
+where
+    S: HeavyDistinctHitterSketch,
+{
+    pub fn sketch(&self) -> &S {
+        &self.sketch
+    }
+
+    pub fn into_inner(self) -> S {
+        self.sketch
+    }
+
+    /// The `k` labels with the highest cardinalities, or all of them if
+    /// there are fewer.
+    pub fn top(&self, k: usize) -> &[(S::Label, u64)] {
+        &self.ranked[..k.min(self.ranked.len())]
+    }
+
+    /// The labels with cardinalities above `threshold`.
+    pub fn labels_above(&self, threshold: u64) -> &[(S::Label, u64)] {
+        let len = self
+            .ranked
+            .partition_point(|&(_, cardinality)| cardinality > threshold);
+        &self.ranked[..len]
+    }
+
+    pub fn cardinality(&self, label: &S::Label) -> u64 {
+        self.sketch.cardinality(label)
+    }
+
+    pub fn total_cardinality(&self) -> Option<u64> {
+        self.sketch.total_cardinality()
+    }
+
+    pub fn num_labels(&self) -> usize {
+        self.ranked.len()
+    }
+}
+
+impl<S> From<S> for Frozen<S>
+where
+    S: HeavyDistinctHitterSketch,
+    S::Label: Clone,
+{
+    fn from(sketch: S) -> Self {
+        Self::new(sketch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, thread};
+
+    use super::*;
+    use crate::exact::Exact;
+
+    #[test]
+    fn it_answers_queries_from_the_ranking() {
+        let mut sketch = Exact::default();
+        for label in 0..10_u32 {
+            (0..u64::from(label) * 10).for_each(|i| sketch.insert(label, &i));
+        }
+        let frozen = Frozen::new(sketch);
+        assert_eq!(frozen.top(3), [(9, 90), (8, 80), (7, 70)]);
+        assert_eq!(frozen.top(100).len(), frozen.num_labels());
+        assert_eq!(frozen.num_labels(), frozen.sketch().num_labels());
+        assert_eq!(frozen.labels_above(65), [(9, 90), (8, 80), (7, 70)]);
+        assert!(frozen.labels_above(90).is_empty());
+        assert_eq!(frozen.cardinality(&4), 40);
+        assert_eq!(frozen.cardinality(&42), 0);
+        assert_eq!(frozen.total_cardinality(), Some(90));
+    }
+
+    #[test]
+    fn it_is_shared_between_threads() {
+        let mut sketch = Exact::default();
+        (0..100_u64).for_each(|i| sketch.insert(i % 3, &i));
+        let frozen = Arc::new(Frozen::new(sketch));
+        let handles = (0..4)
+            .map(|_| {
+                let frozen = Arc::clone(&frozen);
+                thread::spawn(move || frozen.top(1)[0])
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), (0, 34));
+        }
+    }
+}
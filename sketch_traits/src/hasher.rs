@@ -0,0 +1,18 @@
+use core::{fmt, hash::BuildHasher};
+
+/// The hash functions of sketches, built from the seeds of their configs.
+///
+/// Configs are compared, encoded and serialized by their seeds, and rebuild
+/// their hashers from them, so hashers have to be deterministic functions of
+/// their seeds: sketches built with the same seeds, in any process, must hash
+/// items alike for their merges to be meaningful. Implement it to swap ahash,
+/// the default, e.g., for a FIPS-approved or a portable hash function.
+pub trait SeedableBuildHasher: BuildHasher + Clone + fmt::Debug + Send + Sync {
+    fn with_seeds(seeds: [u64; 4]) -> Self;
+}
+
+impl SeedableBuildHasher for ahash::RandomState {
+    fn with_seeds(seeds: [u64; 4]) -> Self {
+        ahash::RandomState::with_seeds(seeds[0], seeds[1], seeds[2], seeds[3])
+    }
+}
@@ -0,0 +1,186 @@
+use alloc::vec::Vec;
+use core::{error, fmt, hash::Hash, iter, ops::Range};
+
+use crate::{collections::HashMap, HeavyDistinctHitterSketch};
+
+/// Skips batches that were already inserted, so that at-least-once pipelines
+/// can redeliver or replay them safely.
+///
+/// Each batch comes with the stream it's from and the range of sequence
+/// numbers it covers. The ranges applied so far are kept per stream, and
+/// coalesced, so that they stay small as long as batches are mostly applied
+/// in order.
+#[derive(Clone, Debug)]
+pub struct Idempotent<S, K> {
+    sketch: S,
+    watermarks: HashMap<K, Watermarks>,
+}
+
+/// What happened to an ingested batch.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Ingested {
+    Applied,
+    /// The batch was already applied, or is empty.
+    Skipped,
+}
+
+impl<S, K> Idempotent<S, K>
+where
+    S: HeavyDistinctHitterSketch,
+    K: Eq + Hash + Clone,
+{
+    pub fn new(sketch: S) -> Self {
+        Self {
+            sketch,
+            watermarks: HashMap::new(),
+        }
+    }
+
+    pub fn sketch(&self) -> &S {
+        &self.sketch
+    }
+
+    pub fn into_inner(self) -> S {
+        self.sketch
+    }
+
+    /// Inserts the batch of `pairs` covering `sequence` on `stream`, unless it
+    /// was already applied. Batches overlapping applied ones without being
+    /// covered by them are rejected, since their new pairs can't be told
+    /// apart from the duplicated ones.
+    pub fn ingest(
+        &mut self,
+        stream: K,
+        sequence: Range<u64>,
+        pairs: impl IntoIterator<Item = (S::Label, S::Item)>,
+    ) -> Result<Ingested, IngestError> {
+        if sequence.is_empty() || self.is_applied(&stream, &sequence) {
+            return Ok(Ingested::Skipped);
+        }
+        let watermarks = self.watermarks.entry(stream).or_default();
+        if watermarks.overlaps(&sequence) {
+            return Err(IngestError::PartialOverlap);
+        }
+        watermarks.insert(sequence);
+        self.sketch.insert_iter(pairs);
+        Ok(Ingested::Applied)
+    }
+
+    /// Whether all of `sequence` was already applied on `stream`.
+    pub fn is_applied(&self, stream: &K, sequence: &Range<u64>) -> bool {
+        self.watermarks
+            .get(stream)
+            .is_some_and(|watermarks| watermarks.covers(sequence))
+    }
+
+    /// The ranges of sequence numbers applied on `stream`, in increasing
+    /// order, e.g., to persist them alongside the sketch.
+    pub fn watermarks(&self, stream: &K) -> &[Range<u64>] {
+        self.watermarks
+            .get(stream)
+            .map_or(&[], |watermarks| &watermarks.0)
+    }
+
+    /// Merges the sketches, and the batches applied to them.
+    pub fn merge(&mut self, other: &Self) -> Result<(), S::MergeError> {
+        self.sketch.merge(&other.sketch)?;
+        for (stream, other_watermarks) in &other.watermarks {
+            let watermarks = self.watermarks.entry(stream.clone()).or_default();
+            other_watermarks
+                .0
+                .iter()
+                .for_each(|range| watermarks.insert(range.clone()));
+        }
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.sketch.clear();
+        self.watermarks.clear();
+    }
+}
+
+/// Disjoint, non-adjacent ranges, in increasing order.
+#[derive(Clone, Default, Debug)]
+struct Watermarks(Vec<Range<u64>>);
+
+impl Watermarks {
+    fn insert(&mut self, range: Range<u64>) {
+        let first = self.0.partition_point(|r| r.end < range.start);
+        let last = self.0.partition_point(|r| r.start <= range.end);
+        let coalesced = self.0[first..last]
+            .iter()
+            .fold(range, |acc, r| acc.start.min(r.start)..acc.end.max(r.end));
+        self.0.splice(first..last, iter::once(coalesced));
+    }
+
+    fn covers(&self, range: &Range<u64>) -> bool {
+        let i = self.0.partition_point(|r| r.end < range.end);
+        self.0
+            .get(i)
+            .is_some_and(|r| r.start <= range.start && range.end <= r.end)
+    }
+
+    fn overlaps(&self, range: &Range<u64>) -> bool {
+        let i = self.0.partition_point(|r| r.end <= range.start);
+        self.0.get(i).is_some_and(|r| r.start < range.end)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum IngestError {
+    /// The batch overlaps applied ones without being covered by them.
+    PartialOverlap,
+}
+
+impl fmt::Display for IngestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IngestError::PartialOverlap => {
+                write!(f, "batch partially overlaps batches already applied")
+            }
+        }
+    }
+}
+
+impl error::Error for IngestError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exact::Exact;
+
+    #[test]
+    fn redelivered_batches_are_skipped() {
+        let mut sketch = Idempotent::new(Exact::default());
+        let batch = |start: u64| (start..start + 10).map(|i| (i % 2, i));
+        assert_eq!(sketch.ingest("a", 0..10, batch(0)), Ok(Ingested::Applied));
+        assert_eq!(sketch.ingest("a", 10..20, batch(10)), Ok(Ingested::Applied));
+        assert_eq!(sketch.ingest("a", 0..10, batch(0)), Ok(Ingested::Skipped));
+        assert_eq!(sketch.ingest("a", 5..15, batch(5)), Ok(Ingested::Skipped));
+        assert_eq!(
+            sketch.ingest("a", 15..25, batch(15)),
+            Err(IngestError::PartialOverlap)
+        );
+        // Streams are tracked separately.
+        assert_eq!(sketch.ingest("b", 0..10, batch(100)), Ok(Ingested::Applied));
+        assert_eq!(sketch.ingest("b", 0..0, batch(200)), Ok(Ingested::Skipped));
+        assert_eq!(sketch.watermarks(&"a"), [Range { start: 0, end: 20 }]);
+        assert_eq!(sketch.sketch().cardinality(&0), 15);
+    }
+
+    #[test]
+    fn watermarks_are_coalesced() {
+        let mut watermarks = Watermarks::default();
+        [10..20, 30..40, 0..5, 20..30, 50..60]
+            .into_iter()
+            .for_each(|range| watermarks.insert(range));
+        assert_eq!(watermarks.0, [0..5, 10..40, 50..60]);
+        assert!(watermarks.covers(&(12..38)));
+        assert!(!watermarks.covers(&(4..11)));
+        assert!(watermarks.overlaps(&(4..11)));
+        assert!(!watermarks.overlaps(&(5..10)));
+        watermarks.insert(0..100);
+        assert_eq!(watermarks.0, [Range { start: 0, end: 100 }]);
+    }
+}
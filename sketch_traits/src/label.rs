@@ -0,0 +1,116 @@
+//! Labels made of one or more fields, e.g. `(customer_id, endpoint)`.
+//!
+//! Sketches only need labels to be `Eq + Hash + Clone`, so tuples and structs
+//! work as labels as they are. `LabelKey` adds a uniform way to display them
+//! (fields are separated by commas, like in the datasets); serialization comes
+//! from the [`codec`](crate::codec) traits, which are implemented for tuples.
+
+use alloc::{string::String, vec::Vec};
+use core::{fmt, hash::Hash};
+
+pub trait LabelKey: Eq + Hash + Clone {
+    fn fmt_label(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+
+    fn display(&self) -> DisplayLabel<'_, Self> {
+        DisplayLabel(self)
+    }
+}
+
+/// Displays a label with [`LabelKey::fmt_label`].
+pub struct DisplayLabel<'a, L: ?Sized>(&'a L);
+
+impl<L> fmt::Display for DisplayLabel<'_, L>
+where
+    L: LabelKey,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt_label(f)
+    }
+}
+
+macro_rules! impl_display_label_key {
+    ($($t:ty),*) => {
+        $(
+            impl LabelKey for $t {
+                fn fmt_label(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    fmt::Display::fmt(self, f)
+                }
+            }
+        )*
+    };
+}
+
+impl_display_label_key!(
+    u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, char, bool, String
+);
+
+impl<L> LabelKey for &L
+where
+    L: LabelKey,
+{
+    fn fmt_label(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt_label(f)
+    }
+}
+
+impl LabelKey for &str {
+    fn fmt_label(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self)
+    }
+}
+
+impl<L> LabelKey for Vec<L>
+where
+    L: LabelKey,
+{
+    fn fmt_label(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, field) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            field.fmt_label(f)?;
+        }
+        Ok(())
+    }
+}
+
+macro_rules! impl_tuple_label_key {
+    ($first:ident $(, $rest:ident)*) => {
+        impl<$first: LabelKey $(, $rest: LabelKey)*> LabelKey for ($first, $($rest,)*) {
+            #[allow(non_snake_case)]
+            fn fmt_label(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                let ($first, $($rest,)*) = self;
+                $first.fmt_label(f)?;
+                $(
+                    f.write_str(",")?;
+                    $rest.fmt_label(f)?;
+                )*
+                Ok(())
+            }
+        }
+    };
+}
+
+impl_tuple_label_key!(A, B);
+impl_tuple_label_key!(A, B, C);
+impl_tuple_label_key!(A, B, C, D);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_displays_composite_labels() {
+        assert_eq!(
+            (42_u32, String::from("/login")).display().to_string(),
+            "42,/login"
+        );
+        assert_eq!(("a", 'b', 3_u8).display().to_string(), "a,b,3");
+        assert_eq!(
+            vec![String::from("x"), String::from("y")]
+                .display()
+                .to_string(),
+            "x,y"
+        );
+    }
+}
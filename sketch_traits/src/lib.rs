@@ -1,32 +1,474 @@
-use std::error;
+#![cfg_attr(not(test), deny(clippy::unwrap_used))]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::{cmp::Reverse, error, hash::Hash, ops::RangeInclusive};
+
+// Rounds the bounds of intervals without std. std's inherent methods take
+// precedence whenever any crate of the build links std, e.g., the tests.
+#[cfg(not(feature = "std"))]
+#[allow(unused_imports)]
+use num_traits::Float as _;
+
+use crate::collections::HashMap;
+
+mod algorithm;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod codec;
+pub mod collections;
+mod count;
+mod dynamic;
+mod evictions;
+#[cfg(test)]
+mod exact;
+mod frozen;
+mod hasher;
+mod idempotent;
+mod label;
+mod memo;
+mod memory;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod observer;
+mod pool;
+pub mod query;
+mod rollup;
+mod seeds;
+mod top;
+
+#[cfg(feature = "metrics")]
+pub use crate::metrics::{MetricCounts, SketchMetrics};
+pub use crate::{
+    algorithm::Algorithm,
+    count::Count,
+    dynamic::{DynHeavyDistinctHitter, DynMergeError},
+    evictions::{Eviction, EvictionLog},
+    frozen::Frozen,
+    hasher::SeedableBuildHasher,
+    idempotent::{Idempotent, IngestError, Ingested},
+    label::{DisplayLabel, LabelKey},
+    memo::Memoized,
+    memory::MemorySize,
+    observer::{Observable, Observer},
+    pool::BufferPool,
+    rollup::{Rollup, RollupError, RollupLevel},
+    seeds::{default_seeds, seeds_from_key},
+    top::TopIter,
+};
+
+/// A sketch estimating the number of distinct items inserted into it.
+///
+/// Implementations must not panic on `insert`, `merge` or `cardinality`,
+/// whatever the input, so that they can run inside long-lived processes.
+/// Invalid states are rejected when building configs instead.
 pub trait CardinalitySketch {
     type Item;
     type MergeError: error::Error;
 
     fn insert(&mut self, item: &Self::Item);
 
+    /// Inserts an item, and returns whether the sketch may have changed, so
+    /// that callers caching its cardinality can skip refreshing it after the
+    /// many inserts of items already counted. Defaults to always changed.
+    fn insert_report(&mut self, item: &Self::Item) -> bool {
+        self.insert(item);
+        true
+    }
+
+    /// Inserts a batch of items. Implementations can override this to amortize
+    /// per-call overhead across the batch.
+    fn insert_all<'a>(&mut self, items: impl IntoIterator<Item = &'a Self::Item>)
+    where
+        Self::Item: 'a,
+    {
+        items.into_iter().for_each(|item| self.insert(item));
+    }
+
+    /// Like [`CardinalitySketch::insert_report`], but the buffers the sketch
+    /// grows into, if any, are taken from `pool`. Defaults to ignoring it.
+    #[inline]
+    fn insert_in(&mut self, item: &Self::Item, pool: &mut BufferPool) -> bool {
+        let _ = pool;
+        self.insert_report(item)
+    }
+
+    /// Like [`CardinalitySketch::insert_all`], but the buffers the sketch
+    /// grows into, if any, are taken from `pool`. Defaults to ignoring it.
+    fn insert_all_in<'a>(
+        &mut self,
+        items: impl IntoIterator<Item = &'a Self::Item>,
+        pool: &mut BufferPool,
+    ) where
+        Self::Item: 'a,
+    {
+        let _ = pool;
+        self.insert_all(items);
+    }
+
+    /// Hints the CPU to start loading what inserting `item` would update, so
+    /// that callers updating many sketches with the same item, e.g., the
+    /// buckets of a label across rows, can overlap their cache misses.
+    /// Defaults to no hint.
+    #[inline]
+    fn prefetch(&self, item: &Self::Item) {
+        let _ = item;
+    }
+
     fn merge(&mut self, other: &Self) -> Result<(), Self::MergeError>;
 
     fn clear(&mut self);
 
+    /// Like [`CardinalitySketch::clear`], but hands the buffers the sketch
+    /// drops, if any, to `pool`, for other sketches to grow into, e.g., for
+    /// counters that are cleared and reused over and over. Defaults to
+    /// clearing.
+    fn clear_into(&mut self, pool: &mut BufferPool) {
+        let _ = pool;
+        self.clear();
+    }
+
     fn cardinality(&self) -> u64;
+
+    /// The cardinality before it's rounded to an integer, e.g., for
+    /// statistics over many estimates not to add up rounding errors.
+    /// Defaults to the integer cardinality.
+    fn cardinality_f64(&self) -> f64 {
+        self.cardinality() as f64
+    }
+
+    /// The cardinality along with its relative standard error, for sketches
+    /// that can quantify it.
+    fn cardinality_with_error(&self) -> Option<Estimate> {
+        None
+    }
+
+    /// The number of distinct items inserted into either sketch, e.g., the
+    /// items of two labels. Fails if the sketches can't be merged.
+    fn union_cardinality(&self, other: &Self) -> Result<u64, Self::MergeError>
+    where
+        Self: Clone,
+    {
+        let mut union = self.clone();
+        union.merge(other)?;
+        Ok(union.cardinality())
+    }
+
+    /// The number of distinct items inserted into both sketches, by
+    /// inclusion-exclusion: `|A| + |B| - |A ∪ B|`.
+    ///
+    /// The errors of the three estimates add up, so small intersections of
+    /// large sets are mostly noise.
+    fn intersection_cardinality(&self, other: &Self) -> Result<u64, Self::MergeError>
+    where
+        Self: Clone,
+    {
+        let union = self.union_cardinality(other)?;
+        Ok(self
+            .cardinality()
+            .saturating_add(other.cardinality())
+            .saturating_sub(union))
+    }
+
+    /// The Jaccard similarity of the sets of items of both sketches, i.e.,
+    /// `|A ∩ B| / |A ∪ B|`, or 0 if both are empty.
+    fn jaccard(&self, other: &Self) -> Result<f64, Self::MergeError>
+    where
+        Self: Clone,
+    {
+        let union = self.union_cardinality(other)?;
+        if union == 0 {
+            return Ok(0.0);
+        }
+        let intersection = self
+            .cardinality()
+            .saturating_add(other.cardinality())
+            .saturating_sub(union);
+        Ok((intersection as f64 / union as f64).min(1.0))
+    }
+}
+
+/// Cardinality sketches that can take items by a stable 64-bit hash of them,
+/// e.g., computed upstream from a wire protocol, rather than hashing them.
+///
+/// The hash is still mixed with the sketch's own seeds, just as a `u64` item
+/// would be, so it only needs to be stable, not well distributed. Items
+/// inserted by hash aren't recognized as the same items inserted as they
+/// are, so a sketch should only be fed through one of the two paths.
+pub trait PrehashedCardinalitySketch: CardinalitySketch {
+    fn insert_hashed(&mut self, item_hash: u64);
+
+    /// Like [`CardinalitySketch::insert_report`], but by the item's hash.
+    fn insert_hashed_report(&mut self, item_hash: u64) -> bool {
+        self.insert_hashed(item_hash);
+        true
+    }
+
+    /// Like [`CardinalitySketch::prefetch`], but by the item's hash.
+    #[inline]
+    fn prefetch_hashed(&self, item_hash: u64) {
+        let _ = item_hash;
+    }
 }
 
+/// Cardinality sketches that can also tell whether an item was likely
+/// inserted into them, e.g., with a Bloom filter.
+pub trait Membership: CardinalitySketch {
+    /// False positives are possible, false negatives aren't: items that were
+    /// inserted are always reported.
+    fn may_contain(&self, item: &Self::Item) -> bool;
+}
+
+/// A cardinality estimate, and how far off it's expected to be.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Estimate {
+    pub cardinality: u64,
+    /// The standard deviation of the estimate, relative to the cardinality.
+    pub relative_standard_error: f64,
+}
+
+impl Estimate {
+    /// The cardinalities within `z` standard errors of the estimate, e.g.,
+    /// `z = 1.96` for a 95% confidence interval if errors are about normal.
+    pub fn interval(&self, z: f64) -> RangeInclusive<u64> {
+        let error = self.cardinality as f64 * self.relative_standard_error * z;
+        // Float to int casts saturate.
+        let lower = (self.cardinality as f64 - error).floor().max(0.0) as u64;
+        let upper = (self.cardinality as f64 + error).ceil() as u64;
+        lower..=upper
+    }
+}
+
+/// A sketch tracking the labels associated with the most distinct items.
+///
+/// As for [`CardinalitySketch`], implementations must not panic on inserts,
+/// merges or queries, whatever the input.
 pub trait HeavyDistinctHitterSketch {
     type Label;
     type Item;
     type MergeError: error::Error;
 
-    fn insert(&mut self, label: Self::Label, item: &Self::Item);
+    #[inline]
+    fn insert(&mut self, label: Self::Label, item: &Self::Item) {
+        self.insert_with_outcome(label, item);
+    }
+
+    /// Like `insert`, but also reports what happened to the label, so that
+    /// callers can log, sample or apply backpressure on top of the sketch.
+    fn insert_with_outcome(
+        &mut self,
+        label: Self::Label,
+        item: &Self::Item,
+    ) -> InsertOutcome<Self::Label>;
 
+    /// Inserts a batch of `(label, item)` pairs. Implementations can override
+    /// this to look labels up once per run of consecutive pairs sharing them,
+    /// so grouping pairs by label pays off.
+    fn insert_iter(&mut self, pairs: impl IntoIterator<Item = (Self::Label, Self::Item)>) {
+        pairs
+            .into_iter()
+            .for_each(|(label, item)| self.insert(label, &item));
+    }
+
+    /// Merges `other` into `self`.
+    ///
+    /// Merging two sketches is commutative. Sketches that evict labels when
+    /// merging (e.g., Space-Saving variants) are only insensitive to how
+    /// merges are grouped as long as the labels fit; otherwise, counters
+    /// evicted by an early merge lose the contributions of later ones.
     fn merge(&mut self, other: &Self) -> Result<(), Self::MergeError>;
 
+    /// Like `merge`, but consumes `other`, so that implementations can move
+    /// its labels and counters over instead of cloning them.
+    fn merge_owned(&mut self, other: Self) -> Result<(), Self::MergeError>
+    where
+        Self: Sized,
+    {
+        self.merge(&other)
+    }
+
     fn clear(&mut self);
 
+    /// Stops tracking `label`, freeing its counter for other labels, e.g., to
+    /// drop labels known to be noise. Returns whether the label was tracked.
+    ///
+    /// Sketches whose labels share state can't forget one of them, so by
+    /// default nothing is removed.
+    fn remove(&mut self, label: &Self::Label) -> bool {
+        let _ = label;
+        false
+    }
+
     fn cardinality(&self, label: &Self::Label) -> u64;
 
+    /// The number of distinct items inserted across all labels, tracked or
+    /// not, e.g., to report heavy hitters as fractions of the stream, or
+    /// `None` if the sketch doesn't keep count of them.
+    fn total_cardinality(&self) -> Option<u64> {
+        None
+    }
+
+    /// The labels the sketch currently tracks, each once, in no particular
+    /// order. Unlike `top(usize::MAX)`, this neither estimates nor sorts
+    /// their cardinalities.
+    fn labels(&self) -> impl Iterator<Item = &Self::Label>;
+
+    /// The number of labels the sketch currently tracks, as listed by
+    /// `labels`. Implementations can override this to count them without
+    /// listing them.
+    fn num_labels(&self) -> usize {
+        self.labels().count()
+    }
+
     fn top(&self, k: usize) -> Vec<(&Self::Label, u64)>;
+
+    /// The labels in decreasing order of cardinality, sorted lazily (see
+    /// [`TopIter`]), for callers that stop after the first few of them
+    /// without knowing how many they need upfront.
+    fn top_iter(&self) -> TopIter<&Self::Label> {
+        self.labels()
+            .map(|label| (label, self.cardinality(label)))
+            .collect()
+    }
+
+    /// Like `top`, but writes owned labels into a caller-provided buffer,
+    /// which is cleared first. Reusing the buffer across calls avoids
+    /// allocating on every query.
+    fn top_into(&self, k: usize, out: &mut Vec<(Self::Label, u64)>)
+    where
+        Self::Label: Clone,
+    {
+        out.clear();
+        out.extend(
+            self.top(k)
+                .into_iter()
+                .map(|(label, cardinality)| (label.clone(), cardinality)),
+        );
+    }
+
+    /// The labels estimated to have more than `threshold` distinct items, in
+    /// decreasing order, e.g., to report every superspreader above a fixed
+    /// fan-out rather than a fixed number of them.
+    fn labels_above(&self, threshold: u64) -> Vec<(&Self::Label, u64)> {
+        let mut entries = self
+            .labels()
+            .map(|label| (label, self.cardinality(label)))
+            .filter(|&(_, cardinality)| cardinality > threshold)
+            .collect::<Vec<_>>();
+        entries.sort_unstable_by_key(|&(_, cardinality)| Reverse(cardinality));
+        entries
+    }
+
+    /// The `k` labels with the highest cardinalities within each group of
+    /// labels, e.g., per region, as classified by `classify`, in decreasing
+    /// order. Labels are classified and estimated in a single pass, without
+    /// sorting all of them as `top(usize::MAX)` would.
+    fn top_grouped<G, F>(&self, k: usize, classify: F) -> HashMap<G, Vec<(&Self::Label, u64)>>
+    where
+        G: Eq + Hash,
+        F: Fn(&Self::Label) -> G,
+    {
+        group_top(
+            self.labels().map(|label| (label, self.cardinality(label))),
+            k,
+            |label| classify(label),
+        )
+    }
+
+    /// Writes the cardinality of each of `labels` into a caller-provided
+    /// buffer, which is cleared first.
+    fn cardinality_many(&self, labels: &[Self::Label], out: &mut Vec<u64>) {
+        out.clear();
+        out.extend(labels.iter().map(|label| self.cardinality(label)));
+    }
+}
+
+/// Heavy distinct hitter sketches that can take items by a stable 64-bit hash
+/// of them, as [`PrehashedCardinalitySketch`] does.
+///
+/// Labels are still passed as they are, since queries look them up.
+pub trait PrehashedHeavyDistinctHitterSketch: HeavyDistinctHitterSketch {
+    #[inline]
+    fn insert_hashed(&mut self, label: Self::Label, item_hash: u64) {
+        self.insert_hashed_with_outcome(label, item_hash);
+    }
+
+    fn insert_hashed_with_outcome(
+        &mut self,
+        label: Self::Label,
+        item_hash: u64,
+    ) -> InsertOutcome<Self::Label>;
+}
+
+/// What inserting an item did to the labels tracked by a sketch.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum InsertOutcome<L> {
+    /// The label was already tracked.
+    UpdatedExisting,
+    /// The label started being tracked without displacing another one.
+    CreatedNew,
+    /// The label started being tracked in place of `label`.
+    Evicted { label: L },
+    /// The label isn't tracked and didn't qualify to be.
+    RejectedByThreshold,
+}
+
+/// Sketches that count their mutations, so that query results can be cached
+/// until the next one (see [`Memoized`]).
+pub trait Versioned {
+    /// Changes whenever the sketch is mutated.
+    fn version(&self) -> u64;
+}
+
+/// Sketches that can audit their internal invariants, e.g., in debug builds
+/// or fuzz targets, to catch state corruption early.
+pub trait Validate {
+    type Violation: error::Error;
+
+    fn debug_validate(&self) -> Result<(), Self::Violation>;
+}
+
+/// Keeps the `k` entries with the highest cardinalities, sorted in decreasing
+/// order, without allocating.
+pub fn truncate_top<L>(entries: &mut Vec<(L, u64)>, k: usize) {
+    if k == 0 {
+        entries.clear();
+        return;
+    }
+    if k < entries.len() {
+        entries.select_nth_unstable_by(k - 1, |a, b| b.1.cmp(&a.1));
+        entries.truncate(k);
+    }
+    entries.sort_unstable_by_key(|&(_, cardinality)| Reverse(cardinality));
+}
+
+/// Groups `entries` by `classify`, keeping the `k` entries with the highest
+/// cardinalities of each group, sorted in decreasing order.
+pub fn group_top<L, G>(
+    entries: impl IntoIterator<Item = (L, u64)>,
+    k: usize,
+    classify: impl Fn(&L) -> G,
+) -> HashMap<G, Vec<(L, u64)>>
+where
+    G: Eq + Hash,
+{
+    let mut groups = HashMap::<G, Vec<(L, u64)>>::new();
+    for entry in entries {
+        let group = groups.entry(classify(&entry.0)).or_default();
+        group.push(entry);
+        // Groups are only truncated once they double, so that pushes stay
+        // amortized constant time.
+        if group.len() >= 2 * k.max(1) {
+            truncate_top(group, k);
+        }
+    }
+    groups.retain(|_, group| {
+        truncate_top(group, k);
+        !group.is_empty()
+    });
+    groups
 }
 
 pub trait New {
@@ -34,3 +476,15 @@ pub trait New {
 
     fn new(config: &Self::Config) -> Self;
 }
+
+/// Sketches that can be converted to a smaller config, e.g., fewer registers
+/// or counters, so that sketches built with different settings can still be
+/// merged once folded to the smallest of them.
+pub trait Fold: New + Sized {
+    type FoldError: error::Error;
+
+    /// The sketch `self` would be, approximately, had it been built with
+    /// `config`. Fails if `config` can't be reached from the sketch's own,
+    /// e.g., if it's larger or hashes items differently.
+    fn fold_to(&self, config: &Self::Config) -> Result<Self, Self::FoldError>;
+}
@@ -0,0 +1,82 @@
+use alloc::vec::Vec;
+use core::hash::Hash;
+
+use crate::{collections::HashMap, HeavyDistinctHitterSketch, Versioned};
+
+/// Caches the results of `top` and `cardinality` queries until the wrapped
+/// sketch is mutated.
+///
+/// Dashboards tend to issue the same queries over and over against a sketch
+/// that only changes between flushes; estimating the cardinality of every
+/// counter on each of them is wasted work.
+#[derive(Clone, Debug)]
+pub struct Memoized<S>
+where
+    S: HeavyDistinctHitterSketch,
+{
+    sketch: S,
+    version: u64,
+    top: Vec<(S::Label, u64)>,
+    top_k: usize,
+    cardinalities: HashMap<S::Label, u64>,
+}
+
+impl<S> Memoized<S>
+where
+    S: HeavyDistinctHitterSketch + Versioned,
+    S::Label: Eq + Hash + Clone,
+{
+    pub fn new(sketch: S) -> Self {
+        Self {
+            version: sketch.version(),
+            sketch,
+            top: Vec::new(),
+            top_k: 0,
+            cardinalities: HashMap::new(),
+        }
+    }
+
+    pub fn sketch(&self) -> &S {
+        &self.sketch
+    }
+
+    /// Mutations through the returned reference invalidate the caches on
+    /// the next query.
+    pub fn sketch_mut(&mut self) -> &mut S {
+        &mut self.sketch
+    }
+
+    pub fn into_inner(self) -> S {
+        self.sketch
+    }
+
+    pub fn top(&mut self, k: usize) -> Vec<(S::Label, u64)> {
+        self.refresh();
+        // A cached top for a larger `k` answers smaller ones as well.
+        if k > self.top_k {
+            self.sketch.top_into(k, &mut self.top);
+            self.top_k = k;
+        }
+        self.top.iter().take(k).cloned().collect()
+    }
+
+    pub fn cardinality(&mut self, label: &S::Label) -> u64 {
+        self.refresh();
+        if let Some(&cardinality) = self.cardinalities.get(label) {
+            return cardinality;
+        }
+        let cardinality = self.sketch.cardinality(label);
+        self.cardinalities.insert(label.clone(), cardinality);
+        cardinality
+    }
+
+    fn refresh(&mut self) {
+        let version = self.sketch.version();
+        if version != self.version {
+            self.version = version;
+            self.top.clear();
+            self.top_k = 0;
+            self.cardinalities.clear();
+        }
+    }
+}
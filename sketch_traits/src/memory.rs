@@ -0,0 +1,186 @@
+//! How much memory sketches take, e.g., to enforce memory budgets.
+//!
+//! Sizes are estimates: allocators round allocations up, and hash tables are
+//! assumed to be laid out like std's SwissTables, with one control byte per
+//! bucket.
+
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::mem::size_of;
+
+use crate::collections::{HashMap, HashSet};
+
+/// Values that can measure the memory they take, including what they own on
+/// the heap, e.g., the bytes of `String` labels.
+pub trait MemorySize {
+    /// The bytes owned on the heap, excluding the value itself.
+    fn heap_size(&self) -> usize;
+
+    /// The bytes taken by the value, inline and on the heap.
+    fn mem_size(&self) -> usize
+    where
+        Self: Sized,
+    {
+        size_of::<Self>() + self.heap_size()
+    }
+}
+
+/// The number of control bytes trailing the buckets of std's hash tables, so
+/// that lookups can read a whole SIMD group past the last bucket.
+const GROUP_WIDTH: usize = 16;
+
+/// The bytes std's hash tables allocate to hold `capacity` entries of `T`:
+/// their number of buckets is a power of two at least 8/7 of the capacity.
+fn hash_table_size<T>(capacity: usize) -> usize {
+    if capacity == 0 {
+        return 0;
+    }
+    let num_buckets = match capacity {
+        0..=3 => 4,
+        4..=7 => 8,
+        _ => (capacity.saturating_mul(8) / 7).next_power_of_two(),
+    };
+    num_buckets * (size_of::<T>() + 1) + GROUP_WIDTH
+}
+
+macro_rules! impl_inline_memory_size {
+    ($($t:ty),*) => {
+        $(
+            impl MemorySize for $t {
+                #[inline]
+                fn heap_size(&self) -> usize {
+                    0
+                }
+            }
+        )*
+    };
+}
+
+impl_inline_memory_size!(
+    (),
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64,
+    char,
+    bool
+);
+
+/// References don't own what they point to.
+impl<T: ?Sized> MemorySize for &T {
+    fn heap_size(&self) -> usize {
+        0
+    }
+}
+
+impl MemorySize for String {
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl MemorySize for Box<str> {
+    fn heap_size(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T: MemorySize> MemorySize for Box<T> {
+    fn heap_size(&self) -> usize {
+        (**self).mem_size()
+    }
+}
+
+impl<T: MemorySize> MemorySize for Option<T> {
+    fn heap_size(&self) -> usize {
+        self.as_ref().map_or(0, MemorySize::heap_size)
+    }
+}
+
+impl<T: MemorySize> MemorySize for Vec<T> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * size_of::<T>() + self.iter().map(MemorySize::heap_size).sum::<usize>()
+    }
+}
+
+impl<T: MemorySize, const N: usize> MemorySize for [T; N] {
+    fn heap_size(&self) -> usize {
+        self.iter().map(MemorySize::heap_size).sum()
+    }
+}
+
+impl<K, V, S> MemorySize for HashMap<K, V, S>
+where
+    K: MemorySize,
+    V: MemorySize,
+{
+    fn heap_size(&self) -> usize {
+        hash_table_size::<(K, V)>(self.capacity())
+            + self
+                .iter()
+                .map(|(key, value)| key.heap_size() + value.heap_size())
+                .sum::<usize>()
+    }
+}
+
+impl<T, S> MemorySize for HashSet<T, S>
+where
+    T: MemorySize,
+{
+    fn heap_size(&self) -> usize {
+        hash_table_size::<T>(self.capacity())
+            + self.iter().map(MemorySize::heap_size).sum::<usize>()
+    }
+}
+
+macro_rules! impl_tuple_memory_size {
+    ($(($($t:ident $i:tt),+)),*) => {
+        $(
+            impl<$($t: MemorySize),+> MemorySize for ($($t,)+) {
+                fn heap_size(&self) -> usize {
+                    0 $(+ self.$i.heap_size())+
+                }
+            }
+        )*
+    };
+}
+
+impl_tuple_memory_size!((A 0, B 1), (A 0, B 1, C 2), (A 0, B 1, C 2, D 3));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_counts_owned_heap_bytes() {
+        assert_eq!(7_u64.mem_size(), 8);
+        assert_eq!(String::with_capacity(10).heap_size(), 10);
+        assert_eq!(("label".to_string(), 1_u32).heap_size(), 5);
+        assert_eq!("label".heap_size(), 0);
+        let labels = vec![Some("a".to_string()), None];
+        assert_eq!(
+            labels.heap_size(),
+            labels.capacity() * size_of::<Option<String>>() + 1
+        );
+
+        let mut map = HashMap::new();
+        assert_eq!(map.heap_size(), 0);
+        map.insert("label".to_string(), 1_u64);
+        // Small tables get 4 buckets.
+        assert_eq!(
+            map.heap_size(),
+            4 * (size_of::<(String, u64)>() + 1) + GROUP_WIDTH + 5
+        );
+        let set = (0..100_u32).collect::<HashSet<_>>();
+        assert!(set.heap_size() >= 100 * size_of::<u32>());
+    }
+}
@@ -0,0 +1,36 @@
+use crate::MemorySize;
+
+/// Sketches that count what they do, so that operators can export their
+/// health to a metrics pipeline without wrapping every call site.
+///
+/// Counts only grow over the life of a sketch, as metrics pipelines expect
+/// of counters: clearing a sketch doesn't reset them, and merging one only
+/// counts the merge, not what the merged sketch counted. Clones start from
+/// the counts of the original, and counts aren't encoded or serialized.
+pub trait SketchMetrics: MemorySize {
+    /// The number of (label, item) pairs inserted.
+    fn num_inserts(&self) -> u64;
+
+    /// The number of times a label stopped being tracked to make room for
+    /// another one.
+    fn num_evictions(&self) -> u64;
+
+    /// The number of sketches merged into this one.
+    fn num_merges(&self) -> u64;
+
+    /// The bytes the sketch currently takes, inline and on the heap.
+    fn current_memory(&self) -> usize
+    where
+        Self: Sized,
+    {
+        self.mem_size()
+    }
+}
+
+/// The counts behind [`SketchMetrics`], for sketches to embed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct MetricCounts {
+    pub inserts: u64,
+    pub evictions: u64,
+    pub merges: u64,
+}
@@ -0,0 +1,40 @@
+use alloc::sync::Arc;
+use core::fmt;
+
+use crate::HeavyDistinctHitterSketch;
+
+/// Callbacks on the labels a sketch starts and stops tracking, e.g., to emit
+/// telemetry or to spill evicted labels to a secondary store.
+///
+/// Observers are called synchronously, from within inserts and merges, so
+/// they should be cheap, e.g., bump a counter or push to a channel. They take
+/// `&self` since the clones of a sketch share them; use interior mutability
+/// to keep state.
+pub trait Observer<L>: Send + Sync {
+    /// `label` started being tracked, by an insert or a merge.
+    fn on_admit(&self, label: &L) {
+        let _ = label;
+    }
+
+    /// `label` stopped being tracked to make room for other labels, with
+    /// `cardinality` its estimate then. Labels removed or cleared explicitly
+    /// aren't reported.
+    fn on_evict(&self, label: &L, cardinality: u64) {
+        let _ = (label, cardinality);
+    }
+}
+
+impl<L> fmt::Debug for dyn Observer<L> + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Observer")
+    }
+}
+
+/// Sketches that report admissions and evictions of labels to an
+/// [`Observer`].
+pub trait Observable: HeavyDistinctHitterSketch {
+    /// Reports the labels admitted and evicted from now on to `observer`,
+    /// replacing the previous one, if any. Observers aren't merged, encoded
+    /// or serialized.
+    fn set_observer(&mut self, observer: Arc<dyn Observer<Self::Label>>);
+}
@@ -0,0 +1,85 @@
+//! A pool of zeroed buffers, for sketches that are cleared and reused over
+//! and over, e.g., the counters of SSS with the offset reset strategy, to
+//! recycle the buffers they drop rather than rely on the allocator to.
+
+use alloc::vec::Vec;
+
+use crate::MemorySize;
+
+/// Zeroed byte buffers, which sketches hand over as they drop them, e.g.,
+/// when cleared (see [`CardinalitySketch::clear_into`]), and take back as
+/// they grow, e.g., into registers that are no longer sparse (see
+/// [`CardinalitySketch::insert_in`]).
+///
+/// Buffers are only handed out to those asking for their exact length, and
+/// zero bytes are valid zero registers of any layout of that length.
+///
+/// [`CardinalitySketch::clear_into`]: crate::CardinalitySketch::clear_into
+/// [`CardinalitySketch::insert_in`]: crate::CardinalitySketch::insert_in
+#[derive(Clone, Debug, Default)]
+pub struct BufferPool {
+    max_buffers: usize,
+    buffers: Vec<Vec<u8>>,
+}
+
+impl BufferPool {
+    /// An empty pool, keeping at most `max_buffers` buffers and dropping
+    /// those handed over beyond.
+    pub fn new(max_buffers: usize) -> Self {
+        Self {
+            max_buffers,
+            buffers: Vec::new(),
+        }
+    }
+
+    /// The number of buffers in the pool.
+    pub fn len(&self) -> usize {
+        self.buffers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffers.is_empty()
+    }
+
+    /// A zeroed buffer of `len` bytes, if there's any left.
+    pub fn take(&mut self, len: usize) -> Option<Vec<u8>> {
+        let i = self.buffers.iter().position(|buffer| buffer.len() == len)?;
+        Some(self.buffers.swap_remove(i))
+    }
+
+    /// Zeroes `buffer` and keeps it, unless the pool is full.
+    pub fn put(&mut self, mut buffer: Vec<u8>) {
+        if self.buffers.len() >= self.max_buffers {
+            return;
+        }
+        buffer.fill(0);
+        self.buffers.push(buffer);
+    }
+}
+
+impl MemorySize for BufferPool {
+    fn heap_size(&self) -> usize {
+        self.buffers.heap_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    #[test]
+    fn it_hands_out_zeroed_buffers_of_the_length_asked_for() {
+        let mut pool = BufferPool::new(2);
+        pool.put(vec![1; 4]);
+        pool.put(vec![2; 8]);
+        pool.put(vec![3; 8]);
+        assert_eq!(pool.len(), 2);
+        assert_eq!(pool.take(16), None);
+        assert_eq!(pool.take(8), Some(vec![0; 8]));
+        assert_eq!(pool.take(8), None);
+        assert_eq!(pool.take(4), Some(vec![0; 4]));
+        assert!(pool.is_empty());
+    }
+}
@@ -0,0 +1,151 @@
+//! Queries over a series of per-interval sketches, e.g., one per minute of a
+//! stream, that are no longer inserted into.
+//!
+//! ```ignore
+//! let intervals = Intervals::new(&sketches);
+//! // The 10 labels with the highest estimates over the last 12 intervals.
+//! let top = intervals.last(12).top_by(10, Aggregate::Max);
+//! // The labels in the top 100 of at least 80% of the intervals.
+//! let persistent = intervals.persistent(100, 0.8);
+//! ```
+
+use alloc::vec::Vec;
+use core::{cmp::Reverse, hash::Hash};
+
+use crate::{collections::HashMap, truncate_top, HeavyDistinctHitterSketch};
+
+/// How the estimates of a label in each interval are combined.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Aggregate {
+    /// The highest estimate, i.e., the label's peak.
+    Max,
+    /// The sum of the estimates, which overcounts items seen in several
+    /// intervals; use [`Intervals::merged`] for distinct counts over the
+    /// union of the intervals.
+    Sum,
+}
+
+/// A series of per-interval sketches, oldest first.
+#[derive(Clone, Copy, Debug)]
+pub struct Intervals<'a, S> {
+    sketches: &'a [S],
+}
+
+impl<'a, S> Intervals<'a, S>
+where
+    S: HeavyDistinctHitterSketch,
+    S::Label: Eq + Hash,
+{
+    pub fn new(sketches: &'a [S]) -> Self {
+        Self { sketches }
+    }
+
+    /// The `n` most recent intervals, or all of them if there are fewer.
+    pub fn last(&self, n: usize) -> Self {
+        let first = self.sketches.len().saturating_sub(n);
+        Self::new(&self.sketches[first..])
+    }
+
+    pub fn len(&self) -> usize {
+        self.sketches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sketches.is_empty()
+    }
+
+    /// The `k` labels with the highest aggregated estimates, in decreasing
+    /// order. Estimates are only taken from the intervals tracking the label.
+    pub fn top_by(&self, k: usize, aggregate: Aggregate) -> Vec<(&'a S::Label, u64)> {
+        let mut aggregated = HashMap::<&S::Label, u64>::new();
+        for sketch in self.sketches {
+            for label in sketch.labels() {
+                let estimate = sketch.cardinality(label);
+                let value = aggregated.entry(label).or_default();
+                *value = match aggregate {
+                    Aggregate::Max => (*value).max(estimate),
+                    Aggregate::Sum => value.saturating_add(estimate),
+                };
+            }
+        }
+        let mut entries = aggregated.into_iter().collect::<Vec<_>>();
+        truncate_top(&mut entries, k);
+        entries
+    }
+
+    /// The labels in the top `k` of at least `min_fraction` of the intervals,
+    /// with the number of such intervals, in decreasing order.
+    pub fn persistent(&self, k: usize, min_fraction: f64) -> Vec<(&'a S::Label, usize)> {
+        let mut counts = HashMap::<&S::Label, usize>::new();
+        for sketch in self.sketches {
+            for (label, _) in sketch.top(k) {
+                *counts.entry(label).or_default() += 1;
+            }
+        }
+        let min_count = min_fraction * self.sketches.len() as f64;
+        let mut entries = counts
+            .into_iter()
+            .filter(|&(_, count)| count as f64 >= min_count)
+            .collect::<Vec<_>>();
+        entries.sort_unstable_by_key(|&(_, count)| Reverse(count));
+        entries
+    }
+
+    /// All the intervals merged into a single sketch, to estimate distinct
+    /// counts over their union, or `None` if there are none.
+    pub fn merged(&self) -> Result<Option<S>, S::MergeError>
+    where
+        S: Clone,
+    {
+        let Some((first, rest)) = self.sketches.split_first() else {
+            return Ok(None);
+        };
+        let mut merged = first.clone();
+        for sketch in rest {
+            merged.merge(sketch)?;
+        }
+        Ok(Some(merged))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exact::Exact;
+
+    /// Label 0 is always around with 12 items, label `i` shows up in interval
+    /// `i` only, with `10 * i` items.
+    fn intervals() -> Vec<Exact<u32>> {
+        (1..=5)
+            .map(|interval| {
+                let mut sketch = Exact::default();
+                (0..12).for_each(|i| sketch.insert(0, &i));
+                (0..u64::from(interval) * 10).for_each(|i| sketch.insert(interval, &i));
+                sketch
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_aggregates_estimates_over_intervals() {
+        let sketches = intervals();
+        let intervals = Intervals::new(&sketches);
+        assert_eq!(intervals.top_by(2, Aggregate::Max), [(&5, 50), (&4, 40)]);
+        assert_eq!(intervals.top_by(1, Aggregate::Sum), [(&0, 60)]);
+        assert_eq!(intervals.last(2).top_by(3, Aggregate::Sum)[2], (&0, 24));
+        assert_eq!(intervals.last(10).len(), 5);
+
+        let merged = intervals.merged().unwrap().unwrap();
+        assert_eq!(merged.cardinality(&0), 12);
+        assert!(Intervals::new(&sketches[..0]).merged().unwrap().is_none());
+    }
+
+    #[test]
+    fn it_finds_persistent_labels() {
+        let sketches = intervals();
+        let intervals = Intervals::new(&sketches);
+        assert_eq!(intervals.persistent(2, 0.8), [(&0, 5)]);
+        assert_eq!(intervals.last(1).persistent(2, 1.0).len(), 2);
+        assert!(intervals.persistent(0, 0.5).is_empty());
+    }
+}
@@ -0,0 +1,258 @@
+use alloc::{collections::VecDeque, vec::Vec};
+use core::{error, fmt, ops::Range};
+
+use crate::HeavyDistinctHitterSketch;
+
+/// Sketches of consecutive intervals, e.g., minutes, rolled up into coarser
+/// ones, e.g., hours and days, each kept for its own retention, so that
+/// ranges of intervals can be queried long after the finest sketches are
+/// gone.
+///
+/// Intervals are numbered in units of the finest granularity, e.g., minutes
+/// since the epoch. Each sketch pushed is merged into the slot of every
+/// coarser level it falls in as it comes, so slots of the current hour or day
+/// are always up to date. Queries merge the fewest slots covering a range,
+/// picking the coarsest slots that fit within it.
+#[derive(Clone, Debug)]
+pub struct Rollup<S> {
+    /// An empty sketch, cloned to start new slots and query results.
+    empty: S,
+    levels: Vec<Level<S>>,
+    last_interval: Option<u64>,
+}
+
+/// A granularity of a [`Rollup`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RollupLevel {
+    /// The number of finest intervals per slot, e.g., 60 for hours of
+    /// minutes. Must be 1 for the first level, and a larger multiple of the
+    /// span of the previous level for the others.
+    pub span: u64,
+    /// The number of slots to keep, e.g., 24 for a day of hours.
+    pub retention: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Level<S> {
+    span: u64,
+    retention: usize,
+    /// Slots by start interval, oldest first.
+    slots: VecDeque<(u64, S)>,
+    /// Intervals before this one were dropped from the level.
+    horizon: u64,
+}
+
+impl<S> Rollup<S>
+where
+    S: HeavyDistinctHitterSketch + Clone,
+{
+    /// Rolls sketches up from the finest to the coarsest of `levels`, e.g.,
+    /// minutes, hours and days. `empty` is cloned to start coarser slots, and
+    /// should have the config of the sketches pushed.
+    pub fn new(empty: S, levels: &[RollupLevel]) -> Result<Self, RollupError<S::MergeError>> {
+        let spans_nest = levels.first().is_some_and(|level| level.span == 1)
+            && levels.windows(2).all(|pair| {
+                pair[1].span > pair[0].span && pair[1].span.is_multiple_of(pair[0].span)
+            });
+        if !spans_nest || levels.iter().any(|level| level.retention == 0) {
+            return Err(RollupError::InvalidLevels);
+        }
+        Ok(Self {
+            empty,
+            levels: levels
+                .iter()
+                .map(|level| Level {
+                    span: level.span,
+                    retention: level.retention,
+                    slots: VecDeque::with_capacity(level.retention),
+                    horizon: 0,
+                })
+                .collect(),
+            last_interval: None,
+        })
+    }
+
+    /// Adds the sketch of `interval`, which must come after the intervals
+    /// pushed so far. Gaps are fine, and query as empty intervals.
+    ///
+    /// The sketch is merged into coarser slots before being stored, so that
+    /// one that fails to merge, e.g., with another config, isn't stored.
+    pub fn push(&mut self, interval: u64, sketch: S) -> Result<(), RollupError<S::MergeError>> {
+        if self.last_interval.is_some_and(|last| interval <= last) {
+            return Err(RollupError::OutOfOrder);
+        }
+        let (finest, coarser) = self.levels.split_first_mut().unwrap_or_else(
+            // Levels are checked to be non-empty on construction.
+            || unreachable!(),
+        );
+        for level in coarser.iter_mut() {
+            let start = interval - interval % level.span;
+            match level.slots.back_mut() {
+                Some((last_start, slot)) if *last_start == start => {
+                    slot.merge(&sketch).map_err(RollupError::Merge)?;
+                }
+                _ => {
+                    let mut slot = self.empty.clone();
+                    slot.merge(&sketch).map_err(RollupError::Merge)?;
+                    level.push(start, slot);
+                }
+            }
+        }
+        finest.push(interval, sketch);
+        self.last_interval = Some(interval);
+        Ok(())
+    }
+
+    /// The merged sketches of the intervals in `range`, or an error if some
+    /// of them were dropped from every level that could cover them.
+    pub fn query(&self, range: Range<u64>) -> Result<S, RollupError<S::MergeError>> {
+        let mut merged = self.empty.clone();
+        let mut start = range.start;
+        while start < range.end {
+            // The coarsest slot starting at `start` that fits in the range
+            // and is still retained.
+            let level = self
+                .levels
+                .iter()
+                .rev()
+                .find(|level| {
+                    start.is_multiple_of(level.span)
+                        && start + level.span <= range.end
+                        && start >= level.horizon
+                })
+                .ok_or(RollupError::Expired)?;
+            if let Some(slot) = level.slot(start) {
+                merged.merge(slot).map_err(RollupError::Merge)?;
+            }
+            start += level.span;
+        }
+        Ok(merged)
+    }
+
+    pub fn clear(&mut self) {
+        for level in &mut self.levels {
+            level.slots.clear();
+            level.horizon = 0;
+        }
+        self.last_interval = None;
+    }
+}
+
+impl<S> Level<S> {
+    fn push(&mut self, start: u64, slot: S) {
+        if self.slots.len() == self.retention {
+            if let Some((oldest, _)) = self.slots.pop_front() {
+                self.horizon = oldest + self.span;
+            }
+        }
+        self.slots.push_back((start, slot));
+    }
+
+    fn slot(&self, start: u64) -> Option<&S> {
+        self.slots
+            .binary_search_by_key(&start, |&(slot_start, _)| slot_start)
+            .ok()
+            .map(|i| &self.slots[i].1)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RollupError<E> {
+    /// Level spans don't nest, or a level retains no slots.
+    InvalidLevels,
+    /// The interval doesn't come after the ones pushed so far.
+    OutOfOrder,
+    /// Some intervals of the range are no longer retained.
+    Expired,
+    Merge(E),
+}
+
+impl<E> fmt::Display for RollupError<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RollupError::InvalidLevels => {
+                write!(f, "rollup levels should nest and retain slots")
+            }
+            RollupError::OutOfOrder => write!(f, "intervals should be pushed in order"),
+            RollupError::Expired => write!(f, "the range is no longer retained"),
+            RollupError::Merge(e) => write!(f, "failed to merge sketches: {}", e),
+        }
+    }
+}
+
+impl<E> error::Error for RollupError<E> where E: fmt::Debug + fmt::Display {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exact::Exact;
+
+    /// Minutes kept for 2 hours, hours for a day, and days for a week.
+    fn rollup() -> Rollup<Exact<u32>> {
+        let levels = [
+            RollupLevel {
+                span: 1,
+                retention: 120,
+            },
+            RollupLevel {
+                span: 60,
+                retention: 24,
+            },
+            RollupLevel {
+                span: 60 * 24,
+                retention: 7,
+            },
+        ];
+        Rollup::new(Exact::default(), &levels).unwrap()
+    }
+
+    /// Label 0 gets item `minute`, so counts are numbers of minutes.
+    fn minute(minute: u64) -> Exact<u32> {
+        let mut sketch = Exact::default();
+        sketch.insert(0, &minute);
+        sketch
+    }
+
+    #[test]
+    fn ranges_are_covered_by_the_coarsest_slots() {
+        let mut rollup = rollup();
+        let days = 3;
+        for m in 0..days * 24 * 60 {
+            rollup.push(m, minute(m)).unwrap();
+        }
+        let count =
+            |rollup: &Rollup<Exact<u32>>, range| rollup.query(range).map(|s| s.cardinality(&0));
+
+        // Recent minutes, hours of the last day, and whole days.
+        assert_eq!(count(&rollup, 4250..4300), Ok(50));
+        assert_eq!(count(&rollup, 60 * 50..60 * 70 + 30), Ok(20 * 60 + 30));
+        assert_eq!(count(&rollup, 0..days * 24 * 60), Ok(days * 24 * 60));
+        // Minutes of the first day are only covered by whole days now.
+        assert_eq!(count(&rollup, 30..90), Err(RollupError::Expired));
+        assert_eq!(count(&rollup, 0..24 * 60), Ok(24 * 60));
+
+        assert_eq!(rollup.push(10, minute(10)), Err(RollupError::OutOfOrder));
+        rollup.clear();
+        assert_eq!(count(&rollup, 0..10), Ok(0));
+    }
+
+    #[test]
+    fn levels_must_nest() {
+        let level = |span, retention| RollupLevel { span, retention };
+        for levels in [
+            vec![],
+            vec![level(2, 10)],
+            vec![level(1, 10), level(60, 24), level(90, 10)],
+            vec![level(1, 10), level(1, 10)],
+            vec![level(1, 0)],
+        ] {
+            assert!(matches!(
+                Rollup::new(Exact::<u32>::default(), &levels),
+                Err(RollupError::InvalidLevels)
+            ));
+        }
+    }
+}
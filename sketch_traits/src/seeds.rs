@@ -0,0 +1,52 @@
+/// Seeds for configs built without explicit ones.
+///
+/// They're random with the `std` feature. Without it, there's no entropy
+/// source to draw them from, so they're fixed: pass seeds explicitly wherever
+/// sketches of different processes shouldn't hash items alike.
+pub fn default_seeds<const N: usize>() -> [u64; N] {
+    #[cfg(feature = "std")]
+    let seeds = core::array::from_fn(|_| rand::random());
+    #[cfg(not(feature = "std"))]
+    let seeds = core::array::from_fn(|i| splitmix64(0, i as u64));
+    seeds
+}
+
+/// Seeds derived from `key`, e.g., the name of a pipeline, so that processes
+/// deployed independently hash items alike by agreeing on a key rather than
+/// on raw seeds. The derivation won't change: the FNV-1a hash of the key's
+/// bytes seeds SplitMix64, whose outputs are the seeds.
+pub fn seeds_from_key<const N: usize>(key: &str) -> [u64; N] {
+    let state = key.bytes().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+    });
+    core::array::from_fn(|i| splitmix64(state, i as u64))
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// The `i`-th output of SplitMix64 seeded with `state`, to spread seeds over
+/// all their bits.
+fn splitmix64(state: u64, i: u64) -> u64 {
+    let mut z = state.wrapping_add((i + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_derive_stable_distinct_seeds() {
+        let seeds = seeds_from_key::<2>("service-ingest-v1");
+        assert_eq!(
+            seeds,
+            [9_803_663_413_376_232_147, 9_922_673_863_503_458_497]
+        );
+        assert_eq!(seeds_from_key::<2>("service-ingest-v1"), seeds);
+        assert_ne!(seeds_from_key::<2>("service-ingest-v2"), seeds);
+        assert_ne!(seeds[0], seeds[1]);
+    }
+}
@@ -0,0 +1,84 @@
+use alloc::{collections::BinaryHeap, vec::Vec};
+use core::{cmp::Ordering, iter::FusedIterator};
+
+/// Entries in decreasing order of cardinality, sorted lazily: collecting them
+/// heapifies them in linear time, and each entry then takes a logarithmic
+/// pop, so that taking the first few of many entries doesn't sort them all.
+///
+/// Entries with the same cardinality come out in no particular order.
+#[derive(Clone, Debug)]
+pub struct TopIter<L> {
+    heap: BinaryHeap<Ranked<L>>,
+}
+
+/// An entry ranked by its cardinality alone, so that labels don't need to be
+/// ordered.
+#[derive(Clone, Debug)]
+struct Ranked<L>(u64, L);
+
+impl<L> PartialEq for Ranked<L> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<L> Eq for Ranked<L> {}
+
+impl<L> PartialOrd for Ranked<L> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<L> Ord for Ranked<L> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+
+impl<L> FromIterator<(L, u64)> for TopIter<L> {
+    fn from_iter<T: IntoIterator<Item = (L, u64)>>(entries: T) -> Self {
+        Self {
+            heap: entries
+                .into_iter()
+                .map(|(label, cardinality)| Ranked(cardinality, label))
+                .collect::<Vec<_>>()
+                .into(),
+        }
+    }
+}
+
+impl<L> Iterator for TopIter<L> {
+    type Item = (L, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.heap
+            .pop()
+            .map(|Ranked(cardinality, label)| (label, cardinality))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.heap.len(), Some(self.heap.len()))
+    }
+}
+
+impl<L> ExactSizeIterator for TopIter<L> {}
+
+impl<L> FusedIterator for TopIter<L> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_come_out_in_decreasing_order() {
+        let entries = [("a", 3), ("b", 10), ("c", 1), ("d", 7)];
+        let top = entries.into_iter().collect::<TopIter<_>>();
+        assert_eq!(top.len(), 4);
+        assert_eq!(
+            top.collect::<Vec<_>>(),
+            [("b", 10), ("d", 7), ("a", 3), ("c", 1)]
+        );
+        assert_eq!(TopIter::<&str>::from_iter([]).next(), None);
+    }
+}
@@ -1,17 +1,20 @@
-use std::{error, fmt};
+use core::{error, fmt};
 
 use ahash::RandomState;
-use rand::random;
+use sketch_traits::{default_seeds, MemorySize, SeedableBuildHasher};
 
+/// The parameters of Spread sketches, hashing labels and items with `H`,
+/// ahash by default.
 #[derive(Clone, Debug)]
-pub struct Config<C> {
+pub struct Config<C, H = RandomState> {
     /// The level of redundancy of the underlying Count-Min Sketch, a.k.a the depth.
     pub(crate) num_rows: usize,
     /// The "width" of the sketch; corresponds to the number of labels we should
-    /// be able to accuractely estimate.
+    /// be able to accuractely estimate. Wide sketches are cheaper with compact
+    /// cardinality sketches, e.g., HyperLogLogs with 32 registers.
     pub(crate) num_cols: usize,
-    seeds: [u64; 8],
-    pub(crate) hash_builders: [RandomState; 2],
+    pub(crate) seeds: [u64; 8],
+    pub(crate) hash_builders: [H; 2],
     pub(crate) cardinality_sketch_config: C,
 }
 
@@ -21,35 +24,53 @@ impl<C> Config<C> {
         num_cols: usize,
         cardinality_sketch_config: C,
         seeds: Option<[u64; 8]>,
+    ) -> Result<Self, ConfigError> {
+        Self::with_hasher(num_rows, num_cols, cardinality_sketch_config, seeds)
+    }
+}
+
+impl<C, H> Config<C, H>
+where
+    H: SeedableBuildHasher,
+{
+    /// Like [`Config::new`], but hashing labels and items with `H`, built
+    /// from the seeds.
+    pub fn with_hasher(
+        num_rows: usize,
+        num_cols: usize,
+        cardinality_sketch_config: C,
+        seeds: Option<[u64; 8]>,
     ) -> Result<Self, ConfigError> {
         if num_rows == 0 {
             return Err(ConfigError::ZeroNumRows);
         } else if num_cols == 0 {
             return Err(ConfigError::ZeroNumCols);
         }
-        let seeds_or_random = seeds.unwrap_or_else(random);
+        let seeds_or_random = seeds.unwrap_or_else(default_seeds);
         Ok(Self {
             num_rows,
             num_cols,
             seeds: seeds_or_random,
             hash_builders: [
-                RandomState::with_seeds(
+                H::with_seeds([
                     seeds_or_random[0],
                     seeds_or_random[1],
                     seeds_or_random[2],
                     seeds_or_random[3],
-                ),
-                RandomState::with_seeds(
+                ]),
+                H::with_seeds([
                     seeds_or_random[4],
                     seeds_or_random[5],
                     seeds_or_random[6],
                     seeds_or_random[7],
-                ),
+                ]),
             ],
             cardinality_sketch_config,
         })
     }
+}
 
+impl<C, H> Config<C, H> {
     /// The amount of redundancy (usually a small constant)
     pub fn num_rows(&self) -> usize {
         self.num_rows
@@ -65,7 +86,97 @@ impl<C> Config<C> {
     }
 }
 
-impl<C> PartialEq for Config<C>
+/// The number of rows of [`Config::default`] and [`Config::builder`].
+pub const DEFAULT_NUM_ROWS: usize = 4;
+
+/// The number of columns of [`Config::default`] and [`Config::builder`], for
+/// about a hundred heavy hitters.
+pub const DEFAULT_NUM_COLS: usize = 128;
+
+impl<C> Config<C> {
+    /// Builds a config by naming only the parameters that differ from
+    /// [`Config::default`]'s.
+    pub fn builder() -> ConfigBuilder<C> {
+        ConfigBuilder {
+            num_rows: DEFAULT_NUM_ROWS,
+            num_cols: DEFAULT_NUM_COLS,
+            cardinality_sketch_config: None,
+            seeds: None,
+        }
+    }
+}
+
+/// [`DEFAULT_NUM_ROWS`] by [`DEFAULT_NUM_COLS`] cardinality sketches with
+/// default configs, and random seeds.
+impl<C> Default for Config<C>
+where
+    C: Default,
+{
+    fn default() -> Self {
+        Self::builder()
+            .build()
+            .expect("the default numbers of rows and columns are supported")
+    }
+}
+
+/// Builds [`Config`]s, see [`Config::builder`].
+#[derive(Clone, Debug)]
+pub struct ConfigBuilder<C> {
+    num_rows: usize,
+    num_cols: usize,
+    cardinality_sketch_config: Option<C>,
+    seeds: Option<[u64; 8]>,
+}
+
+impl<C> ConfigBuilder<C> {
+    pub fn rows(mut self, num_rows: usize) -> Self {
+        self.num_rows = num_rows;
+        self
+    }
+
+    pub fn cols(mut self, num_cols: usize) -> Self {
+        self.num_cols = num_cols;
+        self
+    }
+
+    /// Configures the cardinality sketches of the buckets, rather than using
+    /// the default config.
+    pub fn cardinality_sketch_config(mut self, cardinality_sketch_config: C) -> Self {
+        self.cardinality_sketch_config = Some(cardinality_sketch_config);
+        self
+    }
+
+    /// Seeds the hashers, rather than drawing random seeds.
+    pub fn seeds(mut self, seeds: [u64; 8]) -> Self {
+        self.seeds = Some(seeds);
+        self
+    }
+
+    /// Fails like [`Config::new`].
+    pub fn build(self) -> Result<Config<C>, ConfigError>
+    where
+        C: Default,
+    {
+        self.build_with_hasher()
+    }
+
+    /// Like [`build`](Self::build), but hashing labels and items with `H`, as
+    /// [`Config::with_hasher`].
+    pub fn build_with_hasher<H>(self) -> Result<Config<C, H>, ConfigError>
+    where
+        C: Default,
+        H: SeedableBuildHasher,
+    {
+        Config::with_hasher(
+            self.num_rows,
+            self.num_cols,
+            self.cardinality_sketch_config.unwrap_or_default(),
+            self.seeds,
+        )
+    }
+}
+
+impl<C, H> PartialEq for Config<C, H>
 where
     C: PartialEq,
 {
@@ -77,7 +188,16 @@ where
     }
 }
 
-impl<C> Eq for Config<C> where C: Eq {}
+impl<C, H> Eq for Config<C, H> where C: Eq {}
+
+impl<C, H> MemorySize for Config<C, H>
+where
+    C: MemorySize,
+{
+    fn heap_size(&self) -> usize {
+        self.cardinality_sketch_config.heap_size()
+    }
+}
 
 #[derive(Clone, Debug)]
 pub enum ConfigError {
@@ -99,3 +219,45 @@ impl fmt::Display for ConfigError {
 }
 
 impl error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builders_default_to_new() {
+        let hll_config = hll::Config::new(32, Some([0, 1, 2, 3, 4, 5, 6, 7])).unwrap();
+        let seeds = [8; 8];
+        assert_eq!(
+            Config::builder()
+                .cardinality_sketch_config(hll_config.clone())
+                .seeds(seeds)
+                .build()
+                .unwrap(),
+            Config::new(
+                DEFAULT_NUM_ROWS,
+                DEFAULT_NUM_COLS,
+                hll_config.clone(),
+                Some(seeds)
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            Config::builder()
+                .rows(2)
+                .cols(16)
+                .cardinality_sketch_config(hll_config.clone())
+                .seeds(seeds)
+                .build()
+                .unwrap(),
+            Config::new(2, 16, hll_config, Some(seeds)).unwrap()
+        );
+        assert!(matches!(
+            Config::<hll::Config>::builder().cols(0).build(),
+            Err(ConfigError::ZeroNumCols)
+        ));
+        let config = Config::<hll::Config>::default();
+        assert_eq!(config.num_rows(), DEFAULT_NUM_ROWS);
+        assert_eq!(config.num_cols(), DEFAULT_NUM_COLS);
+    }
+}
@@ -0,0 +1,72 @@
+use alloc::string::{String, ToString};
+use core::fmt::Write;
+
+use sketch_traits::{CardinalitySketch, LabelKey, New};
+
+use crate::SpreadSketch;
+
+impl<L, S, H> SpreadSketch<L, S, H>
+where
+    L: LabelKey,
+    S: CardinalitySketch + New,
+{
+    /// Renders the bucket grid as a Graphviz digraph, one row of nodes per
+    /// row of buckets, each showing its candidate label, level and count.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph spread {\n    node [shape=box];\n");
+        for (r, row) in self.buckets.chunks(self.config.num_cols).enumerate() {
+            let _ = writeln!(dot, "    {{\n        rank=same;");
+            for (c, bucket) in row.iter().enumerate() {
+                let label = match &bucket.label {
+                    Some(label) => label.display().to_string(),
+                    None => String::from("-"),
+                };
+                let _ = writeln!(
+                    dot,
+                    "        b_{}_{} [label=\"{}\\nlevel {}\\n{}\"];",
+                    r,
+                    c,
+                    label.escape_debug(),
+                    bucket.level,
+                    bucket.count(),
+                );
+            }
+            let _ = writeln!(dot, "    }}");
+        }
+        // Invisible edges stack the rows in order.
+        for r in 1..self.config.num_rows {
+            let _ = writeln!(dot, "    b_{}_0 -> b_{}_0 [style=invis];", r - 1, r);
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hll::HyperLogLog;
+    use sketch_traits::HeavyDistinctHitterSketch;
+
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn it_renders_the_bucket_grid() {
+        let config = Config::new(
+            2,
+            3,
+            hll::Config::new(64, Some([8, 9, 10, 11, 12, 13, 14, 15])).unwrap(),
+            Some([0, 1, 2, 3, 4, 5, 6, 7]),
+        )
+        .unwrap();
+        let mut sketch = SpreadSketch::<String, HyperLogLog<u64>>::new(&config);
+        for i in 0..100 {
+            sketch.insert(String::from("a \"quoted\" label"), &i);
+        }
+        let dot = sketch.to_dot();
+        assert!(dot.starts_with("digraph spread {"));
+        assert!(dot.contains("b_1_2 [label="));
+        assert!(dot.contains("a \\\"quoted\\\" label"));
+        assert!(dot.contains("b_0_0 -> b_1_0 [style=invis];"));
+    }
+}
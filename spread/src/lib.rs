@@ -7,19 +7,38 @@
 //! and network-wide detection of superspread- ers. In IEEE INFOCOM 2020 - IEEE
 //! Conference on Computer Communications, pages 1608–1617, 2020.
 
+#![cfg_attr(not(test), deny(clippy::unwrap_used))]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
 mod config;
+#[cfg(feature = "dot")]
+mod dot;
+#[cfg(feature = "rayon")]
+mod par;
+#[cfg(feature = "serde")]
+mod serde_impls;
 
-use std::{
+use alloc::vec::Vec;
+use core::{
+    cmp::Reverse,
     error,
     fmt::{self, Debug},
     hash::Hash,
     iter::repeat_with,
 };
 
-use itertools::Itertools;
-use sketch_traits::{CardinalitySketch, HeavyDistinctHitterSketch, New};
+use ahash::RandomState;
+use sketch_traits::{
+    truncate_top, CardinalitySketch, HeavyDistinctHitterSketch, InsertOutcome, MemorySize, New,
+    PrehashedCardinalitySketch, PrehashedHeavyDistinctHitterSketch, SeedableBuildHasher, Validate,
+    Versioned,
+};
+#[cfg(feature = "metrics")]
+use sketch_traits::{MetricCounts, SketchMetrics};
 
-pub use crate::config::{Config, ConfigError};
+pub use crate::config::{Config, ConfigBuilder, ConfigError, DEFAULT_NUM_COLS, DEFAULT_NUM_ROWS};
 
 #[derive(Clone, Debug)]
 struct Bucket<L, S> {
@@ -44,14 +63,30 @@ where
 impl<L, S> Bucket<L, S>
 where
     S: CardinalitySketch,
-    L: Clone,
+    L: PartialEq + Clone,
 {
-    fn update(&mut self, label: L, item: &S::Item, l: u8) {
+    fn clear(&mut self) {
+        self.label = None;
+        self.sketch.clear();
+        self.level = 0;
+    }
+
+    /// Inserts an item into the sketch with `insert`, and makes `label` the
+    /// candidate if its level `l` is at least the current candidate's.
+    fn update(&mut self, label: L, l: u8, insert: impl FnOnce(&mut S)) -> InsertOutcome<L> {
         // TODO: insert the (label, item) pair instead of just item
-        self.sketch.insert(item);
-        if self.level <= l {
-            self.label = Some(label);
+        insert(&mut self.sketch);
+        if self.label.as_ref() == Some(&label) {
+            self.level = self.level.max(l);
+            InsertOutcome::UpdatedExisting
+        } else if self.level <= l {
             self.level = l;
+            match self.label.replace(label) {
+                Some(evicted) => InsertOutcome::Evicted { label: evicted },
+                None => InsertOutcome::CreatedNew,
+            }
+        } else {
+            InsertOutcome::RejectedByThreshold
         }
     }
 
@@ -59,30 +94,65 @@ where
         self.sketch.cardinality()
     }
 
-    fn merge(&mut self, other: &Self) -> Result<(), S::MergeError> {
-        if other.level > self.level {
+    /// Keeps the candidate label with the highest level. Ties are broken by
+    /// keeping the label with the lowest `label_hash`, so that merging is
+    /// commutative.
+    fn merge(&mut self, other: &Self, label_hash: impl Fn(&L) -> u64) -> Result<(), S::MergeError> {
+        self.sketch.merge(&other.sketch)?;
+        if other.outranks(self, label_hash) {
             self.level = other.level;
             self.label = other.label.clone();
         }
-        self.sketch.merge(&other.sketch)
+        Ok(())
+    }
+
+    /// Like `merge`, but moves the candidate label of `other` over.
+    fn merge_owned(
+        &mut self,
+        other: Self,
+        label_hash: impl Fn(&L) -> u64,
+    ) -> Result<(), S::MergeError> {
+        self.sketch.merge(&other.sketch)?;
+        if other.outranks(self, label_hash) {
+            self.level = other.level;
+            self.label = other.label;
+        }
+        Ok(())
+    }
+
+    fn outranks(&self, other: &Self, label_hash: impl Fn(&L) -> u64) -> bool {
+        let priority = |bucket: &Self| {
+            (
+                bucket.level,
+                bucket.label.as_ref().map(|l| Reverse(label_hash(l))),
+            )
+        };
+        priority(self) > priority(other)
     }
 }
 
 #[derive(Clone, Debug)]
-pub struct SpreadSketch<L, S>
+pub struct SpreadSketch<L, S, H = RandomState>
 where
     S: New,
 {
-    config: Config<S::Config>,
+    config: Config<S::Config, H>,
     buckets: Vec<Bucket<L, S>>,
+    /// The items of all labels, which each row of buckets splits between
+    /// them.
+    total: S,
+    version: u64,
+    #[cfg(feature = "metrics")]
+    metrics: MetricCounts,
 }
 
-impl<L, S> New for SpreadSketch<L, S>
+impl<L, S, H> New for SpreadSketch<L, S, H>
 where
     S: New,
     S::Config: Clone,
+    H: SeedableBuildHasher,
 {
-    type Config = Config<S::Config>;
+    type Config = Config<S::Config, H>;
 
     fn new(config: &Self::Config) -> Self {
         Self {
@@ -90,26 +160,67 @@ where
             buckets: repeat_with(|| Bucket::new(&config.cardinality_sketch_config))
                 .take(config.num_rows * config.num_cols)
                 .collect(),
+            total: S::new(&config.cardinality_sketch_config),
+            version: 0,
+            #[cfg(feature = "metrics")]
+            metrics: MetricCounts::default(),
         }
     }
 }
-impl<L, S> HeavyDistinctHitterSketch for SpreadSketch<L, S>
+impl<L, S, H> HeavyDistinctHitterSketch for SpreadSketch<L, S, H>
 where
     L: Eq + Hash + Clone,
     S: CardinalitySketch + New,
     S::Item: Hash,
     S::Config: Eq,
+    H: SeedableBuildHasher,
 {
     type Label = L;
     type Item = S::Item;
     type MergeError = MergeError;
 
-    fn insert(&mut self, label: L, item: &S::Item) {
-        let l = u8::try_from(self.global_hash(&label, item).leading_zeros()).unwrap();
-        for r in 0..self.config.num_rows {
-            let c = self.row_hash(r, &label);
-            let bucket_index = self.bucket_index(r, c);
-            self.buckets[bucket_index].update(label.clone(), item, l);
+    /// A label spans several buckets, so the outcomes of updating each of
+    /// them are combined: evictions take precedence over updates, which take
+    /// precedence over creations. Only the first evicted label is reported.
+    fn insert_with_outcome(&mut self, label: L, item: &S::Item) -> InsertOutcome<L> {
+        self.insert_key(
+            label,
+            item,
+            |sketch| sketch.prefetch(item),
+            |sketch| sketch.insert(item),
+        )
+    }
+
+    /// Locates the buckets of a label once per run of consecutive pairs
+    /// sharing it, rather than hashing the label in every row for each item.
+    fn insert_iter(&mut self, pairs: impl IntoIterator<Item = (L, S::Item)>) {
+        let mut pairs = pairs.into_iter().peekable();
+        let mut bucket_indices = Vec::with_capacity(self.config.num_rows);
+        while let Some((label, item)) = pairs.next() {
+            bucket_indices.clear();
+            bucket_indices.extend(
+                (0..self.config.num_rows).map(|r| self.bucket_index(r, self.row_hash(r, &label))),
+            );
+            let mut next = Some(item);
+            while let Some(item) = next {
+                self.version = self.version.wrapping_add(1);
+                #[cfg(feature = "metrics")]
+                {
+                    self.metrics.inserts += 1;
+                }
+                // A 64-bit hash has at most 64 leading zeros, so this cannot truncate.
+                let l = self.global_hash(&label, &item).leading_zeros() as u8;
+                self.total.insert(&item);
+                for &bucket_index in &bucket_indices {
+                    let bucket_outcome =
+                        self.buckets[bucket_index]
+                            .update(label.clone(), l, |sketch| sketch.insert(&item));
+                    self.count_eviction(&bucket_outcome);
+                }
+                next = pairs
+                    .next_if(|(next, _)| *next == label)
+                    .map(|(_, item)| item);
+            }
         }
     }
 
@@ -117,15 +228,60 @@ where
         if self.config != other.config {
             return Err(MergeError::ConfigMismatch);
         }
+        self.version = self.version.wrapping_add(1);
+        #[cfg(feature = "metrics")]
+        {
+            self.metrics.merges += 1;
+        }
+        self.total.merge(&other.total).unwrap_or_else(
+            // By construction, parameters cannot mismatch.
+            |_| unreachable!(),
+        );
+        let hash_builder = &self.config.hash_builders[0];
         self.buckets
             .iter_mut()
             .zip(other.buckets.iter())
-            .for_each(|(s, o)| s.merge(o).unwrap());
+            .for_each(|(s, o)| {
+                s.merge(o, |label| hash_builder.hash_one(label))
+                    .unwrap_or_else(
+                        // By construction, parameters cannot mismatch.
+                        |_| unreachable!(),
+                    )
+            });
+        Ok(())
+    }
+
+    fn merge_owned(&mut self, other: Self) -> Result<(), Self::MergeError> {
+        if self.config != other.config {
+            return Err(MergeError::ConfigMismatch);
+        }
+        self.version = self.version.wrapping_add(1);
+        #[cfg(feature = "metrics")]
+        {
+            self.metrics.merges += 1;
+        }
+        self.total.merge(&other.total).unwrap_or_else(
+            // By construction, parameters cannot mismatch.
+            |_| unreachable!(),
+        );
+        let hash_builder = &self.config.hash_builders[0];
+        self.buckets
+            .iter_mut()
+            .zip(other.buckets)
+            .for_each(|(s, o)| {
+                s.merge_owned(o, |label| hash_builder.hash_one(label))
+                    .unwrap_or_else(
+                        // By construction, parameters cannot mismatch.
+                        |_| unreachable!(),
+                    )
+            });
         Ok(())
     }
 
     fn clear(&mut self) {
-        todo!()
+        self.version = self.version.wrapping_add(1);
+        self.buckets.iter_mut().for_each(Bucket::clear);
+        self.total.clear();
     }
 
     fn cardinality(&self, label: &L) -> u64 {
@@ -137,39 +293,168 @@ where
             .map(|bucket_index| &self.buckets[bucket_index])
             .map(|bucket| bucket.count())
             .min()
-            .unwrap()
+            .unwrap_or(0) // there is at least one row
     }
 
-    fn top(&self, k: usize) -> Vec<(&L, u64)> {
+    /// Estimated from a sketch of its own, rather than by merging the
+    /// buckets of a row on every query.
+    fn total_cardinality(&self) -> Option<u64> {
+        Some(self.total.cardinality())
+    }
+
+    fn labels(&self) -> impl Iterator<Item = &L> {
         self.buckets
             .iter()
-            .filter_map(|b| b.label.as_ref())
-            .into_iter()
-            .unique()
+            .enumerate()
+            .filter_map(|(i, b)| b.label.as_ref().map(|l| (i / self.config.num_cols, l)))
+            .filter(|&(row, label)| self.first_row_with(row, label))
+            .map(|(_, label)| label)
+    }
+
+    fn top(&self, k: usize) -> Vec<(&L, u64)> {
+        let mut entries = self
+            .labels()
             .map(|l| (l, self.cardinality(l)))
-            .sorted_by_key(|&(_, cardinality)| -(cardinality as i32))
-            .take(k)
-            .collect()
+            .collect::<Vec<_>>();
+        truncate_top(&mut entries, k);
+        entries
+    }
+
+    fn top_into(&self, k: usize, out: &mut Vec<(L, u64)>) {
+        out.clear();
+        out.extend(
+            self.buckets
+                .iter()
+                .enumerate()
+                .filter_map(|(i, b)| b.label.as_ref().map(|l| (i / self.config.num_cols, l)))
+                .filter(|&(row, label)| self.first_row_with(row, label))
+                .map(|(_, label)| (label.clone(), self.cardinality(label))),
+        );
+        truncate_top(out, k);
+    }
+
+    fn labels_above(&self, threshold: u64) -> Vec<(&L, u64)> {
+        // A label's estimate is at most the count of any bucket holding it,
+        // so buckets at or below the threshold can be skipped without hashing.
+        let mut entries = self
+            .buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.count() > threshold)
+            .filter_map(|(i, b)| b.label.as_ref().map(|l| (i / self.config.num_cols, l)))
+            .filter(|&(row, label)| self.first_row_with(row, label))
+            .map(|(_, label)| (label, self.cardinality(label)))
+            .filter(|&(_, cardinality)| cardinality > threshold)
+            .collect::<Vec<_>>();
+        entries.sort_unstable_by_key(|&(_, cardinality)| Reverse(cardinality));
+        entries
     }
 }
 
-impl<L, S> SpreadSketch<L, S>
+impl<L, S, H> SpreadSketch<L, S, H>
 where
     S: New,
+    H: SeedableBuildHasher,
 {
-    pub fn config(&self) -> &Config<S::Config> {
+    pub fn config(&self) -> &Config<S::Config, H> {
         &self.config
     }
 }
 
-impl<L, S> SpreadSketch<L, S>
+/// Item hashes are leveled and counted as `u64` items would be.
+impl<L, S, H> PrehashedHeavyDistinctHitterSketch for SpreadSketch<L, S, H>
+where
+    L: Eq + Hash + Clone,
+    S: PrehashedCardinalitySketch + New,
+    S::Item: Hash,
+    S::Config: Eq,
+    H: SeedableBuildHasher,
+{
+    #[inline]
+    fn insert_hashed_with_outcome(&mut self, label: L, item_hash: u64) -> InsertOutcome<L> {
+        self.insert_key(
+            label,
+            &item_hash,
+            |sketch| sketch.prefetch_hashed(item_hash),
+            |sketch| sketch.insert_hashed(item_hash),
+        )
+    }
+}
+
+impl<L, S, H> SpreadSketch<L, S, H>
 where
     S: CardinalitySketch + New,
+    L: PartialEq + Clone + Hash,
+    H: SeedableBuildHasher,
+{
+    /// Inserts an item, leveled by `key`, into the buckets of `label` with
+    /// `insert`: `key` is either the item or its hash.
+    ///
+    /// The buckets of a batch of rows are located and prefetched, then what
+    /// `insert` updates in their sketches is prefetched with `prefetch`,
+    /// before any of them is updated, so that the cache misses of the
+    /// buckets, and then of the registers they point to, overlap.
+    #[inline]
+    fn insert_key(
+        &mut self,
+        label: L,
+        key: &impl Hash,
+        prefetch_sketch: impl Fn(&S),
+        insert: impl Fn(&mut S),
+    ) -> InsertOutcome<L> {
+        self.version = self.version.wrapping_add(1);
+        #[cfg(feature = "metrics")]
+        {
+            self.metrics.inserts += 1;
+        }
+        const BATCH_SIZE: usize = 8;
+        // A 64-bit hash has at most 64 leading zeros, so this cannot truncate.
+        let l = self.global_hash(&label, key).leading_zeros() as u8;
+        insert(&mut self.total);
+        let mut bucket_indices = [0; BATCH_SIZE];
+        let mut outcome = InsertOutcome::RejectedByThreshold;
+        for first_row in (0..self.config.num_rows).step_by(BATCH_SIZE) {
+            let rows = first_row..(first_row + BATCH_SIZE).min(self.config.num_rows);
+            let len = rows.len();
+            for (bucket_index, r) in bucket_indices.iter_mut().zip(rows) {
+                *bucket_index = self.bucket_index(r, self.row_hash(r, &label));
+                prefetch(&self.buckets[*bucket_index]);
+            }
+            for &bucket_index in &bucket_indices[..len] {
+                prefetch_sketch(&self.buckets[bucket_index].sketch);
+            }
+            for &bucket_index in &bucket_indices[..len] {
+                let bucket_outcome = self.buckets[bucket_index].update(label.clone(), l, &insert);
+                self.count_eviction(&bucket_outcome);
+                if precedence(&bucket_outcome) > precedence(&outcome) {
+                    outcome = bucket_outcome;
+                }
+            }
+        }
+        outcome
+    }
+
+    /// Counts the buckets whose candidate an insert replaced, for
+    /// [`SketchMetrics`](sketch_traits::SketchMetrics).
+    #[inline]
+    fn count_eviction(&mut self, bucket_outcome: &InsertOutcome<L>) {
+        #[cfg(feature = "metrics")]
+        if let InsertOutcome::Evicted { .. } = bucket_outcome {
+            self.metrics.evictions += 1;
+        }
+        #[cfg(not(feature = "metrics"))]
+        let _ = bucket_outcome;
+    }
+}
+
+impl<L, S, H> SpreadSketch<L, S, H>
+where
+    S: New,
     L: Hash,
-    S::Item: Hash,
+    H: SeedableBuildHasher,
 {
     #[inline]
-    fn global_hash(&self, label: &L, item: &S::Item) -> u64 {
+    fn global_hash(&self, label: &L, item: &impl Hash) -> u64 {
         self.config.hash_builders[0].hash_one((item, label))
     }
 
@@ -179,12 +464,168 @@ where
         self.config.hash_builders[1].hash_one((row, label)) as usize % self.config.num_cols
     }
 
+    /// Whether `row` is the first row whose bucket for `label` holds it as a
+    /// candidate. Labels can only be held by the buckets they hash to, which
+    /// lets us deduplicate candidates without a set.
+    fn first_row_with(&self, row: usize, label: &L) -> bool
+    where
+        L: Eq,
+    {
+        (0..row).all(|r| {
+            let bucket_index = self.bucket_index(r, self.row_hash(r, label));
+            self.buckets[bucket_index].label.as_ref() != Some(label)
+        })
+    }
+
     #[inline]
     fn bucket_index(&self, row_index: usize, col_index: usize) -> usize {
         row_index * self.config.num_cols + col_index
     }
 }
 
+fn precedence<L>(outcome: &InsertOutcome<L>) -> u8 {
+    match outcome {
+        InsertOutcome::RejectedByThreshold => 0,
+        InsertOutcome::CreatedNew => 1,
+        InsertOutcome::UpdatedExisting => 2,
+        InsertOutcome::Evicted { .. } => 3,
+    }
+}
+
+/// Hints the CPU to start loading `value` into the cache.
+#[inline(always)]
+fn prefetch<T>(value: &T) {
+    #[cfg(target_arch = "x86_64")]
+    // SAFETY: prefetching is a hint that never faults, and `value` is valid anyway.
+    unsafe {
+        use core::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+        _mm_prefetch::<_MM_HINT_T0>((value as *const T).cast());
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    let _ = value;
+}
+
+impl<L, S, H> Extend<(L, S::Item)> for SpreadSketch<L, S, H>
+where
+    L: Eq + Hash + Clone,
+    S: CardinalitySketch + New,
+    S::Item: Hash,
+    S::Config: Eq,
+    H: SeedableBuildHasher,
+{
+    fn extend<T: IntoIterator<Item = (L, S::Item)>>(&mut self, pairs: T) {
+        self.insert_iter(pairs);
+    }
+}
+
+impl<L, S> MemorySize for Bucket<L, S>
+where
+    L: MemorySize,
+    S: MemorySize,
+{
+    fn heap_size(&self) -> usize {
+        self.label.heap_size() + self.sketch.heap_size()
+    }
+}
+
+impl<L, S, H> MemorySize for SpreadSketch<L, S, H>
+where
+    L: MemorySize,
+    S: New + MemorySize,
+    S::Config: MemorySize,
+{
+    fn heap_size(&self) -> usize {
+        self.config.heap_size() + self.buckets.heap_size() + self.total.heap_size()
+    }
+}
+
+/// Evictions count the buckets whose candidate label an insert replaced:
+/// inserting a label can evict several, and candidates replaced by merges
+/// aren't counted.
+#[cfg(feature = "metrics")]
+impl<L, S, H> SketchMetrics for SpreadSketch<L, S, H>
+where
+    L: MemorySize,
+    S: New + MemorySize,
+    S::Config: MemorySize,
+{
+    fn num_inserts(&self) -> u64 {
+        self.metrics.inserts
+    }
+
+    fn num_evictions(&self) -> u64 {
+        self.metrics.evictions
+    }
+
+    fn num_merges(&self) -> u64 {
+        self.metrics.merges
+    }
+}
+
+impl<L, S, H> Versioned for SpreadSketch<L, S, H>
+where
+    S: New,
+{
+    fn version(&self) -> u64 {
+        self.version
+    }
+}
+
+impl<L, S, H> Validate for SpreadSketch<L, S, H>
+where
+    S: New + Validate,
+{
+    type Violation = InvariantViolation<S::Violation>;
+
+    fn debug_validate(&self) -> Result<(), Self::Violation> {
+        if self.buckets.len() != self.config.num_rows * self.config.num_cols {
+            return Err(InvariantViolation::NumBucketsMismatch);
+        }
+        for bucket in self.buckets.iter() {
+            if bucket.label.is_none() && bucket.level != 0 {
+                return Err(InvariantViolation::EmptyBucketWithLevel);
+            }
+            // Levels are leading zeros of a 64-bit hash.
+            if bucket.level > 64 {
+                return Err(InvariantViolation::LevelOutOfRange);
+            }
+            bucket
+                .sketch
+                .debug_validate()
+                .map_err(InvariantViolation::Bucket)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum InvariantViolation<E> {
+    NumBucketsMismatch,
+    EmptyBucketWithLevel,
+    LevelOutOfRange,
+    Bucket(E),
+}
+
+impl<E> fmt::Display for InvariantViolation<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvariantViolation::NumBucketsMismatch => {
+                write!(f, "the number of buckets does not match the config")
+            }
+            InvariantViolation::EmptyBucketWithLevel => {
+                write!(f, "a bucket without a label has a non-zero level")
+            }
+            InvariantViolation::LevelOutOfRange => write!(f, "a bucket level exceeds 64"),
+            InvariantViolation::Bucket(e) => write!(f, "invalid bucket sketch: {}", e),
+        }
+    }
+}
+
+impl<E> error::Error for InvariantViolation<E> where E: Debug + fmt::Display {}
+
 #[derive(Clone, Debug)]
 pub enum MergeError {
     ConfigMismatch,
@@ -205,6 +646,7 @@ mod tests {
     use std::collections::{HashMap, HashSet};
 
     use hll::HyperLogLog;
+    use proptest::{collection::vec, prelude::*};
     use sketch_traits::HeavyDistinctHitterSketch;
 
     use super::*;
@@ -238,11 +680,25 @@ mod tests {
         let mut bucket: Bucket<String, HyperLogLog<u32>> = Bucket::new(&seeded_hll_config(32));
         let label = String::from("label");
         for i in 0..100 {
-            bucket.update(label.clone(), &i, 1);
+            bucket.update(label.clone(), 1, |sketch| sketch.insert(&i));
         }
         assert!(relative_error(bucket.count(), 100) < 0.5);
     }
 
+    #[test]
+    fn failed_bucket_merges_keep_the_candidate() {
+        let mut bucket: Bucket<u32, HyperLogLog<u32>> = Bucket::new(&seeded_hll_config(32));
+        bucket.update(1, 1, |sketch| sketch.insert(&1));
+        let other_config = hll::Config::new(32, Some([1; 8])).unwrap();
+        let mut other: Bucket<u32, HyperLogLog<u32>> = Bucket::new(&other_config);
+        other.update(2, 5, |sketch| sketch.insert(&2));
+        let label_hash = |label: &u32| u64::from(*label);
+        assert!(bucket.merge(&other, label_hash).is_err());
+        assert_eq!((bucket.label, bucket.level), (Some(1), 1));
+        assert!(bucket.merge_owned(other, label_hash).is_err());
+        assert_eq!((bucket.label, bucket.level), (Some(1), 1));
+    }
+
     #[test]
     fn new_sketch_works() {
         let mut sketch: SpreadSketch<String, HyperLogLog<u32>> =
@@ -272,6 +728,161 @@ mod tests {
         }
     }
 
+    #[test]
+    fn top_into_matches_top() {
+        let mut sketch = SpreadSketch::<_, HyperLogLog<_>>::new(&seeded_config(4, 100));
+        for l in 1..20 {
+            for i in 0..10 * l {
+                sketch.insert(l, &i);
+            }
+        }
+        let mut out = vec![];
+        sketch.top_into(5, &mut out);
+        let mut top = sketch
+            .top(5)
+            .into_iter()
+            .map(|(l, c)| (*l, c))
+            .collect::<Vec<_>>();
+        assert_eq!(out.len(), 5);
+        assert_eq!(
+            out.iter().map(|&(_, c)| c).collect::<Vec<_>>(),
+            top.iter().map(|&(_, c)| c).collect::<Vec<_>>()
+        );
+        out.sort();
+        top.sort();
+        assert_eq!(out, top);
+    }
+
+    #[test]
+    fn labels_match_top() {
+        let mut sketch = SpreadSketch::<_, HyperLogLog<_>>::new(&seeded_config(4, 100));
+        for l in 1..20 {
+            for i in 0..10 * l {
+                sketch.insert(l, &i);
+            }
+        }
+        // Labels span several buckets but are listed once.
+        let mut labels = sketch.labels().copied().collect::<Vec<_>>();
+        let mut top = sketch
+            .top(usize::MAX)
+            .into_iter()
+            .map(|(l, _)| *l)
+            .collect::<Vec<_>>();
+        labels.sort();
+        top.sort();
+        assert_eq!(labels, top);
+        assert_eq!(sketch.num_labels(), labels.len());
+    }
+
+    #[test]
+    fn insert_iter_matches_insert() {
+        let pairs = (0..10_000_u64).map(|i| (i / 7 % 300, i));
+        let mut one_by_one = SpreadSketch::<_, HyperLogLog<_>>::new(&seeded_config(4, 100));
+        pairs
+            .clone()
+            .for_each(|(label, i)| one_by_one.insert(label, &i));
+        let mut batched = SpreadSketch::<_, HyperLogLog<_>>::new(&seeded_config(4, 100));
+        batched.extend(pairs);
+        assert_eq!(batched.top(usize::MAX), one_by_one.top(usize::MAX));
+        assert_eq!(batched.total_cardinality(), one_by_one.total_cardinality());
+        assert_eq!(batched.version(), one_by_one.version());
+    }
+
+    #[test]
+    fn top_grouped_matches_top_per_group() {
+        let mut sketch = SpreadSketch::<u32, HyperLogLog<u64>>::new(&seeded_config(4, 100));
+        for i in 0..10_000_u64 {
+            let label = (i / 7 % 300) as u32;
+            sketch.insert(label, &(i % (u64::from(label) * 3 + 1)));
+        }
+        let classify = |label: &u32| label % 3;
+        let grouped = sketch.top_grouped(4, classify);
+        let groups = sketch.labels().map(classify).collect::<HashSet<_>>();
+        assert_eq!(grouped.len(), groups.len());
+        for (group, top) in grouped {
+            let mut expected = sketch.top(usize::MAX);
+            expected.retain(|(label, _)| classify(label) == group);
+            assert_eq!(
+                top.iter().map(|&(_, c)| c).collect::<Vec<_>>(),
+                expected.iter().take(4).map(|&(_, c)| c).collect::<Vec<_>>()
+            );
+        }
+        assert!(sketch.top_grouped(0, classify).is_empty());
+    }
+
+    #[test]
+    fn hashes_are_inserted_like_u64_items() {
+        let mut hashed = SpreadSketch::<_, HyperLogLog<String>>::new(&seeded_config(4, 100));
+        let mut items = SpreadSketch::<_, HyperLogLog<u64>>::new(&seeded_config(4, 100));
+        for i in 0..10_000_u64 {
+            hashed.insert_hashed(i / 7 % 300, i);
+            items.insert(i / 7 % 300, &i);
+        }
+        assert_eq!(hashed.top(usize::MAX), items.top(usize::MAX));
+    }
+
+    #[test]
+    fn cleared_sketches_behave_like_fresh_ones() {
+        let pairs = || (0..10_000_u64).map(|i| (i % 300, i * i % 1_000));
+        let mut fresh = SpreadSketch::<_, HyperLogLog<_>>::new(&seeded_config(4, 100));
+        let mut cleared = fresh.clone();
+        cleared.extend((0..10_000_u64).map(|i| (i % 1_000, i)));
+        cleared.clear();
+        assert_eq!(cleared.top(usize::MAX), []);
+
+        fresh.extend(pairs());
+        cleared.extend(pairs());
+        assert_eq!(cleared.top(usize::MAX), fresh.top(usize::MAX));
+        assert!(cleared.debug_validate().is_ok());
+    }
+
+    #[test]
+    fn top_iter_matches_top() {
+        let mut sketch = SpreadSketch::<_, HyperLogLog<_>>::new(&seeded_config(4, 100));
+        sketch.extend((0..10_000_u64).map(|i| (i % 300, i * i % 1_000)));
+        let top = sketch.top(usize::MAX);
+        let lazy = sketch.top_iter().collect::<Vec<_>>();
+        assert_eq!(lazy.len(), top.len());
+        assert_eq!(
+            lazy.iter().map(|&(_, c)| c).collect::<Vec<_>>(),
+            top.iter().map(|&(_, c)| c).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn labels_above_matches_top() {
+        let mut sketch = SpreadSketch::<_, HyperLogLog<_>>::new(&seeded_config(4, 100));
+        sketch.extend((0..10_000_u64).map(|i| (i % 300, i * i % 1_000)));
+        for threshold in [0, 10, 30, u64::MAX] {
+            let expected = sketch
+                .top(usize::MAX)
+                .into_iter()
+                .filter(|&(_, cardinality)| cardinality > threshold)
+                .map(|(_, cardinality)| cardinality)
+                .collect::<Vec<_>>();
+            let above = sketch.labels_above(threshold);
+            assert_eq!(above.iter().map(|&(_, c)| c).collect::<Vec<_>>(), expected);
+        }
+    }
+
+    #[test]
+    fn it_validates_invariants() {
+        let mut sketch = SpreadSketch::<_, HyperLogLog<_>>::new(&seeded_config(4, 100));
+        assert!(sketch.debug_validate().is_ok());
+        for l in 1..20 {
+            for i in 0..10 * l {
+                sketch.insert(l, &i);
+            }
+        }
+        assert!(sketch.debug_validate().is_ok());
+
+        sketch.buckets.pop();
+        assert!(matches!(
+            sketch.debug_validate(),
+            Err(InvariantViolation::NumBucketsMismatch)
+        ));
+    }
+
     #[test]
     fn merge_works() {
         let mut sketch1 = SpreadSketch::<_, HyperLogLog<_>>::new(&seeded_config(4, 100));
@@ -304,4 +915,182 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn total_cardinality_matches_a_row_of_buckets() {
+        let mut left = SpreadSketch::<u32, HyperLogLog<u64>>::new(&seeded_config(4, 100));
+        let mut right = left.clone();
+        left.extend((0..5_000_u64).map(|i| ((i % 1_000) as u32, i)));
+        right.extend((5_000..10_000_u64).map(|i| ((i % 1_000) as u32, i)));
+        assert!(relative_error(left.total_cardinality().unwrap(), 5_000) < 0.1);
+        assert!(left.merge(&right).is_ok());
+        let mut row = HyperLogLog::new(&left.config.cardinality_sketch_config);
+        for bucket in &left.buckets[..100] {
+            assert!(row.merge(&bucket.sketch).is_ok());
+        }
+        assert_eq!(left.total_cardinality(), Some(row.cardinality()));
+        left.clear();
+        assert_eq!(left.total_cardinality(), Some(0));
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn metrics_count_inserts_evictions_and_merges() {
+        let pairs = (0..5_000_u64).map(|i| ((i % 1_000) as u32, i));
+        let mut inserted = SpreadSketch::<u32, HyperLogLog<u64>>::new(&seeded_config(4, 100));
+        let mut num_evictions = 0;
+        for (label, item) in pairs.clone() {
+            if let InsertOutcome::Evicted { .. } = inserted.insert_with_outcome(label, &item) {
+                num_evictions += 1;
+            }
+        }
+        // Inserting a label can evict the candidates of several buckets.
+        assert!(inserted.num_evictions() >= num_evictions);
+        assert_eq!(inserted.num_inserts(), 5_000);
+
+        let mut extended = SpreadSketch::<u32, HyperLogLog<u64>>::new(&seeded_config(4, 100));
+        extended.extend(pairs);
+        assert_eq!(extended.num_inserts(), inserted.num_inserts());
+        assert_eq!(extended.num_evictions(), inserted.num_evictions());
+
+        assert!(extended.merge(&inserted).is_ok());
+        assert!(extended.merge_owned(inserted).is_ok());
+        assert_eq!(extended.num_merges(), 2);
+        extended.clear();
+        assert_eq!(extended.num_inserts(), 5_000);
+        assert_eq!(extended.current_memory(), extended.mem_size());
+    }
+
+    #[test]
+    fn it_never_panics_on_arbitrary_input() {
+        let config = Config::new(2, 2, seeded_hll_config(16), Some(SEEDS)).unwrap();
+        proptest!(|(
+            pairs1 in vec((any::<u8>(), any::<u64>()), 0..200),
+            pairs2 in vec((any::<u8>(), any::<u64>()), 0..200),
+            queries in vec(any::<u8>(), 0..10),
+            k in 0..5_usize,
+        )| {
+            let mut sketch1: SpreadSketch<u8, HyperLogLog<u64>> = SpreadSketch::new(&config);
+            pairs1.iter().for_each(|(label, item)| sketch1.insert(*label, item));
+            let mut sketch2: SpreadSketch<u8, HyperLogLog<u64>> = SpreadSketch::new(&config);
+            pairs2.iter().for_each(|(label, item)| sketch2.insert(*label, item));
+
+            prop_assert!(sketch1.merge(&sketch2).is_ok());
+            queries.iter().for_each(|label| {
+                sketch1.cardinality(label);
+            });
+            prop_assert!(sketch1.top(k).len() <= k);
+            prop_assert!(sketch1.debug_validate().is_ok());
+        });
+    }
+
+    fn candidates(sketch: &SpreadSketch<u8, HyperLogLog<u64>>) -> Vec<(Option<u8>, u8, u64)> {
+        sketch
+            .buckets
+            .iter()
+            .map(|bucket| (bucket.label, bucket.level, bucket.count()))
+            .collect()
+    }
+
+    #[test]
+    fn it_supports_many_columns_of_compact_sketches() {
+        let config = Config::new(4, 1 << 16, seeded_hll_config(32), Some(SEEDS)).unwrap();
+        let mut sketch: SpreadSketch<u64, HyperLogLog<u64>> = SpreadSketch::new(&config);
+        (0..10_000_u64).for_each(|i| sketch.insert(0, &i));
+        (1..10_000_u64).for_each(|label| sketch.insert(label, &label));
+
+        let top = sketch.top(1);
+        assert_eq!(*top[0].0, 0);
+        // 32 registers have a standard error of about 18%.
+        assert!(relative_error(10_000, top[0].1) < 0.5);
+        assert!(sketch.debug_validate().is_ok());
+    }
+
+    #[test]
+    fn merge_is_commutative() {
+        let config = seeded_config(2, 4);
+        // Few distinct labels and items make level ties likely.
+        proptest!(|(
+            pairs1 in vec((0..8_u8, 0..16_u64), 0..100),
+            pairs2 in vec((0..8_u8, 0..16_u64), 0..100),
+        )| {
+            let mut sketch1: SpreadSketch<u8, HyperLogLog<u64>> = SpreadSketch::new(&config);
+            pairs1.iter().for_each(|(label, item)| sketch1.insert(*label, item));
+            let mut sketch2: SpreadSketch<u8, HyperLogLog<u64>> = SpreadSketch::new(&config);
+            pairs2.iter().for_each(|(label, item)| sketch2.insert(*label, item));
+
+            let mut merged12 = sketch1.clone();
+            prop_assert!(merged12.merge(&sketch2).is_ok());
+            let mut merged21 = sketch2;
+            prop_assert!(merged21.merge(&sketch1).is_ok());
+
+            prop_assert_eq!(candidates(&merged12), candidates(&merged21));
+        });
+    }
+
+    const MERGE_ORDERS: [[usize; 3]; 6] = [
+        [0, 1, 2],
+        [0, 2, 1],
+        [1, 0, 2],
+        [1, 2, 0],
+        [2, 0, 1],
+        [2, 1, 0],
+    ];
+
+    #[test]
+    fn merge_order_does_not_matter() {
+        let config = seeded_config(2, 4);
+        proptest!(|(pairs in vec(vec((0..8_u8, 0..16_u64), 0..100), 3))| {
+            let sketches = pairs
+                .iter()
+                .map(|pairs| {
+                    let mut sketch: SpreadSketch<u8, HyperLogLog<u64>> = SpreadSketch::new(&config);
+                    pairs.iter().for_each(|(label, item)| sketch.insert(*label, item));
+                    sketch
+                })
+                .collect::<Vec<_>>();
+            let merged = MERGE_ORDERS.map(|order| {
+                let mut merged = sketches[order[0]].clone();
+                order[1..].iter().for_each(|&i| merged.merge(&sketches[i]).unwrap());
+                candidates(&merged)
+            });
+            for candidates in merged.iter() {
+                prop_assert_eq!(candidates, &merged[0]);
+            }
+
+            // Merging partial aggregates, as in an aggregation tree.
+            let mut left = sketches[1].clone();
+            prop_assert!(left.merge(&sketches[2]).is_ok());
+            let mut tree = sketches[0].clone();
+            prop_assert!(tree.merge(&left).is_ok());
+            prop_assert_eq!(candidates(&tree), merged[0].clone());
+            let mut owned = sketches[0].clone();
+            prop_assert!(owned.merge_owned(left).is_ok());
+            prop_assert_eq!(candidates(&owned), merged[0].clone());
+        });
+    }
+
+    #[test]
+    fn rejected_merges_keep_the_version() {
+        let mut sketch: SpreadSketch<u64, HyperLogLog<u64>> =
+            SpreadSketch::new(&seeded_config(2, 4));
+        sketch.insert(1, &1);
+        let other = SpreadSketch::new(&seeded_config(2, 8));
+        let version = sketch.version();
+        assert!(matches!(
+            sketch.merge(&other),
+            Err(MergeError::ConfigMismatch)
+        ));
+        assert!(matches!(
+            sketch.merge_owned(other),
+            Err(MergeError::ConfigMismatch)
+        ));
+        assert_eq!(sketch.version(), version);
+    }
+
+    #[test]
+    fn it_is_send_and_sync_regardless_of_items() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<SpreadSketch<u32, HyperLogLog<std::rc::Rc<str>>>>();
+    }
 }
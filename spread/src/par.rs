@@ -0,0 +1,89 @@
+//! Parallel merges, for aggregators merging many sketches.
+
+use core::hash::Hash;
+
+use rayon::prelude::*;
+use sketch_traits::{CardinalitySketch, New, SeedableBuildHasher};
+
+use crate::{MergeError, SpreadSketch};
+
+impl<L, S, H> SpreadSketch<L, S, H>
+where
+    L: Eq + Hash + Clone + Send + Sync,
+    S: CardinalitySketch + New + Send + Sync,
+    S::Config: Eq,
+    H: SeedableBuildHasher,
+{
+    /// Like `merge`, but merges the buckets in parallel, on rayon's thread
+    /// pool. Buckets are merged independently, so the result is the same.
+    pub fn par_merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        if self.config != other.config {
+            return Err(MergeError::ConfigMismatch);
+        }
+        self.version = self.version.wrapping_add(1);
+        #[cfg(feature = "metrics")]
+        {
+            self.metrics.merges += 1;
+        }
+        self.total.merge(&other.total).unwrap_or_else(
+            // By construction, parameters cannot mismatch.
+            |_| unreachable!(),
+        );
+        let hash_builder = &self.config.hash_builders[0];
+        self.buckets
+            .par_iter_mut()
+            .zip(other.buckets.par_iter())
+            .for_each(|(s, o)| {
+                s.merge(o, |label| hash_builder.hash_one(label))
+                    .unwrap_or_else(
+                        // By construction, parameters cannot mismatch.
+                        |_| unreachable!(),
+                    )
+            });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hll::HyperLogLog;
+    use sketch_traits::{HeavyDistinctHitterSketch, Versioned};
+
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn par_merge_matches_merge() {
+        let config = Config::new(
+            4,
+            100,
+            hll::Config::new(64, Some([0; 8])).unwrap(),
+            Some([1; 8]),
+        )
+        .unwrap();
+        let mut left = SpreadSketch::<_, HyperLogLog<_>>::new(&config);
+        let mut right = left.clone();
+        left.extend((0..10_000_u64).map(|i| (i % 300, i)));
+        right.extend((0..10_000_u64).map(|i| (i % 500, i * 7)));
+
+        let mut merged = left.clone();
+        assert!(merged.merge(&right).is_ok());
+        assert!(left.par_merge(&right).is_ok());
+        assert_eq!(left.top(usize::MAX), merged.top(usize::MAX));
+    }
+
+    #[test]
+    fn rejected_merges_keep_the_version() {
+        let hll_config = hll::Config::new(64, Some([0; 8])).unwrap();
+        let config = Config::new(4, 100, hll_config.clone(), Some([1; 8])).unwrap();
+        let mut sketch = SpreadSketch::<_, HyperLogLog<_>>::new(&config);
+        sketch.insert(1_u64, &1_u64);
+        let other = SpreadSketch::new(&Config::new(4, 100, hll_config, Some([2; 8])).unwrap());
+        let version = sketch.version();
+        assert!(matches!(
+            sketch.par_merge(&other),
+            Err(MergeError::ConfigMismatch)
+        ));
+        assert_eq!(sketch.version(), version);
+    }
+}
@@ -0,0 +1,224 @@
+//! Sketches serialize their config and buckets, each as a label, the state of
+//! its cardinality sketch and its level, then the sketch of all items;
+//! configs only serialize their parameters and seeds, and are rebuilt, and
+//! checked, from them when deserializing.
+
+use alloc::vec::Vec;
+
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+use sketch_traits::{CardinalitySketch, New, SeedableBuildHasher};
+
+use crate::{Bucket, Config, SpreadSketch};
+
+#[derive(Serialize)]
+#[serde(rename = "Config")]
+struct ConfigRef<'a, C> {
+    num_rows: usize,
+    num_cols: usize,
+    seeds: &'a [u64; 8],
+    cardinality_sketch_config: &'a C,
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "Config")]
+struct ConfigRepr<C> {
+    num_rows: usize,
+    num_cols: usize,
+    seeds: [u64; 8],
+    cardinality_sketch_config: C,
+}
+
+impl<C, H> Serialize for Config<C, H>
+where
+    C: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ConfigRef {
+            num_rows: self.num_rows,
+            num_cols: self.num_cols,
+            seeds: &self.seeds,
+            cardinality_sketch_config: &self.cardinality_sketch_config,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, C, H> Deserialize<'de> for Config<C, H>
+where
+    C: Deserialize<'de>,
+    H: SeedableBuildHasher,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = ConfigRepr::deserialize(deserializer)?;
+        Config::with_hasher(
+            repr.num_rows,
+            repr.num_cols,
+            repr.cardinality_sketch_config,
+            Some(repr.seeds),
+        )
+        .map_err(D::Error::custom)
+    }
+}
+
+/// Serializes buckets as a sequence of (label, sketch, level) triples.
+struct BucketsRef<'a, L, S>(&'a [Bucket<L, S>]);
+
+impl<L, S> Serialize for BucketsRef<'_, L, S>
+where
+    L: Serialize,
+    S: Serialize,
+{
+    fn serialize<T: Serializer>(&self, serializer: T) -> Result<T::Ok, T::Error> {
+        serializer.collect_seq(
+            self.0
+                .iter()
+                .map(|bucket| (&bucket.label, &bucket.sketch, bucket.level)),
+        )
+    }
+}
+
+#[derive(Serialize)]
+#[serde(
+    rename = "SpreadSketch",
+    bound(serialize = "L: Serialize, S: Serialize, C: Serialize")
+)]
+struct SketchRef<'a, L, S, C, H> {
+    config: &'a Config<C, H>,
+    buckets: BucketsRef<'a, L, S>,
+    total: &'a S,
+}
+
+#[derive(Deserialize)]
+#[serde(
+    rename = "SpreadSketch",
+    bound(
+        deserialize = "L: Deserialize<'de>, S: Deserialize<'de>, C: Deserialize<'de>, H: SeedableBuildHasher"
+    )
+)]
+struct SketchRepr<L, S, C, H> {
+    config: Config<C, H>,
+    buckets: Vec<(Option<L>, S, u8)>,
+    /// Missing from sketches serialized before it was tracked.
+    total: Option<S>,
+}
+
+impl<L, S, H> Serialize for SpreadSketch<L, S, H>
+where
+    L: Serialize,
+    S: New + Serialize,
+    S::Config: Serialize,
+    H: SeedableBuildHasher,
+{
+    fn serialize<T: Serializer>(&self, serializer: T) -> Result<T::Ok, T::Error> {
+        SketchRef {
+            config: &self.config,
+            buckets: BucketsRef(&self.buckets),
+            total: &self.total,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, L, S, H> Deserialize<'de> for SpreadSketch<L, S, H>
+where
+    L: Deserialize<'de>,
+    S: CardinalitySketch + New + Deserialize<'de>,
+    S::Config: Deserialize<'de> + Clone,
+    H: SeedableBuildHasher,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = SketchRepr::<L, S, S::Config, H>::deserialize(deserializer)?;
+        let mut sketch = Self::new(&repr.config);
+        if repr.buckets.len() != sketch.buckets.len() {
+            return Err(D::Error::custom(
+                "the number of buckets does not match the config",
+            ));
+        }
+        for (bucket, (label, bucket_sketch, level)) in sketch.buckets.iter_mut().zip(repr.buckets) {
+            if label.is_none() && level != 0 {
+                return Err(D::Error::custom("an empty bucket has a non-zero level"));
+            }
+            // Levels are leading zeros of a 64-bit hash.
+            if level > 64 {
+                return Err(D::Error::custom("a bucket's level is out of range"));
+            }
+            bucket
+                .sketch
+                .merge(&bucket_sketch)
+                .map_err(|_| D::Error::custom("a bucket's config does not match the sketch's"))?;
+            bucket.label = label;
+            bucket.level = level;
+        }
+        match repr.total {
+            Some(total) => sketch
+                .total
+                .merge(&total)
+                .map_err(|_| D::Error::custom("the total's config does not match the sketch's"))?,
+            // Every item was inserted into exactly one bucket of each row, so
+            // the first row holds all of them.
+            None => {
+                for bucket in &sketch.buckets[..sketch.config.num_cols] {
+                    sketch.total.merge(&bucket.sketch).unwrap_or_else(
+                        // By construction, parameters cannot mismatch.
+                        |_| unreachable!(),
+                    );
+                }
+            }
+        }
+        Ok(sketch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hll::HyperLogLog;
+    use sketch_traits::HeavyDistinctHitterSketch;
+
+    use super::*;
+
+    type HllSpreadSketch<L, I> = SpreadSketch<L, HyperLogLog<I>>;
+
+    fn config() -> Config<hll::Config> {
+        Config::new(
+            2,
+            8,
+            hll::Config::new(64, Some([8, 9, 10, 11, 12, 13, 14, 15])).unwrap(),
+            Some([0, 1, 2, 3, 4, 5, 6, 7]),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn it_round_trips() {
+        let mut sketch = HllSpreadSketch::<String, u64>::new(&config());
+        for label in 0..20_u64 {
+            for i in 0..label * 10 {
+                sketch.insert(label.to_string(), &i);
+            }
+        }
+        let json = serde_json::to_string(&sketch).unwrap();
+        let decoded: HllSpreadSketch<String, u64> = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.config, sketch.config);
+        assert_eq!(decoded.top(usize::MAX), sketch.top(usize::MAX));
+        assert_eq!(decoded.total_cardinality(), sketch.total_cardinality());
+
+        // Sketches serialized without their total rebuild it from the buckets.
+        let mut json = serde_json::to_value(&sketch).unwrap();
+        json.as_object_mut().unwrap().remove("total");
+        let decoded: HllSpreadSketch<String, u64> = serde_json::from_value(json).unwrap();
+        assert_eq!(decoded.total_cardinality(), sketch.total_cardinality());
+    }
+
+    #[test]
+    fn it_rejects_invalid_states() {
+        let json = serde_json::to_value(HllSpreadSketch::<u32, u64>::new(&config())).unwrap();
+
+        let mut missing = json.clone();
+        missing["buckets"].as_array_mut().unwrap().pop();
+        assert!(serde_json::from_value::<HllSpreadSketch<u32, u64>>(missing).is_err());
+
+        let mut level = json;
+        level["buckets"][0][2] = 3.into();
+        assert!(serde_json::from_value::<HllSpreadSketch<u32, u64>>(level).is_err());
+    }
+}
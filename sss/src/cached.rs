@@ -1,4 +1,4 @@
-use sketch_traits::{CardinalitySketch, New};
+use sketch_traits::{BufferPool, CardinalitySketch, Estimate, MemorySize, New};
 
 #[derive(Clone, Debug)]
 pub(crate) struct Cached<S> {
@@ -21,6 +21,37 @@ where
     }
 }
 
+impl<S> Cached<S>
+where
+    S: CardinalitySketch,
+{
+    #[cfg(feature = "serde")]
+    pub(crate) fn from_sketch(sketch: S) -> Self {
+        Self {
+            cardinality: sketch.cardinality(),
+            sketch,
+        }
+    }
+
+    /// Whether the cached cardinality matches the underlying sketch's.
+    pub(crate) fn is_consistent(&self) -> bool {
+        self.cardinality == self.sketch.cardinality()
+    }
+
+    pub(crate) fn sketch(&self) -> &S {
+        &self.sketch
+    }
+}
+
+impl<S> MemorySize for Cached<S>
+where
+    S: MemorySize,
+{
+    fn heap_size(&self) -> usize {
+        self.sketch.heap_size()
+    }
+}
+
 impl<S> CardinalitySketch for Cached<S>
 where
     S: CardinalitySketch,
@@ -28,9 +59,49 @@ where
     type Item = S::Item;
     type MergeError = S::MergeError;
 
+    /// Only refreshes the cached cardinality if the sketch may have changed.
     #[inline]
     fn insert(&mut self, item: &Self::Item) {
-        self.sketch.insert(item);
+        self.insert_report(item);
+    }
+
+    #[inline]
+    fn insert_report(&mut self, item: &Self::Item) -> bool {
+        let changed = self.sketch.insert_report(item);
+        if changed {
+            self.cardinality = self.sketch.cardinality();
+        }
+        changed
+    }
+
+    #[inline]
+    fn insert_in(&mut self, item: &Self::Item, pool: &mut BufferPool) -> bool {
+        let changed = self.sketch.insert_in(item, pool);
+        if changed {
+            self.cardinality = self.sketch.cardinality();
+        }
+        changed
+    }
+
+    /// Only refreshes the cached cardinality once for the whole batch.
+    #[inline]
+    fn insert_all<'a>(&mut self, items: impl IntoIterator<Item = &'a Self::Item>)
+    where
+        Self::Item: 'a,
+    {
+        self.sketch.insert_all(items);
+        self.cardinality = self.sketch.cardinality();
+    }
+
+    #[inline]
+    fn insert_all_in<'a>(
+        &mut self,
+        items: impl IntoIterator<Item = &'a Self::Item>,
+        pool: &mut BufferPool,
+    ) where
+        Self::Item: 'a,
+    {
+        self.sketch.insert_all_in(items, pool);
         self.cardinality = self.sketch.cardinality();
     }
 
@@ -47,8 +118,23 @@ where
         self.cardinality = 0;
     }
 
+    #[inline]
+    fn clear_into(&mut self, pool: &mut BufferPool) {
+        self.sketch.clear_into(pool);
+        self.cardinality = 0;
+    }
+
     #[inline]
     fn cardinality(&self) -> u64 {
         self.cardinality
     }
+
+    fn cardinality_with_error(&self) -> Option<Estimate> {
+        self.sketch.cardinality_with_error()
+    }
+
+    /// Not cached: computed by the sketch.
+    fn cardinality_f64(&self) -> f64 {
+        self.sketch.cardinality_f64()
+    }
 }
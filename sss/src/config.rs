@@ -1,19 +1,21 @@
-use std::{error, fmt};
+use core::{error, fmt};
+
+use sketch_traits::MemorySize;
 
 use crate::counter::ResetStrategy;
 
 #[derive(Clone, PartialEq, Eq, Debug)]
-pub struct Config<C> {
+pub struct Config<C, R = ResetStrategy> {
     /// The maximum number of counters to keep.
     pub(crate) max_num_counters: usize,
-    pub(crate) reset_strategy: ResetStrategy,
+    pub(crate) reset_strategy: R,
     pub(crate) cardinality_sketch_config: C,
 }
 
-impl<C> Config<C> {
+impl<C, R> Config<C, R> {
     pub fn new(
         size: usize,
-        reset_strategy: ResetStrategy,
+        reset_strategy: R,
         cardinality_sketch_config: C,
     ) -> Result<Self, ConfigError> {
         if size == 0 {
@@ -26,7 +28,7 @@ impl<C> Config<C> {
         })
     }
 
-    pub fn reset_strategy(&self) -> &ResetStrategy {
+    pub fn reset_strategy(&self) -> &R {
         &self.reset_strategy
     }
 
@@ -35,6 +37,16 @@ impl<C> Config<C> {
     }
 }
 
+/// Reset strategies are assumed to be held inline.
+impl<C, R> MemorySize for Config<C, R>
+where
+    C: MemorySize,
+{
+    fn heap_size(&self) -> usize {
+        self.cardinality_sketch_config.heap_size()
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum ConfigError {
     ZeroSize,
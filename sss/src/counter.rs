@@ -1,7 +1,31 @@
-use sketch_traits::CardinalitySketch;
+use sketch_traits::{BufferPool, CardinalitySketch, Count, MemorySize};
+
+/// What to do with a counter's sketch before mapping it to a different label.
+///
+/// Implement this to prototype other bias corrections than the ones offered
+/// by [`ResetStrategy`].
+pub trait Reset {
+    fn reset<S, C>(&self, counter: &mut Counter<S, C>)
+    where
+        S: CardinalitySketch,
+        C: Count;
+
+    /// Like [`Reset::reset`], but hands the buffers the sketch drops, if
+    /// any, to `pool`, for the sketches of other labels to grow into.
+    /// Defaults to ignoring it.
+    fn reset_in<S, C>(&self, counter: &mut Counter<S, C>, pool: &mut BufferPool)
+    where
+        S: CardinalitySketch,
+        C: Count,
+    {
+        let _ = pool;
+        self.reset(counter);
+    }
+}
 
 /// What to do with a sketch before mapping it to a different label.
 #[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ResetStrategy {
     /// Sketches are reused as they are across labels.
     Recycle,
@@ -9,36 +33,101 @@ pub enum ResetStrategy {
     Offset,
 }
 
+impl Reset for ResetStrategy {
+    /// Like [`Reset::reset_in`], with a pool that keeps nothing.
+    #[inline]
+    fn reset<S, C>(&self, counter: &mut Counter<S, C>)
+    where
+        S: CardinalitySketch,
+        C: Count,
+    {
+        self.reset_in(counter, &mut BufferPool::new(0));
+    }
+
+    /// Offset sketches are cleared into `pool`.
+    #[inline]
+    fn reset_in<S, C>(&self, counter: &mut Counter<S, C>, pool: &mut BufferPool)
+    where
+        S: CardinalitySketch,
+        C: Count,
+    {
+        match self {
+            ResetStrategy::Recycle => {
+                // do nothing; we're going to keep using the sketch as is
+            }
+            ResetStrategy::Offset => {
+                counter.offset = counter
+                    .offset
+                    .saturating_add(C::from_u64(counter.sketch.cardinality()));
+                counter.sketch.clear_into(pool);
+            }
+        }
+    }
+}
+
+/// A cardinality sketch and the offset added to its estimates.
+///
+/// Offsets are counted in `C`, e.g., `f64` for counters whose offsets decay.
 #[derive(Clone, Debug)]
-pub(crate) struct Counter<S> {
+pub struct Counter<S, C = u64> {
     pub(crate) sketch: S,
-    pub(crate) offset: u64,
+    pub(crate) offset: C,
+}
+
+/// Offsets are counts, which are held inline.
+impl<S, C> MemorySize for Counter<S, C>
+where
+    S: MemorySize,
+{
+    fn heap_size(&self) -> usize {
+        self.sketch.heap_size()
+    }
 }
 
-impl<S> Counter<S> {
-    pub(crate) fn new(sketch: S) -> Self {
-        Self { sketch, offset: 0 }
+impl<S, C> Counter<S, C>
+where
+    C: Count,
+{
+    pub fn new(sketch: S) -> Self {
+        Self {
+            sketch,
+            offset: C::default(),
+        }
+    }
+
+    pub fn sketch(&self) -> &S {
+        &self.sketch
+    }
+
+    pub fn sketch_mut(&mut self) -> &mut S {
+        &mut self.sketch
+    }
+
+    pub fn offset(&self) -> C {
+        self.offset
+    }
+
+    pub fn set_offset(&mut self, offset: C) {
+        self.offset = offset;
     }
 }
 
-impl<S> Counter<S>
+impl<S, C> Counter<S, C>
 where
     S: CardinalitySketch,
+    C: Count,
 {
     #[inline]
-    pub(crate) fn reset(&mut self, reset_strategy: &ResetStrategy) {
-        match reset_strategy {
-            ResetStrategy::Recycle => {
-                // do nothing; we're going to keep using the sketch as is
-            }
-            ResetStrategy::Offset => {
-                self.offset += self.sketch.cardinality();
-                self.sketch.clear();
-            }
-        }
+    pub fn reset(&mut self, reset_strategy: &impl Reset) {
+        reset_strategy.reset(self);
+    }
+
+    #[inline]
+    pub fn reset_in(&mut self, reset_strategy: &impl Reset, pool: &mut BufferPool) {
+        reset_strategy.reset_in(self, pool);
     }
 
-    pub(crate) fn offset_cardinality(&self) -> u64 {
-        self.sketch.cardinality() + self.offset
+    pub fn offset_cardinality(&self) -> C {
+        C::from_u64(self.sketch.cardinality()).saturating_add(self.offset)
     }
 }
@@ -4,111 +4,239 @@
 //!
 //! The `SetCounter` can use any cardinality sketch, but we only provide HyperLogLog
 
+#![cfg_attr(not(test), deny(clippy::unwrap_used))]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
 mod cached;
 mod config;
 mod counter;
+#[cfg(feature = "rayon")]
+mod par;
+#[cfg(feature = "serde")]
+mod serde_impls;
 
-use std::{collections::HashMap, error, fmt, hash::Hash};
+use alloc::{sync::Arc, vec::Vec};
+use core::{
+    cmp::Reverse,
+    error, fmt,
+    hash::{Hash, Hasher},
+};
 
-use sketch_traits::{CardinalitySketch, HeavyDistinctHitterSketch, New};
+use sketch_traits::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    group_top, truncate_top, BufferPool, CardinalitySketch, Eviction, EvictionLog,
+    HeavyDistinctHitterSketch, InsertOutcome, Membership, MemorySize, New, Observable, Observer,
+    TopIter, Validate, Versioned,
+};
+#[cfg(feature = "metrics")]
+use sketch_traits::{MetricCounts, SketchMetrics};
 
-use crate::{cached::Cached, counter::Counter};
+use crate::cached::Cached;
 pub use crate::{
     config::{Config, ConfigError},
-    counter::ResetStrategy,
+    counter::{Counter, Reset, ResetStrategy},
 };
 
+/// The buffers kept for counters to grow into. Counters are reset one at a
+/// time, into sketches that start small again, so a single buffer covers a
+/// reset and the next counter to grow.
+const MAX_POOLED_BUFFERS: usize = 1;
+
 #[derive(Clone, Debug)]
-pub struct SpaceSavingSets<L, S>
+pub struct SpaceSavingSets<L, S, R = ResetStrategy>
 where
     S: New,
 {
-    config: Config<S::Config>,
+    config: Config<S::Config, R>,
     counters: HashMap<L, Counter<Cached<S>>>,
+    /// The items of all labels, which counters forget as labels are evicted.
+    total: S,
+    /// The buffers that counters drop as they're reset, e.g., the registers
+    /// of HyperLogLogs that are sparse again (see [`Reset::reset_in`]).
+    pool: BufferPool,
+    version: u64,
+    evictions: Option<EvictionLog<L>>,
+    observer: Option<Arc<dyn Observer<L>>>,
+    #[cfg(feature = "metrics")]
+    metrics: MetricCounts,
 }
 
-impl<L, S> New for SpaceSavingSets<L, S>
+impl<L, S, R> New for SpaceSavingSets<L, S, R>
 where
     S: New,
     S::Config: Clone,
+    R: Clone,
 {
-    type Config = Config<S::Config>;
+    type Config = Config<S::Config, R>;
 
     fn new(config: &Self::Config) -> Self {
         Self {
             config: config.clone(),
             counters: HashMap::new(),
+            total: S::new(&config.cardinality_sketch_config),
+            pool: BufferPool::new(MAX_POOLED_BUFFERS),
+            version: 0,
+            evictions: None,
+            observer: None,
+            #[cfg(feature = "metrics")]
+            metrics: MetricCounts::default(),
         }
     }
 }
 
-impl<L, S> HeavyDistinctHitterSketch for SpaceSavingSets<L, S>
+impl<L, S, R> HeavyDistinctHitterSketch for SpaceSavingSets<L, S, R>
 where
     L: Eq + Hash + Clone,
     S: CardinalitySketch + New,
     S::Config: Eq,
+    R: Reset + Eq,
 {
     type Label = L;
     type Item = S::Item;
     type MergeError = MergeError;
 
-    fn insert(&mut self, label: L, item: &S::Item) {
-        let full = self.full();
-        let key_exists = self.counters.contains_key(&label);
-        let counter = if !key_exists {
-            if full {
-                let min_label = self.get_min_label();
-                let mut counter = self.counters.remove(&min_label).unwrap();
-                counter.reset(&self.config.reset_strategy);
-                self.counters.entry(label).or_insert(counter)
-            } else {
-                self.counters.entry(label).or_insert_with(|| {
-                    Counter::new(Cached::new(&self.config.cardinality_sketch_config))
-                })
-            }
+    fn insert_with_outcome(&mut self, label: L, item: &S::Item) -> InsertOutcome<L> {
+        self.version = self.version.wrapping_add(1);
+        #[cfg(feature = "metrics")]
+        {
+            self.metrics.inserts += 1;
+        }
+        self.total.insert(item);
+        if let Some(counter) = self.counters.get_mut(&label) {
+            counter.sketch.insert_in(item, &mut self.pool);
+            return InsertOutcome::UpdatedExisting;
+        }
+        // When full, the counter with the minimum cardinality is remapped to
+        // the new label.
+        let min = if self.full() {
+            self.remove_min_counter()
         } else {
-            self.counters.get_mut(&label).unwrap()
+            None
+        };
+        let (mut counter, outcome) = match min {
+            Some((min_label, mut counter)) => {
+                self.log_eviction(&min_label, counter.offset_cardinality());
+                counter.reset_in(&self.config.reset_strategy, &mut self.pool);
+                (counter, InsertOutcome::Evicted { label: min_label })
+            }
+            None => (
+                Counter::new(Cached::new(&self.config.cardinality_sketch_config)),
+                InsertOutcome::CreatedNew,
+            ),
         };
-        counter.sketch.insert(item);
+        counter.sketch.insert_in(item, &mut self.pool);
+        self.log_admission(&label);
+        self.counters.insert(label, counter);
+        outcome
+    }
+
+    /// Inserts the items of consecutive pairs sharing a tracked label with a
+    /// single lookup, refreshing the cached cardinality once per run.
+    fn insert_iter(&mut self, pairs: impl IntoIterator<Item = (L, S::Item)>) {
+        let mut pairs = pairs.into_iter().peekable();
+        let mut run = Vec::new();
+        while let Some((label, item)) = pairs.next() {
+            let Some(counter) = self.counters.get_mut(&label) else {
+                self.insert(label, &item);
+                continue;
+            };
+            run.push(item);
+            while let Some((_, item)) = pairs.next_if(|(next, _)| *next == label) {
+                run.push(item);
+            }
+            self.version = self.version.wrapping_add(run.len() as u64);
+            #[cfg(feature = "metrics")]
+            {
+                self.metrics.inserts += run.len() as u64;
+            }
+            counter.sketch.insert_all_in(&run, &mut self.pool);
+            self.total.insert_all(&run);
+            run.clear();
+        }
     }
 
     fn merge(&mut self, other: &Self) -> Result<(), Self::MergeError> {
         if self.config != other.config {
             return Err(MergeError::ConfigMismatch);
         }
+        self.version = self.version.wrapping_add(1);
+        #[cfg(feature = "metrics")]
+        {
+            self.metrics.merges += 1;
+        }
 
+        self.merge_total(&other.total);
         for (l, c) in other.counters.iter() {
-            self.counters
-                .entry(l.clone())
-                .or_insert_with(|| {
-                    Counter::new(Cached::new(&self.config.cardinality_sketch_config))
-                })
-                .sketch
-                .merge(&c.sketch)
-                .unwrap();
+            let counter = match self.counters.entry(l.clone()) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    if let Some(observer) = &self.observer {
+                        observer.on_admit(l);
+                    }
+                    entry.insert(Counter::new(Cached::new(
+                        &self.config.cardinality_sketch_config,
+                    )))
+                }
+            };
+            // Like counts in Space-Saving, the overestimation offsets add up.
+            counter.offset = counter.offset.saturating_add(c.offset);
+            counter.sketch.merge(&c.sketch).unwrap_or_else(
+                // By construction, parameters cannot mismatch.
+                |_| unreachable!(),
+            );
         }
-        let mut entries = self
-            .counters
-            .iter()
-            .map(|(label, counter)| (label, counter.offset_cardinality()))
-            .collect::<Vec<_>>();
-        entries.sort_by_key(|&(_, cardinality)| cardinality);
-        entries
-            .into_iter()
-            .rev()
-            .skip(self.config.max_num_counters)
-            .map(|(label, _)| label)
-            .cloned()
-            .collect::<Vec<_>>()
-            .into_iter()
-            .for_each(|label| {
-                self.counters.remove(&label);
-            });
+        self.evict_extra_counters();
         Ok(())
     }
 
+    fn merge_owned(&mut self, other: Self) -> Result<(), Self::MergeError> {
+        if self.config != other.config {
+            return Err(MergeError::ConfigMismatch);
+        }
+        self.version = self.version.wrapping_add(1);
+        #[cfg(feature = "metrics")]
+        {
+            self.metrics.merges += 1;
+        }
+
+        self.merge_total(&other.total);
+        for (l, c) in other.counters {
+            match self.counters.entry(l) {
+                Entry::Occupied(mut entry) => {
+                    let counter = entry.get_mut();
+                    counter.offset = counter.offset.saturating_add(c.offset);
+                    counter.sketch.merge(&c.sketch).unwrap_or_else(
+                        // By construction, parameters cannot mismatch.
+                        |_| unreachable!(),
+                    );
+                }
+                Entry::Vacant(entry) => {
+                    if let Some(observer) = &self.observer {
+                        observer.on_admit(entry.key());
+                    }
+                    entry.insert(c);
+                }
+            }
+        }
+        self.evict_extra_counters();
+        Ok(())
+    }
+
+    /// Evictions keep being logged, from scratch.
     fn clear(&mut self) {
-        todo!()
+        self.version = self.version.wrapping_add(1);
+        self.counters.clear();
+        self.total.clear();
+        if let Some(evictions) = &mut self.evictions {
+            evictions.clear();
+        }
+    }
+
+    fn remove(&mut self, label: &L) -> bool {
+        self.version = self.version.wrapping_add(1);
+        self.counters.remove(label).is_some()
     }
 
     fn cardinality(&self, label: &L) -> u64 {
@@ -124,6 +252,20 @@ where
             })
     }
 
+    /// Estimated from a sketch of its own, so that evicted labels still
+    /// count.
+    fn total_cardinality(&self) -> Option<u64> {
+        Some(self.total.cardinality())
+    }
+
+    fn labels(&self) -> impl Iterator<Item = &L> {
+        self.counters.keys()
+    }
+
+    fn num_labels(&self) -> usize {
+        self.counters.len()
+    }
+
     fn top(&self, k: usize) -> Vec<(&L, u64)> {
         let mut entries = self
             .counters
@@ -133,13 +275,122 @@ where
         entries.sort_by_key(|&(_, cardinality)| cardinality);
         entries.into_iter().rev().take(k).collect::<Vec<_>>()
     }
+
+    fn top_iter(&self) -> TopIter<&L> {
+        self.counters
+            .iter()
+            .map(|(label, counter)| (label, counter.offset_cardinality()))
+            .collect()
+    }
+
+    fn top_into(&self, k: usize, out: &mut Vec<(L, u64)>) {
+        out.clear();
+        out.extend(
+            self.counters
+                .iter()
+                .map(|(label, counter)| (label.clone(), counter.offset_cardinality())),
+        );
+        truncate_top(out, k);
+    }
+
+    fn labels_above(&self, threshold: u64) -> Vec<(&L, u64)> {
+        let mut entries = self
+            .counters
+            .iter()
+            .map(|(label, counter)| (label, counter.offset_cardinality()))
+            .filter(|&(_, cardinality)| cardinality > threshold)
+            .collect::<Vec<_>>();
+        entries.sort_unstable_by_key(|&(_, cardinality)| Reverse(cardinality));
+        entries
+    }
+
+    fn top_grouped<G, F>(&self, k: usize, classify: F) -> HashMap<G, Vec<(&L, u64)>>
+    where
+        G: Eq + Hash,
+        F: Fn(&L) -> G,
+    {
+        group_top(
+            self.counters
+                .iter()
+                .map(|(label, counter)| (label, counter.offset_cardinality())),
+            k,
+            |label| classify(label),
+        )
+    }
+
+    fn cardinality_many(&self, labels: &[L], out: &mut Vec<u64>) {
+        out.clear();
+        // The fallback for untracked labels is only computed once.
+        let mut min_cardinality = None;
+        out.extend(labels.iter().map(|label| match self.counters.get(label) {
+            Some(counter) => counter.offset_cardinality(),
+            None => *min_cardinality.get_or_insert_with(|| {
+                self.counters
+                    .values()
+                    .map(|c| c.offset_cardinality())
+                    .min()
+                    .unwrap_or(0)
+            }),
+        }));
+    }
 }
 
-impl<L, S> SpaceSavingSets<L, S>
+impl<L, S, R> SpaceSavingSets<L, S, R>
 where
-    L: Clone,
+    L: Eq + Hash + Clone,
     S: CardinalitySketch + New,
 {
+    /// Keeps the counters with the highest cardinalities after a merge. Ties
+    /// are broken by label hash so that the counters that are kept don't
+    /// depend on the merge order.
+    fn evict_extra_counters(&mut self) {
+        let mut entries = self
+            .counters
+            .iter()
+            .map(|(label, counter)| (label, counter.offset_cardinality()))
+            .collect::<Vec<_>>();
+        entries.sort_by_cached_key(|&(label, cardinality)| (cardinality, label_hash(label)));
+        entries
+            .into_iter()
+            .rev()
+            .skip(self.config.max_num_counters)
+            .map(|(label, _)| label)
+            .cloned()
+            .collect::<Vec<_>>()
+            .into_iter()
+            .for_each(|label| {
+                if let Some(counter) = self.counters.remove(&label) {
+                    self.log_eviction(&label, counter.offset_cardinality());
+                }
+            });
+    }
+
+    fn merge_total(&mut self, other: &S) {
+        self.total.merge(other).unwrap_or_else(
+            // By construction, parameters cannot mismatch.
+            |_| unreachable!(),
+        );
+    }
+
+    fn log_admission(&self, label: &L) {
+        if let Some(observer) = &self.observer {
+            observer.on_admit(label);
+        }
+    }
+
+    fn log_eviction(&mut self, label: &L, cardinality: u64) {
+        #[cfg(feature = "metrics")]
+        {
+            self.metrics.evictions += 1;
+        }
+        if let Some(observer) = &self.observer {
+            observer.on_evict(label, cardinality);
+        }
+        if let Some(evictions) = &mut self.evictions {
+            evictions.record(label.clone(), cardinality);
+        }
+    }
+
     fn full(&self) -> bool {
         debug_assert!(self.counters.len() <= self.config.max_num_counters);
         self.counters.len() == self.config.max_num_counters
@@ -148,28 +399,261 @@ where
     // TODO: see if using a min-heap makes things faster. Since a SetCounter
     // only ever increases, we only need to push the node down the tree on
     // insert if it gets larger than its children.
-    fn get_min_label(&self) -> L {
+    fn get_min_label(&self) -> Option<L> {
         self.counters
             .iter()
             .min_by_key(|(_, counter)| counter.offset_cardinality())
             .map(|(label, _)| (*label).clone())
-            .unwrap()
+    }
+
+    fn remove_min_counter(&mut self) -> Option<(L, Counter<Cached<S>>)> {
+        let min_label = self.get_min_label()?;
+        self.counters.remove_entry(&min_label)
+    }
+}
+
+impl<L, S, R> SpaceSavingSets<L, S, R>
+where
+    S: CardinalitySketch + New + Clone,
+{
+    /// Rolls labels up to a coarser projection, e.g., `(customer, endpoint)`
+    /// to `customer`, and returns the `k` projected labels with the highest
+    /// cardinalities.
+    ///
+    /// Counters that project to the same label have their sketches merged
+    /// and their offsets summed. Only tracked labels contribute.
+    pub fn top_by_projection<P, F>(&self, k: usize, f: F) -> Vec<(P, u64)>
+    where
+        P: Eq + Hash,
+        F: Fn(&L) -> P,
+    {
+        let mut rolled_up: HashMap<P, Counter<Cached<S>>> = HashMap::new();
+        for (label, counter) in self.counters.iter() {
+            match rolled_up.entry(f(label)) {
+                Entry::Occupied(mut entry) => {
+                    let rolled_up = entry.get_mut();
+                    rolled_up.offset = rolled_up.offset.saturating_add(counter.offset);
+                    rolled_up.sketch.merge(&counter.sketch).unwrap_or_else(
+                        // By construction, parameters cannot mismatch.
+                        |_| unreachable!(),
+                    );
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(counter.clone());
+                }
+            }
+        }
+        let mut entries = rolled_up
+            .into_iter()
+            .map(|(projection, counter)| (projection, counter.offset_cardinality()))
+            .collect::<Vec<_>>();
+        truncate_top(&mut entries, k);
+        entries
+    }
+
+    /// The number of distinct items across `labels`, e.g., all the endpoints
+    /// of one customer, estimated by merging their sketches and summing their
+    /// offsets.
+    ///
+    /// Items shared between labels are only counted once, but their offsets
+    /// can't tell shared items apart. Labels that aren't tracked, or that
+    /// are repeated, don't contribute.
+    pub fn cardinality_union(&self, labels: &[L]) -> u64
+    where
+        L: Eq + Hash,
+    {
+        let mut union: Counter<Cached<S>> =
+            Counter::new(Cached::new(&self.config.cardinality_sketch_config));
+        let mut seen = HashSet::with_capacity(labels.len());
+        for (label, counter) in labels
+            .iter()
+            .filter_map(|label| self.counters.get_key_value(label))
+        {
+            if !seen.insert(label) {
+                continue;
+            }
+            union.offset = union.offset.saturating_add(counter.offset);
+            union.sketch.merge(&counter.sketch).unwrap_or_else(
+                // By construction, parameters cannot mismatch.
+                |_| unreachable!(),
+            );
+        }
+        union.offset_cardinality()
+    }
+}
+
+impl<L, S, R> SpaceSavingSets<L, S, R>
+where
+    L: Eq + Hash,
+    S: Membership + New,
+{
+    /// Whether `item` was likely inserted under `label`, e.g., with
+    /// [`hll::BloomHyperLogLog`] counters, or `None` if the label isn't
+    /// tracked.
+    ///
+    /// Recycled counters keep the items of the labels they were taken over
+    /// from, so those are likely reported as well; offset ones only know of
+    /// the items inserted since.
+    pub fn may_contain(&self, label: &L, item: &S::Item) -> Option<bool> {
+        self.counters
+            .get(label)
+            .map(|counter| counter.sketch.sketch().may_contain(item))
     }
 }
 
-impl<L, S> SpaceSavingSets<L, S>
+impl<L, S, R> SpaceSavingSets<L, S, R>
 where
     S: New,
 {
-    pub fn config(&self) -> &Config<S::Config> {
+    pub fn config(&self) -> &Config<S::Config, R> {
         &self.config
     }
 
-    pub fn num_counters(&self) -> usize {
-        self.counters.len()
+    /// Starts logging the last `capacity` labels evicted, by inserts or
+    /// merges, with their estimates at the time, e.g., to explain why a label
+    /// disappeared from reports. Logs aren't merged, and aren't serialized.
+    pub fn track_evictions(&mut self, capacity: usize) {
+        self.evictions = Some(EvictionLog::new(capacity));
+    }
+
+    /// The logged evictions, most recent first. Empty unless evictions are
+    /// tracked.
+    pub fn recent_evictions(&self) -> impl Iterator<Item = &Eviction<L>> {
+        self.evictions.iter().flat_map(EvictionLog::recent)
+    }
+}
+
+/// A deterministic hash of `label`, unlike the ones of `HashMap`s. Without
+/// std, there's no SipHash to use, so labels are hashed with fixed-key aHash.
+fn label_hash<L: Hash>(label: &L) -> u64 {
+    #[cfg(feature = "std")]
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    #[cfg(not(feature = "std"))]
+    let mut hasher = ahash::AHasher::default();
+    label.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl<L, S, R> Extend<(L, S::Item)> for SpaceSavingSets<L, S, R>
+where
+    L: Eq + Hash + Clone,
+    S: CardinalitySketch + New,
+    S::Config: Eq,
+    R: Reset + Eq,
+{
+    fn extend<T: IntoIterator<Item = (L, S::Item)>>(&mut self, pairs: T) {
+        self.insert_iter(pairs);
+    }
+}
+
+impl<L, S, R> MemorySize for SpaceSavingSets<L, S, R>
+where
+    L: MemorySize,
+    S: New + MemorySize,
+    S::Config: MemorySize,
+{
+    fn heap_size(&self) -> usize {
+        self.config.heap_size()
+            + self.counters.heap_size()
+            + self.total.heap_size()
+            + self.pool.heap_size()
+            + self.evictions.heap_size()
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl<L, S, R> SketchMetrics for SpaceSavingSets<L, S, R>
+where
+    L: MemorySize,
+    S: New + MemorySize,
+    S::Config: MemorySize,
+{
+    fn num_inserts(&self) -> u64 {
+        self.metrics.inserts
+    }
+
+    fn num_evictions(&self) -> u64 {
+        self.metrics.evictions
+    }
+
+    fn num_merges(&self) -> u64 {
+        self.metrics.merges
+    }
+}
+
+impl<L, S, R> Versioned for SpaceSavingSets<L, S, R>
+where
+    S: New,
+{
+    fn version(&self) -> u64 {
+        self.version
+    }
+}
+
+/// Evictions are reported before the eviction log records them, and labels
+/// admitted by a merge may be evicted right away by the same merge.
+impl<L, S, R> Observable for SpaceSavingSets<L, S, R>
+where
+    L: Eq + Hash + Clone,
+    S: CardinalitySketch + New,
+    S::Config: Eq,
+    R: Reset + Eq,
+{
+    fn set_observer(&mut self, observer: Arc<dyn Observer<L>>) {
+        self.observer = Some(observer);
+    }
+}
+
+impl<L, S, R> Validate for SpaceSavingSets<L, S, R>
+where
+    S: CardinalitySketch + New + Validate,
+{
+    type Violation = InvariantViolation<S::Violation>;
+
+    fn debug_validate(&self) -> Result<(), Self::Violation> {
+        if self.counters.len() > self.config.max_num_counters {
+            return Err(InvariantViolation::TooManyCounters);
+        }
+        for counter in self.counters.values() {
+            if !counter.sketch.is_consistent() {
+                return Err(InvariantViolation::StaleCachedCardinality);
+            }
+            counter
+                .sketch
+                .sketch()
+                .debug_validate()
+                .map_err(InvariantViolation::Counter)?;
+        }
+        Ok(())
     }
 }
 
+#[derive(Clone, Debug)]
+pub enum InvariantViolation<E> {
+    TooManyCounters,
+    StaleCachedCardinality,
+    Counter(E),
+}
+
+impl<E> fmt::Display for InvariantViolation<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvariantViolation::TooManyCounters => {
+                write!(f, "there are more counters than the configured maximum")
+            }
+            InvariantViolation::StaleCachedCardinality => {
+                write!(f, "a cached cardinality is stale")
+            }
+            InvariantViolation::Counter(e) => write!(f, "invalid counter: {}", e),
+        }
+    }
+}
+
+impl<E> error::Error for InvariantViolation<E> where E: fmt::Debug + fmt::Display {}
+
 #[derive(Clone, Debug)]
 pub enum MergeError {
     ConfigMismatch,
@@ -190,7 +674,8 @@ mod tests {
     use std::collections::{HashMap, HashSet};
 
     use hll::HyperLogLog;
-    use sketch_traits::HeavyDistinctHitterSketch;
+    use proptest::{collection::vec, prelude::*};
+    use sketch_traits::{Count, HeavyDistinctHitterSketch};
 
     use super::*;
     use crate::ResetStrategy;
@@ -428,4 +913,522 @@ mod tests {
             ) < 0.1
         );
     }
+
+    #[test]
+    fn buffered_queries_match_allocating_ones() {
+        let mut sketch: SpaceSavingSets<char, HyperLogLog<u64>> =
+            SpaceSavingSets::new(&config(ResetStrategy::Offset));
+        for (n, label) in ('a'..='o').enumerate() {
+            for i in 0..(n as u64 + 1) * 20 {
+                sketch.insert(label, &i);
+            }
+        }
+
+        let mut out = vec![(' ', 0)];
+        sketch.top_into(5, &mut out);
+        let top = sketch.top(5);
+        assert_eq!(out.len(), 5);
+        assert_eq!(
+            out.iter().map(|&(_, c)| c).collect::<Vec<_>>(),
+            top.iter().map(|&(_, c)| c).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            sketch
+                .top_iter()
+                .take(5)
+                .map(|(_, c)| c)
+                .collect::<Vec<_>>(),
+            top.iter().map(|&(_, c)| c).collect::<Vec<_>>()
+        );
+        assert_eq!(sketch.top_iter().len(), sketch.num_labels());
+
+        let labels = ('a'..='z').collect::<Vec<_>>();
+        let mut cardinalities = vec![];
+        sketch.cardinality_many(&labels, &mut cardinalities);
+        assert_eq!(
+            cardinalities,
+            labels
+                .iter()
+                .map(|l| sketch.cardinality(l))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn it_validates_invariants() {
+        let mut sketch: SpaceSavingSets<char, HyperLogLog<u64>> =
+            SpaceSavingSets::new(&config(ResetStrategy::Offset));
+        assert!(sketch.debug_validate().is_ok());
+        for (n, label) in ('a'..='o').enumerate() {
+            for i in 0..(n as u64 + 1) * 20 {
+                sketch.insert(label, &i);
+            }
+        }
+        assert!(sketch.debug_validate().is_ok());
+        let other = sketch.clone();
+        assert!(sketch.merge(&other).is_ok());
+        assert!(sketch.debug_validate().is_ok());
+    }
+
+    #[test]
+    fn top_by_projection_merges_counters() {
+        let mut sketch: SpaceSavingSets<(char, u32), HyperLogLog<u64>> =
+            SpaceSavingSets::new(&config(ResetStrategy::Offset));
+        for endpoint in 0..3_u64 {
+            for i in endpoint * 50..endpoint * 50 + 100 {
+                sketch.insert(('a', endpoint as u32), &i);
+            }
+        }
+        for i in 0..150 {
+            sketch.insert(('b', 0), &i);
+        }
+
+        let top = sketch.top_by_projection(1, |&(customer, _)| customer);
+        assert_eq!(top.len(), 1);
+        assert_eq!(top[0].0, 'a');
+        assert!(relative_error(top[0].1, 200) < 0.1);
+    }
+
+    #[test]
+    fn evictions_are_logged() {
+        let mut sketch: SpaceSavingSets<u32, HyperLogLog<u64>> =
+            SpaceSavingSets::new(&config(ResetStrategy::Offset));
+        sketch.track_evictions(3);
+        let mut evicted = Vec::new();
+        for i in 0..10_000_u64 {
+            if let InsertOutcome::Evicted { label } =
+                sketch.insert_with_outcome((i % 100) as u32, &i)
+            {
+                evicted.push(label);
+            }
+        }
+        assert!(evicted.len() > 3);
+        assert_eq!(
+            sketch
+                .recent_evictions()
+                .map(|eviction| eviction.label)
+                .collect::<Vec<_>>(),
+            evicted.iter().rev().take(3).copied().collect::<Vec<_>>()
+        );
+        assert!(sketch
+            .recent_evictions()
+            .all(|eviction| eviction.cardinality > 0));
+
+        // Labels evicted by merges are logged too.
+        let mut other = sketch.clone();
+        other.clear();
+        other.extend((0..SIZE as u64 * 10_000).map(|i| (1_000 + (i % SIZE as u64) as u32, i)));
+        assert!(sketch.merge(&other).is_ok());
+        for eviction in sketch.recent_evictions() {
+            assert!(eviction.label < 100);
+            assert!(!sketch.labels().any(|&label| label == eviction.label));
+        }
+
+        sketch.clear();
+        assert_eq!(sketch.recent_evictions().count(), 0);
+    }
+
+    /// Mirrors the labels tracked by a sketch from its admissions and
+    /// evictions.
+    #[derive(Default)]
+    struct Mirror(std::sync::Mutex<HashSet<u32>>);
+
+    impl Observer<u32> for Mirror {
+        fn on_admit(&self, label: &u32) {
+            assert!(self.0.lock().unwrap().insert(*label));
+        }
+
+        fn on_evict(&self, label: &u32, _: u64) {
+            assert!(self.0.lock().unwrap().remove(label));
+        }
+    }
+
+    #[test]
+    fn observers_see_admissions_and_evictions() {
+        let mut sketch: SpaceSavingSets<u32, HyperLogLog<u64>> =
+            SpaceSavingSets::new(&config(ResetStrategy::Offset));
+        let mirror = Arc::new(Mirror::default());
+        sketch.set_observer(mirror.clone());
+        let tracked = |sketch: &SpaceSavingSets<u32, HyperLogLog<u64>>| {
+            sketch.labels().copied().collect::<HashSet<_>>()
+        };
+
+        sketch.extend((0..10_000_u64).map(|i| ((i % 100) as u32, i)));
+        assert_eq!(*mirror.0.lock().unwrap(), tracked(&sketch));
+
+        let mut other: SpaceSavingSets<u32, HyperLogLog<u64>> =
+            SpaceSavingSets::new(&config(ResetStrategy::Offset));
+        other.extend((0..20_000_u64).map(|i| (100 + (i % 20) as u32, i)));
+        assert!(sketch.merge(&other).is_ok());
+        assert_eq!(*mirror.0.lock().unwrap(), tracked(&sketch));
+        assert!(sketch.merge_owned(other).is_ok());
+        assert_eq!(*mirror.0.lock().unwrap(), tracked(&sketch));
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn metrics_count_inserts_evictions_and_merges() {
+        let mut sketch: SpaceSavingSets<u32, HyperLogLog<u64>> =
+            SpaceSavingSets::new(&config(ResetStrategy::Offset));
+        let mut num_evictions = 0;
+        for i in 0..1_000_u64 {
+            if let InsertOutcome::Evicted { .. } = sketch.insert_with_outcome((i % 100) as u32, &i)
+            {
+                num_evictions += 1;
+            }
+        }
+        assert!(num_evictions > 0);
+        assert_eq!(sketch.num_evictions(), num_evictions);
+        let tracked = *sketch.labels().next().unwrap();
+        sketch.extend((0..1_000_u64).map(|i| (tracked, i)));
+        assert_eq!(sketch.num_inserts(), 2_000);
+
+        let other = sketch.clone();
+        assert!(sketch.merge(&other).is_ok());
+        assert!(sketch.merge_owned(other).is_ok());
+        assert_eq!(sketch.num_merges(), 2);
+
+        sketch.clear();
+        assert_eq!(sketch.num_inserts(), 2_000);
+        assert_eq!(sketch.current_memory(), sketch.mem_size());
+    }
+
+    #[test]
+    fn bloom_counters_tell_items_of_tracked_labels() {
+        let bloom_config = hll::BloomConfig::new(
+            hll::Config::new(COUNTER_SIZE, Some(HLL_SEEDS)).unwrap(),
+            4_096,
+            5,
+            Some([0, 1, 2, 3]),
+        )
+        .unwrap();
+        let mut sketch: SpaceSavingSets<u32, hll::BloomHyperLogLog<u64>> =
+            SpaceSavingSets::new(&Config::new(SIZE, ResetStrategy::Offset, bloom_config).unwrap());
+        for label in 0..=SIZE as u32 {
+            for i in 0..100 {
+                sketch.insert(label, &(u64::from(label) * 1_000 + i));
+            }
+        }
+        // The last label took an offset counter over, which was cleared.
+        let last = SIZE as u32;
+        assert_eq!(
+            sketch.may_contain(&last, &(u64::from(last) * 1_000)),
+            Some(true)
+        );
+        let evicted = (0..last).find(|label| !sketch.counters.contains_key(label));
+        let evicted = evicted.unwrap();
+        assert_eq!(sketch.may_contain(&evicted, &0), None);
+        assert!((0..last)
+            .filter(|&label| label != evicted)
+            .all(
+                |label| sketch.may_contain(&label, &(u64::from(label) * 1_000 + 99)) == Some(true)
+            ));
+        assert_eq!(
+            sketch.may_contain(&last, &(u64::from(evicted) * 1_000)),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn cardinality_union_counts_shared_items_once() {
+        let mut sketch: SpaceSavingSets<u32, HyperLogLog<u64>> =
+            SpaceSavingSets::new(&config(ResetStrategy::Offset));
+        for label in 0..3_u64 {
+            for i in label * 50..label * 50 + 100 {
+                sketch.insert(label as u32, &i);
+            }
+        }
+        assert!(relative_error(sketch.cardinality_union(&[0, 1, 2]), 200) < 0.1);
+        assert!(relative_error(sketch.cardinality_union(&[0, 2, 0, 42]), 200) < 0.1);
+        assert_eq!(sketch.cardinality_union(&[0]), sketch.cardinality(&0));
+        assert_eq!(sketch.cardinality_union(&[42]), 0);
+    }
+
+    #[test]
+    fn total_cardinality_counts_items_of_evicted_labels() {
+        let mut left: SpaceSavingSets<u32, HyperLogLog<u64>> =
+            SpaceSavingSets::new(&config(ResetStrategy::Offset));
+        let mut right = left.clone();
+        left.extend((0..5_000_u64).map(|i| ((i % 100) as u32, i)));
+        right.extend((5_000..10_000_u64).map(|i| ((i % 100) as u32, i)));
+        assert!(relative_error(left.total_cardinality().unwrap(), 5_000) < 0.1);
+        assert!(left.merge(&right).is_ok());
+        assert!(relative_error(left.total_cardinality().unwrap(), 10_000) < 0.1);
+        left.clear();
+        assert_eq!(left.total_cardinality(), Some(0));
+    }
+
+    #[test]
+    fn custom_reset_strategies_are_applied() {
+        // Only carry half of the evicted counter's cardinality over.
+        #[derive(Clone, PartialEq, Eq, Debug)]
+        struct HalfOffset;
+
+        impl Reset for HalfOffset {
+            fn reset<S, C>(&self, counter: &mut Counter<S, C>)
+            where
+                S: CardinalitySketch,
+                C: Count,
+            {
+                let half = C::from_u64(counter.sketch().cardinality() / 2);
+                counter.set_offset(counter.offset() + half);
+                counter.sketch_mut().clear();
+            }
+        }
+
+        let config = Config::new(
+            SIZE,
+            HalfOffset,
+            hll::Config::new(COUNTER_SIZE, Some(HLL_SEEDS)).unwrap(),
+        )
+        .unwrap();
+        let mut sketch: SpaceSavingSets<char, HyperLogLog<u64>, HalfOffset> =
+            SpaceSavingSets::new(&config);
+        for label in 'a'..='j' {
+            for i in 0..100 {
+                sketch.insert(label, &i);
+            }
+        }
+        for i in 0..100 {
+            sketch.insert('k', &i);
+        }
+        assert!(relative_error(sketch.cardinality(&'k'), 150) < 0.1);
+    }
+
+    #[test]
+    fn it_never_panics_on_arbitrary_input() {
+        proptest!(|(
+            pairs1 in vec((any::<u8>(), any::<u64>()), 0..200),
+            pairs2 in vec((any::<u8>(), any::<u64>()), 0..200),
+            queries in vec(any::<u8>(), 0..10),
+            k in 0..5_usize,
+            reset_strategy in prop_oneof![Just(ResetStrategy::Recycle), Just(ResetStrategy::Offset)],
+        )| {
+            let config = Config::new(
+                2,
+                reset_strategy,
+                hll::Config::new(16, Some(HLL_SEEDS)).unwrap(),
+            )
+            .unwrap();
+            let mut sketch1: SpaceSavingSets<u8, HyperLogLog<u64>> = SpaceSavingSets::new(&config);
+            pairs1.iter().for_each(|(label, item)| sketch1.insert(*label, item));
+            let mut sketch2: SpaceSavingSets<u8, HyperLogLog<u64>> = SpaceSavingSets::new(&config);
+            pairs2.iter().for_each(|(label, item)| sketch2.insert(*label, item));
+
+            prop_assert!(sketch1.merge(&sketch2).is_ok());
+            queries.iter().for_each(|label| {
+                sketch1.cardinality(label);
+            });
+            prop_assert!(sketch1.top(k).len() <= k);
+            prop_assert!(sketch1.debug_validate().is_ok());
+        });
+    }
+
+    const MERGE_ORDERS: [[usize; 3]; 6] = [
+        [0, 1, 2],
+        [0, 2, 1],
+        [1, 0, 2],
+        [1, 2, 0],
+        [2, 0, 1],
+        [2, 1, 0],
+    ];
+
+    fn counters(sketch: &SpaceSavingSets<u8, HyperLogLog<u64>>) -> Vec<(u8, u64)> {
+        let mut counters = sketch
+            .top(usize::MAX)
+            .into_iter()
+            .map(|(label, cardinality)| (*label, cardinality))
+            .collect::<Vec<_>>();
+        counters.sort();
+        counters
+    }
+
+    #[test]
+    fn merge_order_does_not_matter() {
+        // Pruning makes merges order dependent in general, but not when all
+        // labels fit, and never between two sketches.
+        proptest!(|(
+            pairs in vec(vec((0..SIZE as u8, any::<u64>()), 0..100), 3),
+            overflowing_pairs in vec(vec((0..2 * SIZE as u8, 0..4_u64), 0..100), 2),
+            reset_strategy in prop_oneof![Just(ResetStrategy::Recycle), Just(ResetStrategy::Offset)],
+        )| {
+            let config = config(reset_strategy);
+            let new_sketch = |pairs: &Vec<(u8, u64)>| {
+                let mut sketch: SpaceSavingSets<u8, HyperLogLog<u64>> =
+                    SpaceSavingSets::new(&config);
+                pairs.iter().for_each(|(label, item)| sketch.insert(*label, item));
+                sketch
+            };
+
+            let sketches = pairs.iter().map(new_sketch).collect::<Vec<_>>();
+            let merged = MERGE_ORDERS.map(|order| {
+                let mut merged = sketches[order[0]].clone();
+                order[1..].iter().for_each(|&i| merged.merge(&sketches[i]).unwrap());
+                counters(&merged)
+            });
+            for counters in merged.iter() {
+                prop_assert_eq!(counters, &merged[0]);
+            }
+
+            let sketch1 = new_sketch(&overflowing_pairs[0]);
+            let sketch2 = new_sketch(&overflowing_pairs[1]);
+            let mut merged12 = sketch1.clone();
+            prop_assert!(merged12.merge(&sketch2).is_ok());
+            let mut owned12 = sketch1.clone();
+            prop_assert!(owned12.merge_owned(sketch2.clone()).is_ok());
+            prop_assert_eq!(counters(&owned12), counters(&merged12));
+            let mut merged21 = sketch2;
+            prop_assert!(merged21.merge(&sketch1).is_ok());
+            prop_assert_eq!(counters(&merged12), counters(&merged21));
+        });
+    }
+
+    #[test]
+    fn it_is_send_and_sync_regardless_of_items() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<SpaceSavingSets<u32, HyperLogLog<std::rc::Rc<str>>>>();
+    }
+
+    #[test]
+    fn counters_can_offset_fractional_counts() {
+        let config = hll::Config::new(COUNTER_SIZE, Some(HLL_SEEDS)).unwrap();
+        let mut counter = Counter::<HyperLogLog<u64>, f64>::new(HyperLogLog::new(&config));
+        counter.set_offset(0.5);
+        for i in 0..100 {
+            counter.sketch_mut().insert(&i);
+        }
+        let cardinality = counter.sketch().cardinality();
+        counter.reset(&ResetStrategy::Offset);
+        assert_eq!(counter.sketch().cardinality(), 0);
+        assert_eq!(counter.offset_cardinality(), cardinality as f64 + 0.5);
+    }
+
+    #[test]
+    fn offset_counters_grow_into_the_buffers_of_reset_ones() {
+        // Registers of the default config start sparse.
+        let config = Config::new(
+            1,
+            ResetStrategy::Offset,
+            hll::Config::new(COUNTER_SIZE, Some(HLL_SEEDS)).unwrap(),
+        )
+        .unwrap();
+        let mut sketch: SpaceSavingSets<u32, HyperLogLog<u64>> = SpaceSavingSets::new(&config);
+        let registers = |sketch: &SpaceSavingSets<u32, HyperLogLog<u64>>, label| {
+            let sketch = sketch.counters[&label].sketch.sketch();
+            (!sketch.is_sparse()).then(|| sketch.registers().as_ptr())
+        };
+        sketch.extend((0..10_000_u64).map(|i| (1, i)));
+        let buffer = registers(&sketch, 1);
+        assert!(buffer.is_some());
+
+        sketch.insert(2, &0);
+        assert_eq!(registers(&sketch, 2), None);
+        assert_eq!(sketch.pool.len(), 1);
+        sketch.extend((1..10_000_u64).map(|i| (2, i)));
+        assert_eq!(registers(&sketch, 2), buffer);
+        assert!(sketch.pool.is_empty());
+        assert!(sketch.debug_validate().is_ok());
+    }
+
+    #[test]
+    fn insert_reports_outcomes() {
+        let config = Config::new(
+            2,
+            ResetStrategy::Offset,
+            hll::Config::new(COUNTER_SIZE, Some(HLL_SEEDS)).unwrap(),
+        )
+        .unwrap();
+        let mut sketch: SpaceSavingSets<u32, HyperLogLog<u64>> = SpaceSavingSets::new(&config);
+        assert_eq!(sketch.insert_with_outcome(1, &0), InsertOutcome::CreatedNew);
+        assert_eq!(
+            sketch.insert_with_outcome(1, &1),
+            InsertOutcome::UpdatedExisting
+        );
+        assert_eq!(sketch.insert_with_outcome(2, &0), InsertOutcome::CreatedNew);
+        assert_eq!(
+            sketch.insert_with_outcome(3, &0),
+            InsertOutcome::Evicted { label: 2 }
+        );
+        assert_eq!(sketch.num_labels(), 2);
+    }
+
+    #[test]
+    fn removed_labels_free_their_counters() {
+        let config = Config::new(
+            2,
+            ResetStrategy::Offset,
+            hll::Config::new(COUNTER_SIZE, Some(HLL_SEEDS)).unwrap(),
+        )
+        .unwrap();
+        let mut sketch: SpaceSavingSets<u32, HyperLogLog<u64>> = SpaceSavingSets::new(&config);
+        (0..100).for_each(|i| sketch.insert(1, &i));
+        sketch.insert(2, &0);
+        assert!(sketch.remove(&1));
+        assert!(!sketch.remove(&1));
+        assert_eq!(sketch.labels().collect::<Vec<_>>(), [&2]);
+        assert_eq!(sketch.insert_with_outcome(3, &0), InsertOutcome::CreatedNew);
+        assert_eq!(sketch.num_labels(), 2);
+    }
+
+    #[test]
+    fn cleared_sketches_behave_like_fresh_ones() {
+        let pairs = || (0..1_000_u64).map(|i| ((i % 7) as u32, i));
+        let mut fresh: SpaceSavingSets<u32, HyperLogLog<u64>> =
+            SpaceSavingSets::new(&config(ResetStrategy::Offset));
+        let mut cleared = fresh.clone();
+        cleared.extend((0..1_000_u64).map(|i| ((i % 100) as u32, i)));
+        cleared.clear();
+        assert_eq!(cleared.top(usize::MAX), []);
+        assert_eq!(cleared.config(), fresh.config());
+
+        fresh.extend(pairs());
+        cleared.extend(pairs());
+        let mut expected = fresh.top(usize::MAX);
+        let mut actual = cleared.top(usize::MAX);
+        expected.sort();
+        actual.sort();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn rejected_merges_keep_the_version() {
+        let mut sketch: SpaceSavingSets<u32, HyperLogLog<u64>> =
+            SpaceSavingSets::new(&config(ResetStrategy::Offset));
+        sketch.insert(1, &1);
+        let other = SpaceSavingSets::new(&config(ResetStrategy::Recycle));
+        let version = sketch.version();
+        assert!(matches!(
+            sketch.merge(&other),
+            Err(MergeError::ConfigMismatch)
+        ));
+        assert!(matches!(
+            sketch.merge_owned(other),
+            Err(MergeError::ConfigMismatch)
+        ));
+        assert_eq!(sketch.version(), version);
+    }
+
+    #[test]
+    fn insert_iter_matches_insert() {
+        let config = Config::new(
+            16,
+            ResetStrategy::Offset,
+            hll::Config::new(COUNTER_SIZE, Some(HLL_SEEDS)).unwrap(),
+        )
+        .unwrap();
+        // Runs of consecutive pairs share labels, some of which get evicted.
+        let pairs = (0..10_000_u64).map(|i| ((i / 7 % 300) as u32, i));
+        let mut one_by_one: SpaceSavingSets<u32, HyperLogLog<u64>> = SpaceSavingSets::new(&config);
+        pairs
+            .clone()
+            .for_each(|(label, i)| one_by_one.insert(label, &i));
+        let mut batched: SpaceSavingSets<u32, HyperLogLog<u64>> = SpaceSavingSets::new(&config);
+        batched.extend(pairs);
+        assert_eq!(batched.total_cardinality(), one_by_one.total_cardinality());
+        let mut expected = one_by_one.top(usize::MAX);
+        let mut actual = batched.top(usize::MAX);
+        expected.sort_unstable();
+        actual.sort_unstable();
+        assert_eq!(actual, expected);
+    }
 }
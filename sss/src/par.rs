@@ -0,0 +1,107 @@
+//! Parallel merges, for aggregators merging many sketches.
+
+use core::hash::Hash;
+
+use rayon::prelude::*;
+use sketch_traits::{CardinalitySketch, New};
+
+use crate::{cached::Cached, Counter, MergeError, Reset, SpaceSavingSets};
+
+impl<L, S, R> SpaceSavingSets<L, S, R>
+where
+    L: Eq + Hash + Clone + Send + Sync,
+    S: CardinalitySketch + New + Send + Sync,
+    S::Config: Eq,
+    R: Reset + Eq,
+{
+    /// Like `merge`, but merges the counters of labels in parallel, on
+    /// rayon's thread pool, each shard of the counters on its own thread.
+    pub fn par_merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        if self.config != other.config {
+            return Err(MergeError::ConfigMismatch);
+        }
+        self.version = self.version.wrapping_add(1);
+        #[cfg(feature = "metrics")]
+        {
+            self.metrics.merges += 1;
+        }
+
+        self.merge_total(&other.total);
+        // The counters of labels only tracked by `other` are created first,
+        // so that the shards only have to update counters in place.
+        for label in other.counters.keys() {
+            if !self.counters.contains_key(label) {
+                self.log_admission(label);
+                self.counters.insert(
+                    label.clone(),
+                    Counter::new(Cached::new(&self.config.cardinality_sketch_config)),
+                );
+            }
+        }
+        let others = &other.counters;
+        self.counters.par_iter_mut().for_each(|(label, counter)| {
+            let Some(c) = others.get(label) else {
+                return;
+            };
+            counter.offset = counter.offset.saturating_add(c.offset);
+            counter.sketch.merge(&c.sketch).unwrap_or_else(
+                // By construction, parameters cannot mismatch.
+                |_| unreachable!(),
+            );
+        });
+        self.evict_extra_counters();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hll::HyperLogLog;
+    use sketch_traits::{HeavyDistinctHitterSketch, Versioned};
+
+    use super::*;
+    use crate::{Config, ResetStrategy};
+
+    #[test]
+    fn par_merge_matches_merge() {
+        let config = Config::new(
+            100,
+            ResetStrategy::Offset,
+            hll::Config::new(64, Some([0; 8])).unwrap(),
+        )
+        .unwrap();
+        let mut left: SpaceSavingSets<u64, HyperLogLog<u64>> = SpaceSavingSets::new(&config);
+        let mut right = left.clone();
+        left.extend((0..10_000_u64).map(|i| (i % 150, i)));
+        right.extend((0..10_000_u64).map(|i| (i % 120, i * 7)));
+
+        let mut merged = left.clone();
+        assert!(merged.merge(&right).is_ok());
+        assert!(left.par_merge(&right).is_ok());
+        let (mut expected, mut actual) = (merged.top(usize::MAX), left.top(usize::MAX));
+        expected.sort();
+        actual.sort();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn rejected_merges_keep_the_version() {
+        let config = |num_registers| {
+            Config::new(
+                100,
+                ResetStrategy::Offset,
+                hll::Config::new(num_registers, Some([0; 8])).unwrap(),
+            )
+            .unwrap()
+        };
+        let mut sketch: SpaceSavingSets<u64, HyperLogLog<u64>> = SpaceSavingSets::new(&config(64));
+        sketch.insert(1, &1);
+        let other = SpaceSavingSets::new(&config(128));
+        let version = sketch.version();
+        assert!(matches!(
+            sketch.par_merge(&other),
+            Err(MergeError::ConfigMismatch)
+        ));
+        assert_eq!(sketch.version(), version);
+    }
+}
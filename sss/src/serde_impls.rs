@@ -0,0 +1,214 @@
+//! Sketches serialize their config and counters, each as a label, the state of
+//! its cardinality sketch and its offset, followed by the sketch of all items;
+//! configs are checked when deserializing.
+
+use alloc::vec::Vec;
+use core::hash::Hash;
+
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+use sketch_traits::{collections::HashMap, CardinalitySketch, New};
+
+use crate::{cached::Cached, Config, Counter, SpaceSavingSets};
+
+#[derive(Serialize)]
+#[serde(rename = "Config")]
+struct ConfigRef<'a, C, R> {
+    max_num_counters: usize,
+    reset_strategy: &'a R,
+    cardinality_sketch_config: &'a C,
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "Config")]
+struct ConfigRepr<C, R> {
+    max_num_counters: usize,
+    reset_strategy: R,
+    cardinality_sketch_config: C,
+}
+
+impl<C, R> Serialize for Config<C, R>
+where
+    C: Serialize,
+    R: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ConfigRef {
+            max_num_counters: self.max_num_counters,
+            reset_strategy: &self.reset_strategy,
+            cardinality_sketch_config: &self.cardinality_sketch_config,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, C, R> Deserialize<'de> for Config<C, R>
+where
+    C: Deserialize<'de>,
+    R: Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = ConfigRepr::deserialize(deserializer)?;
+        Config::new(
+            repr.max_num_counters,
+            repr.reset_strategy,
+            repr.cardinality_sketch_config,
+        )
+        .map_err(D::Error::custom)
+    }
+}
+
+/// Serializes counters as a sequence of (label, sketch, offset) triples.
+struct CountersRef<'a, L, S>(&'a HashMap<L, Counter<Cached<S>>>);
+
+impl<L, S> Serialize for CountersRef<'_, L, S>
+where
+    L: Serialize,
+    S: CardinalitySketch + Serialize,
+{
+    fn serialize<T: Serializer>(&self, serializer: T) -> Result<T::Ok, T::Error> {
+        serializer.collect_seq(
+            self.0
+                .iter()
+                .map(|(label, counter)| (label, counter.sketch.sketch(), counter.offset)),
+        )
+    }
+}
+
+#[derive(Serialize)]
+#[serde(
+    rename = "SpaceSavingSets",
+    bound = "L: Serialize, S: CardinalitySketch + Serialize, C: Serialize, R: Serialize"
+)]
+struct SketchRef<'a, L, S, C, R> {
+    config: &'a Config<C, R>,
+    counters: CountersRef<'a, L, S>,
+    total: &'a S,
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "SpaceSavingSets")]
+struct SketchRepr<L, S, C, R> {
+    config: Config<C, R>,
+    counters: Vec<(L, S, u64)>,
+    /// Missing from sketches serialized before it was tracked.
+    total: Option<S>,
+}
+
+impl<L, S, R> Serialize for SpaceSavingSets<L, S, R>
+where
+    L: Serialize,
+    S: CardinalitySketch + New + Serialize,
+    S::Config: Serialize,
+    R: Serialize,
+{
+    fn serialize<T: Serializer>(&self, serializer: T) -> Result<T::Ok, T::Error> {
+        SketchRef {
+            config: &self.config,
+            counters: CountersRef(&self.counters),
+            total: &self.total,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, L, S, R> Deserialize<'de> for SpaceSavingSets<L, S, R>
+where
+    L: Deserialize<'de> + Eq + Hash,
+    S: CardinalitySketch + New + Deserialize<'de>,
+    S::Config: Deserialize<'de> + Clone,
+    R: Deserialize<'de> + Clone,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = SketchRepr::<L, S, S::Config, R>::deserialize(deserializer)?;
+        let mut sketch = Self::new(&repr.config);
+        if repr.counters.len() > sketch.config.max_num_counters {
+            return Err(D::Error::custom(
+                "there are more counters than the configured maximum",
+            ));
+        }
+        for (label, counter_sketch, offset) in repr.counters {
+            let mut counter = Counter::new(Cached::new(&sketch.config.cardinality_sketch_config));
+            counter
+                .sketch
+                .merge(&Cached::from_sketch(counter_sketch))
+                .map_err(|_| D::Error::custom("a counter's config does not match the sketch's"))?;
+            counter.offset = offset;
+            if sketch.counters.insert(label, counter).is_some() {
+                return Err(D::Error::custom("a label has more than one counter"));
+            }
+        }
+        if let Some(total) = repr.total {
+            sketch
+                .total
+                .merge(&total)
+                .map_err(|_| D::Error::custom("the total's config does not match the sketch's"))?;
+        }
+        Ok(sketch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sketch_traits::HeavyDistinctHitterSketch;
+
+    use super::*;
+    use crate::ResetStrategy;
+
+    type HllSpaceSavingSets<L, I> = SpaceSavingSets<L, hll::HyperLogLog<I>>;
+
+    fn config() -> Config<hll::Config> {
+        Config::new(
+            10,
+            ResetStrategy::Offset,
+            hll::Config::new(512, Some([8, 9, 10, 11, 12, 13, 14, 15])).unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn it_round_trips() {
+        let mut sketch = HllSpaceSavingSets::<String, u64>::new(&config());
+        for label in 0..20_u64 {
+            for i in 0..label * 10 {
+                sketch.insert(label.to_string(), &i);
+            }
+        }
+        let json = serde_json::to_string(&sketch).unwrap();
+        let decoded: HllSpaceSavingSets<String, u64> = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.config, sketch.config);
+        assert_eq!(decoded.total_cardinality(), sketch.total_cardinality());
+        let mut expected = sketch.top(usize::MAX);
+        let mut actual = decoded.top(usize::MAX);
+        expected.sort();
+        actual.sort();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn it_rejects_invalid_states() {
+        let mut sketch = HllSpaceSavingSets::<u32, u64>::new(&config());
+        sketch.insert(0, &0);
+        let json = serde_json::to_value(&sketch).unwrap();
+
+        let mut duplicated = json.clone();
+        let counter = duplicated["counters"][0].clone();
+        duplicated["counters"].as_array_mut().unwrap().push(counter);
+        assert!(serde_json::from_value::<HllSpaceSavingSets<u32, u64>>(duplicated).is_err());
+
+        let mut zero_size = json;
+        zero_size["config"]["max_num_counters"] = 0.into();
+        assert!(serde_json::from_value::<HllSpaceSavingSets<u32, u64>>(zero_size).is_err());
+    }
+
+    #[test]
+    fn corrupted_offsets_saturate() {
+        let mut sketch = HllSpaceSavingSets::<u32, u64>::new(&config());
+        sketch.insert(0, &0);
+        let mut json = serde_json::to_value(&sketch).unwrap();
+        json["counters"][0][2] = u64::MAX.into();
+        let mut corrupted: HllSpaceSavingSets<u32, u64> = serde_json::from_value(json).unwrap();
+        assert_eq!(corrupted.cardinality(&0), u64::MAX);
+        assert!(corrupted.merge(&corrupted.clone()).is_ok());
+        assert_eq!(corrupted.cardinality(&0), u64::MAX);
+    }
+}
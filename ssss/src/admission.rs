@@ -0,0 +1,85 @@
+use core::hash::{BuildHasher, Hash};
+
+/// Crudely estimates the cardinality of an untracked label's set from a
+/// single one of its items, to decide whether the label may take over the
+/// counter with the minimum cardinality.
+///
+/// Implement this to prototype other estimators than the ones offered by
+/// [`AdmissionStrategy`].
+pub trait AdmissionEstimator {
+    fn estimate<L, I>(&self, hash_builder: &impl BuildHasher, label: &L, item: &I) -> u64
+    where
+        L: Hash,
+        I: Hash;
+}
+
+/// How to estimate the cardinality of an untracked label's set.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AdmissionStrategy {
+    /// `u64::MAX / hash(item)`: the cardinality of a set whose minimum hash
+    /// would be the item's.
+    #[default]
+    HashRatio,
+    /// `2^(trailing zeros of hash(item) + 1)`, as in HyperLogLog.
+    TrailingZeros,
+    /// The larger of two hash ratios of the item, from independent hashes,
+    /// halved since it's as if twice as many items were hashed.
+    TwoHashMax,
+}
+
+impl AdmissionEstimator for AdmissionStrategy {
+    #[inline]
+    fn estimate<L, I>(&self, hash_builder: &impl BuildHasher, _label: &L, item: &I) -> u64
+    where
+        L: Hash,
+        I: Hash,
+    {
+        let hash_ratio = |hash: u64| (u64::MAX as f64 / hash as f64) as u64;
+        match self {
+            AdmissionStrategy::HashRatio => hash_ratio(hash_builder.hash_one(item)),
+            AdmissionStrategy::TrailingZeros => {
+                let trailing_zeros = hash_builder.hash_one(item).trailing_zeros();
+                1_u64.checked_shl(trailing_zeros + 1).unwrap_or(u64::MAX)
+            }
+            AdmissionStrategy::TwoHashMax => {
+                let first = hash_builder.hash_one(item);
+                let second = hash_builder.hash_one((item, 1_u8));
+                hash_ratio(first).max(hash_ratio(second)) / 2
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ahash::RandomState;
+
+    use super::*;
+
+    #[test]
+    fn estimates_grow_with_the_cardinality() {
+        let hash_builder = RandomState::with_seeds(0, 1, 2, 3);
+        for strategy in [
+            AdmissionStrategy::HashRatio,
+            AdmissionStrategy::TrailingZeros,
+            AdmissionStrategy::TwoHashMax,
+        ] {
+            // The largest estimate over n items grows about linearly with n.
+            let max_estimate = |n: u64| {
+                (0..n)
+                    .map(|item| strategy.estimate(&hash_builder, &(), &item))
+                    .max()
+                    .unwrap_or(0)
+            };
+            let (small, large) = (max_estimate(100), max_estimate(100_000));
+            assert!(small < large, "{:?}: {} >= {}", strategy, small, large);
+            assert!(
+                (10_000..10_000_000).contains(&large),
+                "{:?}: {}",
+                strategy,
+                large
+            );
+        }
+    }
+}
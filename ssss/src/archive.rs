@@ -0,0 +1,245 @@
+//! Compact, read-only records of sketches, for long-term retention.
+//!
+//! Archives only keep the labels and cardinality sketches of the counters,
+//! folded to a lower precision. The threshold, versions, rejections and
+//! evictions are dropped, since archives are never inserted into again.
+//!
+//! Payload layout: version, cardinality sketch config, number of counters,
+//! then the labels of all counters followed by the states of their
+//! cardinality sketches, as for sketches.
+
+use alloc::vec::Vec;
+use core::hash::Hash;
+
+use sketch_traits::{
+    codec::{decode_version, encode_version, Decode, DecodeError, Encode, EncodeState},
+    collections::{hash_map::Entry, HashMap},
+    truncate_top, CardinalitySketch, Fold, MemorySize, New,
+};
+
+use crate::{MergeError, SamplingSpaceSavingSets};
+
+// Version 2 added the estimator to HyperLogLog configs, version 3 the sparse
+// encoding of their registers, and version 4 their register width.
+const FORMAT_VERSION: u8 = 4;
+
+/// The counters of a sketch, folded to a lower precision, e.g., to keep
+/// months of them around for historical queries.
+///
+/// Archives can be merged with each other, e.g., to query the labels of a
+/// whole quarter, but not inserted into. Merges keep the labels of both
+/// archives, so that no label of any archived period is lost.
+#[derive(Clone, Debug)]
+pub struct Archive<L, S>
+where
+    S: New,
+{
+    config: S::Config,
+    counters: HashMap<L, S>,
+}
+
+impl<L, S, A, H> SamplingSpaceSavingSets<L, S, A, H>
+where
+    L: Eq + Hash + Clone,
+    S: CardinalitySketch + Fold,
+{
+    /// Archives the counters, folding each of their cardinality sketches to
+    /// `config`. Fails if the sketches can't be folded to it, e.g., if it's
+    /// more precise than theirs.
+    pub fn archive(&self, config: &S::Config) -> Result<Archive<L, S>, MergeError>
+    where
+        S::Config: Clone,
+    {
+        let counters = self
+            .counters
+            .iter()
+            .map(|(label, counter)| {
+                let sketch = counter
+                    .sketch()
+                    .fold_to(config)
+                    .map_err(|_| MergeError::ConfigMismatch)?;
+                Ok((label.clone(), sketch))
+            })
+            .collect::<Result<_, MergeError>>()?;
+        Ok(Archive {
+            config: config.clone(),
+            counters,
+        })
+    }
+}
+
+impl<L, S> Archive<L, S>
+where
+    L: Eq + Hash + Clone,
+    S: CardinalitySketch + New + Clone,
+    S::Config: PartialEq,
+{
+    pub fn config(&self) -> &S::Config {
+        &self.config
+    }
+
+    pub fn len(&self) -> usize {
+        self.counters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counters.is_empty()
+    }
+
+    /// The estimated cardinality of `label`, or 0 if it wasn't tracked.
+    pub fn cardinality(&self, label: &L) -> u64 {
+        self.counters
+            .get(label)
+            .map_or(0, CardinalitySketch::cardinality)
+    }
+
+    pub fn labels(&self) -> impl Iterator<Item = &L> {
+        self.counters.keys()
+    }
+
+    pub fn top(&self, k: usize) -> Vec<(&L, u64)> {
+        let mut entries = self
+            .counters
+            .iter()
+            .map(|(label, sketch)| (label, sketch.cardinality()))
+            .collect::<Vec<_>>();
+        truncate_top(&mut entries, k);
+        entries
+    }
+
+    /// Merges the counters of `other`, e.g., the archive of the next period.
+    pub fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        if self.config != other.config {
+            return Err(MergeError::ConfigMismatch);
+        }
+        for (label, sketch) in &other.counters {
+            match self.counters.entry(label.clone()) {
+                Entry::Occupied(mut entry) => entry.get_mut().merge(sketch).unwrap_or_else(
+                    // By construction, parameters cannot mismatch.
+                    |_| unreachable!(),
+                ),
+                Entry::Vacant(entry) => {
+                    entry.insert(sketch.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<L, S> MemorySize for Archive<L, S>
+where
+    L: MemorySize,
+    S: New + MemorySize,
+    S::Config: MemorySize,
+{
+    fn heap_size(&self) -> usize {
+        self.config.heap_size() + self.counters.heap_size()
+    }
+}
+
+impl<L, S> Encode for Archive<L, S>
+where
+    L: Encode,
+    S: New + EncodeState,
+    S::Config: Encode,
+{
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_version(out, FORMAT_VERSION);
+        self.config.encode(out);
+        self.counters.len().encode(out);
+        self.counters.keys().for_each(|label| label.encode(out));
+        self.counters
+            .values()
+            .for_each(|sketch| sketch.encode_state(out));
+    }
+}
+
+impl<L, S> Decode for Archive<L, S>
+where
+    L: Decode + Eq + Hash,
+    S: New + EncodeState,
+    S::Config: Decode,
+{
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        decode_version(input, FORMAT_VERSION)?;
+        let config = S::Config::decode(input)?;
+        let num_counters = usize::decode(input)?;
+        // Each label takes at least a byte, which bounds the allocation.
+        if num_counters > input.len() {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let labels = (0..num_counters)
+            .map(|_| L::decode(input))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut counters = HashMap::with_capacity(num_counters);
+        for label in labels {
+            let mut sketch = S::new(&config);
+            sketch.merge_encoded_state(input)?;
+            if counters.insert(label, sketch).is_some() {
+                return Err(DecodeError::InvalidValue);
+            }
+        }
+        Ok(Self { config, counters })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sketch_traits::HeavyDistinctHitterSketch;
+
+    use super::*;
+    use crate::{Config, HllSamplingSpaceSavingSets};
+
+    const HLL_SEEDS: [u64; 8] = [8, 9, 10, 11, 12, 13, 14, 15];
+
+    fn sketch(labels: std::ops::Range<u32>) -> HllSamplingSpaceSavingSets<u32, u64> {
+        let config = Config::new(
+            10,
+            hll::Config::new(2048, Some(HLL_SEEDS)).unwrap(),
+            Some([0, 1, 2, 3]),
+        )
+        .unwrap();
+        let mut sketch = SamplingSpaceSavingSets::new(&config);
+        for label in labels {
+            for i in 0..u64::from(label + 1) * 100 {
+                sketch.insert(label, &i);
+            }
+        }
+        sketch
+    }
+
+    #[test]
+    fn archives_round_trip_and_merge() {
+        let archive_config = hll::Config::new(128, Some(HLL_SEEDS)).unwrap();
+        let mut archive = sketch(0..10).archive(&archive_config).unwrap();
+        let next = sketch(5..15).archive(&archive_config).unwrap();
+        assert_eq!(archive.len(), 10);
+        let label_9 = archive.cardinality(&9) as f64;
+        assert!((label_9 - 1000.0).abs() / 1000.0 < 0.3);
+
+        let mut buf = vec![];
+        archive.encode(&mut buf);
+        let mut full = vec![];
+        sketch(0..10).encode(&mut full);
+        assert!(buf.len() < full.len());
+        let decoded = Archive::<u32, hll::HyperLogLog<u64>>::decode(&mut buf.as_slice()).unwrap();
+        let mut expected = archive.top(usize::MAX);
+        let mut actual = decoded.top(usize::MAX);
+        expected.sort();
+        actual.sort();
+        assert_eq!(actual, expected);
+
+        assert!(archive.merge(&next).is_ok());
+        assert_eq!(archive.len(), 15);
+        assert_eq!(archive.top(1)[0].0, &14);
+        // Items are shared across periods, so merging doesn't add them up.
+        assert_eq!(archive.cardinality(&9), next.cardinality(&9));
+
+        let other_config = hll::Config::new(256, Some(HLL_SEEDS)).unwrap();
+        let other = sketch(0..1).archive(&other_config).unwrap();
+        assert!(archive.merge(&other).is_err());
+        let finer = hll::Config::new(4096, Some(HLL_SEEDS)).unwrap();
+        assert!(sketch(0..1).archive(&finer).is_err());
+    }
+}
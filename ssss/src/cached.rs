@@ -1,9 +1,25 @@
-use sketch_traits::{CardinalitySketch, New};
+use alloc::vec::Vec;
+
+use sketch_traits::{
+    codec::{DecodeError, EncodeState},
+    CardinalitySketch, Estimate, MemorySize, New, PrehashedCardinalitySketch,
+};
 
 #[derive(Clone, Debug)]
 pub(crate) struct Cached<S> {
     sketch: S,
     cardinality: u64,
+    /// The version of the parent sketch when the counter last changed.
+    modified: u64,
+}
+
+impl<S> MemorySize for Cached<S>
+where
+    S: MemorySize,
+{
+    fn heap_size(&self) -> usize {
+        self.sketch.heap_size()
+    }
 }
 
 impl<S> New for Cached<S>
@@ -17,10 +33,52 @@ where
         Self {
             sketch: S::new(config),
             cardinality: 0,
+            modified: 0,
         }
     }
 }
 
+impl<S> Cached<S>
+where
+    S: CardinalitySketch,
+{
+    pub(crate) fn from_sketch(sketch: S) -> Self {
+        Self {
+            cardinality: sketch.cardinality(),
+            sketch,
+            modified: 0,
+        }
+    }
+
+    /// Whether the cached cardinality matches the underlying sketch's.
+    pub(crate) fn is_consistent(&self) -> bool {
+        self.cardinality == self.sketch.cardinality()
+    }
+
+    pub(crate) fn sketch(&self) -> &S {
+        &self.sketch
+    }
+
+    /// Updates the sketch with `update`, e.g., to insert an item by its hash,
+    /// then refreshes the cached cardinality if `update` reports the sketch
+    /// may have changed.
+    #[inline]
+    pub(crate) fn update(&mut self, update: impl FnOnce(&mut S) -> bool) {
+        if update(&mut self.sketch) {
+            self.cardinality = self.sketch.cardinality();
+        }
+    }
+
+    #[inline]
+    pub(crate) fn touch(&mut self, version: u64) {
+        self.modified = version;
+    }
+
+    pub(crate) fn modified(&self) -> u64 {
+        self.modified
+    }
+}
+
 impl<S> CardinalitySketch for Cached<S>
 where
     S: CardinalitySketch,
@@ -28,9 +86,28 @@ where
     type Item = S::Item;
     type MergeError = S::MergeError;
 
+    /// Only refreshes the cached cardinality if the sketch may have changed.
     #[inline]
     fn insert(&mut self, item: &Self::Item) {
-        self.sketch.insert(item);
+        self.insert_report(item);
+    }
+
+    #[inline]
+    fn insert_report(&mut self, item: &Self::Item) -> bool {
+        let changed = self.sketch.insert_report(item);
+        if changed {
+            self.cardinality = self.sketch.cardinality();
+        }
+        changed
+    }
+
+    /// Only refreshes the cached cardinality once for the whole batch.
+    #[inline]
+    fn insert_all<'a>(&mut self, items: impl IntoIterator<Item = &'a Self::Item>)
+    where
+        Self::Item: 'a,
+    {
+        self.sketch.insert_all(items);
         self.cardinality = self.sketch.cardinality();
     }
 
@@ -51,4 +128,47 @@ where
     fn cardinality(&self) -> u64 {
         self.cardinality
     }
+
+    fn cardinality_with_error(&self) -> Option<Estimate> {
+        self.sketch.cardinality_with_error()
+    }
+
+    /// Not cached: computed by the sketch.
+    fn cardinality_f64(&self) -> f64 {
+        self.sketch.cardinality_f64()
+    }
+}
+
+impl<S> PrehashedCardinalitySketch for Cached<S>
+where
+    S: PrehashedCardinalitySketch,
+{
+    #[inline]
+    fn insert_hashed(&mut self, item_hash: u64) {
+        self.insert_hashed_report(item_hash);
+    }
+
+    #[inline]
+    fn insert_hashed_report(&mut self, item_hash: u64) -> bool {
+        let changed = self.sketch.insert_hashed_report(item_hash);
+        if changed {
+            self.cardinality = self.sketch.cardinality();
+        }
+        changed
+    }
+}
+
+impl<S> EncodeState for Cached<S>
+where
+    S: CardinalitySketch + EncodeState,
+{
+    fn encode_state(&self, out: &mut Vec<u8>) {
+        self.sketch.encode_state(out);
+    }
+
+    fn merge_encoded_state(&mut self, input: &mut &[u8]) -> Result<(), DecodeError> {
+        self.sketch.merge_encoded_state(input)?;
+        self.cardinality = self.sketch.cardinality();
+        Ok(())
+    }
 }
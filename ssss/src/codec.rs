@@ -0,0 +1,477 @@
+//! Payload layout: version, config, threshold, number of counters, then the
+//! labels of all counters followed by the states of their cardinality
+//! sketches, in the same order, and the state of the sketch of all items.
+//! Keeping each column contiguous lets generic compressors (see
+//! `sketch_traits::codec::zstd`) pick up more redundancy.
+//!
+//! Deltas share the layout, but only hold the counters that changed since a
+//! given version of the sketch, along with the whole sketch of all items.
+//! Since merging counters is idempotent, merging a delta into a sketch that
+//! has already merged the earlier payloads is like merging the whole sketch.
+
+use alloc::vec::Vec;
+use core::hash::Hash;
+
+use sketch_traits::{
+    codec::{
+        decode_version, encode_version, Decode, DecodeError, Encode, EncodeDelta, EncodeState,
+        MergeEncoded,
+    },
+    CardinalitySketch, New, SeedableBuildHasher,
+};
+
+use crate::{cached::Cached, AdmissionStrategy, Config, MergeError, SamplingSpaceSavingSets};
+
+// Version 2 added the hash width to HyperLogLog configs, version 3 the
+// admission strategy, version 4 laid counters out column-wise along with
+// compact HyperLogLog registers, version 5 added the overcommit factor, and
+// version 6 the sketch of all items, version 7 the estimator to HyperLogLog
+// configs, version 8 the sparse encoding of HyperLogLog registers, version 9
+// the register width to HyperLogLog configs, and version 10 dropped the
+// overcommit factor, which only tunes memory.
+const FORMAT_VERSION: u8 = 10;
+
+impl<L, S, H> Encode for SamplingSpaceSavingSets<L, S, AdmissionStrategy, H>
+where
+    L: Encode,
+    S: CardinalitySketch + New + EncodeState,
+    S::Config: Encode,
+    H: SeedableBuildHasher,
+{
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.encode_counters(self.counters.len(), || self.counters.iter(), out);
+    }
+}
+
+/// Counters the receiver pruned since the snapshot aren't sent again unless
+/// they changed, so the receiver may drop labels that merging a full payload
+/// would have brought back.
+impl<L, S, H> EncodeDelta for SamplingSpaceSavingSets<L, S, AdmissionStrategy, H>
+where
+    L: Encode,
+    S: CardinalitySketch + New + EncodeState,
+    S::Config: Encode,
+    H: SeedableBuildHasher,
+{
+    /// The version of the sketch at the time (see
+    /// [`Versioned`](sketch_traits::Versioned)).
+    type Snapshot = u64;
+
+    fn snapshot(&self) -> u64 {
+        self.version
+    }
+
+    fn encode_delta(&self, snapshot: &u64, out: &mut Vec<u8>) {
+        let changed = || {
+            self.counters
+                .iter()
+                .filter(move |(_, counter)| counter.modified() > *snapshot)
+        };
+        self.encode_counters(changed().count(), changed, out);
+    }
+}
+
+impl<L, S, H> SamplingSpaceSavingSets<L, S, AdmissionStrategy, H>
+where
+    L: Encode,
+    S: CardinalitySketch + New + EncodeState,
+    S::Config: Encode,
+    H: SeedableBuildHasher,
+{
+    /// Encodes the counters yielded by `counters`, which is called once per
+    /// column.
+    fn encode_counters<'a, C>(
+        &'a self,
+        num_counters: usize,
+        counters: impl Fn() -> C,
+        out: &mut Vec<u8>,
+    ) where
+        C: Iterator<Item = (&'a L, &'a Cached<S>)>,
+    {
+        encode_version(out, FORMAT_VERSION);
+        self.config.encode(out);
+        self.threshold.encode(out);
+        num_counters.encode(out);
+        counters().for_each(|(label, _)| label.encode(out));
+        counters().for_each(|(_, counter)| counter.encode_state(out));
+        self.total.encode_state(out);
+    }
+}
+
+/// Sketches are decoded with configs that don't overcommit, as the
+/// overcommit factor isn't encoded, so the counters of a sketch that did are
+/// pruned to the top `max_num_counters`.
+impl<L, S, H> Decode for SamplingSpaceSavingSets<L, S, AdmissionStrategy, H>
+where
+    L: Decode + Eq + Hash + Clone,
+    S: CardinalitySketch + New + EncodeState,
+    S::Config: Decode + Clone,
+    H: SeedableBuildHasher,
+{
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        decode_version(input, FORMAT_VERSION)?;
+        let mut sketch = Self::new(&Config::<_, _, H>::decode(input)?);
+        sketch.threshold = u64::decode(input)?;
+        let num_counters = usize::decode(input)?;
+        for label in decode_labels(input, num_counters)? {
+            let mut counter = Cached::new(&sketch.config.cardinality_sketch_config);
+            counter.merge_encoded_state(input)?;
+            if sketch.counters.insert(label, counter).is_some() {
+                return Err(DecodeError::InvalidValue);
+            }
+        }
+        sketch.total.merge_encoded_state(input)?;
+        let min_cardinality = sketch.counters.values().map(|c| c.cardinality()).min();
+        if min_cardinality.is_some_and(|min| sketch.threshold > min) {
+            return Err(DecodeError::InvalidValue);
+        }
+        if sketch.counters.len() > sketch.config.overcommitted_capacity() {
+            sketch.prune();
+        }
+        Ok(sketch)
+    }
+}
+
+impl<L, S, H> MergeEncoded for SamplingSpaceSavingSets<L, S, AdmissionStrategy, H>
+where
+    L: Decode + Eq + Hash + Clone,
+    S: CardinalitySketch + New + EncodeState,
+    S::Config: Decode + Eq,
+    H: SeedableBuildHasher,
+{
+    type Error = MergeError;
+
+    /// Merges an encoded sketch counter by counter.
+    ///
+    /// The states of all counters are read before any of them is merged, so
+    /// that a malformed payload, e.g., with out of range registers, leaves the
+    /// sketch untouched.
+    fn merge_encoded(&mut self, mut input: &[u8]) -> Result<(), Self::Error> {
+        decode_version(&mut input, FORMAT_VERSION)?;
+        if Config::<S::Config, AdmissionStrategy, H>::decode(&mut input)? != self.config {
+            return Err(MergeError::ConfigMismatch);
+        }
+        let _threshold = u64::decode(&mut input)?;
+        // Payloads of overcommitting sketches may hold more counters than
+        // this one keeps, which merging prunes.
+        let num_counters = usize::decode(&mut input)?;
+        let labels = decode_labels(&mut input, num_counters)?;
+        let counters = labels
+            .into_iter()
+            .map(|label| {
+                let mut counter = Cached::new(&self.config.cardinality_sketch_config);
+                counter.merge_encoded_state(&mut input)?;
+                Ok((label, counter))
+            })
+            .collect::<Result<Vec<_>, DecodeError>>()?;
+        let mut total = S::new(&self.config.cardinality_sketch_config);
+        total.merge_encoded_state(&mut input)?;
+
+        self.version = self.version.wrapping_add(1);
+        #[cfg(feature = "metrics")]
+        {
+            self.metrics.merges += 1;
+        }
+        self.merge_total(&total);
+        for (label, other) in counters {
+            if self.observer.is_some() && !self.counters.contains_key(&label) {
+                self.log_admission(&label);
+            }
+            let counter = self.counters.get_or_insert_with(label, || {
+                Cached::new(&self.config.cardinality_sketch_config)
+            });
+            counter.merge(&other).unwrap_or_else(
+                // By construction, parameters cannot mismatch.
+                |_| unreachable!(),
+            );
+            counter.touch(self.version);
+        }
+        self.prune_after_merge();
+        Ok(())
+    }
+}
+
+fn decode_labels<L>(input: &mut &[u8], num_counters: usize) -> Result<Vec<L>, DecodeError>
+where
+    L: Decode,
+{
+    (0..num_counters).map(|_| L::decode(input)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use sketch_traits::{codec::DeltaEncoder, HeavyDistinctHitterSketch, Validate, Versioned};
+
+    use super::*;
+    use crate::{AdmissionStrategy, HllSamplingSpaceSavingSets};
+
+    fn config() -> Config<hll::Config> {
+        Config::new(
+            10,
+            hll::Config::new(512, Some([8, 9, 10, 11, 12, 13, 14, 15])).unwrap(),
+            Some([0, 1, 2, 3]),
+        )
+        .unwrap()
+    }
+
+    fn sketch(
+        labels: impl Iterator<Item = u32>,
+        items: std::ops::Range<u64>,
+    ) -> HllSamplingSpaceSavingSets<u32, u64> {
+        let mut sketch = SamplingSpaceSavingSets::new(&config());
+        for label in labels {
+            for i in items.clone() {
+                sketch.insert(label, &(i * label as u64));
+            }
+        }
+        sketch
+    }
+
+    fn sorted_top(sketch: &HllSamplingSpaceSavingSets<u32, u64>) -> Vec<(u32, u64)> {
+        let mut top = sketch
+            .top(usize::MAX)
+            .into_iter()
+            .map(|(l, c)| (*l, c))
+            .collect::<Vec<_>>();
+        top.sort();
+        top
+    }
+
+    #[test]
+    fn it_round_trips() {
+        let sketch = sketch(0..10, 0..100);
+        let mut buf = vec![];
+        sketch.encode(&mut buf);
+        let decoded = HllSamplingSpaceSavingSets::<u32, u64>::decode(&mut buf.as_slice()).unwrap();
+        assert_eq!(sorted_top(&decoded), sorted_top(&sketch));
+        assert_eq!(decoded.threshold, sketch.threshold);
+        assert_eq!(decoded.total_cardinality(), sketch.total_cardinality());
+    }
+
+    #[test]
+    fn it_merges_encoded_like_merge() {
+        let sketch1 = sketch(0..10, 0..100);
+        let sketch2 = sketch(5..15, 50..300);
+
+        let mut buf = vec![];
+        sketch2.encode(&mut buf);
+        let mut streamed = sketch1.clone();
+        assert!(streamed.merge_encoded(&buf).is_ok());
+        let mut merged = sketch1;
+        assert!(merged.merge(&sketch2).is_ok());
+
+        assert_eq!(sorted_top(&streamed), sorted_top(&merged));
+        assert_eq!(streamed.threshold, merged.threshold);
+        assert_eq!(streamed.total_cardinality(), merged.total_cardinality());
+    }
+
+    #[test]
+    fn deltas_merge_like_full_payloads() {
+        let mut agent = sketch(0..10, 0..100);
+        let mut full = vec![];
+        agent.encode(&mut full);
+        let snapshot = agent.snapshot();
+        let mut aggregate = HllSamplingSpaceSavingSets::<u32, u64>::new(&config());
+        assert!(aggregate.merge_encoded(&full).is_ok());
+
+        for i in 100..200 {
+            agent.insert(3, &(i * 3));
+        }
+        let mut delta = vec![];
+        agent.encode_delta(&snapshot, &mut delta);
+        let mut full = vec![];
+        agent.encode(&mut full);
+        // Only the counter of label 3 is sent, along with the sketch of all
+        // items.
+        assert!(delta.len() < full.len() / 2);
+
+        let mut expected = aggregate.clone();
+        assert!(expected.merge_encoded(&full).is_ok());
+        assert!(aggregate.merge_encoded(&delta).is_ok());
+        assert_eq!(sorted_top(&aggregate), sorted_top(&expected));
+        assert_eq!(aggregate.total_cardinality(), expected.total_cardinality());
+
+        let mut empty = vec![];
+        agent.encode_delta(&agent.snapshot(), &mut empty);
+        let decoded =
+            HllSamplingSpaceSavingSets::<u32, u64>::decode(&mut empty.as_slice()).unwrap();
+        assert_eq!(decoded.num_labels(), 0);
+    }
+
+    #[test]
+    fn delta_encoders_replicate_sketches() {
+        let mut agent = HllSamplingSpaceSavingSets::<u32, u64>::new(&config());
+        let mut aggregate = agent.clone();
+        let mut encoder = DeltaEncoder::new();
+        for round in 0..5 {
+            // Only a few labels change every round.
+            for label in round..round + 3 {
+                for i in 0..50 {
+                    agent.insert(label, &(u64::from(round) * 1_000 + i));
+                }
+            }
+            let mut buf = vec![];
+            encoder.encode_next(&agent, &mut buf);
+            assert!(aggregate.merge_encoded(&buf).is_ok());
+        }
+        assert_eq!(sorted_top(&aggregate), sorted_top(&agent));
+        assert_eq!(aggregate.total_cardinality(), agent.total_cardinality());
+    }
+
+    #[test]
+    fn it_round_trips_composite_labels() {
+        let mut sketch = HllSamplingSpaceSavingSets::<(u32, String), u64>::new(&config());
+        for customer in 0..5 {
+            for endpoint in ["/login", "/search"] {
+                for i in 0..50 {
+                    sketch.insert((customer, endpoint.to_string()), &i);
+                }
+            }
+        }
+        let mut buf = vec![];
+        sketch.encode(&mut buf);
+        let decoded =
+            HllSamplingSpaceSavingSets::<(u32, String), u64>::decode(&mut buf.as_slice()).unwrap();
+        let mut expected = sketch.top(usize::MAX);
+        let mut actual = decoded.top(usize::MAX);
+        expected.sort();
+        actual.sort();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn it_rejects_mismatched_or_truncated_payloads() {
+        let mut buf = vec![];
+        sketch(0..10, 0..100).encode(&mut buf);
+
+        let other_config = Config::new(
+            10,
+            hll::Config::new(512, Some([8, 9, 10, 11, 12, 13, 14, 15])).unwrap(),
+            Some([4, 5, 6, 7]),
+        )
+        .unwrap();
+        let mut other = HllSamplingSpaceSavingSets::<u32, u64>::new(&other_config);
+        assert!(matches!(
+            other.merge_encoded(&buf),
+            Err(MergeError::ConfigMismatch)
+        ));
+        assert_eq!(other.version(), 0);
+
+        buf.truncate(buf.len() - 1);
+        let mut sketch = sketch(0..3, 0..10);
+        let version = sketch.version();
+        assert!(matches!(
+            sketch.merge_encoded(&buf),
+            Err(MergeError::Decode(DecodeError::UnexpectedEnd))
+        ));
+        assert_eq!(sorted_top(&sketch), sorted_top(&self::sketch(0..3, 0..10)));
+        assert_eq!(sketch.version(), version);
+    }
+
+    /// A payload of counters with raw registers, which may be out of range.
+    fn payload(threshold: u64, num_counters: usize, registers: &[(u32, u8)]) -> Vec<u8> {
+        let mut buf = vec![];
+        encode_version(&mut buf, FORMAT_VERSION);
+        config().encode(&mut buf);
+        threshold.encode(&mut buf);
+        num_counters.encode(&mut buf);
+        registers
+            .iter()
+            .for_each(|(label, _)| label.encode(&mut buf));
+        for &(_, register) in registers {
+            // Tags registers as raw.
+            buf.push(0);
+            buf.extend([register; 512]);
+        }
+        // An empty sketch of all items.
+        buf.push(0);
+        buf.extend([0; 512]);
+        buf
+    }
+
+    #[test]
+    fn corrupted_payloads_leave_the_sketch_untouched() {
+        let mut sketch = sketch(0..3, 0..10);
+        let before = sorted_top(&sketch);
+
+        // Registers can't exceed the number of bits of hashes, plus one.
+        let buf = payload(0, 2, &[(7, 1), (8, 99)]);
+        assert!(matches!(
+            sketch.merge_encoded(&buf),
+            Err(MergeError::Decode(DecodeError::InvalidValue))
+        ));
+        assert!(matches!(
+            HllSamplingSpaceSavingSets::<u32, u64>::decode(&mut buf.as_slice()),
+            Err(DecodeError::InvalidValue)
+        ));
+        // There can't be more counters than the payload holds.
+        let buf = payload(0, usize::MAX, &[(7, 1), (8, 1)]);
+        assert!(matches!(
+            sketch.merge_encoded(&buf),
+            Err(MergeError::Decode(DecodeError::UnexpectedEnd))
+        ));
+        assert!(HllSamplingSpaceSavingSets::<u32, u64>::decode(&mut buf.as_slice()).is_err());
+        assert_eq!(sorted_top(&sketch), before);
+
+        // The threshold can't exceed the minimum cardinality.
+        let buf = payload(u64::MAX, 1, &[(7, 1)]);
+        assert!(HllSamplingSpaceSavingSets::<u32, u64>::decode(&mut buf.as_slice()).is_err());
+        let buf = payload(0, 1, &[(7, 1)]);
+        assert!(HllSamplingSpaceSavingSets::<u32, u64>::decode(&mut buf.as_slice()).is_ok());
+        assert!(sketch.merge_encoded(&buf).is_ok());
+        assert!(sketch.debug_validate().is_ok());
+    }
+
+    #[test]
+    fn it_round_trips_overcommitted_sketches() {
+        let config = config().with_overcommit(2, 0).unwrap();
+        let mut sketch = HllSamplingSpaceSavingSets::<u32, u64>::new(&config);
+        for sketch_labels in [0..10, 10..20] {
+            let mut other = HllSamplingSpaceSavingSets::new(&config);
+            for label in sketch_labels {
+                other.insert(label, &u64::from(label));
+            }
+            sketch.merge(&other).unwrap();
+        }
+        assert_eq!(sketch.num_labels(), 20);
+        let mut buf = vec![];
+        sketch.encode(&mut buf);
+        // The overcommit factor isn't encoded, so decoding prunes, but
+        // merging into an overcommitting sketch keeps all the counters.
+        let decoded = HllSamplingSpaceSavingSets::<u32, u64>::decode(&mut buf.as_slice()).unwrap();
+        assert_eq!(decoded.config(), &config);
+        assert_eq!(decoded.config().overcommit_factor(), 1);
+        assert_eq!(decoded.num_labels(), 10);
+        assert!(decoded.debug_validate().is_ok());
+        let mut merged = HllSamplingSpaceSavingSets::<u32, u64>::new(&config);
+        assert!(merged.merge_encoded(&buf).is_ok());
+        assert_eq!(merged.num_labels(), 20);
+
+        // Agents that don't overcommit merge into aggregators that do.
+        let mut aggregator = HllSamplingSpaceSavingSets::<u32, u64>::new(&config);
+        assert!(aggregator.merge(&decoded).is_ok());
+        let mut buf = vec![];
+        decoded.encode(&mut buf);
+        assert!(aggregator.merge_encoded(&buf).is_ok());
+        assert_eq!(aggregator.num_labels(), 10);
+    }
+
+    #[test]
+    fn it_round_trips_admission_strategies() {
+        let two_hash_config = config().with_admission(AdmissionStrategy::TwoHashMax);
+        let mut buf = vec![];
+        two_hash_config.encode(&mut buf);
+        assert_eq!(Config::decode(&mut buf.as_slice()), Ok(two_hash_config));
+
+        let mut other = HllSamplingSpaceSavingSets::<u32, u64>::new(&config());
+        let mut buf = vec![];
+        HllSamplingSpaceSavingSets::<u32, u64>::new(
+            &config().with_admission(AdmissionStrategy::TrailingZeros),
+        )
+        .encode(&mut buf);
+        assert!(matches!(
+            other.merge_encoded(&buf),
+            Err(MergeError::ConfigMismatch)
+        ));
+    }
+}
@@ -1,15 +1,34 @@
-use std::{error, fmt};
+use alloc::vec::Vec;
+use core::{error, fmt};
 
 use ahash::RandomState;
-use rand::random;
+use sketch_traits::{
+    codec::{Decode, DecodeError, Encode},
+    default_seeds, MemorySize, SeedableBuildHasher,
+};
 
+use crate::admission::AdmissionStrategy;
+
+/// The parameters of SSSS, hashing labels and items with `H`, ahash by
+/// default.
 #[derive(Clone, Debug)]
-pub struct Config<C> {
+pub struct Config<C, A = AdmissionStrategy, H = RandomState> {
     /// The maximum number of counters to keep.
     pub(crate) max_num_counters: usize,
-    seeds: [u64; 4],
-    pub(crate) hash_builder: RandomState,
+    pub(crate) seeds: [u64; 4],
+    pub(crate) hash_builder: H,
     pub(crate) cardinality_sketch_config: C,
+    pub(crate) admission: A,
+    /// How many times `max_num_counters` counters merges may keep around
+    /// before pruning. Like `merges_per_prune`, it only tunes the memory of
+    /// the sketch, so sketches with either merge and encode the same.
+    pub(crate) overcommit_factor: usize,
+    /// Merges prune after this many of them, or only once the overcommitted
+    /// capacity is exceeded if zero.
+    pub(crate) merges_per_prune: usize,
+    /// How many counters are kept in the hot array ahead of the map, see
+    /// [`Config::with_hot_capacity`].
+    pub(crate) hot_capacity: usize,
 }
 
 impl<C> Config<C> {
@@ -17,24 +36,110 @@ impl<C> Config<C> {
         max_num_counters: usize,
         cardinality_sketch_config: C,
         seeds: Option<[u64; 4]>,
+    ) -> Result<Self, ConfigError> {
+        Self::with_hasher(max_num_counters, cardinality_sketch_config, seeds)
+    }
+}
+
+impl<C, H> Config<C, AdmissionStrategy, H>
+where
+    H: SeedableBuildHasher,
+{
+    /// Like [`Config::new`], but hashing labels and items with `H`, built
+    /// from the seeds.
+    pub fn with_hasher(
+        max_num_counters: usize,
+        cardinality_sketch_config: C,
+        seeds: Option<[u64; 4]>,
     ) -> Result<Self, ConfigError> {
         if max_num_counters == 0 {
             return Err(ConfigError::ZeroMaxNumCounters);
         }
-        let seeds_or_random = seeds.unwrap_or_else(random);
+        let seeds_or_random = seeds.unwrap_or_else(default_seeds);
         Ok(Self {
             max_num_counters,
             seeds: seeds_or_random,
-            hash_builder: RandomState::with_seeds(
-                seeds_or_random[0],
-                seeds_or_random[1],
-                seeds_or_random[2],
-                seeds_or_random[3],
-            ),
+            hash_builder: H::with_seeds(seeds_or_random),
             cardinality_sketch_config,
+            admission: AdmissionStrategy::default(),
+            overcommit_factor: 1,
+            merges_per_prune: 1,
+            hot_capacity: 0,
+        })
+    }
+}
+
+impl<C, A, H> Config<C, A, H> {
+    /// Sets how the cardinalities of untracked labels are estimated when
+    /// deciding whether to admit them.
+    pub fn with_admission<B>(self, admission: B) -> Config<C, B, H> {
+        Config {
+            max_num_counters: self.max_num_counters,
+            seeds: self.seeds,
+            hash_builder: self.hash_builder,
+            cardinality_sketch_config: self.cardinality_sketch_config,
+            admission,
+            overcommit_factor: self.overcommit_factor,
+            merges_per_prune: self.merges_per_prune,
+            hot_capacity: self.hot_capacity,
+        }
+    }
+
+    /// Lets merges keep up to `overcommit_factor` times `max_num_counters`
+    /// counters, and only prune back down to `max_num_counters` every
+    /// `merges_per_prune` merges, or once the overcommitted capacity is
+    /// exceeded, or when calling
+    /// [`prune`](crate::SamplingSpaceSavingSets::prune).
+    ///
+    /// Bursts of merges then sort counters once rather than after each merge,
+    /// and labels just below the cut in one merge can still make it once the
+    /// others are in, at the cost of the memory of the extra counters. Setting
+    /// `merges_per_prune` to zero leaves pruning to the capacity and explicit
+    /// calls. Queries in between see all the counters kept.
+    ///
+    /// Configs that only differ by these settings are equal, so that an
+    /// overcommitting aggregator merges the sketches of agents that don't,
+    /// and the settings aren't encoded.
+    pub fn with_overcommit(
+        self,
+        overcommit_factor: usize,
+        merges_per_prune: usize,
+    ) -> Result<Self, ConfigError> {
+        if overcommit_factor == 0
+            || self
+                .max_num_counters
+                .checked_mul(overcommit_factor)
+                .is_none()
+        {
+            return Err(ConfigError::InvalidOvercommitFactor);
+        }
+        Ok(Self {
+            overcommit_factor,
+            merges_per_prune,
+            ..self
         })
     }
 
+    /// Keeps up to `hot_capacity` counters, e.g., 64, in a small array looked
+    /// up ahead of the map of the others, where the counters updated last
+    /// move to, rather than all of them in the map, as by default. The
+    /// `SsssHotCounters` benchmark has the array slower than the map on
+    /// Zipf-distributed integer labels; it may only pay off for labels that
+    /// are costly to hash but cheap to compare.
+    ///
+    /// Like the overcommit factor, this only tunes the sketch, so configs
+    /// that only differ by it are equal, and it isn't encoded.
+    pub fn with_hot_capacity(self, hot_capacity: usize) -> Self {
+        Self {
+            hot_capacity,
+            ..self
+        }
+    }
+
+    pub fn admission(&self) -> &A {
+        &self.admission
+    }
+
     pub fn max_num_counters(&self) -> usize {
         self.max_num_counters
     }
@@ -42,24 +147,218 @@ impl<C> Config<C> {
     pub fn cardinality_sketch_config(&self) -> &C {
         &self.cardinality_sketch_config
     }
+
+    pub fn overcommit_factor(&self) -> usize {
+        self.overcommit_factor
+    }
+
+    pub fn merges_per_prune(&self) -> usize {
+        self.merges_per_prune
+    }
+
+    pub fn hot_capacity(&self) -> usize {
+        self.hot_capacity
+    }
+
+    /// The number of counters merges may keep before they have to prune.
+    pub(crate) fn overcommitted_capacity(&self) -> usize {
+        // Checked when setting the factor.
+        self.max_num_counters * self.overcommit_factor
+    }
+}
+
+/// The number of counters of [`Config::default`] and [`Config::builder`].
+pub const DEFAULT_MAX_NUM_COUNTERS: usize = 100;
+
+impl<C> Config<C> {
+    /// Builds a config by naming only the parameters that differ from
+    /// [`Config::default`]'s.
+    pub fn builder() -> ConfigBuilder<C> {
+        ConfigBuilder {
+            max_num_counters: DEFAULT_MAX_NUM_COUNTERS,
+            cardinality_sketch_config: None,
+            seeds: None,
+            admission: AdmissionStrategy::default(),
+            overcommit_factor: 1,
+            merges_per_prune: 1,
+            hot_capacity: 0,
+        }
+    }
+}
+
+/// [`DEFAULT_MAX_NUM_COUNTERS`] counters of cardinality sketches with
+/// default configs, and random seeds.
+impl<C> Default for Config<C>
+where
+    C: Default,
+{
+    fn default() -> Self {
+        Self::builder()
+            .build()
+            .expect("the default number of counters is supported")
+    }
+}
+
+/// Builds [`Config`]s, see [`Config::builder`].
+#[derive(Clone, Debug)]
+pub struct ConfigBuilder<C> {
+    max_num_counters: usize,
+    cardinality_sketch_config: Option<C>,
+    seeds: Option<[u64; 4]>,
+    admission: AdmissionStrategy,
+    overcommit_factor: usize,
+    merges_per_prune: usize,
+    hot_capacity: usize,
+}
+
+impl<C> ConfigBuilder<C> {
+    pub fn counters(mut self, max_num_counters: usize) -> Self {
+        self.max_num_counters = max_num_counters;
+        self
+    }
+
+    /// Configures the cardinality sketches of the counters, rather than
+    /// using the default config.
+    pub fn cardinality_sketch_config(mut self, cardinality_sketch_config: C) -> Self {
+        self.cardinality_sketch_config = Some(cardinality_sketch_config);
+        self
+    }
+
+    /// Seeds the hashers, rather than drawing random seeds.
+    pub fn seeds(mut self, seeds: [u64; 4]) -> Self {
+        self.seeds = Some(seeds);
+        self
+    }
+
+    /// See [`Config::with_admission`].
+    pub fn admission(mut self, admission: AdmissionStrategy) -> Self {
+        self.admission = admission;
+        self
+    }
+
+    /// See [`Config::with_overcommit`].
+    pub fn overcommit(mut self, overcommit_factor: usize, merges_per_prune: usize) -> Self {
+        self.overcommit_factor = overcommit_factor;
+        self.merges_per_prune = merges_per_prune;
+        self
+    }
+
+    /// See [`Config::with_hot_capacity`].
+    pub fn hot_capacity(mut self, hot_capacity: usize) -> Self {
+        self.hot_capacity = hot_capacity;
+        self
+    }
+
+    /// Fails like [`Config::new`] and [`Config::with_overcommit`].
+    pub fn build(self) -> Result<Config<C>, ConfigError>
+    where
+        C: Default,
+    {
+        self.build_with_hasher()
+    }
+
+    /// Like [`build`](Self::build), but hashing labels and items with `H`, as
+    /// [`Config::with_hasher`].
+    pub fn build_with_hasher<H>(self) -> Result<Config<C, AdmissionStrategy, H>, ConfigError>
+    where
+        C: Default,
+        H: SeedableBuildHasher,
+    {
+        Config::with_hasher(
+            self.max_num_counters,
+            self.cardinality_sketch_config.unwrap_or_default(),
+            self.seeds,
+        )?
+        .with_admission(self.admission)
+        .with_hot_capacity(self.hot_capacity)
+        .with_overcommit(self.overcommit_factor, self.merges_per_prune)
+    }
 }
 
-impl<C> PartialEq for Config<C>
+impl<C, A, H> PartialEq for Config<C, A, H>
 where
     C: PartialEq,
+    A: PartialEq,
 {
     fn eq(&self, other: &Self) -> bool {
         self.max_num_counters == other.max_num_counters
             && self.seeds == other.seeds
             && self.cardinality_sketch_config == other.cardinality_sketch_config
+            && self.admission == other.admission
     }
 }
 
-impl<C> Eq for Config<C> where C: Eq {}
+impl<C, A, H> Eq for Config<C, A, H>
+where
+    C: Eq,
+    A: Eq,
+{
+}
+
+/// Admission estimators are assumed to be held inline.
+impl<C, A, H> MemorySize for Config<C, A, H>
+where
+    C: MemorySize,
+{
+    fn heap_size(&self) -> usize {
+        self.cardinality_sketch_config.heap_size()
+    }
+}
+
+impl<C, H> Encode for Config<C, AdmissionStrategy, H>
+where
+    C: Encode,
+{
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.max_num_counters.encode(out);
+        self.seeds.encode(out);
+        self.cardinality_sketch_config.encode(out);
+        self.admission.encode(out);
+    }
+}
+
+impl<C, H> Decode for Config<C, AdmissionStrategy, H>
+where
+    C: Decode,
+    H: SeedableBuildHasher,
+{
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let max_num_counters = usize::decode(input)?;
+        let seeds = <[u64; 4]>::decode(input)?;
+        let cardinality_sketch_config = C::decode(input)?;
+        let admission = AdmissionStrategy::decode(input)?;
+        Config::with_hasher(max_num_counters, cardinality_sketch_config, Some(seeds))
+            .map(|config| config.with_admission(admission))
+            .map_err(|_| DecodeError::InvalidValue)
+    }
+}
+
+impl Encode for AdmissionStrategy {
+    fn encode(&self, out: &mut Vec<u8>) {
+        let tag: u8 = match self {
+            AdmissionStrategy::HashRatio => 0,
+            AdmissionStrategy::TrailingZeros => 1,
+            AdmissionStrategy::TwoHashMax => 2,
+        };
+        tag.encode(out);
+    }
+}
+
+impl Decode for AdmissionStrategy {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match u8::decode(input)? {
+            0 => Ok(AdmissionStrategy::HashRatio),
+            1 => Ok(AdmissionStrategy::TrailingZeros),
+            2 => Ok(AdmissionStrategy::TwoHashMax),
+            _ => Err(DecodeError::InvalidValue),
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub enum ConfigError {
     ZeroMaxNumCounters,
+    InvalidOvercommitFactor,
 }
 
 impl fmt::Display for ConfigError {
@@ -68,8 +367,73 @@ impl fmt::Display for ConfigError {
             ConfigError::ZeroMaxNumCounters => {
                 write!(f, "the size should not be zero")
             }
+            ConfigError::InvalidOvercommitFactor => {
+                write!(
+                    f,
+                    "the overcommit factor should be positive, and not overflow the size"
+                )
+            }
         }
     }
 }
 
 impl error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builders_default_to_new() {
+        let hll_config = hll::Config::new(64, Some([0, 1, 2, 3, 4, 5, 6, 7])).unwrap();
+        let seeds = [8, 9, 10, 11];
+        assert_eq!(
+            Config::builder()
+                .cardinality_sketch_config(hll_config.clone())
+                .seeds(seeds)
+                .build()
+                .unwrap(),
+            Config::new(DEFAULT_MAX_NUM_COUNTERS, hll_config.clone(), Some(seeds)).unwrap()
+        );
+        assert_eq!(
+            Config::builder()
+                .counters(10)
+                .cardinality_sketch_config(hll_config.clone())
+                .seeds(seeds)
+                .admission(AdmissionStrategy::TwoHashMax)
+                .overcommit(4, 0)
+                .build()
+                .unwrap(),
+            Config::new(10, hll_config, Some(seeds))
+                .unwrap()
+                .with_admission(AdmissionStrategy::TwoHashMax)
+                .with_overcommit(4, 0)
+                .unwrap()
+        );
+        assert!(matches!(
+            Config::<hll::Config>::builder().counters(0).build(),
+            Err(ConfigError::ZeroMaxNumCounters)
+        ));
+        assert!(matches!(
+            Config::<hll::Config>::builder().overcommit(0, 1).build(),
+            Err(ConfigError::InvalidOvercommitFactor)
+        ));
+        let config = Config::<hll::Config>::default();
+        assert_eq!(config.max_num_counters(), DEFAULT_MAX_NUM_COUNTERS);
+        assert_eq!(config, config.clone().with_overcommit(4, 0).unwrap());
+        assert_eq!(config.hot_capacity(), 0);
+        assert_eq!(config, config.clone().with_hot_capacity(64));
+        assert_eq!(
+            Config::<hll::Config>::builder()
+                .hot_capacity(64)
+                .build()
+                .unwrap()
+                .hot_capacity(),
+            64
+        );
+        assert_eq!(
+            config.cardinality_sketch_config().num_registers(),
+            hll::DEFAULT_NUM_REGISTERS
+        );
+    }
+}
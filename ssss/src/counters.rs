@@ -0,0 +1,319 @@
+use alloc::vec::Vec;
+use core::{
+    hash::{BuildHasher, Hash},
+    mem,
+};
+
+use ahash::RandomState;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use sketch_traits::{collections::HashMap, MemorySize};
+
+/// Hashes labels into the tags of the hot array. Fixed seeds, as tags never
+/// leave the process.
+const TAG_HASHER: RandomState = RandomState::with_seeds(
+    0x243f_6a88_85a3_08d3,
+    0x1319_8a2e_0370_7344,
+    0xa409_3822_299f_31d0,
+    0x082e_fa98_ec4e_6c89,
+);
+
+/// Counters split between a small hot array, small enough to stay in cache,
+/// and a map holding the warm remainder, or all of them if the hot capacity
+/// is zero (see [`Config::with_hot_capacity`](crate::Config::with_hot_capacity)).
+///
+/// When most inserts hit a few dozen labels, those are found by scanning a
+/// byte tag per hot counter, hashed with a cheaper hasher than the map's, and
+/// only comparing the labels of matching tags; labels that aren't hot mostly
+/// skip the hot array after the tag scan. A warm label is promoted when it's
+/// updated, and the hot counter it replaces is picked with the CLOCK policy:
+/// the hand skips, and unmarks, counters that were updated since it last
+/// passed them.
+///
+/// The map hashes labels with `S`, the sketch's seeded label hasher, so that
+/// sketches of the same config that went through the same updates iterate
+/// their counters in the same order.
+#[derive(Clone, Debug)]
+pub(crate) struct Counters<L, C, S> {
+    hot: Vec<HotCounter<L, C>>,
+    /// The tags of the labels of `hot`, at the same indices.
+    tags: Vec<u8>,
+    hot_capacity: usize,
+    hand: usize,
+    warm: HashMap<L, C, S>,
+}
+
+#[derive(Clone, Debug)]
+struct HotCounter<L, C> {
+    label: L,
+    counter: C,
+    referenced: bool,
+}
+
+impl<L, C, S> Counters<L, C, S> {
+    /// Counters keeping up to `hot_capacity` of them in the hot array, or
+    /// all of them in the map if zero, which hashes labels with
+    /// `hash_builder`.
+    pub(crate) fn with_capacity(capacity: usize, hot_capacity: usize, hash_builder: S) -> Self {
+        Self {
+            hot: Vec::with_capacity(capacity.min(hot_capacity)),
+            tags: Vec::with_capacity(capacity.min(hot_capacity)),
+            hot_capacity,
+            hand: 0,
+            warm: HashMap::with_capacity_and_hasher(
+                capacity.saturating_sub(hot_capacity),
+                hash_builder,
+            ),
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.hot.clear();
+        self.tags.clear();
+        self.hand = 0;
+        self.warm.clear();
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.hot.len() + self.warm.len()
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&L, &C)> {
+        self.hot
+            .iter()
+            .map(|hot| (&hot.label, &hot.counter))
+            .chain(self.warm.iter())
+    }
+
+    #[cfg(feature = "rayon")]
+    pub(crate) fn par_iter_mut(&mut self) -> impl ParallelIterator<Item = (&L, &mut C)>
+    where
+        L: Eq + Hash + Send + Sync,
+        C: Send,
+        S: BuildHasher + Sync,
+    {
+        self.hot
+            .par_iter_mut()
+            .map(|hot| (&hot.label, &mut hot.counter))
+            .chain(self.warm.par_iter_mut())
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = &C> {
+        self.iter().map(|(_, counter)| counter)
+    }
+
+    pub(crate) fn into_entries(self) -> impl Iterator<Item = (L, C)> {
+        self.hot
+            .into_iter()
+            .map(|hot| (hot.label, hot.counter))
+            .chain(self.warm)
+    }
+}
+
+impl<L, C, S> MemorySize for Counters<L, C, S>
+where
+    L: MemorySize,
+    C: MemorySize,
+{
+    fn heap_size(&self) -> usize {
+        self.hot.heap_size() + self.tags.heap_size() + self.warm.heap_size()
+    }
+}
+
+impl<L, C> MemorySize for HotCounter<L, C>
+where
+    L: MemorySize,
+    C: MemorySize,
+{
+    fn heap_size(&self) -> usize {
+        self.label.heap_size() + self.counter.heap_size()
+    }
+}
+
+impl<L, C, S> Counters<L, C, S>
+where
+    L: Eq + Hash,
+    S: BuildHasher,
+{
+    #[inline]
+    fn tag(label: &L) -> u8 {
+        // The high byte, as the low bits of ahash's output are the weakest.
+        (TAG_HASHER.hash_one(label) >> 56) as u8
+    }
+
+    #[inline]
+    fn hot_position(&self, label: &L) -> Option<usize> {
+        if self.hot.is_empty() {
+            return None;
+        }
+        let tag = Self::tag(label);
+        self.tags
+            .iter()
+            .enumerate()
+            .filter(|&(_, &t)| t == tag)
+            .map(|(i, _)| i)
+            .find(|&i| self.hot[i].label == *label)
+    }
+
+    fn push_hot(&mut self, hot: HotCounter<L, C>) -> usize {
+        self.tags.push(Self::tag(&hot.label));
+        self.hot.push(hot);
+        self.hot.len() - 1
+    }
+
+    pub(crate) fn contains_key(&self, label: &L) -> bool {
+        self.hot_position(label).is_some() || self.warm.contains_key(label)
+    }
+
+    pub(crate) fn get(&self, label: &L) -> Option<&C> {
+        match self.hot_position(label) {
+            Some(i) => Some(&self.hot[i].counter),
+            None => self.warm.get(label),
+        }
+    }
+
+    /// Looks the counter up for an update, promoting it if it's warm.
+    #[inline]
+    pub(crate) fn get_mut(&mut self, label: &L) -> Option<&mut C> {
+        if let Some(i) = self.hot_position(label) {
+            let hot = &mut self.hot[i];
+            hot.referenced = true;
+            return Some(&mut hot.counter);
+        }
+        if self.hot_capacity == 0 {
+            return self.warm.get_mut(label);
+        }
+        let (label, counter) = self.warm.remove_entry(label)?;
+        let i = self.promote(label, counter);
+        Some(&mut self.hot[i].counter)
+    }
+
+    /// Moves a counter to the hot array, demoting another one if it's full,
+    /// and returns its index in the hot array.
+    fn promote(&mut self, label: L, counter: C) -> usize {
+        let promoted = HotCounter {
+            label,
+            counter,
+            referenced: true,
+        };
+        if self.hot.len() < self.hot_capacity {
+            return self.push_hot(promoted);
+        }
+        while self.hot[self.hand].referenced {
+            self.hot[self.hand].referenced = false;
+            self.hand = (self.hand + 1) % self.hot.len();
+        }
+        let i = self.hand;
+        self.hand = (self.hand + 1) % self.hot.len();
+        self.tags[i] = Self::tag(&promoted.label);
+        let demoted = mem::replace(&mut self.hot[i], promoted);
+        self.warm.insert(demoted.label, demoted.counter);
+        i
+    }
+
+    pub(crate) fn get_or_insert_with(&mut self, label: L, f: impl FnOnce() -> C) -> &mut C {
+        if let Some(i) = self.hot_position(&label) {
+            return &mut self.hot[i].counter;
+        }
+        if self.hot.len() < self.hot_capacity && !self.warm.contains_key(&label) {
+            let i = self.push_hot(HotCounter {
+                label,
+                counter: f(),
+                referenced: false,
+            });
+            return &mut self.hot[i].counter;
+        }
+        self.warm.entry(label).or_insert_with(f)
+    }
+
+    /// Returns the previous counter of `label`, if any.
+    pub(crate) fn insert(&mut self, label: L, counter: C) -> Option<C> {
+        if let Some(i) = self.hot_position(&label) {
+            return Some(mem::replace(&mut self.hot[i].counter, counter));
+        }
+        if self.hot.len() < self.hot_capacity && !self.warm.contains_key(&label) {
+            self.push_hot(HotCounter {
+                label,
+                counter,
+                referenced: false,
+            });
+            return None;
+        }
+        self.warm.insert(label, counter)
+    }
+
+    pub(crate) fn remove(&mut self, label: &L) -> Option<C> {
+        match self.hot_position(label) {
+            Some(i) => {
+                self.tags.swap_remove(i);
+                let removed = self.hot.swap_remove(i);
+                if self.hand >= self.hot.len() {
+                    self.hand = 0;
+                }
+                Some(removed.counter)
+            }
+            None => self.warm.remove(label),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::{collection::vec, prelude::*};
+
+    use super::*;
+
+    const HOT_CAPACITY: usize = 64;
+
+    #[derive(Clone, Debug)]
+    enum Op {
+        Update(u16),
+        Insert(u16, u32),
+        Remove(u16),
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        // Enough labels to overflow the hot array.
+        let label = 0..(3 * HOT_CAPACITY) as u16;
+        prop_oneof![
+            label.clone().prop_map(Op::Update),
+            (label.clone(), any::<u32>()).prop_map(|(l, c)| Op::Insert(l, c)),
+            label.prop_map(Op::Remove),
+        ]
+    }
+
+    #[test]
+    fn it_behaves_like_a_map() {
+        proptest!(|(ops in vec(op(), 0..1000), hot_capacity in prop_oneof![Just(0), Just(HOT_CAPACITY)])| {
+            let mut counters =
+                Counters::<u16, u32, _>::with_capacity(HOT_CAPACITY, hot_capacity, RandomState::new());
+            let mut expected = HashMap::<u16, u32>::new();
+            for op in ops {
+                match op {
+                    Op::Update(label) => {
+                        if let Some(c) = counters.get_mut(&label) {
+                            *c = c.wrapping_add(1);
+                        }
+                        if let Some(c) = expected.get_mut(&label) {
+                            *c = c.wrapping_add(1);
+                        }
+                    }
+                    Op::Insert(label, c) => {
+                        prop_assert_eq!(counters.insert(label, c), expected.insert(label, c));
+                    }
+                    Op::Remove(label) => {
+                        prop_assert_eq!(counters.remove(&label), expected.remove(&label));
+                    }
+                }
+                prop_assert!(counters.hot.len() <= hot_capacity);
+                prop_assert_eq!(counters.tags.len(), counters.hot.len());
+                for (hot, &tag) in counters.hot.iter().zip(&counters.tags) {
+                    prop_assert_eq!(Counters::<u16, u32, RandomState>::tag(&hot.label), tag);
+                }
+                prop_assert_eq!(counters.len(), expected.len());
+            }
+            let actual = counters.iter().map(|(&l, &c)| (l, c)).collect::<HashMap<_, _>>();
+            prop_assert_eq!(actual, expected);
+        });
+    }
+}
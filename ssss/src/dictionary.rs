@@ -0,0 +1,172 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::Hash,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use sketch_traits::{
+    codec::{Decode, DecodeError, Encode},
+    LabelKey, MemorySize,
+};
+
+/// A label interned in a [`LabelDictionary`].
+///
+/// Symbols are as cheap to clone, hash and encode as integers, which is what
+/// makes merging many sketches with the same labels cheap on aggregators. They
+/// only mean something relative to the dictionary that issued them.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct Symbol(u64);
+
+impl LabelKey for Symbol {
+    fn fmt_label(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+impl MemorySize for Symbol {
+    fn heap_size(&self) -> usize {
+        0
+    }
+}
+
+impl Encode for Symbol {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+    }
+}
+
+impl Decode for Symbol {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        u64::decode(input).map(Symbol)
+    }
+}
+
+/// Interns labels into [`Symbol`]s, for sketches labeled by symbol to share.
+///
+/// Meant for aggregation tiers, which merge many sketches whose labels largely
+/// overlap: rather than cloning every label on every merge, each label is
+/// stored once and the sketches count symbols instead. Share the dictionary
+/// behind an `Arc`; it only ever grows.
+#[derive(Debug)]
+pub struct LabelDictionary<L> {
+    inner: RwLock<Interned<L>>,
+}
+
+#[derive(Debug)]
+struct Interned<L> {
+    symbols: HashMap<Arc<L>, Symbol>,
+    labels: Vec<Arc<L>>,
+}
+
+impl<L> Default for LabelDictionary<L> {
+    fn default() -> Self {
+        Self {
+            inner: RwLock::new(Interned {
+                symbols: HashMap::new(),
+                labels: Vec::new(),
+            }),
+        }
+    }
+}
+
+impl<L> LabelDictionary<L>
+where
+    L: Eq + Hash,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the symbol of `label`, interning it first if needed.
+    pub fn intern(&self, label: L) -> Symbol {
+        if let Some(symbol) = self.symbol(&label) {
+            return symbol;
+        }
+        // A panic while holding the lock can't leave the maps inconsistent,
+        // since they're only ever appended to.
+        let mut inner = self.inner.write().unwrap_or_else(PoisonError::into_inner);
+        // Another thread may have interned the label in the meantime.
+        if let Some(&symbol) = inner.symbols.get(&label) {
+            return symbol;
+        }
+        let symbol = Symbol(inner.labels.len() as u64);
+        let label = Arc::new(label);
+        inner.labels.push(label.clone());
+        inner.symbols.insert(label, symbol);
+        symbol
+    }
+
+    /// The symbol of `label`, if it was interned.
+    pub fn symbol(&self, label: &L) -> Option<Symbol> {
+        let inner = self.inner.read().unwrap_or_else(PoisonError::into_inner);
+        inner.symbols.get(label).copied()
+    }
+
+    /// The label of `symbol`, if it was issued by this dictionary.
+    pub fn resolve(&self, symbol: Symbol) -> Option<Arc<L>> {
+        let inner = self.inner.read().unwrap_or_else(PoisonError::into_inner);
+        let index = usize::try_from(symbol.0).ok()?;
+        inner.labels.get(index).cloned()
+    }
+
+    /// Resolves the symbols of a top-k, e.g., from
+    /// [`top`](sketch_traits::HeavyDistinctHitterSketch::top). Symbols that
+    /// weren't issued by this dictionary are skipped.
+    pub fn resolve_top(&self, top: &[(&Symbol, u64)]) -> Vec<(Arc<L>, u64)> {
+        let inner = self.inner.read().unwrap_or_else(PoisonError::into_inner);
+        top.iter()
+            .filter_map(|&(symbol, cardinality)| {
+                let index = usize::try_from(symbol.0).ok()?;
+                Some((inner.labels.get(index)?.clone(), cardinality))
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .labels
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn it_interns_labels() {
+        let dictionary = LabelDictionary::new();
+        let a = dictionary.intern("a".to_string());
+        let b = dictionary.intern("b".to_string());
+        assert_ne!(a, b);
+        assert_eq!(dictionary.intern("a".to_string()), a);
+        assert_eq!(dictionary.symbol(&"b".to_string()), Some(b));
+        assert_eq!(dictionary.symbol(&"c".to_string()), None);
+        assert_eq!(dictionary.resolve(a).as_deref(), Some(&"a".to_string()));
+        assert_eq!(dictionary.resolve(Symbol(2)), None);
+        assert_eq!(dictionary.len(), 2);
+    }
+
+    #[test]
+    fn it_is_shared_between_threads() {
+        let dictionary = Arc::new(LabelDictionary::new());
+        let symbols = (0..4)
+            .map(|_| {
+                let dictionary = dictionary.clone();
+                thread::spawn(move || (0..100).map(|i| dictionary.intern(i)).collect::<Vec<_>>())
+            })
+            .map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>();
+        assert!(symbols.windows(2).all(|w| w[0] == w[1]));
+        assert_eq!(dictionary.len(), 100);
+    }
+}
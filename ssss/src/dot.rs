@@ -0,0 +1,76 @@
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{cmp::Reverse, fmt::Write};
+
+use sketch_traits::{CardinalitySketch, LabelKey, New};
+
+use crate::SamplingSpaceSavingSets;
+
+impl<L, S, A, H> SamplingSpaceSavingSets<L, S, A, H>
+where
+    L: LabelKey,
+    S: CardinalitySketch + New,
+{
+    /// Renders the counters as a Graphviz digraph, from the highest
+    /// cardinality to the lowest, along with the sampling threshold.
+    pub fn to_dot(&self) -> String {
+        let mut counters = self
+            .counters
+            .iter()
+            .map(|(label, counter)| (label, counter.cardinality()))
+            .collect::<Vec<_>>();
+        counters.sort_by_key(|&(_, cardinality)| Reverse(cardinality));
+
+        let mut dot = String::from("digraph ssss {\n    node [shape=box];\n");
+        let _ = writeln!(
+            dot,
+            "    threshold [shape=plaintext, label=\"threshold {}\"];",
+            self.threshold
+        );
+        for (i, (label, cardinality)) in counters.iter().enumerate() {
+            let _ = writeln!(
+                dot,
+                "    c_{} [label=\"{}\\n{}\"];",
+                i,
+                label.display().to_string().escape_debug(),
+                cardinality,
+            );
+        }
+        // Invisible edges keep the counters in order.
+        for i in 1..counters.len() {
+            let _ = writeln!(dot, "    c_{} -> c_{} [style=invis];", i - 1, i);
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sketch_traits::HeavyDistinctHitterSketch;
+
+    use super::*;
+    use crate::{Config, HllSamplingSpaceSavingSets};
+
+    #[test]
+    fn it_renders_the_counters() {
+        let config = Config::new(
+            10,
+            hll::Config::new(64, Some([8, 9, 10, 11, 12, 13, 14, 15])).unwrap(),
+            Some([0, 1, 2, 3]),
+        )
+        .unwrap();
+        let mut sketch = HllSamplingSpaceSavingSets::<String, u64>::new(&config);
+        for i in 0..100 {
+            sketch.insert(String::from("big"), &i);
+        }
+        sketch.insert(String::from("small"), &0);
+        let dot = sketch.to_dot();
+        assert!(dot.starts_with("digraph ssss {"));
+        assert!(dot.contains("c_0 [label=\"big\\n"));
+        assert!(dot.contains("c_1 [label=\"small\\n1\"];"));
+        assert!(dot.contains("c_0 -> c_1 [style=invis];"));
+    }
+}
@@ -21,94 +21,150 @@
 //! the minimum cardinality on every input which improves the speed
 //! considerably.
 
+#![cfg_attr(not(test), deny(clippy::unwrap_used))]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+mod admission;
+mod archive;
 mod cached;
+mod codec;
 mod config;
-use std::{collections::HashMap, error, fmt, fmt::Debug, hash::Hash};
+mod counters;
+#[cfg(feature = "std")]
+mod dictionary;
+#[cfg(feature = "dot")]
+mod dot;
+#[cfg(feature = "rayon")]
+mod par;
+mod rejections;
+#[cfg(feature = "serde")]
+mod serde_impls;
+use alloc::{sync::Arc, vec::Vec};
+use core::{cmp::Reverse, error, fmt, fmt::Debug, hash::Hash};
 
+use ahash::RandomState;
 use hll::HyperLogLog;
-use sketch_traits::{CardinalitySketch, HeavyDistinctHitterSketch, New};
+use sketch_traits::{
+    codec::DecodeError,
+    collections::{hash_map::Entry, HashMap},
+    group_top, truncate_top, CardinalitySketch, Eviction, EvictionLog, Fold,
+    HeavyDistinctHitterSketch, InsertOutcome, Membership, MemorySize, New, Observable, Observer,
+    PrehashedCardinalitySketch, PrehashedHeavyDistinctHitterSketch, SeedableBuildHasher, TopIter,
+    Validate, Versioned,
+};
+#[cfg(feature = "metrics")]
+use sketch_traits::{MetricCounts, SketchMetrics};
 
-use crate::cached::Cached;
-pub use crate::config::{Config, ConfigError};
+#[cfg(feature = "std")]
+pub use crate::dictionary::{LabelDictionary, Symbol};
+pub use crate::{
+    admission::{AdmissionEstimator, AdmissionStrategy},
+    archive::Archive,
+    config::{Config, ConfigBuilder, ConfigError, DEFAULT_MAX_NUM_COUNTERS},
+};
+use crate::{cached::Cached, counters::Counters, rejections::Rejections};
 
 #[derive(Clone, Debug)]
-pub struct SamplingSpaceSavingSets<L, S>
+pub struct SamplingSpaceSavingSets<L, S, A = AdmissionStrategy, H = RandomState>
 where
     S: New,
 {
-    config: Config<S::Config>,
-    counters: HashMap<L, Cached<S>>,
+    config: Config<S::Config, A, H>,
+    counters: Counters<L, Cached<S>, H>,
+    /// The items of all labels, which counters forget as labels are evicted
+    /// or rejected.
+    total: S,
     /// the initial bar for an item to pass before being considered
     threshold: u64,
+    version: u64,
+    rejections: Option<Rejections<L>>,
+    evictions: Option<EvictionLog<L>>,
+    observer: Option<Arc<dyn Observer<L>>>,
+    /// Merges since the counters were last pruned.
+    merges_since_prune: usize,
+    #[cfg(feature = "metrics")]
+    metrics: MetricCounts,
 }
 
 pub type HllSamplingSpaceSavingSets<L, I> = SamplingSpaceSavingSets<L, HyperLogLog<I>>;
 
-impl<L, S> New for SamplingSpaceSavingSets<L, S>
+impl<L, S, A, H> New for SamplingSpaceSavingSets<L, S, A, H>
 where
     S: New,
     S::Config: Clone,
+    A: Clone,
+    H: SeedableBuildHasher,
 {
-    type Config = Config<S::Config>;
+    type Config = Config<S::Config, A, H>;
 
     fn new(config: &Self::Config) -> Self {
         Self {
             config: config.clone(),
-            counters: HashMap::with_capacity(config.max_num_counters),
+            counters: Counters::with_capacity(
+                config.max_num_counters,
+                config.hot_capacity,
+                config.hash_builder.clone(),
+            ),
+            total: S::new(&config.cardinality_sketch_config),
             threshold: 0,
+            version: 0,
+            rejections: None,
+            evictions: None,
+            observer: None,
+            merges_since_prune: 0,
+            #[cfg(feature = "metrics")]
+            metrics: MetricCounts::default(),
         }
     }
 }
 
-impl<L, S> HeavyDistinctHitterSketch for SamplingSpaceSavingSets<L, S>
+impl<L, S, A, H> HeavyDistinctHitterSketch for SamplingSpaceSavingSets<L, S, A, H>
 where
     L: Eq + Hash + Clone,
     S: CardinalitySketch + New,
     S::Item: Hash,
     S::Config: Eq,
+    A: AdmissionEstimator + Eq,
+    H: SeedableBuildHasher,
 {
     type Label = L;
     type Item = S::Item;
     type MergeError = MergeError;
 
     #[inline]
-    fn insert(&mut self, label: Self::Label, item: &Self::Item) {
-        if let Some(counter) = if let Some(counter) = self.counters.get_mut(&label) {
-            // The counter for the label exists; use it.
-            Some(counter)
-        } else if self.counters.len() < self.config.max_num_counters {
-            // We have space; create a new counter.
-            Some(
-                self.counters
-                    .entry(label)
-                    .or_insert(Cached::new(&self.config.cardinality_sketch_config)),
-            )
-        } else {
-            let cardinality_estimate = self.cardinality_estimate(&label, item);
-            if cardinality_estimate > self.threshold {
-                let (min_label, min_cardinality) = self
-                    .counters
-                    .iter()
-                    .map(|(label, counter)| (label, counter.cardinality()))
-                    .min_by_key(|(_, cardinality)| *cardinality)
-                    .unwrap(); // set threshold to min cardinality
-                self.threshold = min_cardinality;
-                if cardinality_estimate > min_cardinality {
-                    // The sampling threshold is reached, remap the existing counter with the minimum cardinality to the label.
-                    // Remove the counter with the minimum cardinality.
-                    let min_counter = self.counters.remove(&min_label.clone()).unwrap();
-                    // Set threshold to the minimum cardinality.
-                    self.threshold = min_counter.cardinality();
-                    // Map the counter to the new label.
-                    Some(self.counters.entry(label).or_insert(min_counter))
-                } else {
-                    None
-                }
-            } else {
-                None
+    fn insert_with_outcome(
+        &mut self,
+        label: Self::Label,
+        item: &Self::Item,
+    ) -> InsertOutcome<Self::Label> {
+        self.insert_key(label, item, |sketch: &mut S| sketch.insert_report(item))
+    }
+
+    /// Inserts the items of consecutive pairs sharing a tracked label with a
+    /// single lookup, refreshing the cached cardinality once per run.
+    fn insert_iter(&mut self, pairs: impl IntoIterator<Item = (Self::Label, Self::Item)>) {
+        let mut pairs = pairs.into_iter().peekable();
+        let mut run = Vec::new();
+        while let Some((label, item)) = pairs.next() {
+            let Some(counter) = self.counters.get_mut(&label) else {
+                self.insert(label, &item);
+                continue;
+            };
+            run.push(item);
+            while let Some((_, item)) = pairs.next_if(|(next, _)| *next == label) {
+                run.push(item);
             }
-        } {
-            counter.insert(item)
+            self.version = self.version.wrapping_add(run.len() as u64);
+            #[cfg(feature = "metrics")]
+            {
+                self.metrics.inserts += run.len() as u64;
+            }
+            counter.insert_all(&run);
+            counter.touch(self.version);
+            self.total.insert_all(&run);
+            run.clear();
         }
     }
 
@@ -116,50 +172,92 @@ where
         if self.config != other.config {
             return Err(MergeError::ConfigMismatch);
         }
+        self.version = self.version.wrapping_add(1);
+        #[cfg(feature = "metrics")]
+        {
+            self.metrics.merges += 1;
+        }
 
+        self.merge_total(&other.total);
         // merge the two sets of counters
         for (l, c) in other.counters.iter() {
-            self.counters
-                .entry(l.clone())
-                .or_insert_with(|| Cached::new(&self.config.cardinality_sketch_config))
-                .merge(c)
-                .unwrap_or_else(
+            if self.observer.is_some() && !self.counters.contains_key(l) {
+                self.log_admission(l);
+            }
+            let counter = self.counters.get_or_insert_with(l.clone(), || {
+                Cached::new(&self.config.cardinality_sketch_config)
+            });
+            counter.merge(c).unwrap_or_else(
+                // By construction, parameters cannot mismatch.
+                |_| unreachable!(),
+            );
+            counter.touch(self.version);
+        }
+        if let (Some(rejections), Some(other_rejections)) =
+            (&mut self.rejections, &other.rejections)
+        {
+            rejections.merge(other_rejections);
+        }
+
+        self.prune_after_merge();
+        Ok(())
+    }
+
+    fn merge_owned(&mut self, other: Self) -> Result<(), Self::MergeError> {
+        if self.config != other.config {
+            return Err(MergeError::ConfigMismatch);
+        }
+        self.version = self.version.wrapping_add(1);
+        #[cfg(feature = "metrics")]
+        {
+            self.metrics.merges += 1;
+        }
+
+        self.merge_total(&other.total);
+        // Move the counters of the other sketch over, merging the ones of
+        // labels tracked by both into them.
+        for (l, mut c) in other.counters.into_entries() {
+            if let Some(counter) = self.counters.remove(&l) {
+                c.merge(&counter).unwrap_or_else(
                     // By construction, parameters cannot mismatch.
                     |_| unreachable!(),
                 );
+            } else {
+                self.log_admission(&l);
+            }
+            c.touch(self.version);
+            self.counters.insert(l, c);
+        }
+        if let (Some(rejections), Some(other_rejections)) =
+            (&mut self.rejections, &other.rejections)
+        {
+            rejections.merge(other_rejections);
         }
 
-        // only keep the top self.size counters
-        let mut entries = self
-            .counters
-            .iter()
-            .map(|(label, counter)| (label, counter.cardinality()))
-            .collect::<Vec<_>>();
-        entries.sort_by_key(|&(_, cardinality)| cardinality);
-        entries
-            .into_iter()
-            .rev()
-            .skip(self.config.max_num_counters)
-            .map(|(label, _)| label)
-            .cloned()
-            .collect::<Vec<_>>()
-            .into_iter()
-            .for_each(|label| {
-                self.counters.remove(&label);
-            });
-
-        self.threshold = self
-            .counters
-            .values()
-            .map(|counter| counter.cardinality())
-            .min()
-            .unwrap_or(0);
-
+        self.prune_after_merge();
         Ok(())
     }
 
+    /// Rejections and evictions keep being tracked, from scratch.
     fn clear(&mut self) {
-        todo!()
+        self.version = self.version.wrapping_add(1);
+        self.counters.clear();
+        self.total.clear();
+        self.threshold = 0;
+        self.merges_since_prune = 0;
+        if let Some(rejections) = &mut self.rejections {
+            rejections.clear();
+        }
+        if let Some(evictions) = &mut self.evictions {
+            evictions.clear();
+        }
+    }
+
+    /// The threshold is left as is: it's only raised again once the sketch
+    /// is full.
+    fn remove(&mut self, label: &L) -> bool {
+        self.version = self.version.wrapping_add(1);
+        self.counters.remove(label).is_some()
     }
 
     fn cardinality(&self, label: &L) -> u64 {
@@ -175,6 +273,20 @@ where
             })
     }
 
+    /// Estimated from a sketch of its own, so that evicted and rejected
+    /// labels still count.
+    fn total_cardinality(&self) -> Option<u64> {
+        Some(self.total.cardinality())
+    }
+
+    fn labels(&self) -> impl Iterator<Item = &L> {
+        self.counters.iter().map(|(label, _)| label)
+    }
+
+    fn num_labels(&self) -> usize {
+        self.counters.len()
+    }
+
     fn top(&self, k: usize) -> Vec<(&L, u64)> {
         let mut entries = self
             .counters
@@ -184,52 +296,654 @@ where
         entries.sort_by_key(|&(_, cardinality)| cardinality);
         entries.into_iter().rev().take(k).collect::<Vec<_>>()
     }
+
+    fn top_iter(&self) -> TopIter<&L> {
+        self.counters
+            .iter()
+            .map(|(label, counter)| (label, counter.cardinality()))
+            .collect()
+    }
+
+    fn top_into(&self, k: usize, out: &mut Vec<(L, u64)>) {
+        out.clear();
+        out.extend(
+            self.counters
+                .iter()
+                .map(|(label, counter)| (label.clone(), counter.cardinality())),
+        );
+        truncate_top(out, k);
+    }
+
+    fn labels_above(&self, threshold: u64) -> Vec<(&L, u64)> {
+        let mut entries = self
+            .counters
+            .iter()
+            .map(|(label, counter)| (label, counter.cardinality()))
+            .filter(|&(_, cardinality)| cardinality > threshold)
+            .collect::<Vec<_>>();
+        entries.sort_unstable_by_key(|&(_, cardinality)| Reverse(cardinality));
+        entries
+    }
+
+    fn top_grouped<G, F>(&self, k: usize, classify: F) -> HashMap<G, Vec<(&L, u64)>>
+    where
+        G: Eq + Hash,
+        F: Fn(&L) -> G,
+    {
+        group_top(
+            self.counters
+                .iter()
+                .map(|(label, counter)| (label, counter.cardinality())),
+            k,
+            |label| classify(label),
+        )
+    }
+
+    fn cardinality_many(&self, labels: &[L], out: &mut Vec<u64>) {
+        out.clear();
+        // The fallback for untracked labels is only computed once.
+        let mut min_cardinality = None;
+        out.extend(labels.iter().map(|label| match self.counters.get(label) {
+            Some(counter) => counter.cardinality(),
+            None => *min_cardinality.get_or_insert_with(|| {
+                self.counters
+                    .values()
+                    .map(|c| c.cardinality())
+                    .min()
+                    .unwrap_or(0)
+            }),
+        }));
+    }
 }
 
-impl<L, S> SamplingSpaceSavingSets<L, S>
+/// Item hashes are admitted and counted as `u64` items would be.
+impl<L, S, A, H> PrehashedHeavyDistinctHitterSketch for SamplingSpaceSavingSets<L, S, A, H>
 where
-    L: Hash,
-    S: CardinalitySketch + New,
+    L: Eq + Hash + Clone,
+    S: PrehashedCardinalitySketch + New,
     S::Item: Hash,
+    S::Config: Eq,
+    A: AdmissionEstimator + Eq,
+    H: SeedableBuildHasher,
+{
+    #[inline]
+    fn insert_hashed_with_outcome(&mut self, label: L, item_hash: u64) -> InsertOutcome<L> {
+        self.insert_key(label, &item_hash, |sketch: &mut S| {
+            sketch.insert_hashed_report(item_hash)
+        })
+    }
+}
+
+impl<L, S, A, H> SamplingSpaceSavingSets<L, S, A, H>
+where
+    L: Eq + Hash + Clone,
+    S: CardinalitySketch + New,
+    A: AdmissionEstimator,
+    H: SeedableBuildHasher,
+{
+    /// Inserts an item, identified by `key` for admission, with `insert`,
+    /// which reports whether a sketch may have changed: `key` is either the
+    /// item or its hash.
+    #[inline]
+    fn insert_key(
+        &mut self,
+        label: L,
+        key: &impl Hash,
+        insert: impl Fn(&mut S) -> bool,
+    ) -> InsertOutcome<L> {
+        self.version = self.version.wrapping_add(1);
+        #[cfg(feature = "metrics")]
+        {
+            self.metrics.inserts += 1;
+        }
+        insert(&mut self.total);
+        if let Some(counter) = self.counters.get_mut(&label) {
+            // The counter for the label exists; use it.
+            counter.update(&insert);
+            counter.touch(self.version);
+            return InsertOutcome::UpdatedExisting;
+        }
+        if self.counters.len() < self.config.max_num_counters {
+            // We have space; create a new counter.
+            self.log_admission(&label);
+            let counter = self.counters.get_or_insert_with(label, || {
+                Cached::new(&self.config.cardinality_sketch_config)
+            });
+            counter.update(&insert);
+            counter.touch(self.version);
+            return InsertOutcome::CreatedNew;
+        }
+        let cardinality_estimate = self.cardinality_estimate(&label, key);
+        if cardinality_estimate <= self.threshold {
+            return self.reject(label);
+        }
+        // Ties are broken by label hash, as when pruning, rather than by the
+        // order of the counters, which differs between sketches.
+        let hash_builder = &self.config.hash_builder;
+        let Some((min_label, min_cardinality)) = self
+            .counters
+            .iter()
+            .map(|(label, counter)| (label, counter.cardinality()))
+            .min_by(|&(a, a_cardinality), &(b, b_cardinality)| {
+                a_cardinality
+                    .cmp(&b_cardinality)
+                    .then_with(|| hash_builder.hash_one(a).cmp(&hash_builder.hash_one(b)))
+            })
+            .map(|(label, cardinality)| (label.clone(), cardinality))
+        else {
+            return InsertOutcome::RejectedByThreshold;
+        };
+        // Set threshold to the minimum cardinality.
+        self.threshold = min_cardinality;
+        if cardinality_estimate <= min_cardinality {
+            return self.reject(label);
+        }
+        // The sampling threshold is reached, remap the existing counter with the minimum cardinality to the label.
+        // Remove the counter with the minimum cardinality, then map it to the new label.
+        match self.counters.remove(&min_label) {
+            Some(mut min_counter) => {
+                self.log_eviction(&min_label, min_cardinality);
+                self.log_admission(&label);
+                min_counter.update(&insert);
+                min_counter.touch(self.version);
+                self.counters.insert(label, min_counter);
+                InsertOutcome::Evicted { label: min_label }
+            }
+            None => InsertOutcome::RejectedByThreshold,
+        }
+    }
+}
+
+impl<L, S, A, H> SamplingSpaceSavingSets<L, S, A, H>
+where
+    L: Eq + Hash + Clone,
+    S: CardinalitySketch + New,
+    H: SeedableBuildHasher,
+{
+    fn reject(&mut self, label: L) -> InsertOutcome<L> {
+        if let Some(rejections) = &mut self.rejections {
+            let hash = self.config.hash_builder.hash_one(&label);
+            rejections.record(label, hash);
+        }
+        InsertOutcome::RejectedByThreshold
+    }
+
+    fn merge_total(&mut self, other: &S) {
+        self.total.merge(other).unwrap_or_else(
+            // By construction, parameters cannot mismatch.
+            |_| unreachable!(),
+        );
+    }
+
+    fn log_admission(&self, label: &L) {
+        if let Some(observer) = &self.observer {
+            observer.on_admit(label);
+        }
+    }
+
+    fn log_eviction(&mut self, label: &L, cardinality: u64) {
+        #[cfg(feature = "metrics")]
+        {
+            self.metrics.evictions += 1;
+        }
+        if let Some(observer) = &self.observer {
+            observer.on_evict(label, cardinality);
+        }
+        if let Some(evictions) = &mut self.evictions {
+            evictions.record(label.clone(), cardinality);
+        }
+    }
+
+    /// Starts counting how often untracked labels get rejected at the sampling
+    /// threshold, in a Count-Min sketch of `depth` rows of `width` counters.
+    ///
+    /// Labels that keep being rejected hint that the sketch is too small; see
+    /// [`Self::rejection_report`]. Counts are kept through merges with sketches
+    /// tracking rejections with the same dimensions, but aren't encoded.
+    pub fn track_rejections(&mut self, width: usize, depth: usize) {
+        self.rejections = Some(Rejections::new(width, depth, self.config.max_num_counters));
+    }
+
+    /// The `k` labels most often rejected at the sampling threshold, with
+    /// their (over)estimated numbers of rejections, in decreasing order.
+    /// Empty unless rejections are tracked.
+    pub fn rejection_report(&self, k: usize) -> Vec<(&L, u64)> {
+        self.rejections
+            .as_ref()
+            .map(|rejections| rejections.report(k))
+            .unwrap_or_default()
+    }
+
+    /// Starts logging the last `capacity` labels evicted, by inserts or
+    /// prunes, with their estimates at the time, e.g., to explain why a label
+    /// disappeared from reports. Logs aren't merged, and aren't encoded.
+    pub fn track_evictions(&mut self, capacity: usize) {
+        self.evictions = Some(EvictionLog::new(capacity));
+    }
+
+    /// The logged evictions, most recent first. Empty unless evictions are
+    /// tracked.
+    pub fn recent_evictions(&self) -> impl Iterator<Item = &Eviction<L>> {
+        self.evictions.iter().flat_map(EvictionLog::recent)
+    }
+
+    /// Only keeps the top `max_num_counters` counters and resets the threshold
+    /// to the minimum remaining cardinality.
+    ///
+    /// Merges prune on their own, unless the config lets them overcommit
+    /// (see [`Config::with_overcommit`]), in which case this prunes the
+    /// counters kept since, e.g., at the end of a burst of merges.
+    ///
+    /// Ties are broken by label hash so that the counters that are kept don't
+    /// depend on the merge order.
+    pub fn prune(&mut self) {
+        self.version = self.version.wrapping_add(1);
+        self.merges_since_prune = 0;
+        if self.counters.len() > self.config.max_num_counters {
+            let hash_builder = &self.config.hash_builder;
+            let mut entries = self
+                .counters
+                .iter()
+                .map(|(label, counter)| (label, counter.cardinality()))
+                .collect::<Vec<_>>();
+            entries.sort_by_cached_key(|&(label, cardinality)| {
+                (cardinality, hash_builder.hash_one(label))
+            });
+            entries
+                .into_iter()
+                .rev()
+                .skip(self.config.max_num_counters)
+                .map(|(label, _)| label)
+                .cloned()
+                .collect::<Vec<_>>()
+                .into_iter()
+                .for_each(|label| {
+                    if let Some(counter) = self.counters.remove(&label) {
+                        self.log_eviction(&label, counter.cardinality());
+                    }
+                });
+        }
+        self.reset_threshold();
+    }
+
+    /// Prunes if the config says it's due, and otherwise only resets the
+    /// threshold, which merged counters may have fallen below.
+    fn prune_after_merge(&mut self) {
+        self.merges_since_prune = self.merges_since_prune.saturating_add(1);
+        let merges_per_prune = self.config.merges_per_prune;
+        if (merges_per_prune > 0 && self.merges_since_prune >= merges_per_prune)
+            || self.counters.len() > self.config.overcommitted_capacity()
+        {
+            self.prune();
+        } else {
+            self.reset_threshold();
+        }
+    }
+
+    fn reset_threshold(&mut self) {
+        self.threshold = self
+            .counters
+            .values()
+            .map(|counter| counter.cardinality())
+            .min()
+            .unwrap_or(0);
+    }
+}
+
+impl<L, S, A, H> SamplingSpaceSavingSets<L, S, A, H>
+where
+    S: CardinalitySketch + New + Clone,
+    H: SeedableBuildHasher,
+{
+    /// Rolls labels up to a coarser projection, e.g., `(customer, endpoint)`
+    /// to `customer`, and returns the `k` projected labels with the highest
+    /// cardinalities.
+    ///
+    /// Counters that project to the same label are merged, so items shared
+    /// between them are only counted once. Only tracked labels contribute.
+    pub fn top_by_projection<P, F>(&self, k: usize, f: F) -> Vec<(P, u64)>
+    where
+        P: Eq + Hash,
+        F: Fn(&L) -> P,
+    {
+        let mut rolled_up: HashMap<P, Cached<S>> = HashMap::new();
+        for (label, counter) in self.counters.iter() {
+            match rolled_up.entry(f(label)) {
+                Entry::Occupied(mut entry) => entry.get_mut().merge(counter).unwrap_or_else(
+                    // By construction, parameters cannot mismatch.
+                    |_| unreachable!(),
+                ),
+                Entry::Vacant(entry) => {
+                    entry.insert(counter.clone());
+                }
+            }
+        }
+        let mut entries = rolled_up
+            .into_iter()
+            .map(|(projection, counter)| (projection, counter.cardinality()))
+            .collect::<Vec<_>>();
+        truncate_top(&mut entries, k);
+        entries
+    }
+
+    /// The number of distinct items across `labels`, e.g., all the endpoints
+    /// of one customer, estimated by merging their counters.
+    ///
+    /// Items shared between labels are only counted once. Labels that aren't
+    /// tracked don't contribute.
+    pub fn cardinality_union(&self, labels: &[L]) -> u64
+    where
+        L: Eq + Hash,
+    {
+        let mut union: Cached<S> = Cached::new(&self.config.cardinality_sketch_config);
+        for counter in labels.iter().filter_map(|label| self.counters.get(label)) {
+            union.merge(counter).unwrap_or_else(
+                // By construction, parameters cannot mismatch.
+                |_| unreachable!(),
+            );
+        }
+        union.cardinality()
+    }
+
+    /// Relabels the counters with their symbols in `dictionary`, interning
+    /// the labels that aren't yet, so that the sketch can then be merged with
+    /// other sketches sharing the dictionary without cloning labels.
+    ///
+    /// Ties in merges are broken by symbol rather than label hash. Rejections
+    /// aren't carried over.
+    #[cfg(feature = "std")]
+    pub fn intern_labels(
+        &self,
+        dictionary: &LabelDictionary<L>,
+    ) -> SamplingSpaceSavingSets<Symbol, S, A, H>
+    where
+        L: Eq + Hash + Clone,
+        S::Config: Clone,
+        A: Clone,
+    {
+        let mut counters = Counters::with_capacity(
+            self.config.max_num_counters,
+            self.config.hot_capacity,
+            self.config.hash_builder.clone(),
+        );
+        for (label, counter) in self.counters.iter() {
+            counters.insert(dictionary.intern(label.clone()), counter.clone());
+        }
+        SamplingSpaceSavingSets {
+            config: self.config.clone(),
+            counters,
+            total: self.total.clone(),
+            threshold: self.threshold,
+            version: self.version,
+            rejections: None,
+            evictions: None,
+            observer: None,
+            merges_since_prune: self.merges_since_prune,
+            #[cfg(feature = "metrics")]
+            metrics: self.metrics,
+        }
+    }
+}
+
+/// Keeps the `max_num_counters` counters of `config` with the highest
+/// cardinalities, ties broken by label hash as when pruning, and folds each of
+/// them to its cardinality sketch config.
+///
+/// Fails if `config` keeps more counters, or hashes labels or admits them
+/// differently, as the counters kept wouldn't be those the sketch would have
+/// kept with it.
+///
+/// The folded sketch starts from version zero, and neither rejections nor
+/// evictions are carried over.
+impl<L, S, A, H> Fold for SamplingSpaceSavingSets<L, S, A, H>
+where
+    L: Eq + Hash + Clone,
+    S: CardinalitySketch + Fold,
+    S::Config: Clone,
+    A: Clone + PartialEq,
+    H: SeedableBuildHasher,
+{
+    type FoldError = MergeError;
+
+    fn fold_to(&self, config: &Self::Config) -> Result<Self, Self::FoldError> {
+        if config.max_num_counters > self.config.max_num_counters
+            || config.seeds != self.config.seeds
+            || config.admission != self.config.admission
+        {
+            return Err(MergeError::ConfigMismatch);
+        }
+        let hash_builder = &self.config.hash_builder;
+        let mut entries = self.counters.iter().collect::<Vec<_>>();
+        entries.sort_by_cached_key(|&(label, counter)| {
+            Reverse((counter.cardinality(), hash_builder.hash_one(label)))
+        });
+        let mut folded = Self::new(config);
+        folded.total = self
+            .total
+            .fold_to(&config.cardinality_sketch_config)
+            .map_err(|_| MergeError::ConfigMismatch)?;
+        for (label, counter) in entries.into_iter().take(config.max_num_counters) {
+            let sketch = counter
+                .sketch()
+                .fold_to(&config.cardinality_sketch_config)
+                .map_err(|_| MergeError::ConfigMismatch)?;
+            folded
+                .counters
+                .insert(label.clone(), Cached::from_sketch(sketch));
+        }
+        folded.reset_threshold();
+        Ok(folded)
+    }
+}
+
+impl<L, S, A, H> SamplingSpaceSavingSets<L, S, A, H>
+where
+    L: Eq + Hash,
+    S: Membership + New,
+    H: SeedableBuildHasher,
+{
+    /// Whether `item` was likely inserted under `label`, e.g., with
+    /// [`hll::BloomHyperLogLog`] counters, or `None` if the label isn't
+    /// tracked.
+    ///
+    /// Counters taken over from evicted labels keep their items, so those
+    /// are likely reported as well.
+    pub fn may_contain(&self, label: &L, item: &S::Item) -> Option<bool> {
+        self.counters
+            .get(label)
+            .map(|counter| counter.sketch().may_contain(item))
+    }
+}
+
+impl<L, S, A, H> SamplingSpaceSavingSets<L, S, A, H>
+where
+    L: Hash,
+    S: New,
+    A: AdmissionEstimator,
+    H: SeedableBuildHasher,
 {
     #[inline]
-    fn cardinality_estimate(&self, _label: &L, item: &S::Item) -> u64 {
-        (u64::MAX as f64 / self.config.hash_builder.hash_one(item) as f64) as u64
+    fn cardinality_estimate(&self, label: &L, item: &impl Hash) -> u64 {
+        self.config
+            .admission
+            .estimate(&self.config.hash_builder, label, item)
     }
 }
 
-impl<L, S> SamplingSpaceSavingSets<L, S>
+impl<L, S, A, H> SamplingSpaceSavingSets<L, S, A, H>
 where
     S: New,
+    H: SeedableBuildHasher,
 {
-    pub fn config(&self) -> &Config<S::Config> {
+    pub fn config(&self) -> &Config<S::Config, A, H> {
         &self.config
     }
+}
 
-    pub fn num_counters(&self) -> usize {
-        self.counters.len()
+impl<L, S, A, H> Extend<(L, S::Item)> for SamplingSpaceSavingSets<L, S, A, H>
+where
+    L: Eq + Hash + Clone,
+    S: CardinalitySketch + New,
+    S::Item: Hash,
+    S::Config: Eq,
+    A: AdmissionEstimator + Eq,
+    H: SeedableBuildHasher,
+{
+    fn extend<T: IntoIterator<Item = (L, S::Item)>>(&mut self, pairs: T) {
+        self.insert_iter(pairs);
+    }
+}
+
+impl<L, S, A, H> MemorySize for SamplingSpaceSavingSets<L, S, A, H>
+where
+    L: MemorySize,
+    S: New + MemorySize,
+    S::Config: MemorySize,
+{
+    fn heap_size(&self) -> usize {
+        self.config.heap_size()
+            + self.counters.heap_size()
+            + self.total.heap_size()
+            + self.rejections.heap_size()
+            + self.evictions.heap_size()
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl<L, S, A, H> SketchMetrics for SamplingSpaceSavingSets<L, S, A, H>
+where
+    L: MemorySize,
+    S: New + MemorySize,
+    S::Config: MemorySize,
+{
+    fn num_inserts(&self) -> u64 {
+        self.metrics.inserts
+    }
+
+    fn num_evictions(&self) -> u64 {
+        self.metrics.evictions
+    }
+
+    fn num_merges(&self) -> u64 {
+        self.metrics.merges
+    }
+}
+
+impl<L, S, A, H> Versioned for SamplingSpaceSavingSets<L, S, A, H>
+where
+    S: New,
+{
+    fn version(&self) -> u64 {
+        self.version
+    }
+}
+
+/// Evictions are reported before the eviction log records them, and labels
+/// admitted by a merge may be evicted right away by the prune that follows.
+impl<L, S, A, H> Observable for SamplingSpaceSavingSets<L, S, A, H>
+where
+    L: Eq + Hash + Clone,
+    S: CardinalitySketch + New,
+    S::Item: Hash,
+    S::Config: Eq,
+    A: AdmissionEstimator + Eq,
+    H: SeedableBuildHasher,
+{
+    fn set_observer(&mut self, observer: Arc<dyn Observer<L>>) {
+        self.observer = Some(observer);
+    }
+}
+
+impl<L, S, A, H> Validate for SamplingSpaceSavingSets<L, S, A, H>
+where
+    S: CardinalitySketch + New + Validate,
+{
+    type Violation = InvariantViolation<S::Violation>;
+
+    fn debug_validate(&self) -> Result<(), Self::Violation> {
+        if self.counters.len() > self.config.overcommitted_capacity() {
+            return Err(InvariantViolation::TooManyCounters);
+        }
+        for counter in self.counters.values() {
+            if !counter.is_consistent() {
+                return Err(InvariantViolation::StaleCachedCardinality);
+            }
+            counter
+                .sketch()
+                .debug_validate()
+                .map_err(InvariantViolation::Counter)?;
+        }
+        let min_cardinality = self.counters.values().map(|c| c.cardinality()).min();
+        if min_cardinality.is_some_and(|min| self.threshold > min) {
+            return Err(InvariantViolation::ThresholdAboveMinimum);
+        }
+        Ok(())
     }
 }
 
+#[derive(Clone, Debug)]
+pub enum InvariantViolation<E> {
+    TooManyCounters,
+    StaleCachedCardinality,
+    ThresholdAboveMinimum,
+    Counter(E),
+}
+
+impl<E> fmt::Display for InvariantViolation<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvariantViolation::TooManyCounters => {
+                write!(f, "there are more counters than the configured maximum")
+            }
+            InvariantViolation::StaleCachedCardinality => {
+                write!(f, "a cached cardinality is stale")
+            }
+            InvariantViolation::ThresholdAboveMinimum => {
+                write!(f, "the threshold exceeds the minimum counter cardinality")
+            }
+            InvariantViolation::Counter(e) => write!(f, "invalid counter: {}", e),
+        }
+    }
+}
+
+impl<E> error::Error for InvariantViolation<E> where E: fmt::Debug + fmt::Display {}
+
 #[derive(Clone, Debug)]
 pub enum MergeError {
     ConfigMismatch,
+    Decode(DecodeError),
 }
 
 impl fmt::Display for MergeError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             MergeError::ConfigMismatch => write!(f, "sketch configs do not match"),
+            MergeError::Decode(e) => write!(f, "failed to decode sketch: {}", e),
         }
     }
 }
 
+impl From<DecodeError> for MergeError {
+    fn from(e: DecodeError) -> Self {
+        MergeError::Decode(e)
+    }
+}
+
 impl error::Error for MergeError {}
 
 #[cfg(test)]
 mod tests {
     use std::collections::{HashMap, HashSet};
 
+    use proptest::{collection::vec, prelude::*};
+    use sketch_traits::{
+        codec::{Encode, MergeEncoded},
+        Memoized,
+    };
+
     use super::*;
 
     const SIZE: usize = 10;
@@ -265,7 +979,7 @@ mod tests {
                 exact.entry(label).or_default().insert(i);
             }
         }
-        assert!(sketch.num_counters() == sketch.config().max_num_counters());
+        assert!(sketch.num_labels() == sketch.config().max_num_counters());
         let label = 'a';
         assert!(
             relative_error(
@@ -330,8 +1044,9 @@ mod tests {
     #[test]
     fn should_merge_iff_same_config() {
         let hll_config = hll::Config::new(COUNTER_SIZE, Some(HLL_SEEDS)).unwrap();
-        let config1 = Config::new(SIZE, hll_config.clone(), None).unwrap();
-        let config2 = Config::new(SIZE, hll_config, None).unwrap();
+        let config1 = Config::new(SIZE, hll_config.clone(), Some(SEEDS)).unwrap();
+        // Explicit seeds, as configs built without std all get the same ones.
+        let config2 = Config::new(SIZE, hll_config, Some([4, 5, 6, 7])).unwrap();
 
         assert!(HllSamplingSpaceSavingSets::<usize, usize>::new(&config1)
             .merge(&HllSamplingSpaceSavingSets::<usize, usize>::new(&config1))
@@ -340,4 +1055,790 @@ mod tests {
             .merge(&HllSamplingSpaceSavingSets::<usize, usize>::new(&config2))
             .is_err());
     }
+
+    #[test]
+    fn hot_counters_track_like_the_map() {
+        let mut sketch: HllSamplingSpaceSavingSets<u64, u64> =
+            SamplingSpaceSavingSets::new(&config());
+        let mut hot: HllSamplingSpaceSavingSets<u64, u64> =
+            SamplingSpaceSavingSets::new(&config().with_hot_capacity(4));
+        for i in 0..20_000_u64 {
+            // Skewed, so that labels churn through the hot counters.
+            let label = i % 97 % (1 + i % 13);
+            sketch.insert(label, &i);
+            hot.insert(label, &i);
+        }
+        // Counters are iterated in another order, which ties between the
+        // counters to take over may be broken by, so only the labels are the
+        // same.
+        for ((hot_label, hot_count), (label, count)) in
+            hot.top(SIZE).into_iter().zip(sketch.top(SIZE))
+        {
+            assert_eq!(hot_label, label);
+            assert!(relative_error(hot_count, count) < 0.05);
+        }
+        assert!(hot.debug_validate().is_ok());
+    }
+
+    #[test]
+    fn folded_sketches_merge_with_smaller_ones() {
+        let small_config = Config::new(
+            SIZE / 2,
+            hll::Config::new(COUNTER_SIZE / 4, Some(HLL_SEEDS)).unwrap(),
+            Some(SEEDS),
+        )
+        .unwrap();
+        let mut large: HllSamplingSpaceSavingSets<u64, u64> =
+            SamplingSpaceSavingSets::new(&config());
+        let mut small = SamplingSpaceSavingSets::new(&small_config);
+        for label in 0..SIZE as u64 {
+            for i in 0..(label + 1) * 100 {
+                large.insert(label, &i);
+                small.insert(label + SIZE as u64, &i);
+            }
+        }
+
+        let mut folded = large.fold_to(&small_config).unwrap();
+        assert!(folded.debug_validate().is_ok());
+        let mut labels = folded.labels().copied().collect::<Vec<_>>();
+        labels.sort_unstable();
+        assert_eq!(labels, [5, 6, 7, 8, 9]);
+        for &label in &labels {
+            assert!(relative_error(folded.cardinality(&label), (label + 1) * 100) < 0.2);
+        }
+        assert!(folded.merge(&small).is_ok());
+        assert!(small.fold_to(&config()).is_err());
+    }
+
+    #[test]
+    fn it_only_folds_to_as_many_counters_or_fewer() {
+        let hll_config = hll::Config::new(COUNTER_SIZE / 4, Some(HLL_SEEDS)).unwrap();
+        let sketch: HllSamplingSpaceSavingSets<u64, u64> = SamplingSpaceSavingSets::new(&config());
+        let larger = Config::new(2 * SIZE, hll_config.clone(), Some(SEEDS)).unwrap();
+        assert!(matches!(
+            sketch.fold_to(&larger),
+            Err(MergeError::ConfigMismatch)
+        ));
+        let same = Config::new(SIZE, hll_config, Some(SEEDS)).unwrap();
+        assert!(sketch.fold_to(&same).is_ok());
+    }
+
+    #[test]
+    fn it_only_folds_to_the_same_label_hashing_and_admission() {
+        let hll_config = hll::Config::new(COUNTER_SIZE / 4, Some(HLL_SEEDS)).unwrap();
+        let sketch: HllSamplingSpaceSavingSets<u64, u64> = SamplingSpaceSavingSets::new(&config());
+        let other_seeds = Config::new(SIZE, hll_config.clone(), Some([4, 5, 6, 7])).unwrap();
+        assert!(matches!(
+            sketch.fold_to(&other_seeds),
+            Err(MergeError::ConfigMismatch)
+        ));
+        let other_admission = Config::new(SIZE, hll_config, Some(SEEDS))
+            .unwrap()
+            .with_admission(AdmissionStrategy::TrailingZeros);
+        assert!(matches!(
+            sketch.fold_to(&other_admission),
+            Err(MergeError::ConfigMismatch)
+        ));
+    }
+
+    #[test]
+    fn buffered_queries_match_allocating_ones() {
+        let mut sketch: HllSamplingSpaceSavingSets<char, u64> =
+            SamplingSpaceSavingSets::new(&config());
+        for (n, label) in ('a'..='o').enumerate() {
+            for i in 0..(n as u64 + 1) * 20 {
+                sketch.insert(label, &i);
+            }
+        }
+
+        let mut out = vec![(' ', 0)];
+        sketch.top_into(5, &mut out);
+        let top = sketch.top(5);
+        assert_eq!(out.len(), 5);
+        assert_eq!(
+            out.iter().map(|&(_, c)| c).collect::<Vec<_>>(),
+            top.iter().map(|&(_, c)| c).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            sketch
+                .top_iter()
+                .take(5)
+                .map(|(_, c)| c)
+                .collect::<Vec<_>>(),
+            top.iter().map(|&(_, c)| c).collect::<Vec<_>>()
+        );
+        assert_eq!(sketch.top_iter().len(), sketch.num_labels());
+
+        let labels = ('a'..='z').collect::<Vec<_>>();
+        let mut cardinalities = vec![];
+        sketch.cardinality_many(&labels, &mut cardinalities);
+        assert_eq!(
+            cardinalities,
+            labels
+                .iter()
+                .map(|l| sketch.cardinality(l))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    /// A sketch tracking each label `l` in `labels` with `10 * l` items.
+    fn shard(
+        config: &Config<hll::Config>,
+        labels: std::ops::Range<u64>,
+    ) -> HllSamplingSpaceSavingSets<u64, u64> {
+        let mut sketch = SamplingSpaceSavingSets::new(config);
+        for label in labels {
+            (0..10 * label).for_each(|i| sketch.insert(label, &i));
+        }
+        sketch
+    }
+
+    #[test]
+    fn overcommitted_merges_prune_once() {
+        assert!(config().with_overcommit(0, 0).is_err());
+        assert!(config().with_overcommit(usize::MAX, 0).is_err());
+
+        let overcommitted = config().with_overcommit(3, 0).unwrap();
+        let mut sketch = shard(&overcommitted, 1..11);
+        sketch.merge(&shard(&overcommitted, 11..21)).unwrap();
+        sketch.merge_owned(shard(&overcommitted, 21..31)).unwrap();
+        assert_eq!(sketch.num_labels(), 3 * SIZE);
+        assert!(sketch.debug_validate().is_ok());
+
+        // Merges past the overcommitted capacity prune.
+        let mut full = sketch.clone();
+        full.merge(&shard(&overcommitted, 31..41)).unwrap();
+        assert_eq!(full.num_labels(), SIZE);
+
+        let mut pruned = shard(&config(), 1..11);
+        pruned.merge(&shard(&config(), 11..21)).unwrap();
+        pruned.merge(&shard(&config(), 21..31)).unwrap();
+        sketch.prune();
+        assert_eq!(sketch.num_labels(), SIZE);
+        assert!(sketch.debug_validate().is_ok());
+        assert_eq!(sketch.top(SIZE), pruned.top(SIZE));
+
+        let every_other = config().with_overcommit(3, 2).unwrap();
+        let mut sketch = shard(&every_other, 1..11);
+        sketch.merge(&shard(&every_other, 11..21)).unwrap();
+        assert_eq!(sketch.num_labels(), 2 * SIZE);
+        sketch.merge(&shard(&every_other, 21..31)).unwrap();
+        assert_eq!(sketch.num_labels(), SIZE);
+    }
+
+    #[test]
+    fn top_by_projection_merges_counters() {
+        let mut sketch: HllSamplingSpaceSavingSets<(char, u32), u64> =
+            SamplingSpaceSavingSets::new(&config());
+        // 'a' has three endpoints hit by overlapping items, 'b' has a single
+        // larger one.
+        for endpoint in 0..3 {
+            for i in endpoint * 50..endpoint * 50 + 100 {
+                sketch.insert(('a', endpoint as u32), &i);
+            }
+        }
+        for i in 0..150 {
+            sketch.insert(('b', 0), &i);
+        }
+
+        let top = sketch.top_by_projection(2, |&(customer, _)| customer);
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].0, 'a');
+        assert!(relative_error(top[0].1, 200) < 0.1);
+        assert_eq!(top[1].0, 'b');
+        assert!(relative_error(top[1].1, 150) < 0.1);
+    }
+
+    #[test]
+    fn top_grouped_matches_top_per_group() {
+        let mut sketch = HllSamplingSpaceSavingSets::<u32, u64>::new(&config());
+        for i in 0..10_000_u64 {
+            let label = (i / 7 % 300) as u32;
+            sketch.insert(label, &(i % (u64::from(label) * 3 + 1)));
+        }
+        let classify = |label: &u32| label % 3;
+        let grouped = sketch.top_grouped(4, classify);
+        let groups = sketch.labels().map(classify).collect::<HashSet<_>>();
+        assert_eq!(grouped.len(), groups.len());
+        for (group, top) in grouped {
+            let mut expected = sketch.top(usize::MAX);
+            expected.retain(|(label, _)| classify(label) == group);
+            assert_eq!(
+                top.iter().map(|&(_, c)| c).collect::<Vec<_>>(),
+                expected.iter().take(4).map(|&(_, c)| c).collect::<Vec<_>>()
+            );
+        }
+        assert!(sketch.top_grouped(0, classify).is_empty());
+    }
+
+    #[test]
+    fn evictions_are_logged() {
+        let mut sketch: HllSamplingSpaceSavingSets<u32, u64> =
+            SamplingSpaceSavingSets::new(&config());
+        sketch.track_evictions(3);
+        let mut evicted = Vec::new();
+        for i in 0..10_000_u64 {
+            if let InsertOutcome::Evicted { label } =
+                sketch.insert_with_outcome((i % 100) as u32, &i)
+            {
+                evicted.push(label);
+            }
+        }
+        assert!(evicted.len() > 3);
+        assert_eq!(
+            sketch
+                .recent_evictions()
+                .map(|eviction| eviction.label)
+                .collect::<Vec<_>>(),
+            evicted.iter().rev().take(3).copied().collect::<Vec<_>>()
+        );
+        assert!(sketch
+            .recent_evictions()
+            .all(|eviction| eviction.cardinality > 0));
+
+        // Labels evicted by merges are logged too.
+        let mut other = sketch.clone();
+        other.clear();
+        other.extend((0..SIZE as u64 * 10_000).map(|i| (1_000 + (i % SIZE as u64) as u32, i)));
+        assert!(sketch.merge(&other).is_ok());
+        for eviction in sketch.recent_evictions() {
+            assert!(eviction.label < 100);
+            assert!(!sketch.labels().any(|&label| label == eviction.label));
+        }
+
+        sketch.clear();
+        assert_eq!(sketch.recent_evictions().count(), 0);
+    }
+
+    /// Mirrors the labels tracked by a sketch from its admissions and
+    /// evictions.
+    #[derive(Default)]
+    struct Mirror(std::sync::Mutex<HashSet<u32>>);
+
+    impl Observer<u32> for Mirror {
+        fn on_admit(&self, label: &u32) {
+            assert!(self.0.lock().unwrap().insert(*label));
+        }
+
+        fn on_evict(&self, label: &u32, _: u64) {
+            assert!(self.0.lock().unwrap().remove(label));
+        }
+    }
+
+    /// SipHash, keyed by writing the seeds first.
+    #[derive(Clone, Debug)]
+    struct SipBuildHasher([u64; 4]);
+
+    impl core::hash::BuildHasher for SipBuildHasher {
+        type Hasher = std::hash::DefaultHasher;
+
+        fn build_hasher(&self) -> Self::Hasher {
+            let mut hasher = std::hash::DefaultHasher::new();
+            self.0.hash(&mut hasher);
+            hasher
+        }
+    }
+
+    impl SeedableBuildHasher for SipBuildHasher {
+        fn with_seeds(seeds: [u64; 4]) -> Self {
+            Self(seeds)
+        }
+    }
+
+    #[test]
+    fn it_hashes_with_custom_hashers() {
+        let hll_config =
+            hll::Config::<SipBuildHasher>::with_hasher(COUNTER_SIZE, Some(HLL_SEEDS)).unwrap();
+        let config = Config::<_, _, SipBuildHasher>::with_hasher(SIZE, hll_config, Some(SEEDS));
+        let mut sketch =
+            SamplingSpaceSavingSets::<u32, HyperLogLog<u64, SipBuildHasher>, _, _>::new(
+                &config.unwrap(),
+            );
+        for i in 0..4_000_u64 {
+            (1..=8_u32)
+                .filter(|&label| i < u64::from(label) * 500)
+                .for_each(|label| sketch.insert(label, &i));
+        }
+        let top = sketch.top(3);
+        assert_eq!(top.iter().map(|&(l, _)| *l).collect::<Vec<_>>(), [8, 7, 6]);
+        assert!(relative_error(4_000, top[0].1) < 0.1);
+
+        let mut buf = Vec::new();
+        sketch.encode(&mut buf);
+        let mut merged = sketch.clone();
+        merged.clear();
+        assert!(merged.merge_encoded(&buf).is_ok());
+        assert_eq!(merged.top(3), top);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn metrics_count_inserts_evictions_and_merges() {
+        let mut sketch: HllSamplingSpaceSavingSets<u32, u64> =
+            SamplingSpaceSavingSets::new(&config());
+        let mut num_evictions = 0;
+        for i in 0..10_000_u64 {
+            if let InsertOutcome::Evicted { .. } = sketch.insert_with_outcome((i % 100) as u32, &i)
+            {
+                num_evictions += 1;
+            }
+        }
+        assert!(num_evictions > 0);
+        assert_eq!(sketch.num_evictions(), num_evictions);
+        let tracked = *sketch.labels().next().unwrap();
+        sketch.extend((0..1_000_u64).map(|i| (tracked, i)));
+        assert_eq!(sketch.num_inserts(), 11_000);
+
+        // Labels pruned after merges count as evicted too.
+        let mut other: HllSamplingSpaceSavingSets<u32, u64> =
+            SamplingSpaceSavingSets::new(&config());
+        other.extend((0..20_000_u64).map(|i| (100 + (i % SIZE as u64) as u32, i)));
+        let mut buf = vec![];
+        other.encode(&mut buf);
+        assert!(sketch.merge(&other).is_ok());
+        assert!(sketch.merge_owned(other).is_ok());
+        assert!(sketch.merge_encoded(&buf).is_ok());
+        buf.truncate(buf.len() - 1);
+        assert!(sketch.merge_encoded(&buf).is_err());
+        assert_eq!(sketch.num_merges(), 3);
+        assert!(sketch.num_evictions() > num_evictions);
+
+        sketch.clear();
+        assert_eq!(sketch.num_inserts(), 11_000);
+        assert_eq!(sketch.current_memory(), sketch.mem_size());
+    }
+
+    #[test]
+    fn observers_see_admissions_and_evictions() {
+        let mut sketch: HllSamplingSpaceSavingSets<u32, u64> =
+            SamplingSpaceSavingSets::new(&config());
+        let mirror = Arc::new(Mirror::default());
+        sketch.set_observer(mirror.clone());
+        let tracked = |sketch: &HllSamplingSpaceSavingSets<u32, u64>| {
+            sketch.labels().copied().collect::<HashSet<_>>()
+        };
+
+        sketch.extend((0..10_000_u64).map(|i| ((i % 100) as u32, i)));
+        assert_eq!(*mirror.0.lock().unwrap(), tracked(&sketch));
+
+        let mut other: HllSamplingSpaceSavingSets<u32, u64> =
+            SamplingSpaceSavingSets::new(&config());
+        other.extend((0..20_000_u64).map(|i| (100 + (i % 20) as u32, i)));
+        assert!(sketch.merge(&other).is_ok());
+        assert_eq!(*mirror.0.lock().unwrap(), tracked(&sketch));
+        assert!(sketch.merge_owned(other).is_ok());
+        assert_eq!(*mirror.0.lock().unwrap(), tracked(&sketch));
+
+        let mut encoded: HllSamplingSpaceSavingSets<u32, u64> =
+            SamplingSpaceSavingSets::new(&config());
+        encoded.extend((0..40_000_u64).map(|i| (200 + (i % 20) as u32, i)));
+        let mut buf = vec![];
+        encoded.encode(&mut buf);
+        assert!(sketch.merge_encoded(&buf).is_ok());
+        assert_eq!(*mirror.0.lock().unwrap(), tracked(&sketch));
+    }
+
+    #[test]
+    fn bloom_counters_tell_items_of_tracked_labels() {
+        let bloom_config = hll::BloomConfig::new(
+            hll::Config::new(COUNTER_SIZE, Some(HLL_SEEDS)).unwrap(),
+            4_096,
+            5,
+            Some(SEEDS),
+        )
+        .unwrap();
+        let mut sketch: SamplingSpaceSavingSets<u32, hll::BloomHyperLogLog<u64>> =
+            SamplingSpaceSavingSets::new(&Config::new(SIZE, bloom_config, Some(SEEDS)).unwrap());
+        for label in 0..SIZE as u32 {
+            for i in 0..100 {
+                sketch.insert(label, &(u64::from(label) * 1_000 + i));
+            }
+        }
+        assert_eq!(sketch.may_contain(&3, &3_042), Some(true));
+        assert_eq!(sketch.may_contain(&3, &4_042), Some(false));
+        assert_eq!(sketch.may_contain(&(SIZE as u32), &0), None);
+    }
+
+    #[test]
+    fn cardinality_union_counts_shared_items_once() {
+        let mut sketch: HllSamplingSpaceSavingSets<u32, u64> =
+            SamplingSpaceSavingSets::new(&config());
+        for label in 0..3_u64 {
+            for i in label * 50..label * 50 + 100 {
+                sketch.insert(label as u32, &i);
+            }
+        }
+        assert!(relative_error(sketch.cardinality_union(&[0, 1, 2]), 200) < 0.1);
+        assert!(relative_error(sketch.cardinality_union(&[0, 2, 42]), 200) < 0.1);
+        assert_eq!(sketch.cardinality_union(&[0]), sketch.cardinality(&0));
+        assert_eq!(sketch.cardinality_union(&[42]), 0);
+    }
+
+    #[test]
+    fn total_cardinality_counts_items_of_evicted_and_rejected_labels() {
+        let mut left: HllSamplingSpaceSavingSets<u32, u64> =
+            SamplingSpaceSavingSets::new(&config());
+        let mut right = left.clone();
+        left.extend((0..5_000_u64).map(|i| ((i % 100) as u32, i)));
+        right.extend((5_000..10_000_u64).map(|i| ((i % 100) as u32, i)));
+        assert!(relative_error(left.total_cardinality().unwrap(), 5_000) < 0.1);
+        assert!(left.merge(&right).is_ok());
+        assert!(relative_error(left.total_cardinality().unwrap(), 10_000) < 0.1);
+        left.clear();
+        assert_eq!(left.total_cardinality(), Some(0));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn interned_sketches_merge_like_labeled_ones() {
+        let mut left: HllSamplingSpaceSavingSets<String, u64> =
+            SamplingSpaceSavingSets::new(&config());
+        let mut right = left.clone();
+        for (n, label) in ('a'..='e').enumerate() {
+            for i in 0..(n as u64 + 1) * 20 {
+                left.insert(label.to_string(), &i);
+                right.insert(label.to_string(), &(i + 50));
+            }
+        }
+        let dictionary = LabelDictionary::new();
+        let mut interned = left.intern_labels(&dictionary);
+        assert!(interned.merge(&right.intern_labels(&dictionary)).is_ok());
+        assert!(left.merge(&right).is_ok());
+
+        let top = interned.top(SIZE);
+        let resolved = dictionary
+            .resolve_top(&top)
+            .into_iter()
+            .map(|(label, cardinality)| ((*label).clone(), cardinality))
+            .collect::<Vec<_>>();
+        let expected = left
+            .top(SIZE)
+            .into_iter()
+            .map(|(label, cardinality)| (label.clone(), cardinality))
+            .collect::<Vec<_>>();
+        assert_eq!(resolved, expected);
+    }
+
+    #[test]
+    fn it_validates_invariants() {
+        let mut sketch: HllSamplingSpaceSavingSets<char, u64> =
+            SamplingSpaceSavingSets::new(&config());
+        assert!(sketch.debug_validate().is_ok());
+        for (n, label) in ('a'..='o').enumerate() {
+            for i in 0..(n as u64 + 1) * 20 {
+                sketch.insert(label, &i);
+            }
+        }
+        assert!(sketch.debug_validate().is_ok());
+        let other = sketch.clone();
+        assert!(sketch.merge(&other).is_ok());
+        assert!(sketch.debug_validate().is_ok());
+    }
+
+    #[test]
+    fn removed_labels_free_their_counters() {
+        let mut sketch: HllSamplingSpaceSavingSets<u32, u64> =
+            SamplingSpaceSavingSets::new(&config());
+        for label in 0..100 {
+            for i in 0..label + 10 {
+                sketch.insert(label, &u64::from(i));
+            }
+        }
+        let noise = *sketch.labels().next().unwrap();
+        assert!(sketch.remove(&noise));
+        assert!(!sketch.remove(&noise));
+        assert!(sketch.labels().all(|&label| label != noise));
+        assert!(sketch.debug_validate().is_ok());
+        // The freed counter goes to the next label, whatever its cardinality.
+        assert_eq!(
+            sketch.insert_with_outcome(1000, &0),
+            InsertOutcome::CreatedNew
+        );
+        assert_eq!(sketch.num_labels(), SIZE);
+    }
+
+    #[test]
+    fn insert_iter_matches_insert() {
+        // Runs of consecutive pairs share labels, some of which get evicted.
+        let pairs = (0..10_000_u64).map(|i| ((i / 7 % 300) as u32, i));
+        let mut one_by_one: HllSamplingSpaceSavingSets<u32, u64> =
+            SamplingSpaceSavingSets::new(&config());
+        pairs
+            .clone()
+            .for_each(|(label, i)| one_by_one.insert(label, &i));
+        let mut batched: HllSamplingSpaceSavingSets<u32, u64> =
+            SamplingSpaceSavingSets::new(&config());
+        batched.extend(pairs);
+        assert_eq!(batched.top(usize::MAX), one_by_one.top(usize::MAX));
+        assert_eq!(batched.total_cardinality(), one_by_one.total_cardinality());
+        assert_eq!(batched.version(), one_by_one.version());
+    }
+
+    #[test]
+    fn hashes_are_inserted_like_u64_items() {
+        let mut hashed: HllSamplingSpaceSavingSets<u32, String> =
+            SamplingSpaceSavingSets::new(&config());
+        let mut items: HllSamplingSpaceSavingSets<u32, u64> =
+            SamplingSpaceSavingSets::new(&config());
+        for i in 0..10_000_u64 {
+            let label = (i / 7 % 300) as u32;
+            assert_eq!(
+                hashed.insert_hashed_with_outcome(label, i),
+                items.insert_with_outcome(label, &i)
+            );
+        }
+        assert_eq!(hashed.top(usize::MAX), items.top(usize::MAX));
+    }
+
+    #[test]
+    fn mem_size_counts_labels_and_registers() {
+        let mut sketch: HllSamplingSpaceSavingSets<String, u64> =
+            SamplingSpaceSavingSets::new(&config());
+        let empty = sketch.mem_size();
+        for label in 0..SIZE {
+            sketch.insert(format!("{:0>100}", label), &0);
+        }
+        assert!(sketch.mem_size() >= empty + SIZE * 100);
+        // Counters of a single item only hold one sparse register.
+        assert!(sketch.mem_size() < empty + SIZE * (COUNTER_SIZE + 100));
+    }
+
+    #[test]
+    fn cleared_sketches_behave_like_fresh_ones() {
+        let pairs = || (0..1_000_u64).map(|i| ((i % 7) as u32, i));
+        let mut fresh: HllSamplingSpaceSavingSets<u32, u64> =
+            SamplingSpaceSavingSets::new(&config());
+        fresh.track_rejections(16, 2);
+        let mut cleared = fresh.clone();
+        cleared.extend((0..1_000_u64).map(|i| ((i % 100) as u32, i)));
+        assert!(!cleared.rejection_report(1).is_empty());
+        cleared.clear();
+        assert_eq!(cleared.num_labels(), 0);
+        assert_eq!(cleared.threshold, 0);
+        assert!(cleared.rejection_report(1).is_empty());
+
+        fresh.extend(pairs());
+        cleared.extend(pairs());
+        let mut expected = fresh.top(usize::MAX);
+        let mut actual = cleared.top(usize::MAX);
+        expected.sort();
+        actual.sort();
+        assert_eq!(actual, expected);
+        assert!(cleared.debug_validate().is_ok());
+    }
+
+    #[test]
+    fn labels_above_matches_top() {
+        let mut sketch: HllSamplingSpaceSavingSets<u32, u64> =
+            SamplingSpaceSavingSets::new(&config());
+        sketch.extend((0..10_000_u64).map(|i| ((i % 300) as u32, i * i % 1_000)));
+        for threshold in [0, 10, 30, u64::MAX] {
+            let expected = sketch
+                .top(usize::MAX)
+                .into_iter()
+                .filter(|&(_, cardinality)| cardinality > threshold)
+                .map(|(_, cardinality)| cardinality)
+                .collect::<Vec<_>>();
+            let above = sketch.labels_above(threshold);
+            assert_eq!(above.iter().map(|&(_, c)| c).collect::<Vec<_>>(), expected);
+        }
+    }
+
+    #[test]
+    fn it_never_panics_on_arbitrary_input() {
+        let config = Config::new(
+            2,
+            hll::Config::new(16, Some(HLL_SEEDS)).unwrap(),
+            Some(SEEDS),
+        )
+        .unwrap();
+        proptest!(|(
+            pairs1 in vec((any::<u8>(), any::<u64>()), 0..200),
+            pairs2 in vec((any::<u8>(), any::<u64>()), 0..200),
+            queries in vec(any::<u8>(), 0..10),
+            k in 0..5_usize,
+            corruption in (any::<usize>(), any::<u8>()),
+        )| {
+            let mut sketch1: HllSamplingSpaceSavingSets<u8, u64> =
+                SamplingSpaceSavingSets::new(&config);
+            pairs1.iter().for_each(|(label, item)| sketch1.insert(*label, item));
+            let mut sketch2: HllSamplingSpaceSavingSets<u8, u64> =
+                SamplingSpaceSavingSets::new(&config);
+            pairs2.iter().for_each(|(label, item)| sketch2.insert(*label, item));
+
+            prop_assert!(sketch1.merge(&sketch2).is_ok());
+            queries.iter().for_each(|label| {
+                sketch1.cardinality(label);
+            });
+            prop_assert!(sketch1.top(k).len() <= k);
+
+            let mut buf = vec![];
+            sketch2.encode(&mut buf);
+            let (index, byte) = corruption;
+            let len = buf.len();
+            buf[index % len] = byte;
+            let _ = sketch1.merge_encoded(&buf);
+            prop_assert!(sketch1.debug_validate().is_ok());
+        });
+    }
+
+    const MERGE_ORDERS: [[usize; 3]; 6] = [
+        [0, 1, 2],
+        [0, 2, 1],
+        [1, 0, 2],
+        [1, 2, 0],
+        [2, 0, 1],
+        [2, 1, 0],
+    ];
+
+    fn counters(sketch: &HllSamplingSpaceSavingSets<u8, u64>) -> (Vec<(u8, u64)>, u64) {
+        let mut counters = sketch
+            .top(usize::MAX)
+            .into_iter()
+            .map(|(label, cardinality)| (*label, cardinality))
+            .collect::<Vec<_>>();
+        counters.sort();
+        (counters, sketch.threshold)
+    }
+
+    #[test]
+    fn merge_order_does_not_matter() {
+        // Pruning makes merges order dependent in general, but not when all
+        // labels fit, and never between two sketches.
+        proptest!(|(
+            pairs in vec(vec((0..SIZE as u8, any::<u64>()), 0..100), 3),
+            overflowing_pairs in vec(vec((0..2 * SIZE as u8, 0..4_u64), 0..100), 2),
+        )| {
+            let new_sketch = |pairs: &Vec<(u8, u64)>| {
+                let mut sketch: HllSamplingSpaceSavingSets<u8, u64> =
+                    SamplingSpaceSavingSets::new(&config());
+                pairs.iter().for_each(|(label, item)| sketch.insert(*label, item));
+                sketch
+            };
+
+            let sketches = pairs.iter().map(new_sketch).collect::<Vec<_>>();
+            let merged = MERGE_ORDERS.map(|order| {
+                let mut merged = sketches[order[0]].clone();
+                order[1..].iter().for_each(|&i| merged.merge(&sketches[i]).unwrap());
+                counters(&merged)
+            });
+            for counters in merged.iter() {
+                prop_assert_eq!(counters, &merged[0]);
+            }
+
+            let sketch1 = new_sketch(&overflowing_pairs[0]);
+            let sketch2 = new_sketch(&overflowing_pairs[1]);
+            let mut merged12 = sketch1.clone();
+            prop_assert!(merged12.merge(&sketch2).is_ok());
+            let mut owned12 = sketch1.clone();
+            prop_assert!(owned12.merge_owned(sketch2.clone()).is_ok());
+            prop_assert_eq!(counters(&owned12), counters(&merged12));
+            let mut merged21 = sketch2;
+            prop_assert!(merged21.merge(&sketch1).is_ok());
+            prop_assert_eq!(counters(&merged12), counters(&merged21));
+        });
+    }
+
+    #[test]
+    fn memoized_queries_are_invalidated_by_mutations() {
+        let mut sketch: HllSamplingSpaceSavingSets<char, u64> =
+            SamplingSpaceSavingSets::new(&config());
+        for i in 0..100 {
+            sketch.insert('a', &i);
+        }
+        let version = sketch.version();
+        let mut memoized = Memoized::new(sketch);
+        let top = memoized.top(SIZE);
+        assert_eq!(memoized.top(1), top[..1]);
+        let cardinality = memoized.cardinality(&'a');
+        assert_eq!(memoized.sketch().version(), version);
+
+        for i in 100..1000 {
+            memoized.sketch_mut().insert('a', &i);
+        }
+        assert_ne!(memoized.sketch().version(), version);
+        assert!(memoized.cardinality(&'a') > cardinality);
+        assert_eq!(memoized.top(1)[0].1, memoized.sketch().cardinality(&'a'));
+    }
+
+    #[test]
+    fn rejected_merges_keep_the_version() {
+        let mut sketch: HllSamplingSpaceSavingSets<char, u64> =
+            SamplingSpaceSavingSets::new(&config());
+        sketch.insert('a', &1);
+        let hll_config = hll::Config::new(COUNTER_SIZE / 4, Some(HLL_SEEDS)).unwrap();
+        let other_config = Config::new(SIZE, hll_config, Some([4, 5, 6, 7])).unwrap();
+        let other = SamplingSpaceSavingSets::new(&other_config);
+        let version = sketch.version();
+        assert!(matches!(
+            sketch.merge(&other),
+            Err(MergeError::ConfigMismatch)
+        ));
+        assert!(matches!(
+            sketch.merge_owned(other),
+            Err(MergeError::ConfigMismatch)
+        ));
+        assert_eq!(sketch.version(), version);
+    }
+
+    #[test]
+    fn it_is_send_and_sync_regardless_of_items() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        type Sketch = HllSamplingSpaceSavingSets<u32, std::rc::Rc<str>>;
+        assert_send_sync::<Sketch>();
+        assert_send_sync::<sketch_traits::Frozen<Sketch>>();
+    }
+
+    #[test]
+    fn insert_reports_outcomes() {
+        let mut sketch: HllSamplingSpaceSavingSets<u32, u64> =
+            SamplingSpaceSavingSets::new(&config());
+        for label in 0..SIZE as u32 {
+            assert_eq!(
+                sketch.insert_with_outcome(label, &0),
+                InsertOutcome::CreatedNew
+            );
+            assert_eq!(
+                sketch.insert_with_outcome(label, &1),
+                InsertOutcome::UpdatedExisting
+            );
+        }
+        for label in SIZE as u32..100 {
+            match sketch.insert_with_outcome(label, &u64::from(label)) {
+                InsertOutcome::Evicted { label: evicted } => {
+                    assert!(sketch.counters.contains_key(&label));
+                    assert!(!sketch.counters.contains_key(&evicted));
+                }
+                InsertOutcome::RejectedByThreshold => {
+                    assert!(!sketch.counters.contains_key(&label));
+                }
+                outcome => panic!("unexpected outcome: {:?}", outcome),
+            }
+        }
+    }
+
+    #[test]
+    fn it_reports_the_most_rejected_labels() {
+        let mut sketch: HllSamplingSpaceSavingSets<u32, u64> =
+            SamplingSpaceSavingSets::new(&config());
+        assert!(sketch.rejection_report(1).is_empty());
+        sketch.track_rejections(256, 4);
+        for label in 0..SIZE as u32 {
+            for i in 0..10_000 {
+                sketch.insert(label, &i);
+            }
+        }
+        for i in 0..1000 {
+            sketch.insert(100, &i);
+        }
+        for label in 101..110 {
+            for i in 0..10 {
+                sketch.insert(label, &i);
+            }
+        }
+        let report = sketch.rejection_report(SIZE);
+        assert_eq!(report[0].0, &100);
+        assert!(report[0].1 >= 900);
+        assert!(report.len() <= SIZE);
+    }
 }
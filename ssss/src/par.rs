@@ -0,0 +1,109 @@
+//! Parallel merges, for aggregators merging many sketches.
+
+use core::hash::Hash;
+
+use rayon::prelude::*;
+use sketch_traits::{CardinalitySketch, New, SeedableBuildHasher};
+
+use crate::{cached::Cached, AdmissionEstimator, MergeError, SamplingSpaceSavingSets};
+
+impl<L, S, A, H> SamplingSpaceSavingSets<L, S, A, H>
+where
+    L: Eq + Hash + Clone + Send + Sync,
+    S: CardinalitySketch + New + Send + Sync,
+    S::Config: Eq,
+    A: AdmissionEstimator + Eq,
+    H: SeedableBuildHasher,
+{
+    /// Like `merge`, but merges the counters of labels in parallel, on
+    /// rayon's thread pool, each shard of the counters on its own thread.
+    pub fn par_merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        if self.config != other.config {
+            return Err(MergeError::ConfigMismatch);
+        }
+        self.version = self.version.wrapping_add(1);
+        #[cfg(feature = "metrics")]
+        {
+            self.metrics.merges += 1;
+        }
+
+        self.merge_total(&other.total);
+        // The counters of labels only tracked by `other` are created first,
+        // so that the shards only have to update counters in place.
+        for (l, _) in other.counters.iter() {
+            if self.observer.is_some() && !self.counters.contains_key(l) {
+                self.log_admission(l);
+            }
+            self.counters.get_or_insert_with(l.clone(), || {
+                Cached::new(&self.config.cardinality_sketch_config)
+            });
+        }
+        let (others, version) = (&other.counters, self.version);
+        self.counters.par_iter_mut().for_each(|(label, counter)| {
+            let Some(c) = others.get(label) else {
+                return;
+            };
+            counter.merge(c).unwrap_or_else(
+                // By construction, parameters cannot mismatch.
+                |_| unreachable!(),
+            );
+            counter.touch(version);
+        });
+        if let (Some(rejections), Some(other_rejections)) =
+            (&mut self.rejections, &other.rejections)
+        {
+            rejections.merge(other_rejections);
+        }
+
+        self.prune_after_merge();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sketch_traits::{HeavyDistinctHitterSketch, Versioned};
+
+    use super::*;
+    use crate::{Config, HllSamplingSpaceSavingSets};
+
+    #[test]
+    fn par_merge_matches_merge() {
+        let config = Config::new(
+            100,
+            hll::Config::new(64, Some([0; 8])).unwrap(),
+            Some([1; 4]),
+        )
+        .unwrap();
+        let mut left: HllSamplingSpaceSavingSets<u64, u64> = SamplingSpaceSavingSets::new(&config);
+        let mut right = left.clone();
+        left.extend((0..10_000_u64).map(|i| (i % 150, i)));
+        right.extend((0..10_000_u64).map(|i| (i % 120, i * 7)));
+
+        let mut merged = left.clone();
+        assert!(merged.merge(&right).is_ok());
+        assert!(left.par_merge(&right).is_ok());
+        let (mut expected, mut actual) = (merged.top(usize::MAX), left.top(usize::MAX));
+        expected.sort();
+        actual.sort();
+        assert_eq!(actual, expected);
+        assert_eq!(left.version(), merged.version());
+    }
+
+    #[test]
+    fn rejected_merges_keep_the_version() {
+        let hll_config = hll::Config::new(64, Some([0; 8])).unwrap();
+        let config = Config::new(100, hll_config.clone(), Some([1; 4])).unwrap();
+        let mut sketch: HllSamplingSpaceSavingSets<u64, u64> =
+            SamplingSpaceSavingSets::new(&config);
+        sketch.insert(1, &1);
+        let other_config = Config::new(100, hll_config, Some([2; 4])).unwrap();
+        let other = SamplingSpaceSavingSets::new(&other_config);
+        let version = sketch.version();
+        assert!(matches!(
+            sketch.par_merge(&other),
+            Err(MergeError::ConfigMismatch)
+        ));
+        assert_eq!(sketch.version(), version);
+    }
+}
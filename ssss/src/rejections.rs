@@ -0,0 +1,182 @@
+use alloc::{collections::BinaryHeap, vec, vec::Vec};
+use core::{cmp::Reverse, hash::Hash};
+
+use sketch_traits::{collections::HashMap, truncate_top, MemorySize};
+
+/// How often untracked labels were rejected at the sampling threshold.
+///
+/// Rejections are counted per label hash in a Count-Min sketch, next to the
+/// labels with the highest counts. A label that keeps getting rejected is a
+/// sign that the sketch is too small to track it.
+#[derive(Clone, Debug)]
+pub(crate) struct Rejections<L> {
+    width: usize,
+    depth: usize,
+    counts: Vec<u64>,
+    /// The most rejected labels, indexing their slots.
+    candidates: HashMap<L, usize>,
+    /// The candidates and their hashes.
+    slots: Vec<(L, u64)>,
+    /// The slots of the candidates by their counts when last looked at, which
+    /// are at most their current counts, since counts only grow. Entries are
+    /// only brought up to date when they reach the top, so finding the
+    /// candidate to replace doesn't go through all of them.
+    by_count: BinaryHeap<Reverse<(u64, usize)>>,
+    max_num_candidates: usize,
+    /// At most the lowest count of the candidates.
+    min_candidate_count: u64,
+}
+
+impl<L> MemorySize for Rejections<L>
+where
+    L: MemorySize,
+{
+    fn heap_size(&self) -> usize {
+        self.counts.heap_size()
+            + self.candidates.heap_size()
+            + self.slots.heap_size()
+            + self.by_count.capacity() * core::mem::size_of::<Reverse<(u64, usize)>>()
+    }
+}
+
+impl<L> Rejections<L>
+where
+    L: Eq + Hash + Clone,
+{
+    pub(crate) fn new(width: usize, depth: usize, max_num_candidates: usize) -> Self {
+        let width = width.max(1);
+        let depth = depth.max(1);
+        Self {
+            width,
+            depth,
+            counts: vec![0; width * depth],
+            candidates: HashMap::with_capacity(max_num_candidates),
+            slots: Vec::with_capacity(max_num_candidates),
+            by_count: BinaryHeap::with_capacity(max_num_candidates),
+            max_num_candidates,
+            min_candidate_count: 0,
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.counts.fill(0);
+        self.candidates.clear();
+        self.slots.clear();
+        self.by_count.clear();
+        self.min_candidate_count = 0;
+    }
+
+    /// The index of `hash` in row `r`. Rows derive their hashes from the two
+    /// halves of `hash` (Kirsch-Mitzenmacher).
+    #[inline]
+    fn index(&self, hash: u64, r: usize) -> usize {
+        let h2 = hash.rotate_left(32) | 1;
+        // The modulo is below `width`, which is a usize.
+        let c = hash.wrapping_add((r as u64).wrapping_mul(h2)) % self.width as u64;
+        r * self.width + c as usize
+    }
+
+    pub(crate) fn count(&self, hash: u64) -> u64 {
+        (0..self.depth)
+            .map(|r| self.counts[self.index(hash, r)])
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub(crate) fn record(&mut self, label: L, hash: u64) {
+        for r in 0..self.depth {
+            let i = self.index(hash, r);
+            self.counts[i] = self.counts[i].saturating_add(1);
+        }
+        if self.candidates.contains_key(&label) {
+            return;
+        }
+        if self.slots.len() < self.max_num_candidates {
+            self.add_candidate(label, hash);
+            return;
+        }
+        let count = self.count(hash);
+        if count <= self.min_candidate_count {
+            return;
+        }
+        let Some((min_count, slot)) = self.min_candidate() else {
+            return;
+        };
+        self.min_candidate_count = min_count;
+        if count > min_count {
+            self.by_count.pop();
+            let (evicted, _) = core::mem::replace(&mut self.slots[slot], (label.clone(), hash));
+            self.candidates.remove(&evicted);
+            self.candidates.insert(label, slot);
+            self.by_count.push(Reverse((count, slot)));
+        }
+    }
+
+    fn add_candidate(&mut self, label: L, hash: u64) {
+        let slot = self.slots.len();
+        self.by_count.push(Reverse((self.count(hash), slot)));
+        self.candidates.insert(label.clone(), slot);
+        self.slots.push((label, hash));
+    }
+
+    /// The lowest count of the candidates and its slot, which is left at the
+    /// top of `by_count`. Entries are brought up to date until the top one
+    /// is, at which point it's the minimum, as the others are at least
+    /// their outdated counts.
+    fn min_candidate(&mut self) -> Option<(u64, usize)> {
+        loop {
+            let &Reverse((count, slot)) = self.by_count.peek()?;
+            let current = self.count(self.slots[slot].1);
+            if current == count {
+                return Some((count, slot));
+            }
+            self.by_count.pop();
+            self.by_count.push(Reverse((current, slot)));
+        }
+    }
+
+    /// Adds up the counts of both sketches and keeps the most rejected
+    /// candidates of both. Counts can only be added up between sketches of
+    /// the same dimensions, so this leaves the sketch untouched otherwise.
+    pub(crate) fn merge(&mut self, other: &Self) {
+        if (self.width, self.depth) != (other.width, other.depth) {
+            return;
+        }
+        self.counts
+            .iter_mut()
+            .zip(other.counts.iter())
+            .for_each(|(s, o)| *s = s.saturating_add(*o));
+        let slots = core::mem::take(&mut self.slots);
+        let mut entries = slots
+            .into_iter()
+            .chain(
+                other
+                    .slots
+                    .iter()
+                    .filter(|(label, _)| !self.candidates.contains_key(label))
+                    .cloned(),
+            )
+            .map(|(label, hash)| (self.count(hash), hash, label))
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|&(count, hash, _)| Reverse((count, hash)));
+        entries.truncate(self.max_num_candidates);
+        self.candidates.clear();
+        self.by_count.clear();
+        for (_, hash, label) in entries {
+            self.add_candidate(label, hash);
+        }
+        self.min_candidate_count = 0;
+    }
+
+    /// The `k` most rejected labels with their rejection counts, in
+    /// decreasing order.
+    pub(crate) fn report(&self, k: usize) -> Vec<(&L, u64)> {
+        let mut entries = self
+            .slots
+            .iter()
+            .map(|(label, hash)| (label, self.count(*hash)))
+            .collect::<Vec<_>>();
+        truncate_top(&mut entries, k);
+        entries
+    }
+}
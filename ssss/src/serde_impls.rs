@@ -0,0 +1,250 @@
+//! Sketches serialize their config, threshold and counters, each as a label
+//! and the state of its cardinality sketch, then the sketch of all items;
+//! configs only serialize their
+//! parameters and seeds, and are rebuilt, and checked, from them when
+//! deserializing.
+
+use alloc::vec::Vec;
+use core::hash::Hash;
+
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+use sketch_traits::{CardinalitySketch, New, SeedableBuildHasher};
+
+use crate::{cached::Cached, counters::Counters, Config, SamplingSpaceSavingSets};
+
+#[derive(Serialize)]
+#[serde(rename = "Config")]
+struct ConfigRef<'a, C, A> {
+    max_num_counters: usize,
+    seeds: &'a [u64; 4],
+    cardinality_sketch_config: &'a C,
+    admission: &'a A,
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "Config")]
+struct ConfigRepr<C, A> {
+    max_num_counters: usize,
+    seeds: [u64; 4],
+    cardinality_sketch_config: C,
+    admission: A,
+}
+
+impl<C, A, H> Serialize for Config<C, A, H>
+where
+    C: Serialize,
+    A: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ConfigRef {
+            max_num_counters: self.max_num_counters,
+            seeds: &self.seeds,
+            cardinality_sketch_config: &self.cardinality_sketch_config,
+            admission: &self.admission,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, C, A, H> Deserialize<'de> for Config<C, A, H>
+where
+    C: Deserialize<'de>,
+    A: Deserialize<'de>,
+    H: SeedableBuildHasher,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = ConfigRepr::deserialize(deserializer)?;
+        Config::with_hasher(
+            repr.max_num_counters,
+            repr.cardinality_sketch_config,
+            Some(repr.seeds),
+        )
+        .map(|config| config.with_admission(repr.admission))
+        .map_err(D::Error::custom)
+    }
+}
+
+/// Serializes counters as a sequence of (label, sketch) pairs.
+struct CountersRef<'a, L, S, H>(&'a Counters<L, Cached<S>, H>);
+
+impl<L, S, H> Serialize for CountersRef<'_, L, S, H>
+where
+    L: Serialize,
+    S: CardinalitySketch + Serialize,
+{
+    fn serialize<T: Serializer>(&self, serializer: T) -> Result<T::Ok, T::Error> {
+        serializer.collect_seq(
+            self.0
+                .iter()
+                .map(|(label, counter)| (label, counter.sketch())),
+        )
+    }
+}
+
+#[derive(Serialize)]
+#[serde(
+    rename = "SamplingSpaceSavingSets",
+    bound = "L: Serialize, S: CardinalitySketch + Serialize, C: Serialize, A: Serialize"
+)]
+struct SketchRef<'a, L, S, C, A, H> {
+    config: &'a Config<C, A, H>,
+    threshold: u64,
+    counters: CountersRef<'a, L, S, H>,
+    total: &'a S,
+}
+
+#[derive(Deserialize)]
+#[serde(
+    rename = "SamplingSpaceSavingSets",
+    bound(
+        deserialize = "L: Deserialize<'de>, S: Deserialize<'de>, C: Deserialize<'de>, \
+        A: Deserialize<'de>, H: SeedableBuildHasher"
+    )
+)]
+struct SketchRepr<L, S, C, A, H> {
+    config: Config<C, A, H>,
+    threshold: u64,
+    counters: Vec<(L, S)>,
+    /// Missing from sketches serialized before it was tracked.
+    total: Option<S>,
+}
+
+impl<L, S, A, H> Serialize for SamplingSpaceSavingSets<L, S, A, H>
+where
+    L: Serialize,
+    S: CardinalitySketch + New + Serialize,
+    S::Config: Serialize,
+    A: Serialize,
+    H: SeedableBuildHasher,
+{
+    fn serialize<T: Serializer>(&self, serializer: T) -> Result<T::Ok, T::Error> {
+        SketchRef {
+            config: &self.config,
+            threshold: self.threshold,
+            counters: CountersRef(&self.counters),
+            total: &self.total,
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Like decoding, deserializing prunes the counters of sketches that
+/// overcommitted to the top `max_num_counters`.
+impl<'de, L, S, A, H> Deserialize<'de> for SamplingSpaceSavingSets<L, S, A, H>
+where
+    L: Deserialize<'de> + Eq + Hash + Clone,
+    S: CardinalitySketch + New + Deserialize<'de>,
+    S::Config: Deserialize<'de> + Clone,
+    A: Deserialize<'de> + Clone,
+    H: SeedableBuildHasher,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = SketchRepr::<L, S, S::Config, A, H>::deserialize(deserializer)?;
+        let mut sketch = Self::new(&repr.config);
+        for (label, counter_sketch) in repr.counters {
+            let mut counter = Cached::new(&sketch.config.cardinality_sketch_config);
+            counter
+                .merge(&Cached::from_sketch(counter_sketch))
+                .map_err(|_| D::Error::custom("a counter's config does not match the sketch's"))?;
+            if sketch.counters.insert(label, counter).is_some() {
+                return Err(D::Error::custom("a label has more than one counter"));
+            }
+        }
+        let min_cardinality = sketch.counters.values().map(|c| c.cardinality()).min();
+        if min_cardinality.is_some_and(|min| repr.threshold > min) {
+            return Err(D::Error::custom(
+                "the threshold is above the minimum cardinality",
+            ));
+        }
+        sketch.threshold = repr.threshold;
+        if let Some(total) = repr.total {
+            sketch
+                .total
+                .merge(&total)
+                .map_err(|_| D::Error::custom("the total's config does not match the sketch's"))?;
+        }
+        if sketch.counters.len() > sketch.config.overcommitted_capacity() {
+            sketch.prune();
+        }
+        Ok(sketch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sketch_traits::HeavyDistinctHitterSketch;
+
+    use super::*;
+    use crate::{AdmissionStrategy, HllSamplingSpaceSavingSets};
+
+    fn config() -> Config<hll::Config> {
+        Config::new(
+            10,
+            hll::Config::new(512, Some([8, 9, 10, 11, 12, 13, 14, 15])).unwrap(),
+            Some([0, 1, 2, 3]),
+        )
+        .unwrap()
+        .with_admission(AdmissionStrategy::TrailingZeros)
+    }
+
+    #[test]
+    fn it_round_trips() {
+        let mut sketch = HllSamplingSpaceSavingSets::<String, u64>::new(&config());
+        for label in 0..20_u64 {
+            for i in 0..label * 10 {
+                sketch.insert(label.to_string(), &i);
+            }
+        }
+        let json = serde_json::to_string(&sketch).unwrap();
+        let decoded: HllSamplingSpaceSavingSets<String, u64> = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.config, sketch.config);
+        assert_eq!(decoded.threshold, sketch.threshold);
+        assert_eq!(decoded.total_cardinality(), sketch.total_cardinality());
+        let mut expected = sketch.top(usize::MAX);
+        let mut actual = decoded.top(usize::MAX);
+        expected.sort();
+        actual.sort();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn it_rejects_invalid_states() {
+        let mut sketch = HllSamplingSpaceSavingSets::<u32, u64>::new(&config());
+        sketch.insert(0, &0);
+        let json = serde_json::to_value(&sketch).unwrap();
+
+        let mut duplicated = json.clone();
+        let counter = duplicated["counters"][0].clone();
+        duplicated["counters"].as_array_mut().unwrap().push(counter);
+        assert!(
+            serde_json::from_value::<HllSamplingSpaceSavingSets<u32, u64>>(duplicated).is_err()
+        );
+
+        let mut mismatched = json.clone();
+        mismatched["counters"][0][1]["config"]["seeds"][0] = 0.into();
+        assert!(
+            serde_json::from_value::<HllSamplingSpaceSavingSets<u32, u64>>(mismatched).is_err()
+        );
+
+        let mut threshold = json;
+        threshold["threshold"] = u64::MAX.into();
+        assert!(serde_json::from_value::<HllSamplingSpaceSavingSets<u32, u64>>(threshold).is_err());
+    }
+
+    #[test]
+    fn it_prunes_overcommitted_sketches() {
+        let overcommitted = config().with_overcommit(2, 0).unwrap();
+        let mut sketch = HllSamplingSpaceSavingSets::<u32, u64>::new(&overcommitted);
+        for labels in [0..10, 10..20] {
+            let mut other = HllSamplingSpaceSavingSets::new(&overcommitted);
+            labels.for_each(|label| other.insert(label, &u64::from(label)));
+            assert!(sketch.merge(&other).is_ok());
+        }
+        assert_eq!(sketch.num_labels(), 20);
+
+        let json = serde_json::to_string(&sketch).unwrap();
+        let decoded: HllSamplingSpaceSavingSets<u32, u64> = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.config, config());
+        assert_eq!(decoded.num_labels(), 10);
+    }
+}
@@ -0,0 +1,96 @@
+//! Serves queries from several threads over snapshots of a sketch that an
+//! ingest thread keeps inserting into.
+//!
+//! Every so often, the ingest thread publishes a frozen clone of its sketch by
+//! swapping the `Arc` that readers load. Readers only hold the lock for as
+//! long as it takes to clone the `Arc`, and query their snapshot without any
+//! synchronization, cloning or allocation.
+//!
+//! ```
+//! cargo run --release --example query_server
+//! ```
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, PoisonError, RwLock,
+    },
+    thread,
+};
+
+use ssss_sketches::{hll, prelude::*, ssss};
+
+type Sketch = HllSamplingSpaceSavingSets<u32, u64>;
+
+const NUM_READERS: usize = 4;
+const NUM_SNAPSHOTS: u64 = 20;
+const PAIRS_PER_SNAPSHOT: u64 = 100_000;
+
+/// The latest snapshot of the sketch.
+struct Published(RwLock<Arc<Frozen<Sketch>>>);
+
+impl Published {
+    fn load(&self) -> Arc<Frozen<Sketch>> {
+        Arc::clone(&self.0.read().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    fn store(&self, snapshot: Frozen<Sketch>) {
+        let snapshot = Arc::new(snapshot);
+        // The previous snapshot is dropped by the last reader holding it.
+        *self.0.write().unwrap_or_else(PoisonError::into_inner) = snapshot;
+    }
+}
+
+fn main() {
+    let config = ssss::Config::builder()
+        .counters(100)
+        .cardinality_sketch_config(hll::Config::builder().registers(256).build().unwrap())
+        .build()
+        .unwrap();
+    let mut sketch = Sketch::new(&config);
+    let published = Published(RwLock::new(Arc::new(Frozen::new(sketch.clone()))));
+    let done = AtomicBool::new(false);
+
+    let num_queries = thread::scope(|scope| {
+        let readers = (0..NUM_READERS)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut num_queries = 0_u64;
+                    while !done.load(Ordering::Relaxed) {
+                        let snapshot = published.load();
+                        if let Some((label, _)) = snapshot.top(10).first() {
+                            snapshot.cardinality(label);
+                        }
+                        num_queries += 1;
+                    }
+                    num_queries
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for snapshot in 0..NUM_SNAPSHOTS {
+            for i in 0..PAIRS_PER_SNAPSHOT {
+                let item = snapshot * PAIRS_PER_SNAPSHOT + i;
+                let hash = item.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+                // Labels are drawn below a random bound, so that small labels
+                // get the most items.
+                let label = (hash as u32) % (1 + (hash >> 32) as u32 % 1_000);
+                sketch.insert(label, &item);
+            }
+            published.store(Frozen::new(sketch.clone()));
+        }
+        done.store(true, Ordering::Relaxed);
+        readers
+            .into_iter()
+            .map(|reader| reader.join().unwrap())
+            .sum::<u64>()
+    });
+
+    println!(
+        "{} queries from {} readers over {} snapshots",
+        num_queries, NUM_READERS, NUM_SNAPSHOTS
+    );
+    for (label, cardinality) in published.load().top(5) {
+        println!("{}\t{}", label, cardinality);
+    }
+}
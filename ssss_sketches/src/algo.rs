@@ -0,0 +1,402 @@
+//! The sketches of this workspace as [`Algorithm`]s, with the seeds and
+//! parameters the benchmarks evaluated them with.
+//!
+//! Sketches are built with fixed seeds, so that sketches built by different
+//! processes can be merged. Building one panics if the sizes are invalid for
+//! it, e.g., if the counter size isn't a power of two.
+
+use alloc::boxed::Box;
+use core::{any::Any, fmt, hash::Hash};
+
+use hll::HyperLogLog;
+pub use sketch_traits::Algorithm;
+use sketch_traits::{DynHeavyDistinctHitter, HeavyDistinctHitterSketch, New};
+
+#[derive(Clone, Debug)]
+pub struct Achll;
+impl Algorithm for Achll {
+    type Sketch<L, I> = count_hll::LabelArrayCountHLL<L, I>;
+
+    fn new_sketch<L, I>(&self, sketch_size: usize, counter_size: usize) -> Self::Sketch<L, I>
+    where
+        Self::Sketch<L, I>: HeavyDistinctHitterSketch<Label = L, Item = I>,
+    {
+        count_hll::LabelArrayCountHLL::new(
+            &count_hll::Config::new(
+                counter_size,
+                sketch_size,
+                Some([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]),
+            )
+            .unwrap(),
+        )
+    }
+
+    fn optimal_counter_size(&self) -> usize {
+        512
+    }
+}
+impl fmt::Display for Achll {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Count-HLL")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Schll;
+impl Algorithm for Schll {
+    type Sketch<L, I> = count_hll::LabelSetCountHLL<L, I>;
+
+    fn new_sketch<L, I>(&self, sketch_size: usize, counter_size: usize) -> Self::Sketch<L, I>
+    where
+        Self::Sketch<L, I>: HeavyDistinctHitterSketch<Label = L, Item = I>,
+    {
+        count_hll::LabelSetCountHLL::new(
+            &count_hll::Config::new(
+                counter_size,
+                sketch_size,
+                Some([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]),
+            )
+            .unwrap(),
+        )
+    }
+
+    fn optimal_counter_size(&self) -> usize {
+        512
+    }
+}
+impl fmt::Display for Schll {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SCHLL")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Sschll;
+impl Algorithm for Sschll {
+    type Sketch<L, I> = count_hll::SpaceSavingCountHLL<L, I>;
+
+    fn new_sketch<L, I>(&self, sketch_size: usize, counter_size: usize) -> Self::Sketch<L, I>
+    where
+        Self::Sketch<L, I>: HeavyDistinctHitterSketch<Label = L, Item = I>,
+    {
+        count_hll::SpaceSavingCountHLL::new(
+            &count_hll::Config::new(
+                counter_size,
+                sketch_size,
+                Some([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]),
+            )
+            .unwrap(),
+        )
+    }
+
+    fn optimal_counter_size(&self) -> usize {
+        512
+    }
+}
+impl fmt::Display for Sschll {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SS-Count-HLL")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Osss;
+impl Algorithm for Osss {
+    type Sketch<L, I> = sss::SpaceSavingSets<L, HyperLogLog<I>>;
+
+    fn new_sketch<L, I>(&self, sketch_size: usize, counter_size: usize) -> Self::Sketch<L, I>
+    where
+        Self::Sketch<L, I>: HeavyDistinctHitterSketch<Label = L, Item = I>,
+    {
+        sss::SpaceSavingSets::new(
+            &sss::Config::new(
+                sketch_size,
+                sss::ResetStrategy::Offset,
+                hll::Config::new(counter_size, Some([0, 1, 2, 3, 4, 5, 6, 7])).unwrap(),
+            )
+            .unwrap(),
+        )
+    }
+
+    fn optimal_counter_size(&self) -> usize {
+        1024
+    }
+}
+impl fmt::Display for Osss {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "OSSS")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Rsss;
+impl Algorithm for Rsss {
+    type Sketch<L, I> = sss::SpaceSavingSets<L, HyperLogLog<I>>;
+
+    fn new_sketch<L, I>(&self, sketch_size: usize, counter_size: usize) -> Self::Sketch<L, I>
+    where
+        Self::Sketch<L, I>: HeavyDistinctHitterSketch<Label = L, Item = I>,
+    {
+        sss::SpaceSavingSets::new(
+            &sss::Config::new(
+                sketch_size,
+                sss::ResetStrategy::Recycle,
+                hll::Config::new(counter_size, Some([0, 1, 2, 3, 4, 5, 6, 7])).unwrap(),
+            )
+            .unwrap(),
+        )
+    }
+
+    fn optimal_counter_size(&self) -> usize {
+        1024
+    }
+}
+impl fmt::Display for Rsss {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RSSS")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Spread;
+impl Spread {
+    pub const DEPTH: usize = 4;
+}
+
+impl Algorithm for Spread {
+    type Sketch<L, I> = spread::SpreadSketch<L, HyperLogLog<I>>;
+
+    fn new_sketch<L, I>(&self, sketch_size: usize, counter_size: usize) -> Self::Sketch<L, I>
+    where
+        Self::Sketch<L, I>: HeavyDistinctHitterSketch<Label = L, Item = I>,
+    {
+        spread::SpreadSketch::new(
+            &spread::Config::new(
+                Self::DEPTH,
+                sketch_size,
+                hll::Config::new(counter_size, Some([0, 1, 2, 3, 4, 5, 6, 7])).unwrap(),
+                Some([0, 1, 2, 3, 4, 5, 6, 7]),
+            )
+            .unwrap(),
+        )
+    }
+
+    fn optimal_counter_size(&self) -> usize {
+        64
+    }
+}
+impl fmt::Display for Spread {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Spread")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Ssss;
+impl Algorithm for Ssss {
+    type Sketch<L, I> = ssss::HllSamplingSpaceSavingSets<L, I>;
+
+    fn new_sketch<L, I>(&self, sketch_size: usize, counter_size: usize) -> Self::Sketch<L, I>
+    where
+        Self::Sketch<L, I>: HeavyDistinctHitterSketch<Label = L, Item = I>,
+    {
+        ssss::SamplingSpaceSavingSets::new(
+            &ssss::Config::new(
+                sketch_size,
+                hll::Config::new(counter_size, Some([0, 1, 2, 3, 4, 5, 6, 7])).unwrap(),
+                Some([0, 1, 2, 3]),
+            )
+            .unwrap(),
+        )
+    }
+
+    fn optimal_counter_size(&self) -> usize {
+        1024
+    }
+}
+impl fmt::Display for Ssss {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SSSS")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SsssTz;
+impl Algorithm for SsssTz {
+    type Sketch<L, I> = ssss::HllSamplingSpaceSavingSets<L, I>;
+
+    fn new_sketch<L, I>(&self, sketch_size: usize, counter_size: usize) -> Self::Sketch<L, I>
+    where
+        Self::Sketch<L, I>: HeavyDistinctHitterSketch<Label = L, Item = I>,
+    {
+        ssss::SamplingSpaceSavingSets::new(
+            &ssss::Config::new(
+                sketch_size,
+                hll::Config::new(counter_size, Some([0, 1, 2, 3, 4, 5, 6, 7])).unwrap(),
+                Some([0, 1, 2, 3]),
+            )
+            .unwrap()
+            .with_admission(ssss::AdmissionStrategy::TrailingZeros),
+        )
+    }
+
+    fn optimal_counter_size(&self) -> usize {
+        1024
+    }
+}
+impl fmt::Display for SsssTz {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SSSS-TZ")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Ssss2h;
+impl Algorithm for Ssss2h {
+    type Sketch<L, I> = ssss::HllSamplingSpaceSavingSets<L, I>;
+
+    fn new_sketch<L, I>(&self, sketch_size: usize, counter_size: usize) -> Self::Sketch<L, I>
+    where
+        Self::Sketch<L, I>: HeavyDistinctHitterSketch<Label = L, Item = I>,
+    {
+        ssss::SamplingSpaceSavingSets::new(
+            &ssss::Config::new(
+                sketch_size,
+                hll::Config::new(counter_size, Some([0, 1, 2, 3, 4, 5, 6, 7])).unwrap(),
+                Some([0, 1, 2, 3]),
+            )
+            .unwrap()
+            .with_admission(ssss::AdmissionStrategy::TwoHashMax),
+        )
+    }
+
+    fn optimal_counter_size(&self) -> usize {
+        1024
+    }
+}
+impl fmt::Display for Ssss2h {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SSSS-2H")
+    }
+}
+
+/// The names [`new_boxed_sketch`] recognizes, as the benchmarks name them.
+pub const NAMES: [&str; 9] = [
+    "achll", "schll", "sschll", "osss", "rsss", "spread", "ssss", "sssstz", "ssss2h",
+];
+
+pub type BoxedSketch<L, I> = Box<dyn DynHeavyDistinctHitter<Label = L, Item = I> + Send>;
+
+/// Builds the sketch of the algorithm called `name` (see [`NAMES`]), e.g.,
+/// from a service's configuration, with its optimal counter size unless
+/// `counter_size` is given. Returns `None` if no algorithm has that name.
+pub fn new_boxed_sketch<L, I>(
+    name: &str,
+    sketch_size: usize,
+    counter_size: Option<usize>,
+) -> Option<BoxedSketch<L, I>>
+where
+    L: Eq + Hash + Clone + Send + 'static,
+    I: Hash + 'static,
+{
+    fn boxed<A, L, I>(
+        algorithm: A,
+        sketch_size: usize,
+        counter_size: Option<usize>,
+    ) -> BoxedSketch<L, I>
+    where
+        A: Algorithm,
+        A::Sketch<L, I>: HeavyDistinctHitterSketch<Label = L, Item = I> + Any + Send,
+        <A::Sketch<L, I> as HeavyDistinctHitterSketch>::MergeError: Send + Sync + 'static,
+        L: Clone,
+    {
+        let counter_size = counter_size.unwrap_or_else(|| algorithm.optimal_counter_size());
+        Box::new(algorithm.new_sketch(sketch_size, counter_size))
+    }
+
+    let sketch = match name {
+        "achll" => boxed(Achll, sketch_size, counter_size),
+        "schll" => boxed(Schll, sketch_size, counter_size),
+        "sschll" => boxed(Sschll, sketch_size, counter_size),
+        "osss" => boxed(Osss, sketch_size, counter_size),
+        "rsss" => boxed(Rsss, sketch_size, counter_size),
+        "spread" => boxed(Spread, sketch_size, counter_size),
+        "ssss" => boxed(Ssss, sketch_size, counter_size),
+        "sssstz" => boxed(SsssTz, sketch_size, counter_size),
+        "ssss2h" => boxed(Ssss2h, sketch_size, counter_size),
+        _ => return None,
+    };
+    Some(sketch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_name_builds_a_sketch() {
+        for name in NAMES {
+            let mut sketch = new_boxed_sketch::<u32, u64>(name, 16, None).unwrap();
+            for i in 0..1000_u64 {
+                sketch.insert(i as u32 % 4, &i);
+            }
+            assert!(sketch
+                .merge(&new_boxed_sketch(name, 16, None).unwrap())
+                .is_ok());
+            assert!(sketch.cardinality(&0) > 0, "{}", name);
+        }
+        assert!(new_boxed_sketch::<u32, u64>("hll", 16, None).is_none());
+    }
+
+    /// A workload of `heavy` labels with as many distinct items each, and of
+    /// `noise` labels with few items, interleaved in an order drawn from
+    /// `seed`. Heavy labels are numbered from 0, and are the top labels.
+    fn workload(heavy: &[u64], noise: u32, seed: u64) -> Vec<(u32, u64)> {
+        let noise_cardinalities = (0..noise).map(|i| 1 + u64::from(i) % 20);
+        let mut pairs = heavy
+            .iter()
+            .copied()
+            .chain(noise_cardinalities)
+            .enumerate()
+            .flat_map(|(label, cardinality)| {
+                (0..cardinality).map(move |i| (label as u32, (label as u64) << 32 | i))
+            })
+            .collect::<Vec<_>>();
+        pairs.sort_by_key(|&(_, item)| (item ^ seed).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        pairs
+    }
+
+    /// The largest relative errors tolerated on the cardinalities of the top
+    /// labels, with the optimal counter sizes: about 4 standard errors of
+    /// their HyperLogLog counters, plus some slack for the sampling of SSSS
+    /// and the collisions of Spread and Count-HLL.
+    const TOLERANCES: [(&str, f64); 3] = [("ssss", 0.15), ("achll", 0.25), ("spread", 0.6)];
+
+    proptest::proptest! {
+        #![proptest_config(proptest::prelude::ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn top_labels_are_estimated_within_tolerance(
+            heavy in proptest::collection::vec(500_u64..5_000, 10),
+            noise in 100_u32..300,
+            seed in proptest::prelude::any::<u64>(),
+        ) {
+            let pairs = workload(&heavy, noise, seed);
+            for (name, tolerance) in TOLERANCES {
+                let mut sketch = new_boxed_sketch::<u32, u64>(name, 64, None).unwrap();
+                pairs.iter().for_each(|(label, item)| sketch.insert(*label, item));
+                for (label, &cardinality) in heavy.iter().enumerate() {
+                    let estimate = sketch.cardinality(&(label as u32));
+                    let error = (estimate as f64 - cardinality as f64).abs() / cardinality as f64;
+                    proptest::prop_assert!(
+                        error <= tolerance,
+                        "{}: estimated {} for {}",
+                        name,
+                        estimate,
+                        cardinality
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! All the sketches of this workspace behind a single dependency.
+//!
+//! Each sketch crate is re-exported under its own name and gated behind a
+//! feature of the same name; all of them are enabled by default. Configs keep
+//! their crate's namespace (e.g., `ssss_sketches::ssss::Config`) since they
+//! would clash otherwise. With all of them enabled, [`algo`] builds any of
+//! the sketches by name.
+//!
+//! ```ignore
+//! use ssss_sketches::prelude::*;
+//! ```
+//!
+//! Without the `std` feature, enabled by default, the sketches only need
+//! `core` and `alloc`, e.g., to run in embedded collectors.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(all(
+    feature = "hll",
+    feature = "count_hll",
+    feature = "sss",
+    feature = "ssss",
+    feature = "spread"
+))]
+pub mod algo;
+#[cfg(feature = "count_hll")]
+pub use count_hll;
+#[cfg(feature = "hll")]
+pub use hll;
+pub use sketch_traits;
+#[cfg(feature = "spread")]
+pub use spread;
+#[cfg(feature = "sss")]
+pub use sss;
+#[cfg(feature = "ssss")]
+pub use ssss;
+
+/// The traits and sketch types most users need.
+pub mod prelude {
+    #[cfg(feature = "metrics")]
+    pub use sketch_traits::SketchMetrics;
+    pub use sketch_traits::{
+        Algorithm, CardinalitySketch, DynHeavyDistinctHitter, Fold, Frozen,
+        HeavyDistinctHitterSketch, Idempotent, LabelKey, Membership, Memoized, MemorySize, New,
+        Observable, Observer, PrehashedCardinalitySketch, PrehashedHeavyDistinctHitterSketch,
+        SeedableBuildHasher, Validate, Versioned,
+    };
+
+    #[cfg(feature = "count_hll")]
+    pub use count_hll::{LabelArrayCountHLL, LabelSetCountHLL, SpaceSavingCountHLL};
+    #[cfg(feature = "hll")]
+    pub use hll::{BloomHyperLogLog, HyperLogLog};
+    #[cfg(feature = "spread")]
+    pub use spread::SpreadSketch;
+    #[cfg(feature = "sss")]
+    pub use sss::{ResetStrategy, SpaceSavingSets};
+    #[cfg(feature = "ssss")]
+    pub use ssss::{HllSamplingSpaceSavingSets, SamplingSpaceSavingSets};
+}
+
+#[cfg(all(test, feature = "hll", feature = "spread", feature = "ssss"))]
+mod tests {
+    use crate::prelude::*;
+
+    type BoxedSketch = Box<dyn DynHeavyDistinctHitter<Label = String, Item = String> + Send>;
+
+    /// Picks the sketch at runtime, as a service would from its config.
+    fn sketch(algorithm: &str) -> BoxedSketch {
+        let hll_config = hll::Config::new(64, Some([0, 1, 2, 3, 4, 5, 6, 7])).unwrap();
+        match algorithm {
+            "ssss" => Box::new(HllSamplingSpaceSavingSets::new(
+                &ssss::Config::new(10, hll_config, Some([8, 9, 10, 11])).unwrap(),
+            )),
+            _ => Box::new(SpreadSketch::<_, HyperLogLog<_>>::new(
+                &spread::Config::new(4, 16, hll_config, Some([8; 8])).unwrap(),
+            )),
+        }
+    }
+
+    #[test]
+    fn sketches_can_be_picked_at_runtime() {
+        for algorithm in ["ssss", "spread"] {
+            let mut picked = sketch(algorithm);
+            for i in 0..1000 {
+                picked.insert(format!("label{}", i % 4), &i.to_string());
+            }
+            assert!(picked.merge(&sketch(algorithm)).is_ok());
+            assert_eq!(picked.top(4).len(), 4, "{}", algorithm);
+        }
+        assert!(sketch("ssss").merge(&sketch("spread")).is_err());
+    }
+}